@@ -0,0 +1,21 @@
+//! Passes the locked source of clicks-common to the crate as `CLICKS_COMMON_SOURCE`. It follows
+//! a branch, so its version alone does not tell the layout of its types apart.
+fn main() {
+    println!("cargo:rerun-if-changed=Cargo.lock");
+    let lock = std::fs::read_to_string("Cargo.lock").unwrap_or_default();
+    let source = lock
+        .split("[[package]]")
+        .find(|package| {
+            package
+                .lines()
+                .any(|line| line.trim() == r#"name = "common""#)
+        })
+        .and_then(|package| {
+            package
+                .lines()
+                .find_map(|line| line.trim().strip_prefix("source = "))
+        })
+        .map(|source| source.trim_matches('"').to_string())
+        .unwrap_or_default();
+    println!("cargo:rustc-env=CLICKS_COMMON_SOURCE={source}");
+}
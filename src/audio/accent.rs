@@ -0,0 +1,138 @@
+use common::cue::Cue;
+use serde::{Deserialize, Serialize};
+
+/// Longest bar whose beats are looked at to find its length. Longer bars are accented as if
+/// they ended there.
+pub const MAX_BAR_BEATS: usize = 32;
+
+/// How strongly a beat of a bar is clicked.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Accent {
+    /// The first beat of the bar
+    Strong,
+    /// The first beat of a later group in the bar, e.g. beat 3 of 4/4
+    Medium,
+    Weak,
+}
+
+impl Accent {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Accent::Strong => "strong",
+            Accent::Medium => "medium",
+            Accent::Weak => "weak",
+        }
+    }
+
+    /// Accent of the beat at `position` (from 0) in a bar of `length` beats. Bars are grouped
+    /// as is usual for their length: 4/4 as 2+2, 6/8 as 3+3, 7/8 as 2+2+3, 8/8 as 3+3+2 and so
+    /// on. Bars of up to 3 beats, and lengths without a usual grouping, accent only their
+    /// first beat.
+    pub fn of(position: usize, length: usize) -> Self {
+        if position == 0 {
+            return Accent::Strong;
+        }
+        let groups: &[usize] = match length {
+            4 => &[2, 2],
+            5 => &[3, 2],
+            6 => &[3, 3],
+            7 => &[2, 2, 3],
+            8 => &[3, 3, 2],
+            9 => &[3, 3, 3],
+            10 => &[3, 3, 2, 2],
+            12 => &[3, 3, 3, 3],
+            _ => &[],
+        };
+        let mut start = 0;
+        for group in groups {
+            if position == start {
+                return Accent::Medium;
+            }
+            start += group;
+        }
+        Accent::Weak
+    }
+}
+
+/// Position (from 0) of a beat in its bar, and the number of beats in the bar, from the count
+/// of each beat within its bar. `count` gives the count of a beat of the cue, or None past the
+/// end. Bars are taken to end where the count starts over.
+fn bar_position(count: impl Fn(u16) -> Option<usize>, beat_idx: u16) -> (usize, usize) {
+    let Some(beat_count) = count(beat_idx) else {
+        return (0, 1);
+    };
+    let position = beat_count.saturating_sub(1);
+    let mut length = position + 1;
+    let mut previous = beat_count;
+    for next_idx in beat_idx.saturating_add(1)..=u16::MAX {
+        match count(next_idx) {
+            Some(next) if next > previous && length < MAX_BAR_BEATS => {
+                length += 1;
+                previous = next;
+            }
+            _ => break,
+        }
+    }
+    (position, length)
+}
+
+/// Accent of a beat of a cue, from the time signature its bar has.
+pub fn beat_accent(cue: &Cue, beat_idx: u16) -> Accent {
+    let (position, length) = bar_position(|idx| cue_count(cue, idx), beat_idx);
+    Accent::of(position, length)
+}
+
+/// Accents of all beats of the bar a beat of a cue is in, and the position of the beat in it.
+pub fn bar_accents(cue: &Cue, beat_idx: u16) -> (usize, Vec<Accent>) {
+    let (position, length) = bar_position(|idx| cue_count(cue, idx), beat_idx);
+    (
+        position,
+        (0..length).map(|beat| Accent::of(beat, length)).collect(),
+    )
+}
+
+/// Position (from 0) of a beat of a cue in its bar, and the number of beats in the bar.
+pub fn bar_position_in_cue(cue: &Cue, beat_idx: u16) -> (usize, usize) {
+    bar_position(|idx| cue_count(cue, idx), beat_idx)
+}
+
+/// Whether a beat of a cue is the first of its bar.
+pub fn starts_bar(cue: &Cue, beat_idx: u16) -> bool {
+    bar_position(|idx| cue_count(cue, idx), beat_idx).0 == 0
+}
+
+fn cue_count(cue: &Cue, beat_idx: u16) -> Option<usize> {
+    cue.get_beat(beat_idx).map(|beat| beat.count as usize)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accent_patterns() {
+        use Accent::{Medium as M, Strong as S, Weak as W};
+        let pattern = |length| (0..length).map(|beat| Accent::of(beat, length)).collect();
+        let patterns: Vec<Vec<Accent>> = vec![pattern(3), pattern(4), pattern(8)];
+        assert_eq!(
+            patterns,
+            vec![
+                vec![S, W, W],
+                vec![S, W, M, W],
+                vec![S, W, W, M, W, W, M, W],
+            ]
+        );
+    }
+
+    #[test]
+    fn bars_of_changing_length() {
+        // A bar of 4/4, a bar of 3/4 and a bar of 8/8 the cue ends in
+        let counts = [1, 2, 3, 4, 1, 2, 3, 1, 2, 3, 4, 5, 6, 7, 8];
+        let count = |idx: u16| counts.get(idx as usize).copied();
+        assert_eq!(bar_position(count, 0), (0, 4));
+        assert_eq!(bar_position(count, 3), (3, 4));
+        assert_eq!(bar_position(count, 5), (1, 3));
+        assert_eq!(bar_position(count, 13), (6, 8));
+        assert_eq!(bar_position(count, 15), (0, 1));
+    }
+}
@@ -0,0 +1,122 @@
+use crate::audio::{
+    accent::{Accent, bar_position_in_cue},
+    metronome::TICKS_PER_FRAME,
+};
+use common::cue::Cue;
+
+/// Most bars of count-in before the transport starts.
+pub const MAX_COUNT_IN_BARS: u8 = 8;
+
+/// What a count-in has due in a process cycle.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CountInStep {
+    /// Click a count-in beat, with the count-in beats left counting this one
+    Click { accent: Accent, beats_left: usize },
+    /// The count-in is over, start the transport
+    Start,
+}
+
+/// Bars of click before the transport starts, at the tempo and in the time signature of the beat
+/// it starts from, so the band hears where the "1" is. Starting in the middle of a bar, the
+/// count-in runs on to that beat of the bar. Beats are placed on the frame clock like those of
+/// the metronome.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CountIn {
+    /// Position of the first count-in beat, in ticks of the frame clock
+    start: u64,
+    /// Length of a count-in beat in ticks
+    beat_length: u64,
+    /// Beats in the bar the transport starts in
+    bar_beats: usize,
+    /// Count-in beats in all
+    beats: usize,
+    /// Count-in beats clicked so far
+    clicked: usize,
+}
+
+impl CountIn {
+    /// A count-in of `bars` bars starting at frame `frame_time`, for the transport to start
+    /// from beat `beat_idx` of a cue. None if there are no bars to count or no such beat.
+    pub fn new(
+        cue: &Cue,
+        beat_idx: u16,
+        bars: u8,
+        frame_time: u64,
+        sample_rate: usize,
+        playrate_percent: u16,
+    ) -> Option<Self> {
+        let beat = cue.get_beat(beat_idx)?;
+        if bars == 0 || beat.length == 0 || sample_rate == 0 {
+            return None;
+        }
+        let (position, bar_beats) = bar_position_in_cue(cue, beat_idx);
+        Some(Self {
+            start: frame_time * TICKS_PER_FRAME,
+            // A microsecond is sample_rate ticks long
+            beat_length: beat.length as u64 * sample_rate as u64 * 100
+                / playrate_percent.max(1) as u64,
+            bar_beats,
+            beats: bars.min(MAX_COUNT_IN_BARS) as usize * bar_beats + position,
+            clicked: 0,
+        })
+    }
+
+    /// What is due in the process cycle starting at frame `frame_time`: the next count-in beat,
+    /// or the start of the transport once the last one has run its length.
+    pub fn step(&mut self, frame_time: u64) -> Option<CountInStep> {
+        let due = self.start + self.clicked as u64 * self.beat_length;
+        if frame_time * TICKS_PER_FRAME < due {
+            return None;
+        }
+        if self.clicked == self.beats {
+            return Some(CountInStep::Start);
+        }
+        let accent = Accent::of(self.clicked % self.bar_beats, self.bar_beats);
+        let beats_left = self.beats - self.clicked;
+        self.clicked += 1;
+        Some(CountInStep::Click { accent, beats_left })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_bars_of_the_start_beat() {
+        const FRAME_SIZE: u64 = 256;
+        let cue = Cue::example();
+        let beat = cue.get_beat(0).unwrap_or_default();
+        let (position, bar_beats) = bar_position_in_cue(&cue, 0);
+        let beats = 2 * bar_beats + position;
+        let mut count_in = CountIn::new(&cue, 0, 2, 1000, 48000, 100).expect("a count-in");
+
+        let mut steps = vec![];
+        for cycle in 0..100_000 {
+            let frame_time = 1000 + cycle * FRAME_SIZE;
+            match count_in.step(frame_time) {
+                Some(CountInStep::Start) => {
+                    steps.push((frame_time, None));
+                    break;
+                }
+                Some(CountInStep::Click { accent, beats_left }) => {
+                    assert_eq!(beats_left, beats - steps.len());
+                    steps.push((frame_time, Some(accent)));
+                }
+                None => {}
+            }
+        }
+        assert_eq!(steps.len(), beats + 1);
+        assert_eq!(steps[0], (1000, Some(Accent::Strong)));
+        assert_eq!(steps[bar_beats].1, Some(Accent::Strong));
+        // Every beat in the first cycle starting at or after its exact position, the start of
+        // the transport a beat after the last count-in beat
+        for (idx, (frame, _)) in steps.iter().enumerate() {
+            let exact_frame = 1000 + idx as u64 * beat.length as u64 * 48 / 1000;
+            assert!(*frame >= exact_frame && *frame < exact_frame + FRAME_SIZE);
+        }
+
+        assert_eq!(CountIn::new(&cue, 0, 0, 0, 48000, 100), None);
+        assert_eq!(CountIn::new(&cue, u16::MAX, 1, 0, 48000, 100), None);
+    }
+}
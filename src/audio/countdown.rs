@@ -0,0 +1,291 @@
+use common::{
+    cue::Cue,
+    event::{EventCursor, EventDescription, JumpRequirement},
+};
+use serde::{Deserialize, Serialize};
+
+/// Countdown messages per second, whether the transport runs or not.
+pub const COUNTDOWN_RATE: u8 = 4;
+
+/// Number of events in an `UpcomingEvent` preview.
+pub const UPCOMING_EVENTS: usize = 5;
+
+/// Kind and target of an event, as shown to operators and musicians.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CountdownEvent {
+    Playback {
+        channel: u16,
+        clip: u16,
+    },
+    PlaybackStop {
+        channel: u16,
+    },
+    Timecode,
+    TimecodeStop,
+    /// A jump to `destination`, e.g. a vamp or a D.S. al coda. Taken only with jump mode on
+    /// (`Some(true)`) or off (`Some(false)`), or always (`None`).
+    Jump {
+        destination: u16,
+        jump_mode: Option<bool>,
+    },
+}
+
+impl CountdownEvent {
+    /// Digest of an event of the show, or None for events that are not shown.
+    pub fn from_description(description: EventDescription) -> Option<Self> {
+        match description {
+            EventDescription::PlaybackEvent {
+                channel_idx,
+                clip_idx,
+                sample: _,
+            } => Some(Self::Playback {
+                channel: channel_idx,
+                clip: clip_idx,
+            }),
+            EventDescription::PlaybackStopEvent { channel_idx } => Some(Self::PlaybackStop {
+                channel: channel_idx,
+            }),
+            EventDescription::TimecodeEvent { .. } => Some(Self::Timecode),
+            EventDescription::TimecodeStopEvent => Some(Self::TimecodeStop),
+            EventDescription::JumpEvent {
+                destination,
+                requirement,
+                ..
+            } => Some(Self::Jump {
+                destination,
+                jump_mode: match requirement {
+                    JumpRequirement::JumpModeOn => Some(true),
+                    JumpRequirement::JumpModeOff => Some(false),
+                    JumpRequirement::None => None,
+                },
+            }),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Playback { .. } => "playback",
+            Self::PlaybackStop { .. } => "playback_stop",
+            Self::Timecode => "timecode",
+            Self::TimecodeStop => "timecode_stop",
+            Self::Jump { .. } => "jump",
+        }
+    }
+
+    /// Whether a countdown points at the event. Stop events are only previewed.
+    fn is_cue_point(&self) -> bool {
+        matches!(
+            self,
+            Self::Playback { .. } | Self::Timecode | Self::Jump { .. }
+        )
+    }
+}
+
+/// An event coming up in the current cue.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UpcomingEvent {
+    pub beat: u16,
+    pub event: CountdownEvent,
+}
+
+/// The first `count` events after `beat_idx` in a cue, in the order they are programmed. Jumps
+/// are not followed, so events past a jump are listed as if it was not taken.
+pub fn upcoming_events(cue: &Cue, beat_idx: u16, count: usize) -> Vec<UpcomingEvent> {
+    let mut upcoming = vec![];
+    let mut cursor = EventCursor::new(&cue.events);
+    while let Some(event) = cursor.get_next() {
+        if event.location <= beat_idx {
+            continue;
+        }
+        if let Some(digest) = event.event.and_then(CountdownEvent::from_description) {
+            upcoming.push(UpcomingEvent {
+                beat: event.location,
+                event: digest,
+            });
+        }
+    }
+    upcoming.sort_by_key(|event| event.beat);
+    upcoming.truncate(count);
+    upcoming
+}
+
+/// The next event of the current cue and when it happens.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NextEvent {
+    pub event: CountdownEvent,
+    pub beat: u16,
+    /// Time until the event, at the current playrate
+    pub us: u64,
+}
+
+/// Time left in the current cue and until its next playback, timecode or jump event, for
+/// operator screens. Times assume the cue is played as programmed from here on, at the current
+/// playrate.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Countdown {
+    /// Time until the end of the last beat of the cue
+    pub cue_remaining_us: u64,
+    pub next: Option<NextEvent>,
+}
+
+/// The beats and cue points of a cue, worked out when the cue is loaded so counting down does
+/// not walk all its beats and events on every countdown.
+#[derive(Debug, Clone, Default)]
+pub struct CueCountdown {
+    /// Start of every beat at 100 % playrate, from the top of the cue, and the end of the last
+    /// beat after them
+    beat_starts_us: Vec<u64>,
+    /// The first cue point programmed on each beat that has one, in order of beats
+    cue_points: Vec<(u16, CountdownEvent)>,
+}
+
+impl CueCountdown {
+    pub fn new(cue: &Cue) -> Self {
+        let beat_count = cue.get_beats().len().min(u16::MAX as usize) as u16;
+        let mut beat_starts_us = Vec::with_capacity(beat_count as usize + 1);
+        let mut start_us = 0;
+        beat_starts_us.push(start_us);
+        for idx in 0..beat_count {
+            start_us += cue.get_beat(idx).unwrap_or_default().length as u64;
+            beat_starts_us.push(start_us);
+        }
+
+        let mut cue_points = vec![];
+        let mut cursor = EventCursor::new(&cue.events);
+        while let Some(event) = cursor.get_next() {
+            if let Some(kind) = event
+                .event
+                .and_then(CountdownEvent::from_description)
+                .filter(CountdownEvent::is_cue_point)
+            {
+                cue_points.push((event.location, kind));
+            }
+        }
+        // The sort is stable, so the first programmed of the cue points on a beat is kept
+        cue_points.sort_by_key(|(beat, _)| *beat);
+        cue_points.dedup_by_key(|(beat, _)| *beat);
+
+        Self {
+            beat_starts_us,
+            cue_points,
+        }
+    }
+
+    /// Counts down from `beat_idx`, which the transport leaves in `us_to_next_beat`.
+    pub fn at(&self, beat_idx: u16, us_to_next_beat: u32, playrate_percent: u16) -> Countdown {
+        let playrate_percent = playrate_percent.max(1) as u64;
+        let beat_count = self.beat_starts_us.len().saturating_sub(1);
+        let start_us = |beat: u16| {
+            self.beat_starts_us
+                .get((beat as usize).min(beat_count))
+                .copied()
+                .unwrap_or_default()
+        };
+        // Time from now until the start of a following beat
+        let until_beat = |target: u16| {
+            let from_us = start_us(beat_idx.saturating_add(1));
+            us_to_next_beat as u64
+                + start_us(target).saturating_sub(from_us) * 100 / playrate_percent
+        };
+
+        let next = self
+            .cue_points
+            .get(
+                self.cue_points
+                    .partition_point(|(beat, _)| *beat <= beat_idx),
+            )
+            .map(|(beat, event)| NextEvent {
+                event: *event,
+                beat: *beat,
+                us: until_beat(*beat),
+            });
+        Countdown {
+            cue_remaining_us: if (beat_idx as usize) < beat_count {
+                until_beat(beat_count as u16)
+            } else {
+                0
+            },
+            next,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common::event::Event;
+
+    /// The example cue with a playback stop on beat 1 and two playbacks on beat 2.
+    fn cue_with_events() -> Cue {
+        let mut cue = Cue::example();
+        assert!(cue.get_beats().len() > 2);
+        cue.events = Cue::empty().events;
+        let playback = |channel_idx, clip_idx| EventDescription::PlaybackEvent {
+            channel_idx,
+            clip_idx,
+            sample: 0,
+        };
+        cue.events.set(0, Event::new(2, playback(3, 1)));
+        cue.events.set(
+            1,
+            Event::new(1, EventDescription::PlaybackStopEvent { channel_idx: 3 }),
+        );
+        cue.events.set(2, Event::new(2, playback(4, 0)));
+        cue
+    }
+
+    #[test]
+    fn counts_down_to_cue_end() {
+        let cue = cue_with_events();
+        let countdown = CueCountdown::new(&cue);
+        let beat_count = cue.get_beats().len() as u16;
+        let first_beat_us = cue.get_beat(0).unwrap_or_default().length as u32;
+        let total_us: u64 = (0..beat_count)
+            .map(|idx| cue.get_beat(idx).unwrap_or_default().length as u64)
+            .sum();
+
+        assert_eq!(
+            countdown.at(0, first_beat_us, 100).cue_remaining_us,
+            total_us
+        );
+        // Twice the time at half speed
+        let at_half_speed = countdown.at(0, first_beat_us * 2, 50);
+        assert_eq!(at_half_speed.cue_remaining_us, total_us * 2);
+        assert!(at_half_speed.next.unwrap().us <= at_half_speed.cue_remaining_us);
+        assert_eq!(countdown.at(beat_count, 0, 100).cue_remaining_us, 0);
+    }
+
+    #[test]
+    fn counts_down_to_next_cue_point() {
+        let cue = cue_with_events();
+        let countdown = CueCountdown::new(&cue);
+        let second_beat_us = cue.get_beat(1).unwrap_or_default().length as u64;
+
+        // The stop on beat 1 is passed over, and the first playback of beat 2 is shown
+        assert_eq!(
+            countdown.at(0, 1000, 100).next,
+            Some(NextEvent {
+                event: CountdownEvent::Playback {
+                    channel: 3,
+                    clip: 1
+                },
+                beat: 2,
+                us: 1000 + second_beat_us,
+            })
+        );
+        assert_eq!(countdown.at(1, 1000, 100).next.unwrap().us, 1000);
+        assert_eq!(countdown.at(2, 1000, 100).next, None);
+        assert_eq!(CueCountdown::default().at(0, 0, 100).cue_remaining_us, 0);
+    }
+
+    #[test]
+    fn upcoming_in_order() {
+        let cue = Cue::example();
+        let upcoming = upcoming_events(&cue, 0, UPCOMING_EVENTS);
+        assert!(upcoming.len() <= UPCOMING_EVENTS);
+        assert!(upcoming.iter().all(|event| event.beat > 0));
+        assert!(upcoming.windows(2).all(|pair| pair[0].beat <= pair[1].beat));
+        assert!(upcoming_events(&cue, 0, 0).is_empty());
+    }
+}
@@ -0,0 +1,107 @@
+use crate::audio::source::MAX_SAMPLE_RATE;
+use serde::{Deserialize, Serialize};
+
+/// Longest delay an output can be set to.
+pub const MAX_OUTPUT_DELAY_MS: f32 = 100.0;
+/// Speed of sound at 20 °C, used to convert distances to delay.
+const SPEED_OF_SOUND_M_PER_S: f32 = 343.0;
+const DELAY_LINE_LENGTH: usize = MAX_SAMPLE_RATE * MAX_OUTPUT_DELAY_MS as usize / 1000 + 1;
+
+/// Output delay, either as time or as the distance it should compensate for.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum DelayAmount {
+    Milliseconds(f32),
+    Meters(f32),
+}
+
+/// `delay_ms` as far as a delay line reaches, 0 for NaN.
+pub fn clamp_delay_ms(delay_ms: f32) -> f32 {
+    if delay_ms.is_nan() {
+        0.0
+    } else {
+        delay_ms.clamp(0.0, MAX_OUTPUT_DELAY_MS)
+    }
+}
+
+impl DelayAmount {
+    pub fn as_ms(self) -> f32 {
+        match self {
+            DelayAmount::Milliseconds(ms) => ms,
+            DelayAmount::Meters(meters) => meters / SPEED_OF_SOUND_M_PER_S * 1000.0,
+        }
+    }
+}
+
+/// Fixed length delay line for one output. The buffer is allocated up front for the longest
+/// delay at the highest sample rate, so changing the delay never allocates.
+#[derive(Debug, Clone)]
+pub struct DelayLine {
+    buffer: Vec<f32>,
+    write_idx: usize,
+    delay_ms: f32,
+}
+
+impl Default for DelayLine {
+    fn default() -> Self {
+        Self {
+            buffer: vec![0.0; DELAY_LINE_LENGTH],
+            write_idx: 0,
+            delay_ms: 0.0,
+        }
+    }
+}
+
+impl DelayLine {
+    pub fn set_delay_ms(&mut self, delay_ms: f32) {
+        let delay_ms = clamp_delay_ms(delay_ms);
+        if delay_ms != self.delay_ms {
+            // Do not play out stale audio from before the delay was last active
+            self.buffer.fill(0.0);
+            self.delay_ms = delay_ms;
+        }
+    }
+
+    pub fn process(&mut self, buf: &mut [f32], sample_rate: usize) {
+        if self.delay_ms == 0.0 {
+            return;
+        }
+        let len = self.buffer.len();
+        let delay = ((self.delay_ms * sample_rate as f32 / 1000.0) as usize).min(len - 1);
+        for sample in buf {
+            self.buffer[self.write_idx] = *sample;
+            *sample = self.buffer[(self.write_idx + len - delay) % len];
+            self.write_idx = (self.write_idx + 1) % len;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn delays_by_whole_samples() {
+        let mut line = DelayLine::default();
+        line.set_delay_ms(1.0);
+        let mut buf = vec![0.0; 256];
+        buf[0] = 1.0;
+        line.process(&mut buf, 48000);
+        assert_eq!(buf.iter().position(|s| *s == 1.0), Some(48));
+    }
+
+    #[test]
+    fn clamped() {
+        let mut line = DelayLine::default();
+        line.set_delay_ms(f32::NAN);
+        assert_eq!(line.delay_ms, 0.0);
+        line.set_delay_ms(MAX_OUTPUT_DELAY_MS * 2.0);
+        assert_eq!(line.delay_ms, MAX_OUTPUT_DELAY_MS);
+        line.set_delay_ms(-1.0);
+        assert_eq!(line.delay_ms, 0.0);
+    }
+
+    #[test]
+    fn meters() {
+        assert!((DelayAmount::Meters(34.3).as_ms() - 100.0).abs() < 0.01);
+    }
+}
@@ -0,0 +1,237 @@
+use serde::{Deserialize, Serialize};
+use std::{f32::consts::PI, ops::RangeInclusive};
+
+/// Number of bands of the output EQ. Band 0 is a low shelf, band 1 a peaking filter and band 2
+/// a high shelf.
+pub const EQ_BANDS: usize = 3;
+/// Frequencies a band can be set to, in Hz
+pub const EQ_FREQUENCY_RANGE: RangeInclusive<f32> = 10.0..=20000.0;
+/// Most boost or cut of a band, in dB
+pub const MAX_EQ_GAIN_DB: f32 = 24.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct EqBand {
+    /// Center or corner frequency in Hz
+    pub frequency: f32,
+    pub gain_db: f32,
+    pub q: f32,
+}
+
+impl EqBand {
+    /// Whether the band is within `EQ_FREQUENCY_RANGE` and `MAX_EQ_GAIN_DB`, with a positive Q.
+    pub fn is_valid(&self) -> bool {
+        EQ_FREQUENCY_RANGE.contains(&self.frequency)
+            && (-MAX_EQ_GAIN_DB..=MAX_EQ_GAIN_DB).contains(&self.gain_db)
+            && self.q.is_finite()
+            && self.q > 0.0
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct EqConfiguration {
+    pub bands: [EqBand; EQ_BANDS],
+}
+
+impl Default for EqConfiguration {
+    fn default() -> Self {
+        let band = |frequency| EqBand {
+            frequency,
+            gain_db: 0.0,
+            q: std::f32::consts::FRAC_1_SQRT_2,
+        };
+        Self {
+            bands: [band(100.0), band(1000.0), band(8000.0)],
+        }
+    }
+}
+
+impl EqConfiguration {
+    pub fn is_flat(&self) -> bool {
+        self.bands.iter().all(|band| band.gain_db == 0.0)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum BandShape {
+    LowShelf,
+    Peak,
+    HighShelf,
+}
+
+const BAND_SHAPES: [BandShape; EQ_BANDS] =
+    [BandShape::LowShelf, BandShape::Peak, BandShape::HighShelf];
+
+/// Second order IIR filter in transposed direct form II.
+#[derive(Debug, Clone, Copy)]
+struct Biquad {
+    b0: f32,
+    b1: f32,
+    b2: f32,
+    a1: f32,
+    a2: f32,
+    z1: f32,
+    z2: f32,
+}
+
+impl Default for Biquad {
+    fn default() -> Self {
+        Self {
+            b0: 1.0,
+            b1: 0.0,
+            b2: 0.0,
+            a1: 0.0,
+            a2: 0.0,
+            z1: 0.0,
+            z2: 0.0,
+        }
+    }
+}
+
+impl Biquad {
+    /// Set coefficients from the Audio EQ Cookbook (R. Bristow-Johnson), keeping the filter
+    /// state so a running signal does not click. An invalid band passes the signal unchanged,
+    /// and a state that is no longer finite is cleared so the output recovers.
+    fn set(&mut self, shape: BandShape, band: EqBand, sample_rate: usize) {
+        if !self.z1.is_finite() || !self.z2.is_finite() {
+            self.z1 = 0.0;
+            self.z2 = 0.0;
+        }
+        if !band.is_valid() {
+            *self = Self {
+                z1: self.z1,
+                z2: self.z2,
+                ..Self::default()
+            };
+            return;
+        }
+        let nyquist = sample_rate as f32 / 2.0;
+        let frequency = band.frequency.clamp(10.0, nyquist * 0.95);
+        let a = 10f32.powf(band.gain_db / 40.0);
+        let w0 = 2.0 * PI * frequency / sample_rate as f32;
+        let (sin, cos) = w0.sin_cos();
+        let alpha = sin / (2.0 * band.q.max(0.1));
+        let sqrt_a_alpha = 2.0 * a.sqrt() * alpha;
+
+        let (b0, b1, b2, a0, a1, a2) = match shape {
+            BandShape::Peak => (
+                1.0 + alpha * a,
+                -2.0 * cos,
+                1.0 - alpha * a,
+                1.0 + alpha / a,
+                -2.0 * cos,
+                1.0 - alpha / a,
+            ),
+            BandShape::LowShelf => (
+                a * ((a + 1.0) - (a - 1.0) * cos + sqrt_a_alpha),
+                2.0 * a * ((a - 1.0) - (a + 1.0) * cos),
+                a * ((a + 1.0) - (a - 1.0) * cos - sqrt_a_alpha),
+                (a + 1.0) + (a - 1.0) * cos + sqrt_a_alpha,
+                -2.0 * ((a - 1.0) + (a + 1.0) * cos),
+                (a + 1.0) + (a - 1.0) * cos - sqrt_a_alpha,
+            ),
+            BandShape::HighShelf => (
+                a * ((a + 1.0) + (a - 1.0) * cos + sqrt_a_alpha),
+                -2.0 * a * ((a - 1.0) + (a + 1.0) * cos),
+                a * ((a + 1.0) + (a - 1.0) * cos - sqrt_a_alpha),
+                (a + 1.0) - (a - 1.0) * cos + sqrt_a_alpha,
+                2.0 * ((a - 1.0) - (a + 1.0) * cos),
+                (a + 1.0) - (a - 1.0) * cos - sqrt_a_alpha,
+            ),
+        };
+        self.b0 = b0 / a0;
+        self.b1 = b1 / a0;
+        self.b2 = b2 / a0;
+        self.a1 = a1 / a0;
+        self.a2 = a2 / a0;
+    }
+
+    fn process(&mut self, x: f32) -> f32 {
+        let y = self.b0 * x + self.z1;
+        self.z1 = self.b1 * x - self.a1 * y + self.z2;
+        self.z2 = self.b2 * x - self.a2 * y;
+        y
+    }
+}
+
+/// 3-band parametric EQ for one output. Coefficients are only recalculated when the
+/// configuration or sample rate changes, and a flat EQ is bypassed entirely.
+#[derive(Debug, Clone, Default)]
+pub struct ParametricEq {
+    config: EqConfiguration,
+    sample_rate: usize,
+    filters: [Biquad; EQ_BANDS],
+}
+
+impl ParametricEq {
+    pub fn configure(&mut self, config: EqConfiguration) {
+        self.config = config;
+        // Force recalculation on the next buffer
+        self.sample_rate = 0;
+    }
+
+    pub fn process(&mut self, buf: &mut [f32], sample_rate: usize) {
+        if self.config.is_flat() || sample_rate == 0 {
+            return;
+        }
+        if sample_rate != self.sample_rate {
+            for ((filter, shape), band) in self
+                .filters
+                .iter_mut()
+                .zip(BAND_SHAPES)
+                .zip(self.config.bands)
+            {
+                filter.set(shape, band, sample_rate);
+            }
+            self.sample_rate = sample_rate;
+        }
+        for sample in buf {
+            for filter in &mut self.filters {
+                *sample = filter.process(*sample);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn peak_amplitude(eq: &mut ParametricEq, frequency: f32) -> f32 {
+        let mut buf: Vec<f32> = (0..48000)
+            .map(|i| (2.0 * PI * frequency * i as f32 / 48000.0).sin())
+            .collect();
+        eq.process(&mut buf, 48000);
+        // Skip the filter settling time
+        buf[24000..].iter().fold(0.0, |max, s| s.abs().max(max))
+    }
+
+    #[test]
+    fn peak_band() {
+        let mut eq = ParametricEq::default();
+        assert!((peak_amplitude(&mut eq, 1000.0) - 1.0).abs() < 0.01);
+
+        let mut config = EqConfiguration::default();
+        config.bands[1].gain_db = 6.0;
+        eq.configure(config);
+        // +6 dB at the center frequency, untouched far away from it
+        assert!((peak_amplitude(&mut eq, 1000.0) - 1.995).abs() < 0.05);
+        assert!((peak_amplitude(&mut eq, 50.0) - 1.0).abs() < 0.05);
+    }
+
+    #[test]
+    fn recovers_from_invalid_band() {
+        let mut filter = Biquad {
+            z1: f32::NAN,
+            ..Biquad::default()
+        };
+        let band = EqBand {
+            frequency: 1000.0,
+            gain_db: f32::NAN,
+            q: 1.0,
+        };
+        assert!(!band.is_valid());
+        filter.set(BandShape::Peak, band, 48000);
+        assert_eq!(filter.process(0.5), 0.5);
+        assert_eq!(filter.process(0.25), 0.25);
+    }
+}
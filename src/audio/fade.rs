@@ -0,0 +1,191 @@
+/// Longest fade-out on transport stop.
+pub const MAX_STOP_FADE_MS: f32 = 2000.0;
+
+/// Fade-out of all sources when the transport is stopped, so playback is not cut mid-sample with
+/// a click in everyone's ears. The stop itself is held back until the fade has run out.
+#[derive(Debug, Clone, Default)]
+pub struct StopFade {
+    length: usize,
+    /// Samples left of the running fade, None if not fading
+    left: Option<usize>,
+    /// The fade has run out and the held back stop is being carried out
+    finished: bool,
+}
+
+impl StopFade {
+    /// Starts a fade of `length` samples, or keeps a running fade going. Returns false if the
+    /// transport should stop right away: there is no fade, or it has just run out.
+    pub fn start(&mut self, length: usize) -> bool {
+        if std::mem::take(&mut self.finished) || length == 0 {
+            return false;
+        }
+        if self.left.is_none() {
+            self.length = length;
+            self.left = Some(length);
+        }
+        true
+    }
+
+    /// Stops fading, e.g. when the transport is started again during the fade.
+    pub fn cancel(&mut self) {
+        self.left = None;
+        self.finished = false;
+    }
+
+    /// Applies the part of the fade in the current cycle to a buffer.
+    pub fn apply(&self, buf: &mut [f32]) {
+        let Some(left) = self.left else {
+            return;
+        };
+        for (idx, sample) in buf.iter_mut().enumerate() {
+            *sample *= left.saturating_sub(idx) as f32 / self.length as f32;
+        }
+    }
+
+    /// Moves on by a cycle of `frame_size` samples. Returns true when the fade has run out and
+    /// the transport is to be stopped.
+    pub fn advance(&mut self, frame_size: usize) -> bool {
+        match self.left {
+            Some(left) if left <= frame_size => {
+                self.left = None;
+                self.finished = true;
+                true
+            }
+            Some(left) => {
+                self.left = Some(left - frame_size);
+                false
+            }
+            None => false,
+        }
+    }
+}
+
+/// Gain ramp of a playback channel from a fade event, linear in amplitude and sample by sample.
+/// The gain it ends on holds until the next fade.
+#[derive(Debug, Clone)]
+pub struct GainFade {
+    from: f32,
+    to: f32,
+    length: usize,
+    /// Samples of the fade gone by
+    position: usize,
+    running: bool,
+}
+
+impl Default for GainFade {
+    fn default() -> Self {
+        Self {
+            from: 1.0,
+            to: 1.0,
+            length: 0,
+            position: 0,
+            running: false,
+        }
+    }
+}
+
+impl GainFade {
+    /// Starts a fade of `length` samples to the gain factor `to`, from `from` or from the gain
+    /// the channel is at, which may be halfway through another fade.
+    pub fn start(&mut self, from: Option<f32>, to: f32, length: usize) {
+        *self = Self {
+            from: from.unwrap_or(self.gain(0)),
+            to,
+            length,
+            position: 0,
+            running: true,
+        };
+    }
+
+    /// Back to unity gain, e.g. when the transport is stopped.
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+
+    /// Gain factor `offset` samples into the current cycle.
+    fn gain(&self, offset: usize) -> f32 {
+        let position = self.position + offset;
+        if position >= self.length {
+            self.to
+        } else {
+            self.from + (self.to - self.from) * position as f32 / self.length as f32
+        }
+    }
+
+    /// Applies the part of the fade in the current cycle to a buffer.
+    pub fn apply(&self, buf: &mut [f32]) {
+        if !self.running && self.to == 1.0 {
+            return;
+        }
+        for (idx, sample) in buf.iter_mut().enumerate() {
+            *sample *= self.gain(idx);
+        }
+    }
+
+    /// Moves on by a cycle of `frame_size` samples. Returns true when a fade to silence has run
+    /// out and the clip is to be stopped.
+    pub fn advance(&mut self, frame_size: usize) -> bool {
+        if !self.running {
+            return false;
+        }
+        self.position = (self.position + frame_size).min(self.length);
+        if self.position < self.length {
+            return false;
+        }
+        self.running = false;
+        self.to == 0.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fades_then_stops() {
+        let mut fade = StopFade::default();
+        assert!(!fade.start(0));
+        assert!(fade.start(8));
+
+        let mut buf = [1.0; 4];
+        fade.apply(&mut buf);
+        assert_eq!(buf, [1.0, 0.875, 0.75, 0.625]);
+        assert!(!fade.advance(4));
+        // A second stop keeps the running fade
+        assert!(fade.start(100));
+
+        let mut buf = [1.0; 6];
+        fade.apply(&mut buf);
+        assert_eq!(buf, [0.5, 0.375, 0.25, 0.125, 0.0, 0.0]);
+        assert!(fade.advance(6));
+        // The held back stop goes through
+        assert!(!fade.start(8));
+    }
+
+    #[test]
+    fn gain_fades() {
+        let mut fade = GainFade::default();
+        let mut buf = [1.0; 4];
+        fade.apply(&mut buf);
+        assert_eq!(buf, [1.0; 4]);
+
+        fade.start(Some(0.0), 1.0, 8);
+        let mut buf = [1.0; 4];
+        fade.apply(&mut buf);
+        assert_eq!(buf, [0.0, 0.125, 0.25, 0.375]);
+        assert!(!fade.advance(4));
+        // Fading out halfway through the fade in starts from where it got to
+        fade.start(None, 0.0, 4);
+        let mut buf = [1.0; 6];
+        fade.apply(&mut buf);
+        assert_eq!(buf, [0.5, 0.375, 0.25, 0.125, 0.0, 0.0]);
+        assert!(fade.advance(6));
+
+        // Any other gain holds
+        fade.start(None, 0.5, 0);
+        assert!(!fade.advance(4));
+        let mut buf = [1.0; 4];
+        fade.apply(&mut buf);
+        assert_eq!(buf, [0.5; 4]);
+    }
+}
@@ -0,0 +1,164 @@
+use serde::{Deserialize, Serialize};
+
+/// Time constant of the onset envelope's decay.
+const ENVELOPE_RELEASE_S: f32 = 0.05;
+/// Weight of a new inter-onset interval in the smoothed tempo ratio.
+const RATIO_SMOOTHING: f32 = 0.25;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TempoFollowConfiguration {
+    pub enabled: bool,
+    /// Capture input to listen to, e.g. the drummer's click or kick mic
+    pub input: u8,
+    /// Largest deviation from the programmed tempo, in percent
+    pub max_deviation_percent: u8,
+    /// Input level an onset has to exceed, 1.0 being full scale
+    pub threshold: f32,
+}
+
+impl Default for TempoFollowConfiguration {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            input: 0,
+            max_deviation_percent: 5,
+            threshold: 0.3,
+        }
+    }
+}
+
+/// Detects onsets on a capture input and derives the tempo, in percent, that makes the programmed
+/// beat length match the time between them. Intervals that are too far off to be a beat (fills,
+/// missed hits) are ignored, and the tempo never leaves the configured deviation. Only the click
+/// follows the tempo, see `AudioSourceContext::tempo_percent`.
+#[derive(Debug, Clone, Default)]
+pub struct TempoFollower {
+    config: TempoFollowConfiguration,
+    envelope: f32,
+    /// Ready to detect the next onset, i.e. the envelope has fallen since the last one
+    armed: bool,
+    last_onset_us: Option<u64>,
+    /// Smoothed ratio of programmed beat length to detected beat interval
+    ratio: Option<f32>,
+}
+
+impl TempoFollower {
+    pub fn config(&self) -> TempoFollowConfiguration {
+        self.config
+    }
+
+    pub fn configure(&mut self, config: TempoFollowConfiguration) {
+        self.config = config;
+        self.reset();
+    }
+
+    /// Forget detected onsets, e.g. when the transport stops.
+    pub fn reset(&mut self) {
+        self.envelope = 0.0;
+        self.armed = true;
+        self.last_onset_us = None;
+        self.ratio = None;
+    }
+
+    /// Analyse one buffer of the input starting at `time_us`, with the programmed length of the
+    /// current beat in µs. Returns the tempo in percent to change to, if it should change.
+    pub fn process(
+        &mut self,
+        input: &[f32],
+        time_us: u64,
+        sample_rate: usize,
+        beat_length_us: f32,
+        tempo_percent: f32,
+    ) -> Option<f32> {
+        if !self.config.enabled || sample_rate == 0 || beat_length_us <= 0.0 {
+            return None;
+        }
+        let release = (-1.0 / (ENVELOPE_RELEASE_S * sample_rate as f32)).exp();
+        for (i, sample) in input.iter().enumerate() {
+            self.envelope = sample.abs().max(self.envelope * release);
+            if self.armed && self.envelope > self.config.threshold {
+                self.armed = false;
+                let onset_us = time_us + (i as u64 * 1_000_000) / sample_rate as u64;
+                self.onset(onset_us, beat_length_us);
+            } else if !self.armed && self.envelope < self.config.threshold / 2.0 {
+                self.armed = true;
+            }
+        }
+
+        let max_deviation = self.config.max_deviation_percent as f32;
+        let rate = (self.ratio? * 100.0)
+            .round()
+            .clamp(100.0 - max_deviation, 100.0 + max_deviation);
+        (rate != tempo_percent).then_some(rate)
+    }
+
+    fn onset(&mut self, onset_us: u64, beat_length_us: f32) {
+        if let Some(last_onset_us) = self.last_onset_us {
+            let interval_us = (onset_us - last_onset_us) as f32;
+            let ratio = beat_length_us / interval_us;
+            // Allow twice the configured deviation, so the follower can still catch up with a
+            // band that is already outside it
+            let tolerance = 2.0 * self.config.max_deviation_percent as f32 / 100.0;
+            if (ratio - 1.0).abs() <= tolerance {
+                self.ratio = Some(match self.ratio {
+                    Some(smoothed) => smoothed + RATIO_SMOOTHING * (ratio - smoothed),
+                    None => ratio,
+                });
+            }
+        }
+        self.last_onset_us = Some(onset_us);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Feed `beats` clicks, `interval_us` apart, in 10 ms buffers. Returns the resulting
+    /// tempo.
+    fn follow(follower: &mut TempoFollower, interval_us: u64, beats: u64) -> f32 {
+        let mut tempo = 100.0;
+        let mut time_us = 0;
+        while time_us < interval_us * beats {
+            let mut buf = [0.0f32; 480];
+            let click_in_us = (interval_us - time_us % interval_us) % interval_us;
+            if click_in_us < 10_000 {
+                buf[click_in_us as usize * 48 / 1000] = 1.0;
+            }
+            if let Some(rate) = follower.process(&buf, time_us, 48000, 500_000.0, tempo) {
+                tempo = rate;
+            }
+            time_us += 10_000;
+        }
+        playrate
+    }
+
+    #[test]
+    fn follows_within_limits() {
+        let mut follower = TempoFollower::default();
+        follower.configure(TempoFollowConfiguration {
+            enabled: true,
+            ..Default::default()
+        });
+
+        // Band 4 % fast
+        assert_eq!(follow(&mut follower, 480_000, 20), 104.0);
+
+        // Band 8 % fast, limited to the configured 5 %
+        follower.reset();
+        assert_eq!(follow(&mut follower, 463_000, 20), 105.0);
+    }
+
+    #[test]
+    fn ignores_fills() {
+        let mut follower = TempoFollower::default();
+        follower.configure(TempoFollowConfiguration {
+            enabled: true,
+            ..Default::default()
+        });
+
+        // Sixteenths are too far from the beat length to be taken as the tempo
+        assert_eq!(follow(&mut follower, 125_000, 40), 100.0);
+    }
+}
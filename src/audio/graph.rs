@@ -0,0 +1,161 @@
+use jack::{Client, PortFlags};
+use std::{collections::BTreeSet, sync::Mutex};
+
+/// Type of all ports the core registers and routes to.
+pub const AUDIO_PORT_TYPE: &str = "32 bit float mono audio";
+
+/// The parts of a running JACK client that [`AudioHandler`](super::handler::AudioHandler) uses
+/// to look at and change the routing, by port name. Implemented by `jack::Client`, and by
+/// [`MockGraph`] so the routing logic can be tested without a JACK server.
+pub trait PortGraph {
+    /// Names of the audio ports of the client named `client_name` that have all of `flags`.
+    fn port_names(&self, client_name: &str, flags: PortFlags) -> Vec<String>;
+    /// Whether two ports are connected, in either direction.
+    fn is_connected(&self, port: &str, other: &str) -> bool;
+    /// Connects an output port to an input port, or disconnects them.
+    fn connect(&self, source: &str, destination: &str, connect: bool) -> Result<(), String>;
+    fn buffer_size(&self) -> usize;
+    fn sample_rate(&self) -> usize;
+    fn name(&self) -> &str;
+    /// DSP load of the JACK server, in percent
+    fn cpu_load(&self) -> f32;
+}
+
+impl PortGraph for Client {
+    fn port_names(&self, client_name: &str, flags: PortFlags) -> Vec<String> {
+        self.ports(Some(client_name), Some(AUDIO_PORT_TYPE), flags)
+    }
+
+    fn is_connected(&self, port: &str, other: &str) -> bool {
+        self.port_by_name(port)
+            .is_some_and(|port| port.is_connected_to(other).unwrap_or_default())
+    }
+
+    fn connect(&self, source: &str, destination: &str, connect: bool) -> Result<(), String> {
+        let find = |name: &str| {
+            self.port_by_name(name)
+                .ok_or_else(|| format!("no port named {name}"))
+        };
+        let (source, destination) = (find(source)?, find(destination)?);
+        if connect {
+            self.connect_ports(&source, &destination)
+        } else {
+            self.disconnect_ports(&source, &destination)
+        }
+        .map_err(|err| err.to_string())
+    }
+
+    fn buffer_size(&self) -> usize {
+        Client::buffer_size(self) as usize
+    }
+
+    fn sample_rate(&self) -> usize {
+        Client::sample_rate(self) as usize
+    }
+
+    fn name(&self) -> &str {
+        Client::name(self)
+    }
+
+    fn cpu_load(&self) -> f32 {
+        Client::cpu_load(self)
+    }
+}
+
+/// A JACK graph held in memory, for tests and for tools without a JACK server. Ports are named
+/// `client:port` like in JACK.
+pub struct MockGraph {
+    ports: Vec<(String, PortFlags)>,
+    connections: Mutex<BTreeSet<(String, String)>>,
+    buffer_size: usize,
+    sample_rate: usize,
+}
+
+impl MockGraph {
+    pub fn new(buffer_size: usize, sample_rate: usize) -> Self {
+        Self {
+            ports: vec![],
+            connections: Mutex::new(BTreeSet::new()),
+            buffer_size,
+            sample_rate,
+        }
+    }
+
+    /// Adds `count` ports named `client:{prefix}{n}`, numbered from `first`.
+    pub fn with_ports(
+        mut self,
+        client_name: &str,
+        prefix: &str,
+        first: usize,
+        count: usize,
+        flags: PortFlags,
+    ) -> Self {
+        for n in first..first + count {
+            self.ports
+                .push((format!("{client_name}:{prefix}{n}"), flags));
+        }
+        self
+    }
+
+    fn has_port(&self, name: &str, flags: PortFlags) -> bool {
+        self.ports
+            .iter()
+            .any(|(port, port_flags)| port == name && port_flags.contains(flags))
+    }
+}
+
+impl PortGraph for MockGraph {
+    fn port_names(&self, client_name: &str, flags: PortFlags) -> Vec<String> {
+        self.ports
+            .iter()
+            .filter(|(name, port_flags)| {
+                name.split(':').next() == Some(client_name) && port_flags.contains(flags)
+            })
+            .map(|(name, _)| name.clone())
+            .collect()
+    }
+
+    fn is_connected(&self, port: &str, other: &str) -> bool {
+        let connections = self
+            .connections
+            .lock()
+            .unwrap_or_else(|err| err.into_inner());
+        connections.contains(&(port.to_string(), other.to_string()))
+            || connections.contains(&(other.to_string(), port.to_string()))
+    }
+
+    fn connect(&self, source: &str, destination: &str, connect: bool) -> Result<(), String> {
+        if !self.has_port(source, PortFlags::IS_OUTPUT)
+            || !self.has_port(destination, PortFlags::IS_INPUT)
+        {
+            return Err(format!("cannot connect {source} to {destination}"));
+        }
+        let mut connections = self
+            .connections
+            .lock()
+            .unwrap_or_else(|err| err.into_inner());
+        let connection = (source.to_string(), destination.to_string());
+        if connect {
+            connections.insert(connection);
+        } else {
+            connections.remove(&connection);
+        }
+        Ok(())
+    }
+
+    fn buffer_size(&self) -> usize {
+        self.buffer_size
+    }
+
+    fn sample_rate(&self) -> usize {
+        self.sample_rate
+    }
+
+    fn name(&self) -> &str {
+        "clicks-jack-client"
+    }
+
+    fn cpu_load(&self) -> f32 {
+        0.0
+    }
+}
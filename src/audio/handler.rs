@@ -1,8 +1,12 @@
 use crate::{
-    CrossbeamNetwork,
     audio::{
-        notification::JACKNotificationHandler, processor::AudioProcessor, source::SourceConfig,
+        graph::{AUDIO_PORT_TYPE, PortGraph},
+        notification::JACKNotificationHandler,
+        processor::{AudioProcessor, JackProcessor, ShowSwap},
+        source::{AudioSource, SourceConfig},
+        watchdog::{ROUTING_SETTLE, RoutingIntent, RoutingRepair},
     },
+    cbnet::CrossbeamNetwork,
 };
 use common::{
     cue::Show,
@@ -13,34 +17,130 @@ use common::{
     mem::str::StaticString,
     protocol::message::{LargeMessage, Message},
 };
-use jack::{AsyncClient, AudioOut, Client, ClientOptions, Port, PortFlags, Unowned};
+use jack::{AsyncClient, AudioIn, AudioOut, Client, ClientOptions, Port, PortFlags, Unowned};
+use serde::{Deserialize, Serialize};
+use std::{
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::Instant,
+};
+
+/// Bitmask of the system ports connected to a client port, one bit per port.
+pub type RouteMask = u64;
+/// System ports a client port can be routed to, as far as a `RouteMask` reaches.
+pub const MAX_ROUTE_TARGETS: usize = RouteMask::BITS as usize;
+/// Name of the JACK client of the core
+const CLIENT_NAME: &str = "clicks-jack-client";
+
+/// Connections to change to get from the routing `current` to `wanted`, both as per client
+/// output the system ports connected to it, as (output, system port, connect). Outputs left out
+/// of either are left alone.
+pub fn routing_changes(
+    wanted: &[RouteMask],
+    current: &[RouteMask],
+    system_ports: usize,
+) -> Vec<(u8, u8, bool)> {
+    let mut changes = vec![];
+    for (output, (wanted, current)) in wanted.iter().zip(current).enumerate() {
+        for port in 0..system_ports.min(RouteMask::BITS as usize) {
+            let wanted = wanted & (1 << port) != 0;
+            if wanted != (current & (1 << port) != 0) {
+                changes.push((output as u8, port as u8, wanted));
+            }
+        }
+    }
+    changes
+}
+
+/// Makes or removes the connection of client output `from` to system port `to` in `routing`,
+/// as per client output the system ports connected to it. Outputs added to reach `from` have no
+/// connections.
+pub fn set_route(routing: &mut Vec<RouteMask>, from: u8, to: u8, connect: bool) {
+    if to as u32 >= RouteMask::BITS {
+        return;
+    }
+    if routing.len() <= from as usize {
+        routing.resize(from as usize + 1, 0);
+    }
+    if connect {
+        routing[from as usize] |= 1 << to;
+    } else {
+        routing[from as usize] &= !(1 << to);
+    }
+}
+
+/// Capture side of the JACK client: the client input ports and the system capture ports that
+/// can be routed into them.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct InputStatus {
+    pub inputs: usize,
+    pub system_captures: usize,
+    /// Per client input, the system capture ports connected to it
+    pub connections: Vec<RouteMask>,
+}
 
 pub struct AudioHandler {
-    pub client: Option<AsyncClient<JACKNotificationHandler, AudioProcessor>>,
+    pub client: Option<AsyncClient<JACKNotificationHandler, JackProcessor>>,
     pub num_sources: usize,
+    /// Client output ports the sources are mixed onto
+    pub num_ports: usize,
+    pub num_inputs: usize,
     config: AudioConfiguration,
     jack_server_process: Option<std::process::Child>,
     cbnet: CrossbeamNetwork,
     pub jack_status: JACKStatus,
+    /// Stands in for the JACK client when none is running, see `with_graph`
+    graph: Option<Box<dyn PortGraph + Send>>,
+    /// Raised by the notification handler when JACK reports a change of the graph
+    graph_changed: Arc<AtomicBool>,
+    /// When the graph last changed, while it has not been checked since
+    graph_changed_at: Option<Instant>,
+    /// Connections the core made or removed between client outputs and system ports
+    output_intent: RoutingIntent,
+    /// Connections the core made or removed between system captures and client inputs
+    input_intent: RoutingIntent,
 }
 
 impl AudioHandler {
-    pub fn new(num_sources: usize, cbnet: CrossbeamNetwork) -> AudioHandler {
+    pub fn new(
+        num_sources: usize,
+        num_ports: usize,
+        num_inputs: usize,
+        cbnet: CrossbeamNetwork,
+    ) -> AudioHandler {
         AudioHandler {
             jack_status: JACKStatus::default(),
             cbnet,
             client: None,
             num_sources,
+            num_ports,
+            num_inputs,
             config: AudioConfiguration::default(),
             jack_server_process: None,
+            graph: None,
+            graph_changed: Arc::new(AtomicBool::new(false)),
+            graph_changed_at: None,
+            output_intent: RoutingIntent::default(),
+            input_intent: RoutingIntent::default(),
         }
     }
 
+    /// Looks at and routes the ports of `graph` while no JACK client runs, e.g. a
+    /// [`MockGraph`](super::graph::MockGraph) in tests.
+    pub fn with_graph(mut self, graph: impl PortGraph + Send + 'static) -> Self {
+        self.graph = Some(Box::new(graph));
+        self
+    }
+
     pub fn configure(&mut self, config: AudioConfiguration) {
         self.config = config
     }
 
-    pub fn start(&mut self, sources: Vec<SourceConfig>, show: Show) {
+    /// Starts the JACK server and client, and routes the client outputs as in `routing`, the
+    /// routing saved when the core last ran.
+    pub fn start(&mut self, sources: Vec<SourceConfig>, show: Show, routing: &[RouteMask]) {
         self.start_server();
         std::thread::sleep(std::time::Duration::from_secs(5));
         let client_res = self.start_client();
@@ -56,12 +156,19 @@ impl AudioHandler {
             }
             Ok(client) => client,
         };
-        let mut ports: (Vec<Port<AudioOut>>, Vec<Port<Unowned>>) = (vec![], vec![]);
-        ports.0 = self.init_client_ports(&client);
-        ports.1 = self.collect_system_ports(&client);
+        let ports = self.init_client_ports(&client);
+        let inputs = self.init_client_input_ports(&client);
+        self.collect_system_ports(&client);
 
-        let processor = AudioProcessor::new(sources, ports, self.cbnet.clone(), show);
-        let ac = match client.activate_async(JACKNotificationHandler, processor) {
+        let mut processor = AudioProcessor::new(sources, self.cbnet.clone(), show);
+        // JACK reports changes later on through the buffer size callback
+        processor.set_max_frame_size(client.buffer_size() as usize);
+        processor.set_output_ports(ports.len());
+        let processor = JackProcessor::new(processor, inputs, ports);
+        let notification_handler = JACKNotificationHandler {
+            graph_changed: self.graph_changed.clone(),
+        };
+        let ac = match client.activate_async(notification_handler, processor) {
             Ok(val) => val,
             Err(err) => {
                 self.cbnet.log(LogItem::new(
@@ -73,101 +180,114 @@ impl AudioHandler {
             }
         };
         self.client = Some(ac);
+        if !self.route_to(routing) {
+            self.cbnet.log(LogItem::new(
+                "Could not restore all of the saved routing".to_string(),
+                LogContext::AudioHandler,
+                LogKind::Error,
+            ));
+        }
     }
 
-    fn get_ports(&self) -> (Vec<Port<Unowned>>, Vec<Port<Unowned>>) {
-        if self.client.is_none() {
-            return (vec![], vec![]);
+    /// Hands another show and the playback sources playing its media to the running client,
+    /// which carries on without a restart. Returns false if the client is not running.
+    pub fn swap_show(&mut self, show: Show, mut playback: Vec<Box<dyn AudioSource>>) -> bool {
+        let Some(client) = &self.client else {
+            return false;
+        };
+        let frames = client.as_client().buffer_size() as usize;
+        for device in &mut playback {
+            device.set_max_frame_size(frames);
         }
-        let mut ports: (Vec<Port<Unowned>>, Vec<Port<Unowned>>) = (vec![], vec![]);
-        let client = self
-            .client
-            .as_ref()
-            .expect("Client is none is handled.")
-            .as_client();
-        let mut i_ports = client.ports(
-            Some("clicks-jack-client"),
-            Some("32 bit float mono audio"),
-            PortFlags::IS_OUTPUT,
-        );
-        i_ports.sort_by_key(|name| {
-            let mut new_name = name.clone();
-            new_name.retain(|c| c.is_numeric());
-            new_name.parse::<usize>().unwrap_or_default()
-        });
-        ports.0 = i_ports
-            .iter()
-            .map(|name| {
-                client
-                    .port_by_name(name)
-                    .expect("Port names are unexposed and should never be incorrect.")
-            })
-            .collect();
+        self.cbnet.swap_show(ShowSwap { show, playback });
+        true
+    }
 
-        let mut o_ports = client.ports(
-            Some(self.config.server.system_name.str()),
-            Some("32 bit float mono audio"),
-            PortFlags::IS_INPUT,
-        );
-        o_ports.sort_by_key(|name| {
+    /// The JACK client while it runs, or the graph standing in for it.
+    fn graph(&self) -> Option<&dyn PortGraph> {
+        match &self.client {
+            Some(client) => Some(client.as_client() as &dyn PortGraph),
+            None => self.graph.as_deref().map(|graph| graph as &dyn PortGraph),
+        }
+    }
+
+    /// Audio ports of a client with `flags`, in the order of their number.
+    fn port_names(graph: &dyn PortGraph, client_name: &str, flags: PortFlags) -> Vec<String> {
+        let mut names = graph.port_names(client_name, flags);
+        names.sort_by_key(|name| {
             let mut new_name = name.clone();
             new_name.retain(|c| c.is_numeric());
             new_name.parse::<usize>().unwrap_or_default()
         });
-        ports.1 = o_ports
-            .iter()
-            .map(|name| {
-                client
-                    .port_by_name(name)
-                    .expect("Port names are unexposed and should never be incorrect.")
-            })
-            .collect();
-
-        ports
+        names
     }
 
-    pub fn get_connections(&self) -> [u32; 32] {
-        let ports = self.get_ports();
-        let mut out = [0u32; 32];
-        for (a_idx, a_port) in ports.0.iter().enumerate() {
-            for (b_idx, b_port) in ports.1.iter().enumerate() {
-                if a_port
-                    .is_connected_to(
-                        &b_port
-                            .name()
-                            .expect("Port names are unexposed and should never be incorrect."),
-                    )
-                    .unwrap_or_default()
-                {
-                    out[a_idx] |= 0x01 << b_idx;
-                }
-            }
-        }
-        out
+    /// Client output ports and system playback ports.
+    fn get_ports(&self) -> (Vec<String>, Vec<String>) {
+        let Some(graph) = self.graph() else {
+            return (vec![], vec![]);
+        };
+        (
+            Self::port_names(graph, CLIENT_NAME, PortFlags::IS_OUTPUT),
+            Self::port_names(
+                graph,
+                self.config.server.system_name.str(),
+                PortFlags::IS_INPUT,
+            ),
+        )
     }
 
-    pub fn try_route_ports(&mut self, from: u8, to: u8, connect: bool) -> bool {
-        let ports = self.get_ports();
-        let p_from = ports.0[from as usize].clone();
-        let p_to = ports.1[to as usize].clone();
-        let client = match &self.client {
-            Some(val) => val.as_client(),
-            None => return false,
+    /// Client input ports and system capture ports.
+    fn get_input_ports(&self) -> (Vec<String>, Vec<String>) {
+        let Some(graph) = self.graph() else {
+            return (vec![], vec![]);
         };
-        let res = if connect {
-            client.connect_ports(&p_from, &p_to)
-        } else {
-            client.disconnect_ports(&p_from, &p_to)
+        (
+            Self::port_names(graph, CLIENT_NAME, PortFlags::IS_INPUT),
+            Self::port_names(
+                graph,
+                self.config.server.system_name.str(),
+                PortFlags::IS_OUTPUT,
+            ),
+        )
+    }
+
+    /// Per port of `ports`, the ports of `targets` connected to it.
+    fn connection_masks(&self, ports: &[String], targets: &[String]) -> Vec<RouteMask> {
+        let Some(graph) = self.graph() else {
+            return vec![];
         };
+        ports
+            .iter()
+            .map(|port| {
+                targets
+                    .iter()
+                    .take(MAX_ROUTE_TARGETS)
+                    .enumerate()
+                    .filter(|(_, target)| graph.is_connected(port, target))
+                    .fold(0 as RouteMask, |mask, (idx, _)| mask | 0x01 << idx)
+            })
+            .collect()
+    }
 
-        match res {
-            Ok(_) => {
+    /// Connects or disconnects two ports, logging the outcome as `description`.
+    fn try_connect(
+        &self,
+        source: &str,
+        destination: &str,
+        connect: bool,
+        description: String,
+    ) -> bool {
+        let Some(graph) = self.graph() else {
+            return false;
+        };
+        match graph.connect(source, destination, connect) {
+            Ok(()) => {
                 self.cbnet.log(LogItem::new(
-                    format!("Set port [{from}] -> [{to}] to {connect}"),
+                    format!("Set {description} to {connect}"),
                     LogContext::AudioHandler,
                     LogKind::Note,
                 ));
-
                 true
             }
             Err(err) => {
@@ -181,8 +301,141 @@ impl AudioHandler {
         }
     }
 
+    /// Number of client input and system capture ports, or None if the client is not running.
+    pub fn input_io_size(&self) -> Option<(usize, usize)> {
+        self.graph()?;
+        let ports = self.get_input_ports();
+        Some((ports.0.len(), ports.1.len()))
+    }
+
+    pub fn get_input_status(&self) -> InputStatus {
+        let (inputs, captures) = self.get_input_ports();
+        InputStatus {
+            inputs: inputs.len(),
+            system_captures: captures.len(),
+            connections: self.connection_masks(&inputs, &captures),
+        }
+    }
+
+    /// Connect or disconnect system capture port `from` to client input `to`.
+    pub fn try_route_input(&mut self, from: u8, to: u8, connect: bool) -> bool {
+        let (inputs, captures) = self.get_input_ports();
+        let (Some(p_from), Some(p_to)) = (captures.get(from as usize), inputs.get(to as usize))
+        else {
+            return false;
+        };
+        let connected = self.try_connect(
+            p_from,
+            p_to,
+            connect,
+            format!("capture [{from}] -> input [{to}]"),
+        );
+        if connected {
+            self.input_intent
+                .record(to as usize, from as usize, connect);
+        }
+        connected
+    }
+
+    /// Number of client output and system input ports, or None if the client is not running.
+    pub fn io_size(&self) -> Option<(usize, usize)> {
+        self.graph()?;
+        let ports = self.get_ports();
+        Some((ports.0.len(), ports.1.len()))
+    }
+
+    /// Sample rate the client runs and the processor is called at, or None if the client is not
+    /// running.
+    pub fn sample_rate(&self) -> Option<usize> {
+        self.graph().map(|graph| graph.sample_rate())
+    }
+
+    /// Per client output, the system ports connected to it.
+    pub fn get_connections(&self) -> Vec<RouteMask> {
+        let (outputs, playbacks) = self.get_ports();
+        self.connection_masks(&outputs, &playbacks)
+    }
+
+    pub fn try_route_ports(&mut self, from: u8, to: u8, connect: bool) -> bool {
+        let ports = self.get_ports();
+        let (Some(p_from), Some(p_to)) = (ports.0.get(from as usize), ports.1.get(to as usize))
+        else {
+            return false;
+        };
+        let connected = self.try_connect(p_from, p_to, connect, format!("port [{from}] -> [{to}]"));
+        if connected {
+            self.output_intent
+                .record(from as usize, to as usize, connect);
+        }
+        connected
+    }
+
+    /// Routes the client outputs as in `routing`, per output the system ports connected to it.
+    /// Returns false if a connection could not be changed, or the client is not running while
+    /// there is routing to set.
+    pub fn route_to(&mut self, routing: &[RouteMask]) -> bool {
+        let Some((_, system_ports)) = self.io_size() else {
+            return routing.is_empty();
+        };
+        let mut routed = true;
+        for (from, to, connect) in routing_changes(routing, &self.get_connections(), system_ports) {
+            routed &= self.try_route_ports(from, to, connect);
+        }
+        routed
+    }
+
+    /// Looks for connections the core made or removed that have been changed by someone else,
+    /// once the graph has not changed for `ROUTING_SETTLE`, and puts them back if `restore`.
+    /// Returns what was found, which is nothing most of the time.
+    pub fn watch_routing(&mut self, restore: bool, now: Instant) -> Vec<RoutingRepair> {
+        if self.graph_changed.swap(false, Ordering::Acquire) {
+            self.graph_changed_at = Some(now);
+        }
+        match self.graph_changed_at {
+            Some(at) if now.duration_since(at) >= ROUTING_SETTLE => self.graph_changed_at = None,
+            _ => return vec![],
+        }
+        let mut repairs = vec![];
+        for input in [false, true] {
+            let ((ports, targets), intent) = if input {
+                (self.get_input_ports(), &self.input_intent)
+            } else {
+                (self.get_ports(), &self.output_intent)
+            };
+            let current = self.connection_masks(&ports, &targets);
+            for (port, target, connect) in intent.repairs(&current) {
+                // A system port that is gone, e.g. with an unplugged sound card, cannot be
+                // connected again
+                let restored = restore
+                    && targets.get(target).is_some_and(|target| {
+                        let (source, destination) = if input {
+                            (target, &ports[port])
+                        } else {
+                            (&ports[port], target)
+                        };
+                        self.graph().is_some_and(|graph| {
+                            graph.connect(source, destination, connect).is_ok()
+                        })
+                    });
+                let (from, to) = if input {
+                    (target, port)
+                } else {
+                    (port, target)
+                };
+                repairs.push(RoutingRepair {
+                    input,
+                    from: from as u8,
+                    to: to as u8,
+                    connect,
+                    restored,
+                });
+            }
+        }
+        repairs
+    }
+
     pub fn get_jack_status(&mut self) -> JACKStatus {
-        self.jack_status.running = self.client.is_some();
+        self.jack_status.running = self.graph().is_some();
         let devices: [Option<AudioDevice>; 32] = std::array::from_fn(|i| {
             self.get_hw_devices()
                 .get(i)
@@ -193,18 +446,24 @@ impl AudioHandler {
 
         self.jack_status.available_devices = devices;
 
-        if self.jack_status.running {
-            let client = match &self.client {
-                Some(val) => val.as_client(),
-                None => return self.jack_status,
-            };
-            self.jack_status.io_size = (self.get_ports().0.len(), self.get_ports().1.len());
-            self.jack_status.buffer_size = client.buffer_size() as usize;
-            self.jack_status.sample_rate = client.sample_rate() as usize;
+        if let Some(graph) = self.graph() {
+            let (buffer_size, sample_rate) = (graph.buffer_size(), graph.sample_rate());
+            let client_name = StaticString::new(graph.name());
+            let (outputs, playbacks) = self.get_ports();
+            self.jack_status.io_size = (outputs.len(), playbacks.len());
+            self.jack_status.buffer_size = buffer_size;
+            self.jack_status.sample_rate = sample_rate;
             self.jack_status.frame_size = 0;
-            self.jack_status.client_name = StaticString::new(client.name());
+            self.jack_status.client_name = client_name;
             self.jack_status.output_name = self.config.server.system_name;
-            self.jack_status.connections = self.get_connections();
+            // The JACK status has room for the first 32 ports of the first 32 outputs only, the
+            // full routing is sent in `CoreMessage::Routing`
+            let connections = self.get_connections();
+            self.jack_status.connections = std::array::from_fn(|idx| {
+                connections
+                    .get(idx)
+                    .map_or(0, |mask| (mask & u32::MAX as RouteMask) as u32)
+            });
         }
         self.jack_status
     }
@@ -217,17 +476,24 @@ impl AudioHandler {
     }
 
     pub fn start_server(&mut self) {
-        self.jack_server_process = std::process::Command::new("jackd")
-            .arg("-R")
+        let mut command = std::process::Command::new("jackd");
+        command.arg("-R");
+        // Sound cards are only driven through ALSA on Linux. Elsewhere, e.g. on the laptops of
+        // client developers, the server runs on the dummy backend
+        #[cfg(target_os = "linux")]
+        command
             .args(["-d", "alsa"])
-            .args(["-d", self.config.server.device_id.str()])
+            .args(["-d", self.config.server.device_id.str()]);
+        #[cfg(not(target_os = "linux"))]
+        command.args(["-d", "dummy"]);
+        self.jack_server_process = command
             .args(["-r", &self.config.server.sample_rate.to_string()])
             .spawn()
             .ok()
     }
 
     pub fn start_client(&mut self) -> Result<Client, jack::Error> {
-        let client_res = Client::new("clicks-jack-client", ClientOptions::NO_START_SERVER);
+        let client_res = Client::new(CLIENT_NAME, ClientOptions::NO_START_SERVER);
         match client_res {
             Err(err) => Err(err),
             Ok((client, status)) => {
@@ -245,7 +511,7 @@ impl AudioHandler {
         let mut ports = vec![];
         // Register io_matrix.0 amount of ports on the client and save for processor
         // reference
-        for c_out_idx in 0..self.num_sources {
+        for c_out_idx in 0..self.num_ports {
             ports.push(
                 client
                     .register_port(&c_out_idx.to_string(), AudioOut::default())
@@ -255,10 +521,20 @@ impl AudioHandler {
         ports
     }
 
+    pub fn init_client_input_ports(&self, client: &Client) -> Vec<Port<AudioIn>> {
+        (0..self.num_inputs)
+            .map(|idx| {
+                client
+                    .register_port(&format!("in_{idx}"), AudioIn::default())
+                    .expect("Port register failed")
+            })
+            .collect()
+    }
+
     pub fn collect_system_ports(&self, client: &Client) -> Vec<Port<Unowned>> {
         let mut ports = client.ports(
             Some(self.config.server.system_name.str()),
-            Some("32 bit float mono audio"),
+            Some(AUDIO_PORT_TYPE),
             PortFlags::IS_INPUT,
         );
         ports.sort_by_key(|name| {
@@ -287,6 +563,7 @@ impl AudioHandler {
             .notify(Message::Large(LargeMessage::JACKStateChanged(status)));
     }
 
+    #[cfg(target_os = "linux")]
     pub fn get_hw_devices(&self) -> Vec<AudioDevice> {
         let output = std::process::Command::new("aplay")
             .arg("--list-devices")
@@ -307,10 +584,107 @@ impl AudioHandler {
         devices
     }
 
+    /// Sound cards are only listed on Linux, the dummy backend used elsewhere has none.
+    #[cfg(not(target_os = "linux"))]
+    pub fn get_hw_devices(&self) -> Vec<AudioDevice> {
+        vec![]
+    }
+
     pub fn get_cpu_use(&self) -> f32 {
-        match &self.client {
-            Some(val) => val.as_client().cpu_load(),
-            None => 0.0,
-        }
+        self.graph().map_or(0.0, |graph| graph.cpu_load())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audio::graph::MockGraph;
+
+    #[test]
+    fn routing_without_jack() {
+        let graph = MockGraph::new(256, 48000)
+            .with_ports(CLIENT_NAME, "", 0, 3, PortFlags::IS_OUTPUT)
+            .with_ports(CLIENT_NAME, "in_", 0, 2, PortFlags::IS_INPUT)
+            .with_ports("system", "playback_", 1, 12, PortFlags::IS_INPUT)
+            .with_ports("system", "capture_", 1, 4, PortFlags::IS_OUTPUT);
+        let mut ah = AudioHandler::new(3, 3, 2, CrossbeamNetwork::new()).with_graph(graph);
+        ah.config.server.system_name = StaticString::new("system");
+        assert_eq!(ah.io_size(), Some((3, 12)));
+        assert_eq!(ah.sample_rate(), Some(48000));
+
+        // Ports are taken in the order of their number, so playback_10 comes after playback_9
+        assert!(ah.try_route_ports(1, 9, true));
+        assert!(ah.try_route_ports(1, 0, true));
+        assert!(ah.try_route_ports(1, 0, false));
+        assert!(!ah.try_route_ports(3, 0, true));
+        assert_eq!(ah.get_connections(), vec![0, 1 << 9, 0]);
+
+        // Saved routing, with an output that is no longer there
+        assert!(ah.route_to(&[0b11, 1 << 10, 0b1, 0b1]));
+        assert_eq!(ah.get_connections(), vec![0b11, 1 << 10, 0b1]);
+
+        let mut routing = ah.get_connections();
+        set_route(&mut routing, 1, 10, false);
+        set_route(&mut routing, 3, 2, true);
+        set_route(&mut routing, 0, 64, true);
+        assert_eq!(routing, vec![0b11, 0, 0b1, 0b100]);
+
+        assert!(ah.try_route_input(3, 1, true));
+        assert_eq!(
+            ah.get_input_status(),
+            InputStatus {
+                inputs: 2,
+                system_captures: 4,
+                connections: vec![0, 1 << 3],
+            }
+        );
+    }
+
+    #[test]
+    fn routing_watchdog() {
+        let graph = MockGraph::new(256, 48000)
+            .with_ports(CLIENT_NAME, "", 0, 2, PortFlags::IS_OUTPUT)
+            .with_ports(CLIENT_NAME, "in_", 0, 1, PortFlags::IS_INPUT)
+            .with_ports("system", "playback_", 1, 4, PortFlags::IS_INPUT)
+            .with_ports("system", "capture_", 1, 2, PortFlags::IS_OUTPUT);
+        let mut ah = AudioHandler::new(2, 2, 1, CrossbeamNetwork::new()).with_graph(graph);
+        ah.config.server.system_name = StaticString::new("system");
+        assert!(ah.try_route_ports(0, 1, true));
+        assert!(ah.try_route_input(1, 0, true));
+        let (outputs, playbacks) = ah.get_ports();
+        let (inputs, captures) = ah.get_input_ports();
+
+        // Another client takes the output and the input over
+        let graph = ah.graph().unwrap();
+        graph.connect(&outputs[0], &playbacks[1], false).unwrap();
+        graph.connect(&outputs[0], &playbacks[3], true).unwrap();
+        graph.connect(&captures[1], &inputs[0], false).unwrap();
+        ah.graph_changed.store(true, Ordering::Release);
+        let now = Instant::now();
+        assert!(ah.watch_routing(true, now).is_empty());
+        assert_eq!(
+            ah.watch_routing(true, now + ROUTING_SETTLE),
+            vec![
+                RoutingRepair {
+                    input: false,
+                    from: 0,
+                    to: 1,
+                    connect: true,
+                    restored: true,
+                },
+                RoutingRepair {
+                    input: true,
+                    from: 1,
+                    to: 0,
+                    connect: true,
+                    restored: true,
+                },
+            ]
+        );
+        // The connection the core never made stays
+        assert_eq!(ah.get_connections(), vec![0b1010, 0]);
+        assert_eq!(ah.get_input_status().connections, vec![0b10]);
+        // Checked once per change
+        assert!(ah.watch_routing(true, now + ROUTING_SETTLE * 2).is_empty());
     }
 }
@@ -1,19 +1,301 @@
 use crate::audio;
-use crate::audio::source::AudioSourceContext;
+use crate::audio::accent::{Accent, beat_accent};
+use crate::audio::resample::Resampler;
+use crate::audio::source::{AudioSourceContext, DEFAULT_MAX_FRAME_SIZE, MAX_SAMPLE_RATE};
+use crate::audio::stream::read_samples;
+use crate::communication::extension::CoreControlAction;
+use arc_swap::ArcSwap;
 use common::event::{EventDescription, JumpModeChange, JumpRequirement};
 use common::local::status::{AudioSourceState, BeatState, TransportState};
 use common::protocol::message::{Message, SmallMessage};
 use common::protocol::request::ControlAction;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::sync::Arc;
 
-struct MetronomeClick {
-    frequency: usize,
-    length: usize,
+/// Beat positions are counted in millionths of a frame, so beats programmed in microseconds
+/// fall on exact positions at any sample rate and no rounding builds up from beat to beat.
+pub const TICKS_PER_FRAME: u64 = 1_000_000;
+
+/// Longest click, in milliseconds.
+pub const MAX_CLICK_MS: usize = 50;
+/// Longest click in samples, at the highest sample rate.
+const MAX_CLICK_SAMPLES: usize = MAX_CLICK_MS * MAX_SAMPLE_RATE / 1000;
+
+/// Quietest click level, in dB relative to full scale.
+pub const MIN_CLICK_LEVEL_DB: f32 = -60.0;
+
+/// Levels of the click in dB relative to full scale, so the "1" can be made to punch through
+/// loud passages.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ClickLevels {
+    /// Level of the first beat of a bar
+    pub accent_db: f32,
+    /// Level of the other beats
+    pub beat_db: f32,
+}
+
+impl Default for ClickLevels {
+    fn default() -> Self {
+        Self {
+            accent_db: -20.0,
+            beat_db: -20.0,
+        }
+    }
+}
+
+impl ClickLevels {
+    /// Whether both levels are between `MIN_CLICK_LEVEL_DB` and full scale.
+    pub fn is_valid(&self) -> bool {
+        [self.accent_db, self.beat_db]
+            .iter()
+            .all(|level_db| (MIN_CLICK_LEVEL_DB..=0.0).contains(level_db))
+    }
+
+    /// Amplitudes of the strong, medium and weak click. The medium click is halfway between
+    /// the other two in dB.
+    fn amplitudes(&self) -> [f32; 3] {
+        let medium_db = (self.accent_db + self.beat_db) / 2.0;
+        [self.accent_db, medium_db, self.beat_db].map(|level_db| 10.0f32.powf(level_db / 20.0))
+    }
+}
+
+/// Lowest and highest click frequency, in Hz.
+pub const CLICK_FREQUENCY_RANGE: std::ops::RangeInclusive<f32> = 20.0..=20_000.0;
+/// Slowest click decay, in milliseconds.
+pub const MAX_CLICK_DECAY_MS: f32 = 1000.0;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ClickWaveform {
+    #[default]
+    Sine,
+    Square,
+    Triangle,
+    /// White noise, for a woodblock-like tick; the frequency is ignored
+    Noise,
+}
+
+impl ClickWaveform {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "sine" => Some(Self::Sine),
+            "square" => Some(Self::Square),
+            "triangle" => Some(Self::Triangle),
+            "noise" => Some(Self::Noise),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Sine => "sine",
+            Self::Square => "square",
+            Self::Triangle => "triangle",
+            Self::Noise => "noise",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ClickSound {
+    pub frequency_hz: f32,
+    pub length_ms: f32,
+    /// Waveform of this click instead of that of all clicks, e.g. to set the "1" apart
+    #[serde(default)]
+    pub waveform: Option<ClickWaveform>,
+}
+
+/// How the clicks are synthesized. Stored in the core configuration.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ClickSynthesis {
+    pub waveform: ClickWaveform,
+    /// Strong, medium and weak click
+    pub clicks: [ClickSound; 3],
+    /// Time in which a click decays to about a third (1/e), 0 to keep it at full level to the
+    /// end
+    pub decay_ms: f32,
+}
+
+impl Default for ClickSynthesis {
+    fn default() -> Self {
+        let click = |frequency_hz| ClickSound {
+            frequency_hz,
+            length_ms: 4.0,
+            waveform: None,
+        };
+        Self {
+            waveform: ClickWaveform::Sine,
+            clicks: [click(2000.0), click(1500.0), click(1000.0)],
+            decay_ms: 0.0,
+        }
+    }
+}
+
+impl ClickSynthesis {
+    /// Whether all frequencies are audible, all clicks at most `MAX_CLICK_MS` long and the
+    /// decay at most `MAX_CLICK_DECAY_MS`.
+    pub fn is_valid(&self) -> bool {
+        self.clicks.iter().all(|click| {
+            CLICK_FREQUENCY_RANGE.contains(&click.frequency_hz)
+                && click.length_ms > 0.0
+                && click.length_ms <= MAX_CLICK_MS as f32
+        }) && (0.0..=MAX_CLICK_DECAY_MS).contains(&self.decay_ms)
+    }
+
+    /// Length of a click in samples.
+    fn length(&self, click: usize, sample_rate: usize) -> usize {
+        ((self.clicks[click].length_ms * sample_rate as f32 / 1000.0) as usize)
+            .min(MAX_CLICK_SAMPLES)
+    }
+
+    /// Writes a click at full scale into the start of `out`, as far as it reaches, and returns
+    /// its length. Never allocates.
+    fn render(&self, click: usize, sample_rate: usize, out: &mut [f32]) -> usize {
+        let length = self.length(click, sample_rate).min(out.len());
+        let sample_rate = sample_rate.max(1) as f32;
+        let frequency = self.clicks[click].frequency_hz;
+        let waveform = self.clicks[click].waveform.unwrap_or(self.waveform);
+        let decay_samples = self.decay_ms * sample_rate / 1000.0;
+        // Fixed seed, so a click sounds the same every time it is generated
+        let mut noise: u32 = 0x9E37_79B9;
+        for (i, sample) in out.iter_mut().enumerate().take(length) {
+            let phase = (i as f32 * frequency / sample_rate).fract();
+            let wave = match waveform {
+                ClickWaveform::Sine => (phase * std::f32::consts::TAU).sin(),
+                ClickWaveform::Square => {
+                    if phase < 0.5 {
+                        1.0
+                    } else {
+                        -1.0
+                    }
+                }
+                ClickWaveform::Triangle => 1.0 - 4.0 * (phase - 0.5).abs(),
+                ClickWaveform::Noise => {
+                    noise ^= noise << 13;
+                    noise ^= noise >> 17;
+                    noise ^= noise << 5;
+                    noise as f32 / u32::MAX as f32 * 2.0 - 1.0
+                }
+            };
+            let envelope = if decay_samples > 0.0 {
+                (-(i as f32) / decay_samples).exp()
+            } else {
+                1.0
+            };
+            *sample = wave * envelope;
+        }
+        length
+    }
+}
+
+/// WAV files played instead of the synthesized strong, medium and weak click, by path within
+/// the show directory. None keeps the synthesized click.
+pub type ClickSamples = [Option<String>; 3];
+
+/// Reads a click sample mixed down to mono, converted to `sample_rate` and cut to
+/// `MAX_CLICK_MS`.
+fn read_sample(path: &Path, sample_rate: usize) -> Result<Vec<f32>, hound::Error> {
+    let mut reader = hound::WavReader::open(path)?;
+    let spec = reader.spec();
+    let channels = spec.channels.max(1) as usize;
+    // Only as much as the longest click is read, however long the file
+    let frames = MAX_CLICK_MS * spec.sample_rate as usize / 1000;
+    let mono: Vec<f32> = read_samples(&mut reader, frames * channels)?
+        .chunks(channels)
+        .map(|frame| frame.iter().sum::<f32>() / channels as f32)
+        .collect();
+    let mut sound = match Resampler::new(spec.sample_rate, sample_rate as u32) {
+        Some(resampler) => resampler.convert(&mono),
+        None => mono,
+    };
+    sound.truncate((MAX_CLICK_MS * sample_rate / 1000).min(MAX_CLICK_SAMPLES));
+    Ok(sound)
+}
+
+/// Strong, medium and weak click at full scale, synthesized or read off the RT thread.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClickSounds {
+    synthesis: ClickSynthesis,
+    sample_rate: usize,
+    sounds: [Vec<f32>; 3],
+    /// Which clicks are samples rather than synthesized
+    sampled: [bool; 3],
+}
+
+impl ClickSounds {
+    pub fn synthesize(synthesis: ClickSynthesis, sample_rate: usize) -> Self {
+        Self {
+            synthesis,
+            sample_rate,
+            sounds: std::array::from_fn(|click| {
+                let mut sound = vec![0.0; synthesis.length(click, sample_rate)];
+                synthesis.render(click, sample_rate, &mut sound);
+                sound
+            }),
+            sampled: [false; 3],
+        }
+    }
+
+    /// The synthesized clicks, each replaced by its sample from the show directory if it has
+    /// one. Clicks whose sample cannot be read are synthesized, and the errors returned.
+    pub fn load(
+        synthesis: ClickSynthesis,
+        samples: &ClickSamples,
+        show_path: &Path,
+        sample_rate: usize,
+    ) -> (Self, Vec<String>) {
+        let mut sounds = Self::synthesize(synthesis, sample_rate);
+        let mut errors = vec![];
+        for (click, sample) in samples.iter().enumerate() {
+            let Some(sample) = sample else {
+                continue;
+            };
+            let path = show_path.join(sample);
+            match read_sample(&path, sample_rate) {
+                Ok(sound) => {
+                    sounds.sounds[click] = sound;
+                    sounds.sampled[click] = true;
+                }
+                Err(err) => errors.push(format!("{}: {err}", path.display())),
+            }
+        }
+        (sounds, errors)
+    }
+}
+
+/// The clicks all metronomes play, shared with the main loop, which swaps in newly synthesized
+/// clicks when they are edited.
+pub type ClickBank = Arc<ArcSwap<ClickSounds>>;
+
+pub fn click_bank(synthesis: ClickSynthesis, sample_rate: usize) -> ClickBank {
+    Arc::new(ArcSwap::from_pointee(ClickSounds::synthesize(
+        synthesis,
+        sample_rate,
+    )))
 }
 
 pub struct Metronome {
-    clicks: Vec<MetronomeClick>,
-    click_buffers: [[f32; 96000]; 2],
-    last_beat_time: u64,
+    bank: ClickBank,
+    /// Address of the clicks the buffers were last made from, to notice when the bank is
+    /// swapped
+    sounds: usize,
+    /// Strong, medium and weak click, each padded with silence to the largest cycle size past
+    /// the longest click
+    click_buffers: [Vec<f32>; 3],
+    /// Length of each click in samples
+    click_lengths: [usize; 3],
+    /// Click being played and how far into its buffer, while it runs on past the cycle it
+    /// started in, as clicks at high sample rates may outlast a small cycle
+    playing: Option<(usize, usize)>,
+    levels: ClickLevels,
+    /// Sample rate the clicks were generated at
+    sample_rate: usize,
+    /// Position of the last beat in ticks of the frame clock, None until the first beat after
+    /// the transport is started or moved
+    last_beat: Option<u64>,
     state: BeatState,
     transport: TransportState,
 }
@@ -21,18 +303,18 @@ pub struct Metronome {
 impl Default for Metronome {
     fn default() -> Metronome {
         Metronome {
-            clicks: vec![
-                MetronomeClick {
-                    length: 4,
-                    frequency: 2000,
-                },
-                MetronomeClick {
-                    length: 4,
-                    frequency: 1000,
-                },
+            bank: click_bank(ClickSynthesis::default(), 48000),
+            sounds: 0,
+            click_lengths: [0; 3],
+            last_beat: None,
+            levels: ClickLevels::default(),
+            sample_rate: 48000,
+            click_buffers: [
+                vec![0.0; DEFAULT_MAX_FRAME_SIZE + MAX_CLICK_SAMPLES],
+                vec![0.0; DEFAULT_MAX_FRAME_SIZE + MAX_CLICK_SAMPLES],
+                vec![0.0; DEFAULT_MAX_FRAME_SIZE + MAX_CLICK_SAMPLES],
             ],
-            last_beat_time: 0,
-            click_buffers: [[0f32; 96000]; 2],
+            playing: None,
             state: BeatState::default(),
             transport: TransportState::default(),
         }
@@ -48,73 +330,141 @@ impl Metronome {
         met
     }
 
+    /// A metronome playing the clicks of a bank shared with the main loop.
+    pub fn with_bank(bank: ClickBank) -> Metronome {
+        let mut met = Metronome {
+            bank,
+            ..Default::default()
+        };
+        met.pregen_click_bufs();
+        met
+    }
+
+    /// Fills the click buffers from the bank at the click levels. Clicks synthesized for another
+    /// sample rate are synthesized again in place, without allocating.
     pub fn pregen_click_bufs(&mut self) {
-        for i in 0..2 {
-            let click = &self.clicks[i];
-            let mut buf = [0f32; 96000];
-            for (i, sample) in buf.iter_mut().enumerate().take(click.length * 48) {
-                *sample =
-                    (i as f32 * std::f32::consts::PI * click.frequency as f32 / 24000.0).sin() * 0.1
+        let amplitudes = self.levels.amplitudes();
+        let sounds = self.bank.load();
+        self.sounds = Arc::as_ptr(&sounds) as usize;
+        for (click, buf) in self.click_buffers.iter_mut().enumerate() {
+            buf.fill(0.0);
+            // Samples cannot be converted here, and are played as read until the main loop
+            // reads them again
+            let length = if sounds.sample_rate == self.sample_rate || sounds.sampled[click] {
+                let sound = &sounds.sounds[click];
+                let length = sound.len().min(buf.len());
+                buf[..length].copy_from_slice(&sound[..length]);
+                length
+            } else {
+                sounds.synthesis.render(click, self.sample_rate, buf)
+            };
+            for sample in &mut buf[..length] {
+                *sample *= amplitudes[click];
             }
-            self.click_buffers[i] = buf;
+            self.click_lengths[click] = length;
+        }
+    }
+
+    /// Click of the strong, medium and weak click playing a beat of an accent.
+    fn click_of(accent: Accent) -> usize {
+        match accent {
+            Accent::Strong => 0,
+            Accent::Medium => 1,
+            Accent::Weak => 2,
+        }
+    }
+
+    /// The rest of the click being played, for this cycle.
+    fn continue_click(&mut self, frame_size: usize) -> Option<&[f32]> {
+        let (click, offset) = self.playing?;
+        let length = self.click_lengths[click];
+        if offset >= length {
+            self.playing = None;
+            return None;
+        }
+        self.playing = Some((click, offset + frame_size));
+        Some(&self.click_buffers[click][offset..offset + frame_size])
+    }
+
+    /// Generates the clicks again for another sample rate.
+    fn set_sample_rate(&mut self, sample_rate: usize) {
+        if sample_rate != self.sample_rate && sample_rate > 0 {
+            self.sample_rate = sample_rate;
+            self.pregen_click_bufs();
         }
     }
+
+    /// Changes the click levels from the next beat on.
+    pub fn set_levels(&mut self, levels: ClickLevels) {
+        if levels != self.levels {
+            self.levels = levels;
+            self.pregen_click_bufs();
+        }
+    }
+
+    /// Position of the beat following the last one, in ticks of the frame clock.
+    fn scheduled_tick(&self, ctx: &AudioSourceContext, length_us: u64) -> Option<u64> {
+        // A microsecond is sample_rate ticks long
+        let length = length_us * ctx.sample_rate as u64 * 100 / ctx.click_rate_percent() as u64;
+        self.last_beat.map(|last_beat| last_beat + length)
+    }
 }
 
 impl audio::source::AudioSource for Metronome {
     fn get_status(&mut self, ctx: &audio::source::AudioSourceContext) -> AudioSourceState {
-        let scheduled_time: u64;
-        if let Some(beat) = ctx.cue.get_beat(self.state.beat_idx) {
-            scheduled_time = self.last_beat_time
-                + (beat.length as u64 * 100 / ctx.transport.playrate_percent as u64)
-        } else {
-            scheduled_time = u64::MAX
+        let now = ctx.frame_time * TICKS_PER_FRAME;
+        self.state.us_to_next_beat = match ctx
+            .cue
+            .get_beat(self.state.beat_idx)
+            .and_then(|beat| self.scheduled_tick(ctx, beat.length as u64))
+        {
+            Some(scheduled) if scheduled > now => {
+                ((scheduled - now) / ctx.sample_rate.max(1) as u64) as u32
+            }
+            _ => 0,
         };
-        self.state.us_to_next_beat =
-            if scheduled_time > ctx.jack_time && scheduled_time < u64::MAX / 2 {
-                (scheduled_time - ctx.jack_time) as u32
-            } else {
-                0
-            };
         let ret = AudioSourceState::BeatStatus(self.state);
         self.state.requested_vlt_action = JumpModeChange::None;
         ret
     }
 
-    fn send_buffer(
-        &mut self,
-        ctx: &audio::source::AudioSourceContext,
-    ) -> Result<&[f32], jack::Error> {
+    fn send_buffer<'a>(
+        &'a mut self,
+        ctx: &'a audio::source::AudioSourceContext,
+    ) -> Result<&'a [f32], jack::Error> {
+        self.set_sample_rate(ctx.sample_rate);
+        // Clicks edited since the last cycle take effect from the next beat
+        if self.playing.is_none() && Arc::as_ptr(&self.bank.load()) as usize != self.sounds {
+            self.pregen_click_bufs();
+        }
         if ctx.transport.running {
-            let mut beat = ctx.cue.get_beat(self.state.beat_idx).unwrap_or_default();
+            let beat = ctx.cue.get_beat(self.state.beat_idx).unwrap_or_default();
             let next_beat = match ctx.cue.get_beat(self.state.next_beat_idx) {
                 None => {
-                    return Ok(self.silence(ctx.frame_size));
+                    return Ok(self.continue_click(ctx.frame_size).unwrap_or(ctx.silence()));
                 }
                 Some(val) => val,
             };
-            let scheduled_time: u64 = self.last_beat_time
-                + beat.length as u64 * 100 / ctx.transport.playrate_percent as u64;
+            let now = ctx.frame_time * TICKS_PER_FRAME;
+            let scheduled = self.scheduled_tick(ctx, beat.length as u64);
 
-            if ctx.jack_time > scheduled_time {
+            if scheduled.is_none_or(|scheduled| now > scheduled) {
                 self.state.beat_idx = self.state.next_beat_idx;
-                beat = ctx.cue.get_beat(self.state.beat_idx).unwrap_or_default();
                 self.state.next_beat_idx += 1;
-                if self.last_beat_time == 0 {
-                    self.last_beat_time = ctx.jack_time;
-                } else {
-                    self.last_beat_time = scheduled_time;
-                }
+                // Beats follow on from the scheduled position rather than the cycle they
+                // were played in, so the late start of a cycle does not add up
+                self.last_beat = Some(scheduled.unwrap_or(now));
                 //ctx.cbnet
                 //    .notify(Message::Small(SmallMessage::BeatData(self.state)));
-                return Ok(
-                    &self.click_buffers[if beat.count == 1 { 0 } else { 1 }][0..ctx.frame_size]
-                );
+                let click = Self::click_of(beat_accent(&ctx.cue, self.state.beat_idx));
+                self.playing = Some((click, ctx.frame_size));
+                return Ok(&self.click_buffers[click][0..ctx.frame_size]);
             } else {
-                return Ok(self.silence(ctx.frame_size));
+                return Ok(self.continue_click(ctx.frame_size).unwrap_or(ctx.silence()));
             }
         }
-        Ok(self.silence(ctx.frame_size))
+        // Only a count-in click plays on while the transport holds
+        Ok(self.continue_click(ctx.frame_size).unwrap_or(ctx.silence()))
     }
 
     fn command(&mut self, _ctx: &AudioSourceContext, command: ControlAction) {
@@ -122,17 +472,19 @@ impl audio::source::AudioSource for Metronome {
             ControlAction::TransportZero => {
                 self.state.beat_idx = 0;
                 self.state.next_beat_idx = 0;
-                self.last_beat_time = 0;
+                self.last_beat = None;
             }
             ControlAction::TransportStop => {
-                self.last_beat_time = 0;
+                self.last_beat = None;
+                self.playing = None;
             }
             ControlAction::TransportSeekBeat(beat_idx) => {
                 self.state.next_beat_idx = beat_idx;
             }
             ControlAction::TransportJumpBeat(beat_idx) => {
                 self.state.next_beat_idx = beat_idx;
-                self.last_beat_time = 0;
+                self.last_beat = None;
+                self.playing = None;
             }
             _ => {}
         }
@@ -162,4 +514,183 @@ impl audio::source::AudioSource for Metronome {
     }
 
     fn event_will_occur(&mut self, _ctx: &AudioSourceContext, _event: common::event::Event) {}
+
+    fn core_command(&mut self, _ctx: &AudioSourceContext, command: CoreControlAction) {
+        match command {
+            CoreControlAction::SetClickLevels(levels) => self.set_levels(levels),
+            CoreControlAction::ClickCountIn(accent) => {
+                self.playing = Some((Self::click_of(accent), 0));
+            }
+            _ => {}
+        }
+    }
+
+    fn set_max_frame_size(&mut self, frames: usize) {
+        for buf in self.click_buffers.iter_mut() {
+            buf.resize(frames + MAX_CLICK_SAMPLES, 0.0);
+        }
+        self.pregen_click_bufs();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audio::source::AudioSource;
+    use common::cue::Cue;
+
+    #[test]
+    fn beats_keep_to_the_frame_clock() {
+        const FRAME_SIZE: usize = 256;
+        let mut metronome = Metronome::new();
+        let mut ctx = AudioSourceContext::default();
+        ctx.cue = Cue::example();
+        ctx.frame_size = FRAME_SIZE;
+        ctx.sample_rate = 48000;
+        ctx.transport.running = true;
+        ctx.transport.playrate_percent = 100;
+
+        let mut beat_frames = vec![];
+        for cycle in 0..100_000 {
+            ctx.frame_time = (cycle * FRAME_SIZE) as u64;
+            let clicked = metronome
+                .send_buffer(&ctx)
+                .unwrap()
+                .iter()
+                .any(|sample| *sample != 0.0);
+            if clicked {
+                beat_frames.push(ctx.frame_time);
+            }
+        }
+        assert!(beat_frames.len() > 1);
+
+        // Every beat is played in the first cycle starting after its exact position, however
+        // many beats came before it
+        let mut exact_us = 0;
+        for (idx, frame) in beat_frames.iter().enumerate().skip(1) {
+            exact_us += ctx.cue.get_beat(idx as u16 - 1).unwrap_or_default().length as u64;
+            let exact_frame = exact_us * 48 / 1000;
+            assert!(*frame >= exact_frame && *frame <= exact_frame + FRAME_SIZE as u64);
+        }
+    }
+
+    #[test]
+    fn accent_level() {
+        let mut metronome = Metronome::new();
+        let peak = |buf: &[f32]| buf.iter().fold(0.0f32, |peak, s| peak.max(s.abs()));
+        assert!((peak(&metronome.click_buffers[0]) - 0.1).abs() < 1e-3);
+
+        metronome.set_levels(ClickLevels {
+            accent_db: -6.0,
+            beat_db: -26.0,
+        });
+        assert!((peak(&metronome.click_buffers[0]) - 0.501).abs() < 1e-3);
+        assert!((peak(&metronome.click_buffers[1]) - 0.158).abs() < 1e-3);
+        assert!((peak(&metronome.click_buffers[2]) - 0.050).abs() < 1e-3);
+        assert!(
+            !ClickLevels {
+                accent_db: 6.0,
+                beat_db: -20.0
+            }
+            .is_valid()
+        );
+    }
+
+    #[test]
+    fn clicks_at_44k1() {
+        let mut metronome = Metronome::new();
+        let length = |buf: &[f32]| buf.iter().rposition(|s| *s != 0.0).map_or(0, |idx| idx + 1);
+        assert_eq!(length(&metronome.click_buffers[2]), 192);
+        metronome.set_sample_rate(44100);
+        // Still 4 ms at 1 kHz
+        let click = &metronome.click_buffers[2];
+        assert_eq!(length(click), 176);
+        let crossings = click[..176]
+            .windows(2)
+            .filter(|pair| pair[0] < 0.0 && pair[1] >= 0.0);
+        assert_eq!(crossings.count(), 3);
+    }
+
+    #[test]
+    fn synthesized_clicks() {
+        let bank = click_bank(ClickSynthesis::default(), 48000);
+        let mut metronome = Metronome::with_bank(bank.clone());
+        let mut ctx = AudioSourceContext::default();
+        ctx.cue = Cue::example();
+        ctx.frame_size = 256;
+        ctx.sample_rate = 48000;
+
+        let synthesis = ClickSynthesis {
+            waveform: ClickWaveform::Square,
+            clicks: [ClickSound {
+                frequency_hz: 500.0,
+                length_ms: 20.0,
+                waveform: None,
+            }; 3],
+            decay_ms: 4.0,
+        };
+        assert!(synthesis.is_valid());
+        bank.store(Arc::new(ClickSounds::synthesize(synthesis, 48000)));
+        // Picked up on the next cycle, between clicks
+        metronome.send_buffer(&ctx).unwrap();
+        assert_eq!(metronome.click_lengths, [960; 3]);
+        let click = &metronome.click_buffers[2];
+        assert!((click[0] - 0.1).abs() < 1e-6);
+        // Decayed to 1/e after 4 ms, at the start of a period of the square
+        assert!((click[192] - 0.1 / std::f32::consts::E).abs() < 1e-3);
+        assert!(click[48] < 0.0);
+
+        assert!(
+            !ClickSynthesis {
+                decay_ms: -1.0,
+                ..synthesis
+            }
+            .is_valid()
+        );
+        let mut too_long = synthesis;
+        too_long.clicks[0].length_ms = MAX_CLICK_MS as f32 + 1.0;
+        assert!(!too_long.is_valid());
+    }
+
+    #[test]
+    fn sampled_clicks() {
+        let show_path =
+            std::env::temp_dir().join(format!("clicks-click-samples-{}", std::process::id()));
+        std::fs::create_dir_all(&show_path).expect("temp dir is writable");
+        // A second of stereo woodblock at 44.1 kHz, longer than any click
+        let spec = hound::WavSpec {
+            channels: 2,
+            sample_rate: 44100,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+        let mut writer =
+            hound::WavWriter::create(show_path.join("block.wav"), spec).expect("temp dir");
+        for _ in 0..44100 * 2 {
+            writer
+                .write_sample(16384_i16)
+                .expect("temp dir is writable");
+        }
+        writer.finalize().expect("temp dir is writable");
+
+        let mut synthesis = ClickSynthesis::default();
+        synthesis.clicks[1].waveform = Some(ClickWaveform::Square);
+        let samples = [
+            Some("block.wav".to_string()),
+            None,
+            Some("none.wav".to_string()),
+        ];
+        let (sounds, errors) = ClickSounds::load(synthesis, &samples, &show_path, 48000);
+        assert_eq!(errors.len(), 1, "the missing sample is synthesized");
+        assert_eq!(sounds.sampled, [true, false, false]);
+
+        let metronome = Metronome::with_bank(Arc::new(ArcSwap::from_pointee(sounds)));
+        // Cut to the longest click, at the rate of the server
+        assert_eq!(metronome.click_lengths, [MAX_CLICK_MS * 48, 192, 192]);
+        assert!((metronome.click_buffers[0][1000] - 0.05).abs() < 1e-3);
+        // Only the medium click is a square
+        assert!((metronome.click_buffers[1][10] - 0.1).abs() < 1e-6);
+        assert!(metronome.click_buffers[2][10] < 0.1);
+        let _ = std::fs::remove_dir_all(&show_path);
+    }
 }
@@ -1,7 +1,21 @@
+pub mod accent;
+pub mod count_in;
+pub mod countdown;
+pub mod delay;
+pub mod eq;
+pub mod fade;
+pub mod follower;
+pub mod graph;
 pub mod handler;
 pub mod metronome;
 pub mod notification;
+pub mod phase;
 pub mod playback;
 pub mod processor;
+pub mod profile;
+pub mod resample;
+pub mod simulation;
 pub mod source;
+pub mod stream;
 pub mod timecode;
+pub mod watchdog;
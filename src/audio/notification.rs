@@ -1,13 +1,27 @@
-use jack::NotificationHandler;
+use jack::{Client, NotificationHandler, PortId};
+use std::sync::{
+    Arc,
+    atomic::{AtomicBool, Ordering},
+};
+
+/// Raises `graph_changed` whenever JACK reports a change of ports or connections. JACK does not
+/// allow changing the graph from its callbacks, so the main loop looks at it later, see
+/// `audio::watchdog`.
+pub struct JACKNotificationHandler {
+    pub graph_changed: Arc<AtomicBool>,
+}
 
-pub struct JACKNotificationHandler;
 impl NotificationHandler for JACKNotificationHandler {
     //fn thread_init(&self, _: &Client) {}
     //unsafe fn shutdown(&mut self, _status: ClientStatus, _reason: &str) {}
     //fn freewheel(&mut self, _: &Client, _is_freewheel_enabled: bool) {}
     //fn sample_rate(&mut self, _: &Client, _srate: Frames) -> Control {}
-    //fn client_registration(&mut self, _: &Client, _name: &str, _is_registered: bool) {}
-    //fn port_registration(&mut self, _: &Client, _port_id: PortId, _is_registered: bool) {}
+    fn client_registration(&mut self, _: &Client, _name: &str, _is_registered: bool) {
+        self.graph_changed.store(true, Ordering::Release);
+    }
+    fn port_registration(&mut self, _: &Client, _port_id: PortId, _is_registered: bool) {
+        self.graph_changed.store(true, Ordering::Release);
+    }
     //fn port_rename(
     //    &mut self,
     //    _: &Client,
@@ -16,14 +30,15 @@ impl NotificationHandler for JACKNotificationHandler {
     //    _new_name: &str,
     //) -> Control {
     //}
-    //fn ports_connected(
-    //    &mut self,
-    //    _: &Client,
-    //    _port_id_a: PortId,
-    //    _port_id_b: PortId,
-    //    _are_connected: bool,
-    //) {
-    //}
+    fn ports_connected(
+        &mut self,
+        _: &Client,
+        _port_id_a: PortId,
+        _port_id_b: PortId,
+        _are_connected: bool,
+    ) {
+        self.graph_changed.store(true, Ordering::Release);
+    }
     //fn graph_reorder(&mut self, _: &Client) -> Control {}
     //fn xrun(&mut self, _: &Client) -> Control {}
 }
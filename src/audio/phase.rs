@@ -0,0 +1,70 @@
+/// Highest rate of the beat phase stream. Faster than any display needs, slow enough to not
+/// swamp the network.
+pub const MAX_BEAT_PHASE_RATE: u8 = 50;
+
+/// Fraction of the current beat that has passed, 0.0 at the click and approaching 1.0 just
+/// before the next one.
+pub fn beat_phase(us_to_next_beat: u32, beat_length_us: u32, playrate_percent: u16) -> f32 {
+    if beat_length_us == 0 || playrate_percent == 0 {
+        return 0.0;
+    }
+    let played_length_us = beat_length_us as f32 * 100.0 / playrate_percent as f32;
+    (1.0 - us_to_next_beat as f32 / played_length_us).clamp(0.0, 1.0)
+}
+
+/// Decides which process cycles publish the beat phase, at a fixed rate on the audio clock.
+#[derive(Debug, Clone, Default)]
+pub struct BeatPhaseStream {
+    /// Time between messages, or 0 if the stream is off
+    interval_us: u64,
+    next_us: u64,
+}
+
+impl BeatPhaseStream {
+    /// Sets the rate of the stream in messages per second, 0 to turn it off.
+    pub fn set_rate(&mut self, rate_hz: u8) {
+        self.interval_us = match rate_hz.min(MAX_BEAT_PHASE_RATE) {
+            0 => 0,
+            rate_hz => 1_000_000 / rate_hz as u64,
+        };
+        self.next_us = 0;
+    }
+
+    /// Returns true if a message is due in the cycle starting at `time_us`.
+    pub fn due(&mut self, time_us: u64) -> bool {
+        if self.interval_us == 0 || time_us < self.next_us {
+            return false;
+        }
+        // Skip missed slots instead of catching up with a burst
+        self.next_us = if time_us - self.next_us > self.interval_us {
+            time_us + self.interval_us
+        } else {
+            self.next_us + self.interval_us
+        };
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn phase_at_playrate() {
+        assert_eq!(beat_phase(500_000, 500_000, 100), 0.0);
+        assert_eq!(beat_phase(250_000, 500_000, 100), 0.5);
+        // At half speed the beat lasts a second
+        assert_eq!(beat_phase(250_000, 500_000, 50), 0.75);
+        assert_eq!(beat_phase(0, 0, 100), 0.0);
+    }
+
+    #[test]
+    fn fixed_rate() {
+        let mut stream = BeatPhaseStream::default();
+        assert!(!stream.due(0));
+        stream.set_rate(10);
+        // One second of 480 sample cycles at 48 kHz
+        let due = (0..100).filter(|cycle| stream.due(cycle * 10_000)).count();
+        assert_eq!(due, 10);
+    }
+}
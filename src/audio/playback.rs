@@ -1,6 +1,16 @@
 use crate::{
-    audio::source::{AudioSource, AudioSourceContext, SourceConfig},
+    audio::{
+        fade::{GainFade, StopFade},
+        resample::Resampler,
+        source::{AudioSource, AudioSourceContext, DEFAULT_MAX_FRAME_SIZE, SourceConfig},
+        stream::{ClipStream, MediaStreamer, read_samples},
+    },
     cbnet::CrossbeamNetwork,
+    communication::extension::{CoreControlAction, CoreMessage},
+    show_extension::{
+        AlternateClips, ClipAlternates, ClipChains, ClipPick, FadeEvent, FadeEvents, FadeLength,
+        GainAutomation, MAX_ALTERNATE_CLIPS, gain_factor,
+    },
 };
 use arc_swap::ArcSwap;
 use common::{
@@ -15,82 +25,243 @@ use common::{
         request::ControlAction,
     },
 };
-use std::{fmt::Debug, ops::Div, path::PathBuf, sync::Arc};
+use crossbeam_channel::{Receiver, Sender, unbounded};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fmt::Debug,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, Instant, SystemTime},
+};
 
-const LOCAL_BUF_SIZE: usize = 48000;
+/// Number of cues after the loaded one whose media is read ahead into the cache.
+const PREFETCH_CUES: u8 = 2;
+
+/// Attempts at reading a media file before playing silence instead. Reads from show directories
+/// on network mounts fail now and then, and usually succeed when tried again.
+const MEDIA_READ_ATTEMPTS: u32 = 3;
+const MEDIA_RETRY_DELAY: Duration = Duration::from_millis(200);
+
+/// Loading a cue taking longer than this is logged, as a sign of a slow or stalled disk.
+const SLOW_LOAD: Duration = Duration::from_secs(1);
 
 type AudioBuffer = Vec<f32>;
-struct AudioClip {
-    pub clip_idx: Arc<ArcSwap<usize>>,
-    buffer: Arc<ArcSwap<AudioBuffer>>,
-    local_buffer: [f32; LOCAL_BUF_SIZE],
+
+/// Why a seek or jump left a playback channel outside the loaded clip data, see
+/// `CoreMessage::SeekResult`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SeekOutcome {
+    /// The media of the clip is not in a slot of the channel yet. The clip starts in place once
+    /// the loader has put it there.
+    NotLoaded,
+    /// The seek landed past the end of the clip, which is `length` samples long. The channel is
+    /// silent unless a clip is chained after it.
+    PastEnd { length: u32 },
 }
 
-impl Debug for AudioClip {
+/// Media of a clip, read whole or streamed from disk, see `audio::stream`.
+#[derive(Debug)]
+enum ClipMedia {
+    Loaded(AudioBuffer),
+    Streamed(Arc<ClipStream>),
+}
+
+impl Default for ClipMedia {
+    fn default() -> Self {
+        Self::Loaded(vec![])
+    }
+}
+
+impl ClipMedia {
+    fn get_length(&self) -> u32 {
+        match self {
+            Self::Loaded(buffer) => buffer.len() as u32,
+            Self::Streamed(stream) => stream.get_length(),
+        }
+    }
+}
+
+/// A clip slot, shared between the audio thread playing it and the loader filling it.
+#[derive(Clone)]
+struct ClipSlot {
+    clip_idx: Arc<ArcSwap<usize>>,
+    media: Arc<ArcSwap<ClipMedia>>,
+}
+
+impl Debug for ClipSlot {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::result::Result<(), std::fmt::Error> {
         write!(
             f,
-            "AudioClip {{ clip_idx: {}, length: {} }}",
+            "ClipSlot {{ clip_idx: {}, length: {} }}",
             self.clip_idx,
             self.get_length()
         )
     }
 }
 
-impl AudioClip {
-    pub fn new(idx: usize) -> Self {
+impl ClipSlot {
+    fn new(idx: usize) -> Self {
         Self {
             clip_idx: Arc::new(ArcSwap::from_pointee(idx)),
-            buffer: Arc::new(ArcSwap::from_pointee(vec![])),
-            local_buffer: [0.0f32; LOCAL_BUF_SIZE],
+            media: Arc::new(ArcSwap::from_pointee(ClipMedia::default())),
         }
     }
 
-    // Called in non-RT thread
-    pub fn write(&self, idx: usize, buffer: Vec<f32>) {
+    // Called in non-RT thread. The media is stored first, so a reader finding the new index
+    // also finds its samples.
+    fn write(&self, idx: usize, media: Arc<ClipMedia>) {
+        self.media.store(media);
         self.clip_idx.store(Arc::new(idx));
-        self.buffer.store(Arc::new(buffer));
     }
 
-    // Called in RT thread
-    pub fn read_buffer_slice(&mut self, start: u32, len: usize) -> &[f32] {
-        let buf = &self.buffer.load();
-        self.local_buffer[..len].copy_from_slice(&buf[start as usize..start as usize + len]);
-        &self.local_buffer[0..len]
+    fn read_index(&self) -> usize {
+        **self.clip_idx.load()
+    }
+
+    fn get_length(&self) -> u32 {
+        self.media.load().get_length()
+    }
+
+    fn stream(&self) -> Option<Arc<ClipStream>> {
+        match &**self.media.load() {
+            ClipMedia::Streamed(stream) => Some(Arc::clone(stream)),
+            ClipMedia::Loaded(_) => None,
+        }
+    }
+}
+
+struct AudioClip {
+    slot: ClipSlot,
+}
+
+impl Debug for AudioClip {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::result::Result<(), std::fmt::Error> {
+        write!(f, "AudioClip {{ {:?} }}", self.slot)
+    }
+}
+
+impl AudioClip {
+    fn new(slot: ClipSlot) -> Self {
+        Self { slot }
+    }
+
+    /// Reads the clip from sample `start` into `out`, filling what is past its end with
+    /// silence. Returns the number of samples of clip data read. Called in RT thread.
+    pub fn read_buffer_slice(&self, start: u32, out: &mut [f32]) -> usize {
+        let media = self.slot.media.load();
+        let count = (media.get_length().saturating_sub(start) as usize).min(out.len());
+        let (samples, rest) = out.split_at_mut(count);
+        rest.fill(0.0);
+        match &**media {
+            ClipMedia::Loaded(buf) => {
+                samples.copy_from_slice(&buf[start as usize..start as usize + count])
+            }
+            ClipMedia::Streamed(stream) => stream.read(start, samples),
+        }
+        count
+    }
+    /// Tells the streamer of a streamed clip where it is about to be played from. Called in RT
+    /// thread.
+    pub fn locate(&self, sample: u32) {
+        if let ClipMedia::Streamed(stream) = &**self.slot.media.load() {
+            stream.locate(sample);
+        }
     }
     pub fn read_index(&self) -> usize {
-        **self.clip_idx.load()
+        self.slot.read_index()
     }
     pub fn get_length(&self) -> u32 {
-        self.buffer.load().len() as u32
+        self.slot.get_length()
     }
 }
 
+enum LoaderJob {
+    /// A new show, the alternate clips of its cues, the clip slots created for it and the
+    /// sample rate it is played at
+    Show {
+        show: Show,
+        alternates: Vec<Vec<ClipAlternates>>,
+        slots: Vec<Vec<ClipSlot>>,
+        sample_rate: u32,
+        /// Directory the media of the show is read from
+        show_path: PathBuf,
+    },
+    /// Fill the clip slots with the media of a cue, then read the following cues ahead
+    Load(u8),
+    /// Read the media of a cue into the cache without touching the clip slots
+    Prefetch(u8),
+}
+
 #[derive(Debug)]
 pub struct PlaybackHandler {
-    clips: Vec<Vec<AudioClip>>,
+    slots: Vec<Vec<ClipSlot>>,
     show_path: PathBuf,
     num_channels: usize,
-    cbnet: CrossbeamNetwork,
+    loader: Sender<LoaderJob>,
 }
 
 impl PlaybackHandler {
+    /// Clips longer than `stream_media_s` seconds are streamed from disk, see `audio::stream`.
     pub fn new(
         cbnet: CrossbeamNetwork,
         show_path: PathBuf,
         num_channels: usize,
+        stream_media_s: Option<u32>,
     ) -> PlaybackHandler {
+        let (loader, jobs) = unbounded();
+        let (streamer, streams) = unbounded();
+        let media_loader = MediaLoader {
+            show_path: show_path.clone(),
+            num_channels,
+            cbnet: cbnet.clone(),
+            show: Show::default(),
+            alternates: vec![],
+            slots: vec![],
+            cache: HashMap::new(),
+            prefetched: None,
+            sample_rate: 0,
+            stream_media_s,
+            streamer,
+        };
+        let media_streamer = MediaStreamer::new(cbnet.clone());
+        if let Err(err) = std::thread::Builder::new()
+            .name("clicks-media-loader".to_string())
+            .spawn(move || media_loader.run(jobs))
+        {
+            cbnet.log(LogItem::new(
+                format!("Could not start media loader thread: {err}"),
+                LogContext::AudioSource,
+                LogKind::Error,
+            ));
+        }
+        if let Err(err) = std::thread::Builder::new()
+            .name("clicks-media-streamer".to_string())
+            .spawn(move || media_streamer.run(streams))
+        {
+            cbnet.log(LogItem::new(
+                format!("Could not start media streamer thread: {err}"),
+                LogContext::AudioSource,
+                LogKind::Error,
+            ));
+        }
         PlaybackHandler {
             show_path,
-            clips: Vec::new(),
+            slots: Vec::new(),
             num_channels,
-            cbnet,
+            loader,
         }
     }
 
     // FIXME: if the same clip appears multiple times in the cue, it will be counted as separate
     // occurences. Right/wrong?
-    fn num_channel_clips_in_cue(&self, cue: &Cue, channel: usize) -> usize {
+    fn num_channel_clips_in_cue(
+        &self,
+        cue: &Cue,
+        alternates: &[ClipAlternates],
+        channel: usize,
+    ) -> usize {
         if channel > self.num_channels {
             return 0;
         }
@@ -99,180 +270,717 @@ impl PlaybackHandler {
         while let Some(event) = cursor.get_next() {
             if let Some(EventDescription::PlaybackEvent {
                 channel_idx,
-                clip_idx: _,
+                clip_idx,
                 sample: _,
             }) = event.event
                 && channel_idx == channel as u16
             {
-                clips_in_cue += 1;
+                clips_in_cue += 1 + alternates
+                    .iter()
+                    .filter(|clip| clip.channel == channel_idx && clip.clip == clip_idx)
+                    .map(|clip| clip.alternates.len())
+                    .sum::<usize>();
             }
         }
 
         clips_in_cue
     }
 
-    fn load_wav_buf(&self, channel: usize, clip: u16) -> Vec<f32> {
-        let mut reader = match hound::WavReader::open(
-            self.show_path
-                .join(format!("playback_media/{:0>3}/{:0>3}.wav", channel, clip)),
-        ) {
-            Ok(val) => val,
-            Err(err) => {
-                self.cbnet.log(LogItem::new(
-                    format!("Error opening playback media: {}", err),
-                    LogContext::AudioSource,
-                    LogKind::Error,
-                ));
-                return vec![0.0; 48000];
-            }
-        };
-        let buf: Vec<f32> = match reader.spec().sample_format {
-            hound::SampleFormat::Float => reader
-                .samples::<f32>()
-                .map(|sample| {
-                    if let Err(err) = sample {
-                        self.cbnet.log(LogItem::new(
-                            format!("Error opening playback media: {}", err),
-                            LogContext::AudioSource,
-                            LogKind::Error,
-                        ));
-                        return 0.0;
-                    }
-                    sample.expect("Err already handled.")
-                })
-                .collect(),
-            hound::SampleFormat::Int => reader
-                .samples::<i32>()
-                .map(|sample| {
-                    if let Err(err) = sample {
-                        self.cbnet.log(LogItem::new(
-                            format!("Error opening playback media: {}", err),
-                            LogContext::AudioSource,
-                            LogKind::Error,
-                        ));
-                        return 0.0;
-                    }
-                    (sample.expect("Err already handled.") as f32).div(32768.0)
-                })
-                .collect(),
-        };
-        buf
-    }
-
-    // Returns a vector indexed by channel where each element is that channel's list of clip idxs
-    // in this cue. The inner list is unordered (ordered by first apperance in the cue) and
-    // non-duplicated.
-    fn clip_idxs_in_cue(&self, cue: &Cue) -> Vec<Vec<u16>> {
-        let mut clips: Vec<Vec<u16>> = Vec::new();
-        for _ in 0..self.num_channels {
-            clips.push(Vec::new());
-        }
-        let mut cursor = EventCursor::new(&cue.events);
-        while let Some(event) = cursor.get_next() {
-            if let Some(EventDescription::PlaybackEvent {
-                channel_idx,
-                clip_idx,
-                sample: _,
-            }) = event.event
-                && !clips[channel_idx as usize].contains(&clip_idx)
-            {
-                clips[channel_idx as usize].push(clip_idx);
-            }
-        }
-
-        clips
-    }
-
-    /// Takes a show, and creates audio clip slots for all channels,
-    /// where each channel gets the minimum number of audio clip slots
+    /// Takes a show, the alternate clips of its cues and the sample rate of the audio server,
+    /// which clips at other rates are converted to, and creates audio clip slots for all
+    /// channels, where each channel gets the minimum number of audio clip slots
     /// it will need, i.e. the maximum number of clips in a single cue.
     ///
-    /// Then stores the clips in self.clips:
+    /// Then stores the clips in self.slots:
     ///     [
     ///         [ch1_slot1, ch1_slot2, ...]
     ///         [ch2_slot1, ch2_slot2, ...],
     ///         [ch3_slot1],
     ///         []
     ///     ]
-    pub fn load_show(&mut self, show: Show) {
-        self.clips.clear();
+    pub fn load_show(
+        &mut self,
+        show: Show,
+        alternates: Vec<Vec<ClipAlternates>>,
+        sample_rate: u32,
+    ) {
+        self.slots.clear();
 
         for channel in 0..self.num_channels {
             // Figure out the max num of clips used in a single cue
             let mut max_clips = 0;
-            for cue in &show.cues {
-                max_clips = std::cmp::max(self.num_channel_clips_in_cue(cue, channel), max_clips);
+            for (idx, cue) in show.cues.iter().enumerate() {
+                let cue_alternates = alternates.get(idx).map_or(&[][..], Vec::as_slice);
+                max_clips = std::cmp::max(
+                    self.num_channel_clips_in_cue(cue, cue_alternates, channel),
+                    max_clips,
+                );
             }
 
-            let mut clips = Vec::new();
+            let mut slots = Vec::new();
             for i in 0..max_clips {
-                clips.push(AudioClip::new(i));
+                slots.push(ClipSlot::new(i));
             }
 
-            self.clips.push(clips);
+            self.slots.push(slots);
         }
+
+        let _ = self.loader.send(LoaderJob::Show {
+            show,
+            alternates,
+            slots: self.slots.clone(),
+            sample_rate,
+            show_path: self.show_path.clone(),
+        });
+    }
+
+    /// Reads the media of shows loaded from now on from another show directory.
+    pub fn set_show_path(&mut self, show_path: PathBuf) {
+        self.show_path = show_path;
     }
 
     pub fn create_audio_sources(&mut self) -> Vec<SourceConfig> {
-        let mut devices = vec![];
+        self.create_playback_devices()
+            .into_iter()
+            .enumerate()
+            .map(|(channel, device)| SourceConfig::new(format!("playback_{channel}"), device))
+            .collect()
+    }
+
+    /// A playback device per channel, with the clip slots of the loaded show.
+    pub fn create_playback_devices(&mut self) -> Vec<Box<dyn AudioSource>> {
+        let mut devices: Vec<Box<dyn AudioSource>> = vec![];
         for channel in 0..self.num_channels {
             let mut device = PlaybackDevice::new(channel as u16, self.show_path.clone());
-            for clip in &self.clips[channel] {
-                device.clips.push(AudioClip {
-                    clip_idx: Arc::clone(&clip.clip_idx),
-                    buffer: Arc::clone(&clip.buffer),
-                    local_buffer: [0.0f32; LOCAL_BUF_SIZE],
-                });
+            for slot in &self.slots[channel] {
+                device.clips.push(AudioClip::new(slot.clone()));
             }
-            devices.push(SourceConfig::new(
-                format!("playback_{channel}"),
-                Box::new(device),
-            ));
+            devices.push(Box::new(device));
         }
         devices
     }
 
-    pub fn load_cue(&self, cue: Cue) {
-        for channel in &self.clips {
-            for clips in channel {
-                clips.write(usize::MAX, vec![]);
+    /// Replaces the clips with the media of a cue of the loaded show. The media is read on the
+    /// loader thread, so this returns right away; the clip slots are silent until it is read.
+    pub fn load_cue(&self, cue_idx: u8) {
+        let _ = self.loader.send(LoaderJob::Load(cue_idx));
+    }
+
+    /// Reads the media of a cue of the loaded show ahead of time, without touching the clips
+    /// currently playing, so loading it later does not have to wait for the disk.
+    pub fn prefetch(&self, cue_idx: u8) {
+        let _ = self.loader.send(LoaderJob::Prefetch(cue_idx));
+    }
+
+    pub fn get_status(&self) -> PlaybackHandlerStatus {
+        slot_status(&self.slots)
+    }
+}
+
+fn slot_status(slots: &[Vec<ClipSlot>]) -> PlaybackHandlerStatus {
+    PlaybackHandlerStatus {
+        clips: slots
+            .iter()
+            .map(|v| v.iter().map(|c| c.read_index() as u16).collect())
+            .collect(),
+    }
+}
+
+// Returns a vector indexed by channel where each element is that channel's list of clip idxs
+// in this cue, with their alternates. The inner list is sorted and non-duplicated.
+fn clip_idxs_in_cue(
+    cue: &Cue,
+    alternates: &[ClipAlternates],
+    num_channels: usize,
+) -> Vec<Vec<u16>> {
+    let mut clips: Vec<Vec<u16>> = vec![Vec::new(); num_channels];
+    let mut cursor = EventCursor::new(&cue.events);
+    while let Some(event) = cursor.get_next() {
+        if let Some(EventDescription::PlaybackEvent {
+            channel_idx,
+            clip_idx,
+            sample: _,
+        }) = event.event
+            && let Some(channel) = clips.get_mut(channel_idx as usize)
+        {
+            let picked = alternates
+                .iter()
+                .filter(|clip| clip.channel == channel_idx && clip.clip == clip_idx)
+                .flat_map(ClipAlternates::candidates);
+            for clip in std::iter::once(clip_idx).chain(picked) {
+                if !channel.contains(&clip) {
+                    channel.push(clip);
+                }
             }
         }
+    }
+    for channel in &mut clips {
+        channel.sort();
+    }
+    clips
+}
 
-        for (channel_idx, clips) in self.clip_idxs_in_cue(&cue).iter_mut().enumerate() {
-            clips.sort();
-            for (slot_idx, clip) in clips.iter().enumerate() {
-                let buf = self.load_wav_buf(channel_idx, *clip);
-                self.clips[channel_idx][slot_idx].write(*clip as usize, buf);
+/// Reads playback media on its own thread, so a slow or stalled show directory, e.g. on a
+/// network mount, never holds up the main loop. Media of the cues around the loaded one is
+/// kept in memory.
+struct MediaLoader {
+    show_path: PathBuf,
+    num_channels: usize,
+    cbnet: CrossbeamNetwork,
+    show: Show,
+    /// Alternate clips by cue
+    alternates: Vec<Vec<ClipAlternates>>,
+    slots: Vec<Vec<ClipSlot>>,
+    /// Media by (channel, clip)
+    cache: HashMap<(usize, u16), Arc<ClipMedia>>,
+    /// Cue last asked to be prefetched, e.g. the cue in standby
+    prefetched: Option<u8>,
+    /// Sample rate of the audio server, which clips are converted to
+    sample_rate: u32,
+    /// Length in seconds past which clips are streamed, none to read every clip whole
+    stream_media_s: Option<u32>,
+    /// Sent the streamed clips in the slots whenever they change
+    streamer: Sender<Vec<Arc<ClipStream>>>,
+}
+
+impl MediaLoader {
+    fn run(mut self, jobs: Receiver<LoaderJob>) {
+        while let Ok(job) = jobs.recv() {
+            let mut pending: Vec<LoaderJob> = std::iter::once(job).chain(jobs.try_iter()).collect();
+            // If loading has fallen behind, only the last cue asked for is worth loading
+            if let Some(last) = pending
+                .iter()
+                .rposition(|job| matches!(job, LoaderJob::Load(_)))
+            {
+                let mut idx = 0;
+                pending.retain(|job| {
+                    idx += 1;
+                    idx > last || !matches!(job, LoaderJob::Load(_))
+                });
+            }
+            for job in pending {
+                self.handle(job);
             }
         }
+    }
 
+    fn handle(&mut self, job: LoaderJob) {
+        match job {
+            LoaderJob::Show {
+                show,
+                alternates,
+                slots,
+                sample_rate,
+                show_path,
+            } => {
+                self.show = show;
+                self.show_path = show_path;
+                self.alternates = alternates;
+                self.slots = slots;
+                self.sample_rate = sample_rate;
+                self.cache.clear();
+                self.prefetched = None;
+                let _ = self.streamer.send(vec![]);
+            }
+            LoaderJob::Load(cue_idx) => {
+                self.load(cue_idx);
+                for next in 1..=PREFETCH_CUES {
+                    self.read_cue(cue_idx.saturating_add(next));
+                }
+                self.evict(cue_idx);
+            }
+            LoaderJob::Prefetch(cue_idx) => {
+                self.read_cue(cue_idx);
+                self.prefetched = Some(cue_idx);
+            }
+        }
+    }
+
+    fn load(&mut self, cue_idx: u8) {
+        if cue_idx as usize >= self.show.cues.len() {
+            return;
+        }
+        let start = Instant::now();
+        // Slots keep their media until they are filled, so clips the cues share play on and a
+        // transport started while reading does not find every slot empty. The clips of the
+        // previous cue are not played, as playback events find their clip by index. Media that
+        // could not be read is not cached, and is tried again.
+        let clips = self.clips_in_cue(cue_idx);
+        for (channel, slots) in self.slots.clone().iter().enumerate() {
+            let clips = clips.get(channel).map_or(&[][..], Vec::as_slice);
+            for (slot, clip) in slots.iter().zip(clips) {
+                if slot.read_index() != *clip as usize
+                    || !self.cache.contains_key(&(channel, *clip))
+                {
+                    slot.write(*clip as usize, self.media(channel, *clip));
+                }
+            }
+            for slot in slots.iter().skip(clips.len()) {
+                slot.write(usize::MAX, Arc::default());
+            }
+        }
+        let _ = self.streamer.send(
+            self.slots
+                .iter()
+                .flatten()
+                .filter_map(ClipSlot::stream)
+                .collect(),
+        );
+
+        if start.elapsed() > SLOW_LOAD {
+            self.cbnet.log(LogItem::new(
+                format!(
+                    "Loading the media of cue {cue_idx} took {:.1} s",
+                    start.elapsed().as_secs_f32()
+                ),
+                LogContext::AudioSource,
+                LogKind::Warning,
+            ));
+        }
         self.cbnet
             .notify(Message::Large(LargeMessage::PlaybackHandlerChanged(
-                self.get_status(),
+                slot_status(&self.slots),
             )));
     }
 
-    pub fn get_status(&self) -> PlaybackHandlerStatus {
-        PlaybackHandlerStatus {
-            clips: self
-                .clips
-                .iter()
-                .map(|v| v.iter().map(|c| c.read_index() as u16).collect())
-                .collect(),
+    /// Clips of a cue of the show by channel, see `clip_idxs_in_cue`, none if there is no such
+    /// cue.
+    fn clips_in_cue(&self, cue_idx: u8) -> Vec<Vec<u16>> {
+        let Some(cue) = self.show.cues.get(cue_idx as usize) else {
+            return vec![];
+        };
+        let alternates = self
+            .alternates
+            .get(cue_idx as usize)
+            .map_or(&[][..], Vec::as_slice);
+        clip_idxs_in_cue(cue, alternates, self.num_channels)
+    }
+
+    /// Reads the media of a cue into the cache.
+    fn read_cue(&mut self, cue_idx: u8) {
+        for (channel, clips) in self.clips_in_cue(cue_idx).into_iter().enumerate() {
+            for clip in clips {
+                self.media(channel, clip);
+            }
+        }
+    }
+
+    /// Drops cached media not used by the loaded cue, the cues read ahead or the prefetched cue.
+    fn evict(&mut self, cue_idx: u8) {
+        let mut keep = vec![];
+        for idx in (0..=PREFETCH_CUES)
+            .map(|next| cue_idx.saturating_add(next))
+            .chain(self.prefetched)
+        {
+            for (channel, clips) in self.clips_in_cue(idx).into_iter().enumerate() {
+                keep.extend(clips.into_iter().map(|clip| (channel, clip)));
+            }
+        }
+        self.cache.retain(|key, _| keep.contains(key));
+    }
+
+    /// Media of a clip from the cache or the disk. A clip that cannot be read is reported and
+    /// played as silence, and is tried again the next time it is needed.
+    fn media(&mut self, channel: usize, clip: u16) -> Arc<ClipMedia> {
+        if let Some(media) = self.cache.get(&(channel, clip)) {
+            return Arc::clone(media);
+        }
+        match self.read_media(channel, clip) {
+            Ok(media) => {
+                let media = Arc::new(media);
+                self.cache.insert((channel, clip), Arc::clone(&media));
+                media
+            }
+            Err(error) => {
+                self.cbnet.log(LogItem::new(
+                    format!("Error opening playback media: {error}"),
+                    LogContext::AudioSource,
+                    LogKind::Error,
+                ));
+                self.cbnet.notify_core(CoreMessage::MediaUnavailable {
+                    channel: channel as u16,
+                    clip,
+                    error,
+                });
+                Arc::default()
+            }
+        }
+    }
+
+    fn read_media(&self, channel: usize, clip: u16) -> Result<ClipMedia, String> {
+        let path = self
+            .show_path
+            .join(format!("playback_media/{:0>3}/{:0>3}.wav", channel, clip));
+        let mut attempt = 1;
+        loop {
+            match self.open_media(&path) {
+                Ok(media) => return Ok(media),
+                Err(hound::Error::IoError(err)) if attempt < MEDIA_READ_ATTEMPTS => {
+                    self.cbnet.log(LogItem::new(
+                        format!("Retrying playback media {}: {err}", path.display()),
+                        LogContext::AudioSource,
+                        LogKind::Warning,
+                    ));
+                    std::thread::sleep(MEDIA_RETRY_DELAY * attempt);
+                    attempt += 1;
+                }
+                Err(err) => return Err(format!("{}: {err}", path.display())),
+            }
+        }
+    }
+
+    /// Streams a long mono clip, and reads any other whole. Either is converted to the sample
+    /// rate of the audio server.
+    fn open_media(&self, path: &Path) -> Result<ClipMedia, hound::Error> {
+        let reader = hound::WavReader::open(path)?;
+        let spec = reader.spec();
+        if let Some(seconds) = self.stream_media_s
+            && spec.channels == 1
+            && reader.duration() as u64 > seconds as u64 * spec.sample_rate as u64
+        {
+            return ClipStream::open(path.to_path_buf(), reader, self.sample_rate)
+                .map(|stream| ClipMedia::Streamed(Arc::new(stream)));
         }
+        let bytes = std::fs::read(path)?;
+        let buffer = decode_wav(&bytes)?;
+        Ok(ClipMedia::Loaded(
+            match Resampler::new(spec.sample_rate, self.sample_rate) {
+                Some(resampler) => resampler.convert(&buffer),
+                None => buffer,
+            },
+        ))
     }
 }
 
+fn decode_wav(bytes: &[u8]) -> Result<AudioBuffer, hound::Error> {
+    let mut reader = hound::WavReader::new(std::io::Cursor::new(bytes))?;
+    read_samples(&mut reader, usize::MAX)
+}
+
 #[cfg(test)]
 mod tests {
     use common::event::Event;
 
     use super::*;
+    use crate::show_extension::{ClipChain, MIN_AUTOMATION_GAIN_DB};
+
+    fn device_with_clips(count: usize) -> PlaybackDevice {
+        let mut device = PlaybackDevice::new(0, PathBuf::new());
+        for idx in 0..count {
+            device.clips.push(AudioClip::new(ClipSlot::new(idx)));
+        }
+        device
+    }
+
+    #[test]
+    fn status_of_any_number_of_clips() {
+        for count in [0, 3, 16, 40] {
+            let status = device_with_clips(count).make_status();
+            for (idx, clip) in status.clips.iter().enumerate() {
+                assert_eq!(*clip, if idx < count { idx as u16 } else { 0 });
+            }
+            assert_eq!(status.clip_length, 0);
+        }
+    }
+
+    #[test]
+    fn plays_silence_without_clip_slot() {
+        let mut device = device_with_clips(0);
+        device.active = true;
+        let mut ctx = AudioSourceContext::default();
+        ctx.frame_size = 256;
+        ctx.transport.running = true;
+        assert!(device.send_buffer(&ctx).unwrap().iter().all(|s| *s == 0.0));
+        assert!(!device.active);
+    }
+
+    #[test]
+    fn seeks_outside_clip_data() {
+        let mut device = device_with_clips(1);
+        device.clips[0]
+            .slot
+            .write(7, Arc::new(ClipMedia::Loaded(vec![1.0; 100])));
+        let mut out = [2.0; 20];
+        assert_eq!(device.clips[0].read_buffer_slice(90, &mut out), 10);
+        assert_eq!(out[9..11], [1.0, 0.0]);
+        assert_eq!(device.clips[0].read_buffer_slice(u32::MAX, &mut out), 0);
+
+        let mut ctx = AudioSourceContext::default();
+        let mut seek = |clip_idx, sample| {
+            ctx.cue = Cue::empty();
+            ctx.cue.events.set(
+                0,
+                Event::new(
+                    0,
+                    EventDescription::PlaybackEvent {
+                        channel_idx: 0,
+                        clip_idx,
+                        sample,
+                    },
+                ),
+            );
+            device.command(&ctx, ControlAction::TransportJumpBeat(2));
+            (
+                device.active,
+                device.waiting,
+                ctx.cbnet.core_notif_rx.try_recv().ok(),
+            )
+        };
+        assert!(matches!(seek(7, 50), (true, None, None)));
+        assert!(matches!(
+            seek(7, 500),
+            (
+                true,
+                None,
+                Some(CoreMessage::SeekResult {
+                    clip: 7,
+                    sample: 500,
+                    outcome: SeekOutcome::PastEnd { length: 100 },
+                    ..
+                })
+            )
+        ));
+        // Not in a slot, so not played from another clip's
+        assert!(matches!(
+            seek(9, 0),
+            (
+                false,
+                Some(9),
+                Some(CoreMessage::SeekResult {
+                    outcome: SeekOutcome::NotLoaded,
+                    ..
+                })
+            )
+        ));
+    }
+
+    #[test]
+    fn chained_clips_play_gapless() {
+        let mut device = device_with_clips(2);
+        device.clips[0]
+            .slot
+            .write(4, Arc::new(ClipMedia::Loaded(vec![1.0; 300])));
+        device.clips[1]
+            .slot
+            .write(5, Arc::new(ClipMedia::Loaded(vec![2.0; 300])));
+        device.chains = ClipChains::new(&[ClipChain {
+            channel: 0,
+            clips: vec![4, 5],
+        }]);
+        device.active = true;
+        let mut ctx = AudioSourceContext::default();
+        ctx.frame_size = 256;
+        ctx.transport.running = true;
+
+        assert!(device.send_buffer(&ctx).unwrap().iter().all(|s| *s == 1.0));
+        let buf = device.send_buffer(&ctx).unwrap();
+        assert!(buf[..44].iter().all(|s| *s == 1.0));
+        assert!(buf[44..].iter().all(|s| *s == 2.0));
+        assert_eq!((device.current_clip, device.current_sample), (1, 212));
+
+        // The playback event of the chained clip does not restart it
+        device.event_occured(
+            &ctx,
+            Event::new(
+                8,
+                EventDescription::PlaybackEvent {
+                    channel_idx: 0,
+                    clip_idx: 5,
+                    sample: 0,
+                },
+            ),
+        );
+        assert_eq!((device.current_clip, device.current_sample), (1, 212));
+        // and runs out like any other clip
+        assert!(device.send_buffer(&ctx).unwrap().iter().all(|s| *s == 0.0));
+        assert!(!device.active);
+    }
+
+    #[test]
+    fn stop_all_fades_out_and_stays_silent() {
+        let mut device = device_with_clips(1);
+        device.clips[0]
+            .slot
+            .write(3, Arc::new(ClipMedia::Loaded(vec![1.0; 2048])));
+        device.active = true;
+        let mut ctx = AudioSourceContext::default();
+        ctx.frame_size = 256;
+        ctx.sample_rate = 1000;
+        ctx.transport.running = true;
+        let playback_event = Event::new(
+            4,
+            EventDescription::PlaybackEvent {
+                channel_idx: 0,
+                clip_idx: 3,
+                sample: 0,
+            },
+        );
+
+        device.core_command(&ctx, CoreControlAction::StopAllPlayback { fade_ms: 512.0 });
+        let buf = device.send_buffer(&ctx).unwrap();
+        assert_eq!(buf[0], 1.0);
+        assert_eq!(buf[255], 0.501_953_1);
+        assert!(device.send_buffer(&ctx).unwrap().iter().all(|s| *s <= 0.5));
+        assert!(!device.active);
+        assert!(device.send_buffer(&ctx).unwrap().iter().all(|s| *s == 0.0));
+
+        // Later playback events of the cue do not bring the channel back
+        device.event_occured(&ctx, playback_event.clone());
+        assert!(!device.active);
+        // until the transport has been stopped
+        device.command(&ctx, ControlAction::TransportStop);
+        device.event_occured(&ctx, playback_event);
+        assert!(device.active);
+        assert!(device.send_buffer(&ctx).unwrap().iter().all(|s| *s == 1.0));
+    }
+
+    #[test]
+    fn fade_events_ramp_and_stop() {
+        let mut device = device_with_clips(1);
+        device.clips[0]
+            .slot
+            .write(3, Arc::new(ClipMedia::Loaded(vec![1.0; 2048])));
+        device.active = true;
+        let mut ctx = AudioSourceContext::default();
+        ctx.frame_size = 256;
+        ctx.sample_rate = 1000;
+        ctx.transport.running = true;
+        ctx.transport.playrate_percent = 100;
+        let fade = |channel, from_db, to_db| {
+            CoreControlAction::FadePlayback(FadeEvent {
+                beat: 0,
+                channel,
+                from_db,
+                to_db,
+                length: FadeLength::Ms(512.0),
+            })
+        };
+
+        // Fades of other channels are left to their devices
+        device.core_command(&ctx, fade(1, None, -90.0));
+        assert!(device.send_buffer(&ctx).unwrap().iter().all(|s| *s == 1.0));
+
+        device.core_command(&ctx, fade(0, Some(-90.0), 0.0));
+        let buf = device.send_buffer(&ctx).unwrap();
+        assert_eq!(buf[0], 0.0);
+        assert_eq!(buf[128], 0.25);
+        device.core_command(&ctx, fade(0, None, -90.0));
+        let buf = device.send_buffer(&ctx).unwrap();
+        assert_eq!(buf[0], 0.5);
+        assert!(buf[255] < buf[0]);
+        assert!(device.active);
+        // A fade to silence stops the clip once it has run out
+        device.send_buffer(&ctx).unwrap();
+        assert!(!device.active);
+
+        // A fade in beats lasts as long as the beats of the cue
+        ctx.cue = Cue::example();
+        let length = ctx.cue.get_beat(0).unwrap().length as u64;
+        let event = FadeEvent {
+            length: FadeLength::Beats(1.5),
+            ..FadeEvent::default()
+        };
+        assert_eq!(
+            fade_length(&ctx, &event) as u64,
+            ctx.us_to_samples(length + ctx.cue.get_beat(1).unwrap().length as u64 / 2)
+        );
+        ctx.transport.playrate_percent = 50;
+        assert_eq!(
+            fade_length(
+                &ctx,
+                &FadeEvent {
+                    length: FadeLength::Beats(1.0),
+                    ..event
+                }
+            ) as u64,
+            ctx.us_to_samples(length * 2)
+        );
+
+        // A seek into a fade carries on with it from where it lands
+        ctx.transport.playrate_percent = 100;
+        let event = FadeEvent {
+            channel: 0,
+            from_db: Some(MIN_AUTOMATION_GAIN_DB),
+            to_db: 0.0,
+            length: FadeLength::Beats(2.0),
+            ..FadeEvent::default()
+        };
+        device.core_command(
+            &ctx,
+            CoreControlAction::SetFadeEvents(FadeEvents::new(&[event])),
+        );
+        device.command(&ctx, ControlAction::TransportJumpBeat(1));
+        let mut buf = [1.0; 1];
+        device.gain_fade.apply(&mut buf);
+        let expected = ctx.us_to_samples(length) as f32 / fade_length(&ctx, &event) as f32;
+        assert!((buf[0] - expected).abs() < 1e-3);
+        device.command(&ctx, ControlAction::TransportJumpBeat(0));
+        let mut buf = [1.0; 1];
+        device.gain_fade.apply(&mut buf);
+        assert_eq!(buf, [1.0]);
+
+        // A fade to silence run out by then stops the clip
+        ctx.cue.events.set(
+            0,
+            Event::new(
+                0,
+                EventDescription::PlaybackEvent {
+                    channel_idx: 0,
+                    clip_idx: 3,
+                    sample: 0,
+                },
+            ),
+        );
+        let fade_out = |beats| FadeEvent {
+            from_db: None,
+            to_db: MIN_AUTOMATION_GAIN_DB,
+            length: FadeLength::Beats(beats),
+            ..event
+        };
+        device.core_command(
+            &ctx,
+            CoreControlAction::SetFadeEvents(FadeEvents::new(&[fade_out(2.0)])),
+        );
+        device.command(&ctx, ControlAction::TransportJumpBeat(1));
+        assert!(device.active);
+        device.core_command(
+            &ctx,
+            CoreControlAction::SetFadeEvents(FadeEvents::new(&[fade_out(1.0)])),
+        );
+        device.command(&ctx, ControlAction::TransportJumpBeat(1));
+        assert!(!device.active);
+    }
+
+    #[test]
+    fn alternate_picks() {
+        let alternates = |pick| {
+            AlternateClips::new(&[ClipAlternates {
+                channel: 0,
+                clip: 4,
+                alternates: vec![9, 7],
+                pick,
+            }])
+        };
+        let mut picker = ClipPicker::new(12345);
+        let round_robin = alternates(ClipPick::RoundRobin);
+        let picks: Vec<u16> = (0..4).map(|_| picker.pick(&round_robin, 0, 4)).collect();
+        assert_eq!(picks, [4, 9, 7, 4]);
+        assert_eq!(picker.last(&round_robin, 0, 4), 4);
+        assert_eq!(picker.pick(&round_robin, 0, 5), 5);
+
+        let random = alternates(ClipPick::Random);
+        let mut last = picker.pick(&random, 0, 4);
+        for _ in 0..100 {
+            let pick = picker.pick(&random, 0, 4);
+            assert!([4, 9, 7].contains(&pick) && pick != last);
+            last = pick;
+        }
+    }
+
     #[test]
 
     fn clips_counter() {
-        let pbh = PlaybackHandler::new(CrossbeamNetwork::new(), PathBuf::new(), 32);
+        let pbh = PlaybackHandler::new(CrossbeamNetwork::new(), PathBuf::new(), 32, None);
         for length in [0, 1, 2, 56, 100] {
             for channel in (0..34).step_by(2) {
                 let mut cue = Cue::empty();
@@ -291,17 +999,90 @@ mod tests {
                 }
                 if channel <= 32 {
                     assert_eq!(
-                        pbh.num_channel_clips_in_cue(&cue, channel as usize),
+                        pbh.num_channel_clips_in_cue(&cue, &[], channel as usize),
                         length.min(64) as usize
                     );
                 } else {
-                    assert_eq!(pbh.num_channel_clips_in_cue(&cue, channel as usize), 0);
+                    assert_eq!(pbh.num_channel_clips_in_cue(&cue, &[], channel as usize), 0);
                 }
             }
         }
     }
 }
 
+/// Picks among the alternates of a clip each time its playback event fires. Remembers what each
+/// clip played last while the core runs, so round robins carry on from one performance to the
+/// next and random picks do not repeat.
+#[derive(Debug)]
+struct ClipPicker {
+    /// Clip, and the index of its candidate played last
+    last: [(u16, usize); MAX_ALTERNATE_CLIPS],
+    len: usize,
+    /// State of the xorshift generator of random picks, never 0
+    random: u32,
+}
+
+impl ClipPicker {
+    fn new(seed: u32) -> Self {
+        Self {
+            last: [(0, 0); MAX_ALTERNATE_CLIPS],
+            len: 0,
+            random: seed.max(1),
+        }
+    }
+
+    fn next_random(&mut self) -> usize {
+        self.random ^= self.random << 13;
+        self.random ^= self.random >> 17;
+        self.random ^= self.random << 5;
+        self.random as usize
+    }
+
+    fn last_pick(&self, clip: u16) -> Option<usize> {
+        self.last[..self.len]
+            .iter()
+            .find(|(picked, _)| *picked == clip)
+            .map(|(_, candidate)| *candidate)
+    }
+
+    /// The clip a playback event of `clip` plays this time.
+    fn pick(&mut self, alternates: &AlternateClips, channel: u16, clip: u16) -> u16 {
+        let Some((count, pick)) = alternates.choice(channel, clip) else {
+            return clip;
+        };
+        let last = self.last_pick(clip);
+        let candidate = match (pick, last) {
+            (ClipPick::RoundRobin, Some(last)) => (last + 1) % count,
+            (ClipPick::RoundRobin, None) => 0,
+            (ClipPick::Random, Some(last)) if count > 1 => {
+                (last + 1 + self.next_random() % (count - 1)) % count
+            }
+            (ClipPick::Random, _) => self.next_random() % count,
+        };
+        match self.last[..self.len]
+            .iter_mut()
+            .find(|(picked, _)| *picked == clip)
+        {
+            Some(last) => last.1 = candidate,
+            None if self.len < MAX_ALTERNATE_CLIPS => {
+                self.last[self.len] = (clip, candidate);
+                self.len += 1;
+            }
+            None => {}
+        }
+        alternates
+            .candidate(channel, clip, candidate)
+            .unwrap_or(clip)
+    }
+
+    /// The clip a playback event of `clip` played last time, to place it when seeking.
+    fn last(&self, alternates: &AlternateClips, channel: u16, clip: u16) -> u16 {
+        self.last_pick(clip)
+            .and_then(|candidate| alternates.candidate(channel, clip, candidate))
+            .unwrap_or(clip)
+    }
+}
+
 #[derive(Debug)]
 pub struct PlaybackDevice {
     pub channel_idx: u16,
@@ -310,6 +1091,30 @@ pub struct PlaybackDevice {
     clips: Vec<AudioClip>,
     show_path: PathBuf,
     active: bool,
+    /// Clips of the current cue played back to back
+    chains: ClipChains,
+    /// Whether the current clip was reached by chaining rather than by its playback event
+    chained: bool,
+    /// Gain automation of the current cue
+    automation: GainAutomation,
+    /// Alternate clips of the current cue, and which of them were played last
+    alternates: AlternateClips,
+    picker: ClipPicker,
+    /// Clip audio of the current cycle, sized to the largest cycle size
+    buffer: Vec<f32>,
+    /// Fade-out of `CoreControlAction::StopAllPlayback`
+    fade: StopFade,
+    /// Fade events of the current cue, to pick up a fade where a seek lands in it
+    fade_events: FadeEvents,
+    /// Fade of the latest fade event of the channel
+    gain_fade: GainFade,
+    /// Stopped with `CoreControlAction::StopAllPlayback`, ignoring playback events until the
+    /// transport is stopped, zeroed, seeks or is started again
+    silenced: bool,
+    /// Clip whose playback event came before the loader put its media in a slot, as when a cue
+    /// is launched or continued into while playing. It starts where it would have been once
+    /// the media is there.
+    waiting: Option<u16>,
 }
 
 impl PlaybackDevice {
@@ -321,14 +1126,137 @@ impl PlaybackDevice {
             clips: vec![],
             show_path,
             active: false,
+            chains: ClipChains::default(),
+            chained: false,
+            automation: GainAutomation::default(),
+            alternates: AlternateClips::default(),
+            picker: ClipPicker::new(
+                SystemTime::now()
+                    .duration_since(SystemTime::UNIX_EPOCH)
+                    .map_or(0, |time| time.subsec_nanos())
+                    ^ channel_idx as u32,
+            ),
+            buffer: vec![0.0; DEFAULT_MAX_FRAME_SIZE],
+            fade: StopFade::default(),
+            fade_events: FadeEvents::default(),
+            gain_fade: GainFade::default(),
+            silenced: false,
+            waiting: None,
+        }
+    }
+
+    /// Stops playback until the transport is stopped, zeroed, seeks or is started again.
+    fn silence(&mut self, ctx: &AudioSourceContext) {
+        self.active = false;
+        self.silenced = true;
+        self.waiting = None;
+        self.fade.cancel();
+        ctx.cbnet.notify(Message::Small(SmallMessage::PlaybackData(
+            self.make_status(),
+        )));
+    }
+
+    /// Applies the gain automation of the channel to the cycle in the buffer, moving from the
+    /// gain at its start to the gain at its end.
+    fn automate(&mut self, ctx: &AudioSourceContext) {
+        let Some(from) = self
+            .automation
+            .gain_at(self.channel_idx, ctx.beat_position(0))
+        else {
+            return;
+        };
+        let to = self
+            .automation
+            .gain_at(self.channel_idx, ctx.beat_position(ctx.frame_us()))
+            .unwrap_or(from);
+        let buf = &mut self.buffer[..ctx.frame_size];
+        let step = (to - from) / buf.len() as f32;
+        for (idx, sample) in buf.iter_mut().enumerate() {
+            *sample *= from + step * idx as f32;
+        }
+    }
+
+    /// Applies the fade of the latest fade event of the channel, and a running stop-all fade, to
+    /// the clip audio of the cycle.
+    fn fade_out(&mut self, ctx: &AudioSourceContext) {
+        self.gain_fade.apply(&mut self.buffer[..ctx.frame_size]);
+        self.advance_gain_fade(ctx);
+        self.fade.apply(&mut self.buffer[..ctx.frame_size]);
+        if self.fade.advance(ctx.frame_size) {
+            self.silence(ctx);
+        }
+    }
+
+    /// Keeps time for the fade of the channel, stopping the clip once a fade to silence has run
+    /// out.
+    fn advance_gain_fade(&mut self, ctx: &AudioSourceContext) {
+        if !self.gain_fade.advance(ctx.frame_size) {
+            return;
+        }
+        self.gain_fade.reset();
+        if self.active || self.waiting.is_some() {
+            self.active = false;
+            self.waiting = None;
+            ctx.cbnet.notify(Message::Small(SmallMessage::PlaybackData(
+                self.make_status(),
+            )));
         }
     }
 
+    /// Picks up the fade of the channel where the fade events before `beat_idx` leave it,
+    /// `offset` samples from the start of the beat, so a seek carries on with a fade it lands
+    /// in. The clip is stopped if a fade to silence has run out by then.
+    fn seek_gain_fade(&mut self, ctx: &AudioSourceContext, beat_idx: u16, offset: i32) {
+        // Timed like the fades themselves, by the beats of the cue at the current playrate
+        let playrate = ctx.transport.playrate_percent.max(1) as u64;
+        let samples = |us: u64| ctx.us_to_samples(us * 100 / playrate) as usize;
+        self.gain_fade.reset();
+        let mut fade_start_us = 0;
+        let mut time_us = 0;
+        for beat in 0..beat_idx {
+            for event in self
+                .fade_events
+                .at(beat)
+                .filter(|event| event.channel == self.channel_idx)
+            {
+                if self.gain_fade.advance(samples(time_us - fade_start_us)) {
+                    self.gain_fade.reset();
+                }
+                self.gain_fade.start(
+                    event.from_db.map(gain_factor),
+                    gain_factor(event.to_db),
+                    fade_length(ctx, &event),
+                );
+                fade_start_us = time_us;
+            }
+            time_us += ctx.cue.get_beat(beat).unwrap_or_default().length as u64;
+        }
+        let position = (samples(time_us - fade_start_us) as i64 + offset as i64).max(0);
+        if self.gain_fade.advance(position as usize) {
+            self.gain_fade.reset();
+            self.active = false;
+            self.waiting = None;
+        }
+    }
+
+    /// Slot of the clip chained after the current one, if it is loaded.
+    fn chained_slot(&self) -> Option<usize> {
+        let clip = self.clips.get(self.current_clip)?.read_index();
+        let next = self
+            .chains
+            .next(self.channel_idx, u16::try_from(clip).ok()?)?;
+        self.clips
+            .iter()
+            .position(|clip| clip.read_index() == next as usize)
+    }
+
+    /// Clip playing on the channel at a beat of the cue, as the playback events before it left
+    /// it, and the sample it is at, which may be past its end. None if no clip is playing.
     fn calculate_time_at_beat(
-        &mut self,
+        &self,
         ctx: &AudioSourceContext,
         beat_idx: u16,
-    ) -> (usize, bool, i32) {
+    ) -> Option<(u16, i32)> {
         let mut running_active = false;
         let mut running_clip = 0;
         let mut running_sample = 0_i32;
@@ -346,7 +1274,8 @@ impl PlaybackDevice {
                     }) => {
                         if channel_idx == self.channel_idx {
                             running_sample = sample;
-                            running_clip = self.find_audioclip_idx_from_clip_idx(clip_idx);
+                            running_clip =
+                                self.picker.last(&self.alternates, channel_idx, clip_idx);
                             running_active = true;
                             time_off_us = 0;
                         }
@@ -361,15 +1290,59 @@ impl PlaybackDevice {
             }
             time_off_us += ctx.cue.get_beat(i).unwrap_or_default().length as u64;
         }
-        // TODO: support multiple and resampled sample rates
-        running_sample += time_off_us as i32 / 100 * 48 / 10;
-        (running_clip, running_active, running_sample)
+        let sample = running_sample as i64 + ctx.us_to_samples(time_off_us) as i64;
+        running_active.then_some((
+            running_clip,
+            sample.clamp(i32::MIN as i64, i32::MAX as i64) as i32,
+        ))
     }
 
+    /// Moves the channel to where it is at a beat of the cue, `offset` samples on. Clients are
+    /// told with `CoreMessage::SeekResult` when that is outside the loaded clip data.
+    fn seek(&mut self, ctx: &AudioSourceContext, beat_idx: u16, offset: i32) {
+        self.active = false;
+        self.waiting = None;
+        if self.silenced {
+            return;
+        }
+        let Some((clip_idx, sample)) = self.calculate_time_at_beat(ctx, beat_idx) else {
+            return;
+        };
+        self.current_sample = sample.saturating_add(offset);
+        let outcome = match self.find_audioclip_idx_from_clip_idx(clip_idx) {
+            Some(slot) => {
+                self.current_clip = slot;
+                self.active = true;
+                self.locate();
+                let length = self.current_clip_length();
+                // A chained clip carries on from here
+                if self.current_sample < 0 || (self.current_sample as u32) < length {
+                    return;
+                }
+                SeekOutcome::PastEnd { length }
+            }
+            None => {
+                // Starts in place once the loader has put its media in a slot
+                self.waiting = Some(clip_idx);
+                SeekOutcome::NotLoaded
+            }
+        };
+        ctx.cbnet.notify_core(CoreMessage::SeekResult {
+            channel: self.channel_idx,
+            beat: beat_idx,
+            clip: clip_idx,
+            sample: self.current_sample,
+            outcome,
+        });
+    }
+
+    /// Status of the channel. `PlaybackState` has room for the first 16 clip slots only, the
+    /// clips of all slots of every channel are sent in `PlaybackHandlerChanged` when a cue is
+    /// loaded.
     fn make_status(&self) -> PlaybackState {
         let mut clips = [0u16; 16];
-        for (i, clip) in self.clips.iter().enumerate() {
-            clips[i] = clip.read_index() as u16;
+        for (status, clip) in clips.iter_mut().zip(&self.clips) {
+            *status = clip.read_index() as u16;
         }
         PlaybackState {
             channel: self.channel_idx as u8,
@@ -377,44 +1350,153 @@ impl PlaybackDevice {
             clip_idx: self.current_clip as u16,
             current_sample: self.current_sample,
             playing: self.active,
-            clip_length: if self.clips.is_empty() {
-                0
-            } else {
-                self.clips[self.current_clip].get_length()
-            },
+            clip_length: self.current_clip_length(),
         }
     }
 
-    fn find_audioclip_idx_from_clip_idx(&self, clip_idx: u16) -> usize {
-        for (i, clip) in self.clips.iter().enumerate() {
-            if clip.read_index() == clip_idx as usize {
-                return i;
+    /// Length of the current clip, 0 if the channel has no slot for it.
+    fn current_clip_length(&self) -> u32 {
+        self.clips
+            .get(self.current_clip)
+            .map_or(0, AudioClip::get_length)
+    }
+
+    /// Whether the playback event of a clip is taken care of by chaining: the clip follows the
+    /// current one, or the current clip was reached by chaining into it.
+    fn chained_clip_event(&self, clip_idx: u16) -> bool {
+        let Some(current) = self.clips.get(self.current_clip).map(AudioClip::read_index) else {
+            return false;
+        };
+        (self.chained && current == clip_idx as usize)
+            || u16::try_from(current)
+                .ok()
+                .and_then(|current| self.chains.next(self.channel_idx, current))
+                == Some(clip_idx)
+    }
+
+    /// Starts the waiting clip if its media has been loaded into a slot.
+    fn take_waiting_clip(&mut self) {
+        let Some(clip_idx) = self.waiting else {
+            return;
+        };
+        if let Some(slot) = self.find_audioclip_idx_from_clip_idx(clip_idx) {
+            self.active = true;
+            self.current_clip = slot;
+            self.waiting = None;
+            self.locate();
+        }
+    }
+
+    /// Lets a streamed current clip read ahead from where it is about to play.
+    fn locate(&self) {
+        if let Some(clip) = self.clips.get(self.current_clip) {
+            clip.locate(self.current_sample.max(0) as u32);
+        }
+    }
+
+    /// Slot the media of a clip is in, if it has been loaded.
+    fn find_audioclip_idx_from_clip_idx(&self, clip_idx: u16) -> Option<usize> {
+        self.clips
+            .iter()
+            .position(|clip| clip.read_index() == clip_idx as usize)
+    }
+}
+
+/// Reads from the clip in slot `slot` into `out`, silence if there is no such slot. Returns the
+/// number of samples of clip data read.
+fn read_slot(clips: &[AudioClip], slot: usize, start: u32, out: &mut [f32]) -> usize {
+    match clips.get(slot) {
+        Some(clip) => clip.read_buffer_slice(start, out),
+        None => {
+            out.fill(0.0);
+            0
+        }
+    }
+}
+
+/// Length in samples of a fade from the beat of its event. A fade in beats is timed by the
+/// beats of the cue at the playrate it starts at, and ends with the cue at the latest.
+fn fade_length(ctx: &AudioSourceContext, event: &FadeEvent) -> usize {
+    match event.length {
+        FadeLength::Ms(ms) => (ms * ctx.sample_rate as f32 / 1000.0) as usize,
+        FadeLength::Beats(beats) => {
+            let mut length_us = 0.0;
+            let mut left = beats as f64;
+            let mut beat_idx = event.beat;
+            while left > 0.0
+                && let Some(beat) = ctx.cue.get_beat(beat_idx)
+            {
+                length_us += beat.length as f64 * left.min(1.0);
+                left -= 1.0;
+                let Some(next) = beat_idx.checked_add(1) else {
+                    break;
+                };
+                beat_idx = next;
             }
+            let rate = ctx.click_rate_percent() as f64;
+            ctx.us_to_samples((length_us * 100.0 / rate) as u64) as usize
         }
-        0
     }
 }
 
 impl AudioSource for PlaybackDevice {
-    fn send_buffer(&mut self, ctx: &AudioSourceContext) -> Result<&[f32], jack::Error> {
+    fn send_buffer<'a>(
+        &'a mut self,
+        ctx: &'a AudioSourceContext,
+    ) -> Result<&'a [f32], jack::Error> {
         if !ctx.transport.running {
-            return Ok(self.silence(ctx.frame_size));
+            return Ok(ctx.silence());
+        }
+
+        // Keep time for a clip waiting for its media, so it starts in place
+        if !self.active && self.waiting.is_some() {
+            self.take_waiting_clip();
+            if !self.active {
+                self.current_sample += ctx.frame_size as i32;
+            }
         }
 
         // If currently not playing or prerolling before playing, return silence
         if !self.active || self.current_sample < 0 {
-            return Ok(self.silence(ctx.frame_size));
+            self.advance_gain_fade(ctx);
+            return Ok(ctx.silence());
         }
 
-        // If about to run out of clip length, return silence and stop playback
-        if self.current_sample + ctx.frame_size as i32
-            > self.clips[self.current_clip].get_length() as i32
+        // Running out of a chained clip, continue on the next one from the sample it ends on
+        let length = self.current_clip_length() as i32;
+        if self.current_sample + ctx.frame_size as i32 > length
+            && let Some(next) = self.chained_slot()
         {
+            let tail = (length - self.current_sample).max(0) as usize;
+            let buf = &mut self.buffer[..ctx.frame_size];
+            let (end, start) = buf.split_at_mut(tail);
+            read_slot(
+                &self.clips,
+                self.current_clip,
+                self.current_sample as u32,
+                end,
+            );
+            read_slot(&self.clips, next, 0, start);
+            self.current_clip = next;
+            self.current_sample = start.len() as i32;
+            self.chained = true;
+            self.automate(ctx);
+            self.fade_out(ctx);
+            ctx.cbnet.notify(Message::Small(SmallMessage::PlaybackData(
+                self.make_status(),
+            )));
+            return Ok(&self.buffer[..ctx.frame_size]);
+        }
+
+        // If about to run out of clip length, return silence and stop playback
+        if self.current_sample + ctx.frame_size as i32 > length {
             self.active = false;
+            self.fade.cancel();
+            self.advance_gain_fade(ctx);
             ctx.cbnet.notify(Message::Small(SmallMessage::PlaybackData(
                 self.make_status(),
             )));
-            return Ok(self.silence(ctx.frame_size));
+            return Ok(ctx.silence());
         }
 
         ctx.cbnet.notify(Message::Small(SmallMessage::PlaybackData(
@@ -422,30 +1504,43 @@ impl AudioSource for PlaybackDevice {
         )));
 
         // All is well, return clip audio
-        let buf = self.clips[self.current_clip]
-            .read_buffer_slice(self.current_sample as u32, ctx.frame_size);
+        let buf = &mut self.buffer[..ctx.frame_size];
+        read_slot(
+            &self.clips,
+            self.current_clip,
+            self.current_sample as u32,
+            buf,
+        );
         self.current_sample += ctx.frame_size as i32;
-        Ok(&buf[0..ctx.frame_size])
+        self.automate(ctx);
+        self.fade_out(ctx);
+        Ok(&self.buffer[..ctx.frame_size])
     }
 
     fn command(&mut self, ctx: &AudioSourceContext, command: ControlAction) {
         match command {
-            ControlAction::TransportStop => {
-                self.active = false;
-            }
-            ControlAction::TransportZero => {
+            ControlAction::TransportStop | ControlAction::TransportZero => {
                 self.active = false;
+                self.silenced = false;
+                self.waiting = None;
+                self.fade.cancel();
+                self.gain_fade.reset();
             }
 
+            // A start after an all stop plays again, rather than waiting for a stop
+            ControlAction::TransportStart if !ctx.transport.running => self.silenced = false,
             ControlAction::TransportJumpBeat(beat_idx) => {
-                (self.current_clip, self.active, self.current_sample) =
-                    self.calculate_time_at_beat(ctx, beat_idx);
+                self.seek(ctx, beat_idx, 0);
+                self.seek_gain_fade(ctx, beat_idx, 0);
             }
             ControlAction::TransportSeekBeat(beat_idx) => {
-                (self.current_clip, self.active, self.current_sample) =
-                    self.calculate_time_at_beat(ctx, beat_idx);
-                // TODO: Support multiple and mixed sample rates
-                self.current_sample -= (ctx.beat.us_to_next_beat as i32) * 48 / 1000
+                self.silenced = false;
+                self.waiting = None;
+                self.fade.cancel();
+                let preroll = ctx.us_to_samples(ctx.beat.us_to_next_beat as u64);
+                let offset = -(preroll.min(i32::MAX as u64) as i32);
+                self.seek(ctx, beat_idx, offset);
+                self.seek_gain_fade(ctx, beat_idx, offset);
             }
             _ => {}
         }
@@ -462,28 +1557,31 @@ impl AudioSource for PlaybackDevice {
                 clip_idx,
                 sample,
             }) => {
-                if channel_idx != self.channel_idx {
+                if channel_idx != self.channel_idx || self.silenced {
+                    return;
+                }
+                // A chained clip is already playing, or about to, from where its predecessor
+                // ends
+                if self.active && self.chained_clip_event(clip_idx) {
+                    self.chained = false;
                     return;
                 }
 
                 self.active = false;
+                self.chained = false;
+                self.waiting = Some(self.picker.pick(&self.alternates, channel_idx, clip_idx));
                 // TODO: offset current_sample by time since the event actually occured,
                 // which is 0 <= t < frame_size, since this runs at the start (?) of the
                 // first frame *after* the beat and thus event occurs
                 self.current_sample = sample;
-                for (i, clip) in self.clips.iter().enumerate() {
-                    if clip.read_index() == clip_idx as usize {
-                        self.active = true;
-                        self.current_clip = i;
-                        break;
-                    }
-                }
+                self.take_waiting_clip();
             }
             Some(EventDescription::PlaybackStopEvent { channel_idx }) => {
                 if channel_idx != self.channel_idx {
                     return;
                 }
                 self.active = false;
+                self.waiting = None;
 
                 ctx.cbnet.notify(Message::Small(SmallMessage::PlaybackData(
                     self.make_status(),
@@ -494,4 +1592,34 @@ impl AudioSource for PlaybackDevice {
     }
 
     fn event_will_occur(&mut self, ctx: &AudioSourceContext, event: common::event::Event) {}
+
+    fn core_command(&mut self, ctx: &AudioSourceContext, command: CoreControlAction) {
+        match command {
+            CoreControlAction::SetClipChains(chains) => self.chains = chains,
+            CoreControlAction::SetGainAutomation(automation) => self.automation = automation,
+            CoreControlAction::SetClipAlternates(alternates) => self.alternates = alternates,
+            CoreControlAction::SetFadeEvents(events) => self.fade_events = events,
+            CoreControlAction::FadePlayback(event) if event.channel == self.channel_idx => {
+                self.gain_fade.start(
+                    event.from_db.map(gain_factor),
+                    gain_factor(event.to_db),
+                    fade_length(ctx, &event),
+                );
+            }
+            // `StopPlaybackChannel` is only sent to the device of its channel
+            CoreControlAction::StopAllPlayback { fade_ms }
+            | CoreControlAction::StopPlaybackChannel { fade_ms, .. } => {
+                let length = (fade_ms.max(0.0) * ctx.sample_rate as f32 / 1000.0) as usize;
+                if self.active && self.fade.start(length) {
+                    return;
+                }
+                self.silence(ctx);
+            }
+            _ => {}
+        }
+    }
+
+    fn set_max_frame_size(&mut self, frames: usize) {
+        self.buffer.resize(frames, 0.0);
+    }
 }
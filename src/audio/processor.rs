@@ -11,37 +11,192 @@ use common::{
         request::ControlAction,
     },
 };
-use jack::{AudioOut, Client, Control, Port, ProcessHandler, ProcessScope, Unowned};
+use jack::{AudioIn, AudioOut, Client, Control, Frames, Port, ProcessHandler, ProcessScope};
+use std::time::Instant;
 
 use crate::{
-    CrossbeamNetwork,
-    audio::source::{AudioSourceContext, SourceConfig},
+    audio::{
+        accent::starts_bar,
+        count_in::{CountIn, CountInStep},
+        countdown::{COUNTDOWN_RATE, CueCountdown},
+        delay::DelayLine,
+        eq::ParametricEq,
+        fade::StopFade,
+        follower::TempoFollower,
+        phase::{BeatPhaseStream, beat_phase},
+        profile::{CycleProfile, thread_allocations},
+        source::{AudioSource, AudioSourceContext, DEFAULT_MAX_FRAME_SIZE, SourceConfig},
+    },
+    cbnet::CrossbeamNetwork,
+    communication::extension::{CoreControlAction, CoreMessage, LaunchBoundary},
+    metrics::MAX_METERED_INPUTS,
+    show_extension::{CueMarkers, EndOfCue, FadeEvents, MuteEvents, ZeroTarget},
 };
 
+/// Clock and buffer size of one process cycle.
+#[derive(Debug, Clone, Copy)]
+pub struct CycleTiming {
+    /// Time at the start of the cycle, in microseconds
+    pub time_us: u64,
+    pub frame_size: usize,
+    pub sample_rate: usize,
+}
+
+/// Captured input buffers of a process cycle.
+pub trait AudioInputs {
+    fn input_count(&self) -> usize;
+    fn input(&self, idx: usize) -> &[f32];
+}
+
+impl AudioInputs for Vec<Vec<f32>> {
+    fn input_count(&self) -> usize {
+        self.len()
+    }
+
+    fn input(&self, idx: usize) -> &[f32] {
+        &self[idx]
+    }
+}
+
+/// Output buffers a process cycle writes into, one per client output port.
+pub trait AudioOutputs {
+    fn output(&mut self, idx: usize) -> &mut [f32];
+}
+
+impl AudioOutputs for Vec<Vec<f32>> {
+    fn output(&mut self, idx: usize) -> &mut [f32] {
+        &mut self[idx]
+    }
+}
+
+struct JackInputs<'a> {
+    ports: &'a [Port<AudioIn>],
+    ps: &'a ProcessScope,
+}
+
+impl AudioInputs for JackInputs<'_> {
+    fn input_count(&self) -> usize {
+        self.ports.len()
+    }
+
+    fn input(&self, idx: usize) -> &[f32] {
+        self.ports[idx].as_slice(self.ps)
+    }
+}
+
+struct JackOutputs<'a> {
+    ports: &'a mut [Port<AudioOut>],
+    ps: &'a ProcessScope,
+}
+
+impl AudioOutputs for JackOutputs<'_> {
+    fn output(&mut self, idx: usize) -> &mut [f32] {
+        self.ports[idx].as_mut_slice(self.ps)
+    }
+}
+
+/// Another show handed to the running processor, with the playback sources that play its media,
+/// which replace the last sources of the processor. The processor hands back the show and
+/// sources it replaced, so they are dropped off the RT thread.
+pub struct ShowSwap {
+    pub show: Show,
+    pub playback: Vec<Box<dyn AudioSource>>,
+}
+
+/// Mixes all audio sources and keeps the playback state. Independent of JACK, so it can be
+/// driven by [`JackProcessor`] as well as by a simulated clock.
 pub struct AudioProcessor {
     sources: Vec<SourceConfig>,
+    output_eq: Vec<ParametricEq>,
+    output_delay: Vec<DelayLine>,
+    output_invert: Vec<bool>,
+    /// All outputs silenced, see `CoreControlAction::MuteOutputs`
+    outputs_muted: bool,
+    /// First channel and number of channels rendered, see `CoreControlAction::RenderChannels`
+    rendered_channels: Option<(u8, u8)>,
+    /// Client output ports the sources are mixed onto
+    output_ports: usize,
+    /// Per source, the gain it is summed into every client output port at, see
+    /// `CoreControlAction::SetOutputMix`. A source summed into no port plays on its own.
+    mix_sends: Vec<Vec<Option<f32>>>,
+    /// A processed source, before it is mixed onto the ports
+    mix_buf: Vec<f32>,
+    /// Per source, how long it took in this cycle while profiling, in microseconds
+    source_us: Vec<u32>,
+    /// Gain multiplier of every input into every output
+    pass_through: Vec<[f32; MAX_METERED_INPUTS]>,
+    tempo_follower: TempoFollower,
+    /// Time over which `SetChannelGain` ramps the gain
+    gain_ramp_ms: f32,
+    stop_fade: StopFade,
+    stop_fade_ms: f32,
+    beat_phase: BeatPhaseStream,
+    /// Cycles that publish a countdown, `COUNTDOWN_RATE` times per second
+    countdown_stream: BeatPhaseStream,
+    /// Beats and cue points of the current cue, counted down to
+    cue_countdown: CueCountdown,
+    cue_end: EndOfCue,
+    zero_target: ZeroTarget,
+    markers: CueMarkers,
+    mute_events: MuteEvents,
+    fade_events: FadeEvents,
+    /// Beat the transport was last started from
+    last_start_beat: u16,
+    /// Stop when the current bar ends, see `CoreControlAction::StopAtEndOfBar`
+    stop_at_bar: bool,
+    /// Cue to take over from the running one on a boundary, see `CoreControlAction::LaunchCue`
+    launch: Option<(u8, LaunchBoundary)>,
+    /// Count-in before the transport starts, see `CoreControlAction::StartWithCountIn`
+    count_in: Option<CountIn>,
+    /// Channel muted until the transport starts and the mute it had before, see
+    /// `CoreControlAction::MuteUntilStart`
+    muted_until_start: Option<(u8, bool)>,
     cbnet: CrossbeamNetwork,
     status: CombinedStatus,
     ctx: AudioSourceContext,
-    ports: (Vec<Port<AudioOut>>, Vec<Port<Unowned>>),
+    /// Frames processed since the processor was created
+    frame_time: u64,
     status_changed_flag: bool,
 }
 
 impl AudioProcessor {
-    pub fn new(
-        sources: Vec<SourceConfig>,
-        ports: (Vec<Port<AudioOut>>, Vec<Port<Unowned>>),
-        cbnet: CrossbeamNetwork,
-        show: Show,
-    ) -> AudioProcessor {
+    pub fn new(sources: Vec<SourceConfig>, cbnet: CrossbeamNetwork, show: Show) -> AudioProcessor {
         let mut a = AudioProcessor {
-            ports,
+            output_eq: vec![ParametricEq::default(); sources.len()],
+            output_delay: vec![DelayLine::default(); sources.len()],
+            output_invert: vec![false; sources.len()],
+            outputs_muted: false,
+            rendered_channels: None,
+            output_ports: sources.len(),
+            mix_sends: vec![vec![None; sources.len()]; sources.len()],
+            mix_buf: vec![0.0; DEFAULT_MAX_FRAME_SIZE],
+            source_us: vec![0; sources.len()],
+            pass_through: vec![[0.0; MAX_METERED_INPUTS]; sources.len()],
+            tempo_follower: TempoFollower::default(),
+            gain_ramp_ms: 0.0,
+            stop_fade: StopFade::default(),
+            stop_fade_ms: 0.0,
+            beat_phase: BeatPhaseStream::default(),
+            countdown_stream: BeatPhaseStream::default(),
+            cue_countdown: CueCountdown::default(),
+            cue_end: EndOfCue::default(),
+            zero_target: ZeroTarget::default(),
+            markers: CueMarkers::default(),
+            mute_events: MuteEvents::default(),
+            fade_events: FadeEvents::default(),
+            last_start_beat: 0,
+            stop_at_bar: false,
+            launch: None,
+            count_in: None,
+            muted_until_start: None,
             sources,
             cbnet,
             ctx: AudioSourceContext::default(),
+            frame_time: 0,
             status: CombinedStatus::default(),
             status_changed_flag: false,
         };
+        a.countdown_stream.set_rate(COUNTDOWN_RATE);
         a.load_show(show);
         a.send_all_status();
         a
@@ -85,10 +240,8 @@ impl AudioProcessor {
 
     fn load_cue(&mut self, cue: Cue) {
         self.status.transport.running = false;
+        self.cue_countdown = CueCountdown::new(&cue);
         self.status.cue.cue = cue;
-
-        self.cbnet.command(ControlAction::TransportStop);
-        self.cbnet.command(ControlAction::TransportZero);
         self.notify_push(MessageType::CueData);
         self.notify_push(MessageType::SmallCueData);
     }
@@ -99,7 +252,27 @@ impl AudioProcessor {
         self.notify_push(MessageType::ShowData);
     }
 
+    /// Takes over another show and its playback sources, and loads its first cue.
+    fn swap_show(&mut self, mut swap: ShowSwap) {
+        let first = self.sources.len().saturating_sub(swap.playback.len());
+        for (source, device) in self.sources[first..].iter_mut().zip(&mut swap.playback) {
+            std::mem::swap(&mut source.source_device, device);
+        }
+        std::mem::swap(&mut self.status.show, &mut swap.show);
+        self.cbnet.retire(swap);
+        self.launch = None;
+        self.cbnet.command(ControlAction::LoadCueByIndex(0));
+        self.notify_push(MessageType::ShowData);
+    }
+
     fn handle_command(&mut self, command: ControlAction) {
+        // Fade out first, the stop is carried out when the fade has run out
+        if command == ControlAction::TransportStop
+            && self.status.transport.running
+            && self.stop_fade.start(self.ms_to_samples(self.stop_fade_ms))
+        {
+            return;
+        }
         self.cbnet.log(LogItem::new(
             format!("ControlAction: {command}"),
             LogContext::AudioProcessor,
@@ -108,11 +281,19 @@ impl AudioProcessor {
         match command {
             ControlAction::DumpStatus => self.send_all_status(),
             ControlAction::TransportStart => {
+                self.drop_count_in();
+                self.end_mute_until_start();
+                self.stop_fade.cancel();
+                self.stop_at_bar = false;
                 self.status.transport.running = true;
+                self.last_start_beat = self.status.beat_state().next_beat_idx;
                 self.notify_push(MessageType::TransportData);
                 self.send_beat_events_to_children(self.status.beat_state().beat_idx);
             }
             ControlAction::TransportStop => {
+                self.drop_count_in();
+                self.stop_at_bar = false;
+                self.launch = None;
                 self.status.transport.running = false;
                 self.notify_push(MessageType::TransportData);
             }
@@ -122,6 +303,8 @@ impl AudioProcessor {
             }
 
             ControlAction::LoadCueByIndex(idx) => {
+                self.launch = None;
+                self.drop_count_in();
                 if idx < self.status.show.cues.len() as u8 {
                     self.status.cue.cue_idx = idx as u16;
                     self.load_cue(self.status.show.cues[idx as usize].clone());
@@ -129,7 +312,23 @@ impl AudioProcessor {
             }
 
             ControlAction::SetChannelGain(channel_idx, gain) => {
-                self.sources[channel_idx as usize].set_gain(gain);
+                let samples = self.ms_to_samples(self.gain_ramp_ms);
+                if let Some(source) = self.sources.get_mut(channel_idx as usize) {
+                    source.fade_gain(gain, samples);
+                }
+            }
+
+            ControlAction::SetChannelMute(channel_idx, muted) => {
+                // Muting or unmuting by hand ends a mute until the start
+                if self
+                    .muted_until_start
+                    .is_some_and(|(channel, _)| channel == channel_idx)
+                {
+                    self.muted_until_start = None;
+                }
+                if let Some(source) = self.sources.get_mut(channel_idx as usize) {
+                    source.set_mute(muted);
+                }
             }
 
             ControlAction::ChangeJumpMode(jumpmode) => {
@@ -161,12 +360,217 @@ impl AudioProcessor {
 
         self.compile_child_statuses();
 
+        // Stopped and zeroed right away rather than through the queue, where a start queued
+        // behind the load would be undone
+        if let ControlAction::LoadCueByIndex(idx) = command
+            && (idx as usize) < self.status.show.cues.len()
+        {
+            self.handle_command(ControlAction::TransportStop);
+            self.handle_command(ControlAction::TransportZero);
+        }
+
         if command == ControlAction::TransportZero {
             self.notify_push(MessageType::BeatData);
             self.notify_push(MessageType::TransportData);
         }
     }
 
+    fn handle_core_command(&mut self, command: CoreControlAction) {
+        match command {
+            CoreControlAction::SetOutputEq { output, eq } => {
+                if let Some(output_eq) = self.output_eq.get_mut(output as usize) {
+                    output_eq.configure(eq);
+                }
+            }
+            CoreControlAction::SetOutputDelay { output, delay_ms } => {
+                if let Some(output_delay) = self.output_delay.get_mut(output as usize) {
+                    output_delay.set_delay_ms(delay_ms);
+                }
+            }
+            CoreControlAction::FadeChannelGain {
+                channel,
+                gain,
+                fade_ms,
+            } => {
+                // Even a cut is ramped
+                let samples = self.ms_to_samples(fade_ms.max(self.gain_ramp_ms));
+                if let Some(source) = self.sources.get_mut(channel as usize) {
+                    source.fade_gain(gain, samples);
+                }
+            }
+            CoreControlAction::StopPlaybackChannel { channel, .. } => {
+                if let Some(source) = self.sources.get_mut(channel as usize) {
+                    source.source_device.core_command(&self.ctx, command);
+                }
+            }
+            CoreControlAction::SetGainRamp { ramp_ms } => self.gain_ramp_ms = ramp_ms,
+            CoreControlAction::SetStopFade { fade_ms } => self.stop_fade_ms = fade_ms,
+            CoreControlAction::MuteOutputs(muted) => self.outputs_muted = muted,
+            CoreControlAction::RenderChannels(channels) => self.rendered_channels = channels,
+            CoreControlAction::SetJumpMode(on) => {
+                self.status.transport.vlt = on.unwrap_or(!self.status.transport.vlt);
+                self.notify_push(MessageType::TransportData);
+            }
+            CoreControlAction::SetOutputPolarity { output, invert } => {
+                if let Some(output_invert) = self.output_invert.get_mut(output as usize) {
+                    *output_invert = invert;
+                }
+            }
+            CoreControlAction::SetOutputMix {
+                output,
+                port,
+                gain_db,
+            } => {
+                if let Some(gain) = self
+                    .mix_sends
+                    .get_mut(output as usize)
+                    .and_then(|sends| sends.get_mut(port as usize))
+                {
+                    *gain = gain_db.map(|gain_db| 10.0f32.powf(gain_db / 20.0));
+                }
+            }
+            CoreControlAction::SetPassThrough {
+                input,
+                output,
+                gain_db,
+            } => {
+                if let Some(gain) = self
+                    .pass_through
+                    .get_mut(output as usize)
+                    .and_then(|gains| gains.get_mut(input as usize))
+                {
+                    *gain = gain_db.map_or(0.0, |gain_db| 10.0f32.powf(gain_db / 20.0));
+                }
+            }
+            CoreControlAction::SetCueEnd(end) => self.cue_end = end,
+            CoreControlAction::SetZeroTarget { target, markers } => {
+                self.zero_target = target;
+                self.markers = markers;
+            }
+            CoreControlAction::ZeroToTarget => {
+                let target = match self.zero_target {
+                    ZeroTarget::Top => 0,
+                    ZeroTarget::LastStart => self.last_start_beat,
+                    ZeroTarget::PreviousMarker => self
+                        .markers
+                        .previous(self.status.beat_state().beat_idx)
+                        .unwrap_or(0),
+                };
+                self.handle_command(if target == 0 {
+                    ControlAction::TransportZero
+                } else {
+                    ControlAction::TransportJumpBeat(target)
+                });
+            }
+            CoreControlAction::SetMuteEvents(events) => self.mute_events = events,
+            // Playback devices keep them too, to pick up fades where a seek lands
+            CoreControlAction::SetFadeEvents(events) => {
+                self.fade_events = events;
+                for source in &mut self.sources {
+                    source.source_device.core_command(&self.ctx, command);
+                }
+            }
+            CoreControlAction::StopAtEndOfBar => self.stop_at_bar = self.status.transport.running,
+            CoreControlAction::LaunchCue { cue, at } => {
+                self.launch = self.status.transport.running.then_some((cue, at));
+            }
+            CoreControlAction::StartWithCountIn { bars } => {
+                if !self.status.transport.running {
+                    self.count_in = CountIn::new(
+                        &self.status.cue.cue,
+                        self.status.beat_state().next_beat_idx,
+                        bars,
+                        self.frame_time,
+                        self.ctx.sample_rate,
+                        self.ctx.click_rate_percent(),
+                    );
+                }
+            }
+            CoreControlAction::MuteUntilStart(channel) => {
+                if let Some(source) = self.sources.get_mut(channel as usize) {
+                    self.end_mute_until_start();
+                    self.muted_until_start = Some((channel, source.is_muted()));
+                    source.set_mute(true);
+                    self.cbnet.notify_core(CoreMessage::ChannelMuted {
+                        channel,
+                        muted: true,
+                    });
+                }
+            }
+            CoreControlAction::SetBeatPhaseRate(rate_hz) => self.beat_phase.set_rate(rate_hz),
+            CoreControlAction::SetTempoFollow(config) => {
+                // Return to the programmed tempo when following is turned off
+                if !config.enabled {
+                    self.ctx.tempo_percent = 100;
+                }
+                self.tempo_follower.configure(config);
+            }
+            CoreControlAction::SetTimecodeOutput(_)
+            | CoreControlAction::SetTimecodeFrameRate(_)
+            | CoreControlAction::JamTimecode(_)
+            | CoreControlAction::SetClickLevels(_)
+            | CoreControlAction::SetClipChains(_)
+            | CoreControlAction::SetGainAutomation(_)
+            | CoreControlAction::SetClipAlternates(_)
+            | CoreControlAction::FadePlayback(_)
+            | CoreControlAction::ClickCountIn(_)
+            | CoreControlAction::StopAllPlayback { .. } => {
+                for source in &mut self.sources {
+                    source.source_device.core_command(&self.ctx, command);
+                }
+            }
+        }
+    }
+
+    /// Gives the channel muted until the start back the mute it had.
+    fn end_mute_until_start(&mut self) {
+        let Some((channel, muted)) = self.muted_until_start.take() else {
+            return;
+        };
+        if let Some(source) = self.sources.get_mut(channel as usize) {
+            source.set_mute(muted);
+            self.cbnet
+                .notify_core(CoreMessage::ChannelMuted { channel, muted });
+        }
+    }
+
+    /// Ends a count-in, if one is running, without starting the transport.
+    fn drop_count_in(&mut self) {
+        if self.count_in.take().is_some() {
+            self.cbnet
+                .notify_core(CoreMessage::CountIn { beats_left: 0 });
+        }
+    }
+
+    /// Clicks the beat of the count-in due in this cycle, or starts the transport once it is
+    /// over, so the first beat of the cue is clicked in the place of the next count-in beat.
+    fn step_count_in(&mut self) {
+        let Some(step) = self
+            .count_in
+            .as_mut()
+            .and_then(|count_in| count_in.step(self.frame_time))
+        else {
+            return;
+        };
+        match step {
+            CountInStep::Click { accent, beats_left } => {
+                self.cbnet.notify_core(CoreMessage::CountIn {
+                    beats_left: beats_left.min(u16::MAX as usize) as u16,
+                });
+                for source in &mut self.sources {
+                    source
+                        .source_device
+                        .core_command(&self.ctx, CoreControlAction::ClickCountIn(accent));
+                }
+            }
+            CountInStep::Start => self.handle_command(ControlAction::TransportStart),
+        }
+    }
+
+    fn ms_to_samples(&self, ms: f32) -> usize {
+        (ms.max(0.0) * self.ctx.sample_rate as f32 / 1000.0) as usize
+    }
+
     fn compile_child_statuses(&mut self) {
         let current_beat = self.status.beat_state().beat_idx;
         for (source, status) in self.sources.iter_mut().zip(self.status.sources.iter_mut()) {
@@ -192,20 +596,48 @@ impl AudioProcessor {
         }
     }
 
-    // Get audio buffer from source[idx] and copy it to the JACK client output buffer.
-    fn process_child(&mut self, idx: usize, ps: &ProcessScope) -> Control {
+    // Get audio buffer from source[idx] and copy it to the output buffer.
+    fn process_child(
+        &mut self,
+        idx: usize,
+        inputs: &impl AudioInputs,
+        out_buf: &mut [f32],
+    ) -> Control {
         let source = &mut self.sources[idx];
         let res = source.source_device.send_buffer(&self.ctx);
         if let Ok(buf) = res {
-            let out_buf = self.ports.0[idx].as_mut_slice(ps);
             out_buf.clone_from_slice(buf);
-            let gain = if self.status.transport.playrate_percent != 100 && idx != 0 {
-                0.0
+            if self.status.transport.playrate_percent != 100 && idx != 0 {
+                out_buf.fill(0.0);
             } else {
-                source.get_gain_mult()
-            };
-            for sample in out_buf {
-                *sample *= gain;
+                source.apply_gain(out_buf);
+            }
+            self.stop_fade.apply(out_buf);
+            for (input, input_gain) in self.pass_through[idx]
+                .iter()
+                .enumerate()
+                .take(inputs.input_count())
+            {
+                if *input_gain == 0.0 {
+                    continue;
+                }
+                for (sample, input_sample) in out_buf.iter_mut().zip(inputs.input(input)) {
+                    *sample += input_sample * input_gain;
+                }
+            }
+            self.output_eq[idx].process(out_buf, self.ctx.sample_rate);
+            self.output_delay[idx].process(out_buf, self.ctx.sample_rate);
+            if self.output_invert[idx] {
+                for sample in out_buf.iter_mut() {
+                    *sample = -*sample;
+                }
+            }
+            if self.outputs_muted
+                || self.rendered_channels.is_some_and(|(first, count)| {
+                    !(first as usize..first as usize + count as usize).contains(&idx)
+                })
+            {
+                out_buf.fill(0.0);
             }
             Control::Continue
         } else {
@@ -218,22 +650,95 @@ impl AudioProcessor {
         }
     }
 
-    fn update_context(&mut self, c: &Client, ps: &ProcessScope) {
-        self.ctx = AudioSourceContext {
-            jack_time: c.time(),
-            frame_size: ps.n_frames() as usize,
-            sample_rate: c.sample_rate() as usize,
-            beat: self.status.beat_state(),
-            transport: self.status.transport,
-            cbnet: self.cbnet.clone(),
-            cue: self.status.cue.cue.clone(),
+    fn update_context(&mut self, timing: CycleTiming) {
+        self.ctx.frame_time = self.frame_time;
+        self.ctx.frame_size = timing.frame_size;
+        self.ctx.sample_rate = timing.sample_rate;
+        self.ctx.beat = self.status.beat_state();
+        self.ctx.transport = self.status.transport;
+        self.ctx.cbnet = self.cbnet.clone();
+        self.ctx.cue = self.status.cue.cue.clone();
+    }
+
+    /// Prepares the processor and all sources for cycles of up to `frames` samples. Must not be
+    /// called from the process cycle, as buffers are allocated here.
+    pub fn set_max_frame_size(&mut self, frames: usize) {
+        self.ctx.set_max_frame_size(frames);
+        self.mix_buf = vec![0.0; frames];
+        for source in self.sources.iter_mut() {
+            source.source_device.set_max_frame_size(frames);
+        }
+    }
+
+    /// Sets the number of client output ports the sources are mixed onto. Must not be called
+    /// from the process cycle, as buffers are allocated here.
+    pub fn set_output_ports(&mut self, ports: usize) {
+        self.output_ports = ports;
+        for sends in &mut self.mix_sends {
+            sends.resize(ports, None);
+        }
+    }
+
+    /// Adds a processed source to the ports it is summed into, or to its own port.
+    fn mix_into(&self, idx: usize, buf: &[f32], outputs: &mut impl AudioOutputs) {
+        let sends = &self.mix_sends[idx];
+        if sends.iter().all(Option::is_none) {
+            if idx < self.output_ports {
+                for (sample, source_sample) in outputs.output(idx).iter_mut().zip(buf) {
+                    *sample += source_sample;
+                }
+            }
+            return;
+        }
+        for (port, gain) in sends.iter().enumerate() {
+            let Some(gain) = gain else {
+                continue;
+            };
+            for (sample, source_sample) in outputs.output(port).iter_mut().zip(buf) {
+                *sample += source_sample * gain;
+            }
         }
     }
 
+    /// Loads a cue while the transport is running and carries on from its top. The main loop
+    /// loads its media when told with `CoreMessage::CueLaunched`.
+    fn launch_cue(&mut self, cue: u8) {
+        let Some(launched) = self.status.show.cues.get(cue as usize) else {
+            return;
+        };
+        self.status.cue.cue_idx = cue as u16;
+        self.cue_countdown = CueCountdown::new(launched);
+        self.status.cue.cue = launched.clone();
+        self.notify_push(MessageType::CueData);
+        self.notify_push(MessageType::SmallCueData);
+        self.handle_command(ControlAction::TransportZero);
+        self.send_beat_events_to_children(0);
+        self.cbnet.notify_core(CoreMessage::CueLaunched { cue });
+    }
+
     fn send_beat_events_to_children(&mut self, beat_idx: u16) {
         for event in self.status.cue.cue.events.get_at_location(beat_idx) {
             self.invoke_event(event);
         }
+        let mute_events = self.mute_events;
+        for event in mute_events.at(beat_idx) {
+            if let Some(source) = self.sources.get_mut(event.channel as usize) {
+                source.set_mute(event.muted);
+                self.cbnet.notify_core(CoreMessage::ChannelMuted {
+                    channel: event.channel,
+                    muted: event.muted,
+                });
+            }
+        }
+        // Playback devices pick the fades of their channel
+        let fade_events = self.fade_events;
+        for event in fade_events.at(beat_idx) {
+            for source in &mut self.sources {
+                source
+                    .source_device
+                    .core_command(&self.ctx, CoreControlAction::FadePlayback(event));
+            }
+        }
     }
 
     fn invoke_event(&mut self, event: Event) {
@@ -246,10 +751,80 @@ impl AudioProcessor {
             source.source_device.event_occured(&self.ctx, event);
         }
     }
-}
 
-impl ProcessHandler for AudioProcessor {
-    fn process(&mut self, c: &Client, ps: &ProcessScope) -> Control {
+    fn follow_tempo(&mut self, timing: CycleTiming, inputs: &impl AudioInputs) {
+        let input = self.tempo_follower.config().input as usize;
+        if !self.status.transport.running || input >= inputs.input_count() {
+            self.tempo_follower.reset();
+            return;
+        }
+        // Only the click follows, at the length the beat has at the playrate, as a playrate
+        // other than 100 would silence the playback
+        let beat_length_us = self
+            .status
+            .cue
+            .cue
+            .get_beat(self.status.beat_state().beat_idx)
+            .unwrap_or_default()
+            .length as f32
+            * 100.0
+            / self.status.transport.playrate_percent.max(1) as f32;
+        if let Some(tempo) = self.tempo_follower.process(
+            inputs.input(input),
+            timing.time_us,
+            timing.sample_rate,
+            beat_length_us,
+            self.ctx.tempo_percent as f32,
+        ) {
+            self.ctx.tempo_percent = tempo as u16;
+        }
+    }
+
+    fn publish_beat_phase(&mut self, timing: CycleTiming) {
+        if !self.status.transport.running || !self.beat_phase.due(timing.time_us) {
+            return;
+        }
+        let beat = self.status.beat_state();
+        let beat_length_us = self
+            .status
+            .cue
+            .cue
+            .get_beat(beat.beat_idx)
+            .unwrap_or_default()
+            .length;
+        self.cbnet.notify_core(CoreMessage::BeatPhase {
+            beat_idx: beat.beat_idx,
+            phase: beat_phase(
+                beat.us_to_next_beat,
+                beat_length_us as u32,
+                self.ctx.click_rate_percent(),
+            ),
+        });
+    }
+
+    fn publish_countdown(&mut self, timing: CycleTiming) {
+        if !self.countdown_stream.due(timing.time_us) {
+            return;
+        }
+        let beat = self.status.beat_state();
+        self.cbnet
+            .notify_core(CoreMessage::Countdown(self.cue_countdown.at(
+                beat.beat_idx,
+                beat.us_to_next_beat,
+                self.ctx.click_rate_percent(),
+            )));
+    }
+
+    pub fn process_cycle(
+        &mut self,
+        timing: CycleTiming,
+        inputs: &impl AudioInputs,
+        outputs: &mut impl AudioOutputs,
+    ) -> Control {
+        let cycle_start = Instant::now();
+        let allocations = thread_allocations();
+        let profiling = self.cbnet.profiler.is_enabled();
+
         // Handle channel commands
         loop {
             match self.cbnet.cmd_rx.try_recv() {
@@ -266,11 +841,27 @@ impl ProcessHandler for AudioProcessor {
                 )),
             }
         }
-        self.update_context(c, ps);
+        while let Ok(cmd) = self.cbnet.core_cmd_rx.try_recv() {
+            self.handle_core_command(cmd);
+        }
+        while let Ok(swap) = self.cbnet.show_rx.try_recv() {
+            self.swap_show(swap);
+        }
+
+        for idx in 0..inputs.input_count() {
+            let peak = inputs
+                .input(idx)
+                .iter()
+                .fold(0.0f32, |peak, sample| peak.max(sample.abs()));
+            self.cbnet.metrics.record_input_peak(idx, peak);
+        }
+        self.follow_tempo(timing, inputs);
+
+        self.update_context(timing);
         // Get status from all sources and compile onto self.status
         self.compile_child_statuses();
 
-        // If cue runs out: stop and go to next
+        // If cue runs out: loop or stop, and let the main loop move on to the next cue
         if self
             .status
             .cue
@@ -280,25 +871,147 @@ impl ProcessHandler for AudioProcessor {
             && self.status.transport.running
             && self.status.beat_state().beat_idx < u16::MAX / 2
         {
-            self.status.transport.running = false;
-            self.cbnet.command(ControlAction::TransportStop);
-            self.cbnet.command(ControlAction::LoadNextCue);
-            self.cbnet.command(ControlAction::TransportZero);
+            if self.cue_end == EndOfCue::Loop {
+                // Zero right away, so the end is not detected again on the next cycle
+                self.handle_command(ControlAction::TransportZero);
+            } else {
+                self.status.transport.running = false;
+                self.cbnet.command(ControlAction::TransportStop);
+            }
+            self.cbnet.notify_core(CoreMessage::CueEnded {
+                cue: self.status.cue.cue_idx as u8,
+            });
+        }
+
+        // Take over with a launched cue in the cycle the next beat of the running cue is due in,
+        // so the first beat of the launched cue is clicked in its place
+        let beat_state = self.status.beat_state();
+        if let Some((cue, at)) = self.launch
+            && self.status.transport.running
+            && beat_state.us_to_next_beat == 0
+            && (at == LaunchBoundary::Beat
+                || starts_bar(&self.status.cue.cue, beat_state.next_beat_idx))
+        {
+            self.launch = None;
+            self.launch_cue(cue);
+        }
+
+        // Stop in time for the fade to end on the next bar line, before its downbeat is clicked
+        let beat_state = self.status.beat_state();
+        if self.stop_at_bar
+            && self.status.transport.running
+            && starts_bar(&self.status.cue.cue, beat_state.next_beat_idx)
+            && beat_state.us_to_next_beat as f32 <= self.stop_fade_ms * 1000.0
+        {
+            self.stop_at_bar = false;
+            self.handle_command(ControlAction::TransportStop);
         }
 
-        self.update_context(c, ps);
-        // Get audio frame buffers from all children and play in correct port
+        self.step_count_in();
+
+        self.update_context(timing);
+        // Get audio frame buffers from all children and mix them onto the ports
+        for port in 0..self.output_ports {
+            outputs.output(port).fill(0.0);
+        }
+        let mut mix_buf = std::mem::take(&mut self.mix_buf);
         for i in 0..self.sources.len() {
-            if self.process_child(i, ps) == Control::Quit {
+            let source_start = profiling.then(Instant::now);
+            let buf = &mut mix_buf[..timing.frame_size];
+            if self.process_child(i, inputs, buf) == Control::Quit {
+                self.mix_buf = mix_buf;
                 return Control::Quit;
             };
+            self.mix_into(i, buf, outputs);
+            if let Some(source_start) = source_start {
+                self.source_us[i] = source_start.elapsed().as_micros() as u32;
+            }
         }
+        self.mix_buf = mix_buf;
+
+        self.frame_time += timing.frame_size as u64;
+
+        if self.stop_fade.advance(timing.frame_size) {
+            self.handle_command(ControlAction::TransportStop);
+        }
+
+        self.publish_beat_phase(timing);
+        self.publish_countdown(timing);
 
         if self.status_changed_flag {
             self.notify_push(MessageType::TransportData);
             self.status_changed_flag = false;
         }
 
+        let duration_us = cycle_start.elapsed().as_micros() as u32;
+        self.cbnet.metrics.record_dsp_cycle(duration_us);
+        if profiling {
+            let (slowest_source, slowest_source_us) = self
+                .source_us
+                .iter()
+                .copied()
+                .enumerate()
+                .max_by_key(|(_, us)| *us)
+                .unwrap_or_default();
+            self.cbnet.profiler.record(
+                CycleProfile {
+                    time_us: timing.time_us,
+                    frame_size: timing.frame_size as u32,
+                    duration_us,
+                    allocations: thread_allocations().wrapping_sub(allocations),
+                    slowest_source: slowest_source.min(u8::MAX as usize) as u8,
+                    slowest_source_us,
+                },
+                &self.source_us,
+            );
+        }
+        Control::Continue
+    }
+}
+
+/// JACK process handler, running the [`AudioProcessor`] on the JACK clock and client ports.
+pub struct JackProcessor {
+    processor: AudioProcessor,
+    inputs: Vec<Port<AudioIn>>,
+    ports: Vec<Port<AudioOut>>,
+}
+
+impl JackProcessor {
+    pub fn new(
+        processor: AudioProcessor,
+        inputs: Vec<Port<AudioIn>>,
+        ports: Vec<Port<AudioOut>>,
+    ) -> JackProcessor {
+        JackProcessor {
+            processor,
+            inputs,
+            ports,
+        }
+    }
+}
+
+impl ProcessHandler for JackProcessor {
+    fn process(&mut self, c: &Client, ps: &ProcessScope) -> Control {
+        let timing = CycleTiming {
+            time_us: c.time(),
+            frame_size: ps.n_frames() as usize,
+            sample_rate: c.sample_rate() as usize,
+        };
+        self.processor.process_cycle(
+            timing,
+            &JackInputs {
+                ports: &self.inputs,
+                ps,
+            },
+            &mut JackOutputs {
+                ports: &mut self.ports,
+                ps,
+            },
+        )
+    }
+
+    fn buffer_size(&mut self, _: &Client, size: Frames) -> Control {
+        self.processor.set_max_frame_size(size as usize);
         Control::Continue
     }
 }
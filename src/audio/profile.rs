@@ -0,0 +1,185 @@
+//! Opt-in profiling of the process callback, to chase xruns that only show on one unit.
+//!
+//! While profiling is on, every cycle of the audio processor is recorded in a ring buffer
+//! allocated when profiling is switched on, so recording never allocates on the RT thread. The
+//! main loop writes the buffer out on request.
+use serde::{Deserialize, Serialize};
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    cell::Cell,
+    path::Path,
+    sync::{
+        Mutex,
+        atomic::{AtomicBool, AtomicU32, Ordering},
+    },
+};
+
+/// Cycles kept in the profile, the last 45 seconds at 256 frames and 48 kHz.
+pub const PROFILE_CYCLES: usize = 8192;
+/// Sources whose slowest cycle is kept, as far as a `u8` index reaches.
+const PROFILED_SOURCES: usize = u8::MAX as usize + 1;
+
+thread_local! {
+    static THREAD_ALLOCATIONS: Cell<u32> = const { Cell::new(0) };
+}
+
+/// Allocations made so far by the calling thread, if the binary counts them with
+/// [`CountingAllocator`], else 0.
+pub fn thread_allocations() -> u32 {
+    THREAD_ALLOCATIONS.with(Cell::get)
+}
+
+/// The system allocator, counting the allocations of every thread so allocations in the process
+/// callback show up in the profile. Installed as the global allocator by the `clicks-core`
+/// binary.
+pub struct CountingAllocator;
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        // Threads being torn down have no counter left; their allocations are not counted
+        let _ = THREAD_ALLOCATIONS.try_with(|count| count.set(count.get().wrapping_add(1)));
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) }
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let _ = THREAD_ALLOCATIONS.try_with(|count| count.set(count.get().wrapping_add(1)));
+        unsafe { System.realloc(ptr, layout, new_size) }
+    }
+}
+
+/// One process cycle.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct CycleProfile {
+    /// JACK time at the start of the cycle, in microseconds
+    pub time_us: u64,
+    pub frame_size: u32,
+    pub duration_us: u32,
+    /// Allocations made on the RT thread during the cycle
+    pub allocations: u32,
+    /// The source that took longest, and how long it took
+    pub slowest_source: u8,
+    pub slowest_source_us: u32,
+}
+
+/// Everything recorded since profiling was switched on, as written out.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ProfileReport {
+    /// The last `PROFILE_CYCLES` cycles, oldest first
+    pub cycles: Vec<CycleProfile>,
+    /// Per source, the longest it took in any cycle, in microseconds
+    pub worst_source_us: Vec<u32>,
+    /// Cycles that could not be recorded because the profile was being read
+    pub missed: u32,
+}
+
+impl ProfileReport {
+    pub fn write(&self, path: &Path) -> std::io::Result<()> {
+        let content = serde_json::to_string_pretty(self)?;
+        std::fs::write(path, content)
+    }
+}
+
+#[derive(Debug, Default)]
+struct ProfileBuffer {
+    cycles: Vec<CycleProfile>,
+    /// Where the next cycle is written, once `cycles` is full
+    next: usize,
+    worst_source_us: Vec<u32>,
+}
+
+/// Shared between the RT thread, which records, and the main loop, which switches profiling on
+/// and off and reads the profile.
+#[derive(Debug, Default)]
+pub struct Profiler {
+    enabled: AtomicBool,
+    buffer: Mutex<ProfileBuffer>,
+    missed: AtomicU32,
+}
+
+impl Profiler {
+    /// Switches profiling on or off. Switching it on starts a new profile.
+    pub fn set_enabled(&self, enabled: bool) {
+        if enabled {
+            let mut buffer = self.buffer.lock().unwrap_or_else(|err| err.into_inner());
+            *buffer = ProfileBuffer {
+                cycles: Vec::with_capacity(PROFILE_CYCLES),
+                next: 0,
+                worst_source_us: vec![0; PROFILED_SOURCES],
+            };
+            self.missed.store(0, Ordering::Relaxed);
+        }
+        self.enabled.store(enabled, Ordering::Relaxed);
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    /// Records a cycle and how long each source took in it. Never waits for the lock, so the
+    /// RT thread is not held up while the profile is read; the cycle is counted as missed
+    /// instead.
+    pub fn record(&self, cycle: CycleProfile, source_us: &[u32]) {
+        let Ok(mut buffer) = self.buffer.try_lock() else {
+            self.missed.fetch_add(1, Ordering::Relaxed);
+            return;
+        };
+        if buffer.cycles.len() < buffer.cycles.capacity() {
+            buffer.cycles.push(cycle);
+        } else if !buffer.cycles.is_empty() {
+            let next = buffer.next;
+            buffer.cycles[next] = cycle;
+            buffer.next = (next + 1) % buffer.cycles.len();
+        }
+        for (worst, us) in buffer.worst_source_us.iter_mut().zip(source_us) {
+            *worst = (*worst).max(*us);
+        }
+    }
+
+    /// The profile recorded since profiling was last switched on.
+    pub fn report(&self) -> ProfileReport {
+        let buffer = self.buffer.lock().unwrap_or_else(|err| err.into_inner());
+        let (newer, older) = buffer.cycles.split_at(buffer.next);
+        ProfileReport {
+            cycles: older.iter().chain(newer).copied().collect(),
+            worst_source_us: buffer.worst_source_us.clone(),
+            missed: self.missed.load(Ordering::Relaxed),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ring_of_cycles() {
+        let profiler = Profiler::default();
+        assert!(!profiler.is_enabled());
+        profiler.set_enabled(true);
+        for cycle in 0..PROFILE_CYCLES as u64 + 10 {
+            profiler.record(
+                CycleProfile {
+                    time_us: cycle,
+                    ..Default::default()
+                },
+                &[cycle as u32 % 100, 5],
+            );
+        }
+        let report = profiler.report();
+        assert_eq!(report.cycles.len(), PROFILE_CYCLES);
+        assert_eq!(report.cycles[0].time_us, 10);
+        assert_eq!(
+            report.cycles.last().unwrap().time_us,
+            PROFILE_CYCLES as u64 + 9
+        );
+        assert_eq!(report.worst_source_us[..3], [99, 5, 0]);
+
+        // Switching on again starts over
+        profiler.set_enabled(true);
+        assert!(profiler.report().cycles.is_empty());
+    }
+}
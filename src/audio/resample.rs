@@ -0,0 +1,159 @@
+//! Converting playback clips to the sample rate of the audio server, for clips recorded at
+//! another rate, e.g. 44.1 kHz stems in a 48 kHz show. Clips read whole are converted once when
+//! read; streamed clips chunk by chunk on the streamer thread, see `audio::stream`.
+//!
+//! The conversion is a windowed sinc interpolation. The ratio of two sample rates is rational,
+//! so the fractional positions of the output samples repeat, and the filter is computed once
+//! per position rather than per sample.
+use std::{f64::consts::PI, ops::Range};
+
+/// Zero crossings of the sinc on either side of a sample. More is sharper and slower.
+const ZERO_CROSSINGS: usize = 16;
+
+#[derive(Debug)]
+pub struct Resampler {
+    from: u64,
+    to: u64,
+    /// Common divisor of the rates, the step between fractional positions
+    step: u64,
+    /// Input samples each output sample is made of
+    taps: usize,
+    /// Weights of the taps, `taps` per fractional position
+    kernel: Vec<f32>,
+}
+
+impl Resampler {
+    /// Converts from `from` Hz to `to` Hz, none if they are the same.
+    pub fn new(from: u32, to: u32) -> Option<Self> {
+        if from == to || from == 0 || to == 0 {
+            return None;
+        }
+        let (from, to) = (from as u64, to as u64);
+        let step = gcd(from, to);
+        // Below the output Nyquist frequency when converting down, so nothing folds back
+        let cutoff = (to as f64 / from as f64).min(1.0);
+        let half_width = ZERO_CROSSINGS as f64 / cutoff;
+        let taps = 2 * half_width.ceil() as usize;
+        let mut kernel = Vec::with_capacity((to / step) as usize * taps);
+        for phase in 0..to / step {
+            let fraction = (phase * step) as f64 / to as f64;
+            for tap in 0..taps {
+                // Distance from the output sample to the input sample of the tap
+                let distance = fraction + (taps / 2 - 1) as f64 - tap as f64;
+                let window = if distance.abs() < half_width {
+                    0.5 * (1.0 + (PI * distance / half_width).cos())
+                } else {
+                    0.0
+                };
+                kernel.push((cutoff * sinc(cutoff * distance) * window) as f32);
+            }
+        }
+        Some(Self {
+            from,
+            to,
+            step,
+            taps,
+            kernel,
+        })
+    }
+
+    /// Length at the output rate of `length` samples at the input rate.
+    pub fn output_length(&self, length: usize) -> usize {
+        (length as u64 * self.to / self.from) as usize
+    }
+
+    /// Input sample the window of an output sample starts at, and the fractional position of
+    /// the output sample.
+    fn window(&self, output: usize) -> (i64, usize) {
+        let position = output as u64 * self.from;
+        let first = (position / self.to) as i64 - (self.taps / 2 - 1) as i64;
+        (first, ((position % self.to) / self.step) as usize)
+    }
+
+    /// Input samples the output samples of `output` are made of. Starts before the first input
+    /// sample at the start of a clip.
+    pub fn input_span(&self, output: Range<usize>) -> Range<i64> {
+        if output.is_empty() {
+            return 0..0;
+        }
+        let (start, _) = self.window(output.start);
+        let (last, _) = self.window(output.end - 1);
+        start..last + self.taps as i64
+    }
+
+    /// Output samples of `output`, from input samples that start at input sample `offset`.
+    /// Input samples left out count as silence.
+    pub fn process(&self, input: &[f32], offset: i64, output: Range<usize>) -> Vec<f32> {
+        output
+            .map(|sample| {
+                let (first, phase) = self.window(sample);
+                let weights = &self.kernel[phase * self.taps..(phase + 1) * self.taps];
+                let start = first - offset;
+                weights
+                    .iter()
+                    .enumerate()
+                    .filter_map(|(tap, weight)| {
+                        let idx = usize::try_from(start + tap as i64).ok()?;
+                        Some(input.get(idx)? * weight)
+                    })
+                    .sum::<f32>()
+            })
+            .collect()
+    }
+
+    /// A whole clip at the output rate.
+    pub fn convert(&self, input: &[f32]) -> Vec<f32> {
+        self.process(input, 0, 0..self.output_length(input.len()))
+    }
+}
+
+fn sinc(x: f64) -> f64 {
+    if x == 0.0 {
+        1.0
+    } else {
+        (PI * x).sin() / (PI * x)
+    }
+}
+
+fn gcd(a: u64, b: u64) -> u64 {
+    if b == 0 { a } else { gcd(b, a % b) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sine(frequency: f64, rate: u32, length: usize) -> Vec<f32> {
+        (0..length)
+            .map(|idx| (2.0 * PI * frequency * idx as f64 / rate as f64).sin() as f32)
+            .collect()
+    }
+
+    #[test]
+    fn converts_rates() {
+        assert!(Resampler::new(48000, 48000).is_none());
+        for (from, to) in [(44100, 48000), (96000, 48000)] {
+            let resampler = Resampler::new(from, to).unwrap();
+            let output = resampler.convert(&sine(1000.0, from, from as usize / 10));
+            assert_eq!(output.len(), to as usize / 10);
+            // The same tone at the new rate, away from the edges of the clip
+            let expected = sine(1000.0, to, output.len());
+            let middle = output.len() / 4..output.len() * 3 / 4;
+            for idx in middle {
+                assert!(
+                    (output[idx] - expected[idx]).abs() < 0.01,
+                    "{from} -> {to} at {idx}"
+                );
+            }
+
+            // Chunk by chunk as streamed, the same as all at once
+            let input = sine(440.0, from, 5000);
+            let whole = resampler.convert(&input);
+            let span = resampler.input_span(1000..2000);
+            let start = span.start.max(0);
+            let chunk =
+                resampler.process(&input[start as usize..span.end as usize], start, 1000..2000);
+            assert_eq!(chunk, whole[1000..2000]);
+        }
+    }
+}
@@ -0,0 +1,615 @@
+use crate::{
+    audio::{
+        processor::{AudioProcessor, CycleTiming},
+        source::{DEFAULT_MAX_FRAME_SIZE, SourceConfig},
+    },
+    cbnet::CrossbeamNetwork,
+    communication::extension::CoreControlAction,
+};
+use common::{
+    cue::Show,
+    local::config::LogItem,
+    protocol::{message::Message, request::ControlAction},
+};
+use jack::Control;
+use std::collections::VecDeque;
+
+/// Runs an [`AudioProcessor`] on a virtual clock instead of JACK. Every cycle advances the clock
+/// by exactly its frame size, so a scripted sequence of commands and frame sizes always produces
+/// the same audio and notifications, which are captured for inspection.
+pub struct Simulation {
+    processor: AudioProcessor,
+    cbnet: CrossbeamNetwork,
+    sample_rate: usize,
+    /// Largest cycle size the processor is prepared for
+    max_frame_size: usize,
+    elapsed_samples: u64,
+    input_queues: Vec<VecDeque<f32>>,
+    inputs: Vec<Vec<f32>>,
+    outputs: Vec<Vec<f32>>,
+    captured: Vec<Vec<f32>>,
+    notifications: Vec<Message>,
+    logs: Vec<LogItem>,
+}
+
+impl Simulation {
+    pub fn new(sources: Vec<SourceConfig>, show: Show, sample_rate: usize) -> Self {
+        let cbnet = CrossbeamNetwork::new();
+        let outputs = vec![vec![]; sources.len()];
+        let captured = vec![vec![]; sources.len()];
+        Self {
+            processor: AudioProcessor::new(sources, cbnet.clone(), show),
+            cbnet,
+            sample_rate,
+            max_frame_size: DEFAULT_MAX_FRAME_SIZE,
+            elapsed_samples: 0,
+            input_queues: vec![],
+            inputs: vec![],
+            outputs,
+            captured,
+            notifications: vec![],
+            logs: vec![],
+        }
+    }
+
+    /// Adds capture inputs to the simulation, silent until fed with `feed_input`.
+    pub fn with_inputs(mut self, count: usize) -> Self {
+        self.input_queues = vec![VecDeque::new(); count];
+        self.inputs = vec![vec![]; count];
+        self
+    }
+
+    /// Mixes the sources onto `count` output ports instead of one port per source.
+    pub fn with_output_ports(mut self, count: usize) -> Self {
+        self.processor.set_output_ports(count);
+        self.outputs = vec![vec![]; count];
+        self.captured = vec![vec![]; count];
+        self
+    }
+
+    /// Queues samples on input `idx`. Each cycle consumes as many as its frame size, padding
+    /// with silence when the queue runs dry.
+    pub fn feed_input(&mut self, idx: usize, samples: &[f32]) {
+        self.input_queues[idx].extend(samples);
+    }
+
+    /// Queues a command for the next cycle, like the main loop does.
+    pub fn command(&self, command: ControlAction) {
+        self.cbnet.command(command);
+    }
+
+    /// Queues a core command for the next cycle.
+    pub fn command_core(&self, command: CoreControlAction) {
+        self.cbnet.command_core(command);
+    }
+
+    /// Virtual time at the start of the next cycle, in microseconds.
+    pub fn time_us(&self) -> u64 {
+        self.elapsed_samples * 1_000_000 / self.sample_rate as u64
+    }
+
+    /// Runs one process cycle of `frame_size` samples. Like JACK changing its buffer size, a
+    /// cycle larger than any before first prepares the processor for it.
+    pub fn cycle(&mut self, frame_size: usize) -> Control {
+        if frame_size > self.max_frame_size {
+            self.max_frame_size = frame_size;
+            self.processor.set_max_frame_size(frame_size);
+        }
+        for (input, queue) in self.inputs.iter_mut().zip(&mut self.input_queues) {
+            input.clear();
+            input.extend(queue.drain(..frame_size.min(queue.len())));
+            input.resize(frame_size, 0.0);
+        }
+        for output in &mut self.outputs {
+            output.clear();
+            output.resize(frame_size, 0.0);
+        }
+        let timing = CycleTiming {
+            time_us: self.time_us(),
+            frame_size,
+            sample_rate: self.sample_rate,
+        };
+        let control = self
+            .processor
+            .process_cycle(timing, &self.inputs, &mut self.outputs);
+        self.elapsed_samples += frame_size as u64;
+
+        for (captured, output) in self.captured.iter_mut().zip(&self.outputs) {
+            captured.extend_from_slice(output);
+        }
+        self.notifications.extend(self.cbnet.notif_rx.try_iter());
+        self.logs.extend(self.cbnet.log_rx.try_iter());
+        control
+    }
+
+    /// Runs one cycle per entry in `frame_sizes`, stopping early if the processor quits.
+    pub fn run(&mut self, frame_sizes: impl IntoIterator<Item = usize>) -> Control {
+        for frame_size in frame_sizes {
+            if self.cycle(frame_size) == Control::Quit {
+                return Control::Quit;
+            }
+        }
+        Control::Continue
+    }
+
+    /// All audio written to output port `idx` since the simulation started.
+    pub fn captured(&self, idx: usize) -> &[f32] {
+        &self.captured[idx]
+    }
+
+    /// Notifications emitted since the last call.
+    pub fn take_notifications(&mut self) -> Vec<Message> {
+        std::mem::take(&mut self.notifications)
+    }
+
+    /// Log items emitted since the last call.
+    pub fn take_logs(&mut self) -> Vec<LogItem> {
+        std::mem::take(&mut self.logs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        audio::{
+            accent::{bar_position_in_cue, starts_bar},
+            metronome::Metronome,
+            processor::ShowSwap,
+            timecode::{DEFAULT_LTC_FRAME_RATE, TimecodeSource},
+        },
+        communication::extension::{CoreMessage, LaunchBoundary},
+        show_extension::{EndOfCue, MuteEvent, MuteEvents},
+    };
+    use common::{
+        cue::Cue,
+        protocol::message::{LargeMessage, SmallMessage},
+    };
+
+    fn metronome_and_timecode() -> Vec<SourceConfig> {
+        vec![
+            SourceConfig::new("metronome".to_string(), Box::new(Metronome::new())),
+            SourceConfig::new(
+                "timecode".to_string(),
+                Box::new(TimecodeSource::new(48000, DEFAULT_LTC_FRAME_RATE)),
+            ),
+        ]
+    }
+
+    /// Loads and starts a cue with the commands the main loop sends for it.
+    fn load_and_start(sim: &Simulation, cue: u8) {
+        sim.command(ControlAction::LoadCueByIndex(cue));
+        sim.command(ControlAction::TransportZero);
+        sim.command(ControlAction::TransportStart);
+    }
+
+    /// The last transport state and cue reported in `notifications`.
+    fn transport_and_cue(notifications: Vec<Message>) -> (Option<bool>, Option<u16>) {
+        let mut running = None;
+        let mut cue = None;
+        for message in notifications {
+            match message {
+                Message::Small(SmallMessage::TransportData(transport)) => {
+                    running = Some(transport.running)
+                }
+                Message::Small(SmallMessage::CueData(state)) => cue = Some(state.cue_idx),
+                _ => {}
+            }
+        }
+        (running, cue)
+    }
+
+    #[test]
+    fn seek_and_play() {
+        let mut show = Show::default();
+        show.cues.push(Cue::example());
+        let mut sim = Simulation::new(metronome_and_timecode(), show, 48000);
+
+        // Let the initial cue load settle before scripting the transport
+        assert!(sim.cycle(256) == Control::Continue);
+        sim.command(ControlAction::TransportSeekBeat(40));
+        sim.command(ControlAction::TransportStart);
+
+        let frame_sizes = [64, 256, 1024];
+        assert!(sim.run(frame_sizes.into_iter().cycle().take(3000)) == Control::Continue);
+
+        let total_samples = 256 + 1000 * frame_sizes.iter().sum::<usize>();
+        assert_eq!(sim.captured(0).len(), total_samples);
+        assert_eq!(sim.captured(1).len(), total_samples);
+        assert_eq!(sim.time_us(), total_samples as u64 * 1_000_000 / 48000);
+        assert!(!sim.take_notifications().is_empty());
+    }
+
+    #[test]
+    fn large_buffers() {
+        let mut show = Show::default();
+        show.cues.push(Cue::example());
+        let mut sim = Simulation::new(metronome_and_timecode(), show, 48000);
+
+        assert!(sim.cycle(256) == Control::Continue);
+        sim.command(ControlAction::TransportStart);
+        // jackd at 4096 and 8192 frames, more than one LTC frame per cycle
+        assert!(sim.run([4096; 50]) == Control::Continue);
+        assert!(sim.run([8192; 20]) == Control::Continue);
+
+        let total_samples = 256 + 50 * 4096 + 20 * 8192;
+        assert_eq!(sim.captured(0).len(), total_samples);
+        assert!(sim.captured(1).iter().any(|sample| *sample != 0.0));
+    }
+
+    #[test]
+    fn at_96k() {
+        let mut show = Show::default();
+        show.cues.push(Cue::example());
+        let sources = vec![
+            SourceConfig::new("metronome".to_string(), Box::new(Metronome::new())),
+            SourceConfig::new(
+                "timecode".to_string(),
+                Box::new(TimecodeSource::new(96000, DEFAULT_LTC_FRAME_RATE)),
+            ),
+        ];
+        let mut sim = Simulation::new(sources, show, 96000);
+
+        assert!(sim.cycle(128) == Control::Continue);
+        sim.command(ControlAction::TransportStart);
+        assert!(sim.run([128; 1000]) == Control::Continue);
+
+        // The first click is 384 samples long, three cycles, rather than cut off after one
+        let click = sim.captured(0);
+        let start = click.iter().position(|sample| *sample != 0.0).unwrap();
+        let length = click[start..start + 1000]
+            .iter()
+            .rposition(|sample| *sample != 0.0)
+            .unwrap();
+        assert!((380..384).contains(&length));
+        assert!(sim.captured(1).iter().any(|sample| *sample != 0.0));
+    }
+
+    #[test]
+    fn stop_at_end_of_bar() {
+        let cue = Cue::example();
+        let mut show = Show::default();
+        show.cues.push(cue.clone());
+        let downbeat = (1..cue.get_beats().len() as u16)
+            .find(|idx| starts_bar(&cue, *idx) && !starts_bar(&cue, idx - 1))
+            .expect("example cue has a bar of more than one beat");
+        let mut sim = Simulation::new(metronome_and_timecode(), show, 48000);
+        assert!(sim.cycle(256) == Control::Continue);
+        sim.command(ControlAction::TransportSeekBeat(downbeat - 1));
+        sim.command(ControlAction::TransportStart);
+        sim.command_core(CoreControlAction::StopAtEndOfBar);
+        sim.take_notifications();
+        assert!(sim.run([256; 2000]) == Control::Continue);
+
+        // The last beat of the bar is played out, and the transport stops before the downbeat
+        let mut last_beat = None;
+        let mut stopped_on = None;
+        for message in sim.take_notifications() {
+            match message {
+                Message::Small(SmallMessage::BeatData(state)) => last_beat = Some(state),
+                Message::Small(SmallMessage::TransportData(transport))
+                    if !transport.running && stopped_on.is_none() =>
+                {
+                    stopped_on = last_beat;
+                }
+                _ => {}
+            }
+        }
+        let stopped_on = stopped_on.expect("transport stopped");
+        assert_eq!(stopped_on.beat_idx, downbeat - 1);
+        assert_eq!(stopped_on.next_beat_idx, downbeat);
+        assert_eq!(last_beat.map(|state| state.beat_idx), Some(downbeat - 1));
+    }
+
+    #[test]
+    fn launch_on_bar_line() {
+        let cue = Cue::example();
+        let mut show = Show::default();
+        show.cues.push(cue.clone());
+        show.cues.push(cue.clone());
+        let downbeat = (1..cue.get_beats().len() as u16)
+            .find(|idx| starts_bar(&cue, *idx) && !starts_bar(&cue, idx - 1))
+            .expect("example cue has a bar of more than one beat");
+        let mut sim = Simulation::new(metronome_and_timecode(), show, 48000);
+        assert!(sim.cycle(256) == Control::Continue);
+        sim.command(ControlAction::TransportSeekBeat(downbeat - 1));
+        sim.command(ControlAction::TransportStart);
+        sim.command_core(CoreControlAction::LaunchCue {
+            cue: 1,
+            at: LaunchBoundary::Bar,
+        });
+        sim.take_notifications();
+        assert!(sim.run([256; 2000]) == Control::Continue);
+
+        // The second cue takes over from its first beat instead of the downbeat of the first
+        let mut last_beat = None;
+        let mut launched_after = None;
+        let mut first_launched_beat = None;
+        for message in sim.take_notifications() {
+            match message {
+                Message::Small(SmallMessage::BeatData(state)) => {
+                    if launched_after.is_some() && first_launched_beat.is_none() {
+                        first_launched_beat = Some(state);
+                    }
+                    last_beat = Some(state);
+                }
+                Message::Small(SmallMessage::CueData(cue)) if cue.cue_idx == 1 => {
+                    launched_after = last_beat;
+                }
+                Message::Small(SmallMessage::TransportData(transport)) => {
+                    assert!(transport.running);
+                }
+                _ => {}
+            }
+        }
+        let launched_after = launched_after.expect("cue launched");
+        assert_eq!(launched_after.beat_idx, downbeat - 1);
+        assert_eq!(first_launched_beat.map(|state| state.beat_idx), Some(0));
+    }
+
+    #[test]
+    fn swap_show() {
+        let mut show = Show::default();
+        show.cues.push(Cue::example());
+        let mut sim = Simulation::new(metronome_and_timecode(), show.clone(), 48000);
+        assert!(sim.cycle(256) == Control::Continue);
+
+        // The last source plays the new show, the processor carries on
+        show.cues.push(Cue::example());
+        sim.cbnet.swap_show(ShowSwap {
+            show,
+            playback: vec![Box::new(TimecodeSource::new(48000, DEFAULT_LTC_FRAME_RATE))],
+        });
+        assert!(sim.run([256; 4]) == Control::Continue);
+        let shows: Vec<usize> = sim
+            .take_notifications()
+            .into_iter()
+            .filter_map(|message| match message {
+                Message::Large(LargeMessage::ShowData(show)) => Some(show.cues.len()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(shows.last(), Some(&2));
+
+        // What it replaced is handed back, to be dropped off the RT thread
+        let retired = sim.cbnet.retired_rx.try_recv().expect("show was swapped");
+        assert_eq!(retired.show.cues.len(), 1);
+        assert_eq!(retired.playback.len(), 1);
+        assert_eq!(sim.captured(1).len(), 5 * 256);
+    }
+
+    #[test]
+    fn count_in() {
+        let cue = Cue::example();
+        let mut show = Show::default();
+        show.cues.push(cue.clone());
+        let mut sim = Simulation::new(metronome_and_timecode(), show, 48000);
+        assert!(sim.cycle(256) == Control::Continue);
+        sim.command_core(CoreControlAction::StartWithCountIn { bars: 1 });
+        assert!(sim.run([256; 2000]) == Control::Continue);
+
+        // A bar is clicked up to the beat the cue starts on, and the cue starts a beat after the
+        // last click, with the timecode silent until then
+        let (position, bar_beats) = bar_position_in_cue(&cue, 0);
+        let count_in_beats = bar_beats + position;
+        let sounding: Vec<bool> = sim
+            .captured(0)
+            .chunks(256)
+            .map(|cycle| cycle.iter().any(|sample| *sample != 0.0))
+            .collect();
+        let clicked: Vec<usize> = (1..sounding.len())
+            .filter(|idx| sounding[*idx] && !sounding[idx - 1])
+            .map(|idx| idx * 256)
+            .collect();
+        let beat_length = cue.get_beat(0).unwrap_or_default().length as usize * 48 / 1000;
+        let start = clicked[count_in_beats];
+        assert!(start >= 256 + count_in_beats * beat_length);
+        assert!(start < 256 + count_in_beats * beat_length + 256);
+        assert!(sim.captured(1)[..start].iter().all(|sample| *sample == 0.0));
+        assert!(sim.captured(1)[start..].iter().any(|sample| *sample != 0.0));
+
+        let beats_left: Vec<u16> = sim
+            .cbnet
+            .core_notif_rx
+            .try_iter()
+            .filter_map(|message| match message {
+                CoreMessage::CountIn { beats_left } => Some(beats_left),
+                _ => None,
+            })
+            .collect();
+        let expected: Vec<u16> = (0..=count_in_beats as u16).rev().collect();
+        assert_eq!(beats_left, expected);
+    }
+
+    #[test]
+    fn continue_next() {
+        let cue = Cue::example();
+        let mut show = Show::default();
+        show.cues.push(cue.clone());
+        show.cues.push(cue.clone());
+        let mut sim = Simulation::new(metronome_and_timecode(), show, 48000);
+        assert!(sim.cycle(256) == Control::Continue);
+        sim.command_core(CoreControlAction::SetCueEnd(EndOfCue::ContinueNext));
+        sim.command(ControlAction::TransportStart);
+
+        // Answer the end of the first cue like the main loop, in the cycle after it
+        let cue_samples = (0..cue.get_beats().len() as u16)
+            .map(|idx| cue.get_beat(idx).unwrap_or_default().length as usize)
+            .sum::<usize>()
+            * 48
+            / 1000;
+        let mut ended = false;
+        for _ in 0..cue_samples / 256 + 100 {
+            assert!(sim.cycle(256) == Control::Continue);
+            if sim
+                .cbnet
+                .core_notif_rx
+                .try_iter()
+                .any(|message| matches!(message, CoreMessage::CueEnded { cue: 0 }))
+            {
+                ended = true;
+                break;
+            }
+        }
+        assert!(ended);
+        sim.take_notifications();
+        load_and_start(&sim, 1);
+        assert!(sim.run([256; 100]) == Control::Continue);
+
+        // The second cue is loaded and keeps running, with its first beats clicked
+        assert_eq!(
+            transport_and_cue(sim.take_notifications()),
+            (Some(true), Some(1))
+        );
+        let end = sim.captured(0).len();
+        assert!(
+            sim.captured(0)[end - 100 * 256..]
+                .iter()
+                .any(|sample| *sample != 0.0)
+        );
+    }
+
+    #[test]
+    fn go() {
+        let mut show = Show::default();
+        show.cues.push(Cue::example());
+        show.cues.push(Cue::example());
+        let mut sim = Simulation::new(metronome_and_timecode(), show, 48000);
+        assert!(sim.cycle(256) == Control::Continue);
+        sim.take_notifications();
+
+        // Load, zero and start all land in the same cycle
+        load_and_start(&sim, 1);
+        assert!(sim.cycle(256) == Control::Continue);
+        assert_eq!(
+            transport_and_cue(sim.take_notifications()),
+            (Some(true), Some(1))
+        );
+        assert!(sim.run([256; 100]) == Control::Continue);
+        assert!(sim.captured(0).iter().any(|sample| *sample != 0.0));
+        assert!(sim.captured(1).iter().any(|sample| *sample != 0.0));
+    }
+
+    #[test]
+    fn mute_event() {
+        let cue = Cue::example();
+        let mut show = Show::default();
+        show.cues.push(cue.clone());
+        let mut sim = Simulation::new(metronome_and_timecode(), show, 48000);
+        sim.command_core(CoreControlAction::SetMuteEvents(MuteEvents::new(&[
+            MuteEvent {
+                beat: 2,
+                channel: 0,
+                muted: true,
+            },
+        ])));
+        sim.command(ControlAction::TransportStart);
+        assert!(sim.run([256; 1000]) == Control::Continue);
+
+        // The click is heard up to the muting beat, and not after it
+        let beat_start = |idx: u16| {
+            (0..idx)
+                .map(|idx| cue.get_beat(idx).unwrap_or_default().length as usize)
+                .sum::<usize>()
+                * 48
+                / 1000
+        };
+        let click = sim.captured(0);
+        assert!(click[..beat_start(2)].iter().any(|sample| *sample != 0.0));
+        assert!(click[beat_start(3)..].iter().all(|sample| *sample == 0.0));
+        let muted = sim.cbnet.core_notif_rx.try_iter().filter(|message| {
+            matches!(
+                message,
+                CoreMessage::ChannelMuted {
+                    channel: 0,
+                    muted: true
+                }
+            )
+        });
+        assert_eq!(muted.count(), 1);
+    }
+
+    #[test]
+    fn mute_until_start() {
+        let mut show = Show::default();
+        show.cues.push(Cue::example());
+        let mut sim = Simulation::new(metronome_and_timecode(), show, 48000);
+        sim.command_core(CoreControlAction::MuteUntilStart(1));
+        assert!(sim.run([256; 10]) == Control::Continue);
+        sim.command(ControlAction::TransportStart);
+        assert!(sim.run([256; 100]) == Control::Continue);
+
+        // The timecode is back with the start, as it was before
+        assert!(sim.captured(1).iter().any(|sample| *sample != 0.0));
+        let mutes: Vec<bool> = sim
+            .cbnet
+            .core_notif_rx
+            .try_iter()
+            .filter_map(|message| match message {
+                CoreMessage::ChannelMuted { channel: 1, muted } => Some(muted),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(mutes, vec![true, false]);
+    }
+
+    #[test]
+    fn pass_through() {
+        let simulate = |gain_db: Option<f32>| {
+            let sources = vec![SourceConfig::new(
+                "timecode".to_string(),
+                Box::new(TimecodeSource::new(48000, DEFAULT_LTC_FRAME_RATE)),
+            )];
+            let mut sim = Simulation::new(sources, Show::default(), 48000).with_inputs(2);
+            sim.command_core(CoreControlAction::SetPassThrough {
+                input: 1,
+                output: 0,
+                gain_db,
+            });
+            sim.feed_input(0, &[1.0; 512]);
+            sim.feed_input(1, &[0.5; 512]);
+            assert!(sim.run([256, 256]) == Control::Continue);
+            sim.captured(0).to_vec()
+        };
+
+        let dry = simulate(None);
+        let wet = simulate(Some(-6.0));
+        assert_eq!(dry.len(), 512);
+        for (dry, wet) in dry.iter().zip(&wet) {
+            assert!((wet - dry - 0.5 * 0.501).abs() < 1e-3);
+        }
+    }
+
+    #[test]
+    fn output_mix() {
+        // Stopped metronomes are silent, so each output carries only its pass-through input
+        let sources = (0..3)
+            .map(|idx| SourceConfig::new(format!("metronome {idx}"), Box::new(Metronome::new())))
+            .collect();
+        let mut sim = Simulation::new(sources, Show::default(), 48000)
+            .with_inputs(3)
+            .with_output_ports(2);
+        for (input, level) in [1.0, 0.5, 0.25].into_iter().enumerate() {
+            sim.command_core(CoreControlAction::SetPassThrough {
+                input: input as u8,
+                output: input as u8,
+                gain_db: Some(0.0),
+            });
+            sim.feed_input(input, &[level; 256]);
+        }
+        // Output 1 is summed into port 0 at -6 dB, output 2 has no port of its own
+        sim.command_core(CoreControlAction::SetOutputMix {
+            output: 1,
+            port: 0,
+            gain_db: Some(-6.0),
+        });
+        assert!(sim.cycle(256) == Control::Continue);
+
+        assert!(
+            sim.captured(0)
+                .iter()
+                .all(|sample| (sample - 1.0 - 0.5 * 0.501).abs() < 1e-3)
+        );
+        assert!(sim.captured(1).iter().all(|sample| *sample == 0.0));
+    }
+}
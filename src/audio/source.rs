@@ -7,54 +7,120 @@ use jack::Error;
 use std::fmt::Debug;
 use std::ops::Div;
 
+use crate::audio::phase::beat_phase;
 use crate::cbnet::CrossbeamNetwork;
 
+use crate::communication::extension::CoreControlAction;
+
+/// Longest ramp of gain changes. Longer changes are fades, see `SourceConfig::fade_gain`.
+pub const MAX_GAIN_RAMP_MS: f32 = 500.0;
+
+/// Cycle size sources are prepared for until JACK has told its own.
+pub const DEFAULT_MAX_FRAME_SIZE: usize = 2048;
+/// Highest sample rate buffers of fixed length are sized for, so 96 kHz and 192 kHz clients
+/// work without allocating while playing.
+pub const MAX_SAMPLE_RATE: usize = 192_000;
+
 #[derive(Debug)]
 pub struct AudioSourceContext {
-    pub jack_time: u64,
+    /// Frames processed since the audio client started, at the start of the cycle. Sources
+    /// keep time by this rather than by the system clock, so they cannot drift apart.
+    pub frame_time: u64,
     pub frame_size: usize,
     pub sample_rate: usize,
     pub beat: BeatState,
     pub transport: TransportState,
+    /// Tempo the click follows a live input at, in percent of the programmed tempo, see
+    /// `TempoFollower`. Unlike the playrate it leaves the playback alone.
+    pub tempo_percent: u16,
     pub cbnet: CrossbeamNetwork,
     pub cue: Cue,
+    /// Silence of the largest cycle size, for sources with nothing to play
+    silence: Vec<f32>,
 }
 
 impl AudioSourceContext {
+    /// A cycle of silence.
+    pub fn silence(&self) -> &[f32] {
+        &self.silence[..self.frame_size]
+    }
+
+    /// Sizes the silence for cycles of up to `frames` samples.
+    pub fn set_max_frame_size(&mut self, frames: usize) {
+        self.silence = vec![0.0; frames];
+    }
+
+    /// Number of samples in `us` microseconds at the sample rate of the audio client.
+    pub fn us_to_samples(&self, us: u64) -> u64 {
+        us * self.sample_rate as u64 / 1_000_000
+    }
+
     pub fn samples_to_next_beat(&self) -> usize {
-        (self.beat.us_to_next_beat as usize / 10) * (self.sample_rate / 100) / 1000
+        self.us_to_samples(self.beat.us_to_next_beat as u64) as usize
     }
 
     pub fn will_overrun_frame(&self) -> bool {
         self.samples_to_next_beat() < self.frame_size
     }
+
+    /// Length of the cycle in microseconds.
+    pub fn frame_us(&self) -> u32 {
+        (self.frame_size as u64 * 1_000_000 / self.sample_rate.max(1) as u64) as u32
+    }
+
+    /// Rate the beats of the cue are clicked at, in percent: the playrate and the followed tempo.
+    pub fn click_rate_percent(&self) -> u16 {
+        (self.transport.playrate_percent as u32 * self.tempo_percent as u32 / 100)
+            .clamp(1, u16::MAX as u32) as u16
+    }
+
+    /// Position in the cue in beats, with the passed fraction of the current beat, `us_ahead`
+    /// microseconds into the cycle. Does not look past the next beat.
+    pub fn beat_position(&self, us_ahead: u32) -> f32 {
+        let length_us = self
+            .cue
+            .get_beat(self.beat.beat_idx)
+            .unwrap_or_default()
+            .length;
+        self.beat.beat_idx as f32
+            + beat_phase(
+                self.beat.us_to_next_beat.saturating_sub(us_ahead),
+                length_us as u32,
+                self.click_rate_percent(),
+            )
+    }
 }
 
 impl Default for AudioSourceContext {
     fn default() -> Self {
         Self {
-            jack_time: 0,
+            frame_time: 0,
             frame_size: 0,
             sample_rate: 0,
             beat: BeatState::default(),
             transport: TransportState::default(),
+            tempo_percent: 100,
             cbnet: CrossbeamNetwork::new(),
             cue: Cue::empty(),
+            silence: vec![0.0; DEFAULT_MAX_FRAME_SIZE],
         }
     }
 }
 
 pub trait AudioSource: Send {
-    fn send_buffer(&mut self, ctx: &AudioSourceContext) -> Result<&[f32], Error>;
+    fn send_buffer<'a>(&'a mut self, ctx: &'a AudioSourceContext) -> Result<&'a [f32], Error>;
     fn command(&mut self, ctx: &AudioSourceContext, command: ControlAction);
+    /// Commands of the core protocol extension. Most sources have no use for them.
+    fn core_command(&mut self, _ctx: &AudioSourceContext, _command: CoreControlAction) {}
     fn get_status(&mut self, ctx: &AudioSourceContext) -> AudioSourceState;
 
     fn event_occured(&mut self, ctx: &AudioSourceContext, event: Event);
     fn event_will_occur(&mut self, ctx: &AudioSourceContext, event: Event);
 
-    fn silence(&self, length: usize) -> &[f32] {
-        &[0f32; 2048][0..length]
-    }
+    /// Prepares the source for cycles of up to `frames` samples. Called outside the process
+    /// cycle, when JACK is activated and whenever its buffer size changes, so buffers are sized
+    /// here rather than while playing.
+    fn set_max_frame_size(&mut self, _frames: usize) {}
 }
 
 pub struct SourceConfig {
@@ -62,6 +128,11 @@ pub struct SourceConfig {
     pub source_device: Box<dyn AudioSource>,
     gain_mult: f32,
     gain: f32,
+    muted: bool,
+    /// Multiplier applied to the buffer, moving towards `gain_mult` during a fade
+    current_mult: f32,
+    fade_step: f32,
+    fade_samples: usize,
 }
 
 impl Debug for SourceConfig {
@@ -77,17 +148,81 @@ impl SourceConfig {
             source_device: device,
             gain_mult: 1.0,
             gain: 0.0,
+            muted: false,
+            current_mult: 1.0,
+            fade_step: 0.0,
+            fade_samples: 0,
         }
     }
     pub fn set_gain(&mut self, gain: f32) {
+        self.fade_gain(gain, 0);
+    }
+
+    /// Moves the gain linearly to `gain` over the next `samples` samples.
+    pub fn fade_gain(&mut self, gain: f32, samples: usize) {
         self.gain = gain;
-        self.gain_mult = 10.0f32.powf(gain.div(20.0))
+        self.gain_mult = 10.0f32.powf(gain.div(20.0));
+        self.fade_samples = samples;
+        if samples == 0 {
+            self.current_mult = self.gain_mult;
+        } else {
+            self.fade_step = (self.gain_mult - self.current_mult) / samples as f32;
+        }
+    }
+
+    /// Applies the gain, mute and any running fade to a buffer of the source.
+    pub fn apply_gain(&mut self, buf: &mut [f32]) {
+        for sample in buf.iter_mut() {
+            if self.fade_samples > 0 {
+                self.fade_samples -= 1;
+                self.current_mult = if self.fade_samples == 0 {
+                    self.gain_mult
+                } else {
+                    self.current_mult + self.fade_step
+                };
+            }
+            *sample *= if self.muted { 0.0 } else { self.current_mult };
+        }
+    }
+
+    pub fn set_mute(&mut self, muted: bool) {
+        self.muted = muted;
     }
 
+    pub fn is_muted(&self) -> bool {
+        self.muted
+    }
+
+    /// Gain multiplier of the source, 0 while muted.
     pub fn get_gain_mult(&self) -> f32 {
-        self.gain_mult
+        if self.muted { 0.0 } else { self.gain_mult }
     }
     pub fn get_gain(&self) -> f32 {
         self.gain
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audio::metronome::Metronome;
+
+    #[test]
+    fn gain_fade() {
+        let mut source = SourceConfig::new("test".to_string(), Box::new(Metronome::new()));
+        source.fade_gain(-120.0, 4);
+        let mut buf = [1.0; 6];
+        source.apply_gain(&mut buf);
+        assert!(
+            buf.windows(2)
+                .all(|pair| pair[0] > pair[1] || pair[1] < 1e-5)
+        );
+        assert!(buf[3] < 1e-5 && buf[5] < 1e-5);
+
+        source.set_mute(true);
+        source.set_gain(0.0);
+        let mut buf = [1.0; 2];
+        source.apply_gain(&mut buf);
+        assert_eq!(buf, [0.0; 2]);
+    }
+}
@@ -0,0 +1,398 @@
+//! Streaming long playback clips from disk. Stems running through most of a show do not fit in
+//! memory across all channels, so clips longer than `CoreConfiguration::stream_media_s` are read
+//! chunk by chunk by a streamer thread, into a ring ahead of where they play. The audio thread
+//! only copies chunks already in the ring, and plays silence where the streamer has fallen
+//! behind, e.g. right after seeking while the transport runs.
+//!
+//! The first chunks of a streamed clip are read along with its header and kept, so it starts on
+//! its playback event without waiting for the disk. Clips at another sample rate than the audio
+//! server are converted as they are read, and their chunks count samples at the server rate.
+use crate::{audio::resample::Resampler, cbnet::CrossbeamNetwork};
+use arc_swap::ArcSwapOption;
+use common::local::config::{LogContext, LogItem, LogKind};
+use crossbeam_channel::{Receiver, RecvTimeoutError};
+use hound::WavReader;
+use std::{
+    collections::{HashMap, HashSet, VecDeque, hash_map::Entry},
+    fmt::Debug,
+    fs::File,
+    io::{BufReader, Read},
+    ops::{Div, Range},
+    path::PathBuf,
+    sync::{
+        Arc,
+        atomic::{AtomicU32, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+/// Samples per chunk read from disk
+pub const STREAM_CHUNK: usize = 16384;
+/// Chunks in the ring of a streamed clip, about five seconds at 48 kHz
+const RING_CHUNKS: usize = 16;
+/// Chunks at the start of a streamed clip kept in memory
+const HEAD_CHUNKS: usize = 4;
+/// How often the streamer tops up the rings
+const STREAM_INTERVAL: Duration = Duration::from_millis(10);
+/// How long chunks pushed out of a ring are held on to, so the audio thread never drops the last
+/// reference to one and frees it
+const RETIRE_AFTER: Duration = Duration::from_secs(1);
+
+/// Reads up to `count` samples from where the reader is, as floats.
+pub(crate) fn read_samples<R: Read>(
+    reader: &mut WavReader<R>,
+    count: usize,
+) -> Result<Vec<f32>, hound::Error> {
+    match reader.spec().sample_format {
+        hound::SampleFormat::Float => reader.samples::<f32>().take(count).collect(),
+        hound::SampleFormat::Int => reader
+            .samples::<i32>()
+            .take(count)
+            .map(|sample| sample.map(|sample| (sample as f32).div(32768.0)))
+            .collect(),
+    }
+}
+
+/// Samples `output` of a clip, at the output rate if it is converted, from a reader at input
+/// sample `next`. The reader only seeks if it is not where the samples start.
+fn read_range(
+    reader: &mut WavReader<BufReader<File>>,
+    next: &mut usize,
+    resampler: Option<&Resampler>,
+    output: Range<usize>,
+) -> Result<Vec<f32>, hound::Error> {
+    let span = match resampler {
+        Some(resampler) => resampler.input_span(output.clone()),
+        None => output.start as i64..output.end as i64,
+    };
+    // Converted samples at the start of a clip are made of samples before it too, which are
+    // silent
+    let start = span.start.max(0) as usize;
+    if *next != start {
+        reader.seek(start as u32)?;
+    }
+    let input = read_samples(reader, (span.end as usize).saturating_sub(start))?;
+    *next = start + input.len();
+    Ok(match resampler {
+        Some(resampler) => resampler.process(&input, start as i64, output),
+        None => input,
+    })
+}
+
+#[derive(Debug)]
+struct Chunk {
+    /// Position of the chunk in the clip, in chunks
+    index: usize,
+    samples: Vec<f32>,
+}
+
+/// A clip played from disk, shared between the audio thread playing it and the streamer
+/// filling its ring. Chunk `n` of the clip goes in place `n % RING_CHUNKS` of the ring.
+pub struct ClipStream {
+    path: PathBuf,
+    /// Length at the output rate
+    length: u32,
+    /// Conversion to the output rate, if the clip is at another rate
+    resampler: Option<Resampler>,
+    /// The first `HEAD_CHUNKS` chunks, or the whole clip if it is shorter
+    head: Vec<f32>,
+    ring: Vec<ArcSwapOption<Chunk>>,
+    /// Sample the clip is played from, as last read or placed by the audio thread
+    position: AtomicU32,
+    /// Reads since the streamer last looked that found their chunk missing
+    underruns: AtomicU32,
+}
+
+impl Debug for ClipStream {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::result::Result<(), std::fmt::Error> {
+        write!(
+            f,
+            "ClipStream {{ path: {}, length: {} }}",
+            self.path.display(),
+            self.length
+        )
+    }
+}
+
+impl ClipStream {
+    /// Streams the mono clip of a reader opened on `path` at `sample_rate` Hz, reading its
+    /// first chunks right away.
+    pub fn open(
+        path: PathBuf,
+        mut reader: WavReader<BufReader<File>>,
+        sample_rate: u32,
+    ) -> Result<Self, hound::Error> {
+        let resampler = Resampler::new(reader.spec().sample_rate, sample_rate);
+        let length = match &resampler {
+            Some(resampler) => resampler.output_length(reader.len() as usize) as u32,
+            None => reader.len(),
+        };
+        let head = read_range(
+            &mut reader,
+            &mut 0,
+            resampler.as_ref(),
+            0..(HEAD_CHUNKS * STREAM_CHUNK).min(length as usize),
+        )?;
+        Ok(Self {
+            path,
+            length,
+            resampler,
+            head,
+            ring: (0..RING_CHUNKS).map(|_| ArcSwapOption::empty()).collect(),
+            position: AtomicU32::new(0),
+            underruns: AtomicU32::new(0),
+        })
+    }
+
+    pub fn get_length(&self) -> u32 {
+        self.length
+    }
+
+    /// Tells the streamer where the clip is about to be played from, ahead of reading it.
+    pub fn locate(&self, sample: u32) {
+        self.position.store(sample, Ordering::Relaxed);
+    }
+
+    // Called in RT thread
+    pub fn read(&self, start: u32, out: &mut [f32]) {
+        self.locate(start);
+        let mut position = start as usize;
+        let mut rest = out;
+        while !rest.is_empty() {
+            let count = if position < self.head.len() {
+                let count = rest.len().min(self.head.len() - position);
+                rest[..count].copy_from_slice(&self.head[position..position + count]);
+                count
+            } else {
+                let (index, offset) = (position / STREAM_CHUNK, position % STREAM_CHUNK);
+                let count = rest.len().min(STREAM_CHUNK - offset);
+                match &*self.ring[index % RING_CHUNKS].load() {
+                    Some(chunk)
+                        if chunk.index == index && chunk.samples.len() >= offset + count =>
+                    {
+                        rest[..count].copy_from_slice(&chunk.samples[offset..offset + count]);
+                    }
+                    _ => {
+                        rest[..count].fill(0.0);
+                        self.underruns.fetch_add(1, Ordering::Relaxed);
+                    }
+                }
+                count
+            };
+            position += count;
+            rest = &mut rest[count..];
+        }
+    }
+
+    fn chunks(&self) -> usize {
+        (self.length as usize).div_ceil(STREAM_CHUNK)
+    }
+
+    /// First chunk the ring should hold, the one being played unless that is in the head.
+    fn first_chunk(&self) -> usize {
+        (self.position.load(Ordering::Relaxed) as usize / STREAM_CHUNK)
+            .max(self.head.len() / STREAM_CHUNK)
+    }
+
+    fn has_chunk(&self, index: usize) -> bool {
+        self.ring[index % RING_CHUNKS]
+            .load()
+            .as_ref()
+            .is_some_and(|chunk| chunk.index == index)
+    }
+}
+
+/// Keeps the rings of the streamed clips in the clip slots filled, on its own thread.
+pub struct MediaStreamer {
+    cbnet: CrossbeamNetwork,
+    streams: Vec<Arc<ClipStream>>,
+    /// Open files by path, with the input sample each would read next without seeking
+    readers: HashMap<PathBuf, (WavReader<BufReader<File>>, usize)>,
+    /// Chunks pushed out of a ring, and when
+    retired: VecDeque<(Instant, Arc<Chunk>)>,
+    /// Clips that could not be read, reported once until they can
+    failing: HashSet<PathBuf>,
+    /// Clips that played silence for missing chunks, reported once until they catch up
+    lagging: HashSet<PathBuf>,
+}
+
+impl MediaStreamer {
+    pub fn new(cbnet: CrossbeamNetwork) -> Self {
+        Self {
+            cbnet,
+            streams: vec![],
+            readers: HashMap::new(),
+            retired: VecDeque::new(),
+            failing: HashSet::new(),
+            lagging: HashSet::new(),
+        }
+    }
+
+    /// Streams the clips last sent, until the sender is dropped.
+    pub fn run(mut self, streams: Receiver<Vec<Arc<ClipStream>>>) {
+        loop {
+            match streams.recv_timeout(STREAM_INTERVAL) {
+                Ok(new) => {
+                    self.streams = streams.try_iter().last().unwrap_or(new);
+                    self.readers
+                        .retain(|path, _| self.streams.iter().any(|s| s.path == *path));
+                }
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => return,
+            }
+            self.fill();
+            self.report();
+            let now = Instant::now();
+            while self
+                .retired
+                .front()
+                .is_some_and(|(at, _)| now.duration_since(*at) > RETIRE_AFTER)
+            {
+                self.retired.pop_front();
+            }
+        }
+    }
+
+    /// Reads the missing chunks of every ring. The nearest chunks of all clips are read first, so
+    /// one clip far behind does not hold up the others.
+    fn fill(&mut self) {
+        let streams = self.streams.clone();
+        for ahead in 0..RING_CHUNKS {
+            for stream in &streams {
+                let index = stream.first_chunk() + ahead;
+                if index >= stream.chunks() || stream.has_chunk(index) {
+                    continue;
+                }
+                match self.read_chunk(stream, index) {
+                    Ok(samples) => {
+                        self.failing.remove(&stream.path);
+                        let chunk = Arc::new(Chunk { index, samples });
+                        if let Some(old) = stream.ring[index % RING_CHUNKS].swap(Some(chunk)) {
+                            self.retired.push_back((Instant::now(), old));
+                        }
+                    }
+                    Err(err) => {
+                        // Opened again on the next try
+                        self.readers.remove(&stream.path);
+                        if self.failing.insert(stream.path.clone()) {
+                            self.cbnet.log(LogItem::new(
+                                format!("Error streaming {}: {err}", stream.path.display()),
+                                LogContext::AudioSource,
+                                LogKind::Error,
+                            ));
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    fn read_chunk(&mut self, stream: &ClipStream, index: usize) -> Result<Vec<f32>, hound::Error> {
+        let (reader, next) = match self.readers.entry(stream.path.clone()) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert((WavReader::open(&stream.path)?, 0)),
+        };
+        let start = index * STREAM_CHUNK;
+        read_range(
+            reader,
+            next,
+            stream.resampler.as_ref(),
+            start..(start + STREAM_CHUNK).min(stream.length as usize),
+        )
+    }
+
+    /// Logs clips that have started playing silence for chunks not read in time.
+    fn report(&mut self) {
+        for stream in &self.streams {
+            let underruns = stream.underruns.swap(0, Ordering::Relaxed);
+            if underruns == 0 {
+                self.lagging.remove(&stream.path);
+            } else if self.lagging.insert(stream.path.clone()) {
+                self.cbnet.log(LogItem::new(
+                    format!(
+                        "Streaming {} fell behind, playing silence until it catches up",
+                        stream.path.display()
+                    ),
+                    LogContext::AudioSource,
+                    LogKind::Warning,
+                ));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stream_of(path: &std::path::Path, samples: &[f32], sample_rate: u32) -> ClipStream {
+        let spec = hound::WavSpec {
+            channels: 1,
+            sample_rate,
+            bits_per_sample: 32,
+            sample_format: hound::SampleFormat::Float,
+        };
+        let mut writer = hound::WavWriter::create(path, spec).unwrap();
+        for sample in samples {
+            writer.write_sample(*sample).unwrap();
+        }
+        writer.finalize().unwrap();
+        ClipStream::open(path.to_path_buf(), WavReader::open(path).unwrap(), 48000).unwrap()
+    }
+
+    #[test]
+    fn streams_ahead_of_playback() {
+        let path = std::env::temp_dir().join(format!("clicks-stream-{}.wav", std::process::id()));
+        let length = STREAM_CHUNK * (HEAD_CHUNKS + RING_CHUNKS + 4) + 100;
+        let samples: Vec<f32> = (0..length).map(|idx| (idx / STREAM_CHUNK) as f32).collect();
+        let stream = Arc::new(stream_of(&path, &samples, 48000));
+        assert_eq!(stream.get_length() as usize, length);
+        let mut streamer = MediaStreamer::new(CrossbeamNetwork::new());
+        streamer.streams = vec![Arc::clone(&stream)];
+        let mut out = [1.0; 256];
+
+        // The head plays before anything has been streamed
+        stream.read(0, &mut out);
+        assert!(out.iter().all(|s| *s == 0.0));
+        let end_of_head = (HEAD_CHUNKS * STREAM_CHUNK) as u32;
+        stream.read(end_of_head - 128, &mut out);
+        assert!(out[..128].iter().all(|s| *s == (HEAD_CHUNKS - 1) as f32));
+        // but not what comes after it
+        assert!(out[128..].iter().all(|s| *s == 0.0));
+        assert_eq!(stream.underruns.load(Ordering::Relaxed), 1);
+
+        streamer.fill();
+        stream.read(end_of_head - 128, &mut out);
+        assert!(out[128..].iter().all(|s| *s == HEAD_CHUNKS as f32));
+        assert!(!stream.has_chunk(HEAD_CHUNKS + RING_CHUNKS));
+
+        // Seeking moves the ring along, past the end of the clip
+        let last = stream.chunks() - 1;
+        stream.locate((last * STREAM_CHUNK) as u32);
+        streamer.fill();
+        assert!(stream.has_chunk(last));
+        stream.read((last * STREAM_CHUNK) as u32, &mut out[..100]);
+        assert!(out[..100].iter().all(|s| *s == last as f32));
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn converts_while_streaming() {
+        let path =
+            std::env::temp_dir().join(format!("clicks-stream-rate-{}.wav", std::process::id()));
+        let stream = Arc::new(stream_of(&path, &[0.5; HEAD_CHUNKS * STREAM_CHUNK], 24000));
+        assert_eq!(stream.get_length() as usize, 2 * HEAD_CHUNKS * STREAM_CHUNK);
+        let mut streamer = MediaStreamer::new(CrossbeamNetwork::new());
+        streamer.streams = vec![Arc::clone(&stream)];
+        streamer.fill();
+
+        // Across the end of the head, and of the chunks after it
+        let mut out = [0.0; 256];
+        for start in [HEAD_CHUNKS, HEAD_CHUNKS + 1].map(|chunks| chunks * STREAM_CHUNK - 128) {
+            stream.read(start as u32, &mut out);
+            assert!(out.iter().all(|s| (s - 0.5).abs() < 0.01));
+        }
+        assert_eq!(stream.underruns.load(Ordering::Relaxed), 0);
+        let _ = std::fs::remove_file(&path);
+    }
+}
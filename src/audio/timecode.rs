@@ -1,4 +1,10 @@
-use crate::audio::{self, source::AudioSourceContext};
+use crate::{
+    audio::{
+        self,
+        source::{AudioSourceContext, DEFAULT_MAX_FRAME_SIZE},
+    },
+    communication::extension::CoreControlAction,
+};
 
 use common::{
     event::{EventCursor, EventDescription},
@@ -9,51 +15,151 @@ use common::{
         request::ControlAction,
     },
 };
+use serde::{Deserialize, Serialize};
+
+/// Frame rate of shows that do not set their own.
+pub const DEFAULT_LTC_FRAME_RATE: u8 = 25;
+
+/// Frame rates the LTC output can run at. Frames are always counted without drop frame, so
+/// there is no 29.97 fps.
+pub const LTC_FRAME_RATES: [u8; 3] = [24, 25, 30];
+
+/// Longest frame in samples, that of 24 fps at 96 kHz. LTC is not generated at higher sample
+/// rates.
+const MAX_FRAME_SAMPLES: usize = 4096;
+
+/// Widest edge smoothing, in samples. Wider smoothing blurs the bits of the frame together.
+pub const MAX_LTC_SMOOTHING: u8 = 8;
+
+/// Electrical shape of the LTC output. LTC readers differ widely in the drive level and
+/// polarity they accept.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TimecodeOutputConfiguration {
+    /// Peak amplitude, 1.0 being full scale
+    pub level: f32,
+    /// Width in samples of the moving average softening the edges, 1 for square edges
+    pub smoothing: u8,
+    /// Inverts the signal
+    pub invert: bool,
+}
+
+impl Default for TimecodeOutputConfiguration {
+    fn default() -> Self {
+        Self {
+            level: 0.5,
+            smoothing: 3,
+            invert: false,
+        }
+    }
+}
+
+/// A time of day to set the timecode to, as hours, minutes, seconds and frames.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TimecodeTime {
+    pub hours: u8,
+    pub minutes: u8,
+    pub seconds: u8,
+    pub frames: u8,
+}
+
+impl TimecodeTime {
+    /// Whether the time exists at the highest LTC frame rate.
+    pub fn is_valid(&self) -> bool {
+        self.hours < 24 && self.minutes < 60 && self.seconds < 60 && self.frames < 30
+    }
+
+    /// The time at a frame rate, frames the rate does not have being its last frame.
+    fn instant(&self, frame_rate: u8) -> TimecodeInstant {
+        let mut time = TimecodeInstant::new(frame_rate);
+        time.h = self.hours as _;
+        time.m = self.minutes as _;
+        time.s = self.seconds as _;
+        time.f = self.frames.min(frame_rate - 1) as _;
+        time
+    }
+}
+
+impl std::fmt::Display for TimecodeTime {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{:02}:{:02}:{:02}:{:02}",
+            self.hours, self.minutes, self.seconds, self.frames
+        )
+    }
+}
 
 pub struct TimecodeSource {
     pub properties: TimecodeProperties,
-    volume: f32,
-    frame_buffer: [f32; 8192],
+    output: TimecodeOutputConfiguration,
+    /// Frame rate of the current cue, which timecode events are sent at
+    frame_rate: u8,
+    /// The frame being sent and the one after it
+    frame_buffer: [f32; 2 * MAX_FRAME_SAMPLES],
+    /// Lengths in samples of the two frames in `frame_buffer`
+    frame_lengths: [usize; 2],
+    /// Samples short of a whole frame length, in 1/frame_rate of a sample, carried over to
+    /// the next frame
+    frame_remainder: usize,
+    /// Audio of the current cycle, sized to the largest cycle size
+    block_buffer: Vec<f32>,
     state: TimecodeState,
     last_cycle_frame: TimecodeInstant,
     sample_rate: usize,
     subframe_sample: u64,
+    /// Time to jump to at the next frame boundary
+    jam: Option<TimecodeInstant>,
 }
 
 impl Default for TimecodeSource {
     fn default() -> Self {
         Self {
             properties: TimecodeProperties::default(),
-            volume: 0.5,
-            frame_buffer: [0.0f32; 8192],
+            output: TimecodeOutputConfiguration::default(),
+            frame_rate: DEFAULT_LTC_FRAME_RATE,
+            frame_buffer: [0.0f32; 2 * MAX_FRAME_SAMPLES],
+            frame_lengths: [1920; 2],
+            frame_remainder: 0,
+            block_buffer: vec![0.0; DEFAULT_MAX_FRAME_SIZE],
             state: TimecodeState {
                 running: false,
-                ltc: TimecodeInstant::new(25),
+                ltc: TimecodeInstant::new(DEFAULT_LTC_FRAME_RATE),
             },
-            last_cycle_frame: TimecodeInstant::new(25),
+            last_cycle_frame: TimecodeInstant::new(DEFAULT_LTC_FRAME_RATE),
             sample_rate: 48000,
             subframe_sample: 0,
+            jam: None,
         }
     }
 }
 
 impl TimecodeSource {
-    pub fn new(sample_rate: usize) -> TimecodeSource {
-        TimecodeSource {
+    pub fn new(sample_rate: usize, frame_rate: u8) -> TimecodeSource {
+        let frame_rate = if LTC_FRAME_RATES.contains(&frame_rate) {
+            frame_rate
+        } else {
+            DEFAULT_LTC_FRAME_RATE
+        };
+        let mut tc = TimecodeSource {
+            frame_rate,
             state: TimecodeState {
                 running: false,
-                ltc: TimecodeInstant::new(25),
+                ltc: TimecodeInstant::new(frame_rate),
             },
+            last_cycle_frame: TimecodeInstant::new(frame_rate),
             sample_rate,
             ..Default::default()
-        }
+        };
+        tc.preload_frame_buffer();
+        tc
     }
 
     pub fn init(sample_rate: usize, properties: TimecodeProperties) -> TimecodeSource {
         let mut tc = TimecodeSource {
             state: TimecodeState {
                 running: false,
-                ltc: TimecodeInstant::new(25),
+                ltc: TimecodeInstant::new(DEFAULT_LTC_FRAME_RATE),
             },
             properties,
             sample_rate,
@@ -64,8 +170,40 @@ impl TimecodeSource {
         tc
     }
 
+    /// Changes the level, smoothing and polarity, from the next frame on.
+    pub fn configure_output(&mut self, output: TimecodeOutputConfiguration) {
+        self.output = output;
+    }
+
+    /// Changes the frame rate, e.g. when a cue with its own rate is loaded. The current time is
+    /// kept, as far as it exists at the new rate. Unsupported rates are ignored.
+    pub fn set_frame_rate(&mut self, frame_rate: u8) {
+        if frame_rate == self.frame_rate || !LTC_FRAME_RATES.contains(&frame_rate) {
+            return;
+        }
+        self.frame_rate = frame_rate;
+        self.state.ltc.frame_rate = frame_rate;
+        self.state.ltc.f = self.state.ltc.f.min(frame_rate as i8 - 1);
+        self.state.ltc.frame_progress = 0;
+        self.subframe_sample = 0;
+        self.preload_frame_buffer();
+    }
+
+    /// Jams the timecode to `time` from the next frame on, e.g. to match the house clock again
+    /// after an interruption. The beat position is left alone. While the timecode is not
+    /// running, the time is set at once.
+    pub fn jam(&mut self, time: TimecodeTime) {
+        let time = time.instant(self.frame_rate);
+        if self.state.running {
+            self.jam = Some(time);
+        } else {
+            self.state.ltc = time;
+            self.preload_frame_buffer();
+        }
+    }
+
     fn frame_rate(&self) -> u8 {
-        self.state.ltc.frame_rate
+        self.frame_rate
     }
 
     fn even_parity_bit(&self, mut data: u128) -> u128 {
@@ -158,28 +296,42 @@ impl TimecodeSource {
         t_enc
     }
 
-    fn generate_smpte_frame_buffer(&self, samples_per_bit: usize, frame_offset: i8) -> [f32; 2048] {
+    /// Audio of the frame `frame_offset` frames after the current time, `length` samples long.
+    fn generate_smpte_frame_buffer(
+        &self,
+        length: usize,
+        frame_offset: i8,
+    ) -> [f32; MAX_FRAME_SAMPLES] {
         let mut time_with_offs = self.state.ltc.clone();
         time_with_offs.f += frame_offset;
         time_with_offs.add_progress(0);
         let bits = self.generate_smpte_frame_bits(time_with_offs);
 
-        let mut buf = [0f32; 2048];
+        let level = if self.output.invert {
+            -self.output.level
+        } else {
+            self.output.level
+        };
+        let mut buf = [0f32; MAX_FRAME_SAMPLES];
         let mut current_parity = 1;
         for bit_idx in 0..80 {
             let frame_bit = (0x1 << bit_idx) & bits;
+            // Where a frame is not a multiple of 80 samples long, e.g. at 44.1 kHz, some bits
+            // get a sample more than others, so the bits fill the frame
+            let start = bit_idx as usize * length / 80;
+            let samples_per_bit = (bit_idx as usize + 1) * length / 80 - start;
             for sample_idx in 0..samples_per_bit {
-                let idx = sample_idx + bit_idx as usize * samples_per_bit;
+                let idx = start + sample_idx;
                 if sample_idx == 0 || (sample_idx == samples_per_bit / 2 && frame_bit != 0) {
                     current_parity *= -1;
                 }
 
-                buf[idx] = (current_parity as f32) * self.volume;
+                buf[idx] = (current_parity as f32) * level;
             }
         }
 
-        let mut lp_buffer = [0_f32; 2048];
-        self.low_pass(&buf, &mut lp_buffer);
+        let mut lp_buffer = [0_f32; MAX_FRAME_SAMPLES];
+        self.low_pass(&buf[..length], &mut lp_buffer);
 
         lp_buffer
     }
@@ -195,31 +347,26 @@ impl TimecodeSource {
     }
 
     fn preload_frame_buffer(&mut self) {
-        let samples_per_frame: usize = self.samples_per_frame();
-        let samples_per_bit: usize = self.samples_per_bit();
-
-        let a_frame_buf =
-            &self.generate_smpte_frame_buffer(samples_per_bit, 0)[..samples_per_frame];
-        self.frame_buffer[..samples_per_frame].copy_from_slice(a_frame_buf);
+        self.frame_remainder = 0;
+        self.frame_lengths = [self.next_frame_length(), self.next_frame_length()];
+        let [first, second] = self.frame_lengths;
 
-        let b_frame_buf =
-            &self.generate_smpte_frame_buffer(samples_per_bit, 1)[..samples_per_frame];
-        self.frame_buffer[samples_per_frame..2 * samples_per_frame].copy_from_slice(b_frame_buf);
+        let a_frame_buf = &self.generate_smpte_frame_buffer(first, 0)[..first];
+        self.frame_buffer[..first].copy_from_slice(a_frame_buf);
 
-        //for (i, s) in self.frame_buffer.iter().enumerate() {
-        //    println!("fbuf {i:03} {s}")
-        //}
-
-        //self.state.ltc.sub_us(1_000_000 / self.frame_rate() as u64);
-
-        // DEBUG:
-        //for (i, s) in self.frame_buffer.iter_mut().enumerate() {
-        //*s = i as f32 / 8192.0
-        //}
+        let b_frame_buf = &self.generate_smpte_frame_buffer(second, 1)[..second];
+        self.frame_buffer[first..first + second].copy_from_slice(b_frame_buf);
     }
 
-    fn samples_per_bit(&self) -> usize {
-        self.samples_per_frame() / 80
+    /// Length in samples of the next frame to be generated. Where the sample rate is not a
+    /// multiple of the frame rate, e.g. 44.1 kHz at 24 fps, a frame is a sample longer now and
+    /// then, so the timecode keeps to the sample clock.
+    fn next_frame_length(&mut self) -> usize {
+        let frame_rate = self.frame_rate() as usize;
+        self.frame_remainder += self.sample_rate % frame_rate;
+        let length = self.samples_per_frame() + self.frame_remainder / frame_rate;
+        self.frame_remainder %= frame_rate;
+        length.min(MAX_FRAME_SAMPLES)
     }
 
     fn samples_per_frame(&self) -> usize {
@@ -230,18 +377,18 @@ impl TimecodeSource {
         //for (i, s) in buf.iter().enumerate() {
         //    println!("buf {i:03} {s}")
         //}
-        const LP_WIDTH: usize = 3;
-        let samples_per_frame = self.samples_per_frame();
+        let lp_width = self.output.smoothing.clamp(1, MAX_LTC_SMOOTHING) as usize;
+        let samples_per_frame = buf.len();
         for idx in 0..samples_per_frame {
             let mut cumsum = 0.0;
-            for offs_idx in idx..idx + LP_WIDTH {
+            for offs_idx in idx..idx + lp_width {
                 cumsum += if offs_idx < samples_per_frame {
                     buf[offs_idx]
                 } else {
                     -buf[samples_per_frame - 10]
                 }
             }
-            cumsum /= LP_WIDTH as f32;
+            cumsum /= lp_width as f32;
             out[idx] = cumsum;
         }
         //for (i, s) in out.iter().enumerate() {
@@ -270,6 +417,7 @@ impl TimecodeSource {
                     && event.location == i
                 {
                     time = new_time;
+                    time.frame_rate = self.frame_rate();
                 }
             }
             time.add_us(ctx.cue.get_beat(i).unwrap_or_default().length as u64);
@@ -283,35 +431,32 @@ impl TimecodeSource {
             .add_progress((samples * self.frame_rate() as usize * 65536 / sample_rate) as u16);
     }
 
-    fn calculate_frame_overlap(&mut self, block_size: usize) -> u64 {
-        // FIXME: will run slow(?) on some framerates where samples_per_bit gets truncated
-        let samples_per_frame: usize = self.samples_per_frame();
-        let samples_per_bit: usize = self.samples_per_bit();
-
-        if self.subframe_sample > samples_per_frame as u64 {
+    /// Moves the frame buffer on by a frame once playback is past the first of its two frames.
+    fn roll_frame(&mut self) {
+        while self.subframe_sample > self.frame_lengths[0] as u64 {
+            let [first, second] = self.frame_lengths;
+            self.subframe_sample -= first as u64;
+            if let Some(time) = self.jam.take() {
+                self.state.ltc = time;
+                self.preload_frame_buffer();
+                continue;
+            }
             if self.state.running {
                 self.increment();
             }
 
-            self.subframe_sample -= samples_per_frame as u64;
-
-            self.frame_buffer
-                .copy_within(samples_per_frame..2 * samples_per_frame, 0);
+            self.frame_buffer.copy_within(first..first + second, 0);
 
             // write next frame into next frame buffer
-            let next_frame_buf =
-                &self.generate_smpte_frame_buffer(samples_per_bit, 1)[..samples_per_frame];
-            self.frame_buffer[samples_per_frame..2 * samples_per_frame]
-                .copy_from_slice(next_frame_buf);
+            let next = self.next_frame_length();
+            self.frame_lengths = [second, next];
+            let next_frame_buf = &self.generate_smpte_frame_buffer(next, 1)[..next];
+            self.frame_buffer[second..second + next].copy_from_slice(next_frame_buf);
         }
-
-        let ret = self.subframe_sample;
-        self.subframe_sample += block_size as u64;
-        ret
     }
 
     fn audio_frame(&mut self, frame_size: usize) -> &[f32] {
-        let subframe_sample = self.calculate_frame_overlap(frame_size);
+        self.roll_frame();
 
         self.last_cycle_frame = self.state.ltc;
 
@@ -320,7 +465,20 @@ impl TimecodeSource {
         //    self.advance_by_samples(frame_size, self.sample_rate);
         //}
 
-        &self.frame_buffer[subframe_sample as usize..subframe_sample as usize + frame_size]
+        // A cycle can be longer than a frame, in which case the frame buffer is rolled on
+        // during the cycle
+        let mut written = 0;
+        while written < frame_size {
+            self.roll_frame();
+            let start = self.subframe_sample as usize;
+            let buffered = self.frame_lengths[0] + self.frame_lengths[1];
+            let length = (buffered - start).min(frame_size - written);
+            self.block_buffer[written..written + length]
+                .copy_from_slice(&self.frame_buffer[start..start + length]);
+            written += length;
+            self.subframe_sample += length as u64;
+        }
+        &self.block_buffer[..frame_size]
     }
 }
 
@@ -332,6 +490,7 @@ impl audio::source::AudioSource for TimecodeSource {
     fn command(&mut self, ctx: &AudioSourceContext, command: ControlAction) {
         match command {
             ControlAction::TransportZero => {
+                self.jam = None;
                 self.state.ltc.set_time(0, 0, 0, 0);
                 self.state.ltc.frame_progress = 0;
                 ctx.cbnet
@@ -360,15 +519,31 @@ impl audio::source::AudioSource for TimecodeSource {
         }
     }
 
-    fn send_buffer(&mut self, ctx: &AudioSourceContext) -> Result<&[f32], jack::Error> {
+    fn core_command(&mut self, _ctx: &AudioSourceContext, command: CoreControlAction) {
+        match command {
+            CoreControlAction::SetTimecodeOutput(output) => self.configure_output(output),
+            CoreControlAction::SetTimecodeFrameRate(frame_rate) => self.set_frame_rate(frame_rate),
+            CoreControlAction::JamTimecode(time) => self.jam(time),
+            _ => {}
+        }
+    }
+
+    fn send_buffer<'a>(
+        &'a mut self,
+        ctx: &'a AudioSourceContext,
+    ) -> Result<&'a [f32], jack::Error> {
         if !self.state.running {
-            return Ok(self.silence(ctx.frame_size));
+            return Ok(ctx.silence());
         }
 
         ctx.cbnet
             .notify(Message::Small(SmallMessage::TimecodeData(self.state)));
 
-        self.sample_rate = ctx.sample_rate;
+        if ctx.sample_rate != self.sample_rate && ctx.sample_rate > 0 {
+            self.sample_rate = ctx.sample_rate;
+            self.subframe_sample = 0;
+            self.preload_frame_buffer();
+        }
 
         Ok(self.audio_frame(ctx.frame_size))
     }
@@ -382,6 +557,7 @@ impl audio::source::AudioSource for TimecodeSource {
             // FIXME: actually handle wall time
             if !self.properties.use_wall_time {
                 self.state.ltc = time;
+                self.state.ltc.frame_rate = self.frame_rate();
                 self.preload_frame_buffer();
             }
 
@@ -394,6 +570,10 @@ impl audio::source::AudioSource for TimecodeSource {
                 .notify(Message::Small(SmallMessage::TimecodeData(self.state)));
         }
     }
+
+    fn set_max_frame_size(&mut self, frames: usize) {
+        self.block_buffer.resize(frames, 0.0);
+    }
 }
 
 #[cfg(test)]
@@ -628,6 +808,59 @@ mod tests {
         assert!(!all_zeroes, "Export resulted in a silent file");
     }
 
+    #[test]
+    fn output_level_and_polarity() {
+        use super::*;
+
+        let mut tc = TimecodeSource::init(48000, TimecodeProperties::default());
+        let normal = tc.generate_smpte_frame_buffer(tc.samples_per_frame(), 0);
+        tc.configure_output(TimecodeOutputConfiguration {
+            level: 0.25,
+            smoothing: 3,
+            invert: true,
+        });
+        let inverted = tc.generate_smpte_frame_buffer(tc.samples_per_frame(), 0);
+        for (a, b) in normal.iter().zip(inverted.iter()) {
+            assert!((a * -0.5 - b).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn change_frame_rate() {
+        use super::*;
+
+        let mut tc = TimecodeSource::init(48000, TimecodeProperties::default());
+        tc.state.ltc.f = 24;
+        tc.set_frame_rate(24);
+        assert_eq!(tc.state.ltc.frame_rate, 24);
+        assert_eq!(tc.state.ltc.f, 23);
+        assert_eq!(tc.samples_per_frame(), 2000);
+
+        // 29 fps is no LTC rate
+        tc.set_frame_rate(29);
+        assert_eq!(tc.state.ltc.frame_rate, 24);
+    }
+
+    #[test]
+    fn cycles_longer_than_a_frame() {
+        use crate::audio::source::AudioSource;
+
+        let mut short = TimecodeSource::init(48000, TimecodeProperties::default());
+        let mut long = TimecodeSource::init(48000, TimecodeProperties::default());
+        long.set_max_frame_size(4096);
+
+        let mut short_out = vec![];
+        for _ in 0..48 {
+            short_out.extend_from_slice(short.audio_frame(256));
+        }
+        let mut long_out = vec![];
+        for _ in 0..3 {
+            long_out.extend_from_slice(long.audio_frame(4096));
+        }
+        // The same signal whatever the cycle size, 6 frames of 1920 samples in
+        assert_eq!(short_out, long_out);
+    }
+
     #[test]
     fn advance() {
         use super::*;
@@ -641,6 +874,50 @@ mod tests {
         assert_eq!(tc.state.ltc.f, 1);
     }
 
+    #[test]
+    fn frames_at_44k1() {
+        use super::*;
+
+        let mut tc = TimecodeSource::init(44100, TimecodeProperties::default());
+        tc.set_frame_rate(24);
+        // 1837.5 samples a frame, which the frames make up for between them
+        assert_eq!(tc.frame_lengths, [1837, 1838]);
+        let second: usize = (0..24).map(|_| tc.next_frame_length()).sum();
+        assert_eq!(second, 44100);
+
+        // The bits fill the frame, with no gap before the next one
+        let frame = tc.generate_smpte_frame_buffer(1837, 0);
+        assert_ne!(frame[1836], 0.0);
+        assert_eq!(frame[1837], 0.0);
+    }
+
+    #[test]
+    fn jam_at_frame_boundary() {
+        use super::*;
+
+        let mut tc = TimecodeSource::init(48000, TimecodeProperties::default());
+        tc.audio_frame(256);
+        tc.jam(TimecodeTime {
+            hours: 19,
+            minutes: 30,
+            seconds: 5,
+            frames: 29,
+        });
+        // The frame being sent is finished first, 1920 samples at 25 fps
+        for _ in 0..7 {
+            tc.audio_frame(256);
+        }
+        assert_eq!((tc.state.ltc.h, tc.state.ltc.s), (0, 0));
+        tc.audio_frame(256);
+        let ltc = tc.state.ltc;
+        // Frame 29 does not exist at 25 fps
+        assert_eq!((ltc.h, ltc.m, ltc.s, ltc.f), (19, 30, 5, 24));
+        // and the timecode runs on from the new time
+        tc.audio_frame(1920);
+        tc.audio_frame(256);
+        assert_eq!((tc.state.ltc.s, tc.state.ltc.f), (6, 0));
+    }
+
     #[test]
     fn wraparound() {
         let mut time = TimecodeSource::init(48000, TimecodeProperties::default());
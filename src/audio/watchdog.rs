@@ -0,0 +1,124 @@
+//! Keeping the routing the core has set up. JACK reports every change of the graph to the
+//! notification handler, which only raises a flag; the main loop then compares the connections
+//! with the ones the core made or removed itself and puts back whatever another client, or a
+//! client going away, has changed.
+//!
+//! Connections the core has never touched are left alone, so routing done by hand or by a
+//! session manager stays as it is.
+use crate::audio::handler::RouteMask;
+use serde::{Deserialize, Serialize};
+use std::{fmt, time::Duration};
+
+/// How long the graph has to stay unchanged before it is checked, so a client going away or
+/// coming back is looked at once it is done and not halfway through.
+pub const ROUTING_SETTLE: Duration = Duration::from_millis(500);
+
+/// Connections the core made or removed, per client port, between the client ports and the
+/// system ports on one side of the client.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RoutingIntent {
+    /// Per client port, the system ports the core connected it to
+    connected: Vec<RouteMask>,
+    /// Per client port, the system ports the core disconnected it from
+    disconnected: Vec<RouteMask>,
+}
+
+impl RoutingIntent {
+    /// Records a connection the core made or removed.
+    pub fn record(&mut self, port: usize, target: usize, connect: bool) {
+        if target >= RouteMask::BITS as usize {
+            return;
+        }
+        if self.connected.len() <= port {
+            self.connected.resize(port + 1, 0);
+            self.disconnected.resize(port + 1, 0);
+        }
+        let bit = 0x01 << target;
+        if connect {
+            self.connected[port] |= bit;
+            self.disconnected[port] &= !bit;
+        } else {
+            self.connected[port] &= !bit;
+            self.disconnected[port] |= bit;
+        }
+    }
+
+    /// Changes that bring `current` back to the routing the core set up, as client port, system
+    /// port and whether to connect them. Ports that no longer exist are skipped.
+    pub fn repairs(&self, current: &[RouteMask]) -> Vec<(usize, usize, bool)> {
+        let mut repairs = vec![];
+        for (port, mask) in current.iter().enumerate() {
+            let connected = self.connected.get(port).copied().unwrap_or_default();
+            let disconnected = self.disconnected.get(port).copied().unwrap_or_default();
+            for target in 0..RouteMask::BITS as usize {
+                let bit = 0x01 << target;
+                if connected & bit != 0 && mask & bit == 0 {
+                    repairs.push((port, target, true));
+                } else if disconnected & bit != 0 && mask & bit != 0 {
+                    repairs.push((port, target, false));
+                }
+            }
+        }
+        repairs
+    }
+}
+
+/// A connection found changed by someone other than the core.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RoutingRepair {
+    /// Whether it is the connection of a system capture port to a client input, rather than of
+    /// a client output to a system port
+    pub input: bool,
+    /// Client output, or system capture port for inputs
+    pub from: u8,
+    /// System port, or client input for inputs
+    pub to: u8,
+    /// Whether the connection was dropped and should be there, rather than made and should not
+    pub connect: bool,
+    /// Whether the connection was put back as the core had it
+    pub restored: bool,
+}
+
+impl fmt::Display for RoutingRepair {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (from, to) = (self.from, self.to);
+        if self.input {
+            write!(f, "capture [{from}] -> input [{to}]")?;
+        } else {
+            write!(f, "port [{from}] -> [{to}]")?;
+        }
+        f.write_str(match (self.connect, self.restored) {
+            (true, true) => " was dropped and has been reconnected",
+            (true, false) => " was dropped and has not been reconnected",
+            (false, true) => " was made by another client and has been removed",
+            (false, false) => " was made by another client and has been left",
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn repairs() {
+        let mut intent = RoutingIntent::default();
+        assert!(intent.repairs(&[0b11, 0b01]).is_empty());
+
+        intent.record(0, 1, true);
+        intent.record(1, 0, false);
+        intent.record(1, RouteMask::BITS as usize, true);
+        assert!(intent.repairs(&[0b10, 0b10]).is_empty());
+        // Connections the core never touched are left alone
+        assert!(intent.repairs(&[0b11, 0b100]).is_empty());
+        assert_eq!(
+            intent.repairs(&[0b01, 0b11]),
+            vec![(0, 1, true), (1, 0, false)]
+        );
+        // Output 1 is gone
+        assert_eq!(intent.repairs(&[0]), vec![(0, 1, true)]);
+
+        intent.record(0, 1, false);
+        assert_eq!(intent.repairs(&[0b10]), vec![(0, 1, false)]);
+    }
+}
@@ -0,0 +1,114 @@
+use crate::communication::interface::RequestSource;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::VecDeque,
+    fs::File,
+    io::{BufWriter, Write},
+    path::PathBuf,
+};
+
+/// Number of recent audit entries kept in memory.
+const AUDIT_HISTORY_SIZE: usize = 500;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum AuditOutcome {
+    Accepted,
+    Failed,
+    /// Not acted upon, see `validation::validate`
+    Rejected,
+}
+
+/// A single request as received by the core, with its origin and what came of it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditEntry {
+    /// Unix time in ms
+    pub time: i64,
+    pub source: RequestSource,
+    pub request: String,
+    pub outcome: AuditOutcome,
+}
+
+impl std::fmt::Display for AuditEntry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "[{}] {} from {}: {:?}",
+            chrono::DateTime::from_timestamp_millis(self.time)
+                .unwrap_or_default()
+                .format("%H:%M:%S%.3f"),
+            self.request,
+            self.source,
+            self.outcome
+        )
+    }
+}
+
+/// Record of every request the core has acted on, kept both in memory for queries and in a
+/// JSON Lines audit file next to the regular logs.
+pub struct AuditTrail {
+    history: VecDeque<AuditEntry>,
+    file: Option<BufWriter<File>>,
+}
+
+impl AuditTrail {
+    pub fn new(path: PathBuf) -> Result<Self, std::io::Error> {
+        let full_path = std::env::current_dir()
+            .map_err(|_| std::io::ErrorKind::PermissionDenied)?
+            .join(path);
+        if let Some(parent) = full_path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(full_path)?;
+
+        Ok(Self {
+            history: VecDeque::with_capacity(AUDIT_HISTORY_SIZE),
+            file: Some(BufWriter::new(file)),
+        })
+    }
+
+    /// An audit trail which is only kept in memory, for when the audit file cannot be opened.
+    pub fn in_memory() -> Self {
+        Self {
+            history: VecDeque::with_capacity(AUDIT_HISTORY_SIZE),
+            file: None,
+        }
+    }
+
+    pub fn record(
+        &mut self,
+        source: RequestSource,
+        request: String,
+        outcome: AuditOutcome,
+    ) -> Result<(), std::io::Error> {
+        let entry = AuditEntry {
+            time: chrono::Utc::now().timestamp_millis(),
+            source,
+            request,
+            outcome,
+        };
+
+        if let Some(file) = &mut self.file {
+            serde_json::to_writer(&mut *file, &entry)?;
+            file.write_all(b"\n")?;
+            file.flush()?;
+        }
+
+        if self.history.len() >= AUDIT_HISTORY_SIZE {
+            self.history.pop_front();
+        }
+        self.history.push_back(entry);
+        Ok(())
+    }
+
+    /// The last `count` audit entries, oldest first.
+    pub fn recent(&self, count: usize) -> Vec<AuditEntry> {
+        self.history
+            .iter()
+            .skip(self.history.len().saturating_sub(count))
+            .cloned()
+            .collect()
+    }
+}
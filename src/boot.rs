@@ -1,6 +1,11 @@
-use crate::logger::LogDispatcher;
+use crate::{config::CoreConfiguration, logger::LogDispatcher};
 use common::local::config::{LogContext, LogItem, LogKind, SystemConfiguration};
-use std::{fmt::Display, path::PathBuf, str::FromStr};
+use std::{
+    fmt::Display,
+    path::PathBuf,
+    str::FromStr,
+    time::{Duration, Instant},
+};
 
 #[derive(Debug)]
 pub enum BootError {
@@ -126,6 +131,141 @@ pub fn write_config(config: SystemConfiguration) -> Result<(), BootError> {
     }
 }
 
+pub fn get_core_config_path() -> PathBuf {
+    PathBuf::from_str(".config/clicks/core.conf").expect("PathBuf cannot fail from_str")
+}
+
+/// Reads the core configuration, writing the default one first if there is none yet.
+pub fn get_core_config() -> Result<CoreConfiguration, BootError> {
+    if !std::fs::exists(get_core_config_path()).unwrap_or_default() {
+        write_core_config(&CoreConfiguration::default())?;
+    }
+    let file_content = match std::fs::read_to_string(get_core_config_path()) {
+        Ok(content) => content,
+        Err(err) => return Err(BootError::FileReadError(err.to_string())),
+    };
+
+    match serde_json::from_str::<CoreConfiguration>(&file_content) {
+        Ok(config) => Ok(config),
+        Err(err) => Err(BootError::BootProgramOrderFailure(err.to_string())),
+    }
+}
+
+pub fn write_core_config(config: &CoreConfiguration) -> Result<(), BootError> {
+    let _ = std::fs::create_dir_all(
+        get_core_config_path()
+            .parent()
+            .expect("get_core_config_path() is constant and has a definite parent."),
+    );
+    let config_str = match serde_json::to_string_pretty(config) {
+        Ok(val) => val,
+        Err(err) => return Err(BootError::ConfigWriteError(err.to_string())),
+    };
+
+    match std::fs::write(get_core_config_path(), config_str) {
+        Ok(_) => Ok(()),
+        Err(err) => Err(BootError::ConfigWriteError(err.to_string())),
+    }
+}
+
+/// A configuration that `ConfigPersistence` can write to disk.
+pub trait PersistentConfig {
+    fn write(&self) -> Result<(), BootError>;
+}
+
+impl PersistentConfig for SystemConfiguration {
+    fn write(&self) -> Result<(), BootError> {
+        write_config(*self)
+    }
+}
+
+impl PersistentConfig for CoreConfiguration {
+    fn write(&self) -> Result<(), BootError> {
+        write_core_config(self)
+    }
+}
+
+/// Time a configuration change has to settle before it is written to disk.
+const CONFIG_WRITE_DEBOUNCE: Duration = Duration::from_secs(2);
+/// Longest time unsaved changes may stay in memory while changes keep coming in.
+const CONFIG_WRITE_INTERVAL: Duration = Duration::from_secs(30);
+/// Time before a failed write is retried, doubled on every further failure in a row up to
+/// `CONFIG_RETRY_MAX`.
+const CONFIG_RETRY_MIN: Duration = Duration::from_secs(1);
+const CONFIG_RETRY_MAX: Duration = Duration::from_secs(60);
+
+/// Keeps track of unsaved configuration changes and writes them to disk once they settle, so a
+/// crash or power cut does not lose every change made since boot.
+pub struct ConfigPersistence {
+    dirty: bool,
+    last_change: Instant,
+    last_write: Instant,
+    /// Writes failed in a row, and when the next may be tried
+    failures: u32,
+    retry_at: Option<Instant>,
+}
+
+impl ConfigPersistence {
+    pub fn new() -> Self {
+        Self {
+            dirty: false,
+            last_change: Instant::now(),
+            last_write: Instant::now(),
+            failures: 0,
+            retry_at: None,
+        }
+    }
+
+    pub fn mark_dirty(&mut self) {
+        self.dirty = true;
+        self.last_change = Instant::now();
+    }
+
+    /// Writes the configuration if it is dirty and either no change has come in for
+    /// `CONFIG_WRITE_DEBOUNCE`, or it has been dirty for longer than `CONFIG_WRITE_INTERVAL`.
+    /// Returns true if the configuration was written. After a failed write it is not tried
+    /// again until its backoff has passed.
+    pub fn tick(&mut self, config: &impl PersistentConfig) -> Result<bool, BootError> {
+        if !self.dirty
+            || self.retry_at.is_some_and(|at| Instant::now() < at)
+            || (self.last_change.elapsed() < CONFIG_WRITE_DEBOUNCE
+                && self.last_write.elapsed() < CONFIG_WRITE_INTERVAL)
+        {
+            return Ok(false);
+        }
+        self.flush(config)?;
+        Ok(true)
+    }
+
+    /// Writes the configuration immediately, regardless of the dirty flag.
+    pub fn flush(&mut self, config: &impl PersistentConfig) -> Result<(), BootError> {
+        if let Err(err) = config.write() {
+            let backoff = CONFIG_RETRY_MIN
+                .saturating_mul(1 << self.failures.min(16))
+                .min(CONFIG_RETRY_MAX);
+            self.failures += 1;
+            self.retry_at = Some(Instant::now() + backoff);
+            return Err(err);
+        }
+        self.last_write = Instant::now();
+        self.dirty = false;
+        self.failures = 0;
+        self.retry_at = None;
+        Ok(())
+    }
+
+    /// Writes that have failed in a row since the last one that succeeded.
+    pub fn failures(&self) -> u32 {
+        self.failures
+    }
+}
+
+impl Default for ConfigPersistence {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 //pub fn copy_logs(path: PathBuf) -> Result<(), BootError> {
 //    match std::fs::copy(logger::get_path(), path.join("logs/")) {
 //        Ok(_) => Ok(()),
@@ -168,3 +308,51 @@ pub fn try_load_usb_show() -> Result<(), BootError> {
     }
     Err(BootError::FileDoesNotExist)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+
+    /// Configuration whose writes fail while `broken` is set, counting them.
+    #[derive(Default)]
+    struct Disk {
+        broken: Cell<bool>,
+        writes: Cell<u32>,
+    }
+
+    impl PersistentConfig for Disk {
+        fn write(&self) -> Result<(), BootError> {
+            self.writes.set(self.writes.get() + 1);
+            if self.broken.get() {
+                Err(BootError::FileDoesNotExist)
+            } else {
+                Ok(())
+            }
+        }
+    }
+
+    #[test]
+    fn failed_writes_back_off() {
+        let disk = Disk::default();
+        disk.broken.set(true);
+        let mut persistence = ConfigPersistence::new();
+        persistence.mark_dirty();
+        persistence.last_change -= CONFIG_WRITE_DEBOUNCE;
+        assert!(persistence.tick(&disk).is_err());
+        assert_eq!(persistence.failures(), 1);
+        // Not retried on the next cycles
+        assert!(matches!(persistence.tick(&disk), Ok(false)));
+        assert_eq!(disk.writes.get(), 1);
+
+        persistence.retry_at = Some(Instant::now());
+        assert!(persistence.tick(&disk).is_err());
+        assert_eq!(persistence.failures(), 2);
+
+        disk.broken.set(false);
+        persistence.retry_at = Some(Instant::now());
+        assert!(matches!(persistence.tick(&disk), Ok(true)));
+        assert_eq!(persistence.failures(), 0);
+        assert!(matches!(persistence.tick(&disk), Ok(false)));
+    }
+}
@@ -1,8 +1,21 @@
+use crate::{
+    audio::{processor::ShowSwap, profile::Profiler},
+    communication::extension::{CoreControlAction, CoreMessage},
+    metrics::{Metrics, QueueDepths},
+};
 use common::{
     local::config::LogItem,
     protocol::{message::Message, request::ControlAction},
 };
-use crossbeam_channel::{Receiver, Sender, unbounded};
+use crossbeam_channel::{Receiver, Sender, bounded, unbounded};
+use std::sync::Arc;
+
+/// Capacity of the log queue. The queue is bounded so that logging from the RT thread never
+/// allocates queue space; items are dropped if the logger thread falls this far behind.
+const LOG_QUEUE_CAPACITY: usize = 1024;
+/// Capacity of the queue of shows replaced on the RT thread, bounded for the same reason. A
+/// replaced show is dropped on the RT thread if the main loop falls this far behind.
+const RETIRED_QUEUE_CAPACITY: usize = 4;
 
 #[derive(Debug, Clone)]
 pub struct CrossbeamNetwork {
@@ -12,13 +25,31 @@ pub struct CrossbeamNetwork {
     pub notif_rx: Receiver<Message>,
     log_tx: Sender<LogItem>,
     pub log_rx: Receiver<LogItem>,
+    core_notif_tx: Sender<CoreMessage>,
+    pub core_notif_rx: Receiver<CoreMessage>,
+    core_cmd_tx: Sender<CoreControlAction>,
+    pub core_cmd_rx: Receiver<CoreControlAction>,
+    show_tx: Sender<ShowSwap>,
+    pub show_rx: Receiver<ShowSwap>,
+    retired_tx: Sender<ShowSwap>,
+    /// Shows and playback sources replaced by `swap_show`, to be dropped off the RT thread
+    pub retired_rx: Receiver<ShowSwap>,
+    pub metrics: Arc<Metrics>,
+    pub profiler: Arc<Profiler>,
 }
 
 impl CrossbeamNetwork {
     pub fn new() -> Self {
         let (cmd_tx, cmd_rx): (Sender<ControlAction>, Receiver<ControlAction>) = unbounded();
         let (notif_tx, notif_rx): (Sender<Message>, Receiver<Message>) = unbounded();
-        let (log_tx, log_rx): (Sender<LogItem>, Receiver<LogItem>) = unbounded();
+        let (log_tx, log_rx): (Sender<LogItem>, Receiver<LogItem>) = bounded(LOG_QUEUE_CAPACITY);
+        let (core_notif_tx, core_notif_rx): (Sender<CoreMessage>, Receiver<CoreMessage>) =
+            unbounded();
+        let (core_cmd_tx, core_cmd_rx): (Sender<CoreControlAction>, Receiver<CoreControlAction>) =
+            unbounded();
+        let (show_tx, show_rx): (Sender<ShowSwap>, Receiver<ShowSwap>) = unbounded();
+        let (retired_tx, retired_rx): (Sender<ShowSwap>, Receiver<ShowSwap>) =
+            bounded(RETIRED_QUEUE_CAPACITY);
         Self {
             cmd_tx,
             cmd_rx,
@@ -26,6 +57,16 @@ impl CrossbeamNetwork {
             notif_rx,
             log_tx,
             log_rx,
+            core_notif_tx,
+            core_notif_rx,
+            core_cmd_tx,
+            core_cmd_rx,
+            show_tx,
+            show_rx,
+            retired_tx,
+            retired_rx,
+            metrics: Arc::new(Metrics::default()),
+            profiler: Arc::new(Profiler::default()),
         }
     }
 
@@ -33,13 +74,40 @@ impl CrossbeamNetwork {
         let _ = self.notif_tx.try_send(notif);
     }
 
+    pub fn notify_core(&self, notif: CoreMessage) {
+        let _ = self.core_notif_tx.try_send(notif);
+    }
+
     pub fn command(&self, cmd: ControlAction) {
         let _ = self.cmd_tx.try_send(cmd);
     }
 
+    pub fn command_core(&self, cmd: CoreControlAction) {
+        let _ = self.core_cmd_tx.try_send(cmd);
+    }
+
+    /// Hands another show and its playback sources to the running processor.
+    pub fn swap_show(&self, swap: ShowSwap) {
+        let _ = self.show_tx.try_send(swap);
+    }
+
+    /// Hands back what `swap_show` replaced.
+    pub fn retire(&self, swap: ShowSwap) {
+        let _ = self.retired_tx.try_send(swap);
+    }
+
     pub fn log(&self, log_item: LogItem) {
         let _ = self.log_tx.try_send(log_item);
     }
+
+    pub fn queue_depths(&self) -> QueueDepths {
+        QueueDepths {
+            commands: self.cmd_rx.len(),
+            notifications: self.notif_rx.len(),
+            log: self.log_rx.len(),
+            core_notifications: self.core_notif_rx.len(),
+        }
+    }
 }
 impl Default for CrossbeamNetwork {
     fn default() -> Self {
@@ -0,0 +1,66 @@
+use crate::logger::{LogFormat, LogRetention};
+use clap::Parser;
+use std::{path::PathBuf, time::Duration};
+
+/// Command line arguments of clicks-core. Everything that can change at runtime lives in the
+/// configuration file instead, these are only the options that need to be known before it is
+/// read.
+#[derive(Parser, Debug)]
+#[command(version, about)]
+pub struct Args {
+    /// Format of the log file written to the logs directory
+    #[arg(long, value_enum, default_value_t = LogFormat::Text)]
+    pub log_format: LogFormat,
+
+    /// Delete archived logs older than this many days when starting
+    #[arg(long, value_name = "DAYS", default_value_t = 30)]
+    pub log_max_age: u64,
+
+    /// Delete the oldest archived logs when starting, until they take up at most this many MB
+    #[arg(long, value_name = "MB", default_value_t = 16)]
+    pub log_max_size: u64,
+
+    /// Gzip archived logs older than this many days when starting
+    #[arg(long, value_name = "DAYS", default_value_t = 1)]
+    pub log_compress_after: u64,
+
+    /// Also send log output to a remote syslog collector (RFC 5424 over UDP)
+    #[arg(long, value_name = "HOST:PORT")]
+    pub syslog: Option<String>,
+
+    /// Write the beat timing report of the stored show to a .csv or .json file, or its click
+    /// track to a .mid file, and exit
+    #[arg(long, value_name = "PATH")]
+    pub timing_report: Option<PathBuf>,
+
+    /// Write a JSON description of all requests and messages of this version, for generating
+    /// client bindings, and exit
+    #[arg(long, value_name = "PATH")]
+    pub protocol_schema: Option<PathBuf>,
+
+    /// Write the checksums of the show file and media of a show directory into its manifest,
+    /// e.g. before copying the show to a stick, and exit
+    #[arg(long, value_name = "SHOW_DIR")]
+    pub write_manifest: Option<PathBuf>,
+
+    /// Check a show directory against its manifest and exit, with status 1 if any file is
+    /// missing or damaged
+    #[arg(long, value_name = "SHOW_DIR")]
+    pub verify_manifest: Option<PathBuf>,
+
+    /// Compile the show of a show directory for fast loading, e.g. before copying the show to a
+    /// stick, and exit. Shows are otherwise compiled the first time they are loaded
+    #[arg(long, value_name = "SHOW_DIR")]
+    pub compile_show: Option<PathBuf>,
+}
+
+impl Args {
+    pub fn log_retention(&self) -> LogRetention {
+        const SECS_PER_DAY: u64 = 86400;
+        LogRetention {
+            max_age: Duration::from_secs(self.log_max_age * SECS_PER_DAY),
+            max_size: self.log_max_size * 1_000_000,
+            compress_after: Duration::from_secs(self.log_compress_after * SECS_PER_DAY),
+        }
+    }
+}
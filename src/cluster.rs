@@ -0,0 +1,86 @@
+use crate::failover::MirrorState;
+use serde::{Deserialize, Serialize};
+use std::net::SocketAddr;
+
+/// Running as a follower in a cluster: the unit mirrors the cue and transport of a conductor
+/// playing the same show, and renders only its share of the channels. It is kept to the beat
+/// of the conductor as a backup is, see `MirrorState::actions_from`; offsets within a beat are
+/// reported in `ClusterStatus`, not corrected.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ClusterConfiguration {
+    /// Address of the binary protocol handler of the conductor
+    pub conductor: SocketAddr,
+    /// First channel rendered by this unit
+    pub first_channel: u8,
+    /// Number of channels rendered by this unit, from `first_channel`
+    pub channel_count: u8,
+    /// Time without state from the conductor after which it is reported as gone
+    pub timeout_ms: u32,
+}
+
+impl Default for ClusterConfiguration {
+    fn default() -> Self {
+        Self {
+            conductor: SocketAddr::from(([0, 0, 0, 0], 8081)),
+            first_channel: 0,
+            channel_count: u8::MAX,
+            timeout_ms: 2000,
+        }
+    }
+}
+
+/// State of a follower in a cluster, broadcast to its subscribers every second.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ClusterStatus {
+    pub conductor: SocketAddr,
+    pub first_channel: u8,
+    pub channel_count: u8,
+    pub conductor_alive: bool,
+    /// How much later this unit reached the last beat it shared with the conductor, negative if
+    /// earlier. Only reported, for the operator to act on.
+    pub drift_us: Option<i64>,
+}
+
+/// How much later `local` reached its beat than `conductor`, if both are running on the same
+/// beat.
+pub fn beat_drift(local: &MirrorState, conductor: &MirrorState) -> Option<i64> {
+    if !local.running
+        || !conductor.running
+        || local.cue != conductor.cue
+        || local.beat != conductor.beat
+    {
+        return None;
+    }
+    Some(local.beat_time_us as i64 - conductor.beat_time_us as i64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn drift() {
+        let conductor = MirrorState {
+            cue: 1,
+            beat: 16,
+            running: true,
+            beat_time_us: 5_000_000,
+        };
+        let local = MirrorState {
+            beat_time_us: 5_002_500,
+            ..conductor
+        };
+        assert_eq!(beat_drift(&local, &conductor), Some(2_500));
+        assert_eq!(beat_drift(&conductor, &local), Some(-2_500));
+        assert_eq!(
+            beat_drift(&MirrorState { beat: 17, ..local }, &conductor),
+            None
+        );
+        let stopped = MirrorState {
+            running: false,
+            ..local
+        };
+        assert_eq!(beat_drift(&stopped, &conductor), None);
+    }
+}
@@ -1,8 +1,22 @@
-use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::{
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    time::Instant,
+};
 
 use crate::{
-    communication::{interface::CommunicationInterface, netport::NetworkPort},
+    communication::{
+        decode::{Decoded, decode_postcard},
+        extension::{
+            CORE_MESSAGE_BYTE, CORE_REQUEST_BYTE, CoreMessage, CoreRequest, protocol_compatible,
+        },
+        interface::{CommunicationInterface, RequestSource, SubscriberVersion},
+        netport::NetworkPort,
+        replay::MessageCache,
+    },
+    config::NotificationTarget,
+    failover::MIRROR_EXPIRY,
     logger::LogDispatcher,
+    validation::RejectReason,
 };
 use chrono::{DateTime, Utc};
 use common::{
@@ -20,7 +34,15 @@ use common::{
 pub struct BinaryNetHandler {
     port: NetworkPort,
     subscribers: Vec<SubscriberInfo>,
-    input_queue: Vec<Request>,
+    /// Protocol version stated by clients with `CoreRequest::Hello`, by address
+    protocol_versions: Vec<(SocketAddr, u16)>,
+    /// Backup units mirroring this one, and when they last renewed
+    mirrors: Vec<(SocketAddr, Instant)>,
+    input_queue: Vec<(Request, RequestSource)>,
+    core_input_queue: Vec<(CoreRequest, RequestSource)>,
+    cache: MessageCache,
+    /// Clients sent notifications without subscribing
+    targets: Vec<NotificationTarget>,
 }
 
 impl BinaryNetHandler {
@@ -28,7 +50,12 @@ impl BinaryNetHandler {
         let a = Self {
             port: NetworkPort::new(port),
             subscribers: vec![],
+            protocol_versions: vec![],
+            mirrors: vec![],
             input_queue: vec![],
+            core_input_queue: vec![],
+            cache: MessageCache::new(),
+            targets: vec![],
         };
         logger.log(LogItem::new(
             format!("opened binnet port {}", a.port.socket.local_addr().unwrap()),
@@ -38,18 +65,167 @@ impl BinaryNetHandler {
         a
     }
 
+    /// Sends notifications to fixed clients as well as to subscribers.
+    pub fn with_targets(mut self, targets: Vec<NotificationTarget>) -> Self {
+        self.targets = targets;
+        self
+    }
+
     pub fn publish_subscribers(&mut self) {
         self.notify(Message::Large(LargeMessage::NetworkChanged(
             NetworkStatus {
                 subscribers: self.subscribers.clone(),
             },
         )));
+        self.notify_core(&CoreMessage::Subscribers(self.subscribers()));
+    }
+
+    fn protocol_version(&self, address: SocketAddr) -> Option<u16> {
+        self.protocol_versions
+            .iter()
+            .find(|(from, _)| *from == address)
+            .map(|(_, version)| *version)
+    }
+
+    fn set_protocol_version(&mut self, address: SocketAddr, version: u16) {
+        self.protocol_versions.retain(|(from, _)| *from != address);
+        self.protocol_versions.push((address, version));
+    }
+
+    /// Drops subscribers not heard from for 15 minutes, along with the protocol version they
+    /// stated.
+    fn expire_subscribers(&mut self) {
+        let (subscribers, expired): (Vec<_>, Vec<_>) = std::mem::take(&mut self.subscribers)
+            .into_iter()
+            .partition(|sub| {
+                Utc::now()
+                    .signed_duration_since(
+                        DateTime::from_timestamp_secs(sub.last_contact as i64).unwrap_or_default(),
+                    )
+                    .num_minutes()
+                    < 15
+            });
+        self.subscribers = subscribers;
+        for subscriber in &expired {
+            let address = Self::subscriber_socket_addr(subscriber);
+            self.protocol_versions.retain(|(from, _)| *from != address);
+        }
+    }
+
+    /// Tells a client that its request was not acted upon.
+    fn reject(&mut self, to: SocketAddr, request: String, reason: RejectReason) {
+        self.reply(to, CoreMessage::RequestRejected { request, reason });
+    }
+
+    /// The request of a decoded packet, or None after telling the client why there is none.
+    fn known<T>(&mut self, src: SocketAddr, kind: &str, decoded: Decoded<T>) -> Option<T> {
+        match decoded {
+            Decoded::Known(request) => return Some(request),
+            Decoded::Unsupported { variant } => self.reject(
+                src,
+                format!("{kind} variant {variant}"),
+                RejectReason::Unsupported,
+            ),
+            Decoded::Unreadable(err) => {
+                self.reject(src, format!("{kind}: {err}"), RejectReason::Unreadable)
+            }
+        }
+        None
+    }
+
+    fn subscriber_socket_addr(subscriber: &SubscriberInfo) -> SocketAddr {
+        SocketAddr::new(
+            IpAddr::V4(Ipv4Addr::new(
+                subscriber.address.addr[0],
+                subscriber.address.addr[1],
+                subscriber.address.addr[2],
+                subscriber.address.addr[3],
+            )),
+            subscriber.address.port,
+        )
+    }
+
+    fn encode(message: &Message) -> Option<Vec<u8>> {
+        let mut buffer = match message {
+            Message::Small(message) => postcard::to_stdvec(message),
+            Message::Large(message) => postcard::to_stdvec(message),
+        }
+        .ok()?;
+
+        // insert a message size byte at the start, which tells the client if this is a small or
+        // large message, since otherwise they can happen to look like the other size, and be
+        // parsed incorrectly
+        //
+        // The LSB of the size byte is enough to tell: 1 is small, 0 is large, but we have some
+        // extra redundancy to a) make sure that it is actually a size byte and not a random bit in
+        // some misplaced message, and b) to identify the size byte in both flipped and non-flipped
+        // ordering
+        buffer.insert(
+            0,
+            match message {
+                Message::Small(..) => 0xE1,
+                Message::Large(..) => 0xD2,
+            },
+        );
+        Some(buffer)
+    }
+
+    /// Sends the latest message of every subscribed type to a new subscriber.
+    fn replay(&mut self, subscriber: &SubscriberInfo) {
+        let address = Self::subscriber_socket_addr(subscriber);
+        for message in self.cache.messages() {
+            if subscriber.message_kinds.contains(message.to_type())
+                && let Some(buffer) = Self::encode(message)
+            {
+                self.port.send_to(&buffer, address);
+            }
+        }
+    }
+
+    fn source(&self, src: SocketAddr) -> RequestSource {
+        let address = IpAddress::from_str_and_port(&src.ip().to_string(), src.port());
+        RequestSource {
+            handler: self.name(),
+            handler_index: None,
+            address: src,
+            identifier: self
+                .subscribers
+                .iter()
+                .find(|subscriber| Some(subscriber.address) == address)
+                .map(|subscriber| subscriber.identifier.str().to_string()),
+        }
     }
 }
 
 impl CommunicationInterface for BinaryNetHandler {
-    fn get_inputs(&mut self, limit: usize) -> Vec<Request> {
-        let mut inputs: Vec<Request> = vec![];
+    fn name(&self) -> &'static str {
+        "binnet"
+    }
+
+    fn take_packet_counts(&mut self) -> (u32, u32) {
+        self.port.take_packet_counts()
+    }
+
+    fn rebind(&mut self) -> std::io::Result<Option<SocketAddr>> {
+        self.port.rebind()
+    }
+
+    fn subscribers(&self) -> Vec<SubscriberVersion> {
+        self.subscribers
+            .iter()
+            .map(|subscriber| {
+                let address = Self::subscriber_socket_addr(subscriber);
+                SubscriberVersion {
+                    address,
+                    identifier: subscriber.identifier.str().to_string(),
+                    protocol_version: self.protocol_version(address),
+                }
+            })
+            .collect()
+    }
+
+    fn get_inputs(&mut self, limit: usize) -> Vec<(Request, RequestSource)> {
+        let mut inputs: Vec<(Request, RequestSource)> = vec![];
         inputs.append(&mut self.input_queue);
         while let Some((buf, amt, src)) = self.port.recv() {
             println!("rcv: {amt} from {src:?}");
@@ -60,15 +236,38 @@ impl CommunicationInterface for BinaryNetHandler {
                     subscriber.last_contact = Utc::now().timestamp() as u128;
                 }
             }
-            let msg: Request = match postcard::from_bytes::<Request>(&buf[..amt]) {
-                Ok(msg) => msg,
-                Err(err) => {
-                    panic!(
-                        "failed parse! {err} \n {}",
-                        std::str::from_utf8(&buf[..amt]).unwrap_or_default()
-                    );
+            if amt > 0 && buf[0] == CORE_REQUEST_BYTE {
+                let decoded = decode_postcard::<CoreRequest>(&buf[1..amt]);
+                if let Some(request) = self.known(src, "CoreRequest", decoded) {
+                    // Renewed every half second, kept out of the audit trail
+                    if request == CoreRequest::Mirror {
+                        self.mirrors.retain(|(from, _)| *from != src);
+                        self.mirrors.push((src, Instant::now()));
+                        continue;
+                    }
+                    if let CoreRequest::Hello { protocol_version } = request {
+                        self.set_protocol_version(src, protocol_version);
+                    }
+                    let source = self.source(src);
+                    self.core_input_queue.push((request, source));
                 }
+                continue;
+            }
+            let decoded = decode_postcard::<Request>(&buf[..amt]);
+            let Some(msg) = self.known(src, "Request", decoded) else {
+                continue;
             };
+            if let Request::Subscribe(_) = msg
+                && let Some(version) = self.protocol_version(src)
+                && !protocol_compatible(version)
+            {
+                self.reject(
+                    src,
+                    "Subscribe".to_string(),
+                    RejectReason::IncompatibleProtocol { version },
+                );
+                continue;
+            }
             match msg {
                 Request::Ping => {}
                 Request::Subscribe(mut info) => {
@@ -79,6 +278,10 @@ impl CommunicationInterface for BinaryNetHandler {
                             recognized_subscriber = true;
                         }
                     }
+                    // Replies go to the stated address, which need not be where the request came from
+                    if let Some(version) = self.protocol_version(src) {
+                        self.set_protocol_version(Self::subscriber_socket_addr(&info), version);
+                    }
                     if !recognized_subscriber {
                         self.subscribers.push(SubscriberInfo {
                             last_contact: Utc::now().timestamp() as u128,
@@ -86,7 +289,13 @@ impl CommunicationInterface for BinaryNetHandler {
                         });
                     }
                     self.publish_subscribers();
-                    self.input_queue.push(Request::NotifySubscribers);
+                    // Nothing has been sent yet right after boot, so ask for the full state instead
+                    if self.cache.is_empty() {
+                        let source = self.source(src);
+                        self.input_queue.push((Request::NotifySubscribers, source));
+                    } else {
+                        self.replay(&info);
+                    }
                 }
                 Request::Unsubscribe(info) => {
                     self.subscribers = self
@@ -95,11 +304,14 @@ impl CommunicationInterface for BinaryNetHandler {
                         .into_iter()
                         .filter(|sub| sub.address != info.address)
                         .collect();
+                    let address = Self::subscriber_socket_addr(&info);
+                    self.protocol_versions.retain(|(from, _)| *from != address);
                     self.publish_subscribers();
                 }
                 _ => {}
             }
-            self.input_queue.push(msg);
+            let source = self.source(src);
+            self.input_queue.push((msg, source));
             if inputs.len() + self.input_queue.len() > limit {
                 break;
             } else {
@@ -115,46 +327,48 @@ impl CommunicationInterface for BinaryNetHandler {
         }
     }
 
-    fn notify(&mut self, notification: Message) {
-        self.subscribers = self
-            .subscribers
-            .clone()
-            .into_iter()
-            .filter(|sub| {
-                Utc::now()
-                    .signed_duration_since(
-                        DateTime::from_timestamp_secs(sub.last_contact as i64).unwrap_or_default(),
-                    )
-                    .num_minutes()
-                    < 15
-            })
-            .collect();
+    fn get_core_inputs(&mut self) -> Vec<(CoreRequest, RequestSource)> {
+        std::mem::take(&mut self.core_input_queue)
+    }
 
-        let encoded_result = match notification.clone() {
-            Message::Small(message) => postcard::to_stdvec(&message),
-            Message::Large(message) => postcard::to_stdvec(&message),
+    fn notify_core(&mut self, message: &CoreMessage) {
+        let mut buffer = match postcard::to_stdvec(message) {
+            Ok(res) => res,
+            Err(_err) => return,
         };
+        buffer.insert(0, CORE_MESSAGE_BYTE);
+        for subscriber in &self.subscribers {
+            self.port
+                .send_to(&buffer, Self::subscriber_socket_addr(subscriber));
+        }
+        for target in self.targets.iter().filter(|target| target.wants_core()) {
+            self.port.send_to(&buffer, target.address);
+        }
+        if let CoreMessage::MirrorState(_) = message {
+            self.mirrors
+                .retain(|(_, renewed)| renewed.elapsed() < MIRROR_EXPIRY);
+            for (mirror, _) in &self.mirrors {
+                self.port.send_to(&buffer, *mirror);
+            }
+        }
+    }
 
-        let mut buffer = match encoded_result {
+    fn reply(&mut self, to: SocketAddr, message: CoreMessage) {
+        let mut buffer = match postcard::to_stdvec(&message) {
             Ok(res) => res,
             Err(_err) => return,
         };
+        buffer.insert(0, CORE_MESSAGE_BYTE);
+        self.port.send_to(&buffer, to);
+    }
 
-        // insert a message size byte at the start, which tells the client if this is a small or
-        // large message, since otherwise they can happen to look like the other size, and be
-        // parsed incorrectly
-        //
-        // The LSB of the size byte is enough to tell: 1 is small, 0 is large, but we have some
-        // extra redundancy to a) make sure that it is actually a size byte and not a random bit in
-        // some misplaced message, and b) to identify the size byte in both flipped and non-flipped
-        // ordering
-        buffer.insert(
-            0,
-            match notification {
-                Message::Small(..) => 0xE1,
-                Message::Large(..) => 0xD2,
-            },
-        );
+    fn notify(&mut self, notification: Message) {
+        self.expire_subscribers();
+
+        self.cache.store(&notification);
+        let Some(buffer) = Self::encode(&notification) else {
+            return;
+        };
 
         //println!(
         //    "sent Message: {:?}\n {}\n({} bytes)\n",
@@ -165,19 +379,16 @@ impl CommunicationInterface for BinaryNetHandler {
 
         for subscriber in &self.subscribers {
             if subscriber.message_kinds.contains(notification.to_type()) {
-                self.port.send_to(
-                    &buffer,
-                    SocketAddr::new(
-                        IpAddr::V4(Ipv4Addr::new(
-                            subscriber.address.addr[0],
-                            subscriber.address.addr[1],
-                            subscriber.address.addr[2],
-                            subscriber.address.addr[3],
-                        )),
-                        subscriber.address.port,
-                    ),
-                );
+                self.port
+                    .send_to(&buffer, Self::subscriber_socket_addr(subscriber));
             }
         }
+        for target in self
+            .targets
+            .iter()
+            .filter(|target| target.wants(&notification))
+        {
+            self.port.send_to(&buffer, target.address);
+        }
     }
 }
@@ -0,0 +1,164 @@
+//! Decoding of requests from clients that may speak a newer protocol version.
+//!
+//! Fields added at the end of a request are ignored: postcard leaves trailing bytes unread and
+//! serde_json skips unknown keys. A request variant this build does not know is decoded as
+//! `Decoded::Unsupported`, so it can be refused with a reply instead of being taken for a
+//! garbled packet, and old cores and new clients can run side by side during an upgrade.
+use serde::de::{self, DeserializeOwned, Deserializer, Visitor};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Decoded<T> {
+    Known(T),
+    /// A variant of a newer protocol version, by name on JSON or by index on postcard
+    Unsupported {
+        variant: String,
+    },
+    /// Not a request at all, with the decoding error
+    Unreadable(String),
+}
+
+/// Decodes a postcard encoded request.
+pub fn decode_postcard<T: DeserializeOwned>(buf: &[u8]) -> Decoded<T> {
+    let err = match postcard::from_bytes::<T>(buf) {
+        Ok(request) => return Decoded::Known(request),
+        Err(err) => err,
+    };
+    // The variant index comes first, as a varint
+    match variant_index(buf) {
+        Some(idx) if idx as usize >= variant_names::<T>().len() => Decoded::Unsupported {
+            variant: idx.to_string(),
+        },
+        _ => Decoded::Unreadable(err.to_string()),
+    }
+}
+
+/// Decodes a JSON encoded request.
+pub fn decode_json<T: DeserializeOwned>(text: &str) -> Decoded<T> {
+    let err = match serde_json::from_str::<T>(text) {
+        Ok(request) => return Decoded::Known(request),
+        Err(err) => err,
+    };
+    // Externally tagged: a unit variant is its name, any other an object keyed by its name
+    let name = match serde_json::from_str::<serde_json::Value>(text) {
+        Ok(serde_json::Value::String(name)) => Some(name),
+        Ok(serde_json::Value::Object(object)) if object.len() == 1 => object.keys().next().cloned(),
+        _ => None,
+    };
+    match name {
+        Some(name) if !variant_names::<T>().contains(&name.as_str()) => {
+            Decoded::Unsupported { variant: name }
+        }
+        _ => Decoded::Unreadable(err.to_string()),
+    }
+}
+
+fn variant_index(buf: &[u8]) -> Option<u32> {
+    let mut idx = 0u32;
+    for (byte_idx, byte) in buf.iter().take(5).enumerate() {
+        idx |= ((byte & 0x7F) as u32) << (7 * byte_idx);
+        if byte & 0x80 == 0 {
+            return Some(idx);
+        }
+    }
+    None
+}
+
+/// Names of the variants of enum `T`, empty if `T` is not an enum.
+fn variant_names<T: DeserializeOwned>() -> &'static [&'static str] {
+    let mut probe = VariantProbe::default();
+    let _ = T::deserialize(&mut probe);
+    probe.variants
+}
+
+/// Deserializer that fails on everything, noting the variants of the enum it is asked for.
+#[derive(Default)]
+struct VariantProbe {
+    variants: &'static [&'static str],
+}
+
+impl<'de> Deserializer<'de> for &mut VariantProbe {
+    type Error = de::value::Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, _visitor: V) -> Result<V::Value, Self::Error> {
+        Err(de::Error::custom("not an enum"))
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        variants: &'static [&'static str],
+        _visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        self.variants = variants;
+        Err(de::Error::custom("variants noted"))
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string bytes byte_buf
+        option unit unit_struct newtype_struct seq tuple tuple_struct map struct identifier
+        ignored_any
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    enum Old {
+        Ping,
+        Seek { beat: u16 },
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    enum New {
+        Ping,
+        Seek { beat: u16, bar: u16 },
+        Rewind,
+    }
+
+    #[test]
+    fn newer_requests() {
+        let seek = New::Seek { beat: 4, bar: 2 };
+        let buf = postcard::to_stdvec(&seek).unwrap();
+        assert_eq!(
+            decode_postcard::<Old>(&buf),
+            Decoded::Known(Old::Seek { beat: 4 })
+        );
+        let text = serde_json::to_string(&seek).unwrap();
+        assert_eq!(
+            decode_json::<Old>(&text),
+            Decoded::Known(Old::Seek { beat: 4 })
+        );
+
+        let buf = postcard::to_stdvec(&New::Rewind).unwrap();
+        assert_eq!(
+            decode_postcard::<Old>(&buf),
+            Decoded::Unsupported {
+                variant: "2".to_string()
+            }
+        );
+        let text = serde_json::to_string(&New::Rewind).unwrap();
+        assert_eq!(
+            decode_json::<Old>(&text),
+            Decoded::Unsupported {
+                variant: "Rewind".to_string()
+            }
+        );
+    }
+
+    #[test]
+    fn garbled_requests() {
+        // A known variant cut short
+        assert!(matches!(
+            decode_postcard::<Old>(&[1]),
+            Decoded::Unreadable(_)
+        ));
+        assert!(matches!(
+            decode_json::<Old>("{\"Seek\": 4}"),
+            Decoded::Unreadable(_)
+        ));
+        assert!(matches!(decode_json::<Old>("[]"), Decoded::Unreadable(_)));
+    }
+}
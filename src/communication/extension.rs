@@ -0,0 +1,656 @@
+//! Requests and messages that clicks-core understands on top of the clicks-common protocol.
+//!
+//! On the binary protocol, a `CoreRequest` is sent as a `CORE_REQUEST_BYTE` followed by the
+//! postcard encoded request. A common `Request` can never start with this byte, since postcard
+//! encodes its variant index as a single byte varint. Replies are sent back to the requesting
+//! address only, as a `CORE_MESSAGE_BYTE` followed by the postcard encoded `CoreMessage`,
+//! in the same way as the 0xE1/0xD2 size bytes of common messages. Some core messages, like
+//! `SystemFault`, are instead broadcast to all subscribers.
+use crate::{
+    audio::{
+        accent::Accent,
+        countdown::{Countdown, UpcomingEvent},
+        delay::DelayAmount,
+        eq::{EqBand, EqConfiguration},
+        follower::TempoFollowConfiguration,
+        handler::{InputStatus, RouteMask},
+        metronome::{ClickLevels, ClickSamples, ClickSynthesis},
+        playback::SeekOutcome,
+        timecode::{TimecodeOutputConfiguration, TimecodeTime},
+        watchdog::RoutingRepair,
+    },
+    audit::AuditEntry,
+    cluster::ClusterStatus,
+    communication::{
+        interface::SubscriberVersion,
+        link::LinkStatus,
+        midi::{MidiAction, MidiControl, MidiMapping},
+        schema::SchemaChunk,
+        throttle::NotificationRates,
+    },
+    config::OutputConfiguration,
+    failover::{FailoverRole, MirrorState},
+    macros::MacroStep,
+    manifest::ManifestReport,
+    metrics::{MetricsReport, ResourceAlarm},
+    power::{Passphrase, PowerAction},
+    runlog::RunEntry,
+    show_dump::ShowDumpChunk,
+    show_extension::{
+        AlternateClips, ClipChains, CueColor, CueFlag, CueMarkers, EndOfCue, FadeEvent, FadeEvents,
+        GainAutomation, GainPoint, MuteEvent, MuteEvents, ZeroTarget,
+    },
+    timing::TimingReportFormat,
+    validation::RejectReason,
+};
+use common::cue::Show;
+use common::local::config::{LogContext, LogItem, LogKind};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+pub const CORE_REQUEST_BYTE: u8 = 0xC3;
+pub const CORE_MESSAGE_BYTE: u8 = 0xB4;
+
+/// Version of the binary protocol spoken by this build, the common requests and messages and the
+/// core extension together. Raised on every change that older clients cannot read.
+pub const PROTOCOL_VERSION: u16 = 1;
+/// Oldest protocol version of clients that are still understood.
+pub const MIN_PROTOCOL_VERSION: u16 = 1;
+
+/// Whether a client speaking protocol `version` can be served.
+pub fn protocol_compatible(version: u16) -> bool {
+    (MIN_PROTOCOL_VERSION..=PROTOCOL_VERSION).contains(&version)
+}
+
+/// Maximum number of log items in a single `CoreMessage::LogTail`, to stay well below the
+/// datagram size limit.
+pub const MAX_LOG_TAIL: usize = 100;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum CoreRequest {
+    /// Get the last `count` (max `MAX_LOG_TAIL`) log items matching any of the given kinds and
+    /// contexts. New log items are pushed as `LargeMessage::Log` to subscribers of log messages.
+    LogTail {
+        count: u16,
+        kind: LogKind,
+        context: LogContext,
+    },
+    /// Get the last `count` (max `MAX_LOG_TAIL`) entries of the request audit trail.
+    AuditTail { count: u16 },
+    /// Set one band of the EQ of a client output port. Stored in the core configuration.
+    SetOutputEqBand {
+        output: u8,
+        band: u8,
+        settings: EqBand,
+    },
+    /// Set the alignment delay of a client output port. Stored in the core configuration.
+    SetOutputDelay { output: u8, delay: DelayAmount },
+    /// Rename a client output port, e.g. when a feed is repatched. Stored in the core
+    /// configuration.
+    SetOutputLabel { output: u8, label: String },
+    /// Invert the polarity of a client output port. Stored in the core configuration.
+    SetOutputPolarity { output: u8, invert: bool },
+    /// Mix client input port `input` into client output port `output` at `gain_db`, or stop
+    /// mixing it in if `gain_db` is `None`, at most `MAX_PASS_THROUGH_GAIN_DB`. Stored in the
+    /// core configuration.
+    SetPassThrough {
+        input: u8,
+        output: u8,
+        gain_db: Option<f32>,
+    },
+    /// Sum output `output` into client output port `port` at `gain_db`, or stop summing it into
+    /// that port if `gain_db` is `None`. An output summed into no port plays on its own port.
+    /// Stored in the core configuration.
+    SetOutputMix {
+        output: u8,
+        port: u8,
+        gain_db: Option<f32>,
+    },
+    /// Connect or disconnect system capture port `from` to client input port `to`.
+    ChangeInputRouting { from: u8, to: u8, connect: bool },
+    /// Get the capture input ports and their routing, replied as `CoreMessage::Inputs`.
+    GetInputs,
+    /// Configure following the tempo of a capture input. Stored in the core configuration.
+    SetTempoFollow(TempoFollowConfiguration),
+    /// Get the tempo follow configuration, replied as `CoreMessage::TempoFollow`.
+    GetTempoFollow,
+    /// Set the level, edge smoothing and polarity of the LTC output. Stored in the core
+    /// configuration.
+    SetTimecodeOutput(TimecodeOutputConfiguration),
+    /// Get the LTC output configuration, replied as `CoreMessage::TimecodeOutput`.
+    GetTimecodeOutput,
+    /// Set the time over which channel gain changes are ramped, 0 for instant changes. Stored
+    /// in the core configuration.
+    SetGainRamp { ramp_ms: f32 },
+    /// Set the fade-out of all sources when the transport is stopped, 0 to cut at once. Stored
+    /// in the core configuration.
+    SetStopFade { fade_ms: f32 },
+    /// Set the name of this unit. Stored in the core configuration.
+    SetDeviceName { name: String },
+    /// Set the rate of `CoreMessage::BeatPhase` in messages per second, 0 to turn it off.
+    /// Stored in the core configuration.
+    SetBeatPhaseRate(u8),
+    /// Set the rate limits of continuously updated notifications. Stored in the core
+    /// configuration.
+    SetNotificationRates(NotificationRates),
+    /// Get the notification rate limits, replied as `CoreMessage::NotificationRates`.
+    GetNotificationRates,
+    /// Arm a cue: its media is preloaded and it is announced to all subscribers, but the current
+    /// cue keeps playing until `Go`.
+    Standby { cue: u8 },
+    /// Disarm the cue in standby.
+    CancelStandby,
+    /// Load the cue in standby and start playing it from the top.
+    Go,
+    /// Turn jump mode on or off, or switch it over with `None`, e.g. from a conductor's pedal.
+    /// Vamps waiting for jump mode are taken while it is on. The new mode is sent to all
+    /// subscribers in `TransportData`.
+    SetJumpMode { on: Option<bool> },
+    /// Set what happens when the transport runs past the end of a cue. Stored in the show
+    /// extension file.
+    SetCueEnd { cue: u8, end: EndOfCue },
+    /// Set where `TransportZero` goes. With a cue, the target is stored in the show extension
+    /// file for that cue only, and `None` returns the cue to the default. Without a cue, the
+    /// default is set in the core configuration, and `None` means the top of the cue.
+    SetZeroTarget {
+        cue: Option<u8>,
+        target: Option<ZeroTarget>,
+    },
+    /// Set the rehearsal markers of a cue, as beat indices. Stored in the show extension file.
+    SetCueMarkers { cue: u8, markers: Vec<u16> },
+    /// Get the processing settings of all outputs, replied as `CoreMessage::Outputs`.
+    GetOutputs,
+    /// Get the last `count` (max `MAX_LOG_TAIL`) entries of the run log of the current
+    /// performance, replied as `CoreMessage::RunLog`.
+    RunLog { count: u16 },
+    /// Close the run log of the current performance and start a new one, e.g. before the doors
+    /// open.
+    NewPerformance,
+    /// Write the beat timing report of the loaded show into the show directory, replied as
+    /// `CoreMessage::TimingReportExported`.
+    ExportTimingReport(TimingReportFormat),
+    /// Get a description of all requests and messages of the running version, as JSON, replied
+    /// as one `CoreMessage::ProtocolSchema` per chunk. See `communication::schema`.
+    ExportProtocolSchema,
+    /// Keep the loaded show after the show file has changed on disk. Reloading is done with
+    /// `Request::Initialize`.
+    DismissShowChange,
+    /// Store the current gains, mutes and output routing as a named scene, replacing any scene
+    /// of that name. Stored in the core configuration.
+    StoreScene { name: String },
+    /// Recall a stored scene, fading the gains to it over `fade_ms`.
+    RecallScene { name: String, fade_ms: f32 },
+    /// Delete a stored scene. Stored in the core configuration.
+    DeleteScene { name: String },
+    /// Get the names of the stored scenes, replied as `CoreMessage::Scenes`.
+    GetScenes,
+    /// Set the scene recalled when a cue is loaded, `None` for none. Stored in the show
+    /// extension file.
+    SetCueScene { cue: u8, scene: Option<String> },
+    /// State the protocol version the client speaks, before `Request::Subscribe`. Replied as
+    /// `CoreMessage::Hello`, or rejected with `RejectReason::IncompatibleProtocol`, in which case
+    /// subscriptions from the client are refused. Clients that never send it are served as
+    /// before, with an unknown version.
+    Hello { protocol_version: u16 },
+    /// Sent by a backup unit or cluster follower every `failover::MIRROR_INTERVAL` to receive
+    /// `CoreMessage::MirrorState` from this unit. Handled by the binary protocol handler.
+    Mirror,
+    /// Make a backup unit take over from its primary: stop mirroring it and unmute the outputs.
+    TakeOver,
+    /// Make a backup unit that took over hand control back to its primary: mute the outputs and
+    /// mirror the primary again once it is heard.
+    HandBack,
+    /// Get the failover role of this unit, replied as `CoreMessage::FailoverRole`.
+    GetFailoverRole,
+    /// Map the next control moved on a MIDI device to `action`, or stop waiting for one with
+    /// `None`. Sent on to all subscribers and the MIDI handler as `CoreMessage::MidiLearn`.
+    LearnMidi(Option<MidiAction>),
+    /// Map a MIDI control to an action, replacing what it did before, or unmap it with `None`.
+    /// Stored in the core configuration. Sent by the MIDI handler for a learnt control.
+    MapMidi {
+        control: MidiControl,
+        action: Option<MidiAction>,
+    },
+    /// Get the MIDI mappings, replied as `CoreMessage::MidiMappings`.
+    GetMidiMappings,
+    /// Run a macro: request its steps one after another, each after its delay. See
+    /// `macros::MacroRunner`.
+    RunMacro { name: String },
+    /// Stop requesting the remaining steps of a running macro.
+    StopMacro { name: String },
+    /// Define a macro, replacing any macro of that name, or delete it with `None`. Stored in the
+    /// core configuration.
+    SetMacro {
+        name: String,
+        steps: Option<Vec<MacroStep>>,
+    },
+    /// Get all macros, replied as `CoreMessage::Macros`.
+    GetMacros,
+    /// Fade out all playback over `fade_ms`, `stop_fade_ms` if not given, while the transport,
+    /// click and timecode carry on. Playback stays silent until the transport is stopped, zeroed
+    /// or seeks, so later playback events of the cue do not bring it back.
+    StopAllPlayback { fade_ms: Option<f32> },
+    /// Like `StopAllPlayback`, for a single channel, e.g. to pull a misfiring stem without
+    /// stopping the show
+    StopPlaybackChannel { channel: u8, fade_ms: Option<f32> },
+    /// Jam the LTC output to a time from the next frame on, e.g. to match the house clock again
+    /// after an interruption, without moving the beat position. The next timecode event of the
+    /// cue sets the time as usual.
+    JamTimecode(TimecodeTime),
+    /// Set the levels of the accented and the other clicks. With a cue, the levels are stored
+    /// in the show extension file for that cue only, and `None` returns the cue to the default.
+    /// Without a cue, the default is set in the core configuration, and `None` resets it.
+    SetClickLevels {
+        cue: Option<u8>,
+        levels: Option<ClickLevels>,
+    },
+    /// Get the cues of the loaded show, replied as `CoreMessage::Cues`.
+    GetCues,
+    /// Get the complete show as it is held in memory, replied as one `CoreMessage::ShowDump`
+    /// per chunk, so editing clients open what the unit is running rather than a copy of the
+    /// file.
+    GetShow,
+    /// Stop everything at once, as the panic button of the front panel does: stop the
+    /// transport, cut all playback and mute the timecode output. The timecode output stays
+    /// muted until the transport is started again, and the mute is not stored in the core
+    /// configuration.
+    AllStop,
+    /// Switch profiling of the process callback on or off. Switching it on starts a new profile.
+    /// See `audio::profile`.
+    SetProfiling { on: bool },
+    /// Write the profile recorded since profiling was switched on into the log directory,
+    /// replied as `CoreMessage::ProfileDumped`.
+    DumpProfile,
+    /// Set the color clients draw a cue in, `None` for none. Stored in the show extension file.
+    SetCueColor { cue: u8, color: Option<CueColor> },
+    /// Set the flags of a cue, e.g. `Safety` for flying cues. Stored in the show extension file.
+    SetCueFlags { cue: u8, flags: Vec<CueFlag> },
+    /// Let the current bar finish, then stop the transport, and with it playback, click and
+    /// timecode, as `TransportStop` does, for clean stops in rehearsal. With a stop fade, the
+    /// fade ends on the bar line. Dropped if the transport is stopped or started before then.
+    TransportStopQuantized,
+    /// Load a cue, the next one if `None`, on the next beat or bar line of the running cue and
+    /// carry on playing it from the top, so a transition triggered slightly early still lands
+    /// on the music. Replaces any launch already armed, and is dropped if the transport stops
+    /// first. With the transport stopped, the cue is loaded right away.
+    LaunchCue { cue: Option<u8>, at: LaunchBoundary },
+    /// Set the gain automation of a playback channel in a cue, no points to remove it. Stored
+    /// in the show extension file.
+    SetGainAutomation {
+        cue: u8,
+        channel: u16,
+        points: Vec<GainPoint>,
+    },
+    /// Start the core process over after the same clean shutdown as `Request::Shutdown`, to
+    /// recover a unit out of reach. Only arms the restart, replied as
+    /// `CoreMessage::ConfirmPower`. Needs the `power_passphrase` of the core configuration, if
+    /// one is set.
+    Restart { passphrase: Option<Passphrase> },
+    /// Reboot the unit after a clean shutdown. Armed and confirmed like `Restart`.
+    Reboot { passphrase: Option<Passphrase> },
+    /// Carry out the restart or reboot armed by the same client, with the code it was replied.
+    /// See `power::CONFIRM_TIMEOUT`.
+    ConfirmPower { code: u32 },
+    /// Set how the clicks are synthesized. The clicks are generated off the audio thread and
+    /// played from the next beat on. Stored in the core configuration.
+    SetClickSynthesis(ClickSynthesis),
+    /// Get how the clicks are synthesized, replied as `CoreMessage::ClickSynthesis`.
+    GetClickSynthesis,
+    /// Set the mute events of a cue, replacing those it had. Stored in the show extension
+    /// file.
+    SetMuteEvents { cue: u8, events: Vec<MuteEvent> },
+    /// Seek the current cue to the beat sending the latest timecode at or before a time, e.g.
+    /// for a MIDI Machine Control locate from a lighting desk.
+    LocateTimecode(TimecodeTime),
+    /// Set the fade events of a cue, replacing those it had. Stored in the show extension
+    /// file.
+    SetFadeEvents { cue: u8, events: Vec<FadeEvent> },
+    /// Store the current output routing as a named routing preset, replacing any preset of
+    /// that name. Stored in the core configuration.
+    StoreRoutingPreset { name: String },
+    /// Route the outputs as in a stored routing preset. Kept as the routing to put back at the
+    /// next start while audio is not running.
+    LoadRoutingPreset { name: String },
+    /// Delete a stored routing preset. Stored in the core configuration.
+    DeleteRoutingPreset { name: String },
+    /// Get the names of the stored routing presets, replied as `CoreMessage::RoutingPresets`.
+    GetRoutingPresets,
+    /// Get the shows of the show directory, replied as `CoreMessage::Shows`.
+    ListShows,
+    /// Switch to another show of the show directory, loading its first cue. The audio client
+    /// carries on running, and the transport has to be stopped.
+    LoadShowByName { name: String },
+    /// Set WAV files of the show directory played instead of the synthesized clicks, read off
+    /// the audio thread and played from the next beat on. With a cue, the samples are stored in
+    /// the show extension file for that cue only, and `None` returns the cue to the default.
+    /// Without a cue, the default is set in the core configuration, and `None` resets it.
+    SetClickSamples {
+        cue: Option<u8>,
+        samples: Option<ClickSamples>,
+    },
+    /// Get the default click samples, replied as `CoreMessage::ClickSamples`.
+    GetClickSamples,
+    /// Set how the clicks of a cue are synthesized, instead of the configured synthesis, or
+    /// `None` to return the cue to it. Stored in the show extension file.
+    SetCueClickSynthesis {
+        cue: u8,
+        synthesis: Option<ClickSynthesis>,
+    },
+    /// Click `bars` bars at the tempo and in the time signature of the beat the transport
+    /// starts from, then start it, as `TransportStart` does. Playback and timecode hold at
+    /// their start positions during the count-in. Dropped if the transport is started, stopped
+    /// or another cue is loaded before then. See `count_in::MAX_COUNT_IN_BARS`.
+    TransportStartWithCountIn { bars: u8 },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum CoreMessage {
+    /// Log items, oldest first
+    LogTail(Vec<LogItem>),
+    /// Audit trail entries, oldest first
+    AuditTail(Vec<AuditEntry>),
+    /// A thread of the core has panicked. Broadcast to all subscribers.
+    SystemFault {
+        thread: String,
+        message: String,
+        location: String,
+    },
+    /// Performance figures of the last second. Broadcast to all subscribers next to the
+    /// heartbeat.
+    Metrics(MetricsReport),
+    /// Labels and processing settings of all outputs, by index. Broadcast to all subscribers
+    /// when changed and next to `LargeMessage::JACKStateChanged`.
+    Outputs(Vec<OutputConfiguration>),
+    /// Capture input ports and their routing. Broadcast to all subscribers next to
+    /// `LargeMessage::JACKStateChanged`.
+    Inputs(InputStatus),
+    /// Per client output, the system ports connected to it. Broadcast to all subscribers next to
+    /// `LargeMessage::JACKStateChanged`, whose connections only reach 32 outputs and ports.
+    Routing(Vec<RouteMask>),
+    /// Tempo follow configuration. Broadcast to all subscribers when changed.
+    TempoFollow(TempoFollowConfiguration),
+    /// LTC output configuration. Broadcast to all subscribers when changed.
+    TimecodeOutput(TimecodeOutputConfiguration),
+    /// The cue in standby, if any. Broadcast to all subscribers when a cue is armed or disarmed.
+    Standby(Option<u8>),
+    /// The transport ran past the last beat of a cue. Broadcast to all subscribers.
+    CueEnded { cue: u8 },
+    /// The cue in standby has been started. Broadcast to all subscribers.
+    Go { cue: u8 },
+    /// A cue armed with `CoreRequest::LaunchCue` has been loaded on its boundary and is playing.
+    /// Broadcast to all subscribers.
+    CueLaunched { cue: u8 },
+    /// Notification rate limits. Broadcast to all subscribers when changed.
+    NotificationRates(NotificationRates),
+    /// Position within the current beat, 0.0 at the click, for animating progress between
+    /// beats. Broadcast to all subscribers at the configured rate while the transport runs.
+    BeatPhase { beat_idx: u16, phase: f32 },
+    /// Time left in the current cue and until its next event. Broadcast to all subscribers a few
+    /// times per second.
+    Countdown(Countdown),
+    /// The next events of the current cue after `beat`, for musicians' screens to warn of vamps
+    /// and jumps ahead. Broadcast to all subscribers whenever the cue or beat changes.
+    UpcomingEvents {
+        cue: u8,
+        beat: u16,
+        events: Vec<UpcomingEvent>,
+    },
+    /// Accents of the bar the current beat is in, derived from the time signature, and the
+    /// position of the beat in it, for visual clicks. Broadcast along with `UpcomingEvents`.
+    BarAccents { position: u8, accents: Vec<Accent> },
+    /// Run log entries of the current performance, oldest first
+    RunLog(Vec<RunEntry>),
+    /// Path of the written timing report
+    TimingReportExported { path: String },
+    /// A chunk of the protocol schema, in reply to `CoreRequest::ExportProtocolSchema`
+    ProtocolSchema(SchemaChunk),
+    /// A playback clip could not be read from the show directory, and plays as silence.
+    /// Broadcast to all subscribers.
+    MediaUnavailable {
+        channel: u16,
+        clip: u16,
+        error: String,
+    },
+    /// Whether the show file has changed on disk since the show was loaded. Broadcast to all
+    /// subscribers when a change is noticed, and again when the show is reloaded or the change
+    /// dismissed.
+    ShowFileChanged(bool),
+    /// Names of the stored scenes. Broadcast to all subscribers when changed.
+    Scenes(Vec<String>),
+    /// A scene has been recalled, on request or by loading a cue. Broadcast to all subscribers.
+    SceneRecalled { name: String },
+    /// A request was not acted upon because it does not fit the current state, e.g. it refers to
+    /// a cue or channel that does not exist.
+    RequestRejected {
+        request: String,
+        reason: RejectReason,
+    },
+    /// A request was accepted but could not be carried out, e.g. JACK refused a connection or a
+    /// file could not be written.
+    RequestFailed { request: String, reason: String },
+    /// The protocol version of the core, in reply to `CoreRequest::Hello`
+    Hello {
+        protocol_version: u16,
+        core_version: String,
+        device_name: String,
+    },
+    /// Name of this unit. Broadcast to all subscribers next to the heartbeat and when changed.
+    Identity { name: String },
+    /// Cue and transport state, for backup units and cluster followers. Broadcast to all
+    /// subscribers and mirrors when it changes and every `failover::MIRROR_STATE_INTERVAL`.
+    MirrorState(MirrorState),
+    /// What this unit does in a primary/backup pair. Broadcast to all subscribers when it
+    /// changes.
+    FailoverRole(FailoverRole),
+    /// State of this unit as a cluster follower. Broadcast to all subscribers every second.
+    ClusterStatus(ClusterStatus),
+    /// The action the next control moved on a MIDI device is mapped to, if any. Broadcast to all
+    /// subscribers when learning starts and ends.
+    MidiLearn(Option<MidiAction>),
+    /// All MIDI mappings. Broadcast to all subscribers when changed.
+    MidiMappings(Vec<MidiMapping>),
+    /// Binary protocol subscribers and the protocol version they stated. Broadcast to all
+    /// subscribers next to `LargeMessage::NetworkChanged`.
+    Subscribers(Vec<SubscriberVersion>),
+    /// All macros by name. Broadcast to all subscribers when changed.
+    Macros(BTreeMap<String, Vec<MacroStep>>),
+    /// The files of the loaded show have been checked against its manifest, after it was
+    /// loaded. Broadcast to all subscribers; not sent for shows without a manifest.
+    ShowVerified(ManifestReport),
+    /// Resources running out, see `metrics::ResourceLimits`. Broadcast to all subscribers when
+    /// an alarm is raised or cleared.
+    ResourceAlarms(Vec<ResourceAlarm>),
+    /// The network interface has gone down, come back or changed address. Broadcast to all
+    /// subscribers once the handlers have moved to the new address.
+    Link(LinkStatus),
+    /// The cues of the loaded show, in reply to `CoreRequest::GetCues`.
+    Cues(Vec<CueSummary>),
+    /// A chunk of the loaded show, in reply to `CoreRequest::GetShow`. See `show_dump::assemble`.
+    ShowDump(ShowDumpChunk),
+    /// Path of the written process callback profile
+    ProfileDumped { path: String },
+    /// Color and flags of the current cue, so high-risk cues stand out on every surface.
+    /// Broadcast to all subscribers along with every cue change, and when they are set.
+    CueMarking {
+        cue: u8,
+        color: Option<CueColor>,
+        flags: Vec<CueFlag>,
+    },
+    /// A restart or reboot is armed, in reply to `CoreRequest::Restart` or
+    /// `CoreRequest::Reboot`. Send `code` back with `CoreRequest::ConfirmPower` to carry it out.
+    ConfirmPower { action: PowerAction, code: u32 },
+    /// How the clicks are synthesized. Broadcast to all subscribers when changed.
+    ClickSynthesis(ClickSynthesis),
+    /// A mute event of the current cue, or `AllStop` until the next start, muted or unmuted a
+    /// channel. Broadcast to all subscribers. The mute is not stored in the core configuration,
+    /// so `Outputs` keeps the mute set by hand.
+    ChannelMuted { channel: u8, muted: bool },
+    /// Connections the core set up that another JACK client, or one going away, has changed,
+    /// and whether they were put back. Broadcast to all subscribers, followed by the new
+    /// routing.
+    RoutingDrift(Vec<RoutingRepair>),
+    /// Names of the stored routing presets. Broadcast to all subscribers when changed.
+    RoutingPresets(Vec<String>),
+    /// A seek or jump landed a playback channel outside the loaded data of the clip playing at
+    /// the beat, at `sample` of it. Broadcast to all subscribers.
+    SeekResult {
+        channel: u16,
+        beat: u16,
+        clip: u16,
+        sample: i32,
+        outcome: SeekOutcome,
+    },
+    /// Shows of the show directory, and the one loaded. Broadcast to all subscribers when
+    /// another show is loaded.
+    Shows { loaded: String, shows: Vec<String> },
+    /// Default click samples. Broadcast to all subscribers when changed.
+    ClickSamples(ClickSamples),
+    /// Count-in beats left before the transport starts, 0 when the count-in is over or
+    /// dropped. Broadcast to all subscribers on every count-in beat.
+    CountIn { beats_left: u16 },
+}
+
+/// Where a cue armed with `CoreRequest::LaunchCue` takes over from the running one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum LaunchBoundary {
+    /// The next beat
+    Beat,
+    /// The next downbeat
+    Bar,
+}
+
+impl LaunchBoundary {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "beat" => Some(Self::Beat),
+            "bar" => Some(Self::Bar),
+            _ => None,
+        }
+    }
+}
+
+/// What a cue select page needs to know of a cue, without its beats and events.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CueSummary {
+    pub index: u8,
+    /// Human readable ident, e.g. "12A"
+    pub ident: String,
+    pub name: String,
+    /// Number of beats
+    pub length: u16,
+}
+
+impl CueSummary {
+    /// Summaries of all cues of a show, as far as a `u8` index reaches.
+    pub fn of_show(show: &Show) -> Vec<Self> {
+        show.cues
+            .iter()
+            .zip(0..=u8::MAX)
+            .map(|(cue, index)| Self {
+                index,
+                ident: cue.metadata.human_ident.str().to_string(),
+                name: cue.metadata.name.str().to_string(),
+                length: cue.get_beats().len().min(u16::MAX as usize) as u16,
+            })
+            .collect()
+    }
+}
+
+/// Commands to the audio processor that have no `ControlAction` in the common protocol.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CoreControlAction {
+    SetOutputEq {
+        output: u8,
+        eq: EqConfiguration,
+    },
+    SetOutputDelay {
+        output: u8,
+        delay_ms: f32,
+    },
+    SetOutputPolarity {
+        output: u8,
+        invert: bool,
+    },
+    SetGainRamp {
+        ramp_ms: f32,
+    },
+    SetStopFade {
+        fade_ms: f32,
+    },
+    /// Move the gain of a channel to `gain` dB over `fade_ms`
+    FadeChannelGain {
+        channel: u8,
+        gain: f32,
+        fade_ms: f32,
+    },
+    SetPassThrough {
+        input: u8,
+        output: u8,
+        gain_db: Option<f32>,
+    },
+    SetOutputMix {
+        output: u8,
+        port: u8,
+        gain_db: Option<f32>,
+    },
+    SetTempoFollow(TempoFollowConfiguration),
+    SetBeatPhaseRate(u8),
+    SetTimecodeOutput(TimecodeOutputConfiguration),
+    /// LTC frame rate of the current cue
+    SetTimecodeFrameRate(u8),
+    SetCueEnd(EndOfCue),
+    SetZeroTarget {
+        target: ZeroTarget,
+        markers: CueMarkers,
+    },
+    /// `TransportZero` to the configured target
+    ZeroToTarget,
+    /// Clips of the current cue played back to back
+    SetClipChains(ClipChains),
+    /// Gain automation of the playback channels in the current cue
+    SetGainAutomation(GainAutomation),
+    /// Clips of the current cue with alternates to pick from
+    SetClipAlternates(AlternateClips),
+    /// Mute events of the current cue
+    SetMuteEvents(MuteEvents),
+    /// Jump mode on or off, or switched over with `None`
+    SetJumpMode(Option<bool>),
+    /// Silence all outputs, on a backup unit mirroring its primary
+    MuteOutputs(bool),
+    /// Render only `count` channels from `first`, the share of a cluster follower, or all with
+    /// `None`
+    RenderChannels(Option<(u8, u8)>),
+    /// Fade out all playback channels over `fade_ms` and keep them silent, while the transport,
+    /// click and timecode carry on
+    StopAllPlayback {
+        fade_ms: f32,
+    },
+    /// `StopAllPlayback` for the source at `channel` only
+    StopPlaybackChannel {
+        channel: u8,
+        fade_ms: f32,
+    },
+    JamTimecode(TimecodeTime),
+    /// Click levels of the current cue
+    SetClickLevels(ClickLevels),
+    /// `TransportStop` when the current bar ends
+    StopAtEndOfBar,
+    /// Load a cue of the show on the next boundary of the running cue, and carry on from its top
+    LaunchCue {
+        cue: u8,
+        at: LaunchBoundary,
+    },
+    /// Fade events of the current cue
+    SetFadeEvents(FadeEvents),
+    /// Start a fade on the playback channel of the event
+    FadePlayback(FadeEvent),
+    /// Start the transport after a count-in of `bars` bars
+    StartWithCountIn {
+        bars: u8,
+    },
+    /// Click a count-in beat while the transport holds
+    ClickCountIn(Accent),
+    /// Mute the source at `channel` until the transport next starts, when it gets back the mute
+    /// it had
+    MuteUntilStart(u8),
+}
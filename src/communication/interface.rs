@@ -1,17 +1,134 @@
+use crate::communication::extension::{CoreMessage, CoreRequest};
 use common::protocol::{message::Message, request::Request};
+use serde::{Deserialize, Deserializer, Serialize};
+use std::net::SocketAddr;
+
+/// Names of everything requests come from: the handlers, see `CommunicationInterface::name`,
+/// and the sources within the core.
+const HANDLER_NAMES: [&str; 9] = [
+    "binnet", "osc", "jsonnet", "web", "midi", "panel", "macro", "failover", "script",
+];
+
+/// A handler name as deserialized, "unknown" for one this build does not have.
+pub fn handler_name(name: &str) -> &'static str {
+    HANDLER_NAMES
+        .into_iter()
+        .find(|known| *known == name)
+        .unwrap_or("unknown")
+}
+
+/// Where a request came from.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RequestSource {
+    /// Name of the communication handler the request was received by
+    pub handler: &'static str,
+    /// Place of that handler in the `HandlerRegistry`, for replies to find it. None for requests
+    /// made by the core itself, e.g. by a script or the front panel.
+    #[serde(skip)]
+    pub handler_index: Option<usize>,
+    pub address: SocketAddr,
+    /// Client supplied identifier, if the client is a known subscriber
+    pub identifier: Option<String>,
+}
+
+/// `RequestSource` as deserialized, with a handler name of its own.
+#[derive(Deserialize)]
+#[serde(rename = "RequestSource")]
+struct ReceivedSource {
+    handler: String,
+    address: SocketAddr,
+    identifier: Option<String>,
+}
+
+impl<'de> Deserialize<'de> for RequestSource {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let source = ReceivedSource::deserialize(deserializer)?;
+        Ok(Self {
+            handler: handler_name(&source.handler),
+            handler_index: None,
+            address: source.address,
+            identifier: source.identifier,
+        })
+    }
+}
+
+/// A subscriber and the protocol version it speaks.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SubscriberVersion {
+    pub address: SocketAddr,
+    pub identifier: String,
+    /// None if the subscriber has not stated its version with `CoreRequest::Hello`
+    pub protocol_version: Option<u16>,
+}
+
+impl RequestSource {
+    /// Requests made with the buttons on the device itself.
+    pub fn front_panel() -> Self {
+        Self {
+            handler: "panel",
+            handler_index: None,
+            address: SocketAddr::from(([0, 0, 0, 0], 0)),
+            identifier: None,
+        }
+    }
+}
+
+impl std::fmt::Display for RequestSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.identifier {
+            Some(identifier) => write!(f, "{}:{} ({})", self.handler, self.address, identifier),
+            None => write!(f, "{}:{}", self.handler, self.address),
+        }
+    }
+}
 
 pub trait CommunicationInterface: Send {
-    fn get_inputs(&mut self, limit: usize) -> Vec<Request>;
+    /// Short name of the handler, used in logs, audit entries and metrics.
+    fn name(&self) -> &'static str;
+
+    fn get_inputs(&mut self, limit: usize) -> Vec<(Request, RequestSource)>;
 
-    fn get_all_inputs(&mut self) -> Vec<Request> {
+    fn get_all_inputs(&mut self) -> Vec<(Request, RequestSource)> {
         self.get_inputs(usize::MAX)
     }
 
-    fn get_single_input(&mut self) -> Option<Request> {
+    fn get_single_input(&mut self) -> Option<(Request, RequestSource)> {
         self.get_inputs(1).first().cloned()
     }
 
     fn notify(&mut self, message: Message);
 
     fn notify_multiple(&mut self, messages: Vec<Message>);
+
+    /// Take the clicks-core extension requests received since the last call, together with
+    /// where they came from.
+    fn get_core_inputs(&mut self) -> Vec<(CoreRequest, RequestSource)> {
+        vec![]
+    }
+
+    /// Send a clicks-core extension message to all subscribers.
+    fn notify_core(&mut self, _message: &CoreMessage) {}
+
+    /// Send a clicks-core extension message to a single client.
+    fn reply(&mut self, _to: SocketAddr, _message: CoreMessage) {}
+
+    /// Subscribers of the handlers that have any, for handlers that show them. Sent every
+    /// heartbeat.
+    fn notify_subscribers(&mut self, _subscribers: &[SubscriberVersion]) {}
+
+    /// Subscribers of this handler.
+    fn subscribers(&self) -> Vec<SubscriberVersion> {
+        vec![]
+    }
+
+    /// Number of packets received and sent since the last call.
+    fn take_packet_counts(&mut self) -> (u32, u32) {
+        (0, 0)
+    }
+
+    /// Move the sockets of the handler to the current address of the network interface, after
+    /// it has changed. Returns the new local address, or None if the handler did not move.
+    fn rebind(&mut self) -> std::io::Result<Option<SocketAddr>> {
+        Ok(None)
+    }
 }
@@ -1,129 +1,525 @@
-use std::{
-    fmt::Display,
-    net::{IpAddr, SocketAddr},
-    str::FromStr,
-};
-
+//! Requests and notifications as JSON, for controllers that would rather not speak postcard.
+//!
+//! Over UDP every datagram holds one request or message. Over TCP, for controllers on flaky
+//! networks and for messages larger than a datagram, every frame is the length of its JSON as
+//! 4 bytes big-endian followed by the JSON itself, and subscribers are sent their notifications
+//! over the connection they subscribed on, for as long as it is open.
 use crate::{
-    communication::{interface::CommunicationInterface, netport::NetworkPort},
-    logger,
+    communication::{
+        decode::{Decoded, decode_json},
+        extension::CoreMessage,
+        interface::{CommunicationInterface, RequestSource, SubscriberVersion},
+        netport::NetworkPort,
+        replay::MessageCache,
+    },
+    logger::{LogDispatcher, LogSender},
+    validation::RejectReason,
 };
 use chrono::{DateTime, Utc};
 use common::{
-    local::{
-        config::{LogContext, LogKind},
-        status::NetworkStatus,
-    },
-    mem::{
-        network::{IpAddress, SubscriberInfo},
-        typeflags::MessageType,
+    local::config::{LogContext, LogItem, LogKind},
+    local::status::NetworkStatus,
+    mem::network::{IpAddress, SubscriberInfo},
+    protocol::{
+        message::{LargeMessage, Message},
+        request::Request,
     },
-    protocol::{message::Message, request::Request},
 };
-use core::fmt;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::VecDeque,
+    io::{ErrorKind, Read, Write},
+    net::{IpAddr, Ipv4Addr, SocketAddr, TcpListener, TcpStream},
+};
+
+/// Bytes of the length in front of every frame on TCP
+const LENGTH_BYTES: usize = 4;
+/// Longest frame taken from a client. A longer one is taken for a client out of step with the
+/// framing, and its connection is closed.
+const MAX_FRAME_LEN: usize = 16 * 1024 * 1024;
+/// Bytes queued to a connection that does not keep up before it is closed, so a stalled client
+/// cannot take up memory without end
+const MAX_PENDING: usize = 4 * 1024 * 1024;
+/// Clients connected at once. Further ones are refused until one disconnects, so the main loop
+/// never reads from more sockets than this.
+const MAX_CONNECTIONS: usize = 32;
+
+/// How a JSON handler talks to its clients.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JsonTransport {
+    /// One request or message per datagram, up to 64 KiB
+    #[default]
+    Udp,
+    /// Length-prefixed frames over a connection per client
+    Tcp,
+}
+
+/// `payload` as a frame, preceded by its length.
+pub fn frame(payload: &[u8]) -> Vec<u8> {
+    let mut frame = Vec::with_capacity(LENGTH_BYTES + payload.len());
+    frame.extend((payload.len() as u32).to_be_bytes());
+    frame.extend(payload);
+    frame
+}
+
+/// Takes the first complete frame off the front of `inbox`, if it holds one. Fails with the
+/// length of a frame longer than `MAX_FRAME_LEN`.
+fn take_frame(inbox: &mut Vec<u8>) -> Result<Option<Vec<u8>>, usize> {
+    let Some(length) = inbox.first_chunk::<LENGTH_BYTES>() else {
+        return Ok(None);
+    };
+    let length = u32::from_be_bytes(*length) as usize;
+    if length > MAX_FRAME_LEN {
+        return Err(length);
+    }
+    if inbox.len() < LENGTH_BYTES + length {
+        return Ok(None);
+    }
+    let payload = inbox[LENGTH_BYTES..LENGTH_BYTES + length].to_vec();
+    inbox.drain(..LENGTH_BYTES + length);
+    Ok(Some(payload))
+}
+
+/// A connected TCP client.
+struct Connection {
+    stream: TcpStream,
+    peer: SocketAddr,
+    /// Bytes received and not yet taken as frames
+    inbox: Vec<u8>,
+    /// Frames not yet taken by the socket
+    outbox: Vec<u8>,
+    /// Why the connection is to be closed, once it is
+    closing: Option<String>,
+}
+
+impl Connection {
+    /// Reads what has arrived, closing the connection when the client has.
+    fn fill(&mut self) {
+        let mut buffer = [0; 4096];
+        loop {
+            match self.stream.read(&mut buffer) {
+                Ok(0) => {
+                    self.closing = Some("closed by the client".to_string());
+                    return;
+                }
+                Ok(count) => self.inbox.extend_from_slice(&buffer[..count]),
+                Err(err) if err.kind() == ErrorKind::WouldBlock => return,
+                Err(err) if err.kind() == ErrorKind::Interrupted => {}
+                Err(err) => {
+                    self.closing = Some(err.to_string());
+                    return;
+                }
+            }
+        }
+    }
+
+    /// Writes as much of the outbox as the socket takes.
+    fn flush(&mut self) {
+        while !self.outbox.is_empty() && self.closing.is_none() {
+            match self.stream.write(&self.outbox) {
+                Ok(0) => self.closing = Some("closed by the client".to_string()),
+                Ok(count) => {
+                    self.outbox.drain(..count);
+                }
+                Err(err) if err.kind() == ErrorKind::WouldBlock => return,
+                Err(err) if err.kind() == ErrorKind::Interrupted => {}
+                Err(err) => self.closing = Some(err.to_string()),
+            }
+        }
+    }
+
+    fn send(&mut self, payload: &[u8]) {
+        if self.outbox.len() + LENGTH_BYTES + payload.len() > MAX_PENDING {
+            self.closing = Some("not keeping up with notifications".to_string());
+            return;
+        }
+        self.outbox.extend(frame(payload));
+        self.flush();
+    }
+}
+
+/// Listening socket and connected clients of a JSON handler on TCP.
+struct TcpPort {
+    listener: TcpListener,
+    connections: Vec<Connection>,
+    /// Frames taken off the connections and not yet handled, with where they came from
+    received: VecDeque<(Vec<u8>, SocketAddr)>,
+    /// Clients refused for being over `MAX_CONNECTIONS` since last taken, with the latest of them
+    refused: Option<(SocketAddr, u32)>,
+    packets_in: u32,
+    packets_out: u32,
+}
+
+impl TcpPort {
+    /// Listens on all interfaces, so the port stays open when the address of the unit changes.
+    fn bind(port: usize) -> std::io::Result<Self> {
+        let listener = TcpListener::bind(SocketAddr::new(
+            IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+            port as u16,
+        ))?;
+        listener.set_nonblocking(true)?;
+        Ok(Self {
+            listener,
+            connections: vec![],
+            received: VecDeque::new(),
+            refused: None,
+            packets_in: 0,
+            packets_out: 0,
+        })
+    }
+
+    /// Takes new clients and what the connected ones have sent.
+    fn poll(&mut self) {
+        while let Ok((stream, peer)) = self.listener.accept() {
+            if self.connections.len() >= MAX_CONNECTIONS {
+                let count = self.refused.map_or(0, |(_, count)| count);
+                self.refused = Some((peer, count + 1));
+                continue;
+            }
+            if stream.set_nonblocking(true).is_err() {
+                continue;
+            }
+            let _ = stream.set_nodelay(true);
+            self.connections.push(Connection {
+                stream,
+                peer,
+                inbox: vec![],
+                outbox: vec![],
+                closing: None,
+            });
+        }
+        for connection in &mut self.connections {
+            connection.fill();
+            loop {
+                match take_frame(&mut connection.inbox) {
+                    Ok(Some(payload)) => {
+                        self.packets_in = self.packets_in.wrapping_add(1);
+                        self.received.push_back((payload, connection.peer));
+                    }
+                    Ok(None) => break,
+                    Err(length) => {
+                        connection.closing =
+                            Some(format!("sent a frame of {length} bytes, out of step"));
+                        break;
+                    }
+                }
+            }
+            connection.flush();
+        }
+    }
+
+    fn recv(&mut self) -> Option<(Vec<u8>, SocketAddr)> {
+        if self.received.is_empty() {
+            self.poll();
+        }
+        self.received.pop_front()
+    }
+
+    fn send_to(&mut self, payload: &[u8], peer: SocketAddr) {
+        if let Some(connection) = self
+            .connections
+            .iter_mut()
+            .find(|connection| connection.peer == peer)
+        {
+            connection.send(payload);
+            self.packets_out = self.packets_out.wrapping_add(1);
+        }
+    }
+
+    /// Drops the connections that are to be closed, returning their clients and why.
+    fn take_closed(&mut self) -> Vec<(SocketAddr, String)> {
+        let mut closed = vec![];
+        self.connections.retain(|connection| {
+            if let Some(reason) = &connection.closing {
+                closed.push((connection.peer, reason.clone()));
+                false
+            } else {
+                true
+            }
+        });
+        closed
+    }
+}
+
+enum JsonPort {
+    Udp(NetworkPort),
+    Tcp(TcpPort),
+}
+
+impl JsonPort {
+    fn recv(&mut self) -> Option<(Vec<u8>, SocketAddr)> {
+        match self {
+            Self::Udp(port) => port
+                .recv()
+                .map(|(buf, amt, src)| (buf[..amt].to_vec(), src)),
+            Self::Tcp(port) => port.recv(),
+        }
+    }
+
+    fn send_to(&mut self, payload: &[u8], address: SocketAddr) {
+        match self {
+            Self::Udp(port) => port.send_to(payload, address),
+            Self::Tcp(port) => port.send_to(payload, address),
+        }
+    }
+
+    fn local_addr(&self) -> std::io::Result<SocketAddr> {
+        match self {
+            Self::Udp(port) => port.socket.local_addr(),
+            Self::Tcp(port) => port.listener.local_addr(),
+        }
+    }
+}
 
 pub struct JsonNetHandler {
-    port: NetworkPort,
+    port: JsonPort,
     subscribers: Vec<SubscriberInfo>,
-    input_queue: Vec<Request>,
+    input_queue: Vec<(Request, RequestSource)>,
+    cache: MessageCache,
+    logger: LogSender,
 }
 
 impl JsonNetHandler {
-    pub fn new(port: usize) -> Self {
-        let a = Self {
-            port: NetworkPort::new(port),
-            subscribers: vec![],
-            input_queue: vec![],
-        };
-        logger::log(
+    pub fn new(logger: &LogDispatcher, port: usize, transport: JsonTransport) -> Self {
+        let a = Self::open(logger.sender(), port, transport);
+        logger.log(LogItem::new(
             format!(
-                "opened jsonnet port {}",
-                a.port.socket.local_addr().unwrap()
+                "opened jsonnet port {} ({transport:?})",
+                a.port.local_addr().unwrap()
             ),
             LogContext::Network,
             LogKind::Note,
-        );
+        ));
         a
     }
 
+    fn open(logger: LogSender, port: usize, transport: JsonTransport) -> Self {
+        let port = match transport {
+            JsonTransport::Udp => JsonPort::Udp(NetworkPort::new(port)),
+            JsonTransport::Tcp => {
+                JsonPort::Tcp(TcpPort::bind(port).expect("couldn't open local port"))
+            }
+        };
+        Self {
+            port,
+            subscribers: vec![],
+            input_queue: vec![],
+            cache: MessageCache::new(),
+            logger,
+        }
+    }
+
     pub fn publish_subscribers(&mut self) {
-        let subs_slice: [Option<SubscriberInfo>; 32] =
-            std::array::from_fn(|i| self.subscribers.get(i).cloned().map(Some).unwrap_or(None));
-        self.notify(Message::NetworkChanged(NetworkStatus {
-            subscribers: subs_slice,
-        }));
+        self.notify(Message::Large(LargeMessage::NetworkChanged(
+            NetworkStatus {
+                subscribers: self.subscribers.clone(),
+            },
+        )));
+    }
+
+    fn subscriber_socket_addr(subscriber: &SubscriberInfo) -> SocketAddr {
+        SocketAddr::new(
+            IpAddr::V4(Ipv4Addr::new(
+                subscriber.address.addr[0],
+                subscriber.address.addr[1],
+                subscriber.address.addr[2],
+                subscriber.address.addr[3],
+            )),
+            subscriber.address.port,
+        )
+    }
+
+    fn encode(message: &Message) -> Option<Vec<u8>> {
+        serde_json::to_vec(message).ok()
+    }
+
+    /// Tells a client that its request was not acted upon.
+    fn reject(&mut self, to: SocketAddr, request: String, reason: RejectReason) {
+        self.reply(to, CoreMessage::RequestRejected { request, reason });
+    }
+
+    /// Sends the latest message of every subscribed type to a new subscriber.
+    fn replay(&mut self, subscriber: &SubscriberInfo) {
+        let address = Self::subscriber_socket_addr(subscriber);
+        for message in self.cache.messages() {
+            if subscriber.message_kinds.contains(message.to_type())
+                && let Some(buffer) = Self::encode(message)
+            {
+                self.port.send_to(&buffer, address);
+            }
+        }
+    }
+
+    fn source(&self, src: SocketAddr) -> RequestSource {
+        let address = IpAddress::from_str_and_port(&src.ip().to_string(), src.port());
+        RequestSource {
+            handler: self.name(),
+            handler_index: None,
+            address: src,
+            identifier: self
+                .subscribers
+                .iter()
+                .find(|subscriber| Some(subscriber.address) == address)
+                .map(|subscriber| subscriber.identifier.str().to_string()),
+        }
+    }
+
+    /// Forgets the subscribers of closed TCP connections, logging them and any refused clients.
+    fn drop_closed(&mut self) {
+        let JsonPort::Tcp(port) = &mut self.port else {
+            return;
+        };
+        if let Some((peer, count)) = port.refused.take() {
+            self.logger.log(LogItem::new(
+                format!(
+                    "refused {count} jsonnet connection(s), latest from {peer}: \
+                    already {MAX_CONNECTIONS} connected"
+                ),
+                LogContext::Network,
+                LogKind::Warning,
+            ));
+        }
+        let closed = port.take_closed();
+        if closed.is_empty() {
+            return;
+        }
+        for (peer, reason) in &closed {
+            self.logger.log(LogItem::new(
+                format!("jsonnet connection from {peer} closed: {reason}"),
+                LogContext::Network,
+                LogKind::Note,
+            ));
+        }
+        let before = self.subscribers.len();
+        self.subscribers.retain(|subscriber| {
+            let address = Self::subscriber_socket_addr(subscriber);
+            !closed.iter().any(|(peer, _)| *peer == address)
+        });
+        if self.subscribers.len() != before {
+            self.publish_subscribers();
+        }
     }
 }
 
 impl CommunicationInterface for JsonNetHandler {
-    fn get_inputs(&mut self, limit: usize) -> Vec<Request> {
-        let mut inputs: Vec<Request> = vec![];
+    fn name(&self) -> &'static str {
+        "jsonnet"
+    }
+
+    fn take_packet_counts(&mut self) -> (u32, u32) {
+        match &mut self.port {
+            JsonPort::Udp(port) => port.take_packet_counts(),
+            JsonPort::Tcp(port) => (
+                std::mem::take(&mut port.packets_in),
+                std::mem::take(&mut port.packets_out),
+            ),
+        }
+    }
+
+    fn rebind(&mut self) -> std::io::Result<Option<SocketAddr>> {
+        match &mut self.port {
+            JsonPort::Udp(port) => port.rebind(),
+            JsonPort::Tcp(_) => Ok(None),
+        }
+    }
+
+    fn reply(&mut self, to: SocketAddr, message: CoreMessage) {
+        if let Ok(buffer) = serde_json::to_vec(&message) {
+            self.port.send_to(&buffer, to);
+        }
+    }
+
+    fn subscribers(&self) -> Vec<SubscriberVersion> {
+        self.subscribers
+            .iter()
+            .map(|subscriber| SubscriberVersion {
+                address: Self::subscriber_socket_addr(subscriber),
+                identifier: subscriber.identifier.str().to_string(),
+                protocol_version: None,
+            })
+            .collect()
+    }
+
+    fn get_inputs(&mut self, limit: usize) -> Vec<(Request, RequestSource)> {
+        let mut inputs: Vec<(Request, RequestSource)> = vec![];
         inputs.append(&mut self.input_queue);
-        while let Some((buf, amt, src)) = self.port.recv() {
+        while let Some((buf, src)) = self.port.recv() {
             for subscriber in &mut self.subscribers {
-                if Some(subscriber.address.clone())
+                if Some(subscriber.address)
                     == IpAddress::from_str_and_port(&src.ip().to_string(), src.port())
                 {
                     subscriber.last_contact = Utc::now().timestamp() as u128;
                 }
             }
-            let msg: Request = match serde_json::from_str(match std::str::from_utf8(&buf[..amt]) {
-                Ok(val) => val,
-                Err(err) => panic!("failed conversion! {err}",),
-            }) {
-                Ok(msg) => msg,
-                Err(err) => {
-                    panic!(
-                        "failed parse! {err} \n {}",
-                        std::str::from_utf8(&buf[..amt]).unwrap_or_default()
+            let decoded = match std::str::from_utf8(&buf) {
+                Ok(text) => decode_json::<Request>(text),
+                Err(err) => Decoded::Unreadable(err.to_string()),
+            };
+            let mut msg = match decoded {
+                Decoded::Known(msg) => msg,
+                Decoded::Unsupported { variant } => {
+                    self.reject(
+                        src,
+                        format!("Request variant {variant}"),
+                        RejectReason::Unsupported,
                     );
+                    continue;
+                }
+                Decoded::Unreadable(err) => {
+                    self.reject(src, format!("Request: {err}"), RejectReason::Unreadable);
+                    continue;
                 }
             };
-            match msg.clone() {
+            // Notifications go back over the connection, wherever the client says it is
+            if let (JsonPort::Tcp(_), Request::Subscribe(info) | Request::Unsubscribe(info)) =
+                (&self.port, &mut msg)
+                && let Some(address) =
+                    IpAddress::from_str_and_port(&src.ip().to_string(), src.port())
+            {
+                info.address = address;
+            }
+            match &msg {
                 Request::Ping => {}
                 Request::Subscribe(info) => {
                     let mut recognized_subscriber = false;
                     for subscriber in &mut self.subscribers {
                         if subscriber.address == info.address {
-                            subscriber.message_kinds = info.message_kinds.clone();
+                            subscriber.message_kinds = info.message_kinds;
                             recognized_subscriber = true;
                         }
                     }
                     if !recognized_subscriber {
-                        logger::log(
-                            format!(
-                                "New subscriber: {} at [{}] subscribing to {:?}.",
-                                info.identifier.str(),
-                                info.address,
-                                info.message_kinds
-                            ),
-                            LogContext::Network,
-                            LogKind::Note,
-                        );
-                        self.subscribers.push(info);
+                        self.subscribers.push(SubscriberInfo {
+                            last_contact: Utc::now().timestamp() as u128,
+                            ..info.clone()
+                        });
                     }
                     self.publish_subscribers();
-                    self.input_queue.push(Request::NotifySubscribers);
+                    // Nothing has been sent yet right after boot, so ask for the full state instead
+                    if self.cache.is_empty() {
+                        let source = self.source(src);
+                        self.input_queue.push((Request::NotifySubscribers, source));
+                    } else {
+                        self.replay(&info.clone());
+                    }
                 }
                 Request::Unsubscribe(info) => {
-                    self.subscribers = self
-                        .subscribers
-                        .clone()
-                        .into_iter()
-                        .filter(|sub| sub.address != info.address)
-                        .collect();
+                    self.subscribers.retain(|sub| sub.address != info.address);
                     self.publish_subscribers();
                 }
                 _ => {}
             }
-            self.input_queue.push(msg);
+            let source = self.source(src);
+            self.input_queue.push((msg, source));
             if inputs.len() + self.input_queue.len() > limit {
                 break;
             } else {
                 inputs.append(&mut self.input_queue);
             }
         }
-        return inputs;
+        self.drop_closed();
+        inputs
     }
 
     fn notify_multiple(&mut self, notifications: Vec<Message>) {
@@ -133,40 +529,119 @@ impl CommunicationInterface for JsonNetHandler {
     }
 
     fn notify(&mut self, notification: Message) {
-        if false && notification.to_type() != MessageType::TransportData {
-            logger::log(
-                format!("Sending network message: {notification:?}"),
-                LogContext::Network,
-                LogKind::Debug,
-            );
-        }
-        self.subscribers = self
-            .subscribers
-            .clone()
-            .into_iter()
-            .filter(|sub| {
+        // Subscribers on TCP stay for as long as their connection
+        if let JsonPort::Udp(_) = self.port {
+            self.subscribers.retain(|sub| {
                 Utc::now()
                     .signed_duration_since(
                         DateTime::from_timestamp_secs(sub.last_contact as i64).unwrap_or_default(),
                     )
                     .num_minutes()
                     < 15
-            })
-            .collect();
+            });
+        }
 
+        self.cache.store(&notification);
+        let Some(buffer) = Self::encode(&notification) else {
+            return;
+        };
         for subscriber in &self.subscribers {
             if subscriber.message_kinds.contains(notification.to_type()) {
-                self.port.send_to(
-                    serde_json::to_string(&notification)
-                        .expect("notification has trivial derived conversion")
-                        .as_bytes(),
-                    SocketAddr::new(
-                        IpAddr::from_str(&format!("{}", &subscriber.address).to_string())
-                            .expect("all subscriber addresses are santizied earlier"),
-                        subscriber.address.port,
-                    ),
-                );
+                self.port
+                    .send_to(&buffer, Self::subscriber_socket_addr(subscriber));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn frames() {
+        let mut inbox = frame(b"{}");
+        inbox.extend(frame(&[b' '; 70_000]));
+        inbox.extend(&frame(b"\"Ping\"")[..5]);
+        assert_eq!(take_frame(&mut inbox), Ok(Some(b"{}".to_vec())));
+        // Larger than a datagram
+        assert_eq!(take_frame(&mut inbox).unwrap().unwrap().len(), 70_000);
+        // Not all there yet
+        assert_eq!(take_frame(&mut inbox), Ok(None));
+        inbox.extend(b"Ping\"");
+        assert_eq!(take_frame(&mut inbox), Ok(Some(b"\"Ping\"".to_vec())));
+        assert!(inbox.is_empty());
+
+        let mut inbox = u32::MAX.to_be_bytes().to_vec();
+        assert_eq!(take_frame(&mut inbox), Err(u32::MAX as usize));
+    }
+
+    #[test]
+    fn requests_over_tcp() {
+        let mut handler = JsonNetHandler::open(LogSender::discard(), 0, JsonTransport::Tcp);
+        let port = handler.port.local_addr().unwrap().port();
+        let mut client = TcpStream::connect(("127.0.0.1", port)).unwrap();
+        let connections = |handler: &JsonNetHandler| match &handler.port {
+            JsonPort::Tcp(port) => port.connections.len(),
+            JsonPort::Udp(_) => unreachable!(),
+        };
+        let start = Instant::now();
+        let framed = frame(&serde_json::to_vec(&Request::Ping).unwrap());
+        // Split across writes, as a slow network delivers it
+        client.write_all(&framed[..3]).unwrap();
+        while connections(&handler) == 0 && start.elapsed() < Duration::from_secs(2) {
+            assert!(handler.get_all_inputs().is_empty());
+        }
+        client.write_all(&framed[3..]).unwrap();
+        client.write_all(&framed).unwrap();
+
+        let mut inputs = vec![];
+        while inputs.len() < 2 && start.elapsed() < Duration::from_secs(2) {
+            inputs.extend(handler.get_all_inputs());
+        }
+        assert_eq!(inputs.len(), 2);
+        assert!(matches!(inputs[0].0, Request::Ping));
+        assert_eq!(inputs[0].1.address, client.local_addr().unwrap());
+
+        // The client going away closes the connection
+        drop(client);
+        while connections(&handler) > 0 && start.elapsed() < Duration::from_secs(2) {
+            handler.get_all_inputs();
+        }
+        assert_eq!(connections(&handler), 0);
+    }
+
+    #[test]
+    fn unknown_requests_rejected() {
+        let mut handler = JsonNetHandler::open(LogSender::discard(), 0, JsonTransport::Tcp);
+        let port = handler.port.local_addr().unwrap().port();
+        let mut client = TcpStream::connect(("127.0.0.1", port)).unwrap();
+        client
+            .set_read_timeout(Some(Duration::from_millis(10)))
+            .unwrap();
+        client
+            .write_all(&frame(br#"{"Teleport":{"to":3}}"#))
+            .unwrap();
+
+        let start = Instant::now();
+        let mut received = vec![];
+        let mut buf = [0; 1024];
+        while take_frame(&mut received.clone()) == Ok(None)
+            && start.elapsed() < Duration::from_secs(2)
+        {
+            assert!(handler.get_all_inputs().is_empty());
+            if let Ok(amt) = client.read(&mut buf) {
+                received.extend(&buf[..amt]);
+            }
+        }
+        let reply = take_frame(&mut received).unwrap().unwrap();
+        match serde_json::from_slice(&reply).unwrap() {
+            CoreMessage::RequestRejected { request, reason } => {
+                assert_eq!(request, "Request variant Teleport");
+                assert_eq!(reason, RejectReason::Unsupported);
             }
+            other => panic!("expected a rejection, got {other:?}"),
         }
     }
 }
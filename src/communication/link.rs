@@ -0,0 +1,108 @@
+use local_ip_address::{list_afinet_netifas, local_ip};
+use serde::{Deserialize, Serialize};
+use std::net::IpAddr;
+
+/// State of the network interface the protocol handlers are bound to.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LinkStatus {
+    /// Name of the interface, e.g. "eth0", None if it could not be found
+    pub interface: Option<String>,
+    /// Address of this unit on it, None if it has none
+    pub address: Option<IpAddr>,
+    /// Whether the interface has a carrier and an address
+    pub up: bool,
+}
+
+impl LinkStatus {
+    /// The current state of the interface of the default route.
+    pub fn current() -> Self {
+        let address = local_ip().ok();
+        let interface = address.and_then(|address| {
+            list_afinet_netifas()
+                .ok()?
+                .into_iter()
+                .find(|(_, ip)| *ip == address)
+                .map(|(name, _)| name)
+        });
+        let carrier = interface.as_ref().is_none_or(|name| {
+            // Drivers that do not report the state say "unknown"
+            std::fs::read_to_string(format!("/sys/class/net/{name}/operstate"))
+                .map_or(true, |state| state.trim() != "down")
+        });
+        Self {
+            interface,
+            address,
+            up: address.is_some() && carrier,
+        }
+    }
+}
+
+/// Watches the network interface, so the handlers can be moved to a new address and clients
+/// told, when the link drops or the address changes, e.g. when the house switch reboots or DHCP
+/// hands out another lease.
+pub struct LinkMonitor {
+    status: LinkStatus,
+}
+
+impl LinkMonitor {
+    pub fn new() -> Self {
+        Self {
+            status: LinkStatus::current(),
+        }
+    }
+
+    /// The state of the interface, if it has changed since the last poll.
+    pub fn poll(&mut self) -> Option<LinkStatus> {
+        self.update(LinkStatus::current())
+    }
+
+    fn update(&mut self, status: LinkStatus) -> Option<LinkStatus> {
+        if status == self.status {
+            return None;
+        }
+        self.status = status.clone();
+        Some(status)
+    }
+}
+
+impl Default for LinkMonitor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::Ipv4Addr;
+
+    fn status(address: Option<[u8; 4]>, up: bool) -> LinkStatus {
+        LinkStatus {
+            interface: Some("eth0".to_string()),
+            address: address.map(|address| IpAddr::V4(Ipv4Addr::from(address))),
+            up,
+        }
+    }
+
+    #[test]
+    fn link_changes() {
+        let mut monitor = LinkMonitor {
+            status: status(Some([10, 0, 0, 10]), true),
+        };
+        assert_eq!(monitor.update(status(Some([10, 0, 0, 10]), true)), None);
+        // The switch reboots and DHCP hands out another address
+        assert_eq!(
+            monitor.update(status(Some([10, 0, 0, 10]), false)),
+            Some(status(Some([10, 0, 0, 10]), false))
+        );
+        assert_eq!(
+            monitor.update(status(None, false)),
+            Some(status(None, false))
+        );
+        assert_eq!(monitor.update(status(None, false)), None);
+        assert_eq!(
+            monitor.update(status(Some([10, 0, 0, 23]), true)),
+            Some(status(Some([10, 0, 0, 23]), true))
+        );
+    }
+}
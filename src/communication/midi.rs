@@ -0,0 +1,527 @@
+use crate::{
+    audio::timecode::TimecodeTime,
+    cbnet::CrossbeamNetwork,
+    communication::{
+        extension::{CoreMessage, CoreRequest},
+        interface::{CommunicationInterface, RequestSource},
+        mtc::MtcSender,
+    },
+};
+use common::{
+    local::config::{LogContext, LogItem, LogKind},
+    protocol::{
+        message::{Message, SmallMessage},
+        request::{ControlAction, Request},
+    },
+};
+use crossbeam_channel::{Receiver, Sender, unbounded};
+use midir::{Ignore, MidiInput, MidiInputConnection};
+use serde::{Deserialize, Serialize};
+use std::{net::SocketAddr, time::Duration};
+
+/// Name of the ALSA sequencer client the devices are connected to
+pub(crate) const CLIENT_NAME: &str = "clicks-core";
+/// MIDI Machine Control device ID addressing all devices
+pub const MMC_ALL_DEVICES: u8 = 0x7F;
+/// Time between scans for MIDI devices plugged in or out
+const SCAN_INTERVAL: Duration = Duration::from_secs(1);
+/// Gain of a fader at the bottom and at the top of its travel
+const FADER_MIN_DB: f32 = -60.0;
+const FADER_MAX_DB: f32 = 6.0;
+
+/// A control on a MIDI device: a key or pad sending notes, or a knob, fader or button sending
+/// control changes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MidiControl {
+    Note { channel: u8, note: u8 },
+    ControlChange { channel: u8, controller: u8 },
+}
+
+impl MidiControl {
+    /// The control a MIDI message comes from and its value, 0 for a released note. None for
+    /// messages that are not from a control.
+    pub fn parse(message: &[u8]) -> Option<(Self, u8)> {
+        let [status, data, value, ..] = *message else {
+            return None;
+        };
+        let channel = status & 0x0F;
+        match status & 0xF0 {
+            0x80 => Some((
+                Self::Note {
+                    channel,
+                    note: data,
+                },
+                0,
+            )),
+            0x90 => Some((
+                Self::Note {
+                    channel,
+                    note: data,
+                },
+                value,
+            )),
+            0xB0 => Some((
+                Self::ControlChange {
+                    channel,
+                    controller: data,
+                },
+                value,
+            )),
+            _ => None,
+        }
+    }
+}
+
+/// A MIDI Machine Control command, as lighting desks and DAWs send to run a transport.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MmcCommand {
+    /// Stop or pause
+    Stop,
+    /// Play or deferred play
+    Play,
+    /// Rewind, taken as going to zero
+    Rewind,
+    /// Locate to a time, taken as seeking the current cue to the beat sending it
+    Locate(TimecodeTime),
+}
+
+impl MmcCommand {
+    /// Parses an MMC SysEx message addressed to `device` or to all devices. None for other
+    /// messages and for commands the core does not take.
+    pub fn parse(message: &[u8], device: u8) -> Option<Self> {
+        let [0xF0, 0x7F, id, 0x06, command, ref rest @ ..] = *message else {
+            return None;
+        };
+        if id != device && id != MMC_ALL_DEVICES {
+            return None;
+        }
+        match (command, rest) {
+            (0x01 | 0x09, _) => Some(Self::Stop),
+            (0x02 | 0x03, _) => Some(Self::Play),
+            (0x05, _) => Some(Self::Rewind),
+            // Target sub-command with the time, the rate in the top bits of the hours
+            (0x44, [0x06, 0x01, hours, minutes, seconds, frames, ..]) => {
+                Some(Self::Locate(TimecodeTime {
+                    hours: hours & 0x1F,
+                    minutes: *minutes,
+                    seconds: *seconds,
+                    frames: frames & 0x1F,
+                }))
+            }
+            _ => None,
+        }
+    }
+
+    pub fn request(&self) -> MidiRequest {
+        let action = match *self {
+            MmcCommand::Stop => ControlAction::TransportStop,
+            MmcCommand::Play => ControlAction::TransportStart,
+            MmcCommand::Rewind => ControlAction::TransportZero,
+            MmcCommand::Locate(time) => {
+                return MidiRequest::Core(CoreRequest::LocateTimecode(time));
+            }
+        };
+        MidiRequest::Common(Request::ControlAction(action))
+    }
+}
+
+/// What a MIDI control does. Faders set a gain over their whole travel, everything else acts
+/// when pressed, i.e. on a non-zero value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MidiAction {
+    TransportStart,
+    TransportStop,
+    TransportZero,
+    NextCue,
+    PreviousCue,
+    /// Start the cue in standby
+    Go,
+    ToggleJumpMode,
+    /// Fader setting the gain of a channel
+    ChannelGain(u8),
+    /// Button switching the mute of a channel on or off
+    ChannelMute(u8),
+}
+
+/// A request made by a MIDI action.
+#[derive(Debug, Clone, PartialEq)]
+pub enum MidiRequest {
+    Common(Request),
+    Core(CoreRequest),
+}
+
+impl MidiAction {
+    /// The request made when the control is moved to `value`, if any. A mute button needs to
+    /// know whether the channel is muted to switch it over.
+    pub fn request(&self, value: u8, muted: impl Fn(u8) -> bool) -> Option<MidiRequest> {
+        let action = match *self {
+            MidiAction::ChannelGain(channel) => {
+                let travel = value.min(127) as f32 / 127.0;
+                let gain_db = FADER_MIN_DB + travel * (FADER_MAX_DB - FADER_MIN_DB);
+                return Some(MidiRequest::Common(Request::ControlAction(
+                    ControlAction::SetChannelGain(channel, gain_db),
+                )));
+            }
+            _ if value == 0 => return None,
+            MidiAction::TransportStart => ControlAction::TransportStart,
+            MidiAction::TransportStop => ControlAction::TransportStop,
+            MidiAction::TransportZero => ControlAction::TransportZero,
+            MidiAction::NextCue => ControlAction::LoadNextCue,
+            MidiAction::PreviousCue => ControlAction::LoadPreviousCue,
+            MidiAction::ChannelMute(channel) => {
+                ControlAction::SetChannelMute(channel, !muted(channel))
+            }
+            MidiAction::Go => return Some(MidiRequest::Core(CoreRequest::Go)),
+            MidiAction::ToggleJumpMode => {
+                return Some(MidiRequest::Core(CoreRequest::SetJumpMode { on: None }));
+            }
+        };
+        Some(MidiRequest::Common(Request::ControlAction(action)))
+    }
+
+    /// Channel the action works on, if any.
+    pub fn channel(&self) -> Option<u8> {
+        match *self {
+            MidiAction::ChannelGain(channel) | MidiAction::ChannelMute(channel) => Some(channel),
+            _ => None,
+        }
+    }
+}
+
+/// A control and what it does. Stored in the core configuration.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MidiMapping {
+    pub control: MidiControl,
+    pub action: MidiAction,
+}
+
+/// Takes MIDI controllers, e.g. a nanoKONTROL as an emergency control surface, as a source of
+/// requests. Devices on the ALSA sequencer are connected to as they are plugged in, and their
+/// controls act as mapped in the core configuration.
+///
+/// Mappings are learnt by arming an action with `CoreRequest::LearnMidi`: the next control moved
+/// on any device is mapped to it with a `CoreRequest::MapMidi` from this handler.
+///
+/// MIDI Machine Control from any device runs the transport, and MIDI Timecode following the
+/// timecode output can be sent to an output port, see `communication::mtc`.
+pub struct MidiHandler {
+    mappings: Vec<MidiMapping>,
+    learning: Option<MidiAction>,
+    /// MMC device ID answered to, besides all devices
+    mmc_device: u8,
+    mtc: Option<MtcSender>,
+    /// Muted channels as last broadcast, for mute buttons to switch over
+    muted: Vec<bool>,
+    /// Messages from all connected devices
+    messages: Receiver<Vec<u8>>,
+    input_queue: Vec<(Request, RequestSource)>,
+    core_input_queue: Vec<(CoreRequest, RequestSource)>,
+}
+
+impl MidiHandler {
+    /// Starts watching for devices on a thread of its own, which also receives their messages,
+    /// and sending MTC to the output port named `mtc_output`, if any.
+    pub fn new(
+        cbnet: CrossbeamNetwork,
+        mappings: Vec<MidiMapping>,
+        mmc_device: u8,
+        mtc_output: Option<String>,
+    ) -> Self {
+        let (tx, rx) = unbounded();
+        let watcher_cbnet = cbnet.clone();
+        if let Err(err) = std::thread::Builder::new()
+            .name("clicks-midi".to_string())
+            .spawn(move || watch_devices(tx, watcher_cbnet))
+        {
+            cbnet.log(LogItem::new(
+                format!("Could not start watching for MIDI devices: {err}"),
+                LogContext::Network,
+                LogKind::Error,
+            ));
+        }
+        Self {
+            mappings,
+            learning: None,
+            mmc_device,
+            mtc: mtc_output.map(|port| MtcSender::new(port, cbnet)),
+            muted: vec![],
+            messages: rx,
+            input_queue: vec![],
+            core_input_queue: vec![],
+        }
+    }
+
+    fn source(&self) -> RequestSource {
+        RequestSource {
+            handler: "midi",
+            handler_index: None,
+            address: SocketAddr::from(([0, 0, 0, 0], 0)),
+            identifier: None,
+        }
+    }
+
+    /// Turns the MIDI messages received since the last call into requests.
+    fn take_messages(&mut self) {
+        while let Ok(message) = self.messages.try_recv() {
+            if let Some(command) = MmcCommand::parse(&message, self.mmc_device) {
+                match command.request() {
+                    MidiRequest::Common(request) => self.input_queue.push((request, self.source())),
+                    MidiRequest::Core(request) => {
+                        self.core_input_queue.push((request, self.source()))
+                    }
+                }
+                continue;
+            }
+            let Some((control, value)) = MidiControl::parse(&message) else {
+                continue;
+            };
+            if let Some(action) = self.learning.take() {
+                self.core_input_queue.push((
+                    CoreRequest::MapMidi {
+                        control,
+                        action: Some(action),
+                    },
+                    self.source(),
+                ));
+                continue;
+            }
+            let Some(mapping) = self
+                .mappings
+                .iter()
+                .find(|mapping| mapping.control == control)
+            else {
+                continue;
+            };
+            let muted = |channel: u8| self.muted.get(channel as usize).copied().unwrap_or(false);
+            match mapping.action.request(value, muted) {
+                Some(MidiRequest::Common(request)) => {
+                    self.input_queue.push((request, self.source()))
+                }
+                Some(MidiRequest::Core(request)) => {
+                    self.core_input_queue.push((request, self.source()))
+                }
+                None => {}
+            }
+        }
+    }
+}
+
+impl CommunicationInterface for MidiHandler {
+    fn name(&self) -> &'static str {
+        "midi"
+    }
+
+    fn get_inputs(&mut self, limit: usize) -> Vec<(Request, RequestSource)> {
+        self.take_messages();
+        let count = limit.min(self.input_queue.len());
+        self.input_queue.drain(..count).collect()
+    }
+
+    fn get_core_inputs(&mut self) -> Vec<(CoreRequest, RequestSource)> {
+        std::mem::take(&mut self.core_input_queue)
+    }
+
+    fn notify(&mut self, message: Message) {
+        if let Message::Small(SmallMessage::TimecodeData(state)) = message
+            && let Some(mtc) = &self.mtc
+        {
+            mtc.update(state);
+        }
+    }
+
+    fn notify_multiple(&mut self, messages: Vec<Message>) {
+        for message in messages {
+            self.notify(message);
+        }
+    }
+
+    fn notify_core(&mut self, message: &CoreMessage) {
+        match message {
+            CoreMessage::MidiLearn(action) => self.learning = *action,
+            CoreMessage::MidiMappings(mappings) => self.mappings = mappings.clone(),
+            CoreMessage::Outputs(outputs) => {
+                self.muted = outputs.iter().map(|output| output.muted).collect()
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Connects to MIDI devices as they are plugged in, sending their messages to `messages`.
+/// Devices that are unplugged are let go of.
+fn watch_devices(messages: Sender<Vec<u8>>, cbnet: CrossbeamNetwork) {
+    // Connected devices by port name
+    let mut devices: Vec<(String, MidiInputConnection<()>)> = vec![];
+    let mut missing_logged = false;
+    loop {
+        let Ok(mut scanner) = MidiInput::new(CLIENT_NAME) else {
+            if !missing_logged {
+                cbnet.log(LogItem::new(
+                    "Could not open the ALSA sequencer, MIDI devices are not connected."
+                        .to_string(),
+                    LogContext::Network,
+                    LogKind::Warning,
+                ));
+                missing_logged = true;
+            }
+            std::thread::sleep(SCAN_INTERVAL);
+            continue;
+        };
+        scanner.ignore(Ignore::TimeAndActiveSense);
+        let present: Vec<String> = scanner
+            .ports()
+            .iter()
+            .filter_map(|port| scanner.port_name(port).ok())
+            .filter(|name| !name.starts_with("Midi Through"))
+            .collect();
+
+        devices.retain(|(name, _)| {
+            let kept = present.contains(name);
+            if !kept {
+                cbnet.log(LogItem::new(
+                    format!("MIDI device {name} unplugged."),
+                    LogContext::Network,
+                    LogKind::Note,
+                ));
+            }
+            kept
+        });
+
+        for name in present {
+            if devices.iter().any(|(connected, _)| *connected == name) {
+                continue;
+            }
+            // Connecting takes the input, so each device gets one of its own
+            let Ok(mut input) = MidiInput::new(CLIENT_NAME) else {
+                break;
+            };
+            input.ignore(Ignore::TimeAndActiveSense);
+            let Some(port) = input.ports().into_iter().find(|port| {
+                input
+                    .port_name(port)
+                    .is_ok_and(|port_name| port_name == name)
+            }) else {
+                continue;
+            };
+            let tx = messages.clone();
+            match input.connect(
+                &port,
+                CLIENT_NAME,
+                move |_, message, _| {
+                    let _ = tx.send(message.to_vec());
+                },
+                (),
+            ) {
+                Ok(connection) => {
+                    cbnet.log(LogItem::new(
+                        format!("MIDI device {name} connected."),
+                        LogContext::Network,
+                        LogKind::Note,
+                    ));
+                    devices.push((name, connection));
+                }
+                Err(err) => cbnet.log(LogItem::new(
+                    format!("Could not connect to MIDI device {name}: {err}"),
+                    LogContext::Network,
+                    LogKind::Warning,
+                )),
+            }
+        }
+
+        drop(scanner);
+        std::thread::sleep(SCAN_INTERVAL);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn midi_controls() {
+        assert_eq!(
+            MidiControl::parse(&[0xB1, 7, 100]),
+            Some((
+                MidiControl::ControlChange {
+                    channel: 1,
+                    controller: 7
+                },
+                100
+            ))
+        );
+        assert_eq!(
+            MidiControl::parse(&[0x80, 60, 64]),
+            Some((
+                MidiControl::Note {
+                    channel: 0,
+                    note: 60
+                },
+                0
+            ))
+        );
+        assert_eq!(MidiControl::parse(&[0xF8]), None);
+    }
+
+    #[test]
+    fn machine_control() {
+        assert_eq!(
+            MmcCommand::parse(&[0xF0, 0x7F, 0x7F, 0x06, 0x02, 0xF7], 3),
+            Some(MmcCommand::Play)
+        );
+        assert_eq!(
+            MmcCommand::parse(&[0xF0, 0x7F, 0x03, 0x06, 0x09, 0xF7], 3),
+            Some(MmcCommand::Stop)
+        );
+        // Addressed to another device
+        assert_eq!(
+            MmcCommand::parse(&[0xF0, 0x7F, 0x04, 0x06, 0x02, 0xF7], 3),
+            None
+        );
+        let locate = [
+            0xF0, 0x7F, 0x7F, 0x06, 0x44, 0x06, 0x01, 0x21, 0x02, 0x03, 0x04, 0x00, 0xF7,
+        ];
+        let time = TimecodeTime {
+            hours: 1,
+            minutes: 2,
+            seconds: 3,
+            frames: 4,
+        };
+        assert_eq!(
+            MmcCommand::parse(&locate, MMC_ALL_DEVICES),
+            Some(MmcCommand::Locate(time))
+        );
+        assert_eq!(
+            MmcCommand::Locate(time).request(),
+            MidiRequest::Core(CoreRequest::LocateTimecode(time))
+        );
+    }
+
+    #[test]
+    fn midi_actions() {
+        let unmuted = |_| false;
+        assert_eq!(
+            MidiAction::ChannelGain(2).request(127, unmuted),
+            Some(MidiRequest::Common(Request::ControlAction(
+                ControlAction::SetChannelGain(2, FADER_MAX_DB)
+            )))
+        );
+        assert_eq!(
+            MidiAction::ChannelGain(2).request(0, unmuted),
+            Some(MidiRequest::Common(Request::ControlAction(
+                ControlAction::SetChannelGain(2, FADER_MIN_DB)
+            )))
+        );
+        // Buttons act when pressed, not when released
+        assert_eq!(MidiAction::NextCue.request(0, unmuted), None);
+        assert_eq!(
+            MidiAction::Go.request(127, unmuted),
+            Some(MidiRequest::Core(CoreRequest::Go))
+        );
+        assert_eq!(
+            MidiAction::ChannelMute(1).request(127, |channel| channel == 1),
+            Some(MidiRequest::Common(Request::ControlAction(
+                ControlAction::SetChannelMute(1, false)
+            )))
+        );
+    }
+}
@@ -1,5 +1,15 @@
 pub mod binnet;
+pub mod decode;
+pub mod extension;
 pub mod interface;
-//pub mod jsonnet;
+pub mod jsonnet;
+pub mod link;
+pub mod midi;
+pub mod mtc;
 pub mod netport;
 pub mod osc;
+pub mod registry;
+pub mod replay;
+pub mod schema;
+pub mod throttle;
+pub mod web;
@@ -0,0 +1,357 @@
+//! MIDI Timecode output, for lighting desks and video servers that chase MTC rather than LTC.
+//!
+//! The MTC follows the `TimecodeData` of the timecode source, which reaches the handlers a few
+//! dozen times per second. Between updates the time is carried on by the clock of its own
+//! thread, which sends a quarter frame message every quarter of a frame. Small drift from the
+//! timecode source is taken out gently; when the time jumps, starts or stops, a full frame
+//! message is sent instead, as receivers expect on a locate.
+use crate::{
+    audio::timecode::DEFAULT_LTC_FRAME_RATE, cbnet::CrossbeamNetwork,
+    communication::midi::CLIENT_NAME,
+};
+use common::{
+    local::{
+        config::{LogContext, LogItem, LogKind},
+        status::TimecodeState,
+    },
+    mem::smpte::TimecodeInstant,
+};
+use crossbeam_channel::{Receiver, RecvTimeoutError, Sender, unbounded};
+use midir::{MidiOutput, MidiOutputConnection};
+use std::time::{Duration, Instant};
+
+/// Frames the MTC may be off from the timecode source before it is set with a full frame
+/// message rather than pulled in.
+const RESYNC_FRAMES: f64 = 2.0;
+/// Share of the drift from the timecode source taken out with every update
+const SLEW: f64 = 0.1;
+/// Time between attempts to open the output port while it is missing
+const RECONNECT_INTERVAL: Duration = Duration::from_secs(1);
+const FRAMES_PER_DAY: i64 = 24 * 60 * 60;
+
+/// MTC rate code of an LTC frame rate. 30 fps is sent without drop frame, like the LTC.
+fn rate_code(frame_rate: u8) -> u8 {
+    match frame_rate {
+        24 => 0,
+        30 => 3,
+        _ => 1,
+    }
+}
+
+/// Hours, minutes, seconds and frames of a frame counted from midnight, wrapping at 24 hours.
+fn split(frame: i64, frame_rate: u8) -> [u8; 4] {
+    let frame_rate = frame_rate as i64;
+    let frame = frame.rem_euclid(FRAMES_PER_DAY * frame_rate);
+    let seconds = frame / frame_rate;
+    [
+        (seconds / 3600) as u8,
+        (seconds / 60 % 60) as u8,
+        (seconds % 60) as u8,
+        (frame % frame_rate) as u8,
+    ]
+}
+
+/// Frames from midnight to a time, with the fraction of the frame passed.
+fn frames(time: &TimecodeInstant) -> f64 {
+    let seconds = (time.h as i64 * 60 + time.m as i64) * 60 + time.s as i64;
+    (seconds * time.frame_rate as i64 + time.f as i64) as f64 + time.frame_progress as f64 / 65536.0
+}
+
+/// The quarter frame message of quarter `quarter` counted from midnight. A time takes eight of
+/// them over two frames, so pieces 0 to 3 are sent during even frames and 4 to 7 during the
+/// odd frame after, all carrying the even frame.
+pub fn quarter_frame(quarter: i64, frame_rate: u8) -> [u8; 2] {
+    let frame = quarter.div_euclid(4);
+    let piece = (frame.rem_euclid(2) * 4 + quarter.rem_euclid(4)) as u8;
+    let [hours, minutes, seconds, frames] = split(frame - frame.rem_euclid(2), frame_rate);
+    let nibble = match piece {
+        0 => frames & 0x0F,
+        1 => frames >> 4,
+        2 => seconds & 0x0F,
+        3 => seconds >> 4,
+        4 => minutes & 0x0F,
+        5 => minutes >> 4,
+        6 => hours & 0x0F,
+        _ => hours >> 4 | rate_code(frame_rate) << 1,
+    };
+    [0xF1, piece << 4 | nibble]
+}
+
+/// The full frame message of a frame counted from midnight, sent when the time jumps.
+pub fn full_frame(frame: i64, frame_rate: u8) -> [u8; 10] {
+    let [hours, minutes, seconds, frames] = split(frame, frame_rate);
+    [
+        0xF0,
+        0x7F,
+        0x7F,
+        0x01,
+        0x01,
+        rate_code(frame_rate) << 5 | hours,
+        minutes,
+        seconds,
+        frames,
+        0xF7,
+    ]
+}
+
+/// The time of the MTC output, carried on between updates of the timecode source.
+#[derive(Debug)]
+pub struct MtcClock {
+    frame_rate: u8,
+    running: bool,
+    /// Frames from midnight at `anchor`
+    position: f64,
+    anchor: Instant,
+    /// Last quarter frame sent, counted from midnight
+    sent: i64,
+}
+
+impl MtcClock {
+    pub fn new(now: Instant) -> Self {
+        Self {
+            frame_rate: DEFAULT_LTC_FRAME_RATE,
+            running: false,
+            position: 0.0,
+            anchor: now,
+            sent: -1,
+        }
+    }
+
+    fn position_at(&self, now: Instant) -> f64 {
+        if self.running {
+            self.position
+                + now.saturating_duration_since(self.anchor).as_secs_f64() * self.frame_rate as f64
+        } else {
+            self.position
+        }
+    }
+
+    /// Takes an update of the timecode source received at `now`. Returns a full frame message
+    /// if the time has jumped, started or stopped.
+    pub fn update(&mut self, state: TimecodeState, now: Instant) -> Option<[u8; 10]> {
+        let frame_rate = state.ltc.frame_rate;
+        let position = frames(&state.ltc);
+        let predicted = self.position_at(now);
+        let jumped = if state.running && self.running {
+            (position - predicted).abs() > RESYNC_FRAMES
+        } else {
+            state.running != self.running || position.floor() != predicted.floor()
+        };
+        if jumped || frame_rate != self.frame_rate {
+            self.frame_rate = frame_rate;
+            self.running = state.running;
+            self.position = position;
+            self.anchor = now;
+            // Carry on from the quarter frame the time is in
+            self.sent = (position * 4.0).floor() as i64 - 1;
+            return Some(full_frame(position.floor() as i64, frame_rate));
+        }
+        if self.running {
+            self.position = predicted + (position - predicted) * SLEW;
+            self.anchor = now;
+        }
+        None
+    }
+
+    /// Quarter frame messages due by `now`, and when the next one is due. Nothing is sent while
+    /// stopped, and a clock that fell behind skips to the last two frames.
+    pub fn due(&mut self, now: Instant) -> (Vec<[u8; 2]>, Option<Instant>) {
+        if !self.running {
+            return (vec![], None);
+        }
+        let quarter = (self.position_at(now) * 4.0).floor() as i64;
+        let messages = ((self.sent + 1).max(quarter - 7)..=quarter)
+            .map(|quarter| quarter_frame(quarter, self.frame_rate))
+            .collect();
+        self.sent = self.sent.max(quarter);
+        let frames_to_next = (self.sent + 1) as f64 / 4.0 - self.position;
+        let next = self.anchor + Duration::from_secs_f64(frames_to_next / self.frame_rate as f64);
+        (messages, Some(next))
+    }
+}
+
+/// Sends MTC to a MIDI output port on a thread of its own.
+pub struct MtcSender {
+    updates: Sender<(TimecodeState, Instant)>,
+}
+
+impl MtcSender {
+    /// Starts sending to the first output port whose name contains `port`, opened once it is
+    /// there.
+    pub fn new(port: String, cbnet: CrossbeamNetwork) -> Self {
+        let (tx, rx) = unbounded();
+        let thread_cbnet = cbnet.clone();
+        if let Err(err) = std::thread::Builder::new()
+            .name("clicks-mtc".to_string())
+            .spawn(move || send_mtc(&port, rx, thread_cbnet))
+        {
+            cbnet.log(LogItem::new(
+                format!("Could not start sending MTC: {err}"),
+                LogContext::Network,
+                LogKind::Error,
+            ));
+        }
+        Self { updates: tx }
+    }
+
+    /// Passes on an update of the timecode source.
+    pub fn update(&self, state: TimecodeState) {
+        let _ = self.updates.send((state, Instant::now()));
+    }
+}
+
+fn send_mtc(port: &str, updates: Receiver<(TimecodeState, Instant)>, cbnet: CrossbeamNetwork) {
+    let mut clock = MtcClock::new(Instant::now());
+    let mut connection: Option<MidiOutputConnection> = None;
+    let mut last_attempt: Option<Instant> = None;
+    let mut missing_logged = false;
+    let mut next: Option<Instant> = None;
+    loop {
+        let received = match next {
+            Some(next) => updates.recv_deadline(next),
+            None => updates.recv_timeout(RECONNECT_INTERVAL),
+        };
+        let full_frame = match received {
+            Ok((state, at)) => clock.update(state, at),
+            Err(RecvTimeoutError::Timeout) => None,
+            Err(RecvTimeoutError::Disconnected) => return,
+        };
+        let (quarter_frames, due) = clock.due(Instant::now());
+        next = due;
+
+        if connection.is_none() && last_attempt.is_none_or(|at| at.elapsed() >= RECONNECT_INTERVAL)
+        {
+            last_attempt = Some(Instant::now());
+            connection = open_port(port, &cbnet, &mut missing_logged);
+        }
+        let Some(output) = &mut connection else {
+            continue;
+        };
+        let sent = full_frame
+            .iter()
+            .map(|message| output.send(message))
+            .chain(quarter_frames.iter().map(|message| output.send(message)))
+            .all(|result| result.is_ok());
+        if !sent {
+            cbnet.log(LogItem::new(
+                format!("MTC output {port} went away."),
+                LogContext::Network,
+                LogKind::Warning,
+            ));
+            connection = None;
+        }
+    }
+}
+
+fn open_port(
+    port: &str,
+    cbnet: &CrossbeamNetwork,
+    missing_logged: &mut bool,
+) -> Option<MidiOutputConnection> {
+    let output = MidiOutput::new(CLIENT_NAME).ok()?;
+    let Some(found) = output.ports().into_iter().find(|found| {
+        output
+            .port_name(found)
+            .is_ok_and(|name| name.contains(port))
+    }) else {
+        if !*missing_logged {
+            cbnet.log(LogItem::new(
+                format!("No MIDI output named {port} to send MTC to, waiting for it."),
+                LogContext::Network,
+                LogKind::Warning,
+            ));
+            *missing_logged = true;
+        }
+        return None;
+    };
+    match output.connect(&found, "mtc") {
+        Ok(connection) => {
+            cbnet.log(LogItem::new(
+                format!("Sending MTC to {port}."),
+                LogContext::Network,
+                LogKind::Note,
+            ));
+            *missing_logged = false;
+            Some(connection)
+        }
+        Err(err) => {
+            cbnet.log(LogItem::new(
+                format!("Could not open MIDI output {port}: {err}"),
+                LogContext::Network,
+                LogKind::Warning,
+            ));
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn state(running: bool, seconds: u8, frame: u8) -> TimecodeState {
+        let mut ltc = TimecodeInstant::new(25);
+        ltc.h = 1;
+        ltc.s = seconds as _;
+        ltc.f = frame as _;
+        TimecodeState { running, ltc }
+    }
+
+    #[test]
+    fn messages() {
+        // 01:00:02:11 at 25 fps
+        let frame = (3600 + 2) * 25 + 11;
+        assert_eq!(
+            full_frame(frame, 25),
+            [0xF0, 0x7F, 0x7F, 0x01, 0x01, 0x21, 0, 2, 11, 0xF7]
+        );
+        // Eight quarter frames over frames 10 and 11, all carrying frame 10
+        let pieces: Vec<[u8; 2]> = (0..8)
+            .map(|quarter| quarter_frame((frame - 1) * 4 + quarter, 25))
+            .collect();
+        assert_eq!(
+            pieces,
+            [
+                [0xF1, 0x0A],
+                [0xF1, 0x10],
+                [0xF1, 0x22],
+                [0xF1, 0x30],
+                [0xF1, 0x40],
+                [0xF1, 0x50],
+                [0xF1, 0x61],
+                [0xF1, 0x72],
+            ]
+        );
+        // Wraps at midnight
+        assert_eq!(split(-1, 30), [23, 59, 59, 29]);
+    }
+
+    #[test]
+    fn follows_the_timecode() {
+        let start = Instant::now();
+        let mut clock = MtcClock::new(start);
+        assert!(clock.due(start).0.is_empty());
+
+        let full = clock.update(state(true, 2, 10), start).unwrap();
+        assert_eq!(full[5..9], [0x21, 0, 2, 10]);
+        assert_eq!(
+            clock.due(start).0,
+            [quarter_frame((3602 * 25 + 10) * 4, 25)]
+        );
+        // Four quarter frames per frame of 40 ms
+        let (quarters, next) = clock.due(start + Duration::from_millis(45));
+        assert_eq!(quarters.len(), 4);
+        let next = next.unwrap().duration_since(start);
+        assert!(next > Duration::from_millis(49) && next < Duration::from_millis(51));
+
+        // Small drift is pulled in, a jump is sent as a full frame
+        let later = start + Duration::from_millis(400);
+        assert!(clock.update(state(true, 2, 20), later).is_none());
+        assert!(clock.update(state(true, 5, 0), later).is_some());
+        assert!(clock.update(state(false, 5, 0), later).is_some());
+        assert!(clock.due(later).0.is_empty());
+        // A locate while stopped
+        assert!(clock.update(state(false, 5, 0), later).is_none());
+        assert!(clock.update(state(false, 8, 0), later).is_some());
+    }
+}
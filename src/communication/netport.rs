@@ -1,41 +1,78 @@
 use crate::logger;
 use common::local::config::{LogContext, LogKind};
 use local_ip_address::local_ip;
-use std::net::{SocketAddr, UdpSocket};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, UdpSocket};
 
 const BUFFER_SIZE: usize = 1024 * 64;
 
 #[derive(Debug)]
 pub struct NetworkPort {
     pub socket: UdpSocket,
+    port: usize,
     buffer: [u8; BUFFER_SIZE],
+    packets_in: u32,
+    packets_out: u32,
 }
 
 impl NetworkPort {
+    /// Opens the port on the address of the interface of the default route, or on all
+    /// interfaces if there is no network yet when the core starts.
     pub fn new(port: usize) -> Self {
-        let s = Self {
+        Self {
             buffer: [0; BUFFER_SIZE],
-            socket: UdpSocket::bind(format!(
-                "{}:{}",
-                local_ip().expect("Couldn't find IP"),
-                port
-            ))
+            port,
+            packets_in: 0,
+            packets_out: 0,
+            socket: Self::bind(
+                local_ip().unwrap_or(IpAddr::V4(Ipv4Addr::UNSPECIFIED)),
+                port,
+            )
             .expect("couldn't open local port"),
+        }
+    }
+
+    fn bind(address: IpAddr, port: usize) -> std::io::Result<UdpSocket> {
+        let socket = UdpSocket::bind(SocketAddr::new(address, port as u16))?;
+        socket.set_nonblocking(true)?;
+        Ok(socket)
+    }
+
+    /// Moves the port to the current address of the interface, after it has changed. Returns
+    /// the new local address, or None if the port did not need to move. A port open on all
+    /// interfaces never does.
+    pub fn rebind(&mut self) -> std::io::Result<Option<SocketAddr>> {
+        let bound = self.socket.local_addr()?;
+        let Ok(address) = local_ip() else {
+            return Ok(None);
         };
-        let _ = s.socket.set_nonblocking(true);
-        s
+        if bound.ip().is_unspecified() || bound.ip() == address {
+            return Ok(None);
+        }
+        self.socket = Self::bind(address, self.port)?;
+        Ok(Some(self.socket.local_addr()?))
     }
 
     pub fn recv(&mut self) -> Option<(&[u8; BUFFER_SIZE], usize, SocketAddr)> {
         match self.socket.recv_from(&mut self.buffer) {
-            Ok((amt, src)) => Some((&self.buffer, amt, src)),
+            Ok((amt, src)) => {
+                self.packets_in = self.packets_in.wrapping_add(1);
+                Some((&self.buffer, amt, src))
+            }
             Err(_) => None,
         }
     }
 
+    /// Number of packets received and sent since the last call.
+    pub fn take_packet_counts(&mut self) -> (u32, u32) {
+        (
+            std::mem::take(&mut self.packets_in),
+            std::mem::take(&mut self.packets_out),
+        )
+    }
+
     pub fn send_to(&mut self, content: &[u8], address: SocketAddr) {
         match self.socket.send_to(content, address) {
-            Ok(_) => {}
+            Ok(_) => self.packets_out = self.packets_out.wrapping_add(1),
             Err(err) => {
                 //logger::log(
                 //    format!("Subscriber send error: {err}"),
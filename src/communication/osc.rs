@@ -1,30 +1,104 @@
-use crate::communication::{interface::CommunicationInterface, netport::NetworkPort};
+use crate::audio::{
+    countdown::CountdownEvent,
+    delay::DelayAmount,
+    eq::{EQ_BANDS, EqBand},
+    follower::TempoFollowConfiguration,
+    handler::RouteMask,
+    metronome::{ClickLevels, ClickSamples, ClickSound, ClickSynthesis, ClickWaveform},
+    playback::SeekOutcome,
+    timecode::{TimecodeOutputConfiguration, TimecodeTime},
+};
+use crate::communication::{
+    extension::{CoreMessage, CoreRequest, LaunchBoundary, MAX_LOG_TAIL},
+    interface::{CommunicationInterface, RequestSource},
+    netport::NetworkPort,
+    replay::MessageCache,
+    throttle::NotificationRates,
+};
+use crate::config::{ChannelCounts, NotificationTarget};
+use crate::failover::FailoverRole;
+use crate::metrics::{MAX_METERED_INPUTS, ResourceAlarm};
+use crate::power::{Passphrase, PowerAction};
+use crate::show_extension::{
+    CueColor, CueFlag, EndOfCue, FadeEvent, FadeLength, GainPoint, MuteEvent, ZeroTarget,
+};
+use crate::timing::TimingReportFormat;
+use common::local::config::{LogContext, LogKind};
 use common::protocol::message::{LargeMessage, Message, SmallMessage};
 use common::protocol::request::{ControlAction, Request};
 use rosc::address::{Matcher, OscAddress};
 use rosc::decoder::decode_udp;
 use rosc::{OscBundle, OscError, OscMessage, OscPacket, OscTime, OscType};
+use std::collections::BTreeMap;
 use std::net::{IpAddr, Ipv4Addr, SocketAddr};
-use std::time::SystemTime;
+use std::time::{Duration, Instant, SystemTime};
+
+/// How long a log follower is sent log items after anything was last heard from it, as long as
+/// binary subscribers are kept.
+const LOG_FOLLOWER_TIMEOUT: Duration = Duration::from_secs(15 * 60);
+
+/// A client sent new log items as they are logged, see `/log/follow`.
+struct LogFollower {
+    address: SocketAddr,
+    kind: LogKind,
+    context: LogContext,
+    /// When anything was last received from the client
+    last_contact: Instant,
+}
 
 // Valid control OSC addresses:
 // /subscribe i32
 // /control/
 //      transport/
-//          start
+//          start [i32]     (after a count-in of the given bars, while playback and timecode
+//                          hold)
 //          stop
+//          stop_bar        (stop when the current bar ends)
 //          zero
 //          seek i32
 //          jump i32
+//          vamp/
+//              on          (jump mode on, vamps are taken)
+//              off
+//              next        (switch jump mode over, e.g. from a pedal)
 //      cue/
 //          +
 //          -
 //          load i32
+//          standby i32     (preload and announce a cue without starting it)
+//          cancel          (disarm the cue in standby)
+//          go              (start the cue in standby from the top)
+//          launch/
+//              bar [i32]   (take over with a cue, the next one if none is given, when the
+//                          running cue reaches its next bar line; loads it when stopped)
+//              beat [i32]  (the same on the next beat)
+//          list            (all cues of the show, replied to sender)
+//      playback/
+//          stop [f32]      (silence all playback channels, fading over the given ms, while the
+//                          transport, click and timecode carry on)
+//          stop_channel i32 [f32]  (the same for a single channel)
+//      timecode/
+//          jam i32 i32 i32 i32     (set the LTC output to hours, minutes, seconds, frames from
+//                                  the next frame on, leaving the beat position)
+//          locate i32 i32 i32 i32  (seek the current cue to the beat sending the latest
+//                                  timecode at or before hours, minutes, seconds, frames)
 //  /edit/
 //      channel/
 //          {idx}/
 //              gain f32
 //              mute bool
+//              eq/
+//                  {band} f32 f32 f32  (frequency, gain dB, Q; band 0 low shelf, 1 peak, 2 high shelf)
+//              delay/
+//                  ms f32
+//                  m f32           (distance to compensate for)
+//              label str       (name of what is patched to the output)
+//              invert bool     (invert the polarity)
+//              input/
+//                  {from} [f32]    (mix input {from} in at gain dB, no argument to remove)
+//              mix/
+//                  {port} [f32]    (sum into client output port {port} at gain dB instead of
+//                                   its own port, no argument to remove)
 //              route/
 //                  {to} bool
 //      route/
@@ -32,17 +106,130 @@ use std::time::SystemTime;
 //              {to}/
 //                  set bool
 //                  toggle
+//      input/
+//          {idx}/
+//              route/
+//                  {from} bool     (connect system capture port {from} to input {idx})
+//      cue/
+//          end i32 str     (what cue {i32} does at its end: hold, load_next, standby_next,
+//                           continue_next or loop)
+//          zero i32 [str]  (where zero goes in cue {i32}: top, last_start or previous_marker,
+//                           no str for the default)
+//          markers i32 i32...  (rehearsal marker beats of cue {i32})
+//          scene i32 [str]     (scene recalled when cue {i32} is loaded, no str for none)
+//          click i32 [f32 f32] (accent and other click levels in dB of cue {i32}, none for
+//                               the default)
+//          click_synth i32 [str f32 ...] (click synthesis of cue {i32} as click_synth below,
+//                                         none for the default)
+//          click_samples i32 [str str str] (click samples of cue {i32} as click_samples
+//                                           below, none for the default)
+//          color i32 [str]     (color of cue {i32} as #rrggbb, no str for none)
+//          flags i32 str...    (flags of cue {i32}: warning, safety or optional, none to clear)
+//          automation i32 i32 [f32 f32]... (gain automation of playback channel {i32} in cue
+//                                           {i32}, as beat and gain in dB per point, none to
+//                                           remove it)
+//          mutes i32 [i32 i32 bool]... (mute events of cue {i32}, as beat, channel and mute
+//                                       per event, none to remove them)
+//          fades i32 [i32 i32 f32 f32 f32 str]... (fade events of cue {i32}, as beat, playback
+//                                                  channel, gain in dB from, or nil for the
+//                                                  gain the channel is at, gain in dB to,
+//                                                  length, and beats or ms per event, none to
+//                                                  remove them)
+//      zero str            (where zero goes by default)
+//      click f32 f32       (default accent and other click levels in dB)
+//      click_synth str f32 f32 f32 f32 f32 f32 f32 [str str str] (click waveform: sine,
+//                                                   square, triangle or noise; decay ms; then
+//                                                   frequency Hz and length ms of the strong,
+//                                                   medium and weak click; then optionally the
+//                                                   waveform of each click, nil for the
+//                                                   waveform of all clicks)
+//      click_samples [str str str] (WAV files of the show directory played instead of the
+//                                   strong, medium and weak click, nil or empty for the
+//                                   synthesized click)
+//      follow bool i32 i32 f32 (tempo follow: enabled, input, max deviation %, threshold)
+//      ltc f32 i32 bool    (LTC output: level 0.0 -- 1.0, edge smoothing in samples, invert)
+//      ramp f32            (ms over which channel gain changes are ramped, 0 for instant)
+//      stop_fade f32       (ms of fade-out when the transport stops, 0 to cut at once)
+//      device_name str     (name of this unit)
+//      beat_phase i32      (beat phase messages per second, 0 for none)
+//      rates i32 i32 i32   (max transport, beat, timecode messages per second, 0 for no limit)
 //      config/
 //          ...
+//  /log/
+//      tail i32 [str [str]]    (last n log items, optionally of one kind and one context such
+//                               as Warning and Network, nil or "" for any, replied to sender)
+//      follow bool [str [str]] (send every new log item, optionally of one kind and one
+//                               context, to sender until it is silent for 15 minutes)
+//  /audit/
+//      tail i32            (last n audit trail entries, replied to sender)
+//  /runlog/
+//      tail i32            (last n run log entries of the performance, replied to sender)
+//      new                 (start the run log of a new performance)
+//  /query/                 (current state under /message/ as last sent to subscribers, replied
+//                           to sender without subscribing; nothing until the core has sent it)
+//      transport           (/message/transport/...)
+//      cue                 (/message/cue/...)
+//      channel/
+//          {idx}/
+//              gain        (/message/channel/{idx}/gain)
+//  /outputs                (output processing settings, replied to sender)
+//  /inputs                 (capture inputs and their routing, replied to sender)
+//  /follow                 (tempo follow configuration, replied to sender)
+//  /ltc                    (LTC output configuration, replied to sender)
+//  /click_synth            (click synthesis, replied to sender)
+//  /click_samples          (click samples, replied to sender)
+//  /rates                  (notification rate limits, replied to sender)
+//  /failover/
+//      takeover            (make a backup unit take over from its primary)
+//      handback            (make a backup unit that took over mirror its primary again)
+//      role                (failover role, replied to sender)
+//  /show/
+//      reload              (reload the show from disk)
+//      dismiss             (keep the loaded show after the show file changed)
+//      list                (shows of the show directory, replied to sender)
+//      load str            (switch to another show of the show directory, transport stopped)
+//  /scene/
+//      store str           (store gains, mutes and routing as a named scene)
+//      recall str [f32]    (recall a scene, fading the gains over f32 ms)
+//      delete str
+//  /scenes                 (names of the stored scenes, replied to sender)
+//  /routing/
+//      preset/
+//          store str       (store the output routing as a named routing preset)
+//          load str        (route the outputs as in a routing preset)
+//          delete str
+//      presets             (names of the stored routing presets, replied to sender)
+//  /macro/
+//      run str             (run a macro defined in the core configuration)
+//      stop str            (stop requesting the remaining steps of a running macro)
+//  /macros                 (names of the defined macros, replied to sender)
+//  /timing str             (write the beat timing report as csv or json, or the click
+//                           track as mid, into the show directory, path replied to sender)
+//  /schema                 (JSON description of all requests and messages, replied to sender
+//                           in chunks)
+//  /profile/
+//      record bool         (record the duration of every process cycle, from a new profile)
+//      dump                (write the profile into the log directory, path replied to sender)
+//  /power/
+//      restart [str]       (arm a restart of the core, with the passphrase if one is set;
+//                           confirmation code replied to sender)
+//      reboot [str]        (arm a reboot of the unit, the same way)
+//      confirm i32         (carry out what this sender armed, with the replied code)
 //
 // Valid message (response) OSC addresses:
 //  /message/
 //      transport/
-//          running
+//          running bool
+//          vamp bool       (jump mode, sent on every change)
+//          count_in i32    (count-in beats left before the transport starts, counting the one
+//                          clicked; 0 when the count-in is over)
 //          beat/
 //              index
 //              count
 //              bar
+//              phase i32 f32   (beat index, fraction of the beat passed)
+//              accents i32 str...  (position of the beat in its bar, then the accent of each
+//                                   beat of the bar: strong, medium or weak)
 //          timecode/
 //              h
 //              m
@@ -52,12 +239,95 @@ use std::time::SystemTime;
 //          length
 //          ident
 //          name
+//          standby i32     (cue in standby, -1 for none)
+//          ended i32       (cue the transport ran past the end of)
+//          go i32          (cue started from standby)
+//          launched i32    (cue launched on a beat or bar line)
+//          color i32 str   (color of the current cue as #rrggbb, empty for none, on every
+//                           cue change)
+//          flags i32 str...    (flags of the current cue, on every cue change)
+//          upcoming i32 i32 [str i32 i32]... (cue, beat, then per upcoming event its kind,
+//                                             beat and target: channel or jump destination)
+//          list/
+//              count i32   (number of cues in the show, first in the reply bundle)
+//              entry i32 str str i32   (index, ident, name and number of beats of a cue)
+//      log str
+//      audit str
+//      runlog str
+//      countdown/
+//          cue f32         (seconds left in the current cue)
+//          next str f32 i32 i32 i32 (next event: playback, timecode, jump or none; seconds
+//                                    until it, beat, channel and clip of playback, else -1)
+//      device str          (name of this unit, sent every second)
+//      failover str bool   (role: primary, backup or took_over; whether the primary is heard,
+//                           of a backup)
+//      cluster i32 i32 bool [i64] (channels rendered as follower: first and count; whether the
+//                                  conductor is heard; drift behind it in us, once measured)
+//      timing str          (path of the written timing report)
+//      schema i32 i32 str  (chunk of the protocol schema: index, count and JSON)
+//      profile str         (path of the written process profile)
+//      click_synth str f32 f32 f32 f32 f32 f32 f32 str str str (click synthesis, as set)
+//      click_samples str str str (click samples as set, nil for a synthesized click)
+//      power str i32       (restart or reboot armed, and the code confirming it)
+//      show/
+//          changed bool    (show file changed on disk and not yet reloaded)
+//          verified i32 [str]... (files checked against the manifest, then a description of
+//                                 every missing or damaged file)
+//          list str str...     (the loaded show, then all shows of the show directory, also
+//                               sent when another show is loaded)
+//      scenes str...       (names of the stored scenes)
+//      scene/
+//          recalled str
+//      macros str...       (names of the defined macros)
+//      fault str str str   (thread, message, location)
+//      rejected str str    (request, reason)
+//      failed str str      (request, reason)
+//      media/
+//          unavailable i32 i32 str (channel, clip, error; the clip plays as silence)
+//      seek i32 i32 i32 i32 str [i32] (a seek left a playback channel outside loaded clip
+//                                      data: channel, beat, clip, sample, then not_loaded, or
+//                                      past_end and the length of the clip)
+//      channel/
+//          {idx}/
+//              gain f32            (sent on every change, from any interface)
+//              mute bool           (sent on every change, from any interface)
+//              eq/
+//                  {band} f32 f32 f32
+//              delay f32           (ms)
+//              invert bool
+//              label str
+//              input/
+//                  {from} f32      (gain dB of a pass-through input)
+//              mix/
+//                  {port} f32      (gain dB the output is summed into client port {port} at)
+//              route i32 i32       (bitmasks of connected system ports 0-31 and 32-63)
+//      input/
+//          {idx}/
+//              route i32 i32       (bitmasks of connected system capture ports 0-31 and 32-63)
+//      routing/
+//          drift str       (a connection set up by the core that another JACK client changed,
+//                           and whether it was put back; one message each)
+//          presets str...  (names of the stored routing presets)
+//      follow bool i32 i32 f32
+//      ltc f32 i32 bool
+//      rates i32 i32 i32
+//      metrics/
+//          loop_freq
+//          dsp_mean_us
+//          dsp_max_us
+//          input_peak/
+//              {idx} f32
+//          disk_free_mb i32 i32    (free space for the show and the logs, -1 if unknown)
+//          rss_mb i32              (memory used by the core, -1 if unknown)
+//      link bool [str]     (whether the network is up, and the address of this unit on it)
+//      alarms str...       (resources running out: show_disk_low, log_disk_low or
+//                           memory_high, sent when an alarm is raised or cleared)
 //
 //
 
 pub struct OscNetHandler {
     port: NetworkPort,
-    input_queue: Vec<Request>,
+    input_queue: Vec<(Request, RequestSource)>,
     subscribers: Vec<SocketAddr>,
     bundle_pool: Vec<OscBundle>,
     matcher: Matcher,
@@ -65,35 +335,106 @@ pub struct OscNetHandler {
     address_space: String,
     args: Vec<OscType>,
     last_recv_src: SocketAddr,
+    core_input_queue: Vec<(CoreRequest, RequestSource)>,
+    log_followers: Vec<LogFollower>,
+    cache: MessageCache,
+    /// Latest core message sent to each address, for queries
+    core_cache: BTreeMap<String, OscMessage>,
+    /// Sizes the address space of channels and routes
+    channel_counts: ChannelCounts,
+    /// Clients sent notifications without subscribing
+    targets: Vec<NotificationTarget>,
 }
 
 impl CommunicationInterface for OscNetHandler {
-    fn get_inputs(&mut self, _limit: usize) -> Vec<Request> {
-        let mut inputs: Vec<Request> = vec![];
+    fn name(&self) -> &'static str {
+        "osc"
+    }
+
+    fn take_packet_counts(&mut self) -> (u32, u32) {
+        self.port.take_packet_counts()
+    }
+
+    fn rebind(&mut self) -> std::io::Result<Option<SocketAddr>> {
+        self.port.rebind()
+    }
+
+    fn get_inputs(&mut self, _limit: usize) -> Vec<(Request, RequestSource)> {
+        let mut inputs: Vec<(Request, RequestSource)> = vec![];
         inputs.append(&mut self.input_queue);
         while let Some((buf, amt, src)) = self.port.recv() {
             let data = *buf;
-            if let Ok(mut cc) = self.handle_bytes(&data, amt) {
-                self.input_queue.append(&mut cc);
-            }
             self.last_recv_src = src;
+            for follower in &mut self.log_followers {
+                if follower.address == src {
+                    follower.last_contact = Instant::now();
+                }
+            }
+            if let Ok(cc) = self.handle_bytes(&data, amt) {
+                let source = self.source();
+                self.input_queue
+                    .extend(cc.into_iter().map(|request| (request, source.clone())));
+            }
         }
         inputs
     }
 
     fn notify(&mut self, message: Message) {
-        for msg in self.notif_to_osc(message) {
+        if let Message::Large(LargeMessage::Log(item)) = &message {
+            let msg = OscMessage {
+                addr: "/message/log".to_string(),
+                args: vec![OscType::String(format!("{}: {}", item.kind, item.message))],
+            };
+            self.expire_log_followers(Instant::now());
+            let followers: Vec<SocketAddr> = self
+                .log_followers
+                .iter()
+                .filter(|follower| {
+                    follower.kind.intersects(item.kind) && follower.context.intersects(item.context)
+                })
+                .map(|follower| follower.address)
+                .collect();
+            for follower in followers {
+                self.send_packet_to(&OscPacket::Message(msg.clone()), follower);
+            }
+            self.send_to_targets(&[msg], |target| target.wants(&message));
+            return;
+        }
+        self.cache.store(&message);
+        let messages = self.notif_to_osc(message.clone());
+        self.send_to_targets(&messages, |target| target.wants(&message));
+        for msg in messages {
+            self.send_message(msg);
+        }
+    }
+
+    fn notify_core(&mut self, message: &CoreMessage) {
+        let messages = Self::core_to_osc(message.clone());
+        for msg in &messages {
+            self.core_cache.insert(msg.addr.clone(), msg.clone());
+        }
+        self.send_to_targets(&messages, NotificationTarget::wants_core);
+        for msg in messages {
             self.send_message(msg);
         }
     }
 
+    fn get_core_inputs(&mut self) -> Vec<(CoreRequest, RequestSource)> {
+        std::mem::take(&mut self.core_input_queue)
+    }
+
+    fn reply(&mut self, to: SocketAddr, message: CoreMessage) {
+        let messages = Self::core_to_osc(message);
+        self.reply_messages(to, messages);
+    }
+
     fn notify_multiple(&mut self, _messages: Vec<Message>) {
         todo!()
     }
 }
 
 impl OscNetHandler {
-    pub fn new(port: usize) -> Self {
+    pub fn new(port: usize, channel_counts: ChannelCounts) -> Self {
         Self {
             matcher: Matcher::new("/null").expect("Constant pattern cannot fail"),
             port: NetworkPort::new(port),
@@ -104,9 +445,121 @@ impl OscNetHandler {
             address_space: String::new(),
             args: vec![],
             last_recv_src: SocketAddr::new(IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0)), 0),
+            core_input_queue: vec![],
+            log_followers: vec![],
+            cache: MessageCache::new(),
+            core_cache: BTreeMap::new(),
+            channel_counts,
+            targets: vec![],
+        }
+    }
+
+    /// Sends notifications to fixed clients as well as to subscribers.
+    pub fn with_targets(mut self, targets: Vec<NotificationTarget>) -> Self {
+        self.targets = targets;
+        self
+    }
+
+    /// Sends messages to the fixed clients that want them.
+    fn send_to_targets(
+        &mut self,
+        messages: &[OscMessage],
+        wants: impl Fn(&NotificationTarget) -> bool,
+    ) {
+        let targets: Vec<SocketAddr> = self
+            .targets
+            .iter()
+            .filter(|target| wants(target))
+            .map(|target| target.address)
+            .collect();
+        for target in targets {
+            for msg in messages {
+                self.send_packet_to(&OscPacket::Message(msg.clone()), target);
+            }
         }
     }
 
+    fn source(&self) -> RequestSource {
+        RequestSource {
+            handler: self.name(),
+            handler_index: None,
+            address: self.last_recv_src,
+            identifier: None,
+        }
+    }
+
+    /// Hours, minutes, seconds and frames in the first four arguments.
+    fn timecode_args(&mut self) -> Result<TimecodeTime, OscError> {
+        let (Some(hours), Some(minutes), Some(seconds), Some(frames)) = (
+            self.get_arg(0).int(),
+            self.get_arg(1).int(),
+            self.get_arg(2).int(),
+            self.get_arg(3).int(),
+        ) else {
+            return Err(OscError::BadArg("timecode".to_string()));
+        };
+        let field = |value: i32| value.clamp(0, u8::MAX as i32) as u8;
+        Ok(TimecodeTime {
+            hours: field(hours),
+            minutes: field(minutes),
+            seconds: field(seconds),
+            frames: field(frames),
+        })
+    }
+
+    /// Click synthesis in the arguments from `first` on, as `/edit/click_synth` takes them.
+    fn click_synthesis_args(&mut self, first: usize) -> Result<ClickSynthesis, OscError> {
+        let (Some(waveform), Some(decay_ms)) = (
+            self.get_arg(first)
+                .string()
+                .and_then(|name| ClickWaveform::from_name(&name)),
+            self.get_arg(first + 1).float(),
+        ) else {
+            return Err(OscError::BadArg("click synthesis".to_string()));
+        };
+        let mut clicks = [ClickSound {
+            frequency_hz: 0.0,
+            length_ms: 0.0,
+            waveform: None,
+        }; 3];
+        for (idx, click) in clicks.iter_mut().enumerate() {
+            let (Some(frequency_hz), Some(length_ms)) = (
+                self.get_arg(first + 2 + idx * 2).float(),
+                self.get_arg(first + 3 + idx * 2).float(),
+            ) else {
+                return Err(OscError::BadArg("click synthesis".to_string()));
+            };
+            let waveform = match self.get_arg(first + 8 + idx) {
+                OscType::Nil => None,
+                arg => Some(
+                    arg.string()
+                        .and_then(|name| ClickWaveform::from_name(&name))
+                        .ok_or(OscError::BadArg("click waveform".to_string()))?,
+                ),
+            };
+            *click = ClickSound {
+                frequency_hz,
+                length_ms,
+                waveform,
+            };
+        }
+        Ok(ClickSynthesis {
+            waveform,
+            clicks,
+            decay_ms,
+        })
+    }
+
+    /// Click samples in the three arguments from `first` on, nil or empty for a synthesized
+    /// click.
+    fn click_samples_args(&mut self, first: usize) -> ClickSamples {
+        std::array::from_fn(|idx| {
+            self.get_arg(first + idx)
+                .string()
+                .filter(|sample| !sample.is_empty())
+        })
+    }
+
     fn handle_bytes(&mut self, buf: &[u8], amt: usize) -> Result<Vec<Request>, OscError> {
         let (_, packet) = decode_udp(&buf[..amt])?;
         self.handle_packet(packet)
@@ -174,17 +627,743 @@ impl OscNetHandler {
             "control" => match self.step_address() {
                 "transport" => self.addr_control_transport_(),
                 "cue" => self.addr_control_cue_(),
+                "timecode" => match self.step_address() {
+                    "jam" => {
+                        let time = self.timecode_args()?;
+                        let source = self.source();
+                        self.core_input_queue
+                            .push((CoreRequest::JamTimecode(time), source));
+                        Ok(vec![])
+                    }
+                    "locate" => {
+                        let time = self.timecode_args()?;
+                        let source = self.source();
+                        self.core_input_queue
+                            .push((CoreRequest::LocateTimecode(time), source));
+                        Ok(vec![])
+                    }
+                    _ => Err(OscError::Unimplemented),
+                },
+                "playback" => match self.step_address() {
+                    "stop" => {
+                        let fade_ms = self.get_arg(0).float();
+                        let source = self.source();
+                        self.core_input_queue
+                            .push((CoreRequest::StopAllPlayback { fade_ms }, source));
+                        Ok(vec![])
+                    }
+                    "stop_channel" => {
+                        let Some(channel) = self.get_arg(0).int() else {
+                            return Err(OscError::BadArg("channel index".to_string()));
+                        };
+                        let fade_ms = self.get_arg(1).float();
+                        let source = self.source();
+                        self.core_input_queue.push((
+                            CoreRequest::StopPlaybackChannel {
+                                channel: channel.clamp(0, u8::MAX as i32) as u8,
+                                fade_ms,
+                            },
+                            source,
+                        ));
+                        Ok(vec![])
+                    }
+                    _ => Err(OscError::Unimplemented),
+                },
                 _ => Err(OscError::Unimplemented),
             },
             "edit" => match self.step_address() {
                 "channel" => self.addr_edit_channel_(),
                 "config" => self.addr_edit_config_(),
+                "input" => self.addr_edit_input_(),
+                "cue" => match self.step_address() {
+                    "end" => {
+                        let (Some(cue), Some(end)) = (
+                            self.get_arg(0).int(),
+                            self.get_arg(1)
+                                .string()
+                                .and_then(|name| EndOfCue::from_name(&name)),
+                        ) else {
+                            return Err(OscError::BadArg("end of cue".to_string()));
+                        };
+                        let source = self.source();
+                        self.core_input_queue.push((
+                            CoreRequest::SetCueEnd {
+                                cue: cue.clamp(0, u8::MAX as i32) as u8,
+                                end,
+                            },
+                            source,
+                        ));
+                        Ok(vec![])
+                    }
+                    "zero" => {
+                        let Some(cue) = self.get_arg(0).int() else {
+                            return Err(OscError::BadArg("cue index".to_string()));
+                        };
+                        let target = match self.get_arg(1).string() {
+                            Some(name) => Some(
+                                ZeroTarget::from_name(&name)
+                                    .ok_or(OscError::BadArg("zero target".to_string()))?,
+                            ),
+                            None => None,
+                        };
+                        let source = self.source();
+                        self.core_input_queue.push((
+                            CoreRequest::SetZeroTarget {
+                                cue: Some(cue.clamp(0, u8::MAX as i32) as u8),
+                                target,
+                            },
+                            source,
+                        ));
+                        Ok(vec![])
+                    }
+                    "click" => {
+                        let Some(cue) = self.get_arg(0).int() else {
+                            return Err(OscError::BadArg("cue index".to_string()));
+                        };
+                        let levels = match (self.get_arg(1).float(), self.get_arg(2).float()) {
+                            (Some(accent_db), Some(beat_db)) => {
+                                Some(ClickLevels { accent_db, beat_db })
+                            }
+                            (None, None) => None,
+                            _ => return Err(OscError::BadArg("click levels".to_string())),
+                        };
+                        let source = self.source();
+                        self.core_input_queue.push((
+                            CoreRequest::SetClickLevels {
+                                cue: Some(cue.clamp(0, u8::MAX as i32) as u8),
+                                levels,
+                            },
+                            source,
+                        ));
+                        Ok(vec![])
+                    }
+                    "click_synth" => {
+                        let Some(cue) = self.get_arg(0).int() else {
+                            return Err(OscError::BadArg("cue index".to_string()));
+                        };
+                        let synthesis = match self.get_arg(1) {
+                            OscType::Nil => None,
+                            _ => Some(self.click_synthesis_args(1)?),
+                        };
+                        let source = self.source();
+                        self.core_input_queue.push((
+                            CoreRequest::SetCueClickSynthesis {
+                                cue: cue.clamp(0, u8::MAX as i32) as u8,
+                                synthesis,
+                            },
+                            source,
+                        ));
+                        Ok(vec![])
+                    }
+                    "click_samples" => {
+                        let Some(cue) = self.get_arg(0).int() else {
+                            return Err(OscError::BadArg("cue index".to_string()));
+                        };
+                        let samples = match self.args.len() {
+                            1 => None,
+                            _ => Some(self.click_samples_args(1)),
+                        };
+                        let source = self.source();
+                        self.core_input_queue.push((
+                            CoreRequest::SetClickSamples {
+                                cue: Some(cue.clamp(0, u8::MAX as i32) as u8),
+                                samples,
+                            },
+                            source,
+                        ));
+                        Ok(vec![])
+                    }
+                    "scene" => {
+                        let Some(cue) = self.get_arg(0).int() else {
+                            return Err(OscError::BadArg("cue index".to_string()));
+                        };
+                        let source = self.source();
+                        self.core_input_queue.push((
+                            CoreRequest::SetCueScene {
+                                cue: cue.clamp(0, u8::MAX as i32) as u8,
+                                scene: self.get_arg(1).string(),
+                            },
+                            source,
+                        ));
+                        Ok(vec![])
+                    }
+                    "color" => {
+                        let Some(cue) = self.get_arg(0).int() else {
+                            return Err(OscError::BadArg("cue index".to_string()));
+                        };
+                        let color = match self.get_arg(1).string() {
+                            Some(hex) => Some(
+                                CueColor::from_hex(&hex)
+                                    .ok_or(OscError::BadArg("cue color".to_string()))?,
+                            ),
+                            None => None,
+                        };
+                        let source = self.source();
+                        self.core_input_queue.push((
+                            CoreRequest::SetCueColor {
+                                cue: cue.clamp(0, u8::MAX as i32) as u8,
+                                color,
+                            },
+                            source,
+                        ));
+                        Ok(vec![])
+                    }
+                    "flags" => {
+                        let Some(cue) = self.get_arg(0).int() else {
+                            return Err(OscError::BadArg("cue index".to_string()));
+                        };
+                        let flags = self
+                            .args
+                            .iter()
+                            .skip(1)
+                            .map(|arg| {
+                                arg.clone()
+                                    .string()
+                                    .and_then(|name| CueFlag::from_name(&name))
+                                    .ok_or(OscError::BadArg("cue flag".to_string()))
+                            })
+                            .collect::<Result<Vec<_>, _>>()?;
+                        let source = self.source();
+                        self.core_input_queue.push((
+                            CoreRequest::SetCueFlags {
+                                cue: cue.clamp(0, u8::MAX as i32) as u8,
+                                flags,
+                            },
+                            source,
+                        ));
+                        Ok(vec![])
+                    }
+                    "automation" => {
+                        let (Some(cue), Some(channel)) =
+                            (self.get_arg(0).int(), self.get_arg(1).int())
+                        else {
+                            return Err(OscError::BadArg("cue and channel index".to_string()));
+                        };
+                        if self.args.len() % 2 != 0 {
+                            return Err(OscError::BadArg("automation point".to_string()));
+                        }
+                        let points = self.args[2..]
+                            .chunks(2)
+                            .map(|point| {
+                                match (point[0].clone().float(), point[1].clone().float()) {
+                                    (Some(beat), Some(gain_db)) => Ok(GainPoint { beat, gain_db }),
+                                    _ => Err(OscError::BadArg("automation point".to_string())),
+                                }
+                            })
+                            .collect::<Result<Vec<_>, _>>()?;
+                        let source = self.source();
+                        self.core_input_queue.push((
+                            CoreRequest::SetGainAutomation {
+                                cue: cue.clamp(0, u8::MAX as i32) as u8,
+                                channel: channel.clamp(0, u16::MAX as i32) as u16,
+                                points,
+                            },
+                            source,
+                        ));
+                        Ok(vec![])
+                    }
+                    "mutes" => {
+                        let Some(cue) = self.get_arg(0).int() else {
+                            return Err(OscError::BadArg("cue index".to_string()));
+                        };
+                        if (self.args.len() - 1) % 3 != 0 {
+                            return Err(OscError::BadArg("mute event".to_string()));
+                        }
+                        let events = self.args[1..]
+                            .chunks(3)
+                            .map(|event| {
+                                match (
+                                    event[0].clone().int(),
+                                    event[1].clone().int(),
+                                    event[2].clone().bool(),
+                                ) {
+                                    (Some(beat), Some(channel), Some(muted)) => Ok(MuteEvent {
+                                        beat: beat.clamp(0, u16::MAX as i32) as u16,
+                                        channel: channel.clamp(0, u8::MAX as i32) as u8,
+                                        muted,
+                                    }),
+                                    _ => Err(OscError::BadArg("mute event".to_string())),
+                                }
+                            })
+                            .collect::<Result<Vec<_>, _>>()?;
+                        let source = self.source();
+                        self.core_input_queue.push((
+                            CoreRequest::SetMuteEvents {
+                                cue: cue.clamp(0, u8::MAX as i32) as u8,
+                                events,
+                            },
+                            source,
+                        ));
+                        Ok(vec![])
+                    }
+                    "fades" => {
+                        let Some(cue) = self.get_arg(0).int() else {
+                            return Err(OscError::BadArg("cue index".to_string()));
+                        };
+                        if (self.args.len() - 1) % 6 != 0 {
+                            return Err(OscError::BadArg("fade event".to_string()));
+                        }
+                        let events = self.args[1..]
+                            .chunks(6)
+                            .map(|event| {
+                                let from_db = match &event[2] {
+                                    OscType::Nil => Some(None),
+                                    from => from.clone().float().map(Some),
+                                };
+                                let length =
+                                    match (event[4].clone().float(), event[5].clone().string()) {
+                                        (Some(length), Some(unit)) if unit == "beats" => {
+                                            Some(FadeLength::Beats(length))
+                                        }
+                                        (Some(length), Some(unit)) if unit == "ms" => {
+                                            Some(FadeLength::Ms(length))
+                                        }
+                                        _ => None,
+                                    };
+                                match (
+                                    event[0].clone().int(),
+                                    event[1].clone().int(),
+                                    from_db,
+                                    event[3].clone().float(),
+                                    length,
+                                ) {
+                                    (
+                                        Some(beat),
+                                        Some(channel),
+                                        Some(from_db),
+                                        Some(to_db),
+                                        Some(length),
+                                    ) => Ok(FadeEvent {
+                                        beat: beat.clamp(0, u16::MAX as i32) as u16,
+                                        channel: channel.clamp(0, u16::MAX as i32) as u16,
+                                        from_db,
+                                        to_db,
+                                        length,
+                                    }),
+                                    _ => Err(OscError::BadArg("fade event".to_string())),
+                                }
+                            })
+                            .collect::<Result<Vec<_>, _>>()?;
+                        let source = self.source();
+                        self.core_input_queue.push((
+                            CoreRequest::SetFadeEvents {
+                                cue: cue.clamp(0, u8::MAX as i32) as u8,
+                                events,
+                            },
+                            source,
+                        ));
+                        Ok(vec![])
+                    }
+                    "markers" => {
+                        let Some(cue) = self.get_arg(0).int() else {
+                            return Err(OscError::BadArg("cue index".to_string()));
+                        };
+                        let markers = self
+                            .args
+                            .iter()
+                            .skip(1)
+                            .filter_map(|arg| arg.clone().int())
+                            .map(|beat| beat.clamp(0, u16::MAX as i32) as u16)
+                            .collect();
+                        let source = self.source();
+                        self.core_input_queue.push((
+                            CoreRequest::SetCueMarkers {
+                                cue: cue.clamp(0, u8::MAX as i32) as u8,
+                                markers,
+                            },
+                            source,
+                        ));
+                        Ok(vec![])
+                    }
+                    _ => Err(OscError::Unimplemented),
+                },
+                "zero" => {
+                    let Some(target) = self
+                        .get_arg(0)
+                        .string()
+                        .and_then(|name| ZeroTarget::from_name(&name))
+                    else {
+                        return Err(OscError::BadArg("zero target".to_string()));
+                    };
+                    let source = self.source();
+                    self.core_input_queue.push((
+                        CoreRequest::SetZeroTarget {
+                            cue: None,
+                            target: Some(target),
+                        },
+                        source,
+                    ));
+                    Ok(vec![])
+                }
+                "click" => {
+                    let (Some(accent_db), Some(beat_db)) =
+                        (self.get_arg(0).float(), self.get_arg(1).float())
+                    else {
+                        return Err(OscError::BadArg("click levels".to_string()));
+                    };
+                    let source = self.source();
+                    self.core_input_queue.push((
+                        CoreRequest::SetClickLevels {
+                            cue: None,
+                            levels: Some(ClickLevels { accent_db, beat_db }),
+                        },
+                        source,
+                    ));
+                    Ok(vec![])
+                }
+                "click_synth" => {
+                    let synthesis = self.click_synthesis_args(0)?;
+                    let source = self.source();
+                    self.core_input_queue
+                        .push((CoreRequest::SetClickSynthesis(synthesis), source));
+                    Ok(vec![])
+                }
+                "click_samples" => {
+                    let samples = self.click_samples_args(0);
+                    let source = self.source();
+                    self.core_input_queue.push((
+                        CoreRequest::SetClickSamples {
+                            cue: None,
+                            samples: Some(samples),
+                        },
+                        source,
+                    ));
+                    Ok(vec![])
+                }
+                "rates" => {
+                    let (Some(transport), Some(beat), Some(timecode)) = (
+                        self.get_arg(0).int(),
+                        self.get_arg(1).int(),
+                        self.get_arg(2).int(),
+                    ) else {
+                        return Err(OscError::BadArg("notification rates".to_string()));
+                    };
+                    let source = self.source();
+                    self.core_input_queue.push((
+                        CoreRequest::SetNotificationRates(NotificationRates {
+                            transport: transport.clamp(0, u8::MAX as i32) as u8,
+                            beat: beat.clamp(0, u8::MAX as i32) as u8,
+                            timecode: timecode.clamp(0, u8::MAX as i32) as u8,
+                        }),
+                        source,
+                    ));
+                    Ok(vec![])
+                }
+                "stop_fade" => {
+                    let Some(fade_ms) = self.get_arg(0).float() else {
+                        return Err(OscError::BadArg("stop fade".to_string()));
+                    };
+                    let source = self.source();
+                    self.core_input_queue
+                        .push((CoreRequest::SetStopFade { fade_ms }, source));
+                    Ok(vec![])
+                }
+                "device_name" => {
+                    let Some(name) = self.get_arg(0).string() else {
+                        return Err(OscError::BadArg("device name".to_string()));
+                    };
+                    let source = self.source();
+                    self.core_input_queue
+                        .push((CoreRequest::SetDeviceName { name }, source));
+                    Ok(vec![])
+                }
+                "ramp" => {
+                    let Some(ramp_ms) = self.get_arg(0).float() else {
+                        return Err(OscError::BadArg("gain ramp".to_string()));
+                    };
+                    let source = self.source();
+                    self.core_input_queue
+                        .push((CoreRequest::SetGainRamp { ramp_ms }, source));
+                    Ok(vec![])
+                }
+                "beat_phase" => {
+                    let Some(rate_hz) = self.get_arg(0).int() else {
+                        return Err(OscError::BadArg("beat phase rate".to_string()));
+                    };
+                    let source = self.source();
+                    self.core_input_queue.push((
+                        CoreRequest::SetBeatPhaseRate(rate_hz.clamp(0, u8::MAX as i32) as u8),
+                        source,
+                    ));
+                    Ok(vec![])
+                }
+                "follow" => {
+                    let (Some(enabled), Some(input), Some(max_deviation), Some(threshold)) = (
+                        self.get_arg(0).bool(),
+                        self.get_arg(1).int(),
+                        self.get_arg(2).int(),
+                        self.get_arg(3).float(),
+                    ) else {
+                        return Err(OscError::BadArg("tempo follow".to_string()));
+                    };
+                    let source = self.source();
+                    self.core_input_queue.push((
+                        CoreRequest::SetTempoFollow(TempoFollowConfiguration {
+                            enabled,
+                            input: input as u8,
+                            max_deviation_percent: max_deviation as u8,
+                            threshold,
+                        }),
+                        source,
+                    ));
+                    Ok(vec![])
+                }
+                "ltc" => {
+                    let (Some(level), Some(smoothing), Some(invert)) = (
+                        self.get_arg(0).float(),
+                        self.get_arg(1).int(),
+                        self.get_arg(2).bool(),
+                    ) else {
+                        return Err(OscError::BadArg("LTC output".to_string()));
+                    };
+                    let source = self.source();
+                    self.core_input_queue.push((
+                        CoreRequest::SetTimecodeOutput(TimecodeOutputConfiguration {
+                            level,
+                            smoothing: smoothing.clamp(0, u8::MAX as i32) as u8,
+                            invert,
+                        }),
+                        source,
+                    ));
+                    Ok(vec![])
+                }
+                _ => Err(OscError::Unimplemented),
+            },
+            "log" => self.addr_log_(),
+            "rates" => {
+                let source = self.source();
+                self.core_input_queue
+                    .push((CoreRequest::GetNotificationRates, source));
+                Ok(vec![])
+            }
+            "follow" => {
+                let source = self.source();
+                self.core_input_queue
+                    .push((CoreRequest::GetTempoFollow, source));
+                Ok(vec![])
+            }
+            "ltc" => {
+                let source = self.source();
+                self.core_input_queue
+                    .push((CoreRequest::GetTimecodeOutput, source));
+                Ok(vec![])
+            }
+            "inputs" => {
+                let source = self.source();
+                self.core_input_queue.push((CoreRequest::GetInputs, source));
+                Ok(vec![])
+            }
+            "click_synth" => {
+                let source = self.source();
+                self.core_input_queue
+                    .push((CoreRequest::GetClickSynthesis, source));
+                Ok(vec![])
+            }
+            "click_samples" => {
+                let source = self.source();
+                self.core_input_queue
+                    .push((CoreRequest::GetClickSamples, source));
+                Ok(vec![])
+            }
+            "failover" => {
+                let request = match self.step_address() {
+                    "takeover" => CoreRequest::TakeOver,
+                    "handback" => CoreRequest::HandBack,
+                    "role" => CoreRequest::GetFailoverRole,
+                    _ => return Err(OscError::Unimplemented),
+                };
+                let source = self.source();
+                self.core_input_queue.push((request, source));
+                Ok(vec![])
+            }
+            "show" => match self.step_address() {
+                "reload" => Ok(vec![Request::Initialize]),
+                "dismiss" => {
+                    let source = self.source();
+                    self.core_input_queue
+                        .push((CoreRequest::DismissShowChange, source));
+                    Ok(vec![])
+                }
+                "list" => {
+                    let source = self.source();
+                    self.core_input_queue.push((CoreRequest::ListShows, source));
+                    Ok(vec![])
+                }
+                "load" => {
+                    let Some(name) = self.get_arg(0).string() else {
+                        return Err(OscError::BadArg("show name".to_string()));
+                    };
+                    let source = self.source();
+                    self.core_input_queue
+                        .push((CoreRequest::LoadShowByName { name }, source));
+                    Ok(vec![])
+                }
+                _ => Err(OscError::Unimplemented),
+            },
+            "scene" => {
+                let action = self.step_address().to_string();
+                let Some(name) = self.get_arg(0).string() else {
+                    return Err(OscError::BadArg("scene name".to_string()));
+                };
+                let request = match action.as_str() {
+                    "store" => CoreRequest::StoreScene { name },
+                    "recall" => CoreRequest::RecallScene {
+                        name,
+                        fade_ms: self.get_arg(1).float().unwrap_or(0.0),
+                    },
+                    "delete" => CoreRequest::DeleteScene { name },
+                    _ => return Err(OscError::Unimplemented),
+                };
+                let source = self.source();
+                self.core_input_queue.push((request, source));
+                Ok(vec![])
+            }
+            "scenes" => {
+                let source = self.source();
+                self.core_input_queue.push((CoreRequest::GetScenes, source));
+                Ok(vec![])
+            }
+            "routing" => {
+                let request = match self.step_address().to_string().as_str() {
+                    "presets" => CoreRequest::GetRoutingPresets,
+                    "preset" => {
+                        let action = self.step_address().to_string();
+                        let Some(name) = self.get_arg(0).string() else {
+                            return Err(OscError::BadArg("routing preset name".to_string()));
+                        };
+                        match action.as_str() {
+                            "store" => CoreRequest::StoreRoutingPreset { name },
+                            "load" => CoreRequest::LoadRoutingPreset { name },
+                            "delete" => CoreRequest::DeleteRoutingPreset { name },
+                            _ => return Err(OscError::Unimplemented),
+                        }
+                    }
+                    _ => return Err(OscError::Unimplemented),
+                };
+                let source = self.source();
+                self.core_input_queue.push((request, source));
+                Ok(vec![])
+            }
+            "macro" => {
+                let action = self.step_address().to_string();
+                let Some(name) = self.get_arg(0).string() else {
+                    return Err(OscError::BadArg("macro name".to_string()));
+                };
+                let request = match action.as_str() {
+                    "run" => CoreRequest::RunMacro { name },
+                    "stop" => CoreRequest::StopMacro { name },
+                    _ => return Err(OscError::Unimplemented),
+                };
+                let source = self.source();
+                self.core_input_queue.push((request, source));
+                Ok(vec![])
+            }
+            "macros" => {
+                let source = self.source();
+                self.core_input_queue.push((CoreRequest::GetMacros, source));
+                Ok(vec![])
+            }
+            "timing" => {
+                let Some(format) = self
+                    .get_arg(0)
+                    .string()
+                    .and_then(|name| TimingReportFormat::from_name(&name))
+                else {
+                    return Err(OscError::BadArg("report format".to_string()));
+                };
+                let source = self.source();
+                self.core_input_queue
+                    .push((CoreRequest::ExportTimingReport(format), source));
+                Ok(vec![])
+            }
+            "profile" => {
+                let request = match self.step_address() {
+                    "dump" => CoreRequest::DumpProfile,
+                    "record" => {
+                        let Some(on) = self.get_arg(0).bool() else {
+                            return Err(OscError::BadArg("profiling on".to_string()));
+                        };
+                        CoreRequest::SetProfiling { on }
+                    }
+                    _ => return Err(OscError::Unimplemented),
+                };
+                let source = self.source();
+                self.core_input_queue.push((request, source));
+                Ok(vec![])
+            }
+            "power" => {
+                let passphrase = self.get_arg(0).string().map(Passphrase);
+                let request = match self.step_address() {
+                    "restart" => CoreRequest::Restart { passphrase },
+                    "reboot" => CoreRequest::Reboot { passphrase },
+                    "confirm" => {
+                        let Some(code) = self.get_arg(0).int() else {
+                            return Err(OscError::BadArg("confirmation code".to_string()));
+                        };
+                        CoreRequest::ConfirmPower { code: code as u32 }
+                    }
+                    _ => return Err(OscError::Unimplemented),
+                };
+                let source = self.source();
+                self.core_input_queue.push((request, source));
+                Ok(vec![])
+            }
+            "schema" => {
+                let source = self.source();
+                self.core_input_queue
+                    .push((CoreRequest::ExportProtocolSchema, source));
+                Ok(vec![])
+            }
+            "query" => {
+                let messages = self.query(&format!("/message/{}", self.address));
+                self.reply_messages(self.last_recv_src, messages);
+                Ok(vec![])
+            }
+            "outputs" => {
+                let source = self.source();
+                self.core_input_queue
+                    .push((CoreRequest::GetOutputs, source));
+                Ok(vec![])
+            }
+            "audit" => match self.step_address() {
+                "tail" => {
+                    let count = self
+                        .get_arg(0)
+                        .int()
+                        .unwrap_or(20)
+                        .clamp(0, MAX_LOG_TAIL as i32) as u16;
+                    let source = self.source();
+                    self.core_input_queue
+                        .push((CoreRequest::AuditTail { count }, source));
+                    Ok(vec![])
+                }
+                _ => Err(OscError::Unimplemented),
+            },
+            "runlog" => match self.step_address() {
+                "tail" => {
+                    let count = self
+                        .get_arg(0)
+                        .int()
+                        .unwrap_or(20)
+                        .clamp(0, MAX_LOG_TAIL as i32) as u16;
+                    let source = self.source();
+                    self.core_input_queue
+                        .push((CoreRequest::RunLog { count }, source));
+                    Ok(vec![])
+                }
+                "new" => {
+                    let source = self.source();
+                    self.core_input_queue
+                        .push((CoreRequest::NewPerformance, source));
+                    Ok(vec![])
+                }
                 _ => Err(OscError::Unimplemented),
             },
             "subscribe" => {
                 if let Some(port) = self.get_arg(0).int().unwrap_or_default().into() {
-                    self.subscribers
-                        .push(SocketAddr::new(self.last_recv_src.ip(), port as u16));
+                    let subscriber = SocketAddr::new(self.last_recv_src.ip(), port as u16);
+                    self.subscribers.push(subscriber);
+                    self.replay(subscriber);
                     Ok(vec![])
                 } else {
                     Err(OscError::BadArg("subscriber".to_string()))
@@ -194,6 +1373,54 @@ impl OscNetHandler {
         }
     }
 
+    /// Sends the latest message of every type to a new subscriber.
+    fn replay(&mut self, subscriber: SocketAddr) {
+        let cached: Vec<Message> = self.cache.messages().cloned().collect();
+        for message in cached {
+            for msg in self.notif_to_osc(message) {
+                self.send_packet_to(&OscPacket::Message(msg), subscriber);
+            }
+        }
+    }
+
+    /// Sends messages to a single client in one bundle, if there are any.
+    fn reply_messages(&mut self, to: SocketAddr, messages: Vec<OscMessage>) {
+        if messages.is_empty() {
+            return;
+        }
+        self.send_packet_to(
+            &OscPacket::Bundle(OscBundle {
+                timetag: OscTime::try_from(SystemTime::now())
+                    .expect("SystemTime is after Unix Epoch"),
+                content: messages.into_iter().map(OscPacket::Message).collect(),
+            }),
+            to,
+        );
+    }
+
+    /// Messages of the latest notifications, common and core, whose address, or a part of it up
+    /// to a `/`, matches `pattern`, so stateless remotes can ask for the state subscribers are
+    /// kept up to date with.
+    fn query(&mut self, pattern: &str) -> Vec<OscMessage> {
+        let Ok(matcher) = Matcher::new(pattern) else {
+            return vec![];
+        };
+        let matches = |addr: &str| {
+            addr.match_indices('/')
+                .map(|(idx, _)| &addr[..idx])
+                .chain([addr])
+                .filter_map(|part| OscAddress::new(part.to_string()).ok())
+                .any(|address| matcher.match_address(&address))
+        };
+        let cached: Vec<Message> = self.cache.messages().cloned().collect();
+        cached
+            .into_iter()
+            .flat_map(|message| self.notif_to_osc(message))
+            .chain(self.core_cache.values().cloned())
+            .filter(|msg| matches(&msg.addr))
+            .collect()
+    }
+
     fn send_message(&mut self, msg: OscMessage) {
         self.send_packet(OscPacket::Message(msg));
     }
@@ -210,20 +1437,38 @@ impl OscNetHandler {
 
     fn send_packet(&mut self, packet: OscPacket) {
         for subscriber in self.subscribers.clone() {
-            self.port.send_to(
-                match &rosc::encoder::encode(&packet) {
-                    Ok(val) => val.as_slice(),
-                    Err(_) => continue,
-                },
-                subscriber,
-            );
+            self.send_packet_to(&packet, subscriber);
+        }
+    }
+
+    fn send_packet_to(&mut self, packet: &OscPacket, address: SocketAddr) {
+        if let Ok(buf) = rosc::encoder::encode(packet) {
+            self.port.send_to(&buf, address);
         }
     }
 
     fn addr_control_transport_(&mut self) -> Result<Vec<Request>, OscError> {
         match self.step_address() {
-            "start" => Ok(vec![Request::ControlAction(ControlAction::TransportStart)]),
+            "start" => match self.get_arg(0).int() {
+                Some(bars) => {
+                    let source = self.source();
+                    self.core_input_queue.push((
+                        CoreRequest::TransportStartWithCountIn {
+                            bars: bars.clamp(0, u8::MAX as i32) as u8,
+                        },
+                        source,
+                    ));
+                    Ok(vec![])
+                }
+                None => Ok(vec![Request::ControlAction(ControlAction::TransportStart)]),
+            },
             "stop" => Ok(vec![Request::ControlAction(ControlAction::TransportStop)]),
+            "stop_bar" => {
+                let source = self.source();
+                self.core_input_queue
+                    .push((CoreRequest::TransportStopQuantized, source));
+                Ok(vec![])
+            }
             "zero" => Ok(vec![Request::ControlAction(ControlAction::TransportZero)]),
             "seek" => {
                 if let Some(dest) = self.get_arg(0).int() {
@@ -243,6 +1488,18 @@ impl OscNetHandler {
                     Err(OscError::BadArg("beat index".to_string()))
                 }
             }
+            "vamp" => {
+                let on = match self.step_address() {
+                    "on" => Some(true),
+                    "off" => Some(false),
+                    "next" => None,
+                    _ => return Err(OscError::Unimplemented),
+                };
+                let source = self.source();
+                self.core_input_queue
+                    .push((CoreRequest::SetJumpMode { on }, source));
+                Ok(vec![])
+            }
             _ => Err(OscError::Unimplemented),
         }
     }
@@ -251,6 +1508,48 @@ impl OscNetHandler {
         match self.step_address() {
             "+" => Ok(vec![Request::ControlAction(ControlAction::LoadNextCue)]),
             "-" => Ok(vec![Request::ControlAction(ControlAction::LoadPreviousCue)]),
+            "standby" => {
+                let Some(cue) = self.get_arg(0).int() else {
+                    return Err(OscError::BadArg("cue index".to_string()));
+                };
+                let source = self.source();
+                self.core_input_queue.push((
+                    CoreRequest::Standby {
+                        cue: cue.clamp(0, u8::MAX as i32) as u8,
+                    },
+                    source,
+                ));
+                Ok(vec![])
+            }
+            "cancel" => {
+                let source = self.source();
+                self.core_input_queue
+                    .push((CoreRequest::CancelStandby, source));
+                Ok(vec![])
+            }
+            "go" => {
+                let source = self.source();
+                self.core_input_queue.push((CoreRequest::Go, source));
+                Ok(vec![])
+            }
+            "list" => {
+                let source = self.source();
+                self.core_input_queue.push((CoreRequest::GetCues, source));
+                Ok(vec![])
+            }
+            "launch" => {
+                let Some(at) = LaunchBoundary::from_name(self.step_address()) else {
+                    return Err(OscError::Unimplemented);
+                };
+                let cue = self
+                    .get_arg(0)
+                    .int()
+                    .map(|cue| cue.clamp(0, u8::MAX as i32) as u8);
+                let source = self.source();
+                self.core_input_queue
+                    .push((CoreRequest::LaunchCue { cue, at }, source));
+                Ok(vec![])
+            }
             "load" => {
                 if let Some(cue_idx) = self.get_arg(0).int() {
                     Ok(vec![Request::ControlAction(ControlAction::LoadCueByIndex(
@@ -271,8 +1570,9 @@ impl OscNetHandler {
             return Err(OscError::BadAddress(self.address.clone()));
         }
 
+        let source = self.source();
         let mut cmds = vec![];
-        for chidx in 0..32 {
+        for chidx in indices(self.channel_counts.sources()) {
             if self.addreq(format!("/{chidx}/gain"))
                 && let Some(gain) = self.get_arg(0).float()
             {
@@ -287,7 +1587,91 @@ impl OscNetHandler {
                     chidx, mute,
                 )));
             }
-            for out_idx in 0..64 {
+            for band in 0..EQ_BANDS as u8 {
+                if self.addreq(format!("/{chidx}/eq/{band}"))
+                    && let (Some(frequency), Some(gain_db), Some(q)) = (
+                        self.get_arg(0).float(),
+                        self.get_arg(1).float(),
+                        self.get_arg(2).float(),
+                    )
+                {
+                    self.core_input_queue.push((
+                        CoreRequest::SetOutputEqBand {
+                            output: chidx,
+                            band,
+                            settings: EqBand {
+                                frequency,
+                                gain_db,
+                                q,
+                            },
+                        },
+                        source.clone(),
+                    ));
+                }
+            }
+            for (unit, amount) in [
+                ("ms", DelayAmount::Milliseconds as fn(f32) -> DelayAmount),
+                ("m", DelayAmount::Meters),
+            ] {
+                if self.addreq(format!("/{chidx}/delay/{unit}"))
+                    && let Some(value) = self.get_arg(0).float()
+                {
+                    self.core_input_queue.push((
+                        CoreRequest::SetOutputDelay {
+                            output: chidx,
+                            delay: amount(value),
+                        },
+                        source.clone(),
+                    ));
+                }
+            }
+            if self.addreq(format!("/{chidx}/label"))
+                && let Some(label) = self.get_arg(0).string()
+            {
+                self.core_input_queue.push((
+                    CoreRequest::SetOutputLabel {
+                        output: chidx,
+                        label,
+                    },
+                    source.clone(),
+                ));
+            }
+            if self.addreq(format!("/{chidx}/invert"))
+                && let Some(invert) = self.get_arg(0).bool()
+            {
+                self.core_input_queue.push((
+                    CoreRequest::SetOutputPolarity {
+                        output: chidx,
+                        invert,
+                    },
+                    source.clone(),
+                ));
+            }
+            for port in indices(self.channel_counts.output_ports()) {
+                if self.addreq(format!("/{chidx}/mix/{port}")) {
+                    self.core_input_queue.push((
+                        CoreRequest::SetOutputMix {
+                            output: chidx,
+                            port,
+                            gain_db: self.get_arg(0).float(),
+                        },
+                        source.clone(),
+                    ));
+                }
+            }
+            for input in indices(MAX_METERED_INPUTS) {
+                if self.addreq(format!("/{chidx}/input/{input}")) {
+                    self.core_input_queue.push((
+                        CoreRequest::SetPassThrough {
+                            input,
+                            output: chidx,
+                            gain_db: self.get_arg(0).float(),
+                        },
+                        source.clone(),
+                    ));
+                }
+            }
+            for out_idx in indices(self.channel_counts.route_targets) {
                 if self.addreq(format!("/{chidx}/route/{out_idx}"))
                     && let Some(patch) = self.get_arg(0).bool()
                 {
@@ -298,10 +1682,103 @@ impl OscNetHandler {
         Ok(cmds)
     }
 
+    fn addr_edit_input_(&mut self) -> Result<Vec<Request>, OscError> {
+        if let Ok(matcher) = Matcher::new(&format!("/{}", self.address)) {
+            self.matcher = matcher;
+        } else {
+            return Err(OscError::BadAddress(self.address.clone()));
+        }
+
+        let source = self.source();
+        for input_idx in indices(MAX_METERED_INPUTS) {
+            for capture_idx in indices(self.channel_counts.route_targets) {
+                if self.addreq(format!("/{input_idx}/route/{capture_idx}"))
+                    && let Some(connect) = self.get_arg(0).bool()
+                {
+                    self.core_input_queue.push((
+                        CoreRequest::ChangeInputRouting {
+                            from: capture_idx,
+                            to: input_idx,
+                            connect,
+                        },
+                        source.clone(),
+                    ));
+                }
+            }
+        }
+        Ok(vec![])
+    }
+
     fn addr_edit_config_(&mut self) -> Result<Vec<Request>, OscError> {
         Err(OscError::Unimplemented)
     }
 
+    fn addr_log_(&mut self) -> Result<Vec<Request>, OscError> {
+        let src = self.last_recv_src;
+        let source = self.source();
+        match self.step_address() {
+            "tail" => {
+                let count = self
+                    .get_arg(0)
+                    .int()
+                    .unwrap_or(20)
+                    .clamp(0, MAX_LOG_TAIL as i32) as u16;
+                let (kind, context) = self.log_filter(1)?;
+                self.core_input_queue.push((
+                    CoreRequest::LogTail {
+                        count,
+                        kind,
+                        context,
+                    },
+                    source,
+                ));
+                Ok(vec![])
+            }
+            "follow" => {
+                let (kind, context) = self.log_filter(1)?;
+                self.log_followers
+                    .retain(|follower| follower.address != src);
+                if self.get_arg(0).bool().unwrap_or(true) {
+                    self.log_followers.push(LogFollower {
+                        address: src,
+                        kind,
+                        context,
+                        last_contact: Instant::now(),
+                    });
+                }
+                Ok(vec![])
+            }
+            _ => Err(OscError::Unimplemented),
+        }
+    }
+
+    /// The log kind and context named by the arguments from `idx` on, any for nil or "".
+    fn log_filter(&mut self, idx: usize) -> Result<(LogKind, LogContext), OscError> {
+        let kind = match self.get_arg(idx).string().filter(|name| !name.is_empty()) {
+            Some(name) => {
+                LogKind::from_name(&name).ok_or(OscError::BadArg("log kind".to_string()))?
+            }
+            None => LogKind::all(),
+        };
+        let context = match self
+            .get_arg(idx + 1)
+            .string()
+            .filter(|name| !name.is_empty())
+        {
+            Some(name) => {
+                LogContext::from_name(&name).ok_or(OscError::BadArg("log context".to_string()))?
+            }
+            None => LogContext::all(),
+        };
+        Ok((kind, context))
+    }
+
+    /// Stops sending log items to followers not heard from for `LOG_FOLLOWER_TIMEOUT`.
+    fn expire_log_followers(&mut self, now: Instant) {
+        self.log_followers
+            .retain(|follower| now.duration_since(follower.last_contact) < LOG_FOLLOWER_TIMEOUT);
+    }
+
     fn get_arg(&mut self, idx: usize) -> OscType {
         self.args.get(idx).cloned().unwrap_or(OscType::Nil)
     }
@@ -313,6 +1790,518 @@ impl OscNetHandler {
         })
     }
 
+    fn core_to_osc(message: CoreMessage) -> Vec<OscMessage> {
+        match message {
+            CoreMessage::LogTail(items) => items
+                .into_iter()
+                .map(|item| OscMessage {
+                    addr: "/message/log".to_string(),
+                    args: vec![OscType::String(format!("{}: {}", item.kind, item.message))],
+                })
+                .collect(),
+            CoreMessage::AuditTail(entries) => entries
+                .into_iter()
+                .map(|entry| OscMessage {
+                    addr: "/message/audit".to_string(),
+                    args: vec![OscType::String(entry.to_string())],
+                })
+                .collect(),
+            CoreMessage::MediaUnavailable {
+                channel,
+                clip,
+                error,
+            } => vec![OscMessage {
+                addr: "/message/media/unavailable".to_string(),
+                args: vec![
+                    OscType::Int(channel as i32),
+                    OscType::Int(clip as i32),
+                    OscType::String(error),
+                ],
+            }],
+            CoreMessage::SeekResult {
+                channel,
+                beat,
+                clip,
+                sample,
+                outcome,
+            } => {
+                let mut args = vec![
+                    OscType::Int(channel as i32),
+                    OscType::Int(beat as i32),
+                    OscType::Int(clip as i32),
+                    OscType::Int(sample),
+                ];
+                match outcome {
+                    SeekOutcome::NotLoaded => args.push(OscType::String("not_loaded".to_string())),
+                    SeekOutcome::PastEnd { length } => {
+                        args.push(OscType::String("past_end".to_string()));
+                        args.push(OscType::Int(length.min(i32::MAX as u32) as i32));
+                    }
+                }
+                vec![OscMessage {
+                    addr: "/message/seek".to_string(),
+                    args,
+                }]
+            }
+            CoreMessage::ShowFileChanged(changed) => vec![OscMessage {
+                addr: "/message/show/changed".to_string(),
+                args: vec![OscType::Bool(changed)],
+            }],
+            CoreMessage::TimingReportExported { path } => vec![OscMessage {
+                addr: "/message/timing".to_string(),
+                args: vec![OscType::String(path)],
+            }],
+            CoreMessage::FailoverRole(role) => {
+                let (role, primary_alive) = match role {
+                    FailoverRole::Primary => ("primary", true),
+                    FailoverRole::Backup { primary_alive } => ("backup", primary_alive),
+                    FailoverRole::TookOver => ("took_over", false),
+                };
+                vec![OscMessage {
+                    addr: "/message/failover".to_string(),
+                    args: vec![
+                        OscType::String(role.to_string()),
+                        OscType::Bool(primary_alive),
+                    ],
+                }]
+            }
+            CoreMessage::ClusterStatus(status) => {
+                let mut args = vec![
+                    OscType::Int(status.first_channel as i32),
+                    OscType::Int(status.channel_count as i32),
+                    OscType::Bool(status.conductor_alive),
+                ];
+                if let Some(drift_us) = status.drift_us {
+                    args.push(OscType::Long(drift_us));
+                }
+                vec![OscMessage {
+                    addr: "/message/cluster".to_string(),
+                    args,
+                }]
+            }
+            CoreMessage::Identity { name } => vec![OscMessage {
+                addr: "/message/device".to_string(),
+                args: vec![OscType::String(name)],
+            }],
+            CoreMessage::ProtocolSchema(chunk) => vec![OscMessage {
+                addr: "/message/schema".to_string(),
+                args: vec![
+                    OscType::Int(chunk.index as i32),
+                    OscType::Int(chunk.count as i32),
+                    OscType::String(chunk.json),
+                ],
+            }],
+            CoreMessage::ProfileDumped { path } => vec![OscMessage {
+                addr: "/message/profile".to_string(),
+                args: vec![OscType::String(path)],
+            }],
+            CoreMessage::ChannelMuted { channel, muted } => vec![OscMessage {
+                addr: format!("/message/channel/{channel}/mute"),
+                args: vec![OscType::Bool(muted)],
+            }],
+            CoreMessage::ClickSynthesis(synthesis) => vec![OscMessage {
+                addr: "/message/click_synth".to_string(),
+                args: [
+                    OscType::String(synthesis.waveform.name().to_string()),
+                    OscType::Float(synthesis.decay_ms),
+                ]
+                .into_iter()
+                .chain(synthesis.clicks.iter().flat_map(|click| {
+                    [
+                        OscType::Float(click.frequency_hz),
+                        OscType::Float(click.length_ms),
+                    ]
+                }))
+                .chain(synthesis.clicks.iter().map(|click| {
+                    click.waveform.map_or(OscType::Nil, |waveform| {
+                        OscType::String(waveform.name().to_string())
+                    })
+                }))
+                .collect(),
+            }],
+            CoreMessage::CountIn { beats_left } => vec![OscMessage {
+                addr: "/message/transport/count_in".to_string(),
+                args: vec![OscType::Int(beats_left as i32)],
+            }],
+            CoreMessage::ClickSamples(samples) => vec![OscMessage {
+                addr: "/message/click_samples".to_string(),
+                args: samples
+                    .into_iter()
+                    .map(|sample| sample.map_or(OscType::Nil, OscType::String))
+                    .collect(),
+            }],
+            CoreMessage::ConfirmPower { action, code } => vec![OscMessage {
+                addr: "/message/power".to_string(),
+                args: vec![
+                    OscType::String(
+                        match action {
+                            PowerAction::Restart => "restart",
+                            PowerAction::Reboot => "reboot",
+                        }
+                        .to_string(),
+                    ),
+                    OscType::Int(code as i32),
+                ],
+            }],
+            CoreMessage::RunLog(entries) => entries
+                .into_iter()
+                .map(|entry| OscMessage {
+                    addr: "/message/runlog".to_string(),
+                    args: vec![OscType::String(entry.to_string())],
+                })
+                .collect(),
+            CoreMessage::SystemFault {
+                thread,
+                message,
+                location,
+            } => vec![OscMessage {
+                addr: "/message/fault".to_string(),
+                args: vec![
+                    OscType::String(thread),
+                    OscType::String(message),
+                    OscType::String(location),
+                ],
+            }],
+            CoreMessage::Inputs(status) => status
+                .connections
+                .into_iter()
+                .enumerate()
+                .map(|(idx, mask)| OscMessage {
+                    addr: format!("/message/input/{idx}/route"),
+                    args: route_args(mask),
+                })
+                .collect(),
+            CoreMessage::RoutingDrift(repairs) => repairs
+                .into_iter()
+                .map(|repair| OscMessage {
+                    addr: "/message/routing/drift".to_string(),
+                    args: vec![OscType::String(repair.to_string())],
+                })
+                .collect(),
+            CoreMessage::Routing(connections) => connections
+                .into_iter()
+                .enumerate()
+                .map(|(idx, mask)| OscMessage {
+                    addr: format!("/message/channel/{idx}/route"),
+                    args: route_args(mask),
+                })
+                .collect(),
+            CoreMessage::Scenes(names) => vec![OscMessage {
+                addr: "/message/scenes".to_string(),
+                args: names.into_iter().map(OscType::String).collect(),
+            }],
+            CoreMessage::SceneRecalled { name } => vec![OscMessage {
+                addr: "/message/scene/recalled".to_string(),
+                args: vec![OscType::String(name)],
+            }],
+            CoreMessage::RoutingPresets(names) => vec![OscMessage {
+                addr: "/message/routing/presets".to_string(),
+                args: names.into_iter().map(OscType::String).collect(),
+            }],
+            CoreMessage::Shows { loaded, shows } => vec![OscMessage {
+                addr: "/message/show/list".to_string(),
+                args: std::iter::once(loaded)
+                    .chain(shows)
+                    .map(OscType::String)
+                    .collect(),
+            }],
+            CoreMessage::ShowVerified(report) => vec![OscMessage {
+                addr: "/message/show/verified".to_string(),
+                args: std::iter::once(OscType::Int(report.checked as i32))
+                    .chain(
+                        report
+                            .faults
+                            .iter()
+                            .map(|fault| OscType::String(fault.to_string())),
+                    )
+                    .collect(),
+            }],
+            CoreMessage::Link(link) => vec![OscMessage {
+                addr: "/message/link".to_string(),
+                args: std::iter::once(OscType::Bool(link.up))
+                    .chain(
+                        link.address
+                            .map(|address| OscType::String(address.to_string())),
+                    )
+                    .collect(),
+            }],
+            CoreMessage::ResourceAlarms(alarms) => vec![OscMessage {
+                addr: "/message/alarms".to_string(),
+                args: alarms
+                    .into_iter()
+                    .map(|alarm| {
+                        OscType::String(
+                            match alarm {
+                                ResourceAlarm::ShowDiskLow => "show_disk_low",
+                                ResourceAlarm::LogDiskLow => "log_disk_low",
+                                ResourceAlarm::MemoryHigh => "memory_high",
+                            }
+                            .to_string(),
+                        )
+                    })
+                    .collect(),
+            }],
+            CoreMessage::Macros(macros) => vec![OscMessage {
+                addr: "/message/macros".to_string(),
+                args: macros.into_keys().map(OscType::String).collect(),
+            }],
+            CoreMessage::Cues(cues) => std::iter::once(OscMessage {
+                addr: "/message/cue/list/count".to_string(),
+                args: vec![OscType::Int(cues.len() as i32)],
+            })
+            .chain(cues.into_iter().map(|cue| OscMessage {
+                addr: "/message/cue/list/entry".to_string(),
+                args: vec![
+                    OscType::Int(cue.index as i32),
+                    OscType::String(cue.ident),
+                    OscType::String(cue.name),
+                    OscType::Int(cue.length as i32),
+                ],
+            }))
+            .collect(),
+            // Editing clients speak the binary or JSON protocol
+            CoreMessage::ShowDump(_) => vec![],
+            CoreMessage::Standby(cue) => vec![OscMessage {
+                addr: "/message/cue/standby".to_string(),
+                args: vec![OscType::Int(cue.map_or(-1, |cue| cue as i32))],
+            }],
+            CoreMessage::CueMarking { cue, color, flags } => vec![
+                OscMessage {
+                    addr: "/message/cue/color".to_string(),
+                    args: vec![
+                        OscType::Int(cue as i32),
+                        OscType::String(color.map(|color| color.to_hex()).unwrap_or_default()),
+                    ],
+                },
+                OscMessage {
+                    addr: "/message/cue/flags".to_string(),
+                    args: std::iter::once(OscType::Int(cue as i32))
+                        .chain(
+                            flags
+                                .iter()
+                                .map(|flag| OscType::String(flag.name().to_string())),
+                        )
+                        .collect(),
+                },
+            ],
+            CoreMessage::CueEnded { cue } => vec![OscMessage {
+                addr: "/message/cue/ended".to_string(),
+                args: vec![OscType::Int(cue as i32)],
+            }],
+            CoreMessage::Go { cue } => vec![OscMessage {
+                addr: "/message/cue/go".to_string(),
+                args: vec![OscType::Int(cue as i32)],
+            }],
+            CoreMessage::CueLaunched { cue } => vec![OscMessage {
+                addr: "/message/cue/launched".to_string(),
+                args: vec![OscType::Int(cue as i32)],
+            }],
+            CoreMessage::NotificationRates(rates) => vec![OscMessage {
+                addr: "/message/rates".to_string(),
+                args: vec![
+                    OscType::Int(rates.transport as i32),
+                    OscType::Int(rates.beat as i32),
+                    OscType::Int(rates.timecode as i32),
+                ],
+            }],
+            CoreMessage::BarAccents { position, accents } => vec![OscMessage {
+                addr: "/message/transport/beat/accents".to_string(),
+                args: std::iter::once(OscType::Int(position as i32))
+                    .chain(
+                        accents
+                            .iter()
+                            .map(|accent| OscType::String(accent.name().to_string())),
+                    )
+                    .collect(),
+            }],
+            CoreMessage::UpcomingEvents { cue, beat, events } => vec![OscMessage {
+                addr: "/message/cue/upcoming".to_string(),
+                args: [OscType::Int(cue as i32), OscType::Int(beat as i32)]
+                    .into_iter()
+                    .chain(events.iter().flat_map(|upcoming| {
+                        let target = match upcoming.event {
+                            CountdownEvent::Playback { channel, .. }
+                            | CountdownEvent::PlaybackStop { channel } => channel as i32,
+                            CountdownEvent::Jump { destination, .. } => destination as i32,
+                            _ => -1,
+                        };
+                        [
+                            OscType::String(upcoming.event.name().to_string()),
+                            OscType::Int(upcoming.beat as i32),
+                            OscType::Int(target),
+                        ]
+                    }))
+                    .collect(),
+            }],
+            CoreMessage::Countdown(countdown) => {
+                let (kind, seconds, beat, channel, clip) = match countdown.next {
+                    Some(next) => {
+                        let (channel, clip) = match next.event {
+                            CountdownEvent::Playback { channel, clip } => {
+                                (channel as i32, clip as i32)
+                            }
+                            _ => (-1, -1),
+                        };
+                        (
+                            next.event.name(),
+                            next.us as f32 / 1_000_000.0,
+                            next.beat as i32,
+                            channel,
+                            clip,
+                        )
+                    }
+                    None => ("none", 0.0, -1, -1, -1),
+                };
+                vec![
+                    OscMessage {
+                        addr: "/message/countdown/cue".to_string(),
+                        args: vec![OscType::Float(
+                            countdown.cue_remaining_us as f32 / 1_000_000.0,
+                        )],
+                    },
+                    OscMessage {
+                        addr: "/message/countdown/next".to_string(),
+                        args: vec![
+                            OscType::String(kind.to_string()),
+                            OscType::Float(seconds),
+                            OscType::Int(beat),
+                            OscType::Int(channel),
+                            OscType::Int(clip),
+                        ],
+                    },
+                ]
+            }
+            CoreMessage::BeatPhase { beat_idx, phase } => vec![OscMessage {
+                addr: "/message/transport/beat/phase".to_string(),
+                args: vec![OscType::Int(beat_idx as i32), OscType::Float(phase)],
+            }],
+            CoreMessage::TempoFollow(config) => vec![OscMessage {
+                addr: "/message/follow".to_string(),
+                args: vec![
+                    OscType::Bool(config.enabled),
+                    OscType::Int(config.input as i32),
+                    OscType::Int(config.max_deviation_percent as i32),
+                    OscType::Float(config.threshold),
+                ],
+            }],
+            CoreMessage::TimecodeOutput(output) => vec![OscMessage {
+                addr: "/message/ltc".to_string(),
+                args: vec![
+                    OscType::Float(output.level),
+                    OscType::Int(output.smoothing as i32),
+                    OscType::Bool(output.invert),
+                ],
+            }],
+            CoreMessage::Metrics(report) => [
+                OscMessage {
+                    addr: "/message/metrics/loop_freq".to_string(),
+                    args: vec![OscType::Int(report.main_loop_freq as i32)],
+                },
+                OscMessage {
+                    addr: "/message/metrics/dsp_mean_us".to_string(),
+                    args: vec![OscType::Int(report.dsp.mean_us as i32)],
+                },
+                OscMessage {
+                    addr: "/message/metrics/dsp_max_us".to_string(),
+                    args: vec![OscType::Int(report.dsp.max_us as i32)],
+                },
+                OscMessage {
+                    addr: "/message/metrics/disk_free_mb".to_string(),
+                    args: [report.resources.show_disk, report.resources.log_disk]
+                        .into_iter()
+                        .map(|disk| {
+                            OscType::Int(disk.map_or(-1, |disk| {
+                                (disk.free_bytes / (1024 * 1024)).min(i32::MAX as u64) as i32
+                            }))
+                        })
+                        .collect(),
+                },
+                OscMessage {
+                    addr: "/message/metrics/rss_mb".to_string(),
+                    args: vec![OscType::Int(
+                        report
+                            .resources
+                            .rss_bytes
+                            .map_or(-1, |rss| (rss / (1024 * 1024)) as i32),
+                    )],
+                },
+            ]
+            .into_iter()
+            .chain(
+                report
+                    .input_peaks
+                    .into_iter()
+                    .enumerate()
+                    .map(|(idx, peak)| OscMessage {
+                        addr: format!("/message/metrics/input_peak/{idx}"),
+                        args: vec![OscType::Float(peak)],
+                    }),
+            )
+            .collect(),
+            CoreMessage::Outputs(outputs) => outputs
+                .into_iter()
+                .enumerate()
+                .flat_map(|(idx, output)| {
+                    output
+                        .eq
+                        .bands
+                        .into_iter()
+                        .enumerate()
+                        .map(move |(band, settings)| OscMessage {
+                            addr: format!("/message/channel/{idx}/eq/{band}"),
+                            args: vec![
+                                OscType::Float(settings.frequency),
+                                OscType::Float(settings.gain_db),
+                                OscType::Float(settings.q),
+                            ],
+                        })
+                        .chain([
+                            OscMessage {
+                                addr: format!("/message/channel/{idx}/delay"),
+                                args: vec![OscType::Float(output.delay_ms)],
+                            },
+                            OscMessage {
+                                addr: format!("/message/channel/{idx}/mute"),
+                                args: vec![OscType::Bool(output.muted)],
+                            },
+                            OscMessage {
+                                addr: format!("/message/channel/{idx}/invert"),
+                                args: vec![OscType::Bool(output.invert)],
+                            },
+                            OscMessage {
+                                addr: format!("/message/channel/{idx}/label"),
+                                args: vec![OscType::String(output.label.clone())],
+                            },
+                        ])
+                        .chain(
+                            output
+                                .pass_through
+                                .into_iter()
+                                .map(move |route| OscMessage {
+                                    addr: format!("/message/channel/{idx}/input/{}", route.input),
+                                    args: vec![OscType::Float(route.gain_db)],
+                                }),
+                        )
+                        .chain(output.mix.into_iter().map(move |send| OscMessage {
+                            addr: format!("/message/channel/{idx}/mix/{}", send.port),
+                            args: vec![OscType::Float(send.gain_db)],
+                        }))
+                })
+                .collect(),
+            CoreMessage::RequestRejected { request, reason } => vec![OscMessage {
+                addr: "/message/rejected".to_string(),
+                args: vec![
+                    OscType::String(request),
+                    OscType::String(reason.to_string()),
+                ],
+            }],
+            CoreMessage::RequestFailed { request, reason } => vec![OscMessage {
+                addr: "/message/failed".to_string(),
+                args: vec![OscType::String(request), OscType::String(reason)],
+            }],
+        }
+    }
+
     fn notif_to_osc(&mut self, message: Message) -> Vec<OscMessage> {
         // Helper function to macro generate osc messages with a single argument
         fn osc_msg(addr: &str, arg: OscType) -> OscMessage {
@@ -340,6 +2329,24 @@ impl OscNetHandler {
                     ),
                 ]
             }
+            Message::Large(LargeMessage::ConfigurationChanged(config)) => config
+                .channels
+                .iter()
+                .enumerate()
+                .map(|(idx, channel)| OscMessage {
+                    addr: format!("/message/channel/{idx}/gain"),
+                    args: vec![OscType::Float(channel.gain)],
+                })
+                .collect(),
+            Message::Small(SmallMessage::TransportData(transport)) => {
+                vec![
+                    osc_msg(
+                        "/message/transport/running",
+                        OscType::Bool(transport.running),
+                    ),
+                    osc_msg("/message/transport/vamp", OscType::Bool(transport.vlt)),
+                ]
+            }
             Message::Small(SmallMessage::BeatData(state)) => {
                 vec![
                     osc_msg(
@@ -356,7 +2363,6 @@ impl OscNetHandler {
                     ),
                 ]
             }
-            //          running
             //           {beat/, nextbeat/}
             //              index
             //              count
@@ -370,13 +2376,43 @@ impl OscNetHandler {
     }
 }
 
+/// The first `count` channel indices, as far as a `u8` reaches.
+fn indices(count: usize) -> impl Iterator<Item = u8> {
+    (0..=u8::MAX).take(count)
+}
+
+/// A routing bitmask as two i32, system ports 0-31 and 32-63, so clients reading the first
+/// argument only keep working with up to 32 ports.
+fn route_args(mask: RouteMask) -> Vec<OscType> {
+    vec![
+        OscType::Int(mask as u32 as i32),
+        OscType::Int((mask >> 32) as u32 as i32),
+    ]
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::communication::extension::CueSummary;
+    use common::local::{config::SystemConfiguration, status::TransportState};
+
+    #[test]
+    fn channel_indices() {
+        assert_eq!(indices(3).collect::<Vec<_>>(), vec![0, 1, 2]);
+        assert_eq!(indices(300).count(), 256);
+    }
+
+    #[test]
+    fn route_masks_as_i32() {
+        assert_eq!(
+            route_args(0x8000_0000_0000_0003),
+            vec![OscType::Int(3), OscType::Int(i32::MIN)]
+        );
+    }
 
     #[test]
     fn invalid_osc() {
-        let mut handler = OscNetHandler::new(0);
+        let mut handler = OscNetHandler::new(0, ChannelCounts::default());
         let invalids = ["", "\0\0\0\0\0\0\0", "agewqaehgo"];
         for invalid in invalids {
             let result = handler.handle_bytes(invalid.as_bytes(), invalid.len());
@@ -442,7 +2478,7 @@ mod tests {
             ),
         ];
 
-        let mut handler = OscNetHandler::new(0);
+        let mut handler = OscNetHandler::new(0, ChannelCounts::default());
         for (addr, args, expected) in cases {
             let result = handler
                 .handle_packet(OscPacket::Message(OscMessage {
@@ -453,4 +2489,406 @@ mod tests {
             assert_eq!(result, expected);
         }
     }
+
+    #[test]
+    fn log_tail_osc() {
+        let mut handler = OscNetHandler::new(0, ChannelCounts::default());
+        let result = handler
+            .handle_packet(OscPacket::Message(OscMessage {
+                addr: "/log/tail".to_string(),
+                args: vec![OscType::Int(1000)],
+            }))
+            .expect("Assert Ok");
+        assert!(result.is_empty());
+
+        let core_inputs = handler.get_core_inputs();
+        assert_eq!(core_inputs.len(), 1);
+        assert_eq!(
+            core_inputs[0].0,
+            CoreRequest::LogTail {
+                count: MAX_LOG_TAIL as u16,
+                kind: LogKind::all(),
+                context: LogContext::all(),
+            }
+        );
+
+        handler
+            .handle_packet(OscPacket::Message(OscMessage {
+                addr: "/log/tail".to_string(),
+                args: vec![
+                    OscType::Int(5),
+                    OscType::String(String::new()),
+                    OscType::String("Network".to_string()),
+                ],
+            }))
+            .expect("Assert Ok");
+        assert_eq!(
+            handler.get_core_inputs()[0].0,
+            CoreRequest::LogTail {
+                count: 5,
+                kind: LogKind::all(),
+                context: LogContext::Network,
+            }
+        );
+    }
+
+    #[test]
+    fn log_followers_filtered_and_expired() {
+        let mut handler = OscNetHandler::new(0, ChannelCounts::default());
+        handler
+            .handle_packet(OscPacket::Message(OscMessage {
+                addr: "/log/follow".to_string(),
+                args: vec![
+                    OscType::Bool(true),
+                    OscType::String("Warning".to_string()),
+                    OscType::String("Boot".to_string()),
+                ],
+            }))
+            .expect("Assert Ok");
+        assert_eq!(handler.log_followers.len(), 1);
+        assert_eq!(
+            (
+                handler.log_followers[0].kind,
+                handler.log_followers[0].context
+            ),
+            (LogKind::Warning, LogContext::Boot)
+        );
+        assert!(
+            handler
+                .handle_packet(OscPacket::Message(OscMessage {
+                    addr: "/log/follow".to_string(),
+                    args: vec![OscType::Bool(true), OscType::String("Loud".to_string())],
+                }))
+                .is_err()
+        );
+
+        let now = Instant::now();
+        handler.expire_log_followers(now);
+        assert_eq!(handler.log_followers.len(), 1);
+        handler.expire_log_followers(now + LOG_FOLLOWER_TIMEOUT);
+        assert!(handler.log_followers.is_empty());
+    }
+
+    #[test]
+    fn cue_list_osc() {
+        let mut handler = OscNetHandler::new(0, ChannelCounts::default());
+        handler
+            .handle_packet(OscPacket::Message(OscMessage {
+                addr: "/control/cue/list".to_string(),
+                args: vec![],
+            }))
+            .expect("Assert Ok");
+        assert_eq!(handler.get_core_inputs()[0].0, CoreRequest::GetCues);
+
+        let reply = OscNetHandler::core_to_osc(CoreMessage::Cues(vec![CueSummary {
+            index: 0,
+            ident: "1A".to_string(),
+            name: "Opening".to_string(),
+            length: 64,
+        }]));
+        assert_eq!(reply[0].args, vec![OscType::Int(1)]);
+        assert_eq!(
+            reply[1].args,
+            vec![
+                OscType::Int(0),
+                OscType::String("1A".to_string()),
+                OscType::String("Opening".to_string()),
+                OscType::Int(64),
+            ]
+        );
+    }
+
+    #[test]
+    fn cue_flags_osc() {
+        let mut handler = OscNetHandler::new(0, ChannelCounts::default());
+        handler
+            .handle_packet(OscPacket::Message(OscMessage {
+                addr: "/edit/cue/flags".to_string(),
+                args: vec![
+                    OscType::Int(3),
+                    OscType::String("safety".to_string()),
+                    OscType::String("optional".to_string()),
+                ],
+            }))
+            .expect("Assert Ok");
+        assert_eq!(
+            handler.get_core_inputs()[0].0,
+            CoreRequest::SetCueFlags {
+                cue: 3,
+                flags: vec![CueFlag::Safety, CueFlag::Optional],
+            }
+        );
+        assert!(
+            handler
+                .handle_packet(OscPacket::Message(OscMessage {
+                    addr: "/edit/cue/flags".to_string(),
+                    args: vec![OscType::Int(3), OscType::String("pyro".to_string())],
+                }))
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn gain_automation_osc() {
+        let mut handler = OscNetHandler::new(0, ChannelCounts::default());
+        handler
+            .handle_packet(OscPacket::Message(OscMessage {
+                addr: "/edit/cue/automation".to_string(),
+                args: vec![
+                    OscType::Int(1),
+                    OscType::Int(4),
+                    OscType::Float(16.0),
+                    OscType::Float(0.0),
+                    OscType::Float(18.5),
+                    OscType::Float(-12.0),
+                ],
+            }))
+            .expect("Assert Ok");
+        assert_eq!(
+            handler.get_core_inputs()[0].0,
+            CoreRequest::SetGainAutomation {
+                cue: 1,
+                channel: 4,
+                points: vec![
+                    GainPoint {
+                        beat: 16.0,
+                        gain_db: 0.0
+                    },
+                    GainPoint {
+                        beat: 18.5,
+                        gain_db: -12.0
+                    },
+                ],
+            }
+        );
+        assert!(
+            handler
+                .handle_packet(OscPacket::Message(OscMessage {
+                    addr: "/edit/cue/automation".to_string(),
+                    args: vec![OscType::Int(1), OscType::Int(4), OscType::Float(16.0)],
+                }))
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn fade_events_osc() {
+        let mut handler = OscNetHandler::new(0, ChannelCounts::default());
+        handler
+            .handle_packet(OscPacket::Message(OscMessage {
+                addr: "/edit/cue/fades".to_string(),
+                args: vec![
+                    OscType::Int(2),
+                    OscType::Int(16),
+                    OscType::Int(4),
+                    OscType::Nil,
+                    OscType::Float(-90.0),
+                    OscType::Float(2.0),
+                    OscType::String("beats".to_string()),
+                    OscType::Int(16),
+                    OscType::Int(5),
+                    OscType::Float(-90.0),
+                    OscType::Float(0.0),
+                    OscType::Float(500.0),
+                    OscType::String("ms".to_string()),
+                ],
+            }))
+            .expect("Assert Ok");
+        assert_eq!(
+            handler.get_core_inputs()[0].0,
+            CoreRequest::SetFadeEvents {
+                cue: 2,
+                events: vec![
+                    FadeEvent {
+                        beat: 16,
+                        channel: 4,
+                        from_db: None,
+                        to_db: -90.0,
+                        length: FadeLength::Beats(2.0),
+                    },
+                    FadeEvent {
+                        beat: 16,
+                        channel: 5,
+                        from_db: Some(-90.0),
+                        to_db: 0.0,
+                        length: FadeLength::Ms(500.0),
+                    },
+                ],
+            }
+        );
+        assert!(
+            handler
+                .handle_packet(OscPacket::Message(OscMessage {
+                    addr: "/edit/cue/fades".to_string(),
+                    args: vec![
+                        OscType::Int(2),
+                        OscType::Int(16),
+                        OscType::Int(4),
+                        OscType::Nil,
+                        OscType::Float(-90.0),
+                        OscType::Float(2.0),
+                        OscType::String("bars".to_string()),
+                    ],
+                }))
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn routing_preset_osc() {
+        let mut handler = OscNetHandler::new(0, ChannelCounts::default());
+        for (addr, request) in [
+            (
+                "/routing/preset/load",
+                CoreRequest::LoadRoutingPreset {
+                    name: "show".to_string(),
+                },
+            ),
+            ("/routing/presets", CoreRequest::GetRoutingPresets),
+        ] {
+            handler
+                .handle_packet(OscPacket::Message(OscMessage {
+                    addr: addr.to_string(),
+                    args: vec![OscType::String("show".to_string())],
+                }))
+                .expect("Assert Ok");
+            assert_eq!(handler.get_core_inputs()[0].0, request);
+        }
+        assert!(
+            handler
+                .handle_packet(OscPacket::Message(OscMessage {
+                    addr: "/routing/preset/store".to_string(),
+                    args: vec![],
+                }))
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn click_synthesis_osc() {
+        let mut handler = OscNetHandler::new(0, ChannelCounts::default());
+        let mut synthesis = ClickSynthesis {
+            waveform: ClickWaveform::Triangle,
+            decay_ms: 2.0,
+            ..Default::default()
+        };
+        synthesis.clicks[0].waveform = Some(ClickWaveform::Square);
+        // The reply carries the arguments the edit takes
+        let reply = OscNetHandler::core_to_osc(CoreMessage::ClickSynthesis(synthesis));
+        handler
+            .handle_packet(OscPacket::Message(OscMessage {
+                addr: "/edit/click_synth".to_string(),
+                args: reply[0].args.clone(),
+            }))
+            .expect("Assert Ok");
+        assert_eq!(
+            handler.get_core_inputs()[0].0,
+            CoreRequest::SetClickSynthesis(synthesis)
+        );
+
+        let samples = [Some("clicks/one.wav".to_string()), None, None];
+        let reply = OscNetHandler::core_to_osc(CoreMessage::ClickSamples(samples.clone()));
+        handler
+            .handle_packet(OscPacket::Message(OscMessage {
+                addr: "/edit/cue/click_samples".to_string(),
+                args: [OscType::Int(0)]
+                    .into_iter()
+                    .chain(reply[0].args.clone())
+                    .collect(),
+            }))
+            .expect("Assert Ok");
+        handler
+            .handle_packet(OscPacket::Message(OscMessage {
+                addr: "/edit/cue/click_samples".to_string(),
+                args: vec![OscType::Int(0)],
+            }))
+            .expect("Assert Ok");
+        let inputs: Vec<CoreRequest> = handler
+            .get_core_inputs()
+            .into_iter()
+            .map(|(request, _)| request)
+            .collect();
+        assert_eq!(
+            inputs,
+            [
+                CoreRequest::SetClickSamples {
+                    cue: Some(0),
+                    samples: Some(samples)
+                },
+                CoreRequest::SetClickSamples {
+                    cue: Some(0),
+                    samples: None
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn vamp_osc() {
+        let mut handler = OscNetHandler::new(0, ChannelCounts::default());
+        for (addr, on) in [
+            ("/control/transport/vamp/on", Some(true)),
+            ("/control/transport/vamp/off", Some(false)),
+            ("/control/transport/vamp/next", None),
+        ] {
+            let result = handler
+                .handle_packet(OscPacket::Message(OscMessage {
+                    addr: addr.to_string(),
+                    args: vec![],
+                }))
+                .expect("Assert Ok");
+            assert!(result.is_empty());
+            let core_inputs = handler.get_core_inputs();
+            assert_eq!(core_inputs[0].0, CoreRequest::SetJumpMode { on });
+        }
+    }
+
+    #[test]
+    fn query_osc() {
+        let mut handler = OscNetHandler::new(0, ChannelCounts::default());
+        // Nothing is known before the core has sent it
+        assert!(handler.query("/message/transport").is_empty());
+        handler.notify(Message::Small(SmallMessage::TransportData(
+            TransportState {
+                running: true,
+                ..Default::default()
+            },
+        )));
+        let config = SystemConfiguration::default();
+        let channels = config.channels.len();
+        handler.notify(Message::Large(LargeMessage::ConfigurationChanged(config)));
+
+        let addresses = |messages: Vec<OscMessage>| -> Vec<String> {
+            messages.into_iter().map(|msg| msg.addr).collect()
+        };
+        assert_eq!(
+            addresses(handler.query("/message/transport")),
+            ["/message/transport/running", "/message/transport/vamp"]
+        );
+        assert_eq!(
+            handler.query("/message/transport/running")[0].args,
+            [OscType::Bool(true)]
+        );
+        assert_eq!(
+            handler.query("/message/channel/0/gain").len(),
+            channels.min(1)
+        );
+        assert_eq!(handler.query("/message/channel/*/gain").len(), channels);
+        assert!(handler.query("/message/cue").is_empty());
+        assert!(handler.query("/message/trans").is_empty());
+
+        // Core notifications are answered with the latest one per address
+        assert!(handler.query("/message/channel/3/mute").is_empty());
+        handler.notify_core(&CoreMessage::ChannelMuted {
+            channel: 3,
+            muted: true,
+        });
+        handler.notify_core(&CoreMessage::ChannelMuted {
+            channel: 3,
+            muted: false,
+        });
+        let muted = handler.query("/message/channel/3/mute");
+        assert_eq!(muted.len(), 1);
+        assert_eq!(muted[0].args, [OscType::Bool(false)]);
+    }
 }
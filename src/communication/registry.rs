@@ -0,0 +1,256 @@
+use crate::{
+    cbnet::CrossbeamNetwork,
+    communication::{
+        binnet::BinaryNetHandler,
+        extension::{CoreMessage, CoreRequest},
+        interface::{CommunicationInterface, RequestSource},
+        jsonnet::JsonNetHandler,
+        midi::{MMC_ALL_DEVICES, MidiHandler},
+        osc::OscNetHandler,
+        web::WebStatusHandler,
+    },
+    config::{CoreConfiguration, HandlerConfiguration, NotificationTarget, TargetProtocol},
+    logger::LogDispatcher,
+    metrics::HandlerRates,
+};
+use common::{
+    local::config::{LogContext, LogItem, LogKind},
+    protocol::{message::Message, request::Request},
+};
+
+/// All communication handlers of the core. Requests are collected from, and notifications are
+/// sent to, every registered handler alike, so adding a protocol only means registering its
+/// handler.
+#[derive(Default)]
+pub struct HandlerRegistry {
+    handlers: Vec<Box<dyn CommunicationInterface>>,
+}
+
+impl HandlerRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Opens every handler listed in the configuration.
+    pub fn from_config(
+        core_config: &CoreConfiguration,
+        cbnet: &CrossbeamNetwork,
+        logger: &LogDispatcher,
+    ) -> Self {
+        let channel_counts = core_config.channel_counts;
+        // Fixed notification targets are sent by the first handler of their protocol
+        let mut targets = core_config.notification_targets.clone();
+        let mut take_targets = |protocol| {
+            let (taken, rest) = std::mem::take(&mut targets)
+                .into_iter()
+                .partition(|target: &NotificationTarget| target.protocol == protocol);
+            targets = rest;
+            taken
+        };
+        let mut registry = Self::new();
+        for handler in &core_config.handlers {
+            match handler {
+                HandlerConfiguration::Binary { port } => registry.register(Box::new(
+                    BinaryNetHandler::new(logger, *port)
+                        .with_targets(take_targets(TargetProtocol::Binary)),
+                )),
+                HandlerConfiguration::Osc { port } => registry.register(Box::new(
+                    OscNetHandler::new(*port, channel_counts)
+                        .with_targets(take_targets(TargetProtocol::Osc)),
+                )),
+                HandlerConfiguration::Json { port, transport } => {
+                    registry.register(Box::new(JsonNetHandler::new(logger, *port, *transport)))
+                }
+                HandlerConfiguration::Web { port } => {
+                    registry.register(Box::new(WebStatusHandler::new(logger, *port)))
+                }
+                HandlerConfiguration::Midi {
+                    mmc_device,
+                    mtc_output,
+                } => registry.register(Box::new(MidiHandler::new(
+                    cbnet.clone(),
+                    core_config.midi_mappings.clone(),
+                    mmc_device.unwrap_or(MMC_ALL_DEVICES),
+                    mtc_output.clone(),
+                ))),
+            }
+        }
+        for target in targets {
+            logger.log(LogItem::new(
+                format!(
+                    "No handler to send notifications to {} with, its protocol is not opened",
+                    target.address
+                ),
+                LogContext::Network,
+                LogKind::Warning,
+            ));
+        }
+        registry
+    }
+
+    pub fn register(&mut self, handler: Box<dyn CommunicationInterface>) {
+        self.handlers.push(handler);
+    }
+
+    pub fn get_all_inputs(&mut self) -> Vec<(Request, RequestSource)> {
+        self.handlers
+            .iter_mut()
+            .enumerate()
+            .flat_map(|(index, handler)| Self::received_by(index, handler.get_all_inputs()))
+            .collect()
+    }
+
+    pub fn get_core_inputs(&mut self) -> Vec<(CoreRequest, RequestSource)> {
+        self.handlers
+            .iter_mut()
+            .enumerate()
+            .flat_map(|(index, handler)| Self::received_by(index, handler.get_core_inputs()))
+            .collect()
+    }
+
+    /// Marks requests as received by the handler at `index`, so replies go back through it.
+    fn received_by<T>(
+        index: usize,
+        inputs: Vec<(T, RequestSource)>,
+    ) -> impl Iterator<Item = (T, RequestSource)> {
+        inputs.into_iter().map(move |(request, mut source)| {
+            source.handler_index = Some(index);
+            (request, source)
+        })
+    }
+
+    pub fn notify(&mut self, message: Message) {
+        for handler in &mut self.handlers {
+            handler.notify(message.clone());
+        }
+    }
+
+    pub fn notify_core(&mut self, message: &CoreMessage) {
+        for handler in &mut self.handlers {
+            handler.notify_core(message);
+        }
+    }
+
+    /// Send a message back to where a request came from.
+    pub fn reply(&mut self, to: &RequestSource, message: CoreMessage) {
+        if let Some(handler) = to
+            .handler_index
+            .and_then(|index| self.handlers.get_mut(index))
+        {
+            handler.reply(to.address, message);
+        }
+    }
+
+    /// Hand the subscribers of all handlers to the handlers that show them.
+    pub fn publish_subscribers(&mut self) {
+        let subscribers: Vec<_> = self
+            .handlers
+            .iter()
+            .flat_map(|handler| handler.subscribers())
+            .collect();
+        for handler in &mut self.handlers {
+            handler.notify_subscribers(&subscribers);
+        }
+    }
+
+    /// Move all handlers to the current address of the network interface, after it has changed.
+    pub fn rebind(&mut self, logger: &LogDispatcher) {
+        for handler in &mut self.handlers {
+            match handler.rebind() {
+                Ok(Some(address)) => logger.log(LogItem::new(
+                    format!("moved {} to {address}", handler.name()),
+                    LogContext::Network,
+                    LogKind::Note,
+                )),
+                Ok(None) => {}
+                Err(err) => logger.log(LogItem::new(
+                    format!(
+                        "Could not move {} to the new address: {err}",
+                        handler.name()
+                    ),
+                    LogContext::Network,
+                    LogKind::Error,
+                )),
+            }
+        }
+    }
+
+    pub fn take_packet_rates(&mut self) -> Vec<HandlerRates> {
+        self.handlers
+            .iter_mut()
+            .map(|handler| {
+                let (packets_in, packets_out) = handler.take_packet_counts();
+                HandlerRates {
+                    handler: handler.name(),
+                    packets_in,
+                    packets_out,
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common::protocol::request::ControlAction;
+    use std::{
+        net::SocketAddr,
+        sync::{Arc, Mutex},
+    };
+
+    /// Handler with one pending request, recording the replies it is given.
+    struct Stub {
+        request: Option<(Request, RequestSource)>,
+        replies: Arc<Mutex<Vec<SocketAddr>>>,
+    }
+
+    impl CommunicationInterface for Stub {
+        fn name(&self) -> &'static str {
+            "stub"
+        }
+
+        fn get_inputs(&mut self, _limit: usize) -> Vec<(Request, RequestSource)> {
+            self.request.take().into_iter().collect()
+        }
+
+        fn notify(&mut self, _message: Message) {}
+
+        fn notify_multiple(&mut self, _messages: Vec<Message>) {}
+
+        fn reply(&mut self, to: SocketAddr, _message: CoreMessage) {
+            self.replies.lock().unwrap().push(to);
+        }
+    }
+
+    #[test]
+    fn replies_go_to_the_receiving_handler() {
+        let address: SocketAddr = "10.0.0.2:8081".parse().unwrap();
+        let replies: Vec<Arc<Mutex<Vec<SocketAddr>>>> = vec![Default::default(); 2];
+        let mut registry = HandlerRegistry::new();
+        registry.register(Box::new(Stub {
+            request: None,
+            replies: replies[0].clone(),
+        }));
+        registry.register(Box::new(Stub {
+            request: Some((
+                Request::ControlAction(ControlAction::TransportStart),
+                RequestSource {
+                    address,
+                    ..RequestSource::front_panel()
+                },
+            )),
+            replies: replies[1].clone(),
+        }));
+
+        let inputs = registry.get_all_inputs();
+        assert_eq!(inputs.len(), 1);
+        registry.reply(&inputs[0].1, CoreMessage::Standby(None));
+        assert!(replies[0].lock().unwrap().is_empty());
+        assert_eq!(*replies[1].lock().unwrap(), vec![address]);
+
+        // Requests made by the core itself have no handler to reply through
+        registry.reply(&RequestSource::front_panel(), CoreMessage::Standby(None));
+        assert_eq!(replies[1].lock().unwrap().len(), 1);
+    }
+}
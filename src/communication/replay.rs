@@ -0,0 +1,81 @@
+use common::{
+    mem::typeflags::MessageType,
+    protocol::message::{LargeMessage, Message},
+};
+
+/// The most recent message of every type sent by a handler, replayed to clients that subscribe
+/// mid-show so they are up to date without a `NotifySubscribers` round-trip through the audio
+/// processor.
+#[derive(Debug, Default)]
+pub struct MessageCache {
+    messages: Vec<Message>,
+}
+
+impl MessageCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Keeps `message` as the latest of its type. Log items are a stream rather than state, and
+    /// are never kept.
+    pub fn store(&mut self, message: &Message) {
+        if let Message::Large(LargeMessage::Log(_)) = message {
+            return;
+        }
+        let message_type = message.to_type();
+        match self
+            .messages
+            .iter_mut()
+            .find(|cached| cached.to_type() == message_type)
+        {
+            Some(cached) => *cached = message.clone(),
+            None => self.messages.push(message.clone()),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.messages.is_empty()
+    }
+
+    /// Latest message of every type, in the order the types were first sent.
+    pub fn messages(&self) -> impl Iterator<Item = &Message> {
+        self.messages.iter()
+    }
+
+    /// Latest message of the given type, if one was sent.
+    pub fn get(&self, message_type: MessageType) -> Option<&Message> {
+        self.messages
+            .iter()
+            .find(|cached| cached.to_type() == message_type)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common::{
+        cue::{Cue, Show},
+        local::config::{LogContext, LogItem, LogKind},
+    };
+
+    #[test]
+    fn keeps_latest_per_type() {
+        let mut cache = MessageCache::new();
+        let mut show = Show::default();
+        cache.store(&Message::Large(LargeMessage::ShowData(show.clone())));
+        show.cues.push(Cue::example());
+        cache.store(&Message::Large(LargeMessage::ShowData(show)));
+        cache.store(&Message::Large(LargeMessage::Log(LogItem::new(
+            "not state".to_string(),
+            LogContext::Network,
+            LogKind::Note,
+        ))));
+
+        assert_eq!(cache.messages().count(), 1);
+        let Some(Message::Large(LargeMessage::ShowData(cached))) = cache.get(MessageType::ShowData)
+        else {
+            panic!("show data was not cached");
+        };
+        assert_eq!(cached.cues.len(), 1);
+    }
+}
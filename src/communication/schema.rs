@@ -0,0 +1,148 @@
+//! Machine-readable description of the protocol, for client developers generating bindings.
+//!
+//! The description is traced with serde-reflection from the `Deserialize` implementations of
+//! the protocol types, so it always matches the running version. Types are described in the
+//! serde data model, which maps directly onto postcard on the binary protocol and onto JSON.
+use crate::communication::extension::{
+    CORE_MESSAGE_BYTE, CORE_REQUEST_BYTE, CoreMessage, CoreRequest,
+};
+use common::protocol::{message::Message, request::Request};
+use serde::{Deserialize, Serialize};
+use serde_reflection::{Format, Registry, Tracer, TracerConfig};
+use std::path::Path;
+
+/// Most bytes of the JSON schema in one reply, so every chunk fits a datagram on all handlers,
+/// even with its quotes escaped on the JSON protocol.
+pub const SCHEMA_CHUNK_SIZE: usize = 16 * 1024;
+
+/// All requests and messages of the protocol and the types they are made of.
+#[derive(Debug, Clone, Serialize)]
+pub struct ProtocolSchema {
+    pub version: String,
+    /// Byte a `CoreRequest` is sent after on the binary protocol
+    pub core_request_byte: u8,
+    /// Byte a `CoreMessage` is sent after on the binary protocol
+    pub core_message_byte: u8,
+    /// Types clients send: the common `Request` and the core extension
+    pub requests: Vec<Format>,
+    /// Types clients receive: the common `Message` and the core extension
+    pub messages: Vec<Format>,
+    /// Every struct and enum, by name. Enum variants are keyed by their index, which is how
+    /// they are told apart on the binary protocol.
+    pub types: Registry,
+}
+
+/// One part of the protocol schema as JSON. The schema is split into `count` chunks, sent in
+/// order of `index`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SchemaChunk {
+    pub index: u16,
+    pub count: u16,
+    pub json: String,
+}
+
+impl ProtocolSchema {
+    /// Traces the protocol of this build. Fails if a type cannot be traced completely.
+    pub fn current() -> Result<Self, String> {
+        let mut tracer = Tracer::new(TracerConfig::default());
+        let requests = vec![
+            trace::<Request>(&mut tracer)?,
+            trace::<CoreRequest>(&mut tracer)?,
+        ];
+        let messages = vec![
+            trace::<Message>(&mut tracer)?,
+            trace::<CoreMessage>(&mut tracer)?,
+        ];
+        Ok(Self {
+            version: crate::VERSION.to_string(),
+            core_request_byte: CORE_REQUEST_BYTE,
+            core_message_byte: CORE_MESSAGE_BYTE,
+            requests,
+            messages,
+            types: tracer.registry().map_err(|err| err.to_string())?,
+        })
+    }
+
+    pub fn write(&self, path: &Path) -> Result<(), String> {
+        let content = serde_json::to_vec_pretty(self).map_err(|err| err.to_string())?;
+        std::fs::write(path, content).map_err(|err| err.to_string())
+    }
+
+    /// The schema as JSON, in chunks of at most `SCHEMA_CHUNK_SIZE` bytes.
+    pub fn chunks(&self) -> Result<Vec<SchemaChunk>, String> {
+        let json = serde_json::to_string(self).map_err(|err| err.to_string())?;
+        split(&json, SCHEMA_CHUNK_SIZE)
+    }
+}
+
+fn trace<'de, T: Deserialize<'de>>(tracer: &mut Tracer) -> Result<Format, String> {
+    tracer
+        .trace_simple_type::<T>()
+        .map(|(format, _)| format)
+        .map_err(|err| err.to_string())
+}
+
+/// `json` in chunks of at most `chunk_size` bytes, never splitting a character.
+fn split(json: &str, chunk_size: usize) -> Result<Vec<SchemaChunk>, String> {
+    let mut parts = vec![];
+    let mut rest = json;
+    while !rest.is_empty() {
+        let mut end = chunk_size.min(rest.len());
+        while !rest.is_char_boundary(end) {
+            end -= 1;
+        }
+        let (part, tail) = rest.split_at(end);
+        parts.push(part);
+        rest = tail;
+    }
+    let count =
+        u16::try_from(parts.len()).map_err(|_| "schema is too large to send".to_string())?;
+    Ok(parts
+        .into_iter()
+        .enumerate()
+        .map(|(index, json)| SchemaChunk {
+            index: index as u16,
+            count,
+            json: json.to_string(),
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_reflection::ContainerFormat;
+
+    #[test]
+    fn core_protocol_fully_traced() {
+        let schema = ProtocolSchema::current().unwrap();
+        for name in ["CoreRequest", "CoreMessage"] {
+            let Some(ContainerFormat::Enum(variants)) = schema.types.get(name) else {
+                panic!("{name} is not traced");
+            };
+            assert!(variants.len() > 1, "{name}");
+        }
+    }
+
+    #[test]
+    fn chunks_keep_characters_whole() {
+        let chunks = split("ab→cd", 3).unwrap();
+        assert_eq!(
+            chunks
+                .iter()
+                .map(|chunk| chunk.json.as_str())
+                .collect::<Vec<_>>(),
+            ["ab", "→", "cd"]
+        );
+        assert!(chunks.iter().all(|chunk| chunk.count == 3));
+
+        let schema = ProtocolSchema::current().unwrap();
+        let json: String = schema
+            .chunks()
+            .unwrap()
+            .into_iter()
+            .map(|chunk| chunk.json)
+            .collect();
+        assert_eq!(json, serde_json::to_string(&schema).unwrap());
+    }
+}
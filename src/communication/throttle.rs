@@ -0,0 +1,174 @@
+use common::{
+    mem::typeflags::MessageType,
+    protocol::message::{Message, SmallMessage},
+};
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
+
+/// Highest number of messages per second sent for each continuously updated message type, 0 for
+/// no limit.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NotificationRates {
+    pub transport: u8,
+    pub beat: u8,
+    pub timecode: u8,
+}
+
+impl Default for NotificationRates {
+    fn default() -> Self {
+        Self {
+            transport: 10,
+            beat: 0,
+            timecode: 30,
+        }
+    }
+}
+
+impl NotificationRates {
+    /// Shortest time between two messages of a type, or None if the type is not limited.
+    fn interval(&self, message_type: MessageType) -> Option<Duration> {
+        let rate_hz = match message_type {
+            MessageType::TransportData => self.transport,
+            MessageType::BeatData => self.beat,
+            MessageType::TimecodeData => self.timecode,
+            _ => 0,
+        };
+        (rate_hz > 0).then(|| Duration::from_secs(1) / rate_hz as u32)
+    }
+}
+
+struct ThrottledType {
+    last: Message,
+    last_sent: Instant,
+    pending: Option<Message>,
+}
+
+/// Limits how often continuously updated messages are passed on to the handlers. Messages
+/// arriving too soon are held back, and only the latest of them is sent once the interval has
+/// passed, so the final state always arrives. A discrete change, like the transport starting or
+/// the beat advancing, is always sent immediately.
+pub struct NotificationThrottle {
+    rates: NotificationRates,
+    types: Vec<ThrottledType>,
+}
+
+impl NotificationThrottle {
+    pub fn new(rates: NotificationRates) -> Self {
+        Self {
+            rates,
+            types: vec![],
+        }
+    }
+
+    pub fn set_rates(&mut self, rates: NotificationRates) {
+        self.rates = rates;
+    }
+
+    /// Returns the message if it should be sent now, or holds it back.
+    pub fn offer(&mut self, message: Message, now: Instant) -> Option<Message> {
+        let message_type = message.to_type();
+        let Some(interval) = self.rates.interval(message_type) else {
+            return Some(message);
+        };
+        let Some(throttled) = self
+            .types
+            .iter_mut()
+            .find(|throttled| throttled.last.to_type() == message_type)
+        else {
+            self.types.push(ThrottledType {
+                last: message.clone(),
+                last_sent: now,
+                pending: None,
+            });
+            return Some(message);
+        };
+
+        if is_discrete_change(&throttled.last, &message)
+            || now.duration_since(throttled.last_sent) >= interval
+        {
+            throttled.last = message.clone();
+            throttled.last_sent = now;
+            throttled.pending = None;
+            Some(message)
+        } else {
+            throttled.pending = Some(message);
+            None
+        }
+    }
+
+    /// Held back messages whose interval has passed.
+    pub fn take_due(&mut self, now: Instant) -> Vec<Message> {
+        let mut due = vec![];
+        for throttled in &mut self.types {
+            let Some(interval) = self.rates.interval(throttled.last.to_type()) else {
+                // No longer limited
+                due.extend(throttled.pending.take());
+                continue;
+            };
+            if now.duration_since(throttled.last_sent) >= interval
+                && let Some(message) = throttled.pending.take()
+            {
+                throttled.last = message.clone();
+                throttled.last_sent = now;
+                due.push(message);
+            }
+        }
+        due
+    }
+}
+
+fn is_discrete_change(last: &Message, message: &Message) -> bool {
+    match (last, message) {
+        (
+            Message::Small(SmallMessage::TransportData(last)),
+            Message::Small(SmallMessage::TransportData(transport)),
+        ) => {
+            last.running != transport.running || last.playrate_percent != transport.playrate_percent
+        }
+        (
+            Message::Small(SmallMessage::BeatData(last)),
+            Message::Small(SmallMessage::BeatData(beat)),
+        ) => last.beat_idx != beat.beat_idx,
+        (
+            Message::Small(SmallMessage::TimecodeData(last)),
+            Message::Small(SmallMessage::TimecodeData(timecode)),
+        ) => last.running != timecode.running,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common::local::status::TransportState;
+
+    fn transport(running: bool) -> Message {
+        Message::Small(SmallMessage::TransportData(TransportState {
+            running,
+            ..Default::default()
+        }))
+    }
+
+    #[test]
+    fn limits_and_flushes() {
+        let mut throttle = NotificationThrottle::new(NotificationRates {
+            transport: 10,
+            ..Default::default()
+        });
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+
+        assert!(throttle.offer(transport(false), at(0)).is_some());
+        assert!(throttle.offer(transport(false), at(20)).is_none());
+        assert!(throttle.offer(transport(false), at(40)).is_none());
+        assert!(throttle.take_due(at(60)).is_empty());
+        // Only the latest held back message is sent once the interval has passed
+        assert_eq!(throttle.take_due(at(100)).len(), 1);
+        assert!(throttle.take_due(at(300)).is_empty());
+
+        // Starting the transport is sent right away
+        assert!(throttle.offer(transport(true), at(310)).is_some());
+        assert!(throttle.offer(transport(true), at(320)).is_none());
+    }
+}
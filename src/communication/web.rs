@@ -0,0 +1,375 @@
+use crate::{
+    communication::{
+        extension::CoreMessage,
+        interface::{CommunicationInterface, RequestSource, SubscriberVersion},
+    },
+    logger::LogDispatcher,
+    metrics::{DiskUsage, DspCycleReport, ResourceAlarm, ResourceUsage},
+};
+use common::{
+    local::config::{LogContext, LogItem, LogKind},
+    mem::time::format_hms,
+    protocol::{
+        message::{LargeMessage, Message, SmallMessage},
+        request::Request,
+    },
+};
+use serde::Serialize;
+use std::{
+    collections::VecDeque,
+    io::{BufRead, BufReader, Write},
+    net::{TcpListener, TcpStream},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+/// Log entries shown on the status page
+const LOG_LINES: usize = 20;
+/// Seconds between reloads of the status page in the browser
+const REFRESH_SECS: u32 = 2;
+/// Time a browser gets to send its request before the connection is dropped
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(2);
+/// Time a browser gets to take the page before the connection is dropped
+const RESPONSE_TIMEOUT: Duration = Duration::from_secs(5);
+/// Most header lines read from a request, so a browser cannot keep a connection open by
+/// sending headers forever
+const MAX_HEADER_LINES: usize = 100;
+
+/// What the status page shows, gathered from the notifications sent to all handlers.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct DashboardStatus {
+    pub device_name: String,
+    pub cue: Option<DashboardCue>,
+    pub beat: u16,
+    /// Bar and beat within the bar, e.g. "12.3"
+    pub counter: String,
+    pub running: bool,
+    pub jack: Option<DashboardJack>,
+    pub dsp: Option<DspCycleReport>,
+    pub resources: Option<ResourceUsage>,
+    pub alarms: Vec<ResourceAlarm>,
+    pub subscribers: Vec<SubscriberVersion>,
+    /// Latest log entries, oldest first
+    pub log: VecDeque<LogItem>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DashboardCue {
+    pub index: u16,
+    pub ident: String,
+    pub name: String,
+    pub beats: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DashboardJack {
+    pub running: bool,
+    pub client_name: String,
+    pub sample_rate: usize,
+    pub buffer_size: usize,
+}
+
+impl DashboardStatus {
+    fn update(&mut self, message: &Message) {
+        match message {
+            Message::Large(LargeMessage::CueData(cue)) => {
+                self.cue = Some(DashboardCue {
+                    index: cue.cue_idx as u16,
+                    ident: cue.cue.metadata.human_ident.str().to_string(),
+                    name: cue.cue.metadata.name.str().to_string(),
+                    beats: cue.cue.get_beats().len(),
+                });
+            }
+            Message::Large(LargeMessage::JACKStateChanged(jack)) => {
+                self.jack = Some(DashboardJack {
+                    running: jack.running,
+                    client_name: jack.client_name.str().to_string(),
+                    sample_rate: jack.sample_rate,
+                    buffer_size: jack.buffer_size,
+                });
+            }
+            Message::Large(LargeMessage::Log(item)) => {
+                if self.log.len() >= LOG_LINES {
+                    self.log.pop_front();
+                }
+                self.log.push_back(item.clone());
+            }
+            Message::Small(SmallMessage::TransportData(transport)) => {
+                self.running = transport.running;
+            }
+            Message::Small(SmallMessage::BeatData(beat)) => {
+                self.beat = beat.beat_idx;
+                self.counter = format!("{}.{}", beat.beat.bar_number, beat.beat.count);
+            }
+            _ => {}
+        }
+    }
+
+    fn update_core(&mut self, message: &CoreMessage) {
+        match message {
+            CoreMessage::Identity { name } => self.device_name = name.clone(),
+            CoreMessage::Metrics(report) => {
+                self.dsp = Some(report.dsp.clone());
+                self.resources = Some(report.resources);
+            }
+            CoreMessage::ResourceAlarms(alarms) => self.alarms = alarms.clone(),
+            _ => {}
+        }
+    }
+}
+
+/// Serves a read-only status page over HTTP, so the rig can be checked from any browser. `/`
+/// is a page that reloads itself, `/status.json` the same status as JSON.
+///
+/// The page is served from a thread of its own, so slow browsers do not hold up the main loop,
+/// and every connection on one more, so they do not hold up each other.
+/// The handler only keeps the status it shows up to date, and never takes requests.
+pub struct WebStatusHandler {
+    status: Arc<Mutex<DashboardStatus>>,
+}
+
+impl WebStatusHandler {
+    pub fn new(logger: &LogDispatcher, port: usize) -> Self {
+        let status = Arc::new(Mutex::new(DashboardStatus::default()));
+        let served = status.clone();
+        let opened = TcpListener::bind(("0.0.0.0", port as u16)).and_then(|listener| {
+            let address = listener.local_addr()?;
+            std::thread::Builder::new()
+                .name("clicks-web".to_string())
+                .spawn(move || serve(listener, served))?;
+            Ok(address)
+        });
+        logger.log(match opened {
+            Ok(address) => LogItem::new(
+                format!("opened web status page on {address}"),
+                LogContext::Network,
+                LogKind::Note,
+            ),
+            Err(err) => LogItem::new(
+                format!("Could not open web status page on port {port}: {err}"),
+                LogContext::Network,
+                LogKind::Error,
+            ),
+        });
+        Self { status }
+    }
+
+    fn status(&self) -> std::sync::MutexGuard<'_, DashboardStatus> {
+        self.status.lock().unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+impl CommunicationInterface for WebStatusHandler {
+    fn name(&self) -> &'static str {
+        "web"
+    }
+
+    fn get_inputs(&mut self, _limit: usize) -> Vec<(Request, RequestSource)> {
+        vec![]
+    }
+
+    fn notify(&mut self, message: Message) {
+        self.status().update(&message);
+    }
+
+    fn notify_multiple(&mut self, messages: Vec<Message>) {
+        let mut status = self.status();
+        for message in &messages {
+            status.update(message);
+        }
+    }
+
+    fn notify_core(&mut self, message: &CoreMessage) {
+        self.status().update_core(message);
+    }
+
+    fn notify_subscribers(&mut self, subscribers: &[SubscriberVersion]) {
+        self.status().subscribers = subscribers.to_vec();
+    }
+}
+
+fn serve(listener: TcpListener, status: Arc<Mutex<DashboardStatus>>) {
+    for stream in listener.incoming().flatten() {
+        let snapshot = status.lock().unwrap_or_else(|err| err.into_inner()).clone();
+        let _ = std::thread::Builder::new()
+            .name("clicks-web-conn".to_string())
+            .spawn(move || respond(stream, &snapshot));
+    }
+}
+
+fn respond(mut stream: TcpStream, status: &DashboardStatus) -> std::io::Result<()> {
+    stream.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+    stream.set_write_timeout(Some(RESPONSE_TIMEOUT))?;
+    let mut reader = BufReader::new(&stream);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    // Skip the headers, nothing in them matters
+    let mut header = String::new();
+    for _ in 0..MAX_HEADER_LINES {
+        if reader.read_line(&mut header)? <= 2 {
+            break;
+        }
+        header.clear();
+    }
+
+    let mut parts = request_line.split_whitespace();
+    let (status_line, content_type, body) = match (parts.next(), parts.next()) {
+        (Some("GET"), Some("/")) => ("200 OK", "text/html; charset=utf-8", render_page(status)),
+        (Some("GET"), Some("/status.json")) => (
+            "200 OK",
+            "application/json",
+            serde_json::to_string(status).unwrap_or_default(),
+        ),
+        (Some("GET"), _) => ("404 Not Found", "text/plain", "Not found".to_string()),
+        _ => (
+            "405 Method Not Allowed",
+            "text/plain",
+            "The status page is read-only".to_string(),
+        ),
+    };
+    write!(
+        stream,
+        "HTTP/1.1 {status_line}\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\nCache-Control: no-store\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    )
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// The status page: one table per part of the rig, and the latest log entries.
+pub fn render_page(status: &DashboardStatus) -> String {
+    let name = escape(&status.device_name);
+    let cue = match &status.cue {
+        Some(cue) => format!(
+            "{} {} ({} beats)",
+            escape(&cue.ident),
+            escape(&cue.name),
+            cue.beats
+        ),
+        None => "none".to_string(),
+    };
+    let transport = if status.running { "running" } else { "stopped" };
+    let jack = match &status.jack {
+        Some(jack) if jack.running => format!(
+            "running as {}, {} Hz, {} frames",
+            escape(&jack.client_name),
+            jack.sample_rate,
+            jack.buffer_size
+        ),
+        Some(_) => "<b>not running</b>".to_string(),
+        None => "unknown".to_string(),
+    };
+    let dsp = match &status.dsp {
+        Some(dsp) => format!(
+            "{} cycles/s, mean {} µs, max {} µs",
+            dsp.cycles, dsp.mean_us, dsp.max_us
+        ),
+        None => "unknown".to_string(),
+    };
+    let megabytes = |bytes: u64| bytes / (1024 * 1024);
+    let disk = |disk: Option<DiskUsage>| match disk {
+        Some(disk) => format!(
+            "{} of {} MB free",
+            megabytes(disk.free_bytes),
+            megabytes(disk.total_bytes)
+        ),
+        None => "unknown".to_string(),
+    };
+    let resources = status.resources.unwrap_or_default();
+    let memory = match resources.rss_bytes {
+        Some(rss) => format!("{} MB", megabytes(rss)),
+        None => "unknown".to_string(),
+    };
+    let alarms: String = status
+        .alarms
+        .iter()
+        .map(|alarm| format!("<p><b>Warning: {alarm}</b></p>\n"))
+        .collect();
+    let subscribers: String = status
+        .subscribers
+        .iter()
+        .map(|subscriber| {
+            format!(
+                "<tr><td>{}</td><td>{}</td><td>{}</td></tr>",
+                escape(&subscriber.identifier),
+                subscriber.address,
+                subscriber
+                    .protocol_version
+                    .map_or("-".to_string(), |version| version.to_string())
+            )
+        })
+        .collect();
+    let log: String = status
+        .log
+        .iter()
+        .rev()
+        .map(|item| {
+            format!(
+                "<tr><td>{}</td><td>{}</td><td>{}</td></tr>",
+                format_hms(item.time / 1000).str(),
+                item.kind,
+                escape(item.message.trim())
+            )
+        })
+        .collect();
+
+    format!(
+        "<!DOCTYPE html>
+<html><head><meta charset=\"utf-8\"><meta http-equiv=\"refresh\" content=\"{REFRESH_SECS}\">
+<meta name=\"viewport\" content=\"width=device-width\"><title>{name}</title>
+<style>body{{font-family:sans-serif}}td{{padding:0 .5em}}</style></head>
+<body><h1>{name}</h1>
+{alarms}<table>
+<tr><td>Cue</td><td>{cue}</td></tr>
+<tr><td>Beat</td><td>{} ({})</td></tr>
+<tr><td>Transport</td><td>{transport}</td></tr>
+<tr><td>JACK</td><td>{jack}</td></tr>
+<tr><td>DSP</td><td>{dsp}</td></tr>
+<tr><td>Show disk</td><td>{}</td></tr>
+<tr><td>Log disk</td><td>{}</td></tr>
+<tr><td>Memory</td><td>{memory}</td></tr>
+</table>
+<h2>Subscribers</h2>
+<table>{subscribers}</table>
+<h2>Log</h2>
+<table>{log}</table>
+</body></html>
+",
+        status.beat,
+        status.counter,
+        disk(resources.show_disk),
+        disk(resources.log_disk)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn status_page() {
+        let status = DashboardStatus {
+            device_name: "Clicks-StageLeft".to_string(),
+            cue: Some(DashboardCue {
+                index: 3,
+                ident: "4".to_string(),
+                name: "Rock & <Roll>".to_string(),
+                beats: 96,
+            }),
+            running: true,
+            alarms: vec![ResourceAlarm::LogDiskLow],
+            ..Default::default()
+        };
+        let page = render_page(&status);
+        assert!(page.contains("<title>Clicks-StageLeft</title>"));
+        assert!(page.contains("4 Rock &amp; &lt;Roll&gt; (96 beats)"));
+        assert!(page.contains("<td>running</td>"));
+        assert!(page.contains("<td>unknown</td>"));
+        assert!(page.contains("Warning: log disk space is running out"));
+    }
+}
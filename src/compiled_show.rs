@@ -0,0 +1,173 @@
+//! Shows compiled to postcard, which load in milliseconds where parsing `show.bin` of a show
+//! with hundreds of cues takes seconds on the Pi. The compiled show is written next to
+//! `show.bin` the first time a show is loaded, or ahead of time with `--compile-show`, and is
+//! used for as long as it matches the `show.bin` on disk.
+use crate::{boot::BootError, manifest};
+use common::cue::{Show, ShowBuilder};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// File in the show directory holding the compiled show, next to `show.bin`.
+pub const COMPILED_SHOW_FILE: &str = "show.compiled";
+/// Layout of the compiled show file, bumped when it changes
+const FORMAT_VERSION: u8 = 2;
+
+/// Written ahead of the show, and read on its own, so a show compiled by another version is
+/// never decoded.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct Header {
+    format: u8,
+    /// Version of clicks-common, which defines the layout of `Show`
+    common_version: String,
+    /// Locked git source of clicks-common, with its commit. It follows a branch, and postcard
+    /// is not self-describing, so a layout changed without a version bump would otherwise be
+    /// decoded as garbage.
+    common_source: String,
+    /// SHA-256 of the `show.bin` the show was compiled from, as lowercase hex
+    source: String,
+}
+
+impl Header {
+    fn current(source: String) -> Self {
+        Self {
+            format: FORMAT_VERSION,
+            common_version: common::VERSION.to_string(),
+            common_source: env!("CLICKS_COMMON_SOURCE").to_string(),
+            source,
+        }
+    }
+}
+
+/// A show read from a show directory.
+#[derive(Debug)]
+pub struct LoadedShow {
+    pub show: Show,
+    /// Whether the show was read from its compiled form rather than parsed
+    pub compiled: bool,
+    /// Why the show could not be compiled after being parsed, if it could not
+    pub compile_error: Option<String>,
+}
+
+/// Reads the show of a show directory, from its compiled form if that is up to date. Otherwise
+/// `show.bin` is parsed and compiled for the next time.
+pub fn load(show_path: &Path) -> Result<LoadedShow, BootError> {
+    let source = source_checksum(show_path)?;
+    if let Some(show) = read(show_path, &source) {
+        return Ok(LoadedShow {
+            show,
+            compiled: true,
+            compile_error: None,
+        });
+    }
+    let show = parse(show_path)?;
+    let compile_error = write(show_path, &source, &show)
+        .err()
+        .map(|err| err.to_string());
+    Ok(LoadedShow {
+        show,
+        compiled: false,
+        compile_error,
+    })
+}
+
+/// Parses `show.bin` of a show directory and writes its compiled form, whether or not one is
+/// there already.
+pub fn compile(show_path: &Path) -> Result<Show, BootError> {
+    let source = source_checksum(show_path)?;
+    let show = parse(show_path)?;
+    write(show_path, &source, &show)?;
+    Ok(show)
+}
+
+fn parse(show_path: &Path) -> Result<Show, BootError> {
+    ShowBuilder::from_bin_file(show_path.join("show.bin"))
+        .map_err(|err| BootError::FileReadError(format!("{err:?}")))
+}
+
+fn source_checksum(show_path: &Path) -> Result<String, BootError> {
+    manifest::checksum(&show_path.join("show.bin"))
+        .map_err(|err| BootError::FileReadError(format!("show.bin: {err}")))
+}
+
+/// The compiled show, if there is one compiled from `source` by this version. A show that does
+/// not decode to exactly the bytes written is parsed from `show.bin` again.
+fn read(show_path: &Path, source: &str) -> Option<Show> {
+    let bytes = std::fs::read(show_path.join(COMPILED_SHOW_FILE)).ok()?;
+    let (header, rest) = postcard::take_from_bytes::<Header>(&bytes).ok()?;
+    if header != Header::current(source.to_string()) {
+        return None;
+    }
+    match postcard::take_from_bytes(rest) {
+        Ok((show, [])) => Some(show),
+        _ => None,
+    }
+}
+
+fn write(show_path: &Path, source: &str, show: &Show) -> Result<(), BootError> {
+    let mut bytes = postcard::to_stdvec(&Header::current(source.to_string()))
+        .map_err(|err| BootError::ConfigWriteError(err.to_string()))?;
+    bytes.extend(
+        postcard::to_stdvec(show).map_err(|err| BootError::ConfigWriteError(err.to_string()))?,
+    );
+    // Written aside and moved in place, so a power cut never leaves half a show behind
+    let path = show_path.join(COMPILED_SHOW_FILE);
+    let partial = path.with_extension("compiled.part");
+    std::fs::write(&partial, bytes)
+        .and_then(|()| std::fs::rename(&partial, &path))
+        .map_err(|err| BootError::ConfigWriteError(format!("{COMPILED_SHOW_FILE}: {err}")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common::cue::Cue;
+
+    #[test]
+    fn compiled_show() {
+        let show_path =
+            std::env::temp_dir().join(format!("clicks-compiled-{}", std::process::id()));
+        std::fs::create_dir_all(&show_path).unwrap();
+        // Not a show, so loading only works from the compiled show
+        std::fs::write(show_path.join("show.bin"), b"show").unwrap();
+        let mut show = Show::default();
+        show.cues.push(Cue::example());
+        let source = source_checksum(&show_path).unwrap();
+        write(&show_path, &source, &show).unwrap();
+
+        let loaded = load(&show_path).unwrap();
+        assert!(loaded.compiled);
+        assert_eq!(
+            postcard::to_stdvec(&loaded.show).unwrap(),
+            postcard::to_stdvec(&show).unwrap()
+        );
+
+        // Stale once show.bin changes
+        std::fs::write(show_path.join("show.bin"), b"shoe").unwrap();
+        assert!(load(&show_path).is_err());
+        // And when compiled by another version
+        std::fs::write(show_path.join("show.bin"), b"show").unwrap();
+        let compiled_by = |common_version: &str, common_source: &str| {
+            let mut bytes = postcard::to_stdvec(&Header {
+                format: FORMAT_VERSION,
+                common_version: common_version.to_string(),
+                common_source: common_source.to_string(),
+                source: source.clone(),
+            })
+            .unwrap();
+            bytes.extend(postcard::to_stdvec(&show).unwrap());
+            std::fs::write(show_path.join(COMPILED_SHOW_FILE), bytes).unwrap();
+        };
+        compiled_by("0.0.0", env!("CLICKS_COMMON_SOURCE"));
+        assert!(load(&show_path).is_err());
+        // or another commit of the same version
+        compiled_by(common::VERSION, "git+https://example.com/clicks-common#0");
+        assert!(load(&show_path).is_err());
+        // A show that does not decode to the end is not taken either
+        compiled_by(common::VERSION, env!("CLICKS_COMMON_SOURCE"));
+        let mut bytes = std::fs::read(show_path.join(COMPILED_SHOW_FILE)).unwrap();
+        bytes.push(0);
+        std::fs::write(show_path.join(COMPILED_SHOW_FILE), bytes).unwrap();
+        assert!(load(&show_path).is_err());
+        let _ = std::fs::remove_dir_all(&show_path);
+    }
+}
@@ -0,0 +1,424 @@
+use crate::{
+    audio::{
+        eq::EqConfiguration,
+        follower::TempoFollowConfiguration,
+        handler::RouteMask,
+        metronome::{ClickLevels, ClickSamples, ClickSynthesis},
+        timecode::TimecodeOutputConfiguration,
+    },
+    cluster::ClusterConfiguration,
+    communication::{jsonnet::JsonTransport, midi::MidiMapping, throttle::NotificationRates},
+    failover::BackupConfiguration,
+    hardware::display::DisplayModel,
+    macros::MacroStep,
+    metrics::ResourceLimits,
+    scene::Scene,
+    show_extension::ZeroTarget,
+};
+use common::{
+    mem::typeflags::MessageType,
+    protocol::message::{LargeMessage, Message},
+};
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, net::SocketAddr, path::PathBuf};
+
+/// Configuration of the core itself, as opposed to the `SystemConfiguration` shared with
+/// clients through the common protocol. Stored next to the system configuration.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CoreConfiguration {
+    /// Communication handlers opened at boot. Protocols left out of the list do not open a
+    /// socket at all.
+    pub handlers: Vec<HandlerConfiguration>,
+    /// Clients sent notifications without subscribing
+    pub notification_targets: Vec<NotificationTarget>,
+    /// Processing of each client output port, by index. Outputs past the end of the list are
+    /// left unprocessed.
+    pub outputs: Vec<OutputConfiguration>,
+    /// Number of capture input ports registered on the JACK client
+    pub input_count: usize,
+    /// Following the tempo of a capture input
+    pub tempo_follow: TempoFollowConfiguration,
+    /// Level, smoothing and polarity of the LTC output
+    pub timecode_output: TimecodeOutputConfiguration,
+    /// Beat phase messages per second while the transport runs, 0 for none
+    pub beat_phase_rate: u8,
+    /// Rate limits of continuously updated notifications
+    pub notification_rates: NotificationRates,
+    /// Where `TransportZero` goes in cues that do not set their own target
+    pub zero_target: ZeroTarget,
+    /// Mixer scenes by name
+    pub scenes: BTreeMap<String, Scene>,
+    /// Fade of the scenes recalled by loading a cue
+    pub scene_fade_ms: f32,
+    /// Time over which channel gain changes are ramped, to avoid zipper noise
+    pub gain_ramp_ms: f32,
+    /// Fade-out of all sources when the transport is stopped
+    pub stop_fade_ms: f32,
+    /// Levels of the click in cues that do not set their own
+    pub click_levels: ClickLevels,
+    /// Waveform, pitch, length and decay of the clicks
+    pub click_synthesis: ClickSynthesis,
+    /// WAV files of the show played instead of the synthesized clicks, in cues that do not set
+    /// their own
+    pub click_samples: ClickSamples,
+    /// Name of this unit, e.g. "Clicks-StageLeft", so clients can tell units apart where more
+    /// than one is installed
+    pub device_name: String,
+    /// Mirror another unit with muted outputs, ready to take over from it. None to run as a
+    /// primary.
+    pub backup: Option<BackupConfiguration>,
+    /// Follow a conductor unit, rendering only a share of the channels. Ignored on a backup.
+    pub cluster: Option<ClusterConfiguration>,
+    /// Sizes of the audio engine and the protocol address spaces, read at startup
+    pub channel_counts: ChannelCounts,
+    /// Display module of the front panel, read at startup
+    pub display: DisplayModel,
+    /// What the controls of MIDI devices do, see `communication::midi`
+    pub midi_mappings: Vec<MidiMapping>,
+    /// Rhai script with hooks on cue changes, beats and requests, read at startup. See
+    /// `scripting::ScriptHost`.
+    pub script: Option<PathBuf>,
+    /// Named sequences of control actions, run with `CoreRequest::RunMacro`
+    pub macros: BTreeMap<String, Vec<MacroStep>>,
+    /// Macro run by the macro button of the front panel, if any
+    pub panel_macro: Option<String>,
+    /// BCM number of the GPIO pin of the panic button, pulled up and pressed to ground, if the
+    /// panel has one. Holding NO for two seconds works on every panel.
+    pub panic_gpio: Option<u8>,
+    /// When to warn about the disks filling up or the core using too much memory
+    pub resource_limits: ResourceLimits,
+    /// Passphrase remote restarts and reboots must carry, none to refuse them
+    pub power_passphrase: Option<String>,
+    /// Put back connections the core set up when another JACK client changes them. They are
+    /// reported either way.
+    pub restore_routing: bool,
+    /// Playback clips longer than this many seconds are streamed from disk rather than read
+    /// into memory whole, read at startup. None to read every clip whole.
+    pub stream_media_s: Option<u32>,
+    /// Output routing as the core set it up, per client output the system ports connected to
+    /// it, put back when the audio client starts. Connections changed by other JACK clients are
+    /// not kept. Empty to leave the routing to JACK.
+    pub routing: Vec<RouteMask>,
+    /// Output routings by name, loaded with `CoreRequest::LoadRoutingPreset`
+    pub routing_presets: BTreeMap<String, Vec<RouteMask>>,
+    /// Show of the show directory loaded at boot, as last loaded with
+    /// `CoreRequest::LoadShowByName`. None for the default show, see `show_library`.
+    pub show: Option<String>,
+}
+
+impl Default for CoreConfiguration {
+    fn default() -> Self {
+        Self {
+            handlers: vec![
+                HandlerConfiguration::Binary { port: 8081 },
+                HandlerConfiguration::Osc { port: 8082 },
+            ],
+            notification_targets: vec![],
+            outputs: vec![],
+            input_count: 2,
+            tempo_follow: TempoFollowConfiguration::default(),
+            timecode_output: TimecodeOutputConfiguration::default(),
+            beat_phase_rate: 0,
+            notification_rates: NotificationRates::default(),
+            zero_target: ZeroTarget::default(),
+            scenes: BTreeMap::new(),
+            scene_fade_ms: 500.0,
+            gain_ramp_ms: 20.0,
+            stop_fade_ms: 30.0,
+            click_levels: ClickLevels::default(),
+            click_synthesis: ClickSynthesis::default(),
+            click_samples: ClickSamples::default(),
+            device_name: "clicks".to_string(),
+            backup: None,
+            cluster: None,
+            channel_counts: ChannelCounts::default(),
+            display: DisplayModel::default(),
+            midi_mappings: vec![],
+            script: None,
+            macros: BTreeMap::new(),
+            panel_macro: None,
+            panic_gpio: None,
+            resource_limits: ResourceLimits::default(),
+            power_passphrase: None,
+            restore_routing: true,
+            stream_media_s: Some(60),
+            routing: vec![],
+            routing_presets: BTreeMap::new(),
+            show: None,
+        }
+    }
+}
+
+impl CoreConfiguration {
+    /// Mutable configuration of an output, adding unprocessed outputs up to it if needed.
+    pub fn output_mut(&mut self, idx: usize) -> &mut OutputConfiguration {
+        if self.outputs.len() <= idx {
+            self.outputs.resize(idx + 1, OutputConfiguration::default());
+        }
+        &mut self.outputs[idx]
+    }
+
+    /// Outputs summed into no client output port that have no port of their own either, so
+    /// they are not heard.
+    pub fn unheard_outputs(&self) -> Vec<usize> {
+        (self.channel_counts.output_ports()..self.channel_counts.sources())
+            .filter(|idx| {
+                self.outputs
+                    .get(*idx)
+                    .is_none_or(|output| output.mix.is_empty())
+            })
+            .collect()
+    }
+}
+
+/// Number of channels the engine is built with. Channels are addressed by a `u8` in the
+/// protocol, so only the first 256 of each can be reached.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ChannelCounts {
+    /// Playback channels of the show
+    pub playback_channels: usize,
+    /// JACK ports an output or input can be routed to. The routing status only covers the first
+    /// `audio::handler::MAX_ROUTE_TARGETS`.
+    pub route_targets: usize,
+    /// Client output ports the outputs are mixed onto, one per source if None
+    pub output_ports: Option<usize>,
+}
+
+impl Default for ChannelCounts {
+    fn default() -> Self {
+        Self {
+            playback_channels: 30,
+            route_targets: 64,
+            output_ports: None,
+        }
+    }
+}
+
+impl ChannelCounts {
+    /// Audio sources, and so outputs: the metronome, the timecode and the playback channels.
+    pub fn sources(&self) -> usize {
+        self.playback_channels + 2
+    }
+
+    /// Client output ports registered with JACK.
+    pub fn output_ports(&self) -> usize {
+        self.output_ports.unwrap_or(self.sources())
+    }
+}
+
+/// One communication handler and its options.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "protocol", rename_all = "snake_case")]
+pub enum HandlerConfiguration {
+    Binary {
+        port: usize,
+    },
+    Osc {
+        port: usize,
+    },
+    /// Requests and notifications as JSON, over UDP or, for unreliable networks and large
+    /// messages, over TCP
+    Json {
+        port: usize,
+        #[serde(default)]
+        transport: JsonTransport,
+    },
+    /// Read-only status page for browsers, served over HTTP
+    Web {
+        port: usize,
+    },
+    /// MIDI controllers on the ALSA sequencer, connected to as they are plugged in. Any of
+    /// them can also run the transport with MIDI Machine Control.
+    Midi {
+        /// MMC device ID answered to besides all devices (127), None for all devices only
+        #[serde(default)]
+        mmc_device: Option<u8>,
+        /// Output port to send MIDI Timecode to, or a part of its name. None for no MTC
+        #[serde(default)]
+        mtc_output: Option<String>,
+    },
+}
+
+/// A client that is sent notifications without subscribing, e.g. a timecode display or a
+/// logging box that cannot send a `Subscribe` request. Sent by the first handler of its
+/// protocol.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NotificationTarget {
+    pub address: SocketAddr,
+    pub protocol: TargetProtocol,
+    /// Kinds of messages sent, all if empty
+    #[serde(default)]
+    pub messages: Vec<TargetMessage>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TargetProtocol {
+    Binary,
+    Osc,
+}
+
+/// Kinds of messages a `NotificationTarget` can be sent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TargetMessage {
+    Transport,
+    Beat,
+    Timecode,
+    /// The loaded cue
+    Cue,
+    Show,
+    Log,
+    /// clicks-core extension messages
+    Core,
+}
+
+impl NotificationTarget {
+    /// Whether the target is sent a message of the common protocol.
+    pub fn wants(&self, message: &Message) -> bool {
+        let kind = match message {
+            Message::Large(LargeMessage::Log(_)) => Some(TargetMessage::Log),
+            message => match message.to_type() {
+                MessageType::TransportData => Some(TargetMessage::Transport),
+                MessageType::BeatData => Some(TargetMessage::Beat),
+                MessageType::TimecodeData => Some(TargetMessage::Timecode),
+                MessageType::CueData | MessageType::SmallCueData => Some(TargetMessage::Cue),
+                MessageType::ShowData => Some(TargetMessage::Show),
+                _ => None,
+            },
+        };
+        self.messages.is_empty() || kind.is_some_and(|kind| self.messages.contains(&kind))
+    }
+
+    /// Whether the target is sent clicks-core extension messages.
+    pub fn wants_core(&self) -> bool {
+        self.messages.is_empty() || self.messages.contains(&TargetMessage::Core)
+    }
+}
+
+/// Longest output label, in characters.
+pub const MAX_OUTPUT_LABEL_LEN: usize = 32;
+/// Most gain of a capture input mixed into an output, in dB. Pass-through feeds the performers'
+/// ears, so it is kept within reach of unity.
+pub const MAX_PASS_THROUGH_GAIN_DB: f32 = 12.0;
+
+/// Processing applied to one output after its gain.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct OutputConfiguration {
+    /// What is patched to the output, e.g. "Keys 2 IEM", shown on every client
+    pub label: String,
+    /// Capture inputs mixed straight into the output, before the EQ
+    pub pass_through: Vec<PassThrough>,
+    /// Client output ports the output is summed into, instead of its own port, e.g. to run more
+    /// stems than the interface has outputs
+    pub mix: Vec<MixSend>,
+    pub eq: EqConfiguration,
+    /// Alignment delay, applied after the EQ
+    pub delay_ms: f32,
+    /// Silences the output, keeping its gain
+    pub muted: bool,
+    /// Inverts the polarity, applied last
+    pub invert: bool,
+}
+
+/// A capture input mixed into an output.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct PassThrough {
+    pub input: u8,
+    pub gain_db: f32,
+}
+
+/// A client output port an output is summed into.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct MixSend {
+    pub port: u8,
+    pub gain_db: f32,
+}
+
+impl OutputConfiguration {
+    /// Sets the gain of a pass-through input, or removes it if `gain_db` is `None`.
+    pub fn set_pass_through(&mut self, input: u8, gain_db: Option<f32>) {
+        self.pass_through.retain(|route| route.input != input);
+        if let Some(gain_db) = gain_db {
+            self.pass_through.push(PassThrough { input, gain_db });
+            self.pass_through.sort_by_key(|route| route.input);
+        }
+    }
+
+    /// Sets the gain the output is summed into a client output port at, or stops summing it
+    /// into that port if `gain_db` is `None`.
+    pub fn set_mix_send(&mut self, port: u8, gain_db: Option<f32>) {
+        self.mix.retain(|send| send.port != port);
+        if let Some(gain_db) = gain_db {
+            self.mix.push(MixSend { port, gain_db });
+            self.mix.sort_by_key(|send| send.port);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common::{
+        cue::Show,
+        local::config::{LogContext, LogItem, LogKind},
+    };
+
+    #[test]
+    fn pass_through() {
+        let mut output = OutputConfiguration::default();
+        output.set_pass_through(3, Some(-6.0));
+        output.set_pass_through(1, Some(0.0));
+        output.set_pass_through(3, Some(-12.0));
+        assert_eq!(
+            output.pass_through,
+            vec![
+                PassThrough {
+                    input: 1,
+                    gain_db: 0.0
+                },
+                PassThrough {
+                    input: 3,
+                    gain_db: -12.0
+                },
+            ]
+        );
+        output.set_pass_through(1, None);
+        assert_eq!(output.pass_through.len(), 1);
+    }
+
+    #[test]
+    fn unheard_outputs() {
+        let mut config = CoreConfiguration::default();
+        config.channel_counts.playback_channels = 4;
+        config.channel_counts.output_ports = Some(4);
+        config.output_mut(4).set_mix_send(0, Some(-3.0));
+        assert_eq!(config.unheard_outputs(), vec![5]);
+    }
+
+    #[test]
+    fn notification_target_messages() {
+        let target: NotificationTarget = serde_json::from_str(
+            r#"{ "address": "10.0.0.20:9000", "protocol": "osc", "messages": ["log", "core"] }"#,
+        )
+        .unwrap();
+        assert_eq!(target.protocol, TargetProtocol::Osc);
+        let log = Message::Large(LargeMessage::Log(LogItem::new(
+            "test".to_string(),
+            LogContext::Network,
+            LogKind::Note,
+        )));
+        assert!(target.wants(&log));
+        assert!(target.wants_core());
+        assert!(!target.wants(&Message::Large(LargeMessage::ShowData(Show::default()))));
+
+        let everything = NotificationTarget {
+            messages: vec![],
+            ..target
+        };
+        assert!(everything.wants(&Message::Large(LargeMessage::ShowData(Show::default()))));
+    }
+}
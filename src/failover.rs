@@ -0,0 +1,242 @@
+use crate::communication::{
+    extension::{CORE_MESSAGE_BYTE, CORE_REQUEST_BYTE, CoreMessage, CoreRequest},
+    interface::RequestSource,
+    netport::NetworkPort,
+};
+use common::protocol::request::ControlAction;
+use serde::{Deserialize, Serialize};
+use std::{
+    net::SocketAddr,
+    time::{Duration, Instant},
+};
+
+/// Interval at which a backup or cluster follower renews its `CoreRequest::Mirror` subscription.
+pub const MIRROR_INTERVAL: Duration = Duration::from_millis(500);
+/// Time after which a unit stops sending state to a mirror that has not renewed.
+pub const MIRROR_EXPIRY: Duration = Duration::from_secs(5);
+/// Interval at which a unit sends its state even if unchanged, as a heartbeat.
+pub const MIRROR_STATE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Running as the backup of another unit.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BackupConfiguration {
+    /// Address of the binary protocol handler of the primary
+    pub primary: SocketAddr,
+    /// Take over without a `CoreRequest::TakeOver` when the primary goes silent
+    pub auto_takeover: bool,
+    /// Time without state from the primary after which it is considered gone
+    pub takeover_ms: u32,
+}
+
+impl Default for BackupConfiguration {
+    fn default() -> Self {
+        Self {
+            primary: SocketAddr::from(([0, 0, 0, 0], 8081)),
+            auto_takeover: true,
+            takeover_ms: 2000,
+        }
+    }
+}
+
+/// What a unit does in a primary/backup pair.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FailoverRole {
+    /// Running the show, mirrored by any backups that ask for it
+    Primary,
+    /// Mirroring a primary with muted outputs
+    Backup { primary_alive: bool },
+    /// A backup that has taken over from its primary
+    TookOver,
+}
+
+/// Cue and transport state a backup or cluster follower mirrors.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MirrorState {
+    pub cue: u8,
+    pub beat: u16,
+    pub running: bool,
+    /// Unix time in microseconds at which `beat` was reached, for followers to measure how far
+    /// their beats are from it. Only meaningful between units with synchronized clocks.
+    pub beat_time_us: u64,
+}
+
+impl MirrorState {
+    /// Actions that take a unit from `current` to this state. A running transport is only
+    /// moved when it is more than a beat off, so network latency around a beat change does not
+    /// make the backup seek back and forth.
+    ///
+    /// A different cue is only loaded: loading stops and zeroes the transport after any command
+    /// sent with it, so the beat is caught up with on the next state.
+    pub fn actions_from(&self, current: &MirrorState) -> Vec<ControlAction> {
+        if self.cue != current.cue {
+            return vec![ControlAction::LoadCueByIndex(self.cue)];
+        }
+        let mut actions = vec![];
+        if !self.running && current.running {
+            actions.push(ControlAction::TransportStop);
+        }
+        if self.beat != current.beat && (!self.running || self.beat.abs_diff(current.beat) > 1) {
+            actions.push(ControlAction::TransportSeekBeat(self.beat));
+        }
+        if self.running && !current.running {
+            actions.push(ControlAction::TransportStart);
+        }
+        actions
+    }
+}
+
+/// Link of a backup to its primary, or of a cluster follower to its conductor: keeps the mirror
+/// subscription alive and receives the state.
+pub struct MirrorLink {
+    port: NetworkPort,
+    leader: SocketAddr,
+    last_request: Option<Instant>,
+    /// When state was last received from the leader, none until it has been heard
+    last_contact: Option<Instant>,
+}
+
+impl MirrorLink {
+    pub fn new(leader: SocketAddr) -> Self {
+        Self {
+            port: NetworkPort::new(0),
+            leader,
+            last_request: None,
+            last_contact: None,
+        }
+    }
+
+    /// Renews the subscription when due and returns the latest state received from the leader,
+    /// if any.
+    pub fn poll(&mut self, now: Instant) -> Option<MirrorState> {
+        if self
+            .last_request
+            .is_none_or(|last| now.duration_since(last) >= MIRROR_INTERVAL)
+            && let Ok(mut buffer) = postcard::to_stdvec(&CoreRequest::Mirror)
+        {
+            buffer.insert(0, CORE_REQUEST_BYTE);
+            self.port.send_to(&buffer, self.leader);
+            self.last_request = Some(now);
+        }
+
+        let mut state = None;
+        while let Some((buf, amt, src)) = self.port.recv() {
+            if src != self.leader || amt == 0 || buf[0] != CORE_MESSAGE_BYTE {
+                continue;
+            }
+            if let Ok(CoreMessage::MirrorState(received)) = postcard::from_bytes(&buf[1..amt]) {
+                state = Some(received);
+                self.last_contact = Some(now);
+            }
+        }
+        state
+    }
+
+    /// Moves the link to the current address of the network interface, after it has changed, see
+    /// `NetworkPort::rebind`. A leader heard before gets a full timeout to be heard again at the
+    /// new address.
+    pub fn rebind(&mut self, now: Instant) -> std::io::Result<Option<SocketAddr>> {
+        let moved = self.port.rebind()?;
+        if moved.is_some() {
+            self.last_request = None;
+            if self.last_contact.is_some() {
+                self.last_contact = Some(now);
+            }
+        }
+        Ok(moved)
+    }
+
+    /// Whether the leader has been heard within `timeout`.
+    pub fn leader_alive(&self, now: Instant, timeout: Duration) -> bool {
+        self.last_contact
+            .is_some_and(|last| now.duration_since(last) <= timeout)
+    }
+
+    /// Whether the leader was heard, but nothing has been heard from it for longer than
+    /// `timeout` since. A leader never heard is not lost: it may not have booted yet, and a
+    /// backup taking over from it would leave two units running the show once it has.
+    pub fn leader_lost(&self, now: Instant, timeout: Duration) -> bool {
+        self.last_contact
+            .is_some_and(|last| now.duration_since(last) > timeout)
+    }
+
+    /// Where mirrored requests come from, for the audit trail.
+    pub fn source(&self) -> RequestSource {
+        RequestSource {
+            handler: "failover",
+            handler_index: None,
+            address: self.leader,
+            identifier: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mirrored_actions() {
+        let stopped = MirrorState {
+            cue: 0,
+            beat: 0,
+            running: false,
+            beat_time_us: 0,
+        };
+        assert!(stopped.actions_from(&stopped).is_empty());
+
+        let running = MirrorState {
+            cue: 2,
+            beat: 5,
+            running: true,
+            beat_time_us: 1_000_000,
+        };
+        assert_eq!(
+            running.actions_from(&stopped),
+            vec![ControlAction::LoadCueByIndex(2)]
+        );
+        assert_eq!(
+            running.actions_from(&MirrorState { cue: 2, ..stopped }),
+            vec![
+                ControlAction::TransportSeekBeat(5),
+                ControlAction::TransportStart,
+            ]
+        );
+        // A beat behind while running, or reached at another time
+        let behind = MirrorState { beat: 4, ..running };
+        assert!(running.actions_from(&behind).is_empty());
+        let late = MirrorState {
+            beat_time_us: 1_003_000,
+            ..running
+        };
+        assert!(running.actions_from(&late).is_empty());
+        let lost = MirrorState { beat: 1, ..running };
+        assert_eq!(
+            running.actions_from(&lost),
+            vec![ControlAction::TransportSeekBeat(5)]
+        );
+        assert_eq!(
+            MirrorState { beat: 6, ..stopped }.actions_from(&MirrorState { cue: 0, ..running }),
+            vec![
+                ControlAction::TransportStop,
+                ControlAction::TransportSeekBeat(6)
+            ]
+        );
+    }
+
+    #[test]
+    fn leader_lost_once_heard() {
+        let mut link = MirrorLink::new(SocketAddr::from(([127, 0, 0, 1], 9)));
+        let timeout = Duration::from_millis(2000);
+        let now = Instant::now();
+        // A primary booting after its backup is not taken over from
+        assert!(!link.leader_alive(now + 2 * timeout, timeout));
+        assert!(!link.leader_lost(now + 2 * timeout, timeout));
+
+        link.last_contact = Some(now);
+        assert!(link.leader_alive(now + timeout, timeout));
+        assert!(!link.leader_lost(now + timeout, timeout));
+        assert!(!link.leader_alive(now + 2 * timeout, timeout));
+        assert!(link.leader_lost(now + 2 * timeout, timeout));
+    }
+}
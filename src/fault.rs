@@ -0,0 +1,56 @@
+use crate::{cbnet::CrossbeamNetwork, communication::extension::CoreMessage, hardware};
+use common::local::config::{LogContext, LogItem, LogKind};
+use std::{backtrace::Backtrace, panic::PanicHookInfo};
+
+/// Length of the fault description shown on the display, which is slow to write to.
+const DISPLAY_FAULT_LENGTH: usize = 48;
+
+/// Install a panic hook which, in addition to the default stderr output, writes the panic and a
+/// backtrace to the log, notifies subscribers with `CoreMessage::SystemFault` (if the main loop is
+/// still alive to send it), and shows a fault screen on the display.
+pub fn install_panic_hook(cbnet: CrossbeamNetwork) {
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        default_hook(info);
+
+        let thread = std::thread::current()
+            .name()
+            .unwrap_or("unnamed")
+            .to_string();
+        let message = panic_message(info);
+        let location = info
+            .location()
+            .map(|location| location.to_string())
+            .unwrap_or_default();
+
+        cbnet.log(LogItem::new(
+            format!(
+                "Panic in thread '{thread}' at {location}: {message}\n{}",
+                Backtrace::force_capture()
+            ),
+            LogContext::Boot,
+            LogKind::Error,
+        ));
+        cbnet.notify_core(CoreMessage::SystemFault {
+            thread: thread.clone(),
+            message: message.clone(),
+            location,
+        });
+        let _ = hardware::display::system_fault(
+            &format!("{thread}: {message}")
+                .chars()
+                .take(DISPLAY_FAULT_LENGTH)
+                .collect::<String>(),
+        );
+    }));
+}
+
+fn panic_message(info: &PanicHookInfo) -> String {
+    if let Some(message) = info.payload().downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = info.payload().downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "unknown panic".to_string()
+    }
+}
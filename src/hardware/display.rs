@@ -1,158 +1,413 @@
+use embedded_graphics::{
+    mono_font::{MonoTextStyle, ascii::FONT_5X8},
+    pixelcolor::BinaryColor,
+    prelude::*,
+    text::{Baseline, Text},
+};
+use serde::{Deserialize, Serialize};
+#[cfg(target_os = "linux")]
+use sh1106::{interface::I2cInterface, prelude::GraphicsMode};
+#[cfg(target_os = "linux")]
 use ssd1306::{
     Ssd1306,
-    mode::{DisplayConfig, TerminalMode},
+    mode::{BufferedGraphicsMode, DisplayConfig},
     prelude::I2CInterface,
+    size::{DisplaySize, DisplaySize128x32, DisplaySize128x64},
 };
 
-use crate::VERSION;
+use crate::{VERSION, hardware::panel};
 use common::{VERSION as COMMON_VERSION, cue::Show};
+#[cfg(target_os = "linux")]
 use linux_embedded_hal::I2cdev;
 use local_ip_address::local_ip;
-use ssd1306::size::DisplaySize128x64;
-use std::{net::IpAddr, str::FromStr, time::Duration};
+use std::{convert::Infallible, net::IpAddr, str::FromStr, sync::OnceLock, time::Duration};
+
+#[cfg(target_os = "linux")]
+const I2C_BUS: &str = "/dev/i2c-1";
+
+/// Display module of the front panel, set in core.conf.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DisplayModel {
+    /// SSD1306 controller, 128x64 pixels
+    #[default]
+    #[serde(rename = "ssd1306_128x64")]
+    Ssd1306,
+    /// SSD1306 controller, 128x32 pixels
+    #[serde(rename = "ssd1306_128x32")]
+    Ssd1306Short,
+    /// SH1106 controller, 128x64 pixels
+    #[serde(rename = "sh1106_128x64")]
+    Sh1106,
+}
+
+static MODEL: OnceLock<DisplayModel> = OnceLock::new();
+
+/// Sets the display module of the front panel. Only the first call counts, so it is made at
+/// boot before anything is shown.
+pub fn configure(model: DisplayModel) {
+    let _ = MODEL.set(model);
+}
+
+/// Width of all supported displays, in pixels.
+pub const WIDTH: u32 = 128;
+
+/// Monochrome picture of the whole screen. Everything shown on the front panel is drawn into a
+/// frame first and sent to the display in one go.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+    height: u32,
+    pixels: Vec<bool>,
+}
+
+impl Frame {
+    pub fn new(height: u32) -> Self {
+        Self {
+            height,
+            pixels: vec![false; (WIDTH * height) as usize],
+        }
+    }
+
+    pub fn is_on(&self, x: u32, y: u32) -> bool {
+        x < WIDTH && y < self.height && self.pixels[(y * WIDTH + x) as usize]
+    }
+
+    /// Swaps lit and dark pixels.
+    pub fn invert(&mut self) {
+        for pixel in &mut self.pixels {
+            *pixel = !*pixel;
+        }
+    }
+
+    /// Coordinates of the lit pixels.
+    fn lit(&self) -> impl Iterator<Item = (u32, u32)> + '_ {
+        self.pixels
+            .iter()
+            .enumerate()
+            .filter(|(_, on)| **on)
+            .map(|(idx, _)| (idx as u32 % WIDTH, idx as u32 / WIDTH))
+    }
+}
+
+impl OriginDimensions for Frame {
+    fn size(&self) -> Size {
+        Size::new(WIDTH, self.height)
+    }
+}
 
-fn get_display()
--> Result<Ssd1306<I2CInterface<I2cdev>, DisplaySize128x64, TerminalMode>, std::io::Error> {
-    let i2cdev = I2cdev::new("/dev/i2c-1")?;
+impl DrawTarget for Frame {
+    type Color = BinaryColor;
+    type Error = Infallible;
 
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Pixel<Self::Color>>,
+    {
+        for Pixel(point, color) in pixels {
+            if let (Ok(x), Ok(y)) = (u32::try_from(point.x), u32::try_from(point.y))
+                && x < WIDTH
+                && y < self.height
+            {
+                self.pixels[(y * WIDTH + x) as usize] = color.is_on();
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A front panel display in buffered graphics mode.
+pub(crate) trait PanelDisplay: Send {
+    /// Replaces what is on the screen with `frame`.
+    fn show(&mut self, frame: &Frame);
+    fn height(&self) -> u32;
+}
+
+#[cfg(target_os = "linux")]
+impl<SIZE: DisplaySize> PanelDisplay
+    for Ssd1306<I2CInterface<I2cdev>, SIZE, BufferedGraphicsMode<SIZE>>
+{
+    fn show(&mut self, frame: &Frame) {
+        self.clear_buffer();
+        for (x, y) in frame.lit() {
+            self.set_pixel(x, y, true);
+        }
+        let _ = self.flush();
+    }
+
+    fn height(&self) -> u32 {
+        SIZE::HEIGHT.into()
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl PanelDisplay for GraphicsMode<I2cInterface<I2cdev>> {
+    fn show(&mut self, frame: &Frame) {
+        self.clear();
+        for (x, y) in frame.lit() {
+            self.set_pixel(x, y, 1);
+        }
+        let _ = self.flush();
+    }
+
+    fn height(&self) -> u32 {
+        self.get_dimensions().1.into()
+    }
+}
+
+/// Opens and clears the display of the configured model.
+#[cfg(target_os = "linux")]
+pub(crate) fn open() -> Result<Box<dyn PanelDisplay>, std::io::Error> {
+    let i2cdev = I2cdev::new(I2C_BUS)?;
+    let mut display: Box<dyn PanelDisplay> = match MODEL.get().copied().unwrap_or_default() {
+        DisplayModel::Ssd1306 => Box::new(ssd1306_display(i2cdev, DisplaySize128x64)?),
+        DisplayModel::Ssd1306Short => Box::new(ssd1306_display(i2cdev, DisplaySize128x32)?),
+        DisplayModel::Sh1106 => {
+            let mut display: GraphicsMode<_> = sh1106::Builder::new().connect_i2c(i2cdev).into();
+            display
+                .init()
+                .map_err(|err| std::io::Error::other(format!("{err:?}")))?;
+            Box::new(display)
+        }
+    };
+    let blank = Frame::new(display.height());
+    display.show(&blank);
+    Ok(display)
+}
+
+/// There is no front panel off the Raspberry Pi; everything shown on it is dropped.
+#[cfg(not(target_os = "linux"))]
+pub(crate) fn open() -> Result<Box<dyn PanelDisplay>, std::io::Error> {
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "no front panel display on this platform",
+    ))
+}
+
+#[cfg(target_os = "linux")]
+fn ssd1306_display<SIZE: DisplaySize>(
+    i2cdev: I2cdev,
+    size: SIZE,
+) -> Result<Ssd1306<I2CInterface<I2cdev>, SIZE, BufferedGraphicsMode<SIZE>>, std::io::Error> {
     let interface = ssd1306::I2CDisplayInterface::new(i2cdev);
-    let mut display = ssd1306::Ssd1306::new(
-        interface,
-        DisplaySize128x64,
-        ssd1306::prelude::DisplayRotation::Rotate0,
-    )
-    .into_terminal_mode();
-    display.init().unwrap();
-    let _ = display.clear();
+    let mut display =
+        ssd1306::Ssd1306::new(interface, size, ssd1306::prelude::DisplayRotation::Rotate0)
+            .into_buffered_graphics_mode();
+    display
+        .init()
+        .map_err(|err| std::io::Error::other(format!("{err:?}")))?;
     Ok(display)
 }
 
-pub fn patch_success() -> Result<(), std::io::Error> {
-    let mut display = get_display()?;
-    ip_header(&mut display)?;
-    typewriter(&mut display, "Update succeeded");
-    typewriter(&mut display, "");
-    typewriter(&mut display, "Please reboot");
+/// Characters per line of text, as on the SSD1306 text mode the panel used to run in.
+const TEXT_COLUMNS: usize = 16;
+/// Height of a line of text, in pixels
+const TEXT_ROW_HEIGHT: u32 = 8;
 
-    Ok(())
+/// Splits a line of text into the lines it takes on the screen.
+fn wrap(line: &str) -> Vec<String> {
+    let chars: Vec<char> = line.chars().collect();
+    if chars.is_empty() {
+        return vec![String::new()];
+    }
+    chars
+        .chunks(TEXT_COLUMNS)
+        .map(|chunk| chunk.iter().collect())
+        .collect()
 }
-pub fn patch_failure() -> Result<(), std::io::Error> {
-    let mut display = get_display()?;
-    ip_header(&mut display)?;
-    typewriter(&mut display, "");
-    typewriter(&mut display, "Update failed");
-    typewriter(&mut display, "");
-    typewriter(&mut display, "Something");
-    typewriter(&mut display, "went wrong");
 
+/// Draws as many of the last `lines` as fit, top to bottom.
+pub(crate) fn draw_text(lines: &[String], frame: &mut Frame) {
+    let rows = (frame.size().height / TEXT_ROW_HEIGHT) as usize;
+    let style = MonoTextStyle::new(&FONT_5X8, BinaryColor::On);
+    let shown = &lines[lines.len().saturating_sub(rows)..];
+    for (row, line) in shown.iter().enumerate() {
+        let origin = Point::new(0, (row as u32 * TEXT_ROW_HEIGHT) as i32);
+        let _ = Text::with_baseline(line, origin, style, Baseline::Top).draw(frame);
+    }
+}
+
+/// Text written line by line, scrolling up when the screen is full.
+struct Terminal {
+    display: Box<dyn PanelDisplay>,
+    lines: Vec<String>,
+}
+
+impl Terminal {
+    fn open() -> Result<Self, std::io::Error> {
+        Ok(Self {
+            display: open()?,
+            lines: vec![],
+        })
+    }
+
+    fn print_line(&mut self, line: &str) {
+        self.lines.extend(wrap(line));
+        let mut frame = Frame::new(self.display.height());
+        draw_text(&self.lines, &mut frame);
+        self.display.show(&frame);
+    }
+}
+
+/// Shows `lines` on the front panel. While the performance view runs they interrupt it for a
+/// while, otherwise they are written out one at a time.
+fn show_lines(lines: &[&str]) -> Result<(), std::io::Error> {
+    let lines: Vec<String> = lines.iter().flat_map(|line| wrap(line)).collect();
+    if panel::show_text(&lines) {
+        return Ok(());
+    }
+    let mut terminal = Terminal::open()?;
+    for line in &lines {
+        terminal.print_line(line);
+        std::thread::sleep(LINE_DELAY);
+    }
     Ok(())
 }
+
+/// Pause after each line written, for the text to appear as typed
+const LINE_DELAY: Duration = Duration::from_millis(40);
+
+fn ip_address() -> String {
+    local_ip()
+        .unwrap_or(IpAddr::from_str("0.0.0.0").expect("const eval"))
+        .to_string()
+}
+
+pub fn patch_success() -> Result<(), std::io::Error> {
+    show_lines(&[&ip_address(), "Update succeeded", "", "Please reboot"])
+}
+pub fn patch_failure() -> Result<(), std::io::Error> {
+    show_lines(&[
+        &ip_address(),
+        "",
+        "Update failed",
+        "",
+        "Something",
+        "went wrong",
+    ])
+}
 pub fn show_load_failure(err_str: &str) -> Result<(), std::io::Error> {
-    let mut display = get_display()?;
-    ip_header(&mut display)?;
-    typewriter(&mut display, "Show load failed");
-    typewriter(&mut display, err_str);
+    show_lines(&[&ip_address(), "Show load failed", err_str])
+}
 
-    Ok(())
+pub fn checking_show() -> Result<(), std::io::Error> {
+    show_lines(&["", "Checking show", "", "Please wait"])
 }
 
-pub fn show_load_success(show: &Show) -> Result<(), std::io::Error> {
-    let mut display = get_display()?;
-    ip_header(&mut display)?;
-    typewriter(&mut display, "Loaded show");
-    typewriter(&mut display, show.metadata.name.str());
-    typewriter(&mut display, &format!("{} cues", show.cues.len()));
+pub fn show_damaged(faults: usize) -> Result<(), std::io::Error> {
+    show_lines(&[
+        &ip_address(),
+        "SHOW DAMAGED",
+        "",
+        &format!("{faults} bad files"),
+    ])
+}
 
-    Ok(())
+pub fn show_load_success(show: &Show) -> Result<(), std::io::Error> {
+    show_lines(&[
+        &ip_address(),
+        "Loaded show",
+        show.metadata.name.str(),
+        &format!("{} cues", show.cues.len()),
+    ])
 }
 
 pub fn startup() -> Result<(), std::io::Error> {
-    let mut display = get_display()?;
-    typewriter(&mut display, "Karspexet ClicKS");
-    typewriter(&mut display, "");
-    typewriter(&mut display, &format!("version {}", VERSION));
-    typewriter(&mut display, &format!(" common {}", COMMON_VERSION));
-    typewriter(&mut display, "");
-    ip_header(&mut display)?;
-    typewriter(&mut display, "port 8081");
-
-    Ok(())
+    show_lines(&[
+        "Karspexet ClicKS",
+        "",
+        &format!("version {}", VERSION),
+        &format!(" common {}", COMMON_VERSION),
+        "",
+        &ip_address(),
+        "port 8081",
+    ])
 }
 
 pub fn ask_usb() -> Result<(), std::io::Error> {
-    let mut display = get_display()?;
-    typewriter(&mut display, "");
-    typewriter(&mut display, "Load USB?");
-    typewriter(&mut display, "Plug in now");
-    typewriter(&mut display, "");
-    typewriter(&mut display, "YES/NO");
-
-    Ok(())
+    show_lines(&["", "Load USB?", "Plug in now", "", "YES/NO"])
 }
 
 pub fn ask_patch() -> Result<(), std::io::Error> {
-    let mut display = get_display()?;
-    typewriter(&mut display, "");
-    typewriter(&mut display, "Update found");
-    typewriter(&mut display, "");
-    typewriter(&mut display, "Update?");
-
-    Ok(())
+    show_lines(&["", "Update found", "", "Update?"])
 }
 
 pub fn ask_copy_show() -> Result<(), std::io::Error> {
-    let mut display = get_display()?;
-    typewriter(&mut display, "");
-    typewriter(&mut display, "USB show found");
-    typewriter(&mut display, "");
-    typewriter(&mut display, "Load to core?");
+    show_lines(&["", "USB show found", "", "Load to core?"])
+}
 
-    Ok(())
+pub fn ask_reload_show() -> Result<(), std::io::Error> {
+    show_lines(&["", "Show changed", "", "Reload?"])
 }
 
 pub fn generic_success() -> Result<(), std::io::Error> {
-    let mut display = get_display()?;
-    typewriter(&mut display, "");
-    typewriter(&mut display, "Success!");
-    typewriter(&mut display, "");
-
-    Ok(())
+    show_lines(&["", "Success!", ""])
 }
 pub fn generic_failure(err: String) -> Result<(), std::io::Error> {
-    let mut display = get_display()?;
-    typewriter(&mut display, "Op. failed");
-    typewriter(&mut display, &err);
+    show_lines(&["Op. failed", &err])
+}
 
-    Ok(())
+pub fn network_status(up: bool) -> Result<(), std::io::Error> {
+    show_lines(&[
+        &ip_address(),
+        if up { "Network up" } else { "NETWORK DOWN" },
+    ])
 }
 
-fn typewriter(
-    display: &mut Ssd1306<I2CInterface<I2cdev>, DisplaySize128x64, TerminalMode>,
-    string: &str,
-) {
-    for c in string.to_string().chars() {
-        let _ = display.print_char(c);
-        std::thread::sleep(Duration::from_millis(5));
-    }
-    let _ = display.print_char('\n');
-    std::thread::sleep(Duration::from_millis(5));
+pub fn warning(message: &str) -> Result<(), std::io::Error> {
+    show_lines(&[&ip_address(), "WARNING", "", message])
 }
 
-fn ip_header(
-    display: &mut Ssd1306<I2CInterface<I2cdev>, DisplaySize128x64, TerminalMode>,
-) -> Result<(), std::io::Error> {
-    typewriter(
-        display,
-        &local_ip()
-            .unwrap_or(IpAddr::from_str("0.0.0.0").expect("const eval"))
-            .to_string(),
-    );
+/// Shows that everything was stopped from the panic button. The screen is inverted so it
+/// cannot be taken for a message, and stays until the cue or beat changes.
+pub fn all_stop() -> Result<(), std::io::Error> {
+    let lines: Vec<String> = [
+        "",
+        "ALL STOP",
+        "",
+        "Transport stop",
+        "Playback cut",
+        "LTC muted",
+    ]
+    .iter()
+    .map(|line| line.to_string())
+    .collect();
+    if panel::show_all_stop(&lines) {
+        return Ok(());
+    }
+    let mut display = open()?;
+    let mut frame = Frame::new(display.height());
+    draw_text(&lines, &mut frame);
+    frame.invert();
+    display.show(&frame);
     Ok(())
 }
 
+pub fn system_fault(err: &str) -> Result<(), std::io::Error> {
+    show_lines(&[&ip_address(), "SYSTEM FAULT", "", err])
+}
+
 pub fn debug_print(str: String) -> Result<(), std::io::Error> {
-    let mut display = get_display()?;
-    typewriter(&mut display, &str);
+    show_lines(&[&str])
+}
 
-    Ok(())
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn text_lines() {
+        assert_eq!(wrap(""), vec![String::new()]);
+        assert_eq!(
+            wrap("Show load failed: no such file"),
+            vec!["Show load failed".to_string(), ": no such file".to_string()]
+        );
+
+        // Only the last lines fit, the first one has scrolled off
+        let lines: Vec<String> = (0..5).map(|idx| format!("line {idx}")).collect();
+        let mut frame = Frame::new(32);
+        draw_text(&lines, &mut frame);
+        assert!((0..WIDTH).any(|x| frame.is_on(x, 1)));
+        let mut first = Frame::new(32);
+        draw_text(&lines[1..], &mut first);
+        assert_eq!(frame, first);
+    }
 }
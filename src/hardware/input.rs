@@ -1,10 +1,23 @@
-use rppal::i2c::I2c;
+#[cfg(not(target_os = "linux"))]
+use super::stub::{Gpio, I2c, InputPin};
+use crossbeam_channel::{Receiver, unbounded};
+#[cfg(target_os = "linux")]
+use rppal::{
+    gpio::{Gpio, InputPin},
+    i2c::I2c,
+};
+use std::time::{Duration, Instant};
+
+/// How often the front panel buttons and keypad are read
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
 
 bitflags::bitflags! {
     #[derive(Default)]
 pub struct HwButton: u8 {
     const YES = 0x01;
     const NO = 0x02;
+    /// Runs the macro set as `CoreConfiguration::panel_macro`
+    const MACRO = 0x04;
 }}
 
 fn get_buttons() -> Result<HwButton, Box<dyn std::error::Error>> {
@@ -17,11 +30,241 @@ fn get_buttons() -> Result<HwButton, Box<dyn std::error::Error>> {
 
 pub fn wait_yes_no() -> bool {
     loop {
-        let buttons = get_buttons().unwrap_or_default();
-        if buttons.contains(HwButton::NO) {
-            return false;
-        } else if buttons.contains(HwButton::YES) {
-            return true;
+        if let Some(answer) = poll_yes_no() {
+            return answer;
         }
     }
 }
+
+/// Reads the buttons once, returning None if neither is pressed.
+fn poll_yes_no() -> Option<bool> {
+    let buttons = get_buttons().unwrap_or_default();
+    if buttons.contains(HwButton::NO) {
+        Some(false)
+    } else if buttons.contains(HwButton::YES) {
+        Some(true)
+    } else {
+        None
+    }
+}
+
+/// How long NO is held down to stop everything, long enough not to be mistaken for an answer
+pub const PANIC_HOLD: Duration = Duration::from_secs(2);
+
+/// Tells when the panic button is pressed, or NO has been held down for `PANIC_HOLD`. Either
+/// stops everything once per press.
+#[derive(Debug, Default)]
+pub struct PanicDetector {
+    /// When NO was first seen held down, None if it is up
+    no_down_since: Option<Instant>,
+    /// Whether the panic was already raised for the press going on
+    raised: bool,
+}
+
+impl PanicDetector {
+    /// Takes a read of the panic button and NO, returning whether to stop everything.
+    pub fn update(&mut self, panic_down: bool, no_down: bool, now: Instant) -> bool {
+        let no_held =
+            no_down && now.duration_since(*self.no_down_since.get_or_insert(now)) >= PANIC_HOLD;
+        if !no_down {
+            self.no_down_since = None;
+        }
+        let pressed = panic_down || no_held;
+        let raise = pressed && !self.raised;
+        self.raised = pressed;
+        raise
+    }
+}
+
+/// The panic button, on a GPIO pin of its own so it works whatever happens on the I2C bus, and
+/// NO held down as a stand-in on panels without one. The default watches NO only.
+#[derive(Default)]
+pub struct PanicInput {
+    pin: Option<InputPin>,
+    detector: PanicDetector,
+}
+
+impl PanicInput {
+    /// Watches the panic button on BCM pin `gpio`, pulled up and pressed to ground, if given.
+    pub fn new(gpio: Option<u8>) -> Result<Self, Box<dyn std::error::Error>> {
+        let pin = match gpio {
+            Some(gpio) => Some(Gpio::new()?.get(gpio)?.into_input_pullup()),
+            None => None,
+        };
+        Ok(Self {
+            pin,
+            detector: PanicDetector::default(),
+        })
+    }
+
+    /// Reads the panic button once, and takes `buttons` as read along with it, returning
+    /// whether to stop everything.
+    fn poll(&mut self, buttons: HwButton) -> bool {
+        let panic_down = self.pin.as_ref().is_some_and(|pin| pin.is_low());
+        self.detector
+            .update(panic_down, buttons.contains(HwButton::NO), Instant::now())
+    }
+}
+
+/// Something done on the front panel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PanelEvent {
+    /// YES or NO pressed, true for YES
+    Answer(bool),
+    /// The macro button pressed
+    Macro,
+    /// The panic button pressed, or NO held down, see `PanicInput`
+    Panic,
+    Key(KeypadKey),
+}
+
+/// Reads the buttons, the panic button and the keypad every `POLL_INTERVAL` on a thread of
+/// their own, so a slow I2C bus does not hold up the main loop. Every press is sent once. The
+/// thread ends when the receiver is dropped.
+pub fn start(mut panic: PanicInput) -> Result<Receiver<PanelEvent>, std::io::Error> {
+    let (tx, rx) = unbounded();
+    std::thread::Builder::new()
+        .name("clicks-panel-input".to_string())
+        .spawn(move || {
+            let mut held = HwButton::empty();
+            loop {
+                let buttons = get_buttons().unwrap_or_default();
+                let pressed = buttons.difference(held);
+                held = buttons;
+                let mut events = vec![];
+                if panic.poll(buttons) {
+                    events.push(PanelEvent::Panic);
+                }
+                if pressed.contains(HwButton::NO) {
+                    events.push(PanelEvent::Answer(false));
+                } else if pressed.contains(HwButton::YES) {
+                    events.push(PanelEvent::Answer(true));
+                }
+                if pressed.contains(HwButton::MACRO) {
+                    events.push(PanelEvent::Macro);
+                }
+                events.extend(poll_keypad().map(PanelEvent::Key));
+                for event in events {
+                    if tx.send(event).is_err() {
+                        return;
+                    }
+                }
+                std::thread::sleep(POLL_INTERVAL);
+            }
+        })?;
+    Ok(rx)
+}
+
+/// Address of the numeric keypad, the default of the SparkFun Qwiic Keypad
+const KEYPAD_ADDRESS: u16 = 0x4B;
+/// Keypad register holding the oldest queued key press, 0 if there is none
+const KEYPAD_FIFO: u8 = 0x03;
+/// Keypad register that moves the next queued key press into `KEYPAD_FIFO` when written
+const KEYPAD_UPDATE_FIFO: u8 = 0x06;
+
+/// Longest cue number that can be typed
+pub const MAX_CUE_ENTRY_LEN: usize = 4;
+
+/// A key of the numeric keypad.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeypadKey {
+    Digit(char),
+    /// The `*` key, clearing what has been typed
+    Clear,
+    /// The `#` key, loading the typed cue
+    Go,
+}
+
+impl KeypadKey {
+    fn from_code(code: u8) -> Option<Self> {
+        match code {
+            b'0'..=b'9' => Some(Self::Digit(code as char)),
+            b'*' => Some(Self::Clear),
+            b'#' => Some(Self::Go),
+            _ => None,
+        }
+    }
+}
+
+fn read_keypad() -> Result<u8, Box<dyn std::error::Error>> {
+    let mut i2c = I2c::new()?;
+    i2c.set_slave_address(KEYPAD_ADDRESS)?;
+    i2c.smbus_write_byte(KEYPAD_UPDATE_FIFO, 0x01)?;
+    Ok(i2c.smbus_read_byte(KEYPAD_FIFO)?)
+}
+
+/// Takes the oldest key press queued on the keypad, returning None if there is none or no
+/// keypad is connected.
+fn poll_keypad() -> Option<KeypadKey> {
+    KeypadKey::from_code(read_keypad().ok()?)
+}
+
+/// A cue number being typed on the keypad.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CueEntry {
+    typed: String,
+}
+
+impl CueEntry {
+    /// What has been typed so far.
+    pub fn typed(&self) -> &str {
+        &self.typed
+    }
+
+    /// Takes a key press, returning the typed cue number when GO is pressed. Digits past
+    /// `MAX_CUE_ENTRY_LEN` are ignored, and GO without a number does nothing.
+    pub fn press(&mut self, key: KeypadKey) -> Option<String> {
+        match key {
+            KeypadKey::Digit(digit) => {
+                if self.typed.len() < MAX_CUE_ENTRY_LEN {
+                    self.typed.push(digit);
+                }
+                None
+            }
+            KeypadKey::Clear => {
+                self.typed.clear();
+                None
+            }
+            KeypadKey::Go => {
+                Some(std::mem::take(&mut self.typed)).filter(|typed| !typed.is_empty())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cue_entry() {
+        let mut entry = CueEntry::default();
+        assert_eq!(entry.press(KeypadKey::Go), None);
+        for code in b"12*4512" {
+            assert_eq!(entry.press(KeypadKey::from_code(*code).unwrap()), None);
+        }
+        assert_eq!(entry.typed(), "4512");
+        assert_eq!(entry.press(KeypadKey::Digit('3')), None);
+        assert_eq!(entry.press(KeypadKey::Go), Some("4512".to_string()));
+        assert_eq!(entry.typed(), "");
+        assert_eq!(KeypadKey::from_code(0), None);
+    }
+
+    #[test]
+    fn panic_once_per_press() {
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+        let mut detector = PanicDetector::default();
+        assert!(detector.update(true, false, at(0)));
+        assert!(!detector.update(true, false, at(50)));
+        assert!(!detector.update(false, false, at(100)));
+
+        // NO only stops everything once held for two seconds, and not again until let go
+        assert!(!detector.update(false, true, at(200)));
+        assert!(!detector.update(false, true, at(2100)));
+        assert!(detector.update(false, true, at(2200)));
+        assert!(!detector.update(false, true, at(5000)));
+        assert!(!detector.update(false, false, at(5050)));
+        assert!(!detector.update(false, true, at(5100)));
+    }
+}
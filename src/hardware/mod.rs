@@ -1,3 +1,6 @@
 pub mod display;
 pub mod input;
+pub mod panel;
+#[cfg(not(target_os = "linux"))]
+mod stub;
 pub mod usb;
@@ -0,0 +1,287 @@
+use crate::{
+    hardware::display::{self, Frame, PanelDisplay, WIDTH},
+    show_extension::CueFlag,
+};
+use crossbeam_channel::{Receiver, RecvTimeoutError, Sender, unbounded};
+use embedded_graphics::{
+    mono_font::{
+        MonoTextStyle,
+        ascii::{FONT_6X10, FONT_10X20},
+    },
+    pixelcolor::BinaryColor,
+    prelude::*,
+    primitives::{PrimitiveStyle, Rectangle},
+    text::{Alignment, Baseline, Text, TextStyleBuilder},
+};
+use std::{
+    sync::OnceLock,
+    time::{Duration, Instant},
+};
+
+/// Longest wait for an update before the view is redrawn, which also bounds how long a beat
+/// flash can overstay.
+const FRAME_INTERVAL: Duration = Duration::from_millis(40);
+/// Time the screen stays inverted after a beat
+const FLASH_DURATION: Duration = Duration::from_millis(100);
+/// Time a message interrupts the performance view for
+const TEXT_HOLD: Duration = Duration::from_secs(5);
+/// Height of the cue progress bar, in pixels
+const PROGRESS_HEIGHT: u32 = 6;
+
+/// Where the show is, as shown on the performance view.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PanelState {
+    pub cue_name: String,
+    /// Bar and beat within the bar, e.g. "12.3"
+    pub counter: String,
+    /// Beat of the cue the transport is at, and the number of beats in the cue
+    pub beat: u16,
+    pub beats: u16,
+    /// Cue number being typed on the keypad, empty if none
+    pub entry: String,
+    /// Flags of the cue
+    pub flags: Vec<CueFlag>,
+}
+
+enum PanelUpdate {
+    State(PanelState),
+    Beat,
+    Text(Vec<String>),
+    AllStop(Vec<String>),
+}
+
+static PANEL: OnceLock<Sender<PanelUpdate>> = OnceLock::new();
+
+/// Starts the performance view on a thread of its own, so a slow I2C bus does not hold up the
+/// main loop. Until it is started, the front panel only shows messages.
+pub fn start() -> Result<(), std::io::Error> {
+    let display = display::open()?;
+    let (tx, rx) = unbounded();
+    std::thread::Builder::new()
+        .name("clicks-panel".to_string())
+        .spawn(move || run(display, rx))?;
+    let _ = PANEL.set(tx);
+    Ok(())
+}
+
+/// Shows where the show is.
+pub fn update(state: PanelState) {
+    send(PanelUpdate::State(state));
+}
+
+/// Flashes the screen for a beat of the running transport.
+pub fn beat() {
+    send(PanelUpdate::Beat);
+}
+
+/// Shows the all-stop screen, inverted, in place of the performance view until the cue or beat
+/// changes. False if the view is not running.
+pub(crate) fn show_all_stop(lines: &[String]) -> bool {
+    PANEL
+        .get()
+        .is_some_and(|tx| tx.send(PanelUpdate::AllStop(lines.to_vec())).is_ok())
+}
+
+/// Shows a message in place of the performance view for a while. False if the view is not
+/// running, in which case the message should be written to the display directly.
+pub(crate) fn show_text(lines: &[String]) -> bool {
+    PANEL
+        .get()
+        .is_some_and(|tx| tx.send(PanelUpdate::Text(lines.to_vec())).is_ok())
+}
+
+fn send(update: PanelUpdate) {
+    if let Some(tx) = PANEL.get() {
+        let _ = tx.send(update);
+    }
+}
+
+fn run(mut display: Box<dyn PanelDisplay>, updates: Receiver<PanelUpdate>) {
+    let mut state = PanelState::default();
+    let mut flash_until: Option<Instant> = None;
+    let mut text: Option<(Vec<String>, Instant)> = None;
+    let mut all_stop: Option<Vec<String>> = None;
+    let mut shown: Option<Frame> = None;
+    loop {
+        let mut update = match updates.recv_timeout(FRAME_INTERVAL) {
+            Ok(update) => Some(update),
+            Err(RecvTimeoutError::Timeout) => None,
+            Err(RecvTimeoutError::Disconnected) => return,
+        };
+        let now = Instant::now();
+        // Everything queued while the last frame was sent is taken in before drawing the next
+        while let Some(received) = update {
+            match received {
+                PanelUpdate::State(received) => {
+                    // The show has moved on since everything was stopped
+                    if received != state {
+                        all_stop = None;
+                    }
+                    state = received;
+                }
+                PanelUpdate::Beat => flash_until = Some(now + FLASH_DURATION),
+                PanelUpdate::Text(lines) => text = Some((lines, now + TEXT_HOLD)),
+                PanelUpdate::AllStop(lines) => all_stop = Some(lines),
+            }
+            update = updates.try_recv().ok();
+        }
+
+        let mut frame = Frame::new(display.height());
+        match (&text, &all_stop) {
+            (Some((lines, until)), _) if now < *until => display::draw_text(lines, &mut frame),
+            (_, Some(lines)) => {
+                display::draw_text(lines, &mut frame);
+                frame.invert();
+            }
+            _ => render(
+                &state,
+                flash_until.is_some_and(|until| now < until),
+                &mut frame,
+            ),
+        }
+        if shown.as_ref() != Some(&frame) {
+            display.show(&frame);
+            shown = Some(frame);
+        }
+    }
+}
+
+/// Draws the performance view: the cue and its beats at the top, a large bar and beat counter,
+/// and the progress through the cue at the bottom. The whole screen is inverted during a beat
+/// flash.
+///
+/// On a 32 pixel high display the cue name is left out to make room for the counter. A cue
+/// number typed on the keypad is shown in place of the name, or of the beats on a 32 pixel
+/// high display.
+///
+/// Flagged cues have their flags in the top right corner on a 64 pixel high display, and
+/// high-risk cues are framed by a border on any display.
+pub fn render(state: &PanelState, flash: bool, frame: &mut Frame) {
+    let height = frame.size().height;
+    let small = MonoTextStyle::new(&FONT_6X10, BinaryColor::On);
+    let large = MonoTextStyle::new(&FONT_10X20, BinaryColor::On);
+    let right = TextStyleBuilder::new()
+        .alignment(Alignment::Right)
+        .baseline(Baseline::Top)
+        .build();
+    let beats = format!("{}/{}", state.beat, state.beats);
+
+    if height >= 64 {
+        let _ =
+            Text::with_baseline(&state.cue_name, Point::zero(), small, Baseline::Top).draw(frame);
+        let centered = TextStyleBuilder::new()
+            .alignment(Alignment::Center)
+            .baseline(Baseline::Middle)
+            .build();
+        let _ = Text::with_text_style(
+            &state.counter,
+            Point::new(WIDTH as i32 / 2, 30),
+            large,
+            centered,
+        )
+        .draw(frame);
+        let _ =
+            Text::with_text_style(beats, Point::new(WIDTH as i32, 42), small, right).draw(frame);
+        let flags: Vec<&str> = state.flags.iter().map(|flag| flag_label(*flag)).collect();
+        let _ = Text::with_text_style(
+            &flags.join(" "),
+            Point::new(WIDTH as i32 - 2, 2),
+            small,
+            right,
+        )
+        .draw(frame);
+    } else {
+        let _ =
+            Text::with_baseline(&state.counter, Point::zero(), large, Baseline::Top).draw(frame);
+        let _ = Text::with_text_style(beats, Point::new(WIDTH as i32, 0), small, right).draw(frame);
+    }
+
+    // Progress through the cue, filled from the left
+    let top = height.saturating_sub(PROGRESS_HEIGHT) as i32;
+    let _ = Rectangle::new(Point::new(0, top), Size::new(WIDTH, PROGRESS_HEIGHT))
+        .into_styled(PrimitiveStyle::with_stroke(BinaryColor::On, 1))
+        .draw(frame);
+    if state.beats > 0 {
+        let filled = WIDTH * u32::from(state.beat.min(state.beats)) / u32::from(state.beats);
+        let _ = Rectangle::new(Point::new(0, top), Size::new(filled, PROGRESS_HEIGHT))
+            .into_styled(PrimitiveStyle::with_fill(BinaryColor::On))
+            .draw(frame);
+    }
+
+    if state.flags.iter().any(CueFlag::is_high_risk) {
+        let _ = Rectangle::new(Point::zero(), Size::new(WIDTH, height))
+            .into_styled(PrimitiveStyle::with_stroke(BinaryColor::On, 1))
+            .draw(frame);
+    }
+
+    if flash {
+        frame.invert();
+    }
+}
+
+/// Short name of a flag, as it fits the corner of the screen.
+fn flag_label(flag: CueFlag) -> &'static str {
+    match flag {
+        CueFlag::Warning => "WARN",
+        CueFlag::Safety => "SAFETY",
+        CueFlag::Optional => "OPT",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn state(beat: u16) -> PanelState {
+        PanelState {
+            cue_name: "Ouverture".to_string(),
+            counter: "3.2".to_string(),
+            beat,
+            beats: 64,
+            entry: String::new(),
+            flags: vec![],
+        }
+    }
+
+    #[test]
+    fn cue_progress() {
+        for height in [32, 64] {
+            let mut frame = Frame::new(height);
+            render(&state(16), false, &mut frame);
+            let row = height - PROGRESS_HEIGHT / 2;
+            assert!(frame.is_on(WIDTH / 4 - 2, row));
+            assert!(!frame.is_on(WIDTH / 4 + 2, row));
+            // The outline is drawn all the way
+            assert!(frame.is_on(WIDTH - 1, row));
+        }
+    }
+
+    #[test]
+    fn high_risk_border() {
+        let mut frame = Frame::new(64);
+        render(&state(16), false, &mut frame);
+        assert!(!frame.is_on(0, 20));
+
+        let mut optional = state(16);
+        optional.flags = vec![CueFlag::Optional];
+        render(&optional, false, &mut frame);
+        assert!(!frame.is_on(0, 20));
+
+        let mut safety = state(16);
+        safety.flags = vec![CueFlag::Optional, CueFlag::Safety];
+        let mut frame = Frame::new(32);
+        render(&safety, false, &mut frame);
+        assert!(frame.is_on(0, 20));
+        assert!(frame.is_on(WIDTH - 1, 20));
+    }
+
+    #[test]
+    fn beat_flash() {
+        let mut frame = Frame::new(64);
+        render(&state(16), false, &mut frame);
+        let mut flashed = Frame::new(64);
+        render(&state(16), true, &mut flashed);
+        flashed.invert();
+        assert_eq!(frame, flashed);
+    }
+}
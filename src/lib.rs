@@ -0,0 +1,66 @@
+//! The clicks-core engine: audio processing, show playback and the network protocol handlers
+//! of the clicks system.
+//!
+//! The `clicks-core` binary only parses the command line and hands over to [`runtime::run`].
+//! Tools that want to embed the engine can instead build the parts they need directly, e.g. an
+//! [`audio::handler::AudioHandler`] with their own [`audio::source::SourceConfig`]s, or a
+//! [`communication::registry::HandlerRegistry`] fed by their own frontend.
+#![warn(clippy::all)]
+
+/// Audio engine: JACK client, realtime processor and the audio sources it mixes.
+pub mod audio;
+/// Audit trail of requests received from clients.
+pub mod audit;
+/// Boot sequence helpers and on-disk configuration.
+pub mod boot;
+/// Channels connecting the realtime audio thread with the rest of the core.
+pub mod cbnet;
+/// Command line arguments.
+pub mod cli;
+/// Units rendering shares of the channels of one show in step with a conductor.
+pub mod cluster;
+/// Network protocol handlers and the core-local protocol extension.
+pub mod communication;
+/// Shows compiled for fast loading.
+pub mod compiled_show;
+/// Configuration of the core itself.
+pub mod config;
+/// Primary/backup pairs of units.
+pub mod failover;
+/// Panic handling.
+pub mod fault;
+/// Front panel display, buttons and USB storage.
+pub mod hardware;
+/// Log dispatching to file, network and syslog.
+pub mod logger;
+/// Named sequences of control actions.
+pub mod macros;
+/// Checksums of the files of a show.
+pub mod manifest;
+/// Runtime performance metrics.
+pub mod metrics;
+/// Remote restarts and reboots.
+pub mod power;
+/// Per-performance report of cue timings.
+pub mod runlog;
+/// The main loop tying all subsystems together.
+pub mod runtime;
+/// Named snapshots of the mixer.
+pub mod scene;
+/// Venue-specific hooks in Rhai scripts.
+pub mod scripting;
+/// The loaded show in chunks, for editing clients.
+pub mod show_dump;
+/// Core-local show data stored next to the show.
+pub mod show_extension;
+/// Shows stored side by side in the show directory.
+pub mod show_library;
+/// Noticing changes of the show on disk.
+pub mod show_watcher;
+/// Beat timing reports of a show for other departments.
+pub mod timing;
+/// Checks of incoming requests against the current state.
+pub mod validation;
+
+/// Version of clicks-core, as reported in heartbeats.
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
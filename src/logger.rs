@@ -2,79 +2,558 @@ use crate::{cbnet::CrossbeamNetwork, hardware};
 use common::{
     local::config::{LogContext, LogItem, LogKind},
     mem::time::format_hms,
+    protocol::message::{LargeMessage, Message},
+};
+use crossbeam_channel::{Receiver, Sender, bounded, select, unbounded};
+use flate2::{Compression, write::GzEncoder};
+use serde::Serialize;
+use std::{
+    collections::{BTreeMap, VecDeque},
+    fs::File,
+    io::{BufWriter, Write},
+    net::{SocketAddr, ToSocketAddrs, UdpSocket},
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::{Arc, Mutex},
+    thread::JoinHandle,
+    time::{Duration, SystemTime},
 };
-use std::{io::Write, path::PathBuf, str::FromStr};
 
-#[derive(Default)]
+/// Output format of the log file.
+#[derive(Debug, Clone, Copy, Default, PartialEq, clap::ValueEnum)]
+pub enum LogFormat {
+    /// Human readable lines, `[hh:mm:ss] Kind: message`
+    #[default]
+    Text,
+    /// One JSON record per line, for ingestion into log aggregators
+    JsonLines,
+}
+
+impl LogFormat {
+    fn file_extension(&self) -> &'static str {
+        match self {
+            LogFormat::Text => "txt",
+            LogFormat::JsonLines => "jsonl",
+        }
+    }
+}
+
+/// How long archived logs are kept, and how much space they may take up. Enforced when the
+/// core starts, after the previous log has been archived.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LogRetention {
+    /// Archived logs older than this are deleted
+    pub max_age: Duration,
+    /// Most bytes all archived logs may take up together; the oldest are deleted beyond it
+    pub max_size: u64,
+    /// Archived logs older than this are gzipped
+    pub compress_after: Duration,
+}
+
+impl Default for LogRetention {
+    fn default() -> Self {
+        Self {
+            max_age: Duration::from_secs(30 * 86400),
+            max_size: 16_000_000,
+            compress_after: Duration::from_secs(86400),
+        }
+    }
+}
+
+/// What enforcing the log retention did.
+#[derive(Debug, Default, PartialEq)]
+pub struct RetentionReport {
+    pub compressed: usize,
+    pub deleted: usize,
+    /// Bytes freed by compressing and deleting
+    pub reclaimed: u64,
+    /// Logs that could not be compressed or deleted, and why. They are left as they were.
+    pub failures: Vec<String>,
+}
+
+// Archived logs are named `log_{time}.{ext}`, and `.gz` once compressed; the current log is
+// `log.{ext}`
+fn is_archived_log(name: &str) -> bool {
+    name.starts_with("log_")
+}
+
+/// Replaces a log file with a gzipped copy, keeping its modification time so it ages on from
+/// where it was. Returns the path and size of the copy. The copy is removed again if it could not
+/// be written in full.
+fn compress_log(path: &Path, modified: SystemTime) -> Result<(PathBuf, u64), std::io::Error> {
+    let mut gz_name = path.as_os_str().to_owned();
+    gz_name.push(".gz");
+    let gz_path = PathBuf::from(gz_name);
+    let size = match write_gzip(path, &gz_path, modified) {
+        Ok(size) => size,
+        Err(err) => {
+            let _ = std::fs::remove_file(&gz_path);
+            return Err(err);
+        }
+    };
+    std::fs::remove_file(path)?;
+    Ok((gz_path, size))
+}
+
+/// Writes `path` gzipped to `gz_path`, readable with `zcat` and any log viewer. Only done at
+/// startup, so the best compression is used.
+fn write_gzip(path: &Path, gz_path: &Path, modified: SystemTime) -> Result<u64, std::io::Error> {
+    let mut encoder = GzEncoder::new(File::create(gz_path)?, Compression::best());
+    std::io::copy(&mut File::open(path)?, &mut encoder)?;
+    let file = encoder.finish()?;
+    file.set_modified(modified)?;
+    Ok(file.metadata()?.len())
+}
+
+/// Structured representation of a log item, as written in `LogFormat::JsonLines`.
+#[derive(Serialize)]
+struct LogRecord<'a> {
+    timestamp: String,
+    context: &'a LogContext,
+    kind: &'a LogKind,
+    message: &'a str,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    fields: BTreeMap<&'a str, &'a str>,
+}
+
+/// Maximum number of entries the logger thread writes to file in one go.
+const LOG_BATCH_SIZE: usize = 256;
+/// Number of recent log items kept in memory for `LogDispatcher::tail`.
+const LOG_HISTORY_SIZE: usize = 1000;
+
+/// A log item together with its structured key-value fields.
+pub struct LogEntry {
+    pub item: LogItem,
+    pub fields: Vec<(&'static str, String)>,
+}
+
+impl From<LogItem> for LogEntry {
+    fn from(item: LogItem) -> Self {
+        Self {
+            item,
+            fields: vec![],
+        }
+    }
+}
+
+enum LogCommand {
+    Entry(LogEntry),
+    Flush(Sender<()>),
+    Shutdown,
+}
+
+/// Front end of the logger. Log items are handed to a dedicated logger thread, which batches
+/// them and writes them to file, network and display, so logging never blocks the caller on IO.
+///
+/// Items logged through `CrossbeamNetwork::log` (e.g. from the RT thread) are picked up by the
+/// logger thread directly.
 pub struct LogDispatcher {
-    kind_filter: LogKind,
-    context_filter: LogContext,
-    cbnet: CrossbeamNetwork,
-    file_handler: Option<LogFileHandler>,
+    tx: Sender<LogCommand>,
+    worker: Option<JoinHandle<()>>,
+    history: Arc<Mutex<VecDeque<LogItem>>>,
 }
 
 impl LogDispatcher {
-    pub fn new(cbnet: CrossbeamNetwork) -> Self {
+    pub fn new(
+        cbnet: CrossbeamNetwork,
+        format: LogFormat,
+        retention: LogRetention,
+        syslog_collector: Option<String>,
+    ) -> Self {
         let mut log_queue = Vec::<LogItem>::new();
 
-        let file_handler = LogFileHandler::new("logs".into(), 16000000);
-        if let Err(ref err) = file_handler {
-            log_queue.push(LogItem::new(
+        let file_handler = LogFileHandler::new("logs".into(), format);
+        match &file_handler {
+            Ok(handler) => match handler.enforce_retention(&retention, SystemTime::now()) {
+                Ok(report) => {
+                    if report.compressed + report.deleted > 0 {
+                        log_queue.push(LogItem::new(
+                            format!(
+                                "Compressed {} and deleted {} old logs, reclaiming {:.1} MB.",
+                                report.compressed,
+                                report.deleted,
+                                report.reclaimed as f64 / 1e6
+                            ),
+                            LogContext::Logger,
+                            LogKind::Note,
+                        ));
+                    }
+                    for failure in report.failures {
+                        log_queue.push(LogItem::new(
+                            format!("Could not clean up old log at {failure}"),
+                            LogContext::Logger,
+                            LogKind::Warning,
+                        ));
+                    }
+                }
+                Err(err) => log_queue.push(LogItem::new(
+                    format!("Could not clean up old logs: {err}"),
+                    LogContext::Logger,
+                    LogKind::Warning,
+                )),
+            },
+            Err(err) => log_queue.push(LogItem::new(
                 format!("Error occured on LogDispatcher LogFileHandler: {err}"),
                 LogContext::Logger,
                 LogKind::Error,
-            ));
+            )),
         }
 
-        let a = Self {
+        let syslog = match syslog_collector.map(|addr| SyslogSink::new(&addr)) {
+            Some(Ok(sink)) => Some(sink),
+            Some(Err(err)) => {
+                log_queue.push(LogItem::new(
+                    format!("Could not open syslog output: {err}"),
+                    LogContext::Logger,
+                    LogKind::Error,
+                ));
+                None
+            }
+            None => None,
+        };
+
+        let history = Arc::new(Mutex::new(VecDeque::with_capacity(LOG_HISTORY_SIZE)));
+        let (tx, rx) = unbounded();
+        let worker = LogWorker {
             kind_filter: LogKind::all(),
             context_filter: LogContext::all(),
+            commands: rx,
             cbnet,
             file_handler: file_handler.ok(),
+            syslog,
+            history: Arc::clone(&history),
+            batch: Vec::with_capacity(LOG_BATCH_SIZE),
+        };
+        let worker = match std::thread::Builder::new()
+            .name("clicks-logger".to_string())
+            .spawn(move || worker.run())
+        {
+            Ok(handle) => Some(handle),
+            Err(err) => {
+                eprintln!("Could not start logger thread: {err}");
+                None
+            }
+        };
+
+        let a = Self {
+            tx,
+            worker,
+            history,
         };
 
         for log in log_queue {
             a.log(log);
         }
 
-        return a;
+        a
     }
 
-    pub fn tick(&self) -> Result<(), std::io::Error> {
-        while let Ok(item) = self.cbnet.log_rx.try_recv() {
-            self.log(item)?;
+    pub fn log(&self, item: LogItem) {
+        self.log_with_fields(item, &[])
+    }
+
+    /// A handle to log through that can be kept, e.g. by a handler logging as it runs.
+    pub fn sender(&self) -> LogSender {
+        LogSender {
+            tx: self.tx.clone(),
+        }
+    }
+
+    /// Log an item with additional key-value fields. The fields are kept as separate keys in
+    /// structured log output, and appended to the message in text output.
+    pub fn log_with_fields(&self, item: LogItem, fields: &[(&'static str, String)]) {
+        let _ = self.tx.send(LogCommand::Entry(LogEntry {
+            item,
+            fields: fields.to_vec(),
+        }));
+    }
+
+    /// The last `count` logged items matching any of the given kinds and contexts, oldest first.
+    pub fn tail(&self, count: usize, kind: LogKind, context: LogContext) -> Vec<LogItem> {
+        let history = self.history.lock().unwrap_or_else(|err| err.into_inner());
+        let mut items: Vec<LogItem> = history
+            .iter()
+            .rev()
+            .filter(|item| item.kind.intersects(kind) && item.context.intersects(context))
+            .take(count)
+            .cloned()
+            .collect();
+        items.reverse();
+        items
+    }
+
+    /// Block until everything logged so far has been written, or a timeout passes.
+    pub fn flush(&self) {
+        let (ack_tx, ack_rx) = bounded(1);
+        if self.tx.send(LogCommand::Flush(ack_tx)).is_ok() {
+            let _ = ack_rx.recv_timeout(Duration::from_secs(2));
         }
-        Ok(())
     }
 
-    pub fn log(&self, item: LogItem) -> Result<(), std::io::Error> {
+    /// Write all pending log items and stop the logger thread.
+    pub fn shutdown(&mut self) {
+        let _ = self.tx.send(LogCommand::Shutdown);
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+    }
+}
+
+/// Logs through the `LogDispatcher` it was taken from, see `LogDispatcher::sender`.
+#[derive(Debug, Clone)]
+pub struct LogSender {
+    tx: Sender<LogCommand>,
+}
+
+impl LogSender {
+    pub fn log(&self, item: LogItem) {
+        let _ = self.tx.send(LogCommand::Entry(item.into()));
+    }
+
+    /// A sender whose items are dropped, for tests.
+    #[cfg(test)]
+    pub fn discard() -> Self {
+        Self { tx: unbounded().0 }
+    }
+}
+
+impl Drop for LogDispatcher {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+struct LogWorker {
+    kind_filter: LogKind,
+    context_filter: LogContext,
+    commands: Receiver<LogCommand>,
+    cbnet: CrossbeamNetwork,
+    file_handler: Option<LogFileHandler>,
+    syslog: Option<SyslogSink>,
+    history: Arc<Mutex<VecDeque<LogItem>>>,
+    batch: Vec<LogEntry>,
+}
+
+impl LogWorker {
+    fn run(mut self) {
+        let commands = self.commands.clone();
+        let rt_logs = self.cbnet.log_rx.clone();
+        let mut running = true;
+        while running {
+            select! {
+                recv(commands) -> command => match command {
+                    Ok(command) => running = self.handle_command(command),
+                    // Dispatcher is gone without shutting down
+                    Err(_) => running = false,
+                },
+                recv(rt_logs) -> item => {
+                    if let Ok(item) = item {
+                        self.batch.push(item.into());
+                    }
+                }
+            }
+
+            // Pick up everything else that is already waiting, to write it in one go
+            while running && self.batch.len() < LOG_BATCH_SIZE {
+                if let Ok(item) = rt_logs.try_recv() {
+                    self.batch.push(item.into());
+                } else if let Ok(command) = commands.try_recv() {
+                    running = self.handle_command(command);
+                } else {
+                    break;
+                }
+            }
+            self.write_batch();
+        }
+
+        // Flush what is left on shutdown
+        while let Ok(item) = rt_logs.try_recv() {
+            self.batch.push(item.into());
+        }
+        self.write_batch();
+    }
+
+    // Returns false if the worker should stop
+    fn handle_command(&mut self, command: LogCommand) -> bool {
+        match command {
+            LogCommand::Entry(entry) => self.batch.push(entry),
+            LogCommand::Flush(ack) => {
+                self.write_batch();
+                let _ = ack.send(());
+            }
+            LogCommand::Shutdown => return false,
+        }
+        true
+    }
+
+    fn write_batch(&mut self) {
+        if self.batch.is_empty() {
+            return;
+        }
+
+        let (kind_filter, context_filter) = (self.kind_filter, self.context_filter);
+        self.batch.retain(|entry| {
+            entry.item.kind.intersects(kind_filter) && entry.item.context.intersects(context_filter)
+        });
+
         // Write to file
-        if let Some(handler) = &self.file_handler {
-            handler.log_to_file(&item)?;
+        if let Some(handler) = &self.file_handler
+            && let Err(err) = handler.write_entries(&self.batch)
+        {
+            eprintln!("Could not write log file: {err}");
         }
 
-        // Write to network
-        self.cbnet.notify(common::protocol::message::Message::Large(
-            common::protocol::message::LargeMessage::Log(item.clone()),
-        ));
+        // Write to remote syslog collector
+        if let Some(syslog) = &self.syslog {
+            for entry in &self.batch {
+                if let Err(err) = syslog.send(entry) {
+                    eprintln!("Could not send to syslog collector: {err}");
+                }
+            }
+        }
 
-        // Write (errors and warnings) to display
-        if item.kind.intersects(LogKind::Error | LogKind::Warning) {
-            hardware::display::generic_failure(item.message)?;
+        // Keep for tail requests
+        {
+            let mut history = self.history.lock().unwrap_or_else(|err| err.into_inner());
+            for entry in &self.batch {
+                if history.len() >= LOG_HISTORY_SIZE {
+                    history.pop_front();
+                }
+                history.push_back(entry.item.clone());
+            }
         }
+
+        for entry in self.batch.drain(..) {
+            // Write to network
+            self.cbnet
+                .notify(Message::Large(LargeMessage::Log(entry.item.clone())));
+
+            // Write (errors and warnings) to display
+            if entry
+                .item
+                .kind
+                .intersects(LogKind::Error | LogKind::Warning)
+            {
+                let _ = hardware::display::generic_failure(entry.item.message);
+            }
+        }
+    }
+}
+
+/// Sends log entries as RFC 5424 syslog messages over UDP to a central collector.
+pub struct SyslogSink {
+    socket: UdpSocket,
+    collector: SocketAddr,
+    hostname: String,
+    procid: u32,
+}
+
+impl SyslogSink {
+    /// Facility used for all messages, local0.
+    const FACILITY: u8 = 16;
+    /// Private enterprise number for the structured data ID. 32473 is reserved for documentation
+    /// use, which is what RFC 5424 recommends when no number has been assigned.
+    const SD_ID: &'static str = "clicks@32473";
+
+    pub fn new(collector: &str) -> Result<Self, std::io::Error> {
+        let collector = collector
+            .to_socket_addrs()?
+            .next()
+            .ok_or(std::io::ErrorKind::AddrNotAvailable)?;
+        let socket = UdpSocket::bind(if collector.is_ipv4() {
+            "0.0.0.0:0"
+        } else {
+            "[::]:0"
+        })?;
+        let hostname = std::fs::read_to_string("/proc/sys/kernel/hostname")
+            .map(|name| name.trim().to_string())
+            .ok()
+            .filter(|name| !name.is_empty())
+            .unwrap_or("-".to_string());
+
+        Ok(Self {
+            socket,
+            collector,
+            hostname,
+            procid: std::process::id(),
+        })
+    }
+
+    pub fn send(&self, entry: &LogEntry) -> Result<(), std::io::Error> {
+        self.socket
+            .send_to(self.format(entry).as_bytes(), self.collector)?;
         Ok(())
     }
+
+    fn severity(kind: LogKind) -> u8 {
+        if kind.contains(LogKind::Error) {
+            3
+        } else if kind.contains(LogKind::Warning) {
+            4
+        } else if kind.contains(LogKind::Note) {
+            5
+        } else if kind.contains(LogKind::Command) {
+            6
+        } else {
+            7
+        }
+    }
+
+    // Escape a structured data parameter value according to RFC 5424 section 6.3.3
+    fn escape_param(value: &str) -> String {
+        let mut escaped = String::with_capacity(value.len());
+        for c in value.chars() {
+            if matches!(c, '"' | '\\' | ']') {
+                escaped.push('\\');
+            }
+            escaped.push(c);
+        }
+        escaped
+    }
+
+    fn format(&self, entry: &LogEntry) -> String {
+        let item = &entry.item;
+        let mut structured_data = format!(
+            "[{} context=\"{}\"",
+            Self::SD_ID,
+            Self::escape_param(&flag_name(&item.context))
+        );
+        for (key, value) in &entry.fields {
+            structured_data.push_str(&format!(" {key}=\"{}\"", Self::escape_param(value)));
+        }
+        structured_data.push(']');
+
+        format!(
+            "<{}>1 {} {} clicks-core {} - {} {}",
+            Self::FACILITY as u16 * 8 + Self::severity(item.kind) as u16,
+            chrono::DateTime::from_timestamp_millis(item.time as i64)
+                .unwrap_or_default()
+                .to_rfc3339_opts(chrono::SecondsFormat::Millis, true),
+            self.hostname,
+            self.procid,
+            structured_data,
+            item.message.trim()
+        )
+    }
+}
+
+// Name of a log flag value as it appears in serialized form, e.g. "Boot"
+fn flag_name<T: Serialize>(flag: &T) -> String {
+    match serde_json::to_value(flag) {
+        Ok(serde_json::Value::String(name)) => name,
+        Ok(value) => value.to_string(),
+        Err(_) => "-".to_string(),
+    }
 }
 
 #[derive(Default)]
 pub struct LogFileHandler {
     log_path: PathBuf,
-    // in bytes
-    log_dir_max_size: usize,
+    format: LogFormat,
 }
 
 impl LogFileHandler {
-    fn new(path: PathBuf, max_size: usize) -> Result<Self, std::io::Error> {
+    fn new(path: PathBuf, format: LogFormat) -> Result<Self, std::io::Error> {
         let full_path = std::env::current_dir()
             .map_err(|_| std::io::ErrorKind::PermissionDenied)?
             .join(path);
@@ -84,63 +563,148 @@ impl LogFileHandler {
 
         let a = Self {
             log_path: full_path,
-            log_dir_max_size: max_size,
+            format,
         };
 
         a.archive_current_log()?;
-        a.log_dir_size_check()?;
         a.init_new_log()?;
 
         Ok(a)
     }
 
-    pub fn log_to_file(&self, item: &LogItem) -> Result<(), std::io::Error> {
-        let systime = format_hms(item.time / 1000);
-        let hms_time = systime.str();
+    fn current_log_name(&self) -> String {
+        format!("log.{}", self.format.file_extension())
+    }
 
-        let mut file = std::fs::OpenOptions::new()
+    pub fn write_entries(&self, entries: &[LogEntry]) -> Result<(), std::io::Error> {
+        let file = std::fs::OpenOptions::new()
             .append(true)
-            .open(self.log_path.join("log.txt"))?;
+            .open(self.log_path.join(self.current_log_name()))?;
+        let mut writer = BufWriter::new(file);
 
-        let log_line = format!("[{}] {}: {}\n", hms_time, item.kind, item.message.trim());
-        print!("{}", log_line);
-        let _ = file.write(log_line.as_bytes())?;
-        Ok(())
+        for entry in entries {
+            let log_line = match self.format {
+                LogFormat::Text => Self::format_text(&entry.item, &entry.fields),
+                LogFormat::JsonLines => Self::format_json(&entry.item, &entry.fields)?,
+            };
+            print!("{}", log_line);
+            writer.write_all(log_line.as_bytes())?;
+        }
+        writer.flush()
     }
 
-    pub fn log_dir_size(&self) -> Result<usize, std::io::Error> {
-        let dir_read = std::fs::read_dir(&self.log_path)?;
-        let mut size = 0;
-        for obj in dir_read {
-            size += obj?.metadata()?.len()
+    fn format_text(item: &LogItem, fields: &[(&'static str, String)]) -> String {
+        let systime = format_hms(item.time / 1000);
+        let mut log_line = format!("[{}] {}: {}", systime.str(), item.kind, item.message.trim());
+        for (key, value) in fields {
+            log_line.push_str(&format!(" {key}={value}"));
         }
-        Ok(size as usize)
+        log_line.push('\n');
+        log_line
     }
 
-    fn delete_old_logs(&self, age_limit_days: usize) -> Result<(), std::io::Error> {
-        let dir_read = std::fs::read_dir(&self.log_path)?;
-        const SECS_PER_DAY: usize = 86400;
-        for obj in dir_read {
-            let hook = obj?;
-            if hook
-                .metadata()?
-                .modified()?
-                .elapsed()
-                .map_err(|_| std::io::ErrorKind::TimedOut)?
-                .as_secs() as usize
-                > age_limit_days * SECS_PER_DAY
+    fn format_json(
+        item: &LogItem,
+        fields: &[(&'static str, String)],
+    ) -> Result<String, std::io::Error> {
+        let record = LogRecord {
+            timestamp: chrono::DateTime::from_timestamp_millis(item.time as i64)
+                .unwrap_or_default()
+                .to_rfc3339(),
+            context: &item.context,
+            kind: &item.kind,
+            message: item.message.trim(),
+            fields: fields
+                .iter()
+                .map(|(key, value)| (*key, value.as_str()))
+                .collect(),
+        };
+        let mut log_line = serde_json::to_string(&record)?;
+        log_line.push('\n');
+        Ok(log_line)
+    }
+
+    /// Gzips archived logs older than `retention.compress_after`, then deletes those older than
+    /// `retention.max_age` and, oldest first, those beyond `retention.max_size`. Only archived
+    /// logs are touched, not the current log or other files in the log directory. A log that
+    /// cannot be compressed or deleted is reported and passed over; only failing to list the
+    /// directory is an error.
+    pub fn enforce_retention(
+        &self,
+        retention: &LogRetention,
+        now: SystemTime,
+    ) -> Result<RetentionReport, std::io::Error> {
+        let mut report = RetentionReport::default();
+        let mut kept = vec![];
+        for entry in std::fs::read_dir(&self.log_path)? {
+            let Some((path, metadata)) = entry
+                .and_then(|entry| Ok((entry.path(), entry.metadata()?)))
+                .inspect_err(|err| {
+                    report
+                        .failures
+                        .push(format!("{}: {err}", self.log_path.display()))
+                })
+                .ok()
+            else {
+                continue;
+            };
+            if !metadata.is_file()
+                || !path
+                    .file_name()
+                    .is_some_and(|name| is_archived_log(&name.to_string_lossy()))
+            {
+                continue;
+            }
+            let modified = match metadata.modified() {
+                Ok(modified) => modified,
+                Err(err) => {
+                    report.failures.push(format!("{}: {err}", path.display()));
+                    continue;
+                }
+            };
+            let age = now.duration_since(modified).unwrap_or_default();
+            let size = metadata.len();
+            if age > retention.max_age {
+                match std::fs::remove_file(&path) {
+                    Ok(()) => {
+                        report.deleted += 1;
+                        report.reclaimed += size;
+                        continue;
+                    }
+                    Err(err) => report.failures.push(format!("{}: {err}", path.display())),
+                }
+            } else if age > retention.compress_after
+                && path.extension().is_none_or(|ext| ext != "gz")
             {
-                std::fs::remove_file(hook.path())?
+                match compress_log(&path, modified) {
+                    Ok((gz_path, gz_size)) => {
+                        report.compressed += 1;
+                        report.reclaimed += size.saturating_sub(gz_size);
+                        kept.push((modified, gz_path, gz_size));
+                        continue;
+                    }
+                    Err(err) => report.failures.push(format!("{}: {err}", path.display())),
+                }
             }
+            kept.push((modified, path, size));
         }
-        Ok(())
-    }
 
-    pub fn log_dir_size_check(&self) -> Result<(), std::io::Error> {
-        if self.log_dir_size()? > self.log_dir_max_size {
-            self.delete_old_logs(30)?
+        kept.sort();
+        let mut total: u64 = kept.iter().map(|(_, _, size)| size).sum();
+        for (_, path, size) in kept {
+            if total <= retention.max_size {
+                break;
+            }
+            match std::fs::remove_file(&path) {
+                Ok(()) => {
+                    report.deleted += 1;
+                    report.reclaimed += size;
+                    total -= size;
+                }
+                Err(err) => report.failures.push(format!("{}: {err}", path.display())),
+            }
         }
-        Ok(())
+        Ok(report)
     }
 
     pub fn archive_current_log(&self) -> Result<(), std::io::Error> {
@@ -155,9 +719,9 @@ impl LogFileHandler {
         }
 
         let _ = std::fs::rename(
-            self.log_path.join("log.txt"),
+            self.log_path.join(self.current_log_name()),
             self.log_path.join(
-                PathBuf::from_str(&format!("log_{time_hash}.txt"))
+                PathBuf::from_str(&format!("log_{time_hash}.{}", self.format.file_extension()))
                     .expect("(Semi-)constant path, cannot fail"),
             ),
         );
@@ -165,7 +729,73 @@ impl LogFileHandler {
     }
 
     pub fn init_new_log(&self) -> Result<(), std::io::Error> {
-        std::fs::write(self.log_path.join("log.txt"), [])?;
+        std::fs::write(self.log_path.join(self.current_log_name()), [])?;
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::read::GzDecoder;
+    use std::io::Read;
+
+    #[test]
+    fn retention() {
+        let dir = std::env::temp_dir().join(format!("clicks-log-retention-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).expect("temp dir is writable");
+        let now = SystemTime::now();
+        let day = Duration::from_secs(86400);
+        let write = |name: &str, days: u32| {
+            let file = File::create(dir.join(name)).expect("temp dir is writable");
+            (&file).write_all(&[b'x'; 1000]).unwrap();
+            file.set_modified(now - day * days).unwrap();
+        };
+        write("log.txt", 0);
+        write("log_new.txt", 0);
+        write("log_week.txt", 7);
+        write("log_month.txt", 40);
+        write("log_stuck.txt", 7);
+        write("audit.jsonl", 40);
+        // Nothing can be compressed to where a directory is in the way
+        std::fs::create_dir(dir.join("log_stuck.txt.gz")).unwrap();
+
+        let handler = LogFileHandler {
+            log_path: dir.clone(),
+            format: LogFormat::Text,
+        };
+        let mut retention = LogRetention::default();
+        let report = handler.enforce_retention(&retention, now).unwrap();
+        assert_eq!((report.compressed, report.deleted), (1, 1));
+        assert!(dir.join("log_week.txt.gz").exists());
+        assert!(!dir.join("log_month.txt").exists());
+        assert!(dir.join("audit.jsonl").exists() && dir.join("log.txt").exists());
+        // The log that could not be compressed is reported and left as it was
+        assert_eq!(report.failures.len(), 1);
+        assert!(dir.join("log_stuck.txt").exists());
+
+        let mut data = vec![];
+        GzDecoder::new(File::open(dir.join("log_week.txt.gz")).unwrap())
+            .read_to_end(&mut data)
+            .unwrap();
+        assert_eq!(data, [b'x'; 1000]);
+        assert_eq!(
+            std::fs::metadata(dir.join("log_week.txt.gz"))
+                .unwrap()
+                .modified()
+                .unwrap(),
+            now - day * 7
+        );
+
+        // Over the size budget, the oldest go first
+        std::fs::remove_dir(dir.join("log_stuck.txt.gz")).unwrap();
+        std::fs::remove_file(dir.join("log_stuck.txt")).unwrap();
+        retention.max_size = 1000;
+        let report = handler.enforce_retention(&retention, now).unwrap();
+        assert_eq!((report.compressed, report.deleted), (0, 1));
+        assert!(!dir.join("log_week.txt.gz").exists());
+        assert!(dir.join("log_new.txt").exists());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,150 @@
+use crate::communication::interface::RequestSource;
+use common::protocol::request::{ControlAction, Request};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::VecDeque,
+    net::SocketAddr,
+    time::{Duration, Instant},
+};
+
+/// Most steps a macro may have
+pub const MAX_MACRO_STEPS: usize = 64;
+/// Longest wait before a step, in milliseconds
+pub const MAX_MACRO_DELAY_MS: u32 = 600_000;
+
+/// One step of a macro: an action, requested `delay_ms` after the step before it, or after the
+/// macro was run for the first step.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MacroStep {
+    #[serde(default)]
+    pub delay_ms: u32,
+    pub action: ControlAction,
+}
+
+struct RunningMacro {
+    name: String,
+    steps: VecDeque<MacroStep>,
+    /// When the first of `steps` is due
+    next_at: Instant,
+}
+
+/// Macros that have been run and still have steps to request, e.g. "preset: house open" loading
+/// cue 0, lowering a channel and starting the transport.
+///
+/// Steps are requested from the main loop like any other request, so each is validated and
+/// written to the audit trail on its own. Several macros may run at once, and running a macro
+/// again starts it over.
+#[derive(Default)]
+pub struct MacroRunner {
+    running: Vec<RunningMacro>,
+}
+
+impl MacroRunner {
+    pub fn run(&mut self, name: &str, steps: &[MacroStep], now: Instant) {
+        self.cancel(name);
+        let Some(first) = steps.first() else {
+            return;
+        };
+        self.running.push(RunningMacro {
+            name: name.to_string(),
+            steps: steps.iter().cloned().collect(),
+            next_at: now + Duration::from_millis(first.delay_ms as u64),
+        });
+    }
+
+    /// Stops requesting the remaining steps of a macro.
+    pub fn cancel(&mut self, name: &str) {
+        self.running.retain(|running| running.name != name);
+    }
+
+    pub fn is_running(&self) -> bool {
+        !self.running.is_empty()
+    }
+
+    /// The steps due at `now`, in order, as requests.
+    pub fn due(&mut self, now: Instant) -> Vec<(Request, RequestSource)> {
+        let mut requests = vec![];
+        for running in &mut self.running {
+            while now >= running.next_at
+                && let Some(step) = running.steps.pop_front()
+            {
+                requests.push((
+                    Request::ControlAction(step.action),
+                    Self::source(&running.name),
+                ));
+                if let Some(next) = running.steps.front() {
+                    running.next_at += Duration::from_millis(next.delay_ms as u64);
+                }
+            }
+        }
+        self.running.retain(|running| !running.steps.is_empty());
+        requests
+    }
+
+    /// Where requests made by a macro come from, for validation and the audit trail.
+    pub fn source(name: &str) -> RequestSource {
+        RequestSource {
+            handler: "macro",
+            handler_index: None,
+            address: SocketAddr::from(([0, 0, 0, 0], 0)),
+            identifier: Some(name.to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn step(delay_ms: u32, action: ControlAction) -> MacroStep {
+        MacroStep { delay_ms, action }
+    }
+
+    #[test]
+    fn delayed_steps() {
+        let house_open = [
+            step(0, ControlAction::LoadCueByIndex(0)),
+            step(0, ControlAction::SetChannelGain(0, -10.0)),
+            step(500, ControlAction::TransportStart),
+        ];
+        let start = Instant::now();
+        let mut runner = MacroRunner::default();
+        runner.run("house open", &house_open, start);
+
+        let actions = |requests: Vec<(Request, RequestSource)>| -> Vec<Request> {
+            requests.into_iter().map(|(request, _)| request).collect()
+        };
+        assert_eq!(
+            actions(runner.due(start)),
+            vec![
+                Request::ControlAction(ControlAction::LoadCueByIndex(0)),
+                Request::ControlAction(ControlAction::SetChannelGain(0, -10.0)),
+            ]
+        );
+        assert!(runner.due(start + Duration::from_millis(499)).is_empty());
+        let last = runner.due(start + Duration::from_millis(500));
+        assert_eq!(last[0].1, MacroRunner::source("house open"));
+        assert_eq!(
+            actions(last),
+            vec![Request::ControlAction(ControlAction::TransportStart)]
+        );
+        assert!(!runner.is_running());
+    }
+
+    #[test]
+    fn run_again() {
+        let steps = [
+            step(100, ControlAction::TransportStop),
+            step(100, ControlAction::TransportZero),
+        ];
+        let start = Instant::now();
+        let mut runner = MacroRunner::default();
+        runner.run("reset", &steps, start);
+        assert_eq!(runner.due(start + Duration::from_millis(100)).len(), 1);
+        // Starting over requests the first step again
+        runner.run("reset", &steps, start + Duration::from_millis(150));
+        assert_eq!(runner.due(start + Duration::from_millis(250)).len(), 1);
+        assert_eq!(runner.due(start + Duration::from_millis(350)).len(), 1);
+        assert!(!runner.is_running());
+    }
+}
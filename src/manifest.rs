@@ -0,0 +1,225 @@
+use crate::boot::BootError;
+use crossbeam_channel::{Receiver, bounded};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    io::{ErrorKind, Read},
+    path::{Path, PathBuf},
+};
+
+/// File in the show directory holding the `ShowManifest`, next to `show.bin`.
+pub const MANIFEST_FILE: &str = "manifest.json";
+/// Files and directories of a show that the manifest covers. Files the core writes into the show
+/// directory itself, like `core.json`, are left out, as they change on the unit.
+const MANIFEST_CONTENTS: [&str; 2] = ["show.bin", "playback_media"];
+
+/// Checksums of the show file and all media of a show, so a half-copied stick or a damaged SD
+/// card is found when the show is loaded, rather than as a silent channel.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ShowManifest {
+    /// SHA-256 of every file as lowercase hex, by path relative to the show directory with `/`
+    /// separators
+    pub files: BTreeMap<String, String>,
+}
+
+/// A file that does not match the manifest.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum ManifestFault {
+    Missing { path: String },
+    Mismatch { path: String },
+    Unreadable { path: String, error: String },
+}
+
+impl std::fmt::Display for ManifestFault {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ManifestFault::Missing { path } => write!(f, "{path} is missing"),
+            ManifestFault::Mismatch { path } => write!(f, "{path} does not match its checksum"),
+            ManifestFault::Unreadable { path, error } => {
+                write!(f, "{path} could not be read: {error}")
+            }
+        }
+    }
+}
+
+/// Outcome of checking a show directory against its manifest.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ManifestReport {
+    /// Number of files listed in the manifest
+    pub checked: usize,
+    pub faults: Vec<ManifestFault>,
+}
+
+impl ShowManifest {
+    /// Checksums the show file and media of a show directory.
+    pub fn generate(show_path: &Path) -> Result<Self, BootError> {
+        let mut paths = vec![];
+        for entry in MANIFEST_CONTENTS {
+            collect_files(&show_path.join(entry), &mut paths)
+                .map_err(|err| BootError::FileReadError(format!("{entry}: {err}")))?;
+        }
+        let mut files = BTreeMap::new();
+        for path in paths {
+            let name = relative_name(show_path, &path);
+            let checksum = checksum(&path)
+                .map_err(|err| BootError::FileReadError(format!("{name}: {err}")))?;
+            files.insert(name, checksum);
+        }
+        Ok(Self { files })
+    }
+
+    /// Reads the manifest of a show directory, None if the show has none.
+    pub fn load(show_path: &Path) -> Result<Option<Self>, BootError> {
+        let path = show_path.join(MANIFEST_FILE);
+        if !std::fs::exists(&path).unwrap_or_default() {
+            return Ok(None);
+        }
+        let content = std::fs::read_to_string(path)
+            .map_err(|err| BootError::FileReadError(err.to_string()))?;
+        serde_json::from_str(&content)
+            .map(Some)
+            .map_err(|err| BootError::FileReadError(err.to_string()))
+    }
+
+    pub fn write(&self, show_path: &Path) -> Result<(), BootError> {
+        let content = serde_json::to_string_pretty(self)
+            .map_err(|err| BootError::ConfigWriteError(err.to_string()))?;
+        std::fs::write(show_path.join(MANIFEST_FILE), content)
+            .map_err(|err| BootError::ConfigWriteError(err.to_string()))
+    }
+
+    /// Checks every listed file of a show directory against its checksum. Files that are not
+    /// listed are not looked at.
+    pub fn verify(&self, show_path: &Path) -> ManifestReport {
+        let faults = self
+            .files
+            .iter()
+            .filter_map(|(name, expected)| {
+                let path = name.to_string();
+                match checksum(&show_path.join(name)) {
+                    Ok(actual) if actual == *expected => None,
+                    Ok(_) => Some(ManifestFault::Mismatch { path }),
+                    Err(err) if err.kind() == ErrorKind::NotFound => {
+                        Some(ManifestFault::Missing { path })
+                    }
+                    Err(err) => Some(ManifestFault::Unreadable {
+                        path,
+                        error: err.to_string(),
+                    }),
+                }
+            })
+            .collect();
+        ManifestReport {
+            checked: self.files.len(),
+            faults,
+        }
+    }
+}
+
+/// Checks a show directory against its manifest on a thread of its own, as checksumming all
+/// media takes a while. The receiver gets None if the show has no manifest.
+pub fn verify_in_background(
+    show_path: PathBuf,
+) -> Receiver<Result<Option<ManifestReport>, String>> {
+    let (tx, rx) = bounded(1);
+    let spawned = std::thread::Builder::new()
+        .name("clicks-manifest".to_string())
+        .spawn(move || {
+            let report = ShowManifest::load(&show_path)
+                .map(|manifest| manifest.map(|manifest| manifest.verify(&show_path)))
+                .map_err(|err| err.to_string());
+            let _ = tx.send(report);
+        });
+    if let Err(err) = spawned {
+        let (tx, failed) = bounded(1);
+        let _ = tx.send(Err(err.to_string()));
+        return failed;
+    }
+    rx
+}
+
+fn collect_files(path: &Path, files: &mut Vec<PathBuf>) -> std::io::Result<()> {
+    if path.is_dir() {
+        for entry in std::fs::read_dir(path)? {
+            collect_files(&entry?.path(), files)?;
+        }
+    } else if path.exists() {
+        files.push(path.to_path_buf());
+    }
+    Ok(())
+}
+
+fn relative_name(show_path: &Path, path: &Path) -> String {
+    path.strip_prefix(show_path)
+        .unwrap_or(path)
+        .components()
+        .map(|component| component.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+pub(crate) fn checksum(path: &Path) -> std::io::Result<String> {
+    let mut file = File::open(path)?;
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0u8; 1 << 16];
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+    }
+    Ok(hex::encode(hasher.finalize()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn damaged_show() {
+        let show_path =
+            std::env::temp_dir().join(format!("clicks-manifest-{}", std::process::id()));
+        let media = show_path.join("playback_media/000");
+        std::fs::create_dir_all(&media).unwrap();
+        std::fs::write(show_path.join("show.bin"), b"show").unwrap();
+        std::fs::write(media.join("000.wav"), b"click").unwrap();
+        std::fs::write(media.join("001.wav"), b"stem").unwrap();
+        std::fs::write(show_path.join("core.json"), b"{}").unwrap();
+
+        let manifest = ShowManifest::generate(&show_path).unwrap();
+        manifest.write(&show_path).unwrap();
+        assert_eq!(
+            manifest.files.keys().collect::<Vec<_>>(),
+            [
+                "playback_media/000/000.wav",
+                "playback_media/000/001.wav",
+                "show.bin"
+            ]
+        );
+        let manifest = ShowManifest::load(&show_path).unwrap().unwrap();
+        assert_eq!(manifest.verify(&show_path).faults, vec![]);
+
+        std::fs::write(media.join("000.wav"), b"clack").unwrap();
+        std::fs::remove_file(media.join("001.wav")).unwrap();
+        // Files the core writes itself are not covered
+        std::fs::write(show_path.join("core.json"), b"{\"cues\": {}}").unwrap();
+        assert_eq!(
+            manifest.verify(&show_path),
+            ManifestReport {
+                checked: 3,
+                faults: vec![
+                    ManifestFault::Mismatch {
+                        path: "playback_media/000/000.wav".to_string()
+                    },
+                    ManifestFault::Missing {
+                        path: "playback_media/000/001.wav".to_string()
+                    },
+                ],
+            }
+        );
+        let _ = std::fs::remove_dir_all(&show_path);
+    }
+}
@@ -0,0 +1,398 @@
+use crate::communication::interface::handler_name;
+use crossbeam_channel::{Receiver, RecvTimeoutError, Sender, unbounded};
+#[cfg(unix)]
+use nix::sys::statvfs::statvfs;
+use serde::{Deserialize, Deserializer, Serialize};
+use std::{
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicU32, Ordering},
+    time::Duration,
+};
+
+/// Upper bounds (exclusive, in µs) of the DSP cycle time histogram buckets. The last bucket
+/// holds everything above the last bound.
+pub const DSP_HISTOGRAM_BOUNDS_US: [u32; 8] = [50, 100, 200, 500, 1000, 2000, 5000, 10000];
+const DSP_HISTOGRAM_BUCKETS: usize = DSP_HISTOGRAM_BOUNDS_US.len() + 1;
+/// Most capture inputs that are metered.
+pub const MAX_METERED_INPUTS: usize = 64;
+
+/// Counters shared between the threads of the core. Updating them is lock-free and does not
+/// allocate, so they can be written from the RT thread. They are read and reset once per
+/// `MetricsReport`.
+#[derive(Debug, Default)]
+pub struct Metrics {
+    dsp_cycles: AtomicU32,
+    dsp_cycle_total_us: AtomicU32,
+    dsp_cycle_max_us: AtomicU32,
+    dsp_cycle_histogram: [AtomicU32; DSP_HISTOGRAM_BUCKETS],
+    main_loop_cycles: AtomicU32,
+    /// Bit patterns of the positive f32 peak levels. For non-negative floats the bit pattern
+    /// orders like the value, so `fetch_max` works on them directly.
+    input_peaks: [AtomicU32; MAX_METERED_INPUTS],
+}
+
+impl Metrics {
+    pub fn record_dsp_cycle(&self, duration_us: u32) {
+        self.dsp_cycles.fetch_add(1, Ordering::Relaxed);
+        self.dsp_cycle_total_us
+            .fetch_add(duration_us, Ordering::Relaxed);
+        self.dsp_cycle_max_us
+            .fetch_max(duration_us, Ordering::Relaxed);
+        let bucket = DSP_HISTOGRAM_BOUNDS_US
+            .iter()
+            .position(|bound| duration_us < *bound)
+            .unwrap_or(DSP_HISTOGRAM_BUCKETS - 1);
+        self.dsp_cycle_histogram[bucket].fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_input_peak(&self, input: usize, peak: f32) {
+        if let Some(input_peak) = self.input_peaks.get(input) {
+            input_peak.fetch_max(peak.abs().to_bits(), Ordering::Relaxed);
+        }
+    }
+
+    /// Read and reset the peak level of the first `count` inputs.
+    pub fn take_input_peaks(&self, count: usize) -> Vec<f32> {
+        self.input_peaks
+            .iter()
+            .take(count)
+            .map(|peak| f32::from_bits(peak.swap(0, Ordering::Relaxed)))
+            .collect()
+    }
+
+    pub fn record_main_loop_cycle(&self) {
+        self.main_loop_cycles.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Read and reset the DSP and main loop counters.
+    pub fn take_dsp_report(&self) -> (u32, DspCycleReport) {
+        let cycles = self.dsp_cycles.swap(0, Ordering::Relaxed);
+        let total_us = self.dsp_cycle_total_us.swap(0, Ordering::Relaxed);
+        let report = DspCycleReport {
+            cycles,
+            mean_us: total_us.checked_div(cycles).unwrap_or_default(),
+            max_us: self.dsp_cycle_max_us.swap(0, Ordering::Relaxed),
+            histogram: std::array::from_fn(|i| {
+                self.dsp_cycle_histogram[i].swap(0, Ordering::Relaxed)
+            }),
+        };
+        (self.main_loop_cycles.swap(0, Ordering::Relaxed), report)
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct DspCycleReport {
+    pub cycles: u32,
+    pub mean_us: u32,
+    pub max_us: u32,
+    /// Number of cycles per duration bucket, see `DSP_HISTOGRAM_BOUNDS_US`
+    pub histogram: [u32; DSP_HISTOGRAM_BUCKETS],
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct QueueDepths {
+    pub commands: usize,
+    pub notifications: usize,
+    pub log: usize,
+    pub core_notifications: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct HandlerRates {
+    pub handler: &'static str,
+    pub packets_in: u32,
+    pub packets_out: u32,
+}
+
+/// `HandlerRates` as deserialized, with a handler name of its own.
+#[derive(Deserialize)]
+#[serde(rename = "HandlerRates")]
+struct ReceivedRates {
+    handler: String,
+    packets_in: u32,
+    packets_out: u32,
+}
+
+impl<'de> Deserialize<'de> for HandlerRates {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let rates = ReceivedRates::deserialize(deserializer)?;
+        Ok(Self {
+            handler: handler_name(&rates.handler),
+            packets_in: rates.packets_in,
+            packets_out: rates.packets_out,
+        })
+    }
+}
+
+/// Performance figures aggregated over the last second.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MetricsReport {
+    pub main_loop_freq: u32,
+    pub dsp: DspCycleReport,
+    pub queue_depths: QueueDepths,
+    pub handlers: Vec<HandlerRates>,
+    /// Peak sample level of each capture input, 1.0 being full scale
+    pub input_peaks: Vec<f32>,
+    pub resources: ResourceUsage,
+}
+
+/// Space on a file system, in bytes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DiskUsage {
+    pub free_bytes: u64,
+    pub total_bytes: u64,
+}
+
+impl DiskUsage {
+    /// Space on the file system holding `path`, None if it cannot be read.
+    #[cfg(unix)]
+    pub fn of(path: &Path) -> Option<Self> {
+        let stat = statvfs(path).ok()?;
+        let fragment = stat.fragment_size() as u64;
+        Some(Self {
+            free_bytes: stat.blocks_available() as u64 * fragment,
+            total_bytes: stat.blocks() as u64 * fragment,
+        })
+    }
+
+    /// Disk space is only read on Unix.
+    #[cfg(not(unix))]
+    pub fn of(_path: &Path) -> Option<Self> {
+        None
+    }
+}
+
+/// Disk space left for the show and the logs, and memory used by the core. Each is None if it
+/// could not be read.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ResourceUsage {
+    pub show_disk: Option<DiskUsage>,
+    pub log_disk: Option<DiskUsage>,
+    /// Resident set size of the process
+    pub rss_bytes: Option<u64>,
+}
+
+impl ResourceUsage {
+    pub fn measure(show_path: &Path, log_path: &Path) -> Self {
+        Self {
+            show_disk: DiskUsage::of(show_path),
+            log_disk: DiskUsage::of(log_path),
+            rss_bytes: std::fs::read_to_string("/proc/self/status")
+                .ok()
+                .and_then(|status| rss_bytes(&status)),
+        }
+    }
+}
+
+/// How often `ResourceMonitor` measures the resources.
+pub const RESOURCE_MEASURE_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Measures `ResourceUsage` on a thread of its own, as reading the space left on a stalled
+/// network share can block for long. The thread ends when the monitor is dropped.
+pub struct ResourceMonitor {
+    show_paths: Sender<PathBuf>,
+    measured: Receiver<ResourceUsage>,
+    latest: ResourceUsage,
+}
+
+impl ResourceMonitor {
+    pub fn start(show_path: PathBuf, log_path: PathBuf) -> Self {
+        let (show_paths, paths_rx) = unbounded();
+        let (measured_tx, measured) = unbounded();
+        // Without its thread nothing is measured, and the usage stays unknown
+        let _ = std::thread::Builder::new()
+            .name("clicks-resources".to_string())
+            .spawn(move || {
+                let mut show_path = show_path;
+                loop {
+                    if measured_tx
+                        .send(ResourceUsage::measure(&show_path, &log_path))
+                        .is_err()
+                    {
+                        return;
+                    }
+                    match paths_rx.recv_timeout(RESOURCE_MEASURE_INTERVAL) {
+                        Ok(path) => show_path = path,
+                        Err(RecvTimeoutError::Timeout) => {}
+                        Err(RecvTimeoutError::Disconnected) => return,
+                    }
+                }
+            });
+        Self {
+            show_paths,
+            measured,
+            latest: ResourceUsage::default(),
+        }
+    }
+
+    /// Measures the show disk at `show_path` from now on, after another show was loaded.
+    pub fn set_show_path(&self, show_path: PathBuf) {
+        let _ = self.show_paths.send(show_path);
+    }
+
+    /// The latest measured usage, all unknown before the first measurement.
+    pub fn latest(&mut self) -> ResourceUsage {
+        if let Some(usage) = self.measured.try_iter().last() {
+            self.latest = usage;
+        }
+        self.latest
+    }
+}
+
+/// The resident set size in a `/proc/<pid>/status` file.
+fn rss_bytes(status: &str) -> Option<u64> {
+    let kilobytes = status
+        .lines()
+        .find_map(|line| line.strip_prefix("VmRSS:"))?
+        .trim()
+        .strip_suffix("kB")?
+        .trim()
+        .parse::<u64>()
+        .ok()?;
+    Some(kilobytes * 1024)
+}
+
+/// A resource running out, warned about when the limits in `ResourceLimits` are crossed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ResourceAlarm {
+    /// Little space is left for the show, e.g. for recordings
+    ShowDiskLow,
+    /// Little space is left for the logs, run logs and audit trail
+    LogDiskLow,
+    MemoryHigh,
+}
+
+impl std::fmt::Display for ResourceAlarm {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ResourceAlarm::ShowDiskLow => write!(f, "show disk space is running out"),
+            ResourceAlarm::LogDiskLow => write!(f, "log disk space is running out"),
+            ResourceAlarm::MemoryHigh => write!(f, "memory use is high"),
+        }
+    }
+}
+
+/// When to warn about running out of disk space or memory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ResourceLimits {
+    /// Least free space on the show and log file systems, in MB
+    pub min_free_disk_mb: u64,
+    /// Most memory the core may use, in MB, 0 for no limit
+    pub max_rss_mb: u64,
+}
+
+impl Default for ResourceLimits {
+    fn default() -> Self {
+        Self {
+            min_free_disk_mb: 256,
+            max_rss_mb: 512,
+        }
+    }
+}
+
+impl ResourceLimits {
+    /// The limits `usage` is past. Figures that could not be read raise no alarm.
+    pub fn alarms(&self, usage: &ResourceUsage) -> Vec<ResourceAlarm> {
+        let min_free_bytes = self.min_free_disk_mb * 1024 * 1024;
+        let disk_low =
+            |disk: Option<DiskUsage>| disk.is_some_and(|disk| disk.free_bytes < min_free_bytes);
+        let mut alarms = vec![];
+        if disk_low(usage.show_disk) {
+            alarms.push(ResourceAlarm::ShowDiskLow);
+        }
+        if disk_low(usage.log_disk) {
+            alarms.push(ResourceAlarm::LogDiskLow);
+        }
+        if self.max_rss_mb > 0
+            && usage
+                .rss_bytes
+                .is_some_and(|rss| rss > self.max_rss_mb * 1024 * 1024)
+        {
+            alarms.push(ResourceAlarm::MemoryHigh);
+        }
+        alarms
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dsp_histogram() {
+        let metrics = Metrics::default();
+        for duration_us in [10, 49, 50, 150, 20000] {
+            metrics.record_dsp_cycle(duration_us);
+        }
+        metrics.record_main_loop_cycle();
+
+        let (main_loop_cycles, report) = metrics.take_dsp_report();
+        assert_eq!(main_loop_cycles, 1);
+        assert_eq!(report.cycles, 5);
+        assert_eq!(report.max_us, 20000);
+        assert_eq!(report.mean_us, (10 + 49 + 50 + 150 + 20000) / 5);
+        assert_eq!(report.histogram, [2, 1, 1, 0, 0, 0, 0, 0, 1]);
+
+        // Counters are reset after reading
+        assert_eq!(metrics.take_dsp_report().1, DspCycleReport::default());
+    }
+
+    #[test]
+    fn input_peaks() {
+        let metrics = Metrics::default();
+        metrics.record_input_peak(0, 0.25);
+        metrics.record_input_peak(0, -0.5);
+        metrics.record_input_peak(0, 0.125);
+        metrics.record_input_peak(1, 1.0);
+        metrics.record_input_peak(MAX_METERED_INPUTS, 1.0);
+
+        assert_eq!(metrics.take_input_peaks(3), vec![0.5, 1.0, 0.0]);
+        assert_eq!(metrics.take_input_peaks(2), vec![0.0, 0.0]);
+    }
+
+    #[test]
+    fn resource_alarms() {
+        assert_eq!(
+            rss_bytes("Name:\tclicks-core\nVmRSS:\t   20480 kB\nThreads:\t9\n"),
+            Some(20 * 1024 * 1024)
+        );
+
+        const MB: u64 = 1024 * 1024;
+        let disk = |free_mb: u64| {
+            Some(DiskUsage {
+                free_bytes: free_mb * MB,
+                total_bytes: 32 * 1024 * MB,
+            })
+        };
+        let limits = ResourceLimits::default();
+        let usage = ResourceUsage {
+            show_disk: disk(4096),
+            log_disk: disk(100),
+            rss_bytes: Some(600 * MB),
+        };
+        assert_eq!(
+            limits.alarms(&usage),
+            vec![ResourceAlarm::LogDiskLow, ResourceAlarm::MemoryHigh]
+        );
+        let unlimited = ResourceLimits {
+            max_rss_mb: 0,
+            ..limits
+        };
+        assert_eq!(unlimited.alarms(&usage), vec![ResourceAlarm::LogDiskLow]);
+        assert_eq!(limits.alarms(&ResourceUsage::default()), vec![]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn measured_off_the_caller() {
+        let dir = std::env::temp_dir();
+        let mut monitor = ResourceMonitor::start(dir.clone(), dir);
+        let start = std::time::Instant::now();
+        while monitor.latest().show_disk.is_none() && start.elapsed() < Duration::from_secs(2) {
+            std::thread::sleep(Duration::from_millis(5));
+        }
+        assert!(monitor.latest().show_disk.is_some());
+        assert!(monitor.latest().log_disk.is_some());
+    }
+}
@@ -0,0 +1,203 @@
+//! Restarting the core or rebooting the unit on request, for units installed out of reach.
+//!
+//! Both take two requests: `CoreRequest::Restart` or `CoreRequest::Reboot` arms the action and
+//! is replied with a code, which the same client sends back with `CoreRequest::ConfirmPower`
+//! within `CONFIRM_TIMEOUT`. A stray or replayed request alone never takes a unit down, and
+//! other clients can neither confirm nor cancel an armed action. Both are refused unless the
+//! core configuration sets a passphrase, since the code alone only proves that the client can
+//! read its replies.
+use crate::communication::interface::RequestSource;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::hash_map::RandomState,
+    fmt,
+    hash::{BuildHasher, Hasher},
+    time::{Duration, Instant},
+};
+use subtle::ConstantTimeEq;
+
+/// How long an armed action waits for its confirmation.
+pub const CONFIRM_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PowerAction {
+    /// Start the core process over, as if the unit had booted
+    Restart,
+    /// Reboot the operating system
+    Reboot,
+}
+
+/// Passphrase sent with a restart or reboot request. Never shown in logs, since requests are
+/// logged as they are received.
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Passphrase(pub String);
+
+impl fmt::Debug for Passphrase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Passphrase(..)")
+    }
+}
+
+/// Whether a request carries the passphrase the core configuration asks for, never without
+/// one. The passphrases are compared in constant time, by digest so their length does not tell
+/// either.
+pub fn authorized(required: Option<&str>, given: Option<&Passphrase>) -> bool {
+    required.is_some_and(|required| {
+        given.is_some_and(|given| {
+            Sha256::digest(given.0.as_bytes())
+                .as_slice()
+                .ct_eq(Sha256::digest(required.as_bytes()).as_slice())
+                .into()
+        })
+    })
+}
+
+#[derive(Debug)]
+struct Armed {
+    action: PowerAction,
+    code: u32,
+    source: RequestSource,
+    at: Instant,
+}
+
+/// The action waiting for its confirmation, if any.
+#[derive(Debug, Default)]
+pub struct PowerGate {
+    armed: Option<Armed>,
+}
+
+impl PowerGate {
+    /// Arms an action for `source`, replacing any it armed before, and returns the code that
+    /// confirms it. Fails while another client has an action waiting for its confirmation.
+    pub fn arm(
+        &mut self,
+        action: PowerAction,
+        source: RequestSource,
+        now: Instant,
+    ) -> Result<u32, String> {
+        if let Some(armed) = &self.armed
+            && armed.source != source
+            && now.duration_since(armed.at) <= CONFIRM_TIMEOUT
+        {
+            return Err(format!(
+                "{:?} is waiting for the confirmation of another client",
+                armed.action
+            ));
+        }
+        // Six digits, easy to read back on a console
+        let code = (RandomState::new().build_hasher().finish() % 900_000) as u32 + 100_000;
+        self.armed = Some(Armed {
+            action,
+            code,
+            source,
+            at: now,
+        });
+        Ok(code)
+    }
+
+    /// The armed action, if `code` confirms it. The action is disarmed either way, unless the
+    /// confirmation comes from another client than the one that armed it.
+    pub fn confirm(
+        &mut self,
+        code: u32,
+        source: &RequestSource,
+        now: Instant,
+    ) -> Result<PowerAction, String> {
+        let armed = self.armed.as_ref().ok_or("nothing to confirm")?;
+        if armed.source != *source {
+            return Err("armed by another client".to_string());
+        }
+        let armed = self.armed.take().ok_or("nothing to confirm")?;
+        if now.duration_since(armed.at) > CONFIRM_TIMEOUT {
+            Err("confirmation timed out".to_string())
+        } else if armed.code != code {
+            Err("wrong confirmation code".to_string())
+        } else {
+            Ok(armed.action)
+        }
+    }
+}
+
+/// Replaces the core process with a new one, started with the same arguments. Only returns if
+/// the new process could not be started.
+#[cfg(unix)]
+pub fn restart() -> std::io::Error {
+    use std::os::unix::process::CommandExt;
+    match std::env::current_exe() {
+        Ok(exe) => std::process::Command::new(exe)
+            .args(std::env::args_os().skip(1))
+            .exec(),
+        Err(err) => err,
+    }
+}
+
+#[cfg(not(unix))]
+pub fn restart() -> std::io::Error {
+    std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "cannot restart on this platform",
+    )
+}
+
+/// Asks the init system to reboot the unit.
+pub fn reboot() -> std::io::Result<()> {
+    let status = std::process::Command::new("systemctl")
+        .arg("reboot")
+        .status()?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(std::io::Error::other(format!("systemctl reboot {status}")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn confirmation() {
+        let source = RequestSource::front_panel();
+        let other = RequestSource {
+            address: "10.0.0.2:8081".parse().unwrap(),
+            ..RequestSource::front_panel()
+        };
+        let now = Instant::now();
+        let mut gate = PowerGate::default();
+        assert!(gate.confirm(0, &source, now).is_err());
+
+        let code = gate.arm(PowerAction::Reboot, source.clone(), now).unwrap();
+        assert!((100_000..1_000_000).contains(&code));
+        assert_eq!(gate.confirm(code, &source, now), Ok(PowerAction::Reboot));
+        // Disarmed once confirmed
+        assert!(gate.confirm(code, &source, now).is_err());
+
+        // Another client can neither confirm, cancel nor replace the armed action
+        let code = gate.arm(PowerAction::Restart, source.clone(), now).unwrap();
+        assert!(gate.confirm(code, &other, now).is_err());
+        assert!(gate.arm(PowerAction::Reboot, other.clone(), now).is_err());
+        assert_eq!(gate.confirm(code, &source, now), Ok(PowerAction::Restart));
+
+        let code = gate.arm(PowerAction::Restart, source.clone(), now).unwrap();
+        assert!(gate.confirm(code + 1, &source, now).is_err());
+        assert!(gate.confirm(code, &source, now).is_err());
+        let code = gate.arm(PowerAction::Restart, source.clone(), now).unwrap();
+        let late = now + CONFIRM_TIMEOUT + Duration::from_secs(1);
+        // Once timed out, another client can arm
+        assert!(gate.arm(PowerAction::Reboot, other, late).is_ok());
+        assert!(gate.confirm(code, &source, late).is_err());
+    }
+
+    #[test]
+    fn passphrase() {
+        let given = Passphrase("ladder".to_string());
+        assert!(!authorized(None, None));
+        assert!(!authorized(None, Some(&given)));
+        assert!(authorized(Some("ladder"), Some(&given)));
+        assert!(!authorized(Some("ladder"), None));
+        assert!(!authorized(Some("stairs"), Some(&given)));
+        assert!(!format!("{given:?}").contains("ladder"));
+    }
+}
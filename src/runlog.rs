@@ -0,0 +1,201 @@
+use serde::{Deserialize, Serialize};
+
+/// Number of entries of the current performance kept in memory for queries. The file keeps
+/// them all.
+const RUN_HISTORY_SIZE: usize = 1000;
+use std::{
+    collections::VecDeque,
+    fs::File,
+    io::{BufWriter, Write},
+    path::PathBuf,
+};
+
+/// Something that happened during a performance.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum RunEvent {
+    /// A new run log was started, at boot or on request
+    PerformanceStarted {
+        show: String,
+    },
+    CueLoaded {
+        cue: u8,
+        ident: String,
+    },
+    /// A cue was loaded further ahead than the next one
+    Skipped {
+        from: u8,
+        to: u8,
+    },
+    TransportStarted {
+        cue: u8,
+    },
+    /// The transport was stopped before the end of the cue
+    Held {
+        cue: u8,
+    },
+    /// The cue in standby was started
+    Go {
+        cue: u8,
+    },
+    /// The transport ran past the end of the cue
+    CueEnded {
+        cue: u8,
+    },
+    /// A backup unit took over from its primary
+    TookOver {
+        cue: u8,
+    },
+    /// A backup unit that took over went back to mirroring its primary
+    HandedBack {
+        cue: u8,
+    },
+    /// A thread of the core panicked
+    Fault {
+        thread: String,
+        message: String,
+    },
+    /// Everything was stopped at once, from the panic button or by `CoreRequest::AllStop`
+    AllStop {
+        cue: u8,
+        /// Where the stop came from, e.g. the front panel
+        source: String,
+    },
+}
+
+/// A run event and when it happened.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RunEntry {
+    /// Unix time in ms
+    pub time: i64,
+    pub event: RunEvent,
+}
+
+impl std::fmt::Display for RunEntry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "[{}] {:?}",
+            chrono::DateTime::from_timestamp_millis(self.time)
+                .unwrap_or_default()
+                .format("%H:%M:%S%.3f"),
+            self.event
+        )
+    }
+}
+
+/// Report of a single performance: when every cue was loaded, started, held and ended. Kept in
+/// memory for queries and written to its own JSON Lines file, so stage management gets exact
+/// show timings without a stopwatch.
+pub struct RunLog {
+    dir: PathBuf,
+    /// The last `RUN_HISTORY_SIZE` entries
+    entries: VecDeque<RunEntry>,
+    file: Option<BufWriter<File>>,
+}
+
+impl RunLog {
+    /// A run log writing its performances into `dir`. Nothing is written until the first
+    /// performance is started.
+    pub fn new(dir: PathBuf) -> Self {
+        Self {
+            dir,
+            entries: VecDeque::new(),
+            file: None,
+        }
+    }
+
+    /// Closes the current performance and starts a new one in a new file, named after the
+    /// current time. The performance is started even if the file cannot be opened, and is then
+    /// only kept in memory.
+    pub fn start_performance(&mut self, show: String) -> Result<(), std::io::Error> {
+        self.entries.clear();
+        let file_name = format!("{}.jsonl", chrono::Local::now().format("%Y-%m-%d_%H-%M-%S"));
+        let file = std::env::current_dir()
+            .map(|dir| dir.join(&self.dir))
+            .and_then(|dir| {
+                std::fs::create_dir_all(&dir)?;
+                std::fs::OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(dir.join(file_name))
+            });
+        let result = match file {
+            Ok(file) => {
+                self.file = Some(BufWriter::new(file));
+                Ok(())
+            }
+            Err(err) => {
+                self.file = None;
+                Err(err)
+            }
+        };
+        self.record(RunEvent::PerformanceStarted { show })?;
+        result
+    }
+
+    /// Adds an event to the current performance. The event is kept in memory even if it cannot
+    /// be written to the file.
+    pub fn record(&mut self, event: RunEvent) -> Result<(), std::io::Error> {
+        let entry = RunEntry {
+            time: chrono::Utc::now().timestamp_millis(),
+            event,
+        };
+        let result = match &mut self.file {
+            Some(file) => serde_json::to_writer(&mut *file, &entry)
+                .map_err(std::io::Error::from)
+                .and_then(|_| file.write_all(b"\n"))
+                .and_then(|_| file.flush()),
+            None => Ok(()),
+        };
+        if self.entries.len() >= RUN_HISTORY_SIZE {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(entry);
+        result
+    }
+
+    /// The last `count` entries of the current performance kept in memory, oldest first.
+    pub fn recent(&self, count: usize) -> Vec<RunEntry> {
+        self.entries
+            .iter()
+            .skip(self.entries.len().saturating_sub(count))
+            .cloned()
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recent_entries() {
+        // Without a started performance nothing is written to disk
+        let mut run_log = RunLog::new(PathBuf::new());
+        for cue in 0..5 {
+            run_log
+                .record(RunEvent::CueLoaded {
+                    cue,
+                    ident: cue.to_string(),
+                })
+                .expect("in memory run log");
+        }
+        let recent = run_log.recent(2);
+        assert_eq!(recent.len(), 2);
+        assert_eq!(
+            recent[1].event,
+            RunEvent::CueLoaded {
+                cue: 4,
+                ident: "4".to_string()
+            }
+        );
+        assert_eq!(run_log.recent(10).len(), 5);
+
+        // Only the latest entries are kept in memory
+        for _ in 0..RUN_HISTORY_SIZE {
+            run_log.record(RunEvent::Go { cue: 1 }).unwrap();
+        }
+        assert_eq!(run_log.recent(usize::MAX).len(), RUN_HISTORY_SIZE);
+        assert_eq!(run_log.recent(1)[0].event, RunEvent::Go { cue: 1 });
+    }
+}
@@ -0,0 +1,2415 @@
+use crate::{
+    VERSION, audio,
+    audio::{
+        accent::bar_accents,
+        countdown::{UPCOMING_EVENTS, upcoming_events},
+        delay::clamp_delay_ms,
+        handler::{AudioHandler, set_route},
+        metronome::{
+            ClickLevels, ClickSamples, ClickSounds, ClickSynthesis, Metronome, click_bank,
+        },
+        playback::PlaybackHandler,
+        timecode::{DEFAULT_LTC_FRAME_RATE, TimecodeSource},
+    },
+    audit::{AuditOutcome, AuditTrail},
+    boot::{self, BootError, ConfigPersistence, PersistentConfig},
+    cbnet::CrossbeamNetwork,
+    cli::Args,
+    cluster::{ClusterStatus, beat_drift},
+    communication::{
+        extension::{
+            CoreControlAction, CoreMessage, CoreRequest, CueSummary, MAX_LOG_TAIL, PROTOCOL_VERSION,
+        },
+        interface::RequestSource,
+        link::{LinkMonitor, LinkStatus},
+        midi::MidiMapping,
+        registry::HandlerRegistry,
+        schema::ProtocolSchema,
+        throttle::NotificationThrottle,
+    },
+    compiled_show::{self, LoadedShow},
+    config::{CoreConfiguration, OutputConfiguration},
+    failover::{FailoverRole, MIRROR_STATE_INTERVAL, MirrorLink, MirrorState},
+    fault,
+    logger::LogDispatcher,
+    macros::MacroRunner,
+    manifest::{self, ManifestReport, ShowManifest},
+    metrics::{MAX_METERED_INPUTS, MetricsReport, ResourceAlarm, ResourceMonitor},
+    power::{self, Passphrase, PowerAction, PowerGate},
+    runlog::{RunEvent, RunLog},
+    scene::Scene,
+    scripting::{ScriptEffect, ScriptHost},
+    show_dump,
+    show_extension::{
+        AlternateClips, ChannelAutomation, ClipChains, CueExtension, CueMarkers, EndOfCue,
+        FadeEvents, GainAutomation, MuteEvents, ShowExtension, ZeroTarget,
+    },
+    show_library::{self, DEFAULT_SHOW},
+    show_watcher::ShowWatcher,
+    timing::{TimingReport, beat_at_timecode},
+    validation::{self, ValidationContext},
+};
+use common::{
+    cue::{Cue, Show},
+    local::config::{LogContext, LogItem, LogKind, SystemConfiguration},
+    mem::str::StaticString,
+    protocol::{
+        message::{Heartbeat, LargeMessage, Message, SmallMessage},
+        request::{ControlAction, Request},
+    },
+};
+use crossbeam_channel::TryRecvError;
+use std::{
+    net::SocketAddr,
+    path::Path,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+/// Reason sent with `CoreMessage::RequestFailed` when JACK does not change a connection. The
+/// details are in the log.
+const ROUTING_FAILED: &str = "JACK could not change the connection";
+
+/// Directory of the log files, run logs and audit trail, whose disk space is watched
+const LOG_DIR: &str = "logs";
+
+/// Output of the timecode source, which comes after the metronome
+const TIMECODE_OUTPUT: u8 = 1;
+
+/// Boots the core and runs the main loop until a shutdown is requested or boot fails.
+pub fn run(args: Args) {
+    let cbnet = CrossbeamNetwork::new();
+    fault::install_panic_hook(cbnet.clone());
+    let mut log_dispatcher = LogDispatcher::new(
+        cbnet.clone(),
+        args.log_format,
+        args.log_retention(),
+        args.syslog,
+    );
+
+    #[cfg(feature = "i2c-ui")]
+    {
+        // The panel is used before the configuration is loaded for good
+        crate::hardware::display::configure(
+            boot::get_core_config()
+                .map(|core_config| core_config.display)
+                .unwrap_or_default(),
+        );
+        std::thread::sleep(Duration::from_secs(2));
+        let _ = crate::hardware::display::ask_usb();
+        if crate::hardware::input::wait_yes_no() {
+            crate::hardware::usb::mount();
+            if boot::get_usb_update_path().is_ok_and(|p| p.try_exists().is_ok_and(|b| b)) {
+                let _ = crate::hardware::display::ask_patch();
+                if crate::hardware::input::wait_yes_no() {
+                    if boot::try_patch() {
+                        let _ = crate::hardware::display::patch_success();
+                        std::thread::sleep(Duration::from_secs(2));
+                    } else {
+                        let _ = crate::hardware::display::patch_failure();
+                        return;
+                    }
+                }
+            };
+            if boot::get_usb_show_path().is_ok_and(|p| p.try_exists().is_ok_and(|b| b)) {
+                let _ = crate::hardware::display::ask_copy_show();
+                if crate::hardware::input::wait_yes_no() {
+                    // A damaged show on the stick is not copied over the stored one
+                    let _ = crate::hardware::display::checking_show();
+                    let faults = boot::get_usb_show_path()
+                        .ok()
+                        .and_then(|path| {
+                            ShowManifest::load(&path)
+                                .ok()
+                                .flatten()
+                                .map(|manifest| manifest.verify(&path).faults.len())
+                        })
+                        .unwrap_or_default();
+                    if faults > 0 {
+                        let _ = crate::hardware::display::show_damaged(faults);
+                    } else if boot::try_load_usb_show().is_ok() {
+                        let _ = crate::hardware::display::generic_success();
+                    } else {
+                        let _ = crate::hardware::display::generic_failure(
+                            "Could not copy show from usb".to_string(),
+                        );
+                    }
+                    std::thread::sleep(Duration::from_secs(3));
+                }
+            };
+            crate::hardware::usb::unmount();
+        }
+    }
+
+    let show_root = match boot::get_show_path() {
+        Ok(val) => val,
+        Err(err) => {
+            boot::log_boot_error(&log_dispatcher, err);
+            return;
+        }
+    };
+    // FIXME: ugly way to make sure that jackd is dead after last debug run
+    // should not need to exist in normal operation, because power cycle will reset jackd anyway,
+    // and that is the only in-use way to rerun the program
+    #[cfg(target_os = "linux")]
+    if let Ok(mut child) = std::process::Command::new("killall").arg("jackd").spawn() {
+        let _ = child.wait();
+    }
+
+    let mut config = match boot::get_config() {
+        Ok(conf) => conf,
+        Err(_) => {
+            let _ = boot::write_default_config();
+            SystemConfiguration::default()
+        }
+    };
+
+    let mut core_config = match boot::get_core_config() {
+        Ok(conf) => conf,
+        Err(err) => {
+            boot::log_boot_error(&log_dispatcher, err);
+            CoreConfiguration::default()
+        }
+    };
+    let mut handlers = HandlerRegistry::from_config(&core_config, &cbnet, &log_dispatcher);
+    let mut throttle = NotificationThrottle::new(core_config.notification_rates);
+
+    let mut config_persistence = ConfigPersistence::new();
+    let mut core_config_persistence = ConfigPersistence::new();
+    let mut audit = match AuditTrail::new(Path::new(LOG_DIR).join("audit.jsonl")) {
+        Ok(audit) => audit,
+        Err(err) => {
+            log_dispatcher.log(LogItem::new(
+                format!("Could not open audit log: {err}"),
+                LogContext::Logger,
+                LogKind::Error,
+            ));
+            AuditTrail::in_memory()
+        }
+    };
+
+    // The show last switched to, if it is still there
+    let mut loaded_show = DEFAULT_SHOW.to_string();
+    let mut show_path = show_root.clone();
+    if let Some(name) = &core_config.show
+        && let Some(path) = show_library::show_path(&show_root, name)
+    {
+        loaded_show = name.clone();
+        show_path = path;
+    }
+    let mut show = load_show(&show_path, &log_dispatcher);
+    // Check of the show files against the manifest, running until its outcome is received
+    let mut show_verification = Some(manifest::verify_in_background(show_path.clone()));
+    let mut show_extension = load_show_extension(&show_path, &log_dispatcher);
+    let mut show_watcher = ShowWatcher::new(&show_path);
+    let mut run_log = RunLog::new(Path::new(LOG_DIR).join("runs"));
+    start_performance(&mut run_log, &show, &log_dispatcher);
+
+    #[cfg(feature = "i2c-ui")]
+    {
+        std::thread::sleep(Duration::from_secs(5));
+        let _ = crate::hardware::panel::start();
+        let _ = crate::hardware::display::startup();
+    }
+    let channel_counts = core_config.channel_counts;
+    let mut pbh = PlaybackHandler::new(
+        cbnet.clone(),
+        show_path.clone(),
+        channel_counts.playback_channels,
+        core_config.stream_media_s,
+    );
+    let mut ah = AudioHandler::new(
+        channel_counts.sources(),
+        channel_counts.output_ports(),
+        core_config.input_count.min(MAX_METERED_INPUTS),
+        cbnet.clone(),
+    );
+    let unheard = core_config.unheard_outputs();
+    if !unheard.is_empty() {
+        log_dispatcher.log(LogItem::new(
+            format!(
+                "Outputs {unheard:?} are summed into no client output port and have none of their \
+                 own, they are not heard"
+            ),
+            LogContext::Boot,
+            LogKind::Warning,
+        ));
+    }
+
+    let mut last_heartbeat_time = Instant::now();
+    let mut loop_count = 0;
+    // Bar and beat counter of the performance view, and the cue, beat, counter and keypad entry
+    // it last showed
+    #[cfg(feature = "i2c-ui")]
+    let mut panel_counter = String::new();
+    #[cfg(feature = "i2c-ui")]
+    let mut panel_shown: Option<(u8, u16, String, String)> = None;
+    // Cue number being typed on the keypad
+    #[cfg(feature = "i2c-ui")]
+    let mut cue_entry = crate::hardware::input::CueEntry::default();
+    #[cfg(feature = "i2c-ui")]
+    let panic_input = crate::hardware::input::PanicInput::new(core_config.panic_gpio)
+        .unwrap_or_else(|err| {
+            log_dispatcher.log(LogItem::new(
+                format!("Could not open the panic button: {err}"),
+                LogContext::Boot,
+                LogKind::Error,
+            ));
+            crate::hardware::input::PanicInput::default()
+        });
+    // Presses on the front panel, read on a thread of their own
+    #[cfg(feature = "i2c-ui")]
+    let panel_events = crate::hardware::input::start(panic_input).unwrap_or_else(|err| {
+        log_dispatcher.log(LogItem::new(
+            format!("Could not read the front panel: {err}"),
+            LogContext::Boot,
+            LogKind::Error,
+        ));
+        crossbeam_channel::never()
+    });
+    let mut run_flag = true;
+    // Restart or reboot to carry out once shut down, if any
+    let mut power_action: Option<PowerAction> = None;
+    let mut power_gate = PowerGate::default();
+    // Clicks played by the metronome, synthesized here rather than on the audio thread
+    let clicks = click_bank(
+        core_config.click_synthesis,
+        config.audio.server.sample_rate as usize,
+    );
+    let mut cue_idx = 0;
+    let mut standby: Option<u8> = None;
+    // Cue settings last sent to the audio processor: cue, its extension and the default zero
+    // target
+    let mut sent_cue_settings: Option<(u8, Option<CueExtension>, ZeroTarget, ClickLevels)> = None;
+    // Click synthesis and samples the clicks were last made from, and the sample rate they were
+    // made at
+    let mut sent_clicks: Option<(ClickSynthesis, ClickSamples, usize)> = None;
+    // Cue and transport state as last noted in the run log
+    let mut logged_cue: Option<u8> = None;
+    let mut transport_running = false;
+    // Beat of the transport, and the cue and beat upcoming events were last previewed from
+    let mut beat_idx = 0_u16;
+    let mut previewed: Option<(u8, u16)> = None;
+    // Venue script, and the cue and beat its hooks were last called for
+    let mut script = load_script(&core_config, &log_dispatcher);
+    let mut scripted: Option<(u8, u16)> = None;
+    let mut macro_runner = MacroRunner::default();
+    // Cue whose scene was last recalled
+    let mut scene_cue: Option<u8> = None;
+    // Backup units mirror the state of their primary until they take over, cluster followers
+    // that of their conductor
+    let mut mirror = match (&core_config.backup, &core_config.cluster) {
+        (Some(backup), _) => Some(MirrorLink::new(backup.primary)),
+        (None, Some(cluster)) => Some(MirrorLink::new(cluster.conductor)),
+        (None, None) => None,
+    };
+    let mut failover_role = match core_config.backup {
+        Some(_) => FailoverRole::Backup {
+            primary_alive: false,
+        },
+        None => FailoverRole::Primary,
+    };
+    // Unix time in microseconds at which the transport reached `beat_idx`
+    let mut beat_time_us = 0_u64;
+    // Latest state of the conductor of a cluster follower, and how far behind it this unit was
+    // on the last beat they shared
+    let mut conductor_state: Option<MirrorState> = None;
+    let mut cluster_drift_us: Option<i64> = None;
+    let mut link_monitor = LinkMonitor::new();
+    // Resources measured off the main loop, and those found running out on the last heartbeat
+    let mut resource_monitor =
+        ResourceMonitor::start(show_path.clone(), Path::new(LOG_DIR).to_path_buf());
+    let mut resource_alarms: Vec<ResourceAlarm> = vec![];
+    // State last sent to backups, and when
+    let mut mirrored: Option<(MirrorState, Instant)> = None;
+    while run_flag {
+        loop_count += 1;
+        cbnet.metrics.record_main_loop_cycle();
+        // Get a possible Request from network handler
+        // and decide how to handle it. Network handler has already handled and consumed
+        // network-specific messages.
+
+        let mut inputs = handlers.get_all_inputs();
+        if let Some(script) = &mut script {
+            inputs.extend(take_script_requests(script, &log_dispatcher));
+        }
+        inputs.extend(macro_runner.due(Instant::now()));
+        if let Some(link) = &mut mirror {
+            let now = Instant::now();
+            let current = MirrorState {
+                cue: cue_idx,
+                beat: beat_idx,
+                running: transport_running,
+                beat_time_us,
+            };
+            if let Some(state) = link.poll(now) {
+                let source = link.source();
+                inputs.extend(
+                    state
+                        .actions_from(&current)
+                        .into_iter()
+                        .map(|action| (Request::ControlAction(action), source.clone())),
+                );
+                conductor_state = Some(state);
+            }
+            if let Some(drift) = conductor_state.and_then(|state| beat_drift(&current, &state)) {
+                cluster_drift_us = Some(drift);
+            }
+        }
+        if let Some(link) = &mirror
+            && let Some(backup_config) = &core_config.backup
+        {
+            let now = Instant::now();
+            let timeout = Duration::from_millis(backup_config.takeover_ms as u64);
+            let primary_alive = link.leader_alive(now, timeout);
+            if failover_role != (FailoverRole::Backup { primary_alive }) {
+                failover_role = FailoverRole::Backup { primary_alive };
+                log_dispatcher.log(LogItem::new(
+                    if primary_alive {
+                        "Mirroring the primary.".to_string()
+                    } else {
+                        format!("Primary at {} has gone silent.", backup_config.primary)
+                    },
+                    LogContext::Network,
+                    if primary_alive {
+                        LogKind::Note
+                    } else {
+                        LogKind::Warning
+                    },
+                ));
+                handlers.notify_core(&CoreMessage::FailoverRole(failover_role));
+            }
+            if link.leader_lost(now, timeout) && backup_config.auto_takeover {
+                take_over(
+                    &mut mirror,
+                    &mut failover_role,
+                    cue_idx,
+                    &cbnet,
+                    &mut handlers,
+                    &mut run_log,
+                    &log_dispatcher,
+                );
+            }
+        }
+        // A changed show file is only reloaded once confirmed, by a client or on the front panel
+        if show_watcher.poll() {
+            log_dispatcher.log(LogItem::new(
+                "Show file changed on disk, waiting for confirmation to reload.".to_string(),
+                LogContext::Boot,
+                LogKind::Note,
+            ));
+            handlers.notify_core(&CoreMessage::ShowFileChanged(true));
+            #[cfg(feature = "i2c-ui")]
+            let _ = crate::hardware::display::ask_reload_show();
+        }
+        let repairs = ah.watch_routing(core_config.restore_routing, Instant::now());
+        if !repairs.is_empty() {
+            for repair in &repairs {
+                log_dispatcher.log(LogItem::new(
+                    format!("JACK connection {repair}."),
+                    LogContext::AudioHandler,
+                    LogKind::Warning,
+                ));
+            }
+            handlers.notify_core(&CoreMessage::RoutingDrift(repairs));
+            notify_audio_status(&mut handlers, &mut ah, &core_config.outputs);
+        }
+        if let Some(verification) = &show_verification {
+            match verification.try_recv() {
+                Ok(result) => {
+                    report_show_verification(result, &log_dispatcher, &mut handlers);
+                    show_verification = None;
+                }
+                Err(TryRecvError::Disconnected) => show_verification = None,
+                Err(TryRecvError::Empty) => {}
+            }
+        }
+        // Whether the macro button and the panic button were pressed since the last loop
+        #[cfg(feature = "i2c-ui")]
+        let (mut panel_macro, mut panel_panic) = (false, false);
+        #[cfg(feature = "i2c-ui")]
+        for event in panel_events.try_iter() {
+            use crate::hardware::input::PanelEvent;
+            match event {
+                // A changed show file is reloaded on YES and kept on NO
+                PanelEvent::Answer(true) if show_watcher.is_changed() => inputs.push((
+                    Request::Initialize,
+                    crate::communication::interface::RequestSource::front_panel(),
+                )),
+                PanelEvent::Answer(false) if show_watcher.is_changed() => {
+                    show_watcher.reset();
+                    handlers.notify_core(&CoreMessage::ShowFileChanged(false));
+                    let _ = crate::hardware::display::show_load_success(&show);
+                }
+                PanelEvent::Answer(_) => {}
+                // Cue numbers typed on the keypad are loaded on GO like any other request
+                PanelEvent::Key(key) => {
+                    if let Some(ident) = cue_entry.press(key) {
+                        match show
+                            .cues
+                            .iter()
+                            .position(|cue| cue.metadata.human_ident.str() == ident)
+                        {
+                            Some(idx) => inputs.push((
+                                Request::ControlAction(ControlAction::LoadCueByIndex(idx as u8)),
+                                crate::communication::interface::RequestSource::front_panel(),
+                            )),
+                            None => {
+                                let _ = crate::hardware::display::generic_failure(format!(
+                                    "No cue {ident}"
+                                ));
+                            }
+                        }
+                    }
+                }
+                PanelEvent::Macro => panel_macro = true,
+                PanelEvent::Panic => panel_panic = true,
+            }
+        }
+
+        for (control_message, source) in inputs {
+            let audited = !matches!(control_message, Request::Ping);
+            let request_desc = format!("{control_message:?}");
+            let mut outcome = AuditOutcome::Accepted;
+
+            let validation_ctx =
+                validation_context(&show, cue_idx, standby, &config, &core_config, &ah);
+            if let Err(reason) = validation::validate(&control_message, &validation_ctx) {
+                log_dispatcher.log(LogItem::new(
+                    format!("Rejected {request_desc} from {source}: {reason}"),
+                    LogContext::Network,
+                    LogKind::Warning,
+                ));
+                handlers.reply(
+                    &source,
+                    CoreMessage::RequestRejected {
+                        request: request_desc.clone(),
+                        reason,
+                    },
+                );
+                let _ = audit.record(source, request_desc, AuditOutcome::Rejected);
+                continue;
+            }
+            if audited && let Some(script) = &mut script {
+                script.on_request(&source, &request_desc);
+            }
+
+            match control_message {
+                Request::ControlAction(cmd) => {
+                    if cmd == ControlAction::TransportZero {
+                        // The processor knows where the configured zero target currently is
+                        cbnet.command_core(CoreControlAction::ZeroToTarget);
+                    } else {
+                        cbnet.command(cmd);
+                    }
+                    match cmd {
+                        ControlAction::LoadCueByIndex(idx) => {
+                            cue_idx = idx;
+                            pbh.load_cue(cue_idx)
+                        }
+                        ControlAction::SetChannelGain(channel, gain) => {
+                            config.channels[channel as usize].gain = gain;
+                            config_persistence.mark_dirty();
+                            handlers
+                                .notify(Message::Large(LargeMessage::ConfigurationChanged(config)));
+                        }
+                        ControlAction::SetChannelMute(channel, muted) => {
+                            core_config.output_mut(channel as usize).muted = muted;
+                            core_config_persistence.mark_dirty();
+                            handlers
+                                .notify_core(&CoreMessage::Outputs(core_config.outputs.clone()));
+                        }
+                        ControlAction::LoadPreviousCue => {
+                            if cue_idx > 0 {
+                                cue_idx -= 1;
+                                cbnet.command(ControlAction::LoadCueByIndex(cue_idx));
+                                pbh.load_cue(cue_idx)
+                            }
+                        }
+                        ControlAction::LoadNextCue => {
+                            if cue_idx as usize + 1 < show.cues.len() {
+                                cue_idx += 1;
+                                cbnet.command(ControlAction::LoadCueByIndex(cue_idx));
+                                pbh.load_cue(cue_idx)
+                            }
+                        }
+                        ControlAction::TransportStop if transport_running => {
+                            record_run(
+                                &mut run_log,
+                                RunEvent::Held { cue: cue_idx },
+                                &log_dispatcher,
+                            );
+                        }
+                        _ => {}
+                    }
+                }
+                Request::ChangeRouting(a, b, connect) => {
+                    if ah.try_route_ports(a, b, connect) {
+                        // Only what the core routed is kept, not what others have changed since
+                        if core_config.routing.is_empty() {
+                            core_config.routing = ah.get_connections();
+                        } else {
+                            set_route(&mut core_config.routing, a, b, connect);
+                        }
+                        core_config_persistence.mark_dirty();
+                    } else {
+                        outcome = AuditOutcome::Failed;
+                        handlers.reply(
+                            &source,
+                            CoreMessage::RequestFailed {
+                                request: request_desc.clone(),
+                                reason: ROUTING_FAILED.to_string(),
+                            },
+                        );
+                    }
+                    notify_audio_status(&mut handlers, &mut ah, &core_config.outputs);
+                }
+                Request::NotifySubscribers => {
+                    cbnet.command(ControlAction::DumpStatus);
+                    notify_audio_status(&mut handlers, &mut ah, &core_config.outputs);
+                    handlers.notify(Message::Large(LargeMessage::ConfigurationChanged(config)));
+                    handlers.notify(Message::Large(LargeMessage::PlaybackHandlerChanged(
+                        pbh.get_status(),
+                    )));
+                }
+                Request::Shutdown => {
+                    // Shut down cleanly once out of the main loop
+                    let _ = audit.record(source, request_desc, outcome);
+                    run_flag = false;
+                    break;
+                }
+
+                Request::Initialize => {
+                    show = load_show(&show_path, &log_dispatcher);
+                    show_verification = Some(manifest::verify_in_background(show_path.clone()));
+                    show_extension = load_show_extension(&show_path, &log_dispatcher);
+                    if show_watcher.reset() {
+                        handlers.notify_core(&CoreMessage::ShowFileChanged(false));
+                    }
+                    sent_cue_settings = None;
+                    previewed = None;
+                    scene_cue = None;
+                    if standby.take().is_some() {
+                        handlers.notify_core(&CoreMessage::Standby(None));
+                    }
+                    sent_clicks = None;
+                    let mut sources = vec![
+                        audio::source::SourceConfig::new(
+                            "metronome".to_string(),
+                            Box::new(Metronome::with_bank(clicks.clone())),
+                        ),
+                        audio::source::SourceConfig::new(
+                            "timecode".to_string(),
+                            Box::new(TimecodeSource::new(
+                                config.audio.server.sample_rate as usize,
+                                show.cues.first().map_or(DEFAULT_LTC_FRAME_RATE, |cue| {
+                                    show_extension.frame_rate(cue)
+                                }),
+                            )),
+                        ),
+                    ];
+                    pbh.load_show(
+                        show.clone(),
+                        show_extension.alternates(&show),
+                        config.audio.server.sample_rate as u32,
+                    );
+                    sources.extend(pbh.create_audio_sources());
+                    // TODO: ugly
+                    if !show.cues.is_empty() {
+                        pbh.load_cue(0);
+                    }
+                    for (source, channel) in sources.iter_mut().zip(config.channels.iter()) {
+                        source.set_gain(channel.gain);
+                    }
+
+                    ah.configure(config.audio);
+                    ah.start(sources, show.clone(), &core_config.routing);
+                    cbnet.command_core(CoreControlAction::SetTempoFollow(core_config.tempo_follow));
+                    cbnet.command_core(CoreControlAction::SetTimecodeOutput(
+                        core_config.timecode_output,
+                    ));
+                    cbnet.command_core(CoreControlAction::SetBeatPhaseRate(
+                        core_config.beat_phase_rate,
+                    ));
+                    cbnet.command_core(CoreControlAction::SetGainRamp {
+                        ramp_ms: core_config.gain_ramp_ms,
+                    });
+                    cbnet.command_core(CoreControlAction::SetStopFade {
+                        fade_ms: core_config.stop_fade_ms,
+                    });
+                    cbnet.command_core(CoreControlAction::MuteOutputs(
+                        core_config.backup.is_some() && mirror.is_some(),
+                    ));
+                    cbnet.command_core(CoreControlAction::RenderChannels(
+                        core_config
+                            .cluster
+                            .as_ref()
+                            .filter(|_| core_config.backup.is_none())
+                            .map(|cluster| (cluster.first_channel, cluster.channel_count)),
+                    ));
+                    for (output, output_config) in core_config.outputs.iter().enumerate() {
+                        cbnet.command(ControlAction::SetChannelMute(
+                            output as u8,
+                            output_config.muted,
+                        ));
+                        cbnet.command_core(CoreControlAction::SetOutputEq {
+                            output: output as u8,
+                            eq: output_config.eq,
+                        });
+                        cbnet.command_core(CoreControlAction::SetOutputDelay {
+                            output: output as u8,
+                            delay_ms: output_config.delay_ms,
+                        });
+                        cbnet.command_core(CoreControlAction::SetOutputPolarity {
+                            output: output as u8,
+                            invert: output_config.invert,
+                        });
+                        for route in &output_config.pass_through {
+                            cbnet.command_core(CoreControlAction::SetPassThrough {
+                                input: route.input,
+                                output: output as u8,
+                                gain_db: Some(route.gain_db),
+                            });
+                        }
+                        for send in &output_config.mix {
+                            cbnet.command_core(CoreControlAction::SetOutputMix {
+                                output: output as u8,
+                                port: send.port,
+                                gain_db: Some(send.gain_db),
+                            });
+                        }
+                    }
+                    notify_audio_status(&mut handlers, &mut ah, &core_config.outputs);
+                }
+
+                Request::ChangeConfiguration(conf) => {
+                    config.update(conf);
+                    config_persistence.mark_dirty();
+                    handlers.notify(Message::Large(LargeMessage::ConfigurationChanged(config)));
+                }
+
+                _ => {}
+            };
+
+            if audited && let Err(err) = audit.record(source, request_desc, outcome) {
+                log_dispatcher.log(LogItem::new(
+                    format!("Could not write audit log: {err}"),
+                    LogContext::Logger,
+                    LogKind::Warning,
+                ));
+            }
+        }
+
+        // Requests outside the common protocol are answered directly to the requesting client
+        let mut core_inputs = handlers.get_core_inputs();
+        // The macro button runs its macro once per press
+        #[cfg(feature = "i2c-ui")]
+        {
+            if panel_macro && let Some(name) = &core_config.panel_macro {
+                core_inputs.push((
+                    CoreRequest::RunMacro { name: name.clone() },
+                    RequestSource::front_panel(),
+                ));
+            }
+            // Stopping everything goes first, before anything else asked for in the same loop
+            if panel_panic {
+                core_inputs.insert(0, (CoreRequest::AllStop, RequestSource::front_panel()));
+            }
+        }
+        for (request, source) in core_inputs {
+            let request_desc = format!("{request:?}");
+            let validation_ctx =
+                validation_context(&show, cue_idx, standby, &config, &core_config, &ah);
+            if let Err(reason) = validation::validate_core(&request, &validation_ctx) {
+                handlers.reply(
+                    &source,
+                    CoreMessage::RequestRejected {
+                        request: request_desc,
+                        reason,
+                    },
+                );
+                continue;
+            }
+            if !matches!(request, CoreRequest::Mirror)
+                && let Some(script) = &mut script
+            {
+                script.on_request(&source, &request_desc);
+            }
+            let reply = match request {
+                CoreRequest::SetCueEnd { .. }
+                | CoreRequest::SetZeroTarget { .. }
+                | CoreRequest::SetCueMarkers { .. }
+                | CoreRequest::SetCueScene { .. }
+                | CoreRequest::SetClickLevels { .. }
+                | CoreRequest::SetCueColor { .. }
+                | CoreRequest::SetCueFlags { .. }
+                | CoreRequest::SetGainAutomation { .. }
+                | CoreRequest::SetMuteEvents { .. }
+                | CoreRequest::SetFadeEvents { .. }
+                | CoreRequest::SetClickSamples { cue: Some(_), .. }
+                | CoreRequest::SetCueClickSynthesis { .. } => handle_cue_settings_request(
+                    request,
+                    &show,
+                    &show_path,
+                    &mut show_extension,
+                    &mut core_config,
+                    &mut core_config_persistence,
+                )
+                .map(|()| None),
+                CoreRequest::Standby { .. } | CoreRequest::CancelStandby | CoreRequest::Go => {
+                    handle_cue_request(request, &pbh, &cbnet, &mut cue_idx, &mut standby);
+                    Ok(None)
+                }
+                CoreRequest::RunLog { count } => Ok(Some(CoreMessage::RunLog(
+                    run_log.recent((count as usize).min(MAX_LOG_TAIL)),
+                ))),
+                CoreRequest::ExportTimingReport(format) => {
+                    let path = show_path.join(format!("timing.{}", format.extension()));
+                    TimingReport::from_show(&show)
+                        .write(&path, format)
+                        .map(|()| {
+                            Some(CoreMessage::TimingReportExported {
+                                path: path.display().to_string(),
+                            })
+                        })
+                        .map_err(|err| err.to_string())
+                }
+                CoreRequest::ExportProtocolSchema => ProtocolSchema::current()
+                    .and_then(|schema| schema.chunks())
+                    .map(|chunks| {
+                        for chunk in chunks {
+                            handlers.reply(&source, CoreMessage::ProtocolSchema(chunk));
+                        }
+                        None
+                    }),
+                CoreRequest::StoreScene { name } => {
+                    let scene = Scene::capture(&config, &core_config, &ah.get_connections());
+                    core_config.scenes.insert(name, scene);
+                    core_config_persistence.mark_dirty();
+                    handlers.notify_core(&CoreMessage::Scenes(
+                        core_config.scenes.keys().cloned().collect(),
+                    ));
+                    Ok(None)
+                }
+                CoreRequest::RecallScene { name, fade_ms } => {
+                    if core_config.scenes.contains_key(&name) {
+                        let recalled = recall_scene(
+                            &name,
+                            fade_ms,
+                            &mut config,
+                            &mut core_config,
+                            &mut ah,
+                            &cbnet,
+                            &mut handlers,
+                        );
+                        // Even when routing fails, the gains and mutes have been recalled
+                        config_persistence.mark_dirty();
+                        core_config_persistence.mark_dirty();
+                        recalled.map(|()| None)
+                    } else {
+                        Err(format!("there is no scene named '{name}'"))
+                    }
+                }
+                CoreRequest::RunMacro { name } => {
+                    if let Some(steps) = core_config.macros.get(&name) {
+                        macro_runner.run(&name, steps, Instant::now());
+                    }
+                    Ok(None)
+                }
+                CoreRequest::StopMacro { name } => {
+                    macro_runner.cancel(&name);
+                    Ok(None)
+                }
+                CoreRequest::TakeOver => {
+                    if take_over(
+                        &mut mirror,
+                        &mut failover_role,
+                        cue_idx,
+                        &cbnet,
+                        &mut handlers,
+                        &mut run_log,
+                        &log_dispatcher,
+                    ) {
+                        Ok(None)
+                    } else {
+                        Err("this unit is not a backup".to_string())
+                    }
+                }
+                CoreRequest::HandBack => {
+                    if let Some(backup) = &core_config.backup
+                        && hand_back(
+                            backup.primary,
+                            &mut mirror,
+                            &mut failover_role,
+                            &cbnet,
+                            &mut handlers,
+                            &log_dispatcher,
+                        )
+                    {
+                        record_run(
+                            &mut run_log,
+                            RunEvent::HandedBack { cue: cue_idx },
+                            &log_dispatcher,
+                        );
+                        Ok(None)
+                    } else {
+                        Err("this unit has not taken over".to_string())
+                    }
+                }
+                CoreRequest::GetFailoverRole => Ok(Some(CoreMessage::FailoverRole(failover_role))),
+                CoreRequest::DismissShowChange => {
+                    if show_watcher.reset() {
+                        handlers.notify_core(&CoreMessage::ShowFileChanged(false));
+                    }
+                    Ok(None)
+                }
+                CoreRequest::NewPerformance => {
+                    start_performance(&mut run_log, &show, &log_dispatcher);
+                    // The new performance starts on the current cue
+                    logged_cue = None;
+                    Ok(None)
+                }
+                CoreRequest::GetCues => Ok(Some(CoreMessage::Cues(CueSummary::of_show(&show)))),
+                CoreRequest::ListShows => Ok(Some(CoreMessage::Shows {
+                    loaded: loaded_show.clone(),
+                    shows: show_library::scan(&show_root),
+                })),
+                CoreRequest::LoadShowByName { name } => {
+                    match show_library::show_path(&show_root, &name) {
+                        None => Err(format!("there is no show named '{name}'")),
+                        Some(_) if transport_running => Err("the transport is running".to_string()),
+                        Some(path) => match try_load_show(&path, &log_dispatcher) {
+                            Err(err) => Err(err.to_string()),
+                            Ok(loaded) => {
+                                show = loaded;
+                                show_path = path;
+                                resource_monitor.set_show_path(show_path.clone());
+                                show_verification =
+                                    Some(manifest::verify_in_background(show_path.clone()));
+                                show_extension = load_show_extension(&show_path, &log_dispatcher);
+                                if show_watcher.is_changed() {
+                                    handlers.notify_core(&CoreMessage::ShowFileChanged(false));
+                                }
+                                show_watcher = ShowWatcher::new(&show_path);
+                                sent_cue_settings = None;
+                                sent_clicks = None;
+                                previewed = None;
+                                scene_cue = None;
+                                if standby.take().is_some() {
+                                    handlers.notify_core(&CoreMessage::Standby(None));
+                                }
+                                pbh.set_show_path(show_path.clone());
+                                pbh.load_show(
+                                    show.clone(),
+                                    show_extension.alternates(&show),
+                                    config.audio.server.sample_rate as u32,
+                                );
+                                // The audio client carries on with the playback sources of the
+                                // new show. Not running, it starts with them
+                                ah.swap_show(show.clone(), pbh.create_playback_devices());
+                                if !show.cues.is_empty() {
+                                    pbh.load_cue(0);
+                                }
+                                core_config.show = (name != DEFAULT_SHOW).then(|| name.clone());
+                                core_config_persistence.mark_dirty();
+                                loaded_show = name;
+                                start_performance(&mut run_log, &show, &log_dispatcher);
+                                logged_cue = None;
+                                handlers.notify_core(&CoreMessage::Shows {
+                                    loaded: loaded_show.clone(),
+                                    shows: show_library::scan(&show_root),
+                                });
+                                Ok(None)
+                            }
+                        },
+                    }
+                }
+                CoreRequest::AllStop => {
+                    record_run(
+                        &mut run_log,
+                        RunEvent::AllStop {
+                            cue: cue_idx,
+                            source: source.to_string(),
+                        },
+                        &log_dispatcher,
+                    );
+                    cbnet.command(ControlAction::TransportStop);
+                    cbnet.command_core(CoreControlAction::StopAllPlayback { fade_ms: 0.0 });
+                    // Until the next start only, so LTC does not chase lighting and video again
+                    // by accident, but neither does it stay muted past a reboot
+                    cbnet.command_core(CoreControlAction::MuteUntilStart(TIMECODE_OUTPUT));
+                    log_dispatcher.log(LogItem::new(
+                        format!("All stop from {source}."),
+                        LogContext::Transport,
+                        LogKind::Warning,
+                    ));
+                    #[cfg(feature = "i2c-ui")]
+                    let _ = crate::hardware::display::all_stop();
+                    Ok(None)
+                }
+                CoreRequest::TransportStopQuantized => {
+                    if transport_running {
+                        record_run(
+                            &mut run_log,
+                            RunEvent::Held { cue: cue_idx },
+                            &log_dispatcher,
+                        );
+                    }
+                    cbnet.command_core(CoreControlAction::StopAtEndOfBar);
+                    Ok(None)
+                }
+                CoreRequest::LaunchCue { cue, at } => {
+                    let cue = cue.unwrap_or(cue_idx.saturating_add(1));
+                    if transport_running {
+                        // The processor switches cues on the boundary and tells us to load the
+                        // media then; read it ahead so it is ready in time
+                        pbh.prefetch(cue);
+                        cbnet.command_core(CoreControlAction::LaunchCue { cue, at });
+                    } else {
+                        cue_idx = cue;
+                        pbh.load_cue(cue);
+                        cbnet.command(ControlAction::LoadCueByIndex(cue));
+                    }
+                    Ok(None)
+                }
+                CoreRequest::SetClickSynthesis(synthesis) => {
+                    core_config.click_synthesis = synthesis;
+                    core_config_persistence.mark_dirty();
+                    handlers.notify_core(&CoreMessage::ClickSynthesis(synthesis));
+                    Ok(None)
+                }
+                CoreRequest::Restart { passphrase } => arm_power(
+                    &mut power_gate,
+                    PowerAction::Restart,
+                    passphrase,
+                    &core_config,
+                    &source,
+                    &log_dispatcher,
+                ),
+                CoreRequest::Reboot { passphrase } => arm_power(
+                    &mut power_gate,
+                    PowerAction::Reboot,
+                    passphrase,
+                    &core_config,
+                    &source,
+                    &log_dispatcher,
+                ),
+                CoreRequest::ConfirmPower { code } => power_gate
+                    .confirm(code, &source, Instant::now())
+                    .map(|action| {
+                        log_dispatcher.log(LogItem::new(
+                            format!("{action:?} confirmed by {source}."),
+                            LogContext::Boot,
+                            LogKind::Warning,
+                        ));
+                        power_action = Some(action);
+                        run_flag = false;
+                        None
+                    }),
+                CoreRequest::LocateTimecode(time) => show
+                    .cues
+                    .get(cue_idx as usize)
+                    .and_then(|cue| beat_at_timecode(cue, time))
+                    .map(|beat| {
+                        cbnet.command(ControlAction::TransportSeekBeat(beat));
+                        None
+                    })
+                    .ok_or_else(|| format!("cue {cue_idx} sends no timecode at or before {time}")),
+                CoreRequest::GetShow => show_dump::dump(&show).map(|chunks| {
+                    for chunk in chunks {
+                        handlers.reply(&source, CoreMessage::ShowDump(chunk));
+                    }
+                    None
+                }),
+                request => handle_core_request(
+                    request,
+                    &log_dispatcher,
+                    &audit,
+                    &cbnet,
+                    &mut core_config,
+                    &mut core_config_persistence,
+                    &mut ah,
+                ),
+            };
+            match reply {
+                Ok(Some(reply)) => handlers.reply(&source, reply),
+                Ok(None) => {}
+                Err(reason) => {
+                    log_dispatcher.log(LogItem::new(
+                        format!("Failed {request_desc} from {source}: {reason}"),
+                        LogContext::Network,
+                        LogKind::Warning,
+                    ));
+                    handlers.reply(
+                        &source,
+                        CoreMessage::RequestFailed {
+                            request: request_desc,
+                            reason,
+                        },
+                    );
+                }
+            }
+        }
+        // The rates may have been changed by a core request
+        throttle.set_rates(core_config.notification_rates);
+
+        // Keep the audio processor up to date with the settings of the current cue
+        if let Some(cue) = show.cues.get(cue_idx as usize) {
+            let settings = (
+                cue_idx,
+                show_extension.get(cue),
+                core_config.zero_target,
+                core_config.click_levels,
+            );
+            if sent_cue_settings
+                .as_ref()
+                .map(|(idx, extension, zero_target, click_levels)| {
+                    (*idx, extension.as_ref(), *zero_target, *click_levels)
+                })
+                != Some(settings)
+            {
+                let extension = show_extension.cue(cue);
+                cbnet.command_core(CoreControlAction::SetCueEnd(extension.end));
+                cbnet.command_core(CoreControlAction::SetZeroTarget {
+                    target: extension.zero.unwrap_or(core_config.zero_target),
+                    markers: CueMarkers::new(&extension.markers),
+                });
+                cbnet.command_core(CoreControlAction::SetTimecodeFrameRate(
+                    show_extension.frame_rate(cue),
+                ));
+                cbnet.command_core(CoreControlAction::SetClipChains(ClipChains::new(
+                    &extension.chains,
+                )));
+                cbnet.command_core(CoreControlAction::SetGainAutomation(GainAutomation::new(
+                    &extension.automation,
+                )));
+                cbnet.command_core(CoreControlAction::SetClipAlternates(AlternateClips::new(
+                    &extension.alternates,
+                )));
+                cbnet.command_core(CoreControlAction::SetMuteEvents(MuteEvents::new(
+                    &extension.mutes,
+                )));
+                cbnet.command_core(CoreControlAction::SetFadeEvents(FadeEvents::new(
+                    &extension.fades,
+                )));
+                cbnet.command_core(CoreControlAction::SetClickLevels(
+                    extension.click_levels.unwrap_or(core_config.click_levels),
+                ));
+                handlers.notify_core(&CoreMessage::CueMarking {
+                    cue: cue_idx,
+                    color: extension.color,
+                    flags: extension.flags.clone(),
+                });
+                // The flags of the cue may have changed without the cue or beat changing
+                #[cfg(feature = "i2c-ui")]
+                {
+                    panel_shown = None;
+                }
+                sent_cue_settings = Some((
+                    cue_idx,
+                    settings.1.cloned(),
+                    core_config.zero_target,
+                    core_config.click_levels,
+                ));
+            }
+        }
+
+        // Keep the clicks up to date with those of the current cue, made here rather than on
+        // the audio thread
+        let cue_clicks = show
+            .cues
+            .get(cue_idx as usize)
+            .and_then(|cue| show_extension.get(cue));
+        let click_synthesis = cue_clicks
+            .and_then(|cue| cue.click_synthesis)
+            .unwrap_or(core_config.click_synthesis);
+        let click_samples = cue_clicks
+            .and_then(|cue| cue.click_samples.as_ref())
+            .unwrap_or(&core_config.click_samples);
+        // Made at the rate JACK actually runs at, which may differ from the configured one
+        let click_sample_rate = ah
+            .sample_rate()
+            .unwrap_or(config.audio.server.sample_rate as usize);
+        if sent_clicks
+            .as_ref()
+            .map(|(synthesis, samples, sample_rate)| (*synthesis, samples, *sample_rate))
+            != Some((click_synthesis, click_samples, click_sample_rate))
+        {
+            let (sounds, errors) = ClickSounds::load(
+                click_synthesis,
+                click_samples,
+                &show_path,
+                click_sample_rate,
+            );
+            for error in errors {
+                log_dispatcher.log(LogItem::new(
+                    format!("Could not read click sample {error}"),
+                    LogContext::AudioSource,
+                    LogKind::Warning,
+                ));
+            }
+            clicks.store(Arc::new(sounds));
+            sent_clicks = Some((click_synthesis, click_samples.clone(), click_sample_rate));
+        }
+
+        // Recall the scene attached to a newly loaded cue
+        if scene_cue != Some(cue_idx)
+            && let Some(cue) = show.cues.get(cue_idx as usize)
+        {
+            scene_cue = Some(cue_idx);
+            if let Some(name) = show_extension.get(cue).and_then(|cue| cue.scene.clone()) {
+                let recalled = recall_scene(
+                    &name,
+                    core_config.scene_fade_ms,
+                    &mut config,
+                    &mut core_config,
+                    &mut ah,
+                    &cbnet,
+                    &mut handlers,
+                );
+                config_persistence.mark_dirty();
+                core_config_persistence.mark_dirty();
+                if let Err(err) = recalled {
+                    log_dispatcher.log(LogItem::new(
+                        format!("Could not recall scene '{name}' of cue {cue_idx}: {err}"),
+                        LogContext::AudioHandler,
+                        LogKind::Warning,
+                    ));
+                }
+            }
+        }
+
+        // Note cue changes in the run log, however the cue was loaded
+        if logged_cue != Some(cue_idx)
+            && let Some(cue) = show.cues.get(cue_idx as usize)
+        {
+            if let Some(from) = logged_cue
+                && cue_idx > from.saturating_add(1)
+            {
+                record_run(
+                    &mut run_log,
+                    RunEvent::Skipped { from, to: cue_idx },
+                    &log_dispatcher,
+                );
+            }
+            record_run(
+                &mut run_log,
+                RunEvent::CueLoaded {
+                    cue: cue_idx,
+                    ident: cue.metadata.human_ident.str().to_string(),
+                },
+                &log_dispatcher,
+            );
+            logged_cue = Some(cue_idx);
+        }
+
+        while let Ok(msg) = cbnet.core_notif_rx.try_recv() {
+            match &msg {
+                CoreMessage::CueEnded { cue } => {
+                    record_run(
+                        &mut run_log,
+                        RunEvent::CueEnded { cue: *cue },
+                        &log_dispatcher,
+                    );
+                    if let Some(ended) = show.cues.get(*cue as usize) {
+                        handle_cue_end(
+                            *cue,
+                            show_extension.cue(ended).end,
+                            &show,
+                            &pbh,
+                            &cbnet,
+                            &mut cue_idx,
+                            &mut standby,
+                        );
+                    }
+                }
+                CoreMessage::Go { cue } => {
+                    record_run(&mut run_log, RunEvent::Go { cue: *cue }, &log_dispatcher);
+                }
+                CoreMessage::CueLaunched { cue } => {
+                    cue_idx = *cue;
+                    pbh.load_cue(*cue);
+                }
+                CoreMessage::SystemFault {
+                    thread, message, ..
+                } => {
+                    record_run(
+                        &mut run_log,
+                        RunEvent::Fault {
+                            thread: thread.clone(),
+                            message: message.clone(),
+                        },
+                        &log_dispatcher,
+                    );
+                }
+                _ => {}
+            }
+            handlers.notify_core(&msg);
+        }
+
+        // Shows replaced on the audio thread are dropped here rather than on the RT thread
+        cbnet.retired_rx.try_iter().for_each(drop);
+
+        // Get Messages from audio processor and send them to all network handlers to
+        // broadcast, limiting the rate of continuous updates.
+        let now = Instant::now();
+        while let Ok(msg) = cbnet.notif_rx.try_recv() {
+            if let Message::Small(SmallMessage::TransportData(transport)) = &msg
+                && transport.running != transport_running
+            {
+                transport_running = transport.running;
+                if transport_running {
+                    record_run(
+                        &mut run_log,
+                        RunEvent::TransportStarted { cue: cue_idx },
+                        &log_dispatcher,
+                    );
+                }
+            }
+            if let Message::Small(SmallMessage::BeatData(beat)) = &msg {
+                if beat.beat_idx != beat_idx {
+                    beat_time_us = chrono::Utc::now().timestamp_micros() as u64;
+                    #[cfg(feature = "i2c-ui")]
+                    if transport_running {
+                        crate::hardware::panel::beat();
+                    }
+                }
+                beat_idx = beat.beat_idx;
+                #[cfg(feature = "i2c-ui")]
+                {
+                    panel_counter = format!("{}.{}", beat.beat.bar_number, beat.beat.count);
+                }
+            }
+            if let Some(msg) = throttle.offer(msg, now) {
+                handlers.notify(msg);
+            }
+        }
+        for msg in throttle.take_due(now) {
+            handlers.notify(msg);
+        }
+
+        #[cfg(feature = "i2c-ui")]
+        if panel_shown
+            .as_ref()
+            .is_none_or(|(cue, beat, counter, entry)| {
+                (*cue, *beat, counter, entry.as_str())
+                    != (cue_idx, beat_idx, &panel_counter, cue_entry.typed())
+            })
+            && let Some(cue) = show.cues.get(cue_idx as usize)
+        {
+            crate::hardware::panel::update(crate::hardware::panel::PanelState {
+                cue_name: cue.metadata.name.str().to_string(),
+                counter: panel_counter.clone(),
+                beat: beat_idx,
+                beats: cue.get_beats().len() as u16,
+                entry: cue_entry.typed().to_string(),
+                flags: show_extension
+                    .get(cue)
+                    .map(|extension| extension.flags.clone())
+                    .unwrap_or_default(),
+            });
+            panel_shown = Some((
+                cue_idx,
+                beat_idx,
+                panel_counter.clone(),
+                cue_entry.typed().to_string(),
+            ));
+        }
+
+        // Let the venue script act on cue changes and beats
+        if scripted != Some((cue_idx, beat_idx))
+            && let Some(script) = &mut script
+            && let Some(cue) = show.cues.get(cue_idx as usize)
+        {
+            if scripted.is_some_and(|(scripted_cue, _)| scripted_cue == cue_idx) {
+                script.on_beat(cue_idx, beat_idx);
+            } else {
+                script.on_cue(
+                    cue_idx,
+                    cue.metadata.human_ident.str(),
+                    cue.metadata.name.str(),
+                );
+            }
+            scripted = Some((cue_idx, beat_idx));
+        }
+
+        // Preview the events ahead as the transport advances
+        if previewed != Some((cue_idx, beat_idx))
+            && let Some(cue) = show.cues.get(cue_idx as usize)
+        {
+            handlers.notify_core(&CoreMessage::UpcomingEvents {
+                cue: cue_idx,
+                beat: beat_idx,
+                events: upcoming_events(cue, beat_idx, UPCOMING_EVENTS),
+            });
+            let (position, accents) = bar_accents(cue, beat_idx);
+            handlers.notify_core(&CoreMessage::BarAccents {
+                position: position as u8,
+                accents,
+            });
+            previewed = Some((cue_idx, beat_idx));
+        }
+
+        // Keep backups and followers in step, also when nothing changes so they know this unit
+        // is alive
+        let state = MirrorState {
+            cue: cue_idx,
+            beat: beat_idx,
+            running: transport_running,
+            beat_time_us,
+        };
+        if mirrored.is_none_or(|(sent, at)| sent != state || at.elapsed() >= MIRROR_STATE_INTERVAL)
+        {
+            handlers.notify_core(&CoreMessage::MirrorState(state));
+            mirrored = Some((state, Instant::now()));
+        }
+
+        persist_config(&mut config_persistence, &config, &log_dispatcher);
+        persist_config(&mut core_config_persistence, &core_config, &log_dispatcher);
+
+        if last_heartbeat_time.elapsed().gt(&Duration::from_secs(1)) {
+            // Handlers are moved to a new address before anything is sent from them
+            if let Some(link) = link_monitor.poll() {
+                report_link(&link, &log_dispatcher);
+                if link.up {
+                    handlers.rebind(&log_dispatcher);
+                    if let Some(mirror) = &mut mirror {
+                        rebind_mirror(mirror, &log_dispatcher);
+                    }
+                }
+                handlers.notify_core(&CoreMessage::Link(link));
+            }
+            let heartbeat = Message::Small(SmallMessage::Heartbeat(Heartbeat {
+                common_version: StaticString::new(common::VERSION),
+                system_version: StaticString::new(VERSION),
+                system_time: chrono::Utc::now().timestamp() as u64,
+                cpu_use_audio: ah.get_cpu_use(),
+                process_freq_main: loop_count,
+            }));
+            handlers.notify(heartbeat);
+            // The heartbeat is defined in clicks-common and has no room for the name
+            handlers.notify_core(&CoreMessage::Identity {
+                name: core_config.device_name.clone(),
+            });
+            handlers.publish_subscribers();
+            if core_config.backup.is_none()
+                && let Some(cluster) = &core_config.cluster
+                && let Some(link) = &mirror
+            {
+                let timeout = Duration::from_millis(cluster.timeout_ms as u64);
+                handlers.notify_core(&CoreMessage::ClusterStatus(ClusterStatus {
+                    conductor: cluster.conductor,
+                    first_channel: cluster.first_channel,
+                    channel_count: cluster.channel_count,
+                    conductor_alive: link.leader_alive(Instant::now(), timeout),
+                    drift_us: cluster_drift_us,
+                }));
+            }
+
+            let (main_loop_freq, dsp) = cbnet.metrics.take_dsp_report();
+            let resources = resource_monitor.latest();
+            cbnet.notify_core(CoreMessage::Metrics(MetricsReport {
+                main_loop_freq,
+                dsp,
+                queue_depths: cbnet.queue_depths(),
+                handlers: handlers.take_packet_rates(),
+                input_peaks: cbnet.metrics.take_input_peaks(ah.num_inputs),
+                resources,
+            }));
+            let alarms = core_config.resource_limits.alarms(&resources);
+            if alarms != resource_alarms {
+                report_resource_alarms(&resource_alarms, &alarms, &log_dispatcher);
+                handlers.notify_core(&CoreMessage::ResourceAlarms(alarms.clone()));
+                resource_alarms = alarms;
+            }
+
+            last_heartbeat_time = Instant::now();
+            loop_count = 0;
+        }
+
+        // cap main loop rate to 500kHz to save CPU
+        if loop_count > 500_000 - 100_000 {
+            std::thread::sleep(
+                Duration::from_secs(1).saturating_sub(last_heartbeat_time.elapsed()) / 100_000,
+            );
+        }
+    }
+
+    if let Err(err) = config_persistence.flush(&config) {
+        boot::log_boot_error(&log_dispatcher, err);
+    }
+    if let Err(err) = core_config_persistence.flush(&core_config) {
+        boot::log_boot_error(&log_dispatcher, err);
+    }
+    let goodbye = match power_action {
+        None => "Shutdown. Goodnight.",
+        Some(PowerAction::Restart) => "Shutdown. Restarting.",
+        Some(PowerAction::Reboot) => "Shutdown. Rebooting.",
+    };
+    log_dispatcher.log(LogItem::new(
+        goodbye.to_string(),
+        LogContext::Boot,
+        LogKind::Note,
+    ));
+    handlers.notify(Message::Small(SmallMessage::ShutdownOccured));
+    ah.shutdown();
+    // The log is written out before the process is replaced or the unit goes down
+    log_dispatcher.shutdown();
+    match power_action {
+        None => {}
+        Some(PowerAction::Restart) => eprintln!("Could not restart: {}", power::restart()),
+        Some(PowerAction::Reboot) => {
+            if let Err(err) = power::reboot() {
+                eprintln!("Could not reboot: {err}");
+            }
+        }
+    }
+}
+
+/// Arms a restart or reboot for the requesting client, if it knows the passphrase, and replies
+/// with the code that confirms it.
+fn arm_power(
+    gate: &mut PowerGate,
+    action: PowerAction,
+    passphrase: Option<Passphrase>,
+    core_config: &CoreConfiguration,
+    source: &RequestSource,
+    log_dispatcher: &LogDispatcher,
+) -> Result<Option<CoreMessage>, String> {
+    if !power::authorized(core_config.power_passphrase.as_deref(), passphrase.as_ref()) {
+        return Err("wrong passphrase".to_string());
+    }
+    let code = gate.arm(action, source.clone(), Instant::now())?;
+    log_dispatcher.log(LogItem::new(
+        format!("{action:?} armed by {source}."),
+        LogContext::Boot,
+        LogKind::Note,
+    ));
+    Ok(Some(CoreMessage::ConfirmPower { action, code }))
+}
+
+/// Loads the venue script set in the core configuration, if any.
+fn load_script(
+    core_config: &CoreConfiguration,
+    log_dispatcher: &LogDispatcher,
+) -> Option<ScriptHost> {
+    let path = core_config.script.as_ref()?;
+    match ScriptHost::load(path) {
+        Ok(script) => {
+            log_dispatcher.log(LogItem::new(
+                format!("Loaded script {}.", path.display()),
+                LogContext::Boot,
+                LogKind::Note,
+            ));
+            Some(script)
+        }
+        Err(err) => {
+            log_dispatcher.log(LogItem::new(
+                format!("Could not load script {}: {err}", path.display()),
+                LogContext::Boot,
+                LogKind::Error,
+            ));
+            None
+        }
+    }
+}
+
+/// Logs what the script has logged since the last call, and returns the requests it has made.
+fn take_script_requests(
+    script: &mut ScriptHost,
+    log_dispatcher: &LogDispatcher,
+) -> Vec<(Request, RequestSource)> {
+    let effects = script.take_effects();
+    for effect in &effects {
+        if let ScriptEffect::Log(item) = effect {
+            log_dispatcher.log(item.clone());
+        }
+    }
+    ScriptHost::requests(&effects)
+}
+
+fn validation_context<'a>(
+    show: &'a Show,
+    cue_idx: u8,
+    standby: Option<u8>,
+    config: &SystemConfiguration,
+    core_config: &'a CoreConfiguration,
+    ah: &AudioHandler,
+) -> ValidationContext<'a> {
+    ValidationContext {
+        show,
+        cue_idx,
+        channel_count: config.channels.len(),
+        source_count: ah.num_sources,
+        port_count: ah.num_ports,
+        outputs: &core_config.outputs,
+        input_count: ah.num_inputs,
+        standby_cue: standby,
+        io_size: ah.io_size(),
+        input_io_size: ah.input_io_size(),
+        scenes: &core_config.scenes,
+        macros: &core_config.macros,
+        routing_presets: &core_config.routing_presets,
+        power_passphrase_set: core_config.power_passphrase.is_some(),
+    }
+}
+
+/// Recalls a stored scene: fades the gains, sets the mutes and reroutes the outputs, and tells
+/// all clients. Returns an error if some of the routing could not be changed.
+fn recall_scene(
+    name: &str,
+    fade_ms: f32,
+    config: &mut SystemConfiguration,
+    core_config: &mut CoreConfiguration,
+    ah: &mut AudioHandler,
+    cbnet: &CrossbeamNetwork,
+    handlers: &mut HandlerRegistry,
+) -> Result<(), String> {
+    let Some(scene) = core_config.scenes.get(name).cloned() else {
+        return Err(format!("there is no scene named '{name}'"));
+    };
+    for (channel, (config_channel, gain)) in
+        config.channels.iter_mut().zip(&scene.gains).enumerate()
+    {
+        config_channel.gain = *gain;
+        cbnet.command_core(CoreControlAction::FadeChannelGain {
+            channel: channel as u8,
+            gain: *gain,
+            fade_ms,
+        });
+    }
+    for (output, muted) in scene.muted.iter().enumerate() {
+        core_config.output_mut(output).muted = *muted;
+        cbnet.command(ControlAction::SetChannelMute(output as u8, *muted));
+    }
+    let mut routed = true;
+    if ah.io_size().is_some() {
+        routed = ah.route_to(&scene.routing);
+        core_config.routing = scene.routing.clone();
+    }
+
+    handlers.notify(Message::Large(LargeMessage::ConfigurationChanged(*config)));
+    notify_audio_status(handlers, ah, &core_config.outputs);
+    handlers.notify_core(&CoreMessage::SceneRecalled {
+        name: name.to_string(),
+    });
+    if !routed {
+        return Err(ROUTING_FAILED.to_string());
+    }
+    Ok(())
+}
+
+/// Send the JACK status, the output labels and settings and the capture input status to all
+/// handlers.
+fn notify_audio_status(
+    handlers: &mut HandlerRegistry,
+    ah: &mut AudioHandler,
+    outputs: &[OutputConfiguration],
+) {
+    handlers.notify(Message::Large(LargeMessage::JACKStateChanged(
+        ah.get_jack_status(),
+    )));
+    handlers.notify_core(&CoreMessage::Outputs(outputs.to_vec()));
+    handlers.notify_core(&CoreMessage::Inputs(ah.get_input_status()));
+    handlers.notify_core(&CoreMessage::Routing(ah.get_connections()));
+}
+
+fn persist_config(
+    persistence: &mut ConfigPersistence,
+    config: &impl PersistentConfig,
+    log_dispatcher: &LogDispatcher,
+) {
+    match persistence.tick(config) {
+        Ok(true) => {
+            log_dispatcher.log(LogItem::new(
+                "Saved configuration.".to_string(),
+                LogContext::Boot,
+                LogKind::Note,
+            ));
+        }
+        Ok(false) => {}
+        // Only the first failure of a streak is logged, the writes keep being retried
+        Err(err) if persistence.failures() == 1 => boot::log_boot_error(log_dispatcher, err),
+        Err(_) => {}
+    }
+}
+
+/// Moves on from a cue the transport ran past the end of, as far as the audio processor has not
+/// already done so.
+fn handle_cue_end(
+    cue: u8,
+    end: EndOfCue,
+    show: &Show,
+    pbh: &PlaybackHandler,
+    cbnet: &CrossbeamNetwork,
+    cue_idx: &mut u8,
+    standby: &mut Option<u8>,
+) {
+    // The processor stops, or loops, by itself
+    if matches!(end, EndOfCue::Hold | EndOfCue::Loop) {
+        return;
+    }
+    let next = cue + 1;
+    if next as usize >= show.cues.len() {
+        cbnet.command(ControlAction::TransportZero);
+        return;
+    }
+    match end {
+        EndOfCue::StandbyNext => {
+            cbnet.command(ControlAction::TransportZero);
+            *standby = Some(next);
+            pbh.prefetch(next);
+            cbnet.notify_core(CoreMessage::Standby(Some(next)));
+        }
+        EndOfCue::LoadNext | EndOfCue::ContinueNext => {
+            *cue_idx = next;
+            pbh.load_cue(next);
+            cbnet.command(ControlAction::LoadCueByIndex(next));
+            cbnet.command(ControlAction::TransportZero);
+            if end == EndOfCue::ContinueNext {
+                cbnet.command(ControlAction::TransportStart);
+            }
+        }
+        EndOfCue::Hold | EndOfCue::Loop => {}
+    }
+}
+
+/// Handles requests changing per-cue settings, which are stored in the show extension file.
+fn handle_cue_settings_request(
+    request: CoreRequest,
+    show: &Show,
+    show_path: &Path,
+    show_extension: &mut ShowExtension,
+    core_config: &mut CoreConfiguration,
+    core_config_persistence: &mut ConfigPersistence,
+) -> Result<(), String> {
+    match request {
+        CoreRequest::SetCueEnd { cue, end } => {
+            show_extension.cue_mut(&show.cues[cue as usize]).end = end;
+        }
+        CoreRequest::SetCueMarkers { cue, mut markers } => {
+            markers.sort_unstable();
+            markers.dedup();
+            show_extension.cue_mut(&show.cues[cue as usize]).markers = markers;
+        }
+        CoreRequest::SetZeroTarget {
+            cue: Some(cue),
+            target,
+        } => {
+            show_extension.cue_mut(&show.cues[cue as usize]).zero = target;
+        }
+        CoreRequest::SetZeroTarget { cue: None, target } => {
+            core_config.zero_target = target.unwrap_or_default();
+            core_config_persistence.mark_dirty();
+            return Ok(());
+        }
+        CoreRequest::SetCueScene { cue, scene } => {
+            show_extension.cue_mut(&show.cues[cue as usize]).scene = scene;
+        }
+        CoreRequest::SetCueColor { cue, color } => {
+            show_extension.cue_mut(&show.cues[cue as usize]).color = color;
+        }
+        CoreRequest::SetCueFlags { cue, mut flags } => {
+            flags.sort_unstable();
+            flags.dedup();
+            show_extension.cue_mut(&show.cues[cue as usize]).flags = flags;
+        }
+        CoreRequest::SetGainAutomation {
+            cue,
+            channel,
+            mut points,
+        } => {
+            points.sort_unstable_by(|point, other| point.beat.total_cmp(&other.beat));
+            let automation = &mut show_extension.cue_mut(&show.cues[cue as usize]).automation;
+            automation.retain(|automated| automated.channel != channel);
+            if !points.is_empty() {
+                automation.push(ChannelAutomation { channel, points });
+                automation.sort_unstable_by_key(|automated| automated.channel);
+            }
+        }
+        CoreRequest::SetMuteEvents { cue, mut events } => {
+            events.sort_by_key(|event| event.beat);
+            show_extension.cue_mut(&show.cues[cue as usize]).mutes = events;
+        }
+        CoreRequest::SetFadeEvents { cue, mut events } => {
+            events.sort_by_key(|event| event.beat);
+            show_extension.cue_mut(&show.cues[cue as usize]).fades = events;
+        }
+        CoreRequest::SetClickLevels {
+            cue: Some(cue),
+            levels,
+        } => {
+            show_extension
+                .cue_mut(&show.cues[cue as usize])
+                .click_levels = levels;
+        }
+        CoreRequest::SetClickLevels { cue: None, levels } => {
+            core_config.click_levels = levels.unwrap_or_default();
+            core_config_persistence.mark_dirty();
+            return Ok(());
+        }
+        CoreRequest::SetClickSamples {
+            cue: Some(cue),
+            samples,
+        } => {
+            show_extension
+                .cue_mut(&show.cues[cue as usize])
+                .click_samples = samples;
+        }
+        CoreRequest::SetCueClickSynthesis { cue, synthesis } => {
+            show_extension
+                .cue_mut(&show.cues[cue as usize])
+                .click_synthesis = synthesis;
+        }
+        _ => return Ok(()),
+    }
+    show_extension
+        .write(show_path)
+        .map_err(|err| err.to_string())
+}
+
+/// Handles the standby and go requests, which arm the next cue ahead of time and start it.
+fn handle_cue_request(
+    request: CoreRequest,
+    pbh: &PlaybackHandler,
+    cbnet: &CrossbeamNetwork,
+    cue_idx: &mut u8,
+    standby: &mut Option<u8>,
+) {
+    match request {
+        CoreRequest::Standby { cue } => {
+            *standby = Some(cue);
+            pbh.prefetch(cue);
+            cbnet.notify_core(CoreMessage::Standby(Some(cue)));
+        }
+        CoreRequest::CancelStandby => {
+            if standby.take().is_some() {
+                cbnet.notify_core(CoreMessage::Standby(None));
+            }
+        }
+        CoreRequest::Go => {
+            let Some(cue) = standby.take() else {
+                return;
+            };
+            *cue_idx = cue;
+            pbh.load_cue(cue);
+            cbnet.command(ControlAction::LoadCueByIndex(cue));
+            cbnet.command(ControlAction::TransportZero);
+            cbnet.command(ControlAction::TransportStart);
+            cbnet.notify_core(CoreMessage::Go { cue });
+            cbnet.notify_core(CoreMessage::Standby(None));
+        }
+        _ => {}
+    }
+}
+
+fn handle_core_request(
+    request: CoreRequest,
+    log_dispatcher: &LogDispatcher,
+    audit: &AuditTrail,
+    cbnet: &CrossbeamNetwork,
+    core_config: &mut CoreConfiguration,
+    core_config_persistence: &mut ConfigPersistence,
+    ah: &mut AudioHandler,
+) -> Result<Option<CoreMessage>, String> {
+    let reply = match request {
+        CoreRequest::LogTail {
+            count,
+            kind,
+            context,
+        } => Some(CoreMessage::LogTail(log_dispatcher.tail(
+            (count as usize).min(MAX_LOG_TAIL),
+            kind,
+            context,
+        ))),
+        CoreRequest::AuditTail { count } => Some(CoreMessage::AuditTail(
+            audit.recent((count as usize).min(MAX_LOG_TAIL)),
+        )),
+        CoreRequest::SetOutputEqBand {
+            output,
+            band,
+            settings,
+        } => {
+            let output_config = core_config.output_mut(output as usize);
+            output_config.eq.bands[band as usize] = settings;
+            cbnet.command_core(CoreControlAction::SetOutputEq {
+                output,
+                eq: output_config.eq,
+            });
+            core_config_persistence.mark_dirty();
+            cbnet.notify_core(CoreMessage::Outputs(core_config.outputs.clone()));
+            None
+        }
+        CoreRequest::SetOutputDelay { output, delay } => {
+            let output_config = core_config.output_mut(output as usize);
+            output_config.delay_ms = clamp_delay_ms(delay.as_ms());
+            cbnet.command_core(CoreControlAction::SetOutputDelay {
+                output,
+                delay_ms: output_config.delay_ms,
+            });
+            core_config_persistence.mark_dirty();
+            cbnet.notify_core(CoreMessage::Outputs(core_config.outputs.clone()));
+            None
+        }
+        CoreRequest::SetOutputLabel { output, label } => {
+            core_config.output_mut(output as usize).label = label;
+            core_config_persistence.mark_dirty();
+            cbnet.notify_core(CoreMessage::Outputs(core_config.outputs.clone()));
+            None
+        }
+        CoreRequest::SetOutputPolarity { output, invert } => {
+            core_config.output_mut(output as usize).invert = invert;
+            cbnet.command_core(CoreControlAction::SetOutputPolarity { output, invert });
+            core_config_persistence.mark_dirty();
+            cbnet.notify_core(CoreMessage::Outputs(core_config.outputs.clone()));
+            None
+        }
+        CoreRequest::SetPassThrough {
+            input,
+            output,
+            gain_db,
+        } => {
+            core_config
+                .output_mut(output as usize)
+                .set_pass_through(input, gain_db);
+            cbnet.command_core(CoreControlAction::SetPassThrough {
+                input,
+                output,
+                gain_db,
+            });
+            core_config_persistence.mark_dirty();
+            cbnet.notify_core(CoreMessage::Outputs(core_config.outputs.clone()));
+            None
+        }
+        CoreRequest::SetOutputMix {
+            output,
+            port,
+            gain_db,
+        } => {
+            core_config
+                .output_mut(output as usize)
+                .set_mix_send(port, gain_db);
+            cbnet.command_core(CoreControlAction::SetOutputMix {
+                output,
+                port,
+                gain_db,
+            });
+            core_config_persistence.mark_dirty();
+            cbnet.notify_core(CoreMessage::Outputs(core_config.outputs.clone()));
+            None
+        }
+        CoreRequest::GetOutputs => Some(CoreMessage::Outputs(core_config.outputs.clone())),
+        CoreRequest::ChangeInputRouting { from, to, connect } => {
+            let routed = ah.try_route_input(from, to, connect);
+            cbnet.notify_core(CoreMessage::Inputs(ah.get_input_status()));
+            if !routed {
+                return Err(ROUTING_FAILED.to_string());
+            }
+            None
+        }
+        CoreRequest::SetTempoFollow(config) => {
+            core_config.tempo_follow = config;
+            cbnet.command_core(CoreControlAction::SetTempoFollow(config));
+            core_config_persistence.mark_dirty();
+            cbnet.notify_core(CoreMessage::TempoFollow(config));
+            None
+        }
+        CoreRequest::SetTimecodeOutput(output) => {
+            core_config.timecode_output = output;
+            cbnet.command_core(CoreControlAction::SetTimecodeOutput(output));
+            core_config_persistence.mark_dirty();
+            cbnet.notify_core(CoreMessage::TimecodeOutput(output));
+            None
+        }
+        CoreRequest::SetGainRamp { ramp_ms } => {
+            core_config.gain_ramp_ms = ramp_ms;
+            cbnet.command_core(CoreControlAction::SetGainRamp { ramp_ms });
+            core_config_persistence.mark_dirty();
+            None
+        }
+        CoreRequest::SetStopFade { fade_ms } => {
+            core_config.stop_fade_ms = fade_ms;
+            cbnet.command_core(CoreControlAction::SetStopFade { fade_ms });
+            core_config_persistence.mark_dirty();
+            None
+        }
+        CoreRequest::SetJumpMode { on } => {
+            cbnet.command_core(CoreControlAction::SetJumpMode(on));
+            None
+        }
+        CoreRequest::TransportStartWithCountIn { bars } => {
+            cbnet.command_core(CoreControlAction::StartWithCountIn { bars });
+            None
+        }
+        CoreRequest::StopAllPlayback { fade_ms } => {
+            cbnet.command_core(CoreControlAction::StopAllPlayback {
+                fade_ms: fade_ms.unwrap_or(core_config.stop_fade_ms),
+            });
+            None
+        }
+        CoreRequest::JamTimecode(time) => {
+            cbnet.command_core(CoreControlAction::JamTimecode(time));
+            None
+        }
+        CoreRequest::StopPlaybackChannel { channel, fade_ms } => {
+            cbnet.command_core(CoreControlAction::StopPlaybackChannel {
+                channel,
+                fade_ms: fade_ms.unwrap_or(core_config.stop_fade_ms),
+            });
+            None
+        }
+        // The version is kept by the communication handler, see `validate_core`
+        CoreRequest::Hello { .. } => Some(CoreMessage::Hello {
+            protocol_version: PROTOCOL_VERSION,
+            core_version: VERSION.to_string(),
+            device_name: core_config.device_name.clone(),
+        }),
+        CoreRequest::SetDeviceName { name } => {
+            core_config.device_name = name;
+            core_config_persistence.mark_dirty();
+            cbnet.notify_core(CoreMessage::Identity {
+                name: core_config.device_name.clone(),
+            });
+            None
+        }
+        CoreRequest::SetBeatPhaseRate(rate_hz) => {
+            core_config.beat_phase_rate = rate_hz;
+            cbnet.command_core(CoreControlAction::SetBeatPhaseRate(rate_hz));
+            core_config_persistence.mark_dirty();
+            None
+        }
+        CoreRequest::SetNotificationRates(rates) => {
+            core_config.notification_rates = rates;
+            core_config_persistence.mark_dirty();
+            cbnet.notify_core(CoreMessage::NotificationRates(rates));
+            None
+        }
+        CoreRequest::GetNotificationRates => Some(CoreMessage::NotificationRates(
+            core_config.notification_rates,
+        )),
+        CoreRequest::DeleteScene { name } => {
+            core_config.scenes.remove(&name);
+            core_config_persistence.mark_dirty();
+            cbnet.notify_core(CoreMessage::Scenes(
+                core_config.scenes.keys().cloned().collect(),
+            ));
+            None
+        }
+        CoreRequest::GetScenes => Some(CoreMessage::Scenes(
+            core_config.scenes.keys().cloned().collect(),
+        )),
+        CoreRequest::StoreRoutingPreset { name } => {
+            let routing = if ah.io_size().is_some() {
+                ah.get_connections()
+            } else {
+                core_config.routing.clone()
+            };
+            core_config.routing_presets.insert(name, routing);
+            core_config_persistence.mark_dirty();
+            cbnet.notify_core(CoreMessage::RoutingPresets(
+                core_config.routing_presets.keys().cloned().collect(),
+            ));
+            None
+        }
+        CoreRequest::LoadRoutingPreset { name } => {
+            let Some(routing) = core_config.routing_presets.get(&name).cloned() else {
+                return Err(format!("there is no routing preset named '{name}'"));
+            };
+            // Put back at the next start while audio is not running
+            if ah.io_size().is_none() {
+                core_config.routing = routing;
+                core_config_persistence.mark_dirty();
+                return Ok(None);
+            }
+            let routed = ah.route_to(&routing);
+            core_config.routing = routing;
+            core_config_persistence.mark_dirty();
+            cbnet.notify_core(CoreMessage::Routing(ah.get_connections()));
+            if !routed {
+                return Err(ROUTING_FAILED.to_string());
+            }
+            None
+        }
+        CoreRequest::DeleteRoutingPreset { name } => {
+            core_config.routing_presets.remove(&name);
+            core_config_persistence.mark_dirty();
+            cbnet.notify_core(CoreMessage::RoutingPresets(
+                core_config.routing_presets.keys().cloned().collect(),
+            ));
+            None
+        }
+        CoreRequest::GetRoutingPresets => Some(CoreMessage::RoutingPresets(
+            core_config.routing_presets.keys().cloned().collect(),
+        )),
+        CoreRequest::LearnMidi(action) => {
+            cbnet.notify_core(CoreMessage::MidiLearn(action));
+            None
+        }
+        CoreRequest::MapMidi { control, action } => {
+            core_config
+                .midi_mappings
+                .retain(|mapping| mapping.control != control);
+            if let Some(action) = action {
+                core_config
+                    .midi_mappings
+                    .push(MidiMapping { control, action });
+            }
+            core_config_persistence.mark_dirty();
+            cbnet.notify_core(CoreMessage::MidiMappings(core_config.midi_mappings.clone()));
+            // A learnt control ends learning
+            cbnet.notify_core(CoreMessage::MidiLearn(None));
+            None
+        }
+        CoreRequest::GetMidiMappings => {
+            Some(CoreMessage::MidiMappings(core_config.midi_mappings.clone()))
+        }
+        CoreRequest::SetMacro { name, steps } => {
+            match steps {
+                Some(steps) => core_config.macros.insert(name, steps),
+                None => core_config.macros.remove(&name),
+            };
+            core_config_persistence.mark_dirty();
+            cbnet.notify_core(CoreMessage::Macros(core_config.macros.clone()));
+            None
+        }
+        CoreRequest::GetMacros => Some(CoreMessage::Macros(core_config.macros.clone())),
+        // Cue requests are handled in the main loop
+        CoreRequest::SetCueEnd { .. }
+        | CoreRequest::SetZeroTarget { .. }
+        | CoreRequest::SetCueMarkers { .. }
+        | CoreRequest::SetCueScene { .. }
+        | CoreRequest::SetClickLevels { .. }
+        | CoreRequest::SetCueColor { .. }
+        | CoreRequest::SetCueFlags { .. }
+        | CoreRequest::SetGainAutomation { .. }
+        | CoreRequest::SetMuteEvents { .. }
+        | CoreRequest::SetFadeEvents { .. }
+        | CoreRequest::SetClickSamples { cue: Some(_), .. }
+        | CoreRequest::SetCueClickSynthesis { .. }
+        | CoreRequest::Standby { .. }
+        | CoreRequest::CancelStandby
+        | CoreRequest::Go => None,
+        // The run log is kept by the main loop
+        CoreRequest::RunLog { .. } | CoreRequest::NewPerformance => None,
+        // Running macros are kept by the main loop
+        CoreRequest::RunMacro { .. } | CoreRequest::StopMacro { .. } => None,
+        // The failover role is kept by the main loop, mirrors by the binary protocol handler
+        CoreRequest::TakeOver
+        | CoreRequest::HandBack
+        | CoreRequest::GetFailoverRole
+        | CoreRequest::Mirror => None,
+        // Replied in chunks by the main loop
+        CoreRequest::ExportProtocolSchema => None,
+        // Need the loaded show or its directory, handled in the main loop
+        CoreRequest::ExportTimingReport(_)
+        | CoreRequest::DismissShowChange
+        | CoreRequest::GetCues
+        | CoreRequest::ListShows
+        | CoreRequest::LoadShowByName { .. }
+        | CoreRequest::LocateTimecode(_)
+        | CoreRequest::GetShow
+        | CoreRequest::AllStop
+        | CoreRequest::TransportStopQuantized
+        | CoreRequest::LaunchCue { .. } => None,
+        // The clicks are synthesized by the main loop
+        CoreRequest::SetClickSynthesis(_) => None,
+        CoreRequest::GetClickSynthesis => {
+            Some(CoreMessage::ClickSynthesis(core_config.click_synthesis))
+        }
+        // Read by the main loop, which is up to date on the next pass
+        CoreRequest::SetClickSamples { cue: None, samples } => {
+            core_config.click_samples = samples.unwrap_or_default();
+            core_config_persistence.mark_dirty();
+            cbnet.notify_core(CoreMessage::ClickSamples(core_config.click_samples.clone()));
+            None
+        }
+        CoreRequest::GetClickSamples => {
+            Some(CoreMessage::ClickSamples(core_config.click_samples.clone()))
+        }
+        // Carried out once the main loop has shut down
+        CoreRequest::Restart { .. }
+        | CoreRequest::Reboot { .. }
+        | CoreRequest::ConfirmPower { .. } => None,
+        // Need the system configuration, handled in the main loop
+        CoreRequest::StoreScene { .. } | CoreRequest::RecallScene { .. } => None,
+        CoreRequest::GetTempoFollow => Some(CoreMessage::TempoFollow(core_config.tempo_follow)),
+        CoreRequest::GetTimecodeOutput => {
+            Some(CoreMessage::TimecodeOutput(core_config.timecode_output))
+        }
+        CoreRequest::GetInputs => Some(CoreMessage::Inputs(ah.get_input_status())),
+        CoreRequest::SetProfiling { on } => {
+            cbnet.profiler.set_enabled(on);
+            log_dispatcher.log(LogItem::new(
+                format!(
+                    "Process profiling {}.",
+                    if on { "started" } else { "stopped" }
+                ),
+                LogContext::AudioHandler,
+                LogKind::Note,
+            ));
+            None
+        }
+        CoreRequest::DumpProfile => {
+            let path = Path::new(LOG_DIR).join("profile.json");
+            cbnet
+                .profiler
+                .report()
+                .write(&path)
+                .map_err(|err| err.to_string())?;
+            Some(CoreMessage::ProfileDumped {
+                path: path.display().to_string(),
+            })
+        }
+    };
+    Ok(reply)
+}
+
+/// Starts the run log of a new performance of `show`.
+fn start_performance(run_log: &mut RunLog, show: &Show, log_dispatcher: &LogDispatcher) {
+    if let Err(err) = run_log.start_performance(show.metadata.name.str().to_string()) {
+        log_dispatcher.log(LogItem::new(
+            format!("Could not open run log, keeping it in memory only: {err}"),
+            LogContext::Logger,
+            LogKind::Warning,
+        ));
+    }
+}
+
+/// Moves the link of a backup or cluster follower to the new address of the network interface,
+/// along with the handlers, so it keeps hearing its leader.
+fn rebind_mirror(mirror: &mut MirrorLink, log_dispatcher: &LogDispatcher) {
+    match mirror.rebind(Instant::now()) {
+        Ok(Some(address)) => log_dispatcher.log(LogItem::new(
+            format!("moved failover link to {address}"),
+            LogContext::Network,
+            LogKind::Note,
+        )),
+        Ok(None) => {}
+        Err(err) => log_dispatcher.log(LogItem::new(
+            format!("Could not move failover link to the new address: {err}"),
+            LogContext::Network,
+            LogKind::Error,
+        )),
+    }
+}
+
+/// Makes a backup unit take over from its primary: it stops mirroring and its outputs are
+/// unmuted. Returns false if the unit is not a backup.
+fn take_over(
+    mirror: &mut Option<MirrorLink>,
+    role: &mut FailoverRole,
+    cue_idx: u8,
+    cbnet: &CrossbeamNetwork,
+    handlers: &mut HandlerRegistry,
+    run_log: &mut RunLog,
+    log_dispatcher: &LogDispatcher,
+) -> bool {
+    if !matches!(role, FailoverRole::Backup { .. }) || mirror.take().is_none() {
+        return false;
+    }
+    cbnet.command_core(CoreControlAction::MuteOutputs(false));
+    *role = FailoverRole::TookOver;
+    log_dispatcher.log(LogItem::new(
+        "Took over from the primary.".to_string(),
+        LogContext::Network,
+        LogKind::Warning,
+    ));
+    record_run(run_log, RunEvent::TookOver { cue: cue_idx }, log_dispatcher);
+    handlers.notify_core(&CoreMessage::FailoverRole(*role));
+    true
+}
+
+/// Makes a unit that took over the backup of its primary again: its outputs are muted and it
+/// mirrors the primary once heard. Returns false if the unit has not taken over.
+fn hand_back(
+    primary: SocketAddr,
+    mirror: &mut Option<MirrorLink>,
+    role: &mut FailoverRole,
+    cbnet: &CrossbeamNetwork,
+    handlers: &mut HandlerRegistry,
+    log_dispatcher: &LogDispatcher,
+) -> bool {
+    if *role != FailoverRole::TookOver {
+        return false;
+    }
+    *mirror = Some(MirrorLink::new(primary));
+    cbnet.command_core(CoreControlAction::MuteOutputs(true));
+    *role = FailoverRole::Backup {
+        primary_alive: false,
+    };
+    log_dispatcher.log(LogItem::new(
+        "Handed control back to the primary.".to_string(),
+        LogContext::Network,
+        LogKind::Note,
+    ));
+    handlers.notify_core(&CoreMessage::FailoverRole(*role));
+    true
+}
+
+fn record_run(run_log: &mut RunLog, event: RunEvent, log_dispatcher: &LogDispatcher) {
+    if let Err(err) = run_log.record(event) {
+        log_dispatcher.log(LogItem::new(
+            format!("Could not write run log: {err}"),
+            LogContext::Logger,
+            LogKind::Warning,
+        ));
+    }
+}
+
+fn load_show_extension(show_path: &Path, log_dispatcher: &LogDispatcher) -> ShowExtension {
+    ShowExtension::load(show_path).unwrap_or_else(|err| {
+        boot::log_boot_error(log_dispatcher, err);
+        ShowExtension::default()
+    })
+}
+
+/// Logs a change of the network interface.
+fn report_link(link: &LinkStatus, log_dispatcher: &LogDispatcher) {
+    let interface = link.interface.as_deref().unwrap_or("unknown interface");
+    log_dispatcher.log(match (link.up, link.address) {
+        (true, Some(address)) => LogItem::new(
+            format!("Network is up on {interface} as {address}."),
+            LogContext::Network,
+            LogKind::Note,
+        ),
+        _ => LogItem::new(
+            format!("Network is down on {interface}."),
+            LogContext::Network,
+            LogKind::Warning,
+        ),
+    });
+    #[cfg(feature = "i2c-ui")]
+    let _ = crate::hardware::display::network_status(link.up);
+}
+
+/// Logs the alarms raised and cleared since the last heartbeat. A full disk otherwise only shows
+/// as recordings and logs that quietly fail to be written.
+fn report_resource_alarms(
+    previous: &[ResourceAlarm],
+    alarms: &[ResourceAlarm],
+    log_dispatcher: &LogDispatcher,
+) {
+    for alarm in alarms.iter().filter(|alarm| !previous.contains(alarm)) {
+        log_dispatcher.log(LogItem::new(
+            format!("{alarm}."),
+            LogContext::Boot,
+            LogKind::Warning,
+        ));
+        #[cfg(feature = "i2c-ui")]
+        let _ = crate::hardware::display::warning(&alarm.to_string());
+    }
+    for alarm in previous.iter().filter(|alarm| !alarms.contains(alarm)) {
+        log_dispatcher.log(LogItem::new(
+            format!("Cleared: {alarm}."),
+            LogContext::Boot,
+            LogKind::Note,
+        ));
+    }
+}
+
+/// Logs the outcome of checking the loaded show against its manifest and tells all clients, so
+/// missing or damaged media are known before the show starts.
+fn report_show_verification(
+    result: Result<Option<ManifestReport>, String>,
+    log_dispatcher: &LogDispatcher,
+    handlers: &mut HandlerRegistry,
+) {
+    let report = match result {
+        Ok(Some(report)) => report,
+        Ok(None) => {
+            log_dispatcher.log(LogItem::new(
+                "Show has no manifest, its files were not checked.".to_string(),
+                LogContext::Boot,
+                LogKind::Note,
+            ));
+            return;
+        }
+        Err(err) => {
+            log_dispatcher.log(LogItem::new(
+                format!("Could not check show against its manifest: {err}"),
+                LogContext::Boot,
+                LogKind::Error,
+            ));
+            return;
+        }
+    };
+    if report.faults.is_empty() {
+        log_dispatcher.log(LogItem::new(
+            format!("All {} show files match the manifest.", report.checked),
+            LogContext::Boot,
+            LogKind::Note,
+        ));
+    } else {
+        for fault in &report.faults {
+            log_dispatcher.log(LogItem::new(
+                format!("Show file {fault}"),
+                LogContext::Boot,
+                LogKind::Error,
+            ));
+        }
+        #[cfg(feature = "i2c-ui")]
+        let _ = crate::hardware::display::show_damaged(report.faults.len());
+    }
+    handlers.notify_core(&CoreMessage::ShowVerified(report));
+}
+
+/// Reads the show of a show directory, logging the outcome.
+fn try_load_show(show_path: &Path, log_dispatcher: &LogDispatcher) -> Result<Show, BootError> {
+    match compiled_show::load(show_path) {
+        Ok(LoadedShow {
+            show,
+            compiled,
+            compile_error,
+        }) => {
+            log_dispatcher.log_with_fields(
+                LogItem::new(
+                    format!(
+                        "Successfully loaded {} show with {} cues",
+                        if compiled { "compiled" } else { "parsed" },
+                        show.cues.len()
+                    ),
+                    LogContext::Boot,
+                    LogKind::Note,
+                ),
+                &[
+                    ("show", show.metadata.name.str().to_string()),
+                    ("cues", show.cues.len().to_string()),
+                ],
+            );
+            if let Some(err) = compile_error {
+                log_dispatcher.log(LogItem::new(
+                    format!("Could not compile show, it will be parsed again next time: {err}"),
+                    LogContext::Boot,
+                    LogKind::Warning,
+                ));
+            }
+
+            #[cfg(feature = "i2c-ui")]
+            let _ = crate::hardware::display::show_load_success(&show);
+
+            Ok(show)
+        }
+        Err(err) => {
+            log_dispatcher.log(LogItem::new(
+                format!("Failed to load show: {:?}", err),
+                LogContext::Boot,
+                LogKind::Error,
+            ));
+            println!("show load err {:?}", err);
+
+            #[cfg(feature = "i2c-ui")]
+            let _ = crate::hardware::display::show_load_failure(&err.to_string());
+            Err(err)
+        }
+    }
+}
+
+/// Reads the show of a show directory, or an example show if it cannot be read.
+fn load_show(show_path: &Path, log_dispatcher: &LogDispatcher) -> Show {
+    try_load_show(show_path, log_dispatcher).unwrap_or_else(|_| {
+        let mut show = Show::default();
+        show.cues.push(Cue::example());
+        show.cues[0].events.pop(0);
+        show
+    })
+}
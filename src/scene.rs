@@ -0,0 +1,79 @@
+use crate::{
+    audio::handler::{RouteMask, routing_changes},
+    config::CoreConfiguration,
+};
+use common::local::config::SystemConfiguration;
+use serde::{Deserialize, Serialize};
+
+/// Snapshot of the mixer: gains, mutes and output routing, so e.g. soundcheck and show settings
+/// can be swapped at once.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Scene {
+    /// Gain in dB of every channel, by index
+    pub gains: Vec<f32>,
+    /// Mute of every output, by index
+    pub muted: Vec<bool>,
+    /// System ports every output is connected to, as in `CoreMessage::Routing`. Empty if the
+    /// scene was stored while audio was not running, in which case recalling it leaves the
+    /// routing alone.
+    pub routing: Vec<RouteMask>,
+}
+
+impl Scene {
+    /// Captures the current mixer state. `connections` holds the routing of the running client
+    /// outputs only.
+    pub fn capture(
+        config: &SystemConfiguration,
+        core_config: &CoreConfiguration,
+        connections: &[RouteMask],
+    ) -> Self {
+        Self {
+            gains: config.channels.iter().map(|channel| channel.gain).collect(),
+            muted: core_config
+                .outputs
+                .iter()
+                .map(|output| output.muted)
+                .collect(),
+            routing: connections.to_vec(),
+        }
+    }
+
+    /// Connections to change to get from `current` to the routing of the scene, as
+    /// (output, system port, connect).
+    pub fn routing_changes(
+        &self,
+        current: &[RouteMask],
+        system_ports: usize,
+    ) -> Vec<(u8, u8, bool)> {
+        routing_changes(&self.routing, current, system_ports)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn routing_changes() {
+        let scene = Scene {
+            routing: vec![0b01, 0b10],
+            ..Default::default()
+        };
+        assert_eq!(
+            scene.routing_changes(&[0b11, 0b00, 0b11], 2),
+            vec![(0, 1, false), (1, 1, true)]
+        );
+        // Ports beyond the first 32
+        let wide = Scene {
+            routing: vec![1 << 40],
+            ..Default::default()
+        };
+        assert_eq!(
+            wide.routing_changes(&[1 << 63], 64),
+            vec![(0, 40, true), (0, 63, false)]
+        );
+        // Stored without audio running
+        assert!(Scene::default().routing_changes(&[0b11], 2).is_empty());
+    }
+}
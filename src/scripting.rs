@@ -0,0 +1,310 @@
+use crate::communication::interface::RequestSource;
+use common::{
+    local::config::{LogContext, LogItem, LogKind},
+    protocol::request::{ControlAction, Request},
+};
+use rhai::{AST, Array, CallFnOptions, Dynamic, Engine, FuncArgs, Scope};
+use rosc::{OscMessage, OscPacket, OscType};
+use std::{
+    cell::RefCell,
+    net::{SocketAddr, UdpSocket},
+    path::Path,
+    rc::Rc,
+};
+
+/// Most operations a hook may run, so a runaway script cannot stall the main loop
+const MAX_OPERATIONS: u64 = 100_000;
+
+/// Something a hook asked for, carried out by the main loop after the hook returns.
+#[derive(Debug, Clone)]
+pub enum ScriptEffect {
+    Action(ControlAction),
+    Log(LogItem),
+}
+
+/// Venue-specific glue logic in a Rhai script, run on the main loop and never on the RT thread.
+///
+/// The script defines any of these hooks as functions:
+/// - `on_cue(index, ident, name)` when a cue is loaded
+/// - `on_beat(cue, beat)` when the transport reaches a beat, to act on milestones
+/// - `on_request(handler, request)` for each accepted request, described as in the audit trail
+///
+/// Hooks act through `start()`, `stop()`, `zero()`, `load_cue(index)`, `next_cue()`,
+/// `previous_cue()`, `seek(beat)`, `set_gain(channel, db)` and `mute(channel, muted)`, which are
+/// requested like any other control, `osc(address, path, args)` to send an OSC message to
+/// another device, and `log(message)`.
+pub struct ScriptHost {
+    engine: Engine,
+    ast: AST,
+    scope: Scope<'static>,
+    effects: Rc<RefCell<Vec<ScriptEffect>>>,
+}
+
+impl ScriptHost {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let source = std::fs::read_to_string(path).map_err(|err| err.to_string())?;
+        Self::new(&source)
+    }
+
+    /// Compiles the script and runs its top level once. Hooks are called without running it
+    /// again.
+    pub fn new(source: &str) -> Result<Self, String> {
+        let effects = Rc::new(RefCell::new(vec![]));
+        let mut engine = Engine::new();
+        engine.set_max_operations(MAX_OPERATIONS);
+        register_effects(&mut engine, &effects);
+
+        let ast = engine.compile(source).map_err(|err| err.to_string())?;
+        let mut scope = Scope::new();
+        engine
+            .run_ast_with_scope(&mut scope, &ast)
+            .map_err(|err| err.to_string())?;
+        Ok(Self {
+            engine,
+            ast,
+            scope,
+            effects,
+        })
+    }
+
+    pub fn on_cue(&mut self, cue: u8, ident: &str, name: &str) {
+        self.call("on_cue", (cue as i64, ident.to_string(), name.to_string()));
+    }
+
+    pub fn on_beat(&mut self, cue: u8, beat: u16) {
+        self.call("on_beat", (cue as i64, beat as i64));
+    }
+
+    pub fn on_request(&mut self, source: &RequestSource, request: &str) {
+        // Requests made by the script itself would call back into it
+        if source.handler == SCRIPT_HANDLER {
+            return;
+        }
+        self.call(
+            "on_request",
+            (source.handler.to_string(), request.to_string()),
+        );
+    }
+
+    /// What the hooks have asked for since the last call.
+    pub fn take_effects(&mut self) -> Vec<ScriptEffect> {
+        self.effects.take()
+    }
+
+    /// Where requests made by the script come from, for validation and the audit trail.
+    pub fn source() -> RequestSource {
+        RequestSource {
+            handler: SCRIPT_HANDLER,
+            handler_index: None,
+            address: SocketAddr::from(([0, 0, 0, 0], 0)),
+            identifier: None,
+        }
+    }
+
+    /// The requests among `effects`.
+    pub fn requests(effects: &[ScriptEffect]) -> Vec<(Request, RequestSource)> {
+        effects
+            .iter()
+            .filter_map(|effect| match effect {
+                ScriptEffect::Action(action) => {
+                    Some((Request::ControlAction(*action), Self::source()))
+                }
+                ScriptEffect::Log(_) => None,
+            })
+            .collect()
+    }
+
+    fn call(&mut self, hook: &str, args: impl FuncArgs) {
+        if !self
+            .ast
+            .iter_functions()
+            .any(|function| function.name == hook)
+        {
+            return;
+        }
+        let options = CallFnOptions::new().eval_ast(false);
+        if let Err(err) = self.engine.call_fn_with_options::<Dynamic>(
+            options,
+            &mut self.scope,
+            &self.ast,
+            hook,
+            args,
+        ) {
+            self.effects
+                .borrow_mut()
+                .push(ScriptEffect::Log(LogItem::new(
+                    format!("Script hook {hook} failed: {err}"),
+                    LogContext::Transport,
+                    LogKind::Warning,
+                )));
+        }
+    }
+}
+
+/// Name of the script as a request source
+const SCRIPT_HANDLER: &str = "script";
+
+fn register_effects(engine: &mut Engine, effects: &Rc<RefCell<Vec<ScriptEffect>>>) {
+    let actions: [(&str, ControlAction); 5] = [
+        ("start", ControlAction::TransportStart),
+        ("stop", ControlAction::TransportStop),
+        ("zero", ControlAction::TransportZero),
+        ("next_cue", ControlAction::LoadNextCue),
+        ("previous_cue", ControlAction::LoadPreviousCue),
+    ];
+    for (name, action) in actions {
+        let effects = effects.clone();
+        engine.register_fn(name, move || {
+            effects.borrow_mut().push(ScriptEffect::Action(action));
+        });
+    }
+
+    let push = {
+        let effects = effects.clone();
+        move |effect: ScriptEffect| effects.borrow_mut().push(effect)
+    };
+    let out_of_range = |what: &str, value: i64| {
+        ScriptEffect::Log(LogItem::new(
+            format!("Script asked for {what} {value}, which does not exist"),
+            LogContext::Transport,
+            LogKind::Warning,
+        ))
+    };
+
+    let emit = push.clone();
+    engine.register_fn("load_cue", move |cue: i64| {
+        emit(match u8::try_from(cue) {
+            Ok(cue) => ScriptEffect::Action(ControlAction::LoadCueByIndex(cue)),
+            Err(_) => out_of_range("cue", cue),
+        })
+    });
+    let emit = push.clone();
+    engine.register_fn("seek", move |beat: i64| {
+        emit(match u16::try_from(beat) {
+            Ok(beat) => ScriptEffect::Action(ControlAction::TransportSeekBeat(beat)),
+            Err(_) => out_of_range("beat", beat),
+        })
+    });
+    let emit = push.clone();
+    engine.register_fn("set_gain", move |channel: i64, gain_db: f64| {
+        emit(match u8::try_from(channel) {
+            Ok(channel) => {
+                ScriptEffect::Action(ControlAction::SetChannelGain(channel, gain_db as f32))
+            }
+            Err(_) => out_of_range("channel", channel),
+        })
+    });
+    let emit = push.clone();
+    engine.register_fn("mute", move |channel: i64, muted: bool| {
+        emit(match u8::try_from(channel) {
+            Ok(channel) => ScriptEffect::Action(ControlAction::SetChannelMute(channel, muted)),
+            Err(_) => out_of_range("channel", channel),
+        })
+    });
+    let emit = push.clone();
+    engine.register_fn("log", move |message: &str| {
+        emit(ScriptEffect::Log(LogItem::new(
+            message.to_string(),
+            LogContext::Transport,
+            LogKind::Note,
+        )))
+    });
+
+    let socket = UdpSocket::bind(("0.0.0.0", 0)).ok();
+    let emit = push;
+    engine.register_fn("osc", move |target: &str, path: &str, args: Array| {
+        if let Err(err) = send_osc(socket.as_ref(), target, path, args) {
+            emit(ScriptEffect::Log(LogItem::new(
+                format!("Script could not send {path} to {target}: {err}"),
+                LogContext::Network,
+                LogKind::Warning,
+            )));
+        }
+    });
+}
+
+fn send_osc(
+    socket: Option<&UdpSocket>,
+    target: &str,
+    path: &str,
+    args: Array,
+) -> Result<(), String> {
+    let socket = socket.ok_or("no socket")?;
+    let target: SocketAddr = target.parse().map_err(|_| "not an address")?;
+    let args = args
+        .into_iter()
+        .map(|arg| {
+            if let Ok(int) = arg.as_int() {
+                Ok(OscType::Int(int as i32))
+            } else if let Ok(float) = arg.as_float() {
+                Ok(OscType::Float(float as f32))
+            } else if let Ok(bool) = arg.as_bool() {
+                Ok(OscType::Bool(bool))
+            } else if arg.is_string() {
+                Ok(OscType::String(arg.to_string()))
+            } else {
+                Err(format!("cannot send {} over OSC", arg.type_name()))
+            }
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let packet = OscPacket::Message(OscMessage {
+        addr: path.to_string(),
+        args,
+    });
+    let buffer = rosc::encoder::encode(&packet).map_err(|err| err.to_string())?;
+    socket
+        .send_to(&buffer, target)
+        .map_err(|err| err.to_string())?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hooks() {
+        let mut host = ScriptHost::new(
+            r#"
+            fn on_beat(cue, beat) {
+                if beat == 16 { next_cue(); }
+            }
+            fn on_cue(index, ident, name) {
+                if ident == "7" { set_gain(2, -6.0); load_cue(300); }
+            }
+            "#,
+        )
+        .unwrap();
+        host.on_request(&RequestSource::front_panel(), "TransportStart");
+        host.on_beat(0, 15);
+        assert!(host.take_effects().is_empty());
+
+        host.on_cue(6, "7", "Finale");
+        let effects = host.take_effects();
+        // The cue does not exist, which is logged instead
+        assert!(matches!(
+            effects.as_slice(),
+            [
+                ScriptEffect::Action(ControlAction::SetChannelGain(2, _)),
+                ScriptEffect::Log(_)
+            ]
+        ));
+        assert_eq!(
+            ScriptHost::requests(&effects),
+            vec![(
+                Request::ControlAction(ControlAction::SetChannelGain(2, -6.0)),
+                ScriptHost::source()
+            )]
+        );
+    }
+
+    #[test]
+    fn runaway_hook() {
+        let mut host = ScriptHost::new("fn on_beat(cue, beat) { loop {} }").unwrap();
+        host.on_beat(0, 1);
+        assert!(matches!(
+            host.take_effects().as_slice(),
+            [ScriptEffect::Log(_)]
+        ));
+    }
+}
@@ -0,0 +1,132 @@
+use common::cue::Show;
+use flate2::{Compression, read::DeflateDecoder, write::DeflateEncoder};
+use serde::{Deserialize, Serialize};
+use std::io::{Read, Write};
+
+/// Most bytes of one chunk of a show dump encoded as JSON, so every chunk fits a datagram on
+/// all handlers. As a JSON array of numbers, every compressed byte takes up to four bytes.
+pub const SHOW_DUMP_CHUNK_SIZE: usize = 16 * 1024;
+/// Bytes of a chunk encoded as JSON besides its data, with room for the message around it
+const CHUNK_OVERHEAD: usize = 64;
+
+/// One part of a dump of the loaded show. The dump is the postcard encoded `Show`, deflate
+/// compressed and split into `count` chunks, sent in order of `index`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ShowDumpChunk {
+    pub index: u16,
+    pub count: u16,
+    pub data: Vec<u8>,
+}
+
+/// The show as it is held in memory, in chunks.
+pub fn dump(show: &Show) -> Result<Vec<ShowDumpChunk>, String> {
+    let encoded = postcard::to_stdvec(show).map_err(|err| err.to_string())?;
+    split(&compress(&encoded), SHOW_DUMP_CHUNK_SIZE)
+}
+
+/// `data` deflate compressed. Shows are small, so the best compression costs little.
+fn compress(data: &[u8]) -> Vec<u8> {
+    let mut encoder = DeflateEncoder::new(Vec::new(), Compression::best());
+    encoder
+        .write_all(data)
+        .expect("Writing to a Vec cannot fail");
+    encoder.finish().expect("Writing to a Vec cannot fail")
+}
+
+/// Bytes of `byte` in a JSON array of numbers, with its comma.
+fn json_len(byte: u8) -> usize {
+    match byte {
+        0..=9 => 2,
+        10..=99 => 3,
+        _ => 4,
+    }
+}
+
+/// `data` in chunks of at most `chunk_size` bytes encoded as JSON, the longest encoding on
+/// any handler.
+fn split(data: &[u8], chunk_size: usize) -> Result<Vec<ShowDumpChunk>, String> {
+    let budget = chunk_size
+        .saturating_sub(CHUNK_OVERHEAD)
+        .max(json_len(u8::MAX));
+    let mut parts: Vec<&[u8]> = vec![];
+    let mut rest = data;
+    loop {
+        let mut used = 0;
+        let end = rest
+            .iter()
+            .position(|byte| {
+                used += json_len(*byte);
+                used > budget
+            })
+            .unwrap_or(rest.len());
+        let (part, tail) = rest.split_at(end);
+        parts.push(part);
+        rest = tail;
+        if rest.is_empty() {
+            break;
+        }
+    }
+    let count = u16::try_from(parts.len()).map_err(|_| "show is too large to dump".to_string())?;
+    Ok(parts
+        .into_iter()
+        .enumerate()
+        .map(|(index, data)| ShowDumpChunk {
+            index: index as u16,
+            count,
+            data: data.to_vec(),
+        })
+        .collect())
+}
+
+/// The show from all chunks of a dump, in any order, as a client puts it back together.
+pub fn assemble(chunks: &[ShowDumpChunk]) -> Result<Show, String> {
+    let mut chunks = chunks.to_vec();
+    chunks.sort_by_key(|chunk| chunk.index);
+    let count = chunks.first().map_or(0, |chunk| chunk.count);
+    if chunks.len() != count as usize
+        || chunks
+            .iter()
+            .enumerate()
+            .any(|(idx, chunk)| chunk.index as usize != idx || chunk.count != count)
+    {
+        return Err("show dump is incomplete".to_string());
+    }
+    let compressed: Vec<u8> = chunks.into_iter().flat_map(|chunk| chunk.data).collect();
+    let mut encoded = vec![];
+    DeflateDecoder::new(compressed.as_slice())
+        .read_to_end(&mut encoded)
+        .map_err(|err| err.to_string())?;
+    postcard::from_bytes(&encoded).map_err(|err| err.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::communication::extension::CoreMessage;
+    use common::cue::Cue;
+
+    #[test]
+    fn dump_and_assemble() {
+        let mut show = Show::default();
+        show.cues.push(Cue::example());
+        assert_eq!(dump(&show).unwrap().len(), 1);
+
+        // Chunks smaller than a datagram, as for a show of a few hundred cues
+        let encoded = postcard::to_stdvec(&show).unwrap();
+        let mut chunks = split(&compress(&encoded), 128).unwrap();
+        assert!(chunks.len() > 1);
+        assert!(chunks.iter().all(|chunk| {
+            let json = serde_json::to_string(&CoreMessage::ShowDump(chunk.clone())).unwrap();
+            json.len() <= 128
+        }));
+
+        chunks.reverse();
+        let assembled = assemble(&chunks).unwrap();
+        assert_eq!(
+            postcard::to_stdvec(&assembled).unwrap(),
+            postcard::to_stdvec(&show).unwrap()
+        );
+        chunks.pop();
+        assert!(assemble(&chunks).is_err());
+    }
+}
@@ -0,0 +1,793 @@
+use crate::{
+    audio::{
+        metronome::{ClickLevels, ClickSamples, ClickSynthesis},
+        timecode::DEFAULT_LTC_FRAME_RATE,
+    },
+    boot::BootError,
+};
+use common::cue::{Cue, Show};
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, path::Path};
+
+/// File in the show directory holding the `ShowExtension`, next to `show.bin`.
+pub const SHOW_EXTENSION_FILE: &str = "core.json";
+
+/// Maximum number of rehearsal markers in a cue.
+pub const MAX_CUE_MARKERS: usize = 32;
+/// Maximum number of clips following another in the chains of a cue.
+pub const MAX_CHAINED_CLIPS: usize = 32;
+/// Maximum number of clips the playback events of a cue pick from, over all its clips with
+/// alternates.
+pub const MAX_ALTERNATE_CLIPS: usize = 32;
+/// Maximum number of gain automation points of a cue, over all its channels.
+pub const MAX_AUTOMATION_POINTS: usize = 64;
+/// Range of automation gains. The bottom is silence.
+pub const MIN_AUTOMATION_GAIN_DB: f32 = -90.0;
+pub const MAX_AUTOMATION_GAIN_DB: f32 = 6.0;
+/// Maximum number of mute events in a cue.
+pub const MAX_MUTE_EVENTS: usize = 64;
+/// Maximum number of fade events in a cue.
+pub const MAX_FADE_EVENTS: usize = 64;
+
+/// What the transport does when it runs past the last beat of a cue.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EndOfCue {
+    /// Stop and stay on the cue
+    Hold,
+    /// Stop and load the next cue, ready to start
+    #[default]
+    LoadNext,
+    /// Stop and put the next cue in standby
+    StandbyNext,
+    /// Load the next cue and keep playing
+    ContinueNext,
+    /// Start the cue over from the top
+    Loop,
+}
+
+impl EndOfCue {
+    /// Parses the snake_case name used in the show extension file.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "hold" => Some(Self::Hold),
+            "load_next" => Some(Self::LoadNext),
+            "standby_next" => Some(Self::StandbyNext),
+            "continue_next" => Some(Self::ContinueNext),
+            "loop" => Some(Self::Loop),
+            _ => None,
+        }
+    }
+}
+
+/// Where `TransportZero` takes the transport.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ZeroTarget {
+    /// The first beat of the cue
+    #[default]
+    Top,
+    /// The beat the transport was last started from
+    LastStart,
+    /// The nearest rehearsal marker at or before the current beat
+    PreviousMarker,
+}
+
+impl ZeroTarget {
+    /// Parses the snake_case name used in the configuration files.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "top" => Some(Self::Top),
+            "last_start" => Some(Self::LastStart),
+            "previous_marker" => Some(Self::PreviousMarker),
+            _ => None,
+        }
+    }
+}
+
+/// Marks a cue as needing attention from everyone running the show.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CueFlag {
+    /// Something in the cue is easy to get wrong, e.g. a fast transition
+    Warning,
+    /// People or scenery are at risk if the cue goes wrong, e.g. flying or pyro
+    Safety,
+    /// The cue may be cut from the show
+    Optional,
+}
+
+impl CueFlag {
+    /// Parses the snake_case name used in the show extension file.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "warning" => Some(Self::Warning),
+            "safety" => Some(Self::Safety),
+            "optional" => Some(Self::Optional),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Warning => "warning",
+            Self::Safety => "safety",
+            Self::Optional => "optional",
+        }
+    }
+
+    /// Whether cues with this flag are made to stand out, not just labelled.
+    pub fn is_high_risk(&self) -> bool {
+        matches!(self, Self::Warning | Self::Safety)
+    }
+}
+
+/// Color a cue is drawn in on clients, as red, green and blue.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CueColor(pub [u8; 3]);
+
+impl CueColor {
+    /// Parses a `#rrggbb` color, the `#` being optional.
+    pub fn from_hex(hex: &str) -> Option<Self> {
+        let hex = hex.strip_prefix('#').unwrap_or(hex);
+        if hex.len() != 6 || !hex.is_ascii() {
+            return None;
+        }
+        let channel = |idx: usize| u8::from_str_radix(&hex[idx * 2..idx * 2 + 2], 16).ok();
+        Some(Self([channel(0)?, channel(1)?, channel(2)?]))
+    }
+
+    pub fn to_hex(&self) -> String {
+        let [red, green, blue] = self.0;
+        format!("#{red:02x}{green:02x}{blue:02x}")
+    }
+}
+
+/// Per-cue settings of the core that the clicks-common cue format has no room for.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CueExtension {
+    pub end: EndOfCue,
+    /// Where `TransportZero` goes in this cue, instead of the configured default
+    pub zero: Option<ZeroTarget>,
+    /// Beats starting a rehearsal section, e.g. letter A, B, ...
+    pub markers: Vec<u16>,
+    /// LTC frame rate of this cue, instead of the rate of the show
+    pub frame_rate: Option<u8>,
+    /// Mixer scene recalled when the cue is loaded
+    pub scene: Option<String>,
+    /// Click levels of this cue, instead of the configured default
+    pub click_levels: Option<ClickLevels>,
+    /// Clips played back to back. Every clip still needs its playback event, which places it
+    /// when seeking.
+    pub chains: Vec<ClipChain>,
+    /// Color clients draw the cue in
+    pub color: Option<CueColor>,
+    /// Flags of the cue, shown on every surface
+    pub flags: Vec<CueFlag>,
+    /// Gain of playback channels over the cue, e.g. fades and dips under dialogue
+    pub automation: Vec<ChannelAutomation>,
+    /// Clips played instead of the clip of a playback event, picked each time it fires
+    pub alternates: Vec<ClipAlternates>,
+    /// Channels muted or unmuted as the transport passes a beat, e.g. for a scene change
+    pub mutes: Vec<MuteEvent>,
+    /// Playback channels faded from a beat, e.g. to end a clip without a click
+    pub fades: Vec<FadeEvent>,
+    /// Click synthesis of this cue, instead of the configured one
+    pub click_synthesis: Option<ClickSynthesis>,
+    /// Click samples of this cue, instead of the configured ones
+    pub click_samples: Option<ClickSamples>,
+}
+
+/// Mutes or unmutes a channel when the transport passes a beat of the cue, as the mute of the
+/// channel would be set by hand. Fires like the events of the cue, so not on beats that are
+/// jumped or seeked over.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MuteEvent {
+    pub beat: u16,
+    pub channel: u8,
+    pub muted: bool,
+}
+
+/// How long a fade takes.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FadeLength {
+    /// Beats of the cue from the beat the fade starts on, at the tempo and playrate it starts at
+    Beats(f32),
+    Ms(f32),
+}
+
+impl Default for FadeLength {
+    fn default() -> Self {
+        Self::Ms(0.0)
+    }
+}
+
+/// Fades a playback channel when the transport passes a beat of the cue, e.g. to end a clip
+/// without a click, or to crossfade with a fade out on one channel and a fade in on another.
+/// Fires like the events of the cue, so not on beats that are jumped or seeked over. A fade to
+/// silence stops the clip of the channel once it has run out; any other gain holds until the
+/// next fade or until the transport stops or is moved.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct FadeEvent {
+    pub beat: u16,
+    pub channel: u16,
+    /// Gain in dB to fade from, none to fade from the gain the channel is at
+    #[serde(default)]
+    pub from_db: Option<f32>,
+    /// Gain in dB to fade to, `MIN_AUTOMATION_GAIN_DB` for silence
+    pub to_db: f32,
+    pub length: FadeLength,
+}
+
+impl FadeEvent {
+    pub fn is_valid(&self) -> bool {
+        let in_range =
+            |gain_db: f32| (MIN_AUTOMATION_GAIN_DB..=MAX_AUTOMATION_GAIN_DB).contains(&gain_db);
+        let (FadeLength::Beats(length) | FadeLength::Ms(length)) = self.length;
+        self.from_db.is_none_or(in_range)
+            && in_range(self.to_db)
+            && length.is_finite()
+            && length >= 0.0
+    }
+}
+
+/// Gain factor of a gain in dB, the bottom of the automation range being silence.
+pub fn gain_factor(gain_db: f32) -> f32 {
+    if gain_db <= MIN_AUTOMATION_GAIN_DB {
+        0.0
+    } else {
+        10.0f32.powf(gain_db / 20.0)
+    }
+}
+
+/// How the clip of a playback event with alternates is picked each time the event fires.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ClipPick {
+    /// Each in turn, the clip of the event first
+    #[default]
+    RoundRobin,
+    /// Any but the one played last time
+    Random,
+}
+
+/// Clips the playback events of a clip play instead of it, e.g. several walk-in tracks or takes
+/// of a sound effect, so the show does not sound the same every night. All of them are loaded
+/// with the cue.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ClipAlternates {
+    pub channel: u16,
+    pub clip: u16,
+    pub alternates: Vec<u16>,
+    #[serde(default)]
+    pub pick: ClipPick,
+}
+
+impl ClipAlternates {
+    /// The clip itself and its alternates, in the order they are taken in turn.
+    pub fn candidates(&self) -> impl Iterator<Item = u16> + '_ {
+        std::iter::once(self.clip).chain(self.alternates.iter().copied())
+    }
+}
+
+/// Gain of a playback channel at a point in a cue, in beats from the top. Fractions of a beat
+/// place the point between clicks.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct GainPoint {
+    pub beat: f32,
+    pub gain_db: f32,
+}
+
+impl GainPoint {
+    pub fn is_valid(&self) -> bool {
+        self.beat.is_finite()
+            && self.beat >= 0.0
+            && (MIN_AUTOMATION_GAIN_DB..=MAX_AUTOMATION_GAIN_DB).contains(&self.gain_db)
+    }
+}
+
+/// Gain of a playback channel over a cue. Between points the gain moves evenly in dB, before
+/// the first and after the last it stays at their gain.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChannelAutomation {
+    pub channel: u16,
+    pub points: Vec<GainPoint>,
+}
+
+/// Clips of a channel played back to back, each starting on the sample the one before ends,
+/// e.g. a stem that was split into several files.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ClipChain {
+    pub channel: u16,
+    pub clips: Vec<u16>,
+}
+
+/// Rehearsal markers of a cue in a fixed size array, so they can be handed to the audio
+/// processor without allocating.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct CueMarkers {
+    beats: [u16; MAX_CUE_MARKERS],
+    len: usize,
+}
+
+impl CueMarkers {
+    /// Markers past `MAX_CUE_MARKERS` are left out.
+    pub fn new(markers: &[u16]) -> Self {
+        let mut beats = [0; MAX_CUE_MARKERS];
+        let len = markers.len().min(MAX_CUE_MARKERS);
+        beats[..len].copy_from_slice(&markers[..len]);
+        beats[..len].sort_unstable();
+        Self { beats, len }
+    }
+
+    /// The last marker at or before `beat`.
+    pub fn previous(&self, beat: u16) -> Option<u16> {
+        self.beats[..self.len]
+            .iter()
+            .rev()
+            .find(|marker| **marker <= beat)
+            .copied()
+    }
+}
+
+/// Chained clips of a cue in a fixed size array, as (channel, clip, next clip), so they can be
+/// handed to the audio processor without allocating.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ClipChains {
+    links: [(u16, u16, u16); MAX_CHAINED_CLIPS],
+    len: usize,
+}
+
+impl ClipChains {
+    /// Links past `MAX_CHAINED_CLIPS` are left out.
+    pub fn new(chains: &[ClipChain]) -> Self {
+        let mut links = [(0, 0, 0); MAX_CHAINED_CLIPS];
+        let mut len = 0;
+        for chain in chains {
+            for pair in chain.clips.windows(2) {
+                if len < MAX_CHAINED_CLIPS {
+                    links[len] = (chain.channel, pair[0], pair[1]);
+                    len += 1;
+                }
+            }
+        }
+        Self { links, len }
+    }
+
+    /// The clip chained after `clip` on `channel`.
+    pub fn next(&self, channel: u16, clip: u16) -> Option<u16> {
+        self.links[..self.len]
+            .iter()
+            .find(|(link_channel, from, _)| *link_channel == channel && *from == clip)
+            .map(|(_, _, to)| *to)
+    }
+}
+
+/// Alternate clips of a cue in a fixed size array, as (channel, clip, candidate, pick) with
+/// the candidates of a clip in turn, so they can be handed to the audio processor without
+/// allocating.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct AlternateClips {
+    candidates: [(u16, u16, u16, ClipPick); MAX_ALTERNATE_CLIPS],
+    len: usize,
+}
+
+impl AlternateClips {
+    /// Candidates past `MAX_ALTERNATE_CLIPS` are left out.
+    pub fn new(alternates: &[ClipAlternates]) -> Self {
+        let mut candidates = [(0, 0, 0, ClipPick::default()); MAX_ALTERNATE_CLIPS];
+        let mut len = 0;
+        for clip in alternates.iter().filter(|clip| !clip.alternates.is_empty()) {
+            for candidate in clip.candidates() {
+                if len < MAX_ALTERNATE_CLIPS {
+                    candidates[len] = (clip.channel, clip.clip, candidate, clip.pick);
+                    len += 1;
+                }
+            }
+        }
+        Self { candidates, len }
+    }
+
+    fn of(&self, channel: u16, clip: u16) -> impl Iterator<Item = &(u16, u16, u16, ClipPick)> {
+        self.candidates[..self.len].iter().filter(
+            move |(candidate_channel, candidate_clip, _, _)| {
+                *candidate_channel == channel && *candidate_clip == clip
+            },
+        )
+    }
+
+    /// How many clips a playback event of `clip` on `channel` picks from, and how, or None if
+    /// the clip has no alternates.
+    pub fn choice(&self, channel: u16, clip: u16) -> Option<(usize, ClipPick)> {
+        let (_, _, _, pick) = self.of(channel, clip).next()?;
+        Some((self.of(channel, clip).count(), *pick))
+    }
+
+    /// The `n`th clip a playback event of `clip` on `channel` picks from.
+    pub fn candidate(&self, channel: u16, clip: u16, n: usize) -> Option<u16> {
+        self.of(channel, clip)
+            .nth(n)
+            .map(|(_, _, candidate, _)| *candidate)
+    }
+}
+
+/// Mute events of a cue in a fixed size array, so they can be handed to the audio processor
+/// without allocating.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MuteEvents {
+    events: [MuteEvent; MAX_MUTE_EVENTS],
+    len: usize,
+}
+
+impl Default for MuteEvents {
+    fn default() -> Self {
+        Self {
+            events: [MuteEvent::default(); MAX_MUTE_EVENTS],
+            len: 0,
+        }
+    }
+}
+
+impl MuteEvents {
+    /// Events past `MAX_MUTE_EVENTS` are left out.
+    pub fn new(mutes: &[MuteEvent]) -> Self {
+        let mut events = Self::default();
+        events.len = mutes.len().min(MAX_MUTE_EVENTS);
+        events.events[..events.len].copy_from_slice(&mutes[..events.len]);
+        events
+    }
+
+    /// The events on `beat`, in the order they were set.
+    pub fn at(&self, beat: u16) -> impl Iterator<Item = MuteEvent> + '_ {
+        self.events[..self.len]
+            .iter()
+            .filter(move |event| event.beat == beat)
+            .copied()
+    }
+}
+
+/// Fade events of a cue in a fixed size array, so they can be handed to the audio processor
+/// without allocating.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FadeEvents {
+    events: [FadeEvent; MAX_FADE_EVENTS],
+    len: usize,
+}
+
+impl Default for FadeEvents {
+    fn default() -> Self {
+        Self {
+            events: [FadeEvent::default(); MAX_FADE_EVENTS],
+            len: 0,
+        }
+    }
+}
+
+impl FadeEvents {
+    /// Events past `MAX_FADE_EVENTS` are left out, as are invalid ones.
+    pub fn new(fades: &[FadeEvent]) -> Self {
+        let mut events = Self::default();
+        for fade in fades.iter().filter(|fade| fade.is_valid()) {
+            if events.len < MAX_FADE_EVENTS {
+                events.events[events.len] = *fade;
+                events.len += 1;
+            }
+        }
+        events
+    }
+
+    /// The events on `beat`, in the order they were set.
+    pub fn at(&self, beat: u16) -> impl Iterator<Item = FadeEvent> + '_ {
+        self.events[..self.len]
+            .iter()
+            .filter(move |event| event.beat == beat)
+            .copied()
+    }
+}
+
+/// Gain automation of a cue in a fixed size array, as (channel, point) ordered by channel and
+/// beat, so it can be handed to the audio processor without allocating.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GainAutomation {
+    points: [(u16, GainPoint); MAX_AUTOMATION_POINTS],
+    len: usize,
+}
+
+impl Default for GainAutomation {
+    fn default() -> Self {
+        Self {
+            points: [(
+                0,
+                GainPoint {
+                    beat: 0.0,
+                    gain_db: 0.0,
+                },
+            ); MAX_AUTOMATION_POINTS],
+            len: 0,
+        }
+    }
+}
+
+impl GainAutomation {
+    /// Points past `MAX_AUTOMATION_POINTS` are left out, as are invalid ones.
+    pub fn new(automation: &[ChannelAutomation]) -> Self {
+        let mut gains = Self::default();
+        for channel in automation {
+            for point in channel.points.iter().filter(|point| point.is_valid()) {
+                if gains.len < MAX_AUTOMATION_POINTS {
+                    gains.points[gains.len] = (channel.channel, *point);
+                    gains.len += 1;
+                }
+            }
+        }
+        gains.points[..gains.len].sort_unstable_by(|(channel, point), (other, other_point)| {
+            channel
+                .cmp(other)
+                .then(point.beat.total_cmp(&other_point.beat))
+        });
+        gains
+    }
+
+    /// Gain factor of `channel` at `beat`, None if the channel is not automated.
+    pub fn gain_at(&self, channel: u16, beat: f32) -> Option<f32> {
+        let mut before: Option<GainPoint> = None;
+        for (_, point) in self.points[..self.len]
+            .iter()
+            .filter(|(point_channel, _)| *point_channel == channel)
+        {
+            if point.beat >= beat {
+                let gain_db = match before {
+                    // The point before is earlier than `beat`, so earlier than this one
+                    Some(before) => {
+                        before.gain_db
+                            + (point.gain_db - before.gain_db) * (beat - before.beat)
+                                / (point.beat - before.beat)
+                    }
+                    None => point.gain_db,
+                };
+                return Some(gain_factor(gain_db));
+            }
+            before = Some(*point);
+        }
+        before.map(|point| gain_factor(point.gain_db))
+    }
+}
+
+/// Show data the core understands on top of the clicks-common show. Cues are identified by
+/// their human readable ident, so the settings survive cues being inserted or reordered in the
+/// editor. Cues without an entry use the defaults.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ShowExtension {
+    /// LTC frame rate of the show, `DEFAULT_LTC_FRAME_RATE` if not set
+    pub frame_rate: Option<u8>,
+    pub cues: BTreeMap<String, CueExtension>,
+}
+
+impl ShowExtension {
+    /// Reads the extension from a show directory. A show without one gets the defaults.
+    pub fn load(show_path: &Path) -> Result<Self, BootError> {
+        let path = show_path.join(SHOW_EXTENSION_FILE);
+        if !std::fs::exists(&path).unwrap_or_default() {
+            return Ok(Self::default());
+        }
+        let content = std::fs::read_to_string(path)
+            .map_err(|err| BootError::FileReadError(err.to_string()))?;
+        serde_json::from_str(&content).map_err(|err| BootError::FileReadError(err.to_string()))
+    }
+
+    pub fn write(&self, show_path: &Path) -> Result<(), BootError> {
+        let content = serde_json::to_string_pretty(self)
+            .map_err(|err| BootError::ConfigWriteError(err.to_string()))?;
+        std::fs::write(show_path.join(SHOW_EXTENSION_FILE), content)
+            .map_err(|err| BootError::ConfigWriteError(err.to_string()))
+    }
+
+    pub fn cue(&self, cue: &Cue) -> CueExtension {
+        self.get(cue).cloned().unwrap_or_default()
+    }
+
+    /// Settings of a cue, or None if it uses the defaults.
+    pub fn get(&self, cue: &Cue) -> Option<&CueExtension> {
+        self.cues.get(cue.metadata.human_ident.str())
+    }
+
+    /// LTC frame rate of a cue: its own if it sets one, else that of the show.
+    pub fn frame_rate(&self, cue: &Cue) -> u8 {
+        self.get(cue)
+            .and_then(|extension| extension.frame_rate)
+            .or(self.frame_rate)
+            .unwrap_or(DEFAULT_LTC_FRAME_RATE)
+    }
+
+    /// Alternate clips of every cue of a show, by cue index.
+    pub fn alternates(&self, show: &Show) -> Vec<Vec<ClipAlternates>> {
+        show.cues
+            .iter()
+            .map(|cue| self.get(cue).map_or(vec![], |cue| cue.alternates.clone()))
+            .collect()
+    }
+
+    pub fn cue_mut(&mut self, cue: &Cue) -> &mut CueExtension {
+        self.cues
+            .entry(cue.metadata.human_ident.str().to_string())
+            .or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn defaults_for_unknown_cues() {
+        let mut extension = ShowExtension::default();
+        let cue = Cue::example();
+        assert_eq!(extension.cue(&cue).end, EndOfCue::LoadNext);
+
+        extension.cue_mut(&cue).end = EndOfCue::Loop;
+        let json = serde_json::to_string(&extension).expect("ShowExtension serializes");
+        let extension: ShowExtension = serde_json::from_str(&json).expect("and deserializes");
+        assert_eq!(extension.cue(&cue).end, EndOfCue::Loop);
+    }
+
+    #[test]
+    fn cue_frame_rate_overrides_show() {
+        let mut extension = ShowExtension::default();
+        let cue = Cue::example();
+        assert_eq!(extension.frame_rate(&cue), DEFAULT_LTC_FRAME_RATE);
+        extension.frame_rate = Some(30);
+        assert_eq!(extension.frame_rate(&cue), 30);
+        extension.cue_mut(&cue).frame_rate = Some(24);
+        assert_eq!(extension.frame_rate(&cue), 24);
+    }
+
+    #[test]
+    fn cue_color_hex() {
+        let color = CueColor::from_hex("#FF8000").unwrap();
+        assert_eq!(color, CueColor([255, 128, 0]));
+        assert_eq!(color.to_hex(), "#ff8000");
+        assert_eq!(CueColor::from_hex("00ff00"), Some(CueColor([0, 255, 0])));
+        assert_eq!(CueColor::from_hex("#ff80"), None);
+        assert_eq!(CueColor::from_hex("#ff80zz"), None);
+        assert_eq!(CueColor::from_hex("#ff800é"), None);
+    }
+
+    #[test]
+    fn mute_events() {
+        let mute = |beat, channel, muted| MuteEvent {
+            beat,
+            channel,
+            muted,
+        };
+        let mutes: Vec<MuteEvent> = (0..MAX_MUTE_EVENTS as u16 + 2)
+            .map(|beat| mute(beat, 3, beat % 2 == 0))
+            .chain([mute(4, 5, true)])
+            .collect();
+        let events = MuteEvents::new(&mutes);
+        assert_eq!(
+            events.at(4).collect::<Vec<_>>(),
+            [mute(4, 3, true)],
+            "events past the maximum are left out"
+        );
+        assert_eq!(events.at(MAX_MUTE_EVENTS as u16).count(), 0);
+
+        let events = MuteEvents::new(&[mute(8, 2, true), mute(16, 2, false), mute(8, 4, true)]);
+        assert_eq!(
+            events.at(8).collect::<Vec<_>>(),
+            [mute(8, 2, true), mute(8, 4, true)]
+        );
+        assert_eq!(events.at(9).count(), 0);
+    }
+
+    #[test]
+    fn fade_events() {
+        let fade = |beat, to_db, length| FadeEvent {
+            beat,
+            channel: 2,
+            from_db: None,
+            to_db,
+            length,
+        };
+        assert!(!fade(4, 0.0, FadeLength::Beats(-1.0)).is_valid());
+        assert!(!fade(4, 12.0, FadeLength::Ms(500.0)).is_valid());
+        assert!(
+            !FadeEvent {
+                from_db: Some(f32::NAN),
+                ..fade(4, 0.0, FadeLength::Ms(500.0))
+            }
+            .is_valid()
+        );
+
+        let events = FadeEvents::new(&[
+            fade(8, -90.0, FadeLength::Beats(2.0)),
+            fade(8, 0.0, FadeLength::Ms(f32::INFINITY)),
+            fade(16, -6.0, FadeLength::Ms(500.0)),
+        ]);
+        assert_eq!(
+            events.at(8).collect::<Vec<_>>(),
+            [fade(8, -90.0, FadeLength::Beats(2.0))],
+            "invalid events are left out"
+        );
+        assert_eq!(events.at(9).count(), 0);
+        assert_eq!(gain_factor(-90.0), 0.0);
+        assert!((gain_factor(-6.0) - 0.501).abs() < 0.001);
+    }
+
+    #[test]
+    fn chained_clips() {
+        let chains = ClipChains::new(&[
+            ClipChain {
+                channel: 3,
+                clips: vec![4, 7, 5],
+            },
+            ClipChain {
+                channel: 1,
+                clips: vec![4],
+            },
+        ]);
+        assert_eq!(chains.next(3, 4), Some(7));
+        assert_eq!(chains.next(3, 7), Some(5));
+        assert_eq!(chains.next(3, 5), None);
+        assert_eq!(chains.next(1, 4), None);
+    }
+
+    #[test]
+    fn alternate_clips() {
+        let alternates = AlternateClips::new(&[
+            ClipAlternates {
+                channel: 1,
+                clip: 4,
+                alternates: vec![9, 7],
+                pick: ClipPick::Random,
+            },
+            ClipAlternates {
+                channel: 1,
+                clip: 5,
+                alternates: vec![],
+                pick: ClipPick::RoundRobin,
+            },
+        ]);
+        assert_eq!(alternates.choice(1, 4), Some((3, ClipPick::Random)));
+        assert_eq!(alternates.candidate(1, 4, 0), Some(4));
+        assert_eq!(alternates.candidate(1, 4, 2), Some(7));
+        assert_eq!(alternates.candidate(1, 4, 3), None);
+        assert_eq!(alternates.choice(2, 4), None);
+        assert_eq!(alternates.choice(1, 5), None);
+    }
+
+    #[test]
+    fn automated_gain() {
+        let point = |beat, gain_db| GainPoint { beat, gain_db };
+        let automation = GainAutomation::new(&[ChannelAutomation {
+            channel: 2,
+            points: vec![point(8.0, -20.0), point(4.0, 0.0), point(6.0, f32::NAN)],
+        }]);
+        assert_eq!(automation.gain_at(1, 4.0), None);
+        assert_eq!(automation.gain_at(2, 0.0), Some(1.0));
+        assert_eq!(automation.gain_at(2, 4.0), Some(1.0));
+        let halfway = automation.gain_at(2, 6.0).unwrap();
+        assert!((halfway - 10.0f32.powf(-0.5)).abs() < 1e-6);
+        assert!((automation.gain_at(2, 100.0).unwrap() - 0.1).abs() < 1e-6);
+
+        // The floor is silence, not -90 dB
+        let automation = GainAutomation::new(&[ChannelAutomation {
+            channel: 0,
+            points: vec![point(0.0, 0.0), point(4.0, MIN_AUTOMATION_GAIN_DB)],
+        }]);
+        assert_eq!(automation.gain_at(0, 4.0), Some(0.0));
+        assert_eq!(automation.gain_at(0, 8.0), Some(0.0));
+        assert!(automation.gain_at(0, 3.0).unwrap() > 0.0);
+    }
+
+    #[test]
+    fn previous_marker() {
+        let markers = CueMarkers::new(&[32, 8, 16]);
+        assert_eq!(markers.previous(4), None);
+        assert_eq!(markers.previous(8), Some(8));
+        assert_eq!(markers.previous(20), Some(16));
+        assert_eq!(markers.previous(100), Some(32));
+    }
+}
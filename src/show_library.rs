@@ -0,0 +1,66 @@
+//! Shows stored side by side in the show directory, so a touring rig can switch between e.g. a
+//! headline set and a festival set without copying shows around. Every subdirectory of
+//! `program_memory/clicks.show/` holding a `show.bin` is a show, named after the subdirectory.
+//! The show of `clicks.show/` itself is named `DEFAULT_SHOW`.
+use std::path::{Path, PathBuf};
+
+/// Name of the show stored in the show directory itself.
+pub const DEFAULT_SHOW: &str = "default";
+
+/// Names of the shows in the show directory `root`, the default show first if there is one,
+/// then the others by name.
+pub fn scan(root: &Path) -> Vec<String> {
+    let mut shows: Vec<String> = std::fs::read_dir(root)
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+        .filter(|name| name != DEFAULT_SHOW && show_path(root, name).is_some())
+        .collect();
+    shows.sort();
+    if root.join("show.bin").is_file() {
+        shows.insert(0, DEFAULT_SHOW.to_string());
+    }
+    shows
+}
+
+/// Directory of the show named `name` in the show directory `root`, if there is such a show.
+pub fn show_path(root: &Path, name: &str) -> Option<PathBuf> {
+    let path = if name == DEFAULT_SHOW {
+        root.to_path_buf()
+    } else {
+        // A name is a single directory, never a way out of the show directory
+        if name.is_empty() || name.starts_with('.') || name.contains(['/', '\\']) {
+            return None;
+        }
+        root.join(name)
+    };
+    path.join("show.bin").is_file().then_some(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_shows() {
+        let root = std::env::temp_dir().join(format!("clicks-show-library-{}", std::process::id()));
+        for dir in ["", "festival", "headline", "empty"] {
+            std::fs::create_dir_all(root.join(dir)).expect("temp dir is writable");
+        }
+        for dir in ["", "festival", "headline"] {
+            std::fs::write(root.join(dir).join("show.bin"), b"show").expect("temp dir is writable");
+        }
+        std::fs::write(root.join("notes.txt"), b"not a show").expect("temp dir is writable");
+
+        assert_eq!(scan(&root), [DEFAULT_SHOW, "festival", "headline"]);
+        assert_eq!(show_path(&root, DEFAULT_SHOW), Some(root.clone()));
+        assert_eq!(show_path(&root, "festival"), Some(root.join("festival")));
+        assert_eq!(show_path(&root, "empty"), None);
+        assert_eq!(show_path(&root, "../clicks.show"), None);
+        assert_eq!(show_path(&root, ".."), None);
+
+        std::fs::remove_file(root.join("show.bin")).expect("temp dir is writable");
+        assert_eq!(scan(&root), ["festival", "headline"]);
+        let _ = std::fs::remove_dir_all(&root);
+    }
+}
@@ -0,0 +1,130 @@
+use crossbeam_channel::{Receiver, unbounded};
+use std::{
+    path::Path,
+    time::{Duration, SystemTime},
+};
+
+/// How often the show file is checked for changes.
+pub const CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Notices when `show.bin` is replaced on disk, e.g. synced over a network share, so the
+/// operator can be asked before the running show is reloaded. Polls the modification time
+/// instead of using inotify, which does not see changes made by other hosts on NFS and SMB
+/// mounts. The file is checked on a thread of its own, as a stalled share can block a check
+/// for long; the thread ends when the watcher is dropped.
+pub struct ShowWatcher {
+    /// Modification times seen by the thread, None if there was no file
+    checks: Receiver<Option<SystemTime>>,
+    /// Latest modification time seen, None before the first check
+    latest: Option<Option<SystemTime>>,
+    /// Modification time of the show that is loaded, None before the first check
+    loaded: Option<Option<SystemTime>>,
+    changed: bool,
+}
+
+impl ShowWatcher {
+    pub fn new(show_path: &Path) -> Self {
+        Self::with_interval(show_path, CHECK_INTERVAL)
+    }
+
+    fn with_interval(show_path: &Path, interval: Duration) -> Self {
+        let file = show_path.join("show.bin");
+        let (tx, checks) = unbounded();
+        // Without its thread the watcher never reports a change, which leaves the show as it is
+        let _ = std::thread::Builder::new()
+            .name("clicks-show-watcher".to_string())
+            .spawn(move || {
+                while tx.send(modified(&file)).is_ok() {
+                    std::thread::sleep(interval);
+                }
+            });
+        Self {
+            checks,
+            latest: None,
+            loaded: None,
+            changed: false,
+        }
+    }
+
+    /// Takes the checks made since the last call, the first of them as the loaded show.
+    fn take_checks(&mut self) {
+        for check in self.checks.try_iter() {
+            self.latest = Some(check);
+            self.loaded.get_or_insert(check);
+        }
+    }
+
+    /// Returns true if the file has changed since the show was loaded. A change is only
+    /// reported once, until `reset`.
+    pub fn poll(&mut self) -> bool {
+        self.take_checks();
+        if self.changed || self.latest.is_none() {
+            return false;
+        }
+        self.changed = self.latest != self.loaded;
+        self.changed
+    }
+
+    /// True from when a change is reported until `reset`.
+    pub fn is_changed(&self) -> bool {
+        self.changed
+    }
+
+    /// Takes the file as last checked as the loaded show, after a reload or when the change is
+    /// dismissed. Returns true if a change was pending.
+    pub fn reset(&mut self) -> bool {
+        self.take_checks();
+        if self.latest.is_some() {
+            self.loaded = self.latest;
+        }
+        std::mem::take(&mut self.changed)
+    }
+}
+
+fn modified(file: &Path) -> Option<SystemTime> {
+    std::fs::metadata(file)
+        .and_then(|meta| meta.modified())
+        .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Instant;
+
+    /// Polls until the watcher reports a change, or gives up after `timeout`.
+    fn wait_for_change(watcher: &mut ShowWatcher, timeout: Duration) -> bool {
+        let start = Instant::now();
+        while start.elapsed() < timeout {
+            if watcher.poll() {
+                return true;
+            }
+            std::thread::sleep(Duration::from_millis(5));
+        }
+        false
+    }
+
+    #[test]
+    fn reports_change_once() {
+        let dir = std::env::temp_dir().join(format!("clicks-show-watcher-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).expect("temp dir is writable");
+        let file = dir.join("show.bin");
+        std::fs::write(&file, b"show").expect("temp dir is writable");
+
+        let mut watcher = ShowWatcher::with_interval(&dir, Duration::from_millis(10));
+        assert!(!wait_for_change(&mut watcher, Duration::from_millis(100)));
+
+        std::fs::File::options()
+            .write(true)
+            .open(&file)
+            .and_then(|file| file.set_modified(SystemTime::now() + Duration::from_secs(60)))
+            .expect("show file is writable");
+        assert!(wait_for_change(&mut watcher, Duration::from_secs(2)));
+        assert!(!watcher.poll());
+        assert!(watcher.is_changed());
+
+        assert!(watcher.reset());
+        assert!(!wait_for_change(&mut watcher, Duration::from_millis(100)));
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
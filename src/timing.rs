@@ -0,0 +1,389 @@
+use crate::{audio::timecode::TimecodeTime, boot::BootError};
+use common::{
+    cue::{Cue, Show, ShowBuilder},
+    event::{EventCursor, EventDescription},
+    mem::smpte::TimecodeInstant,
+};
+use serde::{Deserialize, Serialize};
+use std::{fmt::Write, path::Path};
+
+/// File format of an exported `TimingReport`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TimingReportFormat {
+    Csv,
+    Json,
+    /// Standard MIDI file with the tempo map and a click note on every beat, for DAWs
+    Midi,
+}
+
+impl TimingReportFormat {
+    /// Parses the name of a format, which is also its file extension.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "csv" => Some(Self::Csv),
+            "json" => Some(Self::Json),
+            "mid" | "midi" => Some(Self::Midi),
+            _ => None,
+        }
+    }
+
+    /// Guesses the format from the extension of a file name.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        Self::from_name(path.extension()?.to_str()?)
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Csv => "csv",
+            Self::Json => "json",
+            Self::Midi => "mid",
+        }
+    }
+}
+
+/// When a single beat plays, as far as can be told from the show alone.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct BeatTiming {
+    pub cue: u8,
+    pub cue_ident: String,
+    pub beat: u16,
+    pub bar: i32,
+    /// Position of the beat in its bar, 1 on the downbeat
+    pub beat_in_bar: i32,
+    pub tempo_bpm: f32,
+    pub length_us: u64,
+    /// Time from the top of the cue to the beat, at 100 % playrate
+    pub time_s: f64,
+    /// Timecode sent at the beat, if the cue is sending timecode there. Empty where the cue uses
+    /// wall time, which is only known during the performance.
+    pub timecode: Option<String>,
+}
+
+/// Table of every beat of a show, for departments programming their cues against the click.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct TimingReport {
+    pub beats: Vec<BeatTiming>,
+}
+
+impl TimingReport {
+    pub fn from_show(show: &Show) -> Self {
+        let beats = show
+            .cues
+            .iter()
+            .enumerate()
+            .flat_map(|(cue_idx, cue)| cue_timings(cue_idx as u8, cue))
+            .collect();
+        Self { beats }
+    }
+
+    /// Builds the report of the show stored in `show.bin` in a show directory.
+    pub fn from_show_path(show_path: &Path) -> Result<Self, BootError> {
+        let show = ShowBuilder::from_bin_file(show_path.join("show.bin"))
+            .map_err(|err| BootError::FileReadError(format!("{err:?}")))?;
+        Ok(Self::from_show(&show))
+    }
+
+    pub fn to_csv(&self) -> String {
+        let mut csv =
+            String::from("cue,cue_ident,beat,bar,beat_in_bar,tempo_bpm,time_s,timecode\n");
+        for beat in &self.beats {
+            let _ = writeln!(
+                csv,
+                "{},{},{},{},{},{:.2},{:.6},{}",
+                beat.cue,
+                csv_field(&beat.cue_ident),
+                beat.beat,
+                beat.bar,
+                beat.beat_in_bar,
+                beat.tempo_bpm,
+                beat.time_s,
+                beat.timecode.as_deref().unwrap_or_default()
+            );
+        }
+        csv
+    }
+
+    /// Renders the click track as a standard MIDI file: one quarter note per beat, with the
+    /// tempo and time signature changes of the show and a marker at the top of every cue.
+    /// Downbeats click on the high wood block of the GM percussion channel, other beats on the
+    /// low one. Cues follow on from each other as programmed, jumps are not taken.
+    pub fn to_midi(&self) -> Vec<u8> {
+        let mut track = SmfTrack::default();
+        track.meta(0, 0x03, b"ClicKS click track");
+
+        let mut tick = 0;
+        let mut tempo = None;
+        let mut bar_length = None;
+        for (idx, beat) in self.beats.iter().enumerate() {
+            if beat.beat == 0 {
+                track.meta(tick, 0x06, beat.cue_ident.as_bytes());
+            }
+            if beat.length_us == 0 {
+                continue;
+            }
+            let beat_tempo = beat.length_us.min(0xFF_FFFF) as u32;
+            if tempo != Some(beat_tempo) {
+                tempo = Some(beat_tempo);
+                track.meta(tick, 0x51, &beat_tempo.to_be_bytes()[1..]);
+            }
+            if beat.beat_in_bar == 1 {
+                let length = 1 + self.beats[idx + 1..]
+                    .iter()
+                    .take_while(|next| next.cue == beat.cue && next.beat_in_bar != 1)
+                    .count();
+                let length = length.min(u8::MAX as usize) as u8;
+                if bar_length != Some(length) {
+                    bar_length = Some(length);
+                    // Beats are quarter notes, 24 MIDI clocks to the metronome click
+                    track.meta(tick, 0x58, &[length, 2, 24, 8]);
+                }
+            }
+
+            let (note, velocity) = if beat.beat_in_bar == 1 {
+                (MIDI_CLICK_ACCENT, 127)
+            } else {
+                (MIDI_CLICK, 100)
+            };
+            track.event(tick, &[0x99, note, velocity]);
+            track.event(tick + MIDI_PPQ / 4, &[0x89, note, 0]);
+            tick += MIDI_PPQ;
+        }
+        track.meta(tick, 0x2F, &[]);
+
+        let mut smf = b"MThd".to_vec();
+        smf.extend_from_slice(&6_u32.to_be_bytes());
+        // Format 0, a single track
+        smf.extend_from_slice(&0_u16.to_be_bytes());
+        smf.extend_from_slice(&1_u16.to_be_bytes());
+        smf.extend_from_slice(&(MIDI_PPQ as u16).to_be_bytes());
+        smf.extend_from_slice(b"MTrk");
+        smf.extend_from_slice(&(track.data.len() as u32).to_be_bytes());
+        smf.extend_from_slice(&track.data);
+        smf
+    }
+
+    pub fn write(&self, path: &Path, format: TimingReportFormat) -> Result<(), BootError> {
+        let content = match format {
+            TimingReportFormat::Csv => self.to_csv().into_bytes(),
+            TimingReportFormat::Json => serde_json::to_vec_pretty(self)
+                .map_err(|err| BootError::ConfigWriteError(err.to_string()))?,
+            TimingReportFormat::Midi => self.to_midi(),
+        };
+        std::fs::write(path, content).map_err(|err| BootError::ConfigWriteError(err.to_string()))
+    }
+}
+
+/// The beat of a cue that sends the latest timecode at or before `time`, e.g. to locate to a
+/// time a lighting desk asks for. None if the cue sends none that early.
+pub fn beat_at_timecode(cue: &Cue, time: TimecodeTime) -> Option<u16> {
+    let target = (time.hours, time.minutes, time.seconds, time.frames);
+    cue_timecodes(cue)
+        .into_iter()
+        .enumerate()
+        .filter_map(|(beat, timecode)| {
+            let timecode = timecode?;
+            let sent = (
+                timecode.h as u8,
+                timecode.m as u8,
+                timecode.s as u8,
+                timecode.f as u8,
+            );
+            (sent <= target).then_some((sent, beat as u16))
+        })
+        .max()
+        .map(|(_, beat)| beat)
+}
+
+/// Timecode sent at every beat of a cue, None where it sends none or uses wall time.
+fn cue_timecodes(cue: &Cue) -> Vec<Option<TimecodeInstant>> {
+    let mut timecodes = vec![];
+    let mut timecode: Option<TimecodeInstant> = None;
+    let mut cursor = EventCursor::new(&cue.events);
+    let mut beat_idx = 0;
+    while let Some(beat) = cue.get_beat(beat_idx) {
+        while cursor.at_or_before(beat_idx)
+            && let Some(event) = cursor.get_next()
+        {
+            match event.event {
+                Some(EventDescription::TimecodeEvent { time, properties }) => {
+                    timecode = (!properties.use_wall_time).then_some(time);
+                }
+                Some(EventDescription::TimecodeStopEvent) => timecode = None,
+                _ => {}
+            }
+        }
+        timecodes.push(timecode);
+        if let Some(time) = &mut timecode {
+            time.add_us(beat.length as u64);
+        }
+        beat_idx += 1;
+    }
+    timecodes
+}
+
+fn cue_timings(cue_idx: u8, cue: &Cue) -> Vec<BeatTiming> {
+    let cue_ident = cue.metadata.human_ident.str().to_string();
+    let mut timings = vec![];
+    let mut time_us = 0_u64;
+    for (beat_idx, timecode) in cue_timecodes(cue).into_iter().enumerate() {
+        let beat_idx = beat_idx as u16;
+        let Some(beat) = cue.get_beat(beat_idx) else {
+            break;
+        };
+        timings.push(BeatTiming {
+            cue: cue_idx,
+            cue_ident: cue_ident.clone(),
+            beat: beat_idx,
+            bar: beat.bar_number.into(),
+            beat_in_bar: beat.count.into(),
+            tempo_bpm: if beat.length == 0 {
+                0.0
+            } else {
+                60_000_000.0 / beat.length as f32
+            },
+            length_us: beat.length as u64,
+            time_s: time_us as f64 / 1_000_000.0,
+            timecode: timecode
+                .map(|time| format!("{:02}:{:02}:{:02}:{:02}", time.h, time.m, time.s, time.f)),
+        });
+
+        time_us += beat.length as u64;
+    }
+    timings
+}
+
+/// Ticks per quarter note, i.e. per beat, of exported MIDI files.
+const MIDI_PPQ: u64 = 480;
+/// GM percussion notes of the click: high and low wood block.
+const MIDI_CLICK_ACCENT: u8 = 76;
+const MIDI_CLICK: u8 = 77;
+
+/// Events of a MIDI file track, written in order of time.
+#[derive(Default)]
+struct SmfTrack {
+    data: Vec<u8>,
+    tick: u64,
+}
+
+impl SmfTrack {
+    fn event(&mut self, tick: u64, bytes: &[u8]) {
+        self.delta(tick);
+        self.data.extend_from_slice(bytes);
+    }
+
+    fn meta(&mut self, tick: u64, kind: u8, bytes: &[u8]) {
+        self.delta(tick);
+        self.data.extend_from_slice(&[0xFF, kind]);
+        self.variable_length(bytes.len() as u64);
+        self.data.extend_from_slice(bytes);
+    }
+
+    fn delta(&mut self, tick: u64) {
+        self.variable_length(tick - self.tick);
+        self.tick = tick;
+    }
+
+    /// Writes a number 7 bits at a time, most significant first, all but the last byte with the
+    /// top bit set.
+    fn variable_length(&mut self, value: u64) {
+        let mut bytes = vec![(value & 0x7F) as u8];
+        let mut rest = value >> 7;
+        while rest > 0 {
+            bytes.push((rest & 0x7F) as u8 | 0x80);
+            rest >>= 7;
+        }
+        self.data.extend(bytes.iter().rev());
+    }
+}
+
+/// Quotes a CSV field, as cue idents are free text.
+fn csv_field(value: &str) -> String {
+    format!("\"{}\"", value.replace('"', "\"\""))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_beat_in_order() {
+        let mut show = Show::default();
+        show.cues.push(Cue::example());
+        let report = TimingReport::from_show(&show);
+
+        assert_eq!(report.beats.len(), show.cues[0].get_beats().len());
+        assert_eq!(report.beats[0].time_s, 0.0);
+        assert!(
+            report
+                .beats
+                .windows(2)
+                .all(|pair| pair[0].time_s <= pair[1].time_s)
+        );
+        // Header and one line per beat
+        assert_eq!(report.to_csv().lines().count(), report.beats.len() + 1);
+    }
+
+    #[test]
+    fn locate_timecode() {
+        let cue = Cue::example();
+        let mut show = Show::default();
+        show.cues.push(cue.clone());
+        for timing in TimingReport::from_show(&show).beats {
+            let Some(timecode) = timing.timecode else {
+                continue;
+            };
+            let [hours, minutes, seconds, frames] =
+                [0, 3, 6, 9].map(|start| timecode[start..start + 2].parse::<u8>().unwrap());
+            let time = TimecodeTime {
+                hours,
+                minutes,
+                seconds,
+                frames,
+            };
+            assert_eq!(beat_at_timecode(&cue, time), Some(timing.beat));
+        }
+    }
+
+    #[test]
+    fn midi_click_track() {
+        let mut show = Show::default();
+        show.cues.push(Cue::example());
+        let report = TimingReport::from_show(&show);
+        let smf = report.to_midi();
+
+        assert_eq!(&smf[..4], b"MThd");
+        assert_eq!(&smf[14..18], b"MTrk");
+        let track_length = u32::from_be_bytes(smf[18..22].try_into().unwrap()) as usize;
+        assert_eq!(smf.len(), 22 + track_length);
+        assert!(smf.ends_with(&[0xFF, 0x2F, 0x00]));
+        // A note on and off for every beat
+        let notes_on = smf.windows(2).filter(|pair| pair[0] == 0x99).count();
+        let clicked = report
+            .beats
+            .iter()
+            .filter(|beat| beat.length_us > 0)
+            .count();
+        assert!(notes_on >= clicked);
+    }
+
+    #[test]
+    fn variable_length_numbers() {
+        let mut track = SmfTrack::default();
+        for value in [0, 0x40, 0x7F, 0x80, 0x2000, 0x0FFF_FFFF] {
+            track.variable_length(value);
+        }
+        assert_eq!(
+            track.data,
+            [
+                0x00, 0x40, 0x7F, 0x81, 0x00, 0xC0, 0x00, 0xFF, 0xFF, 0xFF, 0x7F
+            ]
+        );
+    }
+
+    #[test]
+    fn quotes_idents() {
+        assert_eq!(csv_field("12A"), "\"12A\"");
+        assert_eq!(csv_field("Act \"2\""), "\"Act \"\"2\"\"\"");
+    }
+}
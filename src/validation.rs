@@ -0,0 +1,1336 @@
+use crate::{
+    VERSION,
+    audio::{
+        count_in::MAX_COUNT_IN_BARS,
+        delay::MAX_OUTPUT_DELAY_MS,
+        eq::{EQ_BANDS, EQ_FREQUENCY_RANGE, EqBand, MAX_EQ_GAIN_DB},
+        fade::MAX_STOP_FADE_MS,
+        handler::RouteMask,
+        metronome::{
+            CLICK_FREQUENCY_RANGE, ClickLevels, ClickSamples, ClickSynthesis, MAX_CLICK_DECAY_MS,
+            MAX_CLICK_MS, MIN_CLICK_LEVEL_DB,
+        },
+        phase::MAX_BEAT_PHASE_RATE,
+        source::MAX_GAIN_RAMP_MS,
+        timecode::{MAX_LTC_SMOOTHING, TimecodeTime},
+    },
+    communication::extension::{
+        CoreRequest, MIN_PROTOCOL_VERSION, PROTOCOL_VERSION, protocol_compatible,
+    },
+    config::{MAX_OUTPUT_LABEL_LEN, MAX_PASS_THROUGH_GAIN_DB, OutputConfiguration},
+    macros::{MAX_MACRO_DELAY_MS, MAX_MACRO_STEPS, MacroStep},
+    scene::Scene,
+    show_extension::{
+        FadeEvent, MAX_AUTOMATION_GAIN_DB, MAX_AUTOMATION_POINTS, MAX_CUE_MARKERS, MAX_FADE_EVENTS,
+        MAX_MUTE_EVENTS, MIN_AUTOMATION_GAIN_DB,
+    },
+};
+use common::{
+    cue::Show,
+    protocol::request::{ControlAction, Request},
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    path::{Component, Path},
+};
+
+/// Why a request was rejected without being acted upon.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum RejectReason {
+    CueOutOfRange { cue: u8, cue_count: usize },
+    ChannelOutOfRange { channel: u8, channel_count: usize },
+    BeatOutOfRange { beat: u16 },
+    OutputPortOutOfRange { port: u8, port_count: usize },
+    SystemPortOutOfRange { port: u8, port_count: usize },
+    AudioNotRunning,
+    EqBandOutOfRange { band: u8 },
+    DelayOutOfRange { delay_ms: f32 },
+    InputPortOutOfRange { port: u8, port_count: usize },
+    CapturePortOutOfRange { port: u8, port_count: usize },
+    RateOutOfRange { rate_hz: u8 },
+    NoCueInStandby,
+    TooManyMarkers { count: usize },
+    LevelOutOfRange { level: f32 },
+    SmoothingOutOfRange { samples: u8 },
+    LabelTooLong { length: usize },
+    UnknownScene { name: String },
+    RampOutOfRange { ramp_ms: f32 },
+    FadeOutOfRange { fade_ms: f32 },
+    UnknownMacro { name: String },
+    TooManySteps { count: usize },
+    StepDelayOutOfRange { delay_ms: u32 },
+    TimeOutOfRange { time: TimecodeTime },
+    ClickLevelOutOfRange { levels: ClickLevels },
+    TooManyPoints { count: usize },
+    PointOutOfRange { beat: f32, gain_db: f32 },
+    ClickSynthesisOutOfRange { synthesis: ClickSynthesis },
+    TooManyMuteEvents { count: usize },
+    TooManyFadeEvents { count: usize },
+    FadeEventOutOfRange { fade: FadeEvent },
+    UnknownRoutingPreset { name: String },
+    ClickSampleOutsideShow { sample: String },
+    CountInOutOfRange { bars: u8 },
+    IncompatibleProtocol { version: u16 },
+    Unsupported,
+    Unreadable,
+    OutputOutOfRange { output: u8, source_count: usize },
+    GainNotFinite { gain_db: f32 },
+    OutputUnheard { output: u8 },
+    EqSettingsOutOfRange { settings: EqBand },
+    PassThroughGainOutOfRange { gain_db: f32 },
+    PowerNotConfigured,
+}
+
+impl std::fmt::Display for RejectReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RejectReason::CueOutOfRange { cue, cue_count } => {
+                write!(f, "cue {cue} does not exist, show has {cue_count} cues")
+            }
+            RejectReason::ChannelOutOfRange {
+                channel,
+                channel_count,
+            } => write!(
+                f,
+                "channel {channel} does not exist, there are {channel_count} channels"
+            ),
+            RejectReason::BeatOutOfRange { beat } => {
+                write!(f, "beat {beat} does not exist in the current cue")
+            }
+            RejectReason::OutputPortOutOfRange { port, port_count } => write!(
+                f,
+                "output port {port} does not exist, there are {port_count} output ports"
+            ),
+            RejectReason::OutputOutOfRange {
+                output,
+                source_count,
+            } => write!(
+                f,
+                "output {output} does not exist, there is one per source and {source_count} sources"
+            ),
+            RejectReason::GainNotFinite { gain_db } => {
+                write!(f, "gain of {gain_db} dB is not a number")
+            }
+            RejectReason::OutputUnheard { output } => write!(
+                f,
+                "output {output} has no client output port of its own, it would not be heard \
+                 without being summed into one"
+            ),
+            RejectReason::EqSettingsOutOfRange { settings } => write!(
+                f,
+                "EQ band of {} Hz, {} dB and Q {} is outside {} -- {} Hz, -{MAX_EQ_GAIN_DB} -- \
+                 {MAX_EQ_GAIN_DB} dB or a Q above 0",
+                settings.frequency,
+                settings.gain_db,
+                settings.q,
+                EQ_FREQUENCY_RANGE.start(),
+                EQ_FREQUENCY_RANGE.end()
+            ),
+            RejectReason::PowerNotConfigured => write!(
+                f,
+                "restarts and reboots are refused until power_passphrase is set in core.conf"
+            ),
+            RejectReason::PassThroughGainOutOfRange { gain_db } => write!(
+                f,
+                "pass-through gain of {gain_db} dB is above the maximum of \
+                 {MAX_PASS_THROUGH_GAIN_DB} dB"
+            ),
+            RejectReason::SystemPortOutOfRange { port, port_count } => write!(
+                f,
+                "system port {port} does not exist, there are {port_count} system ports"
+            ),
+            RejectReason::AudioNotRunning => write!(f, "audio is not running"),
+            RejectReason::EqBandOutOfRange { band } => {
+                write!(
+                    f,
+                    "EQ band {band} does not exist, there are {EQ_BANDS} bands"
+                )
+            }
+            RejectReason::InputPortOutOfRange { port, port_count } => write!(
+                f,
+                "input port {port} does not exist, there are {port_count} input ports"
+            ),
+            RejectReason::CapturePortOutOfRange { port, port_count } => write!(
+                f,
+                "capture port {port} does not exist, there are {port_count} capture ports"
+            ),
+            RejectReason::NoCueInStandby => write!(f, "no cue is in standby"),
+            RejectReason::TooManyMarkers { count } => write!(
+                f,
+                "{count} markers is more than the maximum of {MAX_CUE_MARKERS}"
+            ),
+            RejectReason::RateOutOfRange { rate_hz } => write!(
+                f,
+                "rate of {rate_hz} Hz is above the maximum of {MAX_BEAT_PHASE_RATE} Hz"
+            ),
+            RejectReason::DelayOutOfRange { delay_ms } => write!(
+                f,
+                "delay of {delay_ms} ms is outside 0 -- {MAX_OUTPUT_DELAY_MS} ms"
+            ),
+            RejectReason::FadeOutOfRange { fade_ms } => write!(
+                f,
+                "fade of {fade_ms} ms is outside 0 -- {MAX_STOP_FADE_MS} ms"
+            ),
+            RejectReason::RampOutOfRange { ramp_ms } => write!(
+                f,
+                "ramp of {ramp_ms} ms is outside 0 -- {MAX_GAIN_RAMP_MS} ms"
+            ),
+            RejectReason::UnknownScene { name } => write!(f, "there is no scene named '{name}'"),
+            RejectReason::UnknownMacro { name } => write!(f, "there is no macro named '{name}'"),
+            RejectReason::UnknownRoutingPreset { name } => {
+                write!(f, "there is no routing preset named '{name}'")
+            }
+            RejectReason::CountInOutOfRange { bars } => write!(
+                f,
+                "count-in of {bars} bars is outside 1 -- {MAX_COUNT_IN_BARS} bars"
+            ),
+            RejectReason::ClickSampleOutsideShow { sample } => write!(
+                f,
+                "click sample '{sample}' is not a file within the show directory"
+            ),
+            RejectReason::TooManySteps { count } => write!(
+                f,
+                "macro of {count} steps is longer than the maximum of {MAX_MACRO_STEPS}"
+            ),
+            RejectReason::StepDelayOutOfRange { delay_ms } => write!(
+                f,
+                "step delay of {delay_ms} ms is outside 0 -- {MAX_MACRO_DELAY_MS} ms"
+            ),
+            RejectReason::TimeOutOfRange { time } => write!(f, "{time} is not a time of day"),
+            RejectReason::ClickLevelOutOfRange { levels } => write!(
+                f,
+                "click levels of {} and {} dB are outside {MIN_CLICK_LEVEL_DB} -- 0 dB",
+                levels.accent_db, levels.beat_db
+            ),
+            RejectReason::TooManyPoints { count } => write!(
+                f,
+                "{count} automation points is more than the maximum of {MAX_AUTOMATION_POINTS}"
+            ),
+            RejectReason::PointOutOfRange { beat, gain_db } => write!(
+                f,
+                "automation point of {gain_db} dB at beat {beat} is outside \
+                 {MIN_AUTOMATION_GAIN_DB} -- {MAX_AUTOMATION_GAIN_DB} dB from beat 0 on"
+            ),
+            RejectReason::ClickSynthesisOutOfRange { synthesis } => write!(
+                f,
+                "clicks of {:?} are not all between {} and {} Hz and 0 -- {MAX_CLICK_MS} ms \
+                 long, or their decay of {} ms is outside 0 -- {MAX_CLICK_DECAY_MS} ms",
+                synthesis.clicks,
+                CLICK_FREQUENCY_RANGE.start(),
+                CLICK_FREQUENCY_RANGE.end(),
+                synthesis.decay_ms
+            ),
+            RejectReason::TooManyMuteEvents { count } => write!(
+                f,
+                "{count} mute events is more than the maximum of {MAX_MUTE_EVENTS}"
+            ),
+            RejectReason::TooManyFadeEvents { count } => write!(
+                f,
+                "{count} fade events is more than the maximum of {MAX_FADE_EVENTS}"
+            ),
+            RejectReason::FadeEventOutOfRange { fade } => write!(
+                f,
+                "fade of channel {} at beat {} has gains outside {MIN_AUTOMATION_GAIN_DB} -- \
+                 {MAX_AUTOMATION_GAIN_DB} dB, or a length of {:?} that is negative or not finite",
+                fade.channel, fade.beat, fade.length
+            ),
+            RejectReason::LabelTooLong { length } => write!(
+                f,
+                "label of {length} characters is longer than the maximum of {MAX_OUTPUT_LABEL_LEN}"
+            ),
+            RejectReason::LevelOutOfRange { level } => {
+                write!(f, "level {level} is outside 0.0 -- 1.0")
+            }
+            RejectReason::SmoothingOutOfRange { samples } => write!(
+                f,
+                "smoothing of {samples} samples is outside 1 -- {MAX_LTC_SMOOTHING} samples"
+            ),
+            RejectReason::IncompatibleProtocol { version } => write!(
+                f,
+                "protocol version {version} is not supported, clicks-core {VERSION} speaks \
+                 versions {MIN_PROTOCOL_VERSION} -- {PROTOCOL_VERSION}"
+            ),
+            RejectReason::Unsupported => write!(
+                f,
+                "request is not supported by clicks-core {VERSION}, which speaks protocol \
+                 version {PROTOCOL_VERSION}"
+            ),
+            RejectReason::Unreadable => write!(
+                f,
+                "request could not be read, the client may not speak protocol version \
+                 {PROTOCOL_VERSION} of clicks-core {VERSION}"
+            ),
+        }
+    }
+}
+
+/// The parts of core state requests are checked against.
+pub struct ValidationContext<'a> {
+    pub show: &'a Show,
+    pub cue_idx: u8,
+    pub channel_count: usize,
+    /// Number of sources, running or not. Each source is processed as an output of its own,
+    /// with its own EQ, delay and polarity, before it is mixed onto the client output ports.
+    pub source_count: usize,
+    /// Number of client output ports the outputs are mixed onto, running or not
+    pub port_count: usize,
+    /// Processing of each output, past the end of the list unprocessed
+    pub outputs: &'a [OutputConfiguration],
+    /// Number of client input ports, running or not
+    pub input_count: usize,
+    /// Cue armed by a standby request
+    pub standby_cue: Option<u8>,
+    /// Number of (client output, system input) ports, or None if the audio client is not running
+    pub io_size: Option<(usize, usize)>,
+    /// Number of (client input, system capture) ports, or None if the audio client is not
+    /// running
+    pub input_io_size: Option<(usize, usize)>,
+    /// Stored mixer scenes
+    pub scenes: &'a BTreeMap<String, Scene>,
+    /// Defined macros
+    pub macros: &'a BTreeMap<String, Vec<MacroStep>>,
+    /// Stored routing presets
+    pub routing_presets: &'a BTreeMap<String, Vec<RouteMask>>,
+    /// Whether the core configuration sets the passphrase restarts and reboots must carry
+    pub power_passphrase_set: bool,
+}
+
+/// Checks a request against the current state, so that requests referring to cues, channels,
+/// beats or ports that do not exist are rejected instead of indexing out of bounds.
+pub fn validate(request: &Request, ctx: &ValidationContext) -> Result<(), RejectReason> {
+    match request {
+        Request::ControlAction(action) => validate_action(action, ctx),
+        Request::ChangeRouting(from, to, _) => {
+            let Some((output_count, system_count)) = ctx.io_size else {
+                return Err(RejectReason::AudioNotRunning);
+            };
+            if *from as usize >= output_count {
+                return Err(RejectReason::OutputPortOutOfRange {
+                    port: *from,
+                    port_count: output_count,
+                });
+            }
+            if *to as usize >= system_count {
+                return Err(RejectReason::SystemPortOutOfRange {
+                    port: *to,
+                    port_count: system_count,
+                });
+            }
+            Ok(())
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Like `validate`, for requests outside the common protocol.
+pub fn validate_core(request: &CoreRequest, ctx: &ValidationContext) -> Result<(), RejectReason> {
+    match request {
+        CoreRequest::Hello { protocol_version } => {
+            if !protocol_compatible(*protocol_version) {
+                return Err(RejectReason::IncompatibleProtocol {
+                    version: *protocol_version,
+                });
+            }
+            Ok(())
+        }
+        CoreRequest::SetOutputEqBand {
+            output,
+            band,
+            settings,
+        } => {
+            if *output as usize >= ctx.source_count {
+                return Err(RejectReason::OutputOutOfRange {
+                    output: *output,
+                    source_count: ctx.source_count,
+                });
+            }
+            if *band as usize >= EQ_BANDS {
+                return Err(RejectReason::EqBandOutOfRange { band: *band });
+            }
+            if !settings.is_valid() {
+                return Err(RejectReason::EqSettingsOutOfRange {
+                    settings: *settings,
+                });
+            }
+            Ok(())
+        }
+        CoreRequest::ChangeInputRouting { from, to, .. } => {
+            let Some((input_count, capture_count)) = ctx.input_io_size else {
+                return Err(RejectReason::AudioNotRunning);
+            };
+            if *from as usize >= capture_count {
+                return Err(RejectReason::CapturePortOutOfRange {
+                    port: *from,
+                    port_count: capture_count,
+                });
+            }
+            if *to as usize >= input_count {
+                return Err(RejectReason::InputPortOutOfRange {
+                    port: *to,
+                    port_count: input_count,
+                });
+            }
+            Ok(())
+        }
+        CoreRequest::SetOutputMix {
+            output,
+            port,
+            gain_db,
+        } => {
+            if *output as usize >= ctx.source_count {
+                return Err(RejectReason::OutputOutOfRange {
+                    output: *output,
+                    source_count: ctx.source_count,
+                });
+            }
+            if *port as usize >= ctx.port_count {
+                return Err(RejectReason::OutputPortOutOfRange {
+                    port: *port,
+                    port_count: ctx.port_count,
+                });
+            }
+            match gain_db {
+                Some(gain_db) if !gain_db.is_finite() => {
+                    return Err(RejectReason::GainNotFinite { gain_db: *gain_db });
+                }
+                Some(_) => {}
+                // The last send of an output without a port of its own cannot be removed
+                None => {
+                    let summed = ctx
+                        .outputs
+                        .get(*output as usize)
+                        .is_some_and(|config| config.mix.iter().any(|send| send.port != *port));
+                    if *output as usize >= ctx.port_count && !summed {
+                        return Err(RejectReason::OutputUnheard { output: *output });
+                    }
+                }
+            }
+            Ok(())
+        }
+        CoreRequest::MapMidi {
+            action: Some(action),
+            ..
+        } => match action.channel() {
+            Some(channel) if channel as usize >= ctx.channel_count => {
+                Err(RejectReason::ChannelOutOfRange {
+                    channel,
+                    channel_count: ctx.channel_count,
+                })
+            }
+            _ => Ok(()),
+        },
+        CoreRequest::StopPlaybackChannel { channel, fade_ms } => {
+            if *channel as usize >= ctx.channel_count {
+                return Err(RejectReason::ChannelOutOfRange {
+                    channel: *channel,
+                    channel_count: ctx.channel_count,
+                });
+            }
+            match fade_ms {
+                Some(fade_ms) if !(0.0..=MAX_STOP_FADE_MS).contains(fade_ms) => {
+                    Err(RejectReason::FadeOutOfRange { fade_ms: *fade_ms })
+                }
+                _ => Ok(()),
+            }
+        }
+        CoreRequest::JamTimecode(time) | CoreRequest::LocateTimecode(time) => {
+            if !time.is_valid() {
+                return Err(RejectReason::TimeOutOfRange { time: *time });
+            }
+            Ok(())
+        }
+        CoreRequest::SetPassThrough {
+            input,
+            output,
+            gain_db,
+        } => {
+            if *output as usize >= ctx.source_count {
+                return Err(RejectReason::OutputOutOfRange {
+                    output: *output,
+                    source_count: ctx.source_count,
+                });
+            }
+            if *input as usize >= ctx.input_count {
+                return Err(RejectReason::InputPortOutOfRange {
+                    port: *input,
+                    port_count: ctx.input_count,
+                });
+            }
+            if let Some(gain_db) = *gain_db {
+                if !gain_db.is_finite() {
+                    return Err(RejectReason::GainNotFinite { gain_db });
+                }
+                if gain_db > MAX_PASS_THROUGH_GAIN_DB {
+                    return Err(RejectReason::PassThroughGainOutOfRange { gain_db });
+                }
+            }
+            Ok(())
+        }
+        CoreRequest::Restart { .. } | CoreRequest::Reboot { .. } => {
+            if !ctx.power_passphrase_set {
+                return Err(RejectReason::PowerNotConfigured);
+            }
+            Ok(())
+        }
+        CoreRequest::Standby { cue } => {
+            if *cue as usize >= ctx.show.cues.len() {
+                return Err(RejectReason::CueOutOfRange {
+                    cue: *cue,
+                    cue_count: ctx.show.cues.len(),
+                });
+            }
+            Ok(())
+        }
+        CoreRequest::RecallScene { name, .. } | CoreRequest::DeleteScene { name } => {
+            if !ctx.scenes.contains_key(name) {
+                return Err(RejectReason::UnknownScene { name: name.clone() });
+            }
+            Ok(())
+        }
+        CoreRequest::LoadRoutingPreset { name } | CoreRequest::DeleteRoutingPreset { name } => {
+            if !ctx.routing_presets.contains_key(name) {
+                return Err(RejectReason::UnknownRoutingPreset { name: name.clone() });
+            }
+            Ok(())
+        }
+        CoreRequest::RunMacro { name } => {
+            if !ctx.macros.contains_key(name) {
+                return Err(RejectReason::UnknownMacro { name: name.clone() });
+            }
+            Ok(())
+        }
+        CoreRequest::SetMacro {
+            steps: Some(steps), ..
+        } => {
+            if steps.len() > MAX_MACRO_STEPS {
+                return Err(RejectReason::TooManySteps { count: steps.len() });
+            }
+            match steps.iter().find(|step| step.delay_ms > MAX_MACRO_DELAY_MS) {
+                Some(step) => Err(RejectReason::StepDelayOutOfRange {
+                    delay_ms: step.delay_ms,
+                }),
+                None => Ok(()),
+            }
+        }
+        CoreRequest::SetCueScene { cue, scene } => {
+            if *cue as usize >= ctx.show.cues.len() {
+                return Err(RejectReason::CueOutOfRange {
+                    cue: *cue,
+                    cue_count: ctx.show.cues.len(),
+                });
+            }
+            if let Some(name) = scene
+                && !ctx.scenes.contains_key(name)
+            {
+                return Err(RejectReason::UnknownScene { name: name.clone() });
+            }
+            Ok(())
+        }
+        CoreRequest::SetCueEnd { cue, .. }
+        | CoreRequest::SetCueColor { cue, .. }
+        | CoreRequest::SetCueFlags { cue, .. } => {
+            if *cue as usize >= ctx.show.cues.len() {
+                return Err(RejectReason::CueOutOfRange {
+                    cue: *cue,
+                    cue_count: ctx.show.cues.len(),
+                });
+            }
+            Ok(())
+        }
+        CoreRequest::SetClickLevels { cue, levels } => {
+            if let Some(cue) = cue
+                && *cue as usize >= ctx.show.cues.len()
+            {
+                return Err(RejectReason::CueOutOfRange {
+                    cue: *cue,
+                    cue_count: ctx.show.cues.len(),
+                });
+            }
+            match levels {
+                Some(levels) if !levels.is_valid() => {
+                    Err(RejectReason::ClickLevelOutOfRange { levels: *levels })
+                }
+                _ => Ok(()),
+            }
+        }
+        CoreRequest::SetClickSynthesis(synthesis) => {
+            if synthesis.is_valid() {
+                Ok(())
+            } else {
+                Err(RejectReason::ClickSynthesisOutOfRange {
+                    synthesis: *synthesis,
+                })
+            }
+        }
+        CoreRequest::TransportStartWithCountIn { bars } => {
+            if (1..=MAX_COUNT_IN_BARS).contains(bars) {
+                Ok(())
+            } else {
+                Err(RejectReason::CountInOutOfRange { bars: *bars })
+            }
+        }
+        CoreRequest::SetCueClickSynthesis { cue, synthesis } => {
+            if *cue as usize >= ctx.show.cues.len() {
+                return Err(RejectReason::CueOutOfRange {
+                    cue: *cue,
+                    cue_count: ctx.show.cues.len(),
+                });
+            }
+            match synthesis {
+                Some(synthesis) if !synthesis.is_valid() => {
+                    Err(RejectReason::ClickSynthesisOutOfRange {
+                        synthesis: *synthesis,
+                    })
+                }
+                _ => Ok(()),
+            }
+        }
+        CoreRequest::SetClickSamples { cue, samples } => {
+            if let Some(cue) = cue
+                && *cue as usize >= ctx.show.cues.len()
+            {
+                return Err(RejectReason::CueOutOfRange {
+                    cue: *cue,
+                    cue_count: ctx.show.cues.len(),
+                });
+            }
+            match samples.iter().flat_map(samples_outside_show).next() {
+                Some(sample) => Err(RejectReason::ClickSampleOutsideShow {
+                    sample: sample.clone(),
+                }),
+                None => Ok(()),
+            }
+        }
+        CoreRequest::SetGainAutomation {
+            cue,
+            channel,
+            points,
+        } => {
+            if *cue as usize >= ctx.show.cues.len() {
+                return Err(RejectReason::CueOutOfRange {
+                    cue: *cue,
+                    cue_count: ctx.show.cues.len(),
+                });
+            }
+            if *channel as usize >= ctx.channel_count {
+                return Err(RejectReason::ChannelOutOfRange {
+                    channel: u8::try_from(*channel).unwrap_or(u8::MAX),
+                    channel_count: ctx.channel_count,
+                });
+            }
+            if points.len() > MAX_AUTOMATION_POINTS {
+                return Err(RejectReason::TooManyPoints {
+                    count: points.len(),
+                });
+            }
+            match points.iter().find(|point| !point.is_valid()) {
+                Some(point) => Err(RejectReason::PointOutOfRange {
+                    beat: point.beat,
+                    gain_db: point.gain_db,
+                }),
+                None => Ok(()),
+            }
+        }
+        CoreRequest::LaunchCue { cue, .. } => {
+            let cue = cue.unwrap_or(ctx.cue_idx.saturating_add(1));
+            if cue as usize >= ctx.show.cues.len() {
+                return Err(RejectReason::CueOutOfRange {
+                    cue,
+                    cue_count: ctx.show.cues.len(),
+                });
+            }
+            Ok(())
+        }
+        CoreRequest::SetZeroTarget { cue, .. } => match cue {
+            Some(cue) if *cue as usize >= ctx.show.cues.len() => Err(RejectReason::CueOutOfRange {
+                cue: *cue,
+                cue_count: ctx.show.cues.len(),
+            }),
+            _ => Ok(()),
+        },
+        CoreRequest::SetCueMarkers { cue, markers } => {
+            let Some(marked) = ctx.show.cues.get(*cue as usize) else {
+                return Err(RejectReason::CueOutOfRange {
+                    cue: *cue,
+                    cue_count: ctx.show.cues.len(),
+                });
+            };
+            if markers.len() > MAX_CUE_MARKERS {
+                return Err(RejectReason::TooManyMarkers {
+                    count: markers.len(),
+                });
+            }
+            if let Some(beat) = markers
+                .iter()
+                .find(|beat| marked.get_beat(**beat).is_none())
+            {
+                return Err(RejectReason::BeatOutOfRange { beat: *beat });
+            }
+            Ok(())
+        }
+        CoreRequest::SetMuteEvents { cue, events } => {
+            let Some(muted) = ctx.show.cues.get(*cue as usize) else {
+                return Err(RejectReason::CueOutOfRange {
+                    cue: *cue,
+                    cue_count: ctx.show.cues.len(),
+                });
+            };
+            if events.len() > MAX_MUTE_EVENTS {
+                return Err(RejectReason::TooManyMuteEvents {
+                    count: events.len(),
+                });
+            }
+            if let Some(event) = events
+                .iter()
+                .find(|event| muted.get_beat(event.beat).is_none())
+            {
+                return Err(RejectReason::BeatOutOfRange { beat: event.beat });
+            }
+            match events
+                .iter()
+                .find(|event| event.channel as usize >= ctx.channel_count)
+            {
+                Some(event) => Err(RejectReason::ChannelOutOfRange {
+                    channel: event.channel,
+                    channel_count: ctx.channel_count,
+                }),
+                None => Ok(()),
+            }
+        }
+        CoreRequest::SetFadeEvents { cue, events } => {
+            let Some(faded) = ctx.show.cues.get(*cue as usize) else {
+                return Err(RejectReason::CueOutOfRange {
+                    cue: *cue,
+                    cue_count: ctx.show.cues.len(),
+                });
+            };
+            if events.len() > MAX_FADE_EVENTS {
+                return Err(RejectReason::TooManyFadeEvents {
+                    count: events.len(),
+                });
+            }
+            if let Some(event) = events
+                .iter()
+                .find(|event| faded.get_beat(event.beat).is_none())
+            {
+                return Err(RejectReason::BeatOutOfRange { beat: event.beat });
+            }
+            match events.iter().find(|event| !event.is_valid()) {
+                Some(event) => Err(RejectReason::FadeEventOutOfRange { fade: *event }),
+                None => Ok(()),
+            }
+        }
+        CoreRequest::Go => {
+            if ctx.standby_cue.is_none() {
+                return Err(RejectReason::NoCueInStandby);
+            }
+            Ok(())
+        }
+        CoreRequest::SetBeatPhaseRate(rate_hz) => {
+            if *rate_hz > MAX_BEAT_PHASE_RATE {
+                return Err(RejectReason::RateOutOfRange { rate_hz: *rate_hz });
+            }
+            Ok(())
+        }
+        CoreRequest::SetTempoFollow(config) => {
+            if config.input as usize >= ctx.input_count {
+                return Err(RejectReason::InputPortOutOfRange {
+                    port: config.input,
+                    port_count: ctx.input_count,
+                });
+            }
+            Ok(())
+        }
+        CoreRequest::SetDeviceName { name } => {
+            let length = name.chars().count();
+            if length > MAX_OUTPUT_LABEL_LEN {
+                return Err(RejectReason::LabelTooLong { length });
+            }
+            Ok(())
+        }
+        CoreRequest::SetStopFade { fade_ms }
+        | CoreRequest::StopAllPlayback {
+            fade_ms: Some(fade_ms),
+        } => {
+            if !(0.0..=MAX_STOP_FADE_MS).contains(fade_ms) {
+                return Err(RejectReason::FadeOutOfRange { fade_ms: *fade_ms });
+            }
+            Ok(())
+        }
+        CoreRequest::SetGainRamp { ramp_ms } => {
+            if !(0.0..=MAX_GAIN_RAMP_MS).contains(ramp_ms) {
+                return Err(RejectReason::RampOutOfRange { ramp_ms: *ramp_ms });
+            }
+            Ok(())
+        }
+        CoreRequest::SetTimecodeOutput(output) => {
+            if !(0.0..=1.0).contains(&output.level) {
+                return Err(RejectReason::LevelOutOfRange {
+                    level: output.level,
+                });
+            }
+            if !(1..=MAX_LTC_SMOOTHING).contains(&output.smoothing) {
+                return Err(RejectReason::SmoothingOutOfRange {
+                    samples: output.smoothing,
+                });
+            }
+            Ok(())
+        }
+        CoreRequest::SetOutputLabel { output, label } => {
+            if *output as usize >= ctx.source_count {
+                return Err(RejectReason::OutputOutOfRange {
+                    output: *output,
+                    source_count: ctx.source_count,
+                });
+            }
+            let length = label.chars().count();
+            if length > MAX_OUTPUT_LABEL_LEN {
+                return Err(RejectReason::LabelTooLong { length });
+            }
+            Ok(())
+        }
+        CoreRequest::SetOutputPolarity { output, .. } => {
+            if *output as usize >= ctx.source_count {
+                return Err(RejectReason::OutputOutOfRange {
+                    output: *output,
+                    source_count: ctx.source_count,
+                });
+            }
+            Ok(())
+        }
+        CoreRequest::SetOutputDelay { output, delay } => {
+            if *output as usize >= ctx.source_count {
+                return Err(RejectReason::OutputOutOfRange {
+                    output: *output,
+                    source_count: ctx.source_count,
+                });
+            }
+            let delay_ms = delay.as_ms();
+            if delay_ms.is_nan() || !(0.0..=MAX_OUTPUT_DELAY_MS).contains(&delay_ms) {
+                return Err(RejectReason::DelayOutOfRange { delay_ms });
+            }
+            Ok(())
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Samples that are not a relative path down into the show directory.
+fn samples_outside_show(samples: &ClickSamples) -> impl Iterator<Item = &String> {
+    samples.iter().flatten().filter(|sample| {
+        sample.is_empty()
+            || !Path::new(sample)
+                .components()
+                .all(|component| matches!(component, Component::Normal(_)))
+    })
+}
+
+fn validate_action(action: &ControlAction, ctx: &ValidationContext) -> Result<(), RejectReason> {
+    match action {
+        ControlAction::LoadCueByIndex(cue) => {
+            if *cue as usize >= ctx.show.cues.len() {
+                return Err(RejectReason::CueOutOfRange {
+                    cue: *cue,
+                    cue_count: ctx.show.cues.len(),
+                });
+            }
+        }
+        ControlAction::SetChannelGain(channel, _) | ControlAction::SetChannelMute(channel, _) => {
+            if *channel as usize >= ctx.channel_count {
+                return Err(RejectReason::ChannelOutOfRange {
+                    channel: *channel,
+                    channel_count: ctx.channel_count,
+                });
+            }
+            if let ControlAction::SetChannelGain(_, gain_db) = action
+                && !gain_db.is_finite()
+            {
+                return Err(RejectReason::GainNotFinite { gain_db: *gain_db });
+            }
+        }
+        ControlAction::TransportSeekBeat(beat) | ControlAction::TransportJumpBeat(beat) => {
+            let beat_exists = ctx
+                .show
+                .cues
+                .get(ctx.cue_idx as usize)
+                .is_some_and(|cue| cue.get_beat(*beat).is_some());
+            if !beat_exists {
+                return Err(RejectReason::BeatOutOfRange { beat: *beat });
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audio::delay::DelayAmount;
+    use crate::show_extension::GainPoint;
+    use common::cue::Cue;
+
+    /// Runs `check` against a show of one cue, 32 channels and sources onto 8 ports, and
+    /// running audio.
+    fn with_context(check: impl FnOnce(&ValidationContext)) {
+        let mut show = Show::default();
+        show.cues.push(Cue::example());
+        let scenes = BTreeMap::new();
+        let macros = BTreeMap::new();
+        let routing_presets = BTreeMap::new();
+        check(&ValidationContext {
+            show: &show,
+            cue_idx: 0,
+            channel_count: 32,
+            source_count: 32,
+            port_count: 8,
+            outputs: &[],
+            input_count: 2,
+            standby_cue: None,
+            io_size: Some((32, 2)),
+            input_io_size: Some((2, 2)),
+            scenes: &scenes,
+            macros: &macros,
+            routing_presets: &routing_presets,
+            power_passphrase_set: false,
+        });
+    }
+
+    fn action(action: ControlAction) -> Request {
+        Request::ControlAction(action)
+    }
+
+    #[test]
+    fn load_cue() {
+        with_context(|ctx| {
+            assert_eq!(
+                validate(&action(ControlAction::LoadCueByIndex(0)), ctx),
+                Ok(())
+            );
+            assert_eq!(
+                validate(&action(ControlAction::LoadCueByIndex(1)), ctx),
+                Err(RejectReason::CueOutOfRange {
+                    cue: 1,
+                    cue_count: 1
+                })
+            );
+        });
+    }
+
+    #[test]
+    fn channel_gain() {
+        with_context(|ctx| {
+            assert_eq!(
+                validate(&action(ControlAction::SetChannelGain(31, -6.0)), ctx),
+                Ok(())
+            );
+            assert_eq!(
+                validate(&action(ControlAction::SetChannelGain(32, 0.0)), ctx),
+                Err(RejectReason::ChannelOutOfRange {
+                    channel: 32,
+                    channel_count: 32
+                })
+            );
+            assert_eq!(
+                validate(
+                    &action(ControlAction::SetChannelGain(0, f32::INFINITY)),
+                    ctx
+                ),
+                Err(RejectReason::GainNotFinite {
+                    gain_db: f32::INFINITY
+                })
+            );
+            assert!(matches!(
+                validate(&action(ControlAction::SetChannelGain(0, f32::NAN)), ctx),
+                Err(RejectReason::GainNotFinite { .. })
+            ));
+        });
+    }
+
+    #[test]
+    fn seek() {
+        with_context(|ctx| {
+            assert_eq!(
+                validate(&action(ControlAction::TransportSeekBeat(u16::MAX)), ctx),
+                Err(RejectReason::BeatOutOfRange { beat: u16::MAX })
+            );
+        });
+    }
+
+    #[test]
+    fn change_routing() {
+        with_context(|ctx| {
+            assert_eq!(
+                validate(&Request::ChangeRouting(0, 2, true), ctx),
+                Err(RejectReason::SystemPortOutOfRange {
+                    port: 2,
+                    port_count: 2
+                })
+            );
+            assert_eq!(
+                validate(
+                    &Request::ChangeRouting(0, 0, true),
+                    &ValidationContext {
+                        io_size: None,
+                        ..*ctx
+                    }
+                ),
+                Err(RejectReason::AudioNotRunning)
+            );
+        });
+    }
+
+    #[test]
+    fn stop_playback_channel() {
+        with_context(|ctx| {
+            assert_eq!(
+                validate_core(
+                    &CoreRequest::StopPlaybackChannel {
+                        channel: 32,
+                        fade_ms: None
+                    },
+                    ctx
+                ),
+                Err(RejectReason::ChannelOutOfRange {
+                    channel: 32,
+                    channel_count: 32
+                })
+            );
+        });
+    }
+
+    #[test]
+    fn jam_timecode() {
+        let time = TimecodeTime {
+            hours: 24,
+            minutes: 0,
+            seconds: 0,
+            frames: 0,
+        };
+        with_context(|ctx| {
+            assert_eq!(
+                validate_core(&CoreRequest::JamTimecode(time), ctx),
+                Err(RejectReason::TimeOutOfRange { time })
+            );
+        });
+    }
+
+    #[test]
+    fn go_without_standby() {
+        with_context(|ctx| {
+            assert_eq!(
+                validate_core(&CoreRequest::Go, ctx),
+                Err(RejectReason::NoCueInStandby)
+            );
+        });
+    }
+
+    #[test]
+    fn recall_scene() {
+        with_context(|ctx| {
+            assert_eq!(
+                validate_core(
+                    &CoreRequest::RecallScene {
+                        name: "show".to_string(),
+                        fade_ms: 0.0
+                    },
+                    ctx
+                ),
+                Err(RejectReason::UnknownScene {
+                    name: "show".to_string()
+                })
+            );
+        });
+    }
+
+    #[test]
+    fn load_routing_preset() {
+        with_context(|ctx| {
+            assert_eq!(
+                validate_core(
+                    &CoreRequest::LoadRoutingPreset {
+                        name: "soundcheck".to_string()
+                    },
+                    ctx
+                ),
+                Err(RejectReason::UnknownRoutingPreset {
+                    name: "soundcheck".to_string()
+                })
+            );
+        });
+    }
+
+    #[test]
+    fn click_samples() {
+        let samples = |sample: &str| CoreRequest::SetClickSamples {
+            cue: Some(0),
+            samples: Some([
+                Some("clicks/one.wav".to_string()),
+                None,
+                Some(sample.to_string()),
+            ]),
+        };
+        with_context(|ctx| {
+            assert_eq!(validate_core(&samples("clicks/two.wav"), ctx), Ok(()));
+            for sample in ["../two.wav", "/root/two.wav", ""] {
+                assert_eq!(
+                    validate_core(&samples(sample), ctx),
+                    Err(RejectReason::ClickSampleOutsideShow {
+                        sample: sample.to_string()
+                    })
+                );
+            }
+        });
+    }
+
+    #[test]
+    fn gain_automation() {
+        with_context(|ctx| {
+            let automation = |channel, gain_db| CoreRequest::SetGainAutomation {
+                cue: 0,
+                channel,
+                points: vec![GainPoint { beat: 0.0, gain_db }],
+            };
+            assert_eq!(validate_core(&automation(31, -6.0), ctx), Ok(()));
+            assert_eq!(
+                validate_core(&automation(32, -6.0), ctx),
+                Err(RejectReason::ChannelOutOfRange {
+                    channel: 32,
+                    channel_count: 32
+                })
+            );
+            assert_eq!(
+                validate_core(&automation(300, -6.0), ctx),
+                Err(RejectReason::ChannelOutOfRange {
+                    channel: u8::MAX,
+                    channel_count: 32
+                })
+            );
+            assert_eq!(
+                validate_core(&automation(0, MAX_AUTOMATION_GAIN_DB + 1.0), ctx),
+                Err(RejectReason::PointOutOfRange {
+                    beat: 0.0,
+                    gain_db: MAX_AUTOMATION_GAIN_DB + 1.0
+                })
+            );
+        });
+    }
+
+    #[test]
+    fn macros() {
+        with_context(|ctx| {
+            assert_eq!(
+                validate_core(
+                    &CoreRequest::RunMacro {
+                        name: "house open".to_string()
+                    },
+                    ctx
+                ),
+                Err(RejectReason::UnknownMacro {
+                    name: "house open".to_string()
+                })
+            );
+            assert_eq!(
+                validate_core(
+                    &CoreRequest::SetMacro {
+                        name: "house open".to_string(),
+                        steps: Some(vec![MacroStep {
+                            delay_ms: MAX_MACRO_DELAY_MS + 1,
+                            action: ControlAction::TransportStart,
+                        }]),
+                    },
+                    ctx
+                ),
+                Err(RejectReason::StepDelayOutOfRange {
+                    delay_ms: MAX_MACRO_DELAY_MS + 1
+                })
+            );
+        });
+    }
+
+    #[test]
+    fn output_label() {
+        with_context(|ctx| {
+            assert_eq!(
+                validate_core(
+                    &CoreRequest::SetOutputLabel {
+                        output: 0,
+                        label: "x".repeat(MAX_OUTPUT_LABEL_LEN + 1),
+                    },
+                    ctx
+                ),
+                Err(RejectReason::LabelTooLong {
+                    length: MAX_OUTPUT_LABEL_LEN + 1
+                })
+            );
+        });
+    }
+
+    #[test]
+    fn output_eq_band() {
+        let eq_band = |frequency, gain_db, q| CoreRequest::SetOutputEqBand {
+            output: 0,
+            band: 1,
+            settings: EqBand {
+                frequency,
+                gain_db,
+                q,
+            },
+        };
+        with_context(|ctx| {
+            assert_eq!(validate_core(&eq_band(1000.0, -6.0, 0.7), ctx), Ok(()));
+            for (frequency, gain_db, q) in [
+                (f32::NAN, 0.0, 0.7),
+                (1000.0, f32::INFINITY, 0.7),
+                (1000.0, 0.0, f32::NAN),
+                (5.0, 0.0, 0.7),
+                (25000.0, 0.0, 0.7),
+                (1000.0, 30.0, 0.7),
+                (1000.0, 0.0, 0.0),
+            ] {
+                assert!(matches!(
+                    validate_core(&eq_band(frequency, gain_db, q), ctx),
+                    Err(RejectReason::EqSettingsOutOfRange { .. })
+                ));
+            }
+            assert_eq!(
+                validate_core(
+                    &CoreRequest::SetOutputEqBand {
+                        output: 0,
+                        band: 3,
+                        settings: EqBand {
+                            frequency: 1000.0,
+                            gain_db: 0.0,
+                            q: 0.7
+                        },
+                    },
+                    ctx
+                ),
+                Err(RejectReason::EqBandOutOfRange { band: 3 })
+            );
+        });
+    }
+
+    #[test]
+    fn pass_through() {
+        let pass_through = |gain_db| CoreRequest::SetPassThrough {
+            input: 0,
+            output: 0,
+            gain_db,
+        };
+        with_context(|ctx| {
+            assert_eq!(validate_core(&pass_through(Some(-6.0)), ctx), Ok(()));
+            assert_eq!(validate_core(&pass_through(None), ctx), Ok(()));
+            assert!(matches!(
+                validate_core(&pass_through(Some(f32::NAN)), ctx),
+                Err(RejectReason::GainNotFinite { .. })
+            ));
+            assert_eq!(
+                validate_core(&pass_through(Some(f32::NEG_INFINITY)), ctx),
+                Err(RejectReason::GainNotFinite {
+                    gain_db: f32::NEG_INFINITY
+                })
+            );
+            assert_eq!(
+                validate_core(&pass_through(Some(200.0)), ctx),
+                Err(RejectReason::PassThroughGainOutOfRange { gain_db: 200.0 })
+            );
+        });
+    }
+
+    #[test]
+    fn output_delay() {
+        with_context(|ctx| {
+            for delay in [
+                DelayAmount::Milliseconds(-1.0),
+                DelayAmount::Milliseconds(f32::NAN),
+                DelayAmount::Meters(f32::INFINITY),
+            ] {
+                assert!(matches!(
+                    validate_core(&CoreRequest::SetOutputDelay { output: 0, delay }, ctx),
+                    Err(RejectReason::DelayOutOfRange { .. })
+                ));
+            }
+        });
+    }
+
+    #[test]
+    fn output_polarity() {
+        with_context(|ctx| {
+            assert_eq!(
+                validate_core(
+                    &CoreRequest::SetOutputPolarity {
+                        output: 32,
+                        invert: true,
+                    },
+                    ctx
+                ),
+                Err(RejectReason::OutputOutOfRange {
+                    output: 32,
+                    source_count: 32
+                })
+            );
+        });
+    }
+
+    #[test]
+    fn output_mix() {
+        let mix = |port, gain_db| CoreRequest::SetOutputMix {
+            output: 20,
+            port,
+            gain_db,
+        };
+        with_context(|ctx| {
+            assert_eq!(
+                validate_core(&mix(8, Some(0.0)), ctx),
+                Err(RejectReason::OutputPortOutOfRange {
+                    port: 8,
+                    port_count: 8
+                })
+            );
+            assert_eq!(
+                validate_core(&mix(0, Some(f32::INFINITY)), ctx),
+                Err(RejectReason::GainNotFinite {
+                    gain_db: f32::INFINITY
+                })
+            );
+            assert_eq!(
+                validate_core(&mix(0, None), ctx),
+                Err(RejectReason::OutputUnheard { output: 20 })
+            );
+        });
+    }
+
+    #[test]
+    fn power() {
+        with_context(|ctx| {
+            assert_eq!(
+                validate_core(&CoreRequest::Reboot { passphrase: None }, ctx),
+                Err(RejectReason::PowerNotConfigured)
+            );
+            let ctx = ValidationContext {
+                power_passphrase_set: true,
+                ..*ctx
+            };
+            assert_eq!(
+                validate_core(&CoreRequest::Restart { passphrase: None }, &ctx),
+                Ok(())
+            );
+        });
+    }
+
+    #[test]
+    fn hello() {
+        let hello = |protocol_version| CoreRequest::Hello { protocol_version };
+        with_context(|ctx| {
+            assert_eq!(validate_core(&hello(PROTOCOL_VERSION), ctx), Ok(()));
+            assert_eq!(
+                validate_core(&hello(PROTOCOL_VERSION + 1), ctx),
+                Err(RejectReason::IncompatibleProtocol {
+                    version: PROTOCOL_VERSION + 1
+                })
+            );
+        });
+    }
+}